@@ -1,8 +1,10 @@
 """FastAPI server entrypoint."""
 
 import argparse
+import asyncio
 import logging
 import os
+import ssl
 import time
 from pathlib import Path
 from typing import Optional
@@ -281,15 +283,61 @@ def main():
     parser.add_argument("--host", default="0.0.0.0", help="Host to bind")
     parser.add_argument("--port", "-p", type=int, default=8000, help="Port to bind")
     parser.add_argument("--reload", action="store_true", help="Enable auto-reload")
+    parser.add_argument("--uds", help="Serve on this Unix domain socket instead of --host/--port")
+    parser.add_argument(
+        "--tls-port",
+        type=int,
+        help="Also serve on this localhost port over TLS, requiring a client certificate",
+    )
+    parser.add_argument("--ssl-certfile", help="Server certificate for --tls-port")
+    parser.add_argument("--ssl-keyfile", help="Server private key for --tls-port")
+    parser.add_argument("--ssl-ca-certs", help="CA that client certificates must be issued by")
     args = parser.parse_args()
 
-    uvicorn.run(
+    if args.tls_port is None:
+        uvicorn.run(
+            "interfaces_backend.main:app",
+            host=args.host,
+            port=args.port,
+            uds=args.uds,
+            reload=args.reload,
+            log_config=None,  # Use our logging config instead of uvicorn's default
+        )
+        return
+
+    if not (args.ssl_certfile and args.ssl_keyfile and args.ssl_ca_certs):
+        parser.error("--tls-port requires --ssl-certfile, --ssl-keyfile and --ssl-ca-certs")
+    if args.reload:
+        parser.error("--reload cannot be combined with --tls-port")
+    plain = uvicorn.Config(
         "interfaces_backend.main:app",
         host=args.host,
         port=args.port,
-        reload=args.reload,
-        log_config=None,  # Use our logging config instead of uvicorn's default
+        uds=args.uds,
+        log_config=None,
     )
+    # Same app for the desktop shell; lifespan already runs on the plain server.
+    mutual_tls = uvicorn.Config(
+        "interfaces_backend.main:app",
+        host="127.0.0.1",
+        port=args.tls_port,
+        log_config=None,
+        lifespan="off",
+        ssl_certfile=args.ssl_certfile,
+        ssl_keyfile=args.ssl_keyfile,
+        ssl_ca_certs=args.ssl_ca_certs,
+        ssl_cert_reqs=ssl.CERT_REQUIRED,
+    )
+    asyncio.run(_serve_all([uvicorn.Server(plain), uvicorn.Server(mutual_tls)]))
+
+
+async def _serve_all(servers: list[uvicorn.Server]) -> None:
+    """Run the servers until one stops, e.g. on a signal, then stop the rest."""
+    tasks = [asyncio.create_task(server.serve()) for server in servers]
+    await asyncio.wait(tasks, return_when=asyncio.FIRST_COMPLETED)
+    for server in servers:
+        server.should_exit = True
+    await asyncio.gather(*tasks)
 
 
 if __name__ == "__main__":
@@ -0,0 +1,117 @@
+"""Shared-memory frame ring read by the desktop shell.
+
+When the backend runs under the desktop app, ``PHI_FRAME_DIR`` points at a
+directory (tmpfs on Linux) where each camera stream gets a ``<stream>.ring``
+file. The shell maps the same file and serves frames to the webview through
+its ``frame://`` protocol, so frames never travel through the HTTP API.
+
+The layout must match ``tauri/src-tauri/src/frames.rs``. A 64-byte header
+(magic, version, slot count, slot capacity, latest frame number) is followed
+by fixed-size slots. Each slot is guarded by a sequence word that is odd
+while the slot is being written, so readers can detect torn copies.
+"""
+
+from __future__ import annotations
+
+import mmap
+import os
+import re
+import struct
+import time
+from pathlib import Path
+from typing import Optional
+
+MAGIC = b"PRCSRING"
+VERSION = 1
+HEADER_LEN = 64
+SLOT_HEADER_LEN = 32
+LATEST_OFFSET = 24
+
+_STREAM_NAME = re.compile(r"^[A-Za-z0-9_-]+$")
+
+
+class FrameRingWriter:
+    """Single-writer ring of the most recent frames of one stream."""
+
+    def __init__(
+        self,
+        path: Path,
+        slot_count: int = 4,
+        slot_capacity: int = 8 * 1024 * 1024,
+    ) -> None:
+        if slot_count < 1:
+            raise ValueError("slot_count must be at least 1")
+        # Keep every slot header 8-byte aligned for the reader's atomics.
+        slot_capacity = (slot_capacity + 7) // 8 * 8
+        self.path = Path(path)
+        self.slot_count = slot_count
+        self.slot_capacity = slot_capacity
+        self._seq = 0
+
+        size = HEADER_LEN + slot_count * (SLOT_HEADER_LEN + slot_capacity)
+        tmp = self.path.with_suffix(".ring.tmp")
+        with open(tmp, "wb") as f:
+            f.truncate(size)
+            header = struct.pack(
+                "<8sIII", MAGIC, VERSION, slot_count, slot_capacity
+            )
+            f.write(header)
+        # Publish the ring only once its header is complete.
+        os.replace(tmp, self.path)
+
+        self._file = open(self.path, "r+b")
+        self._map = mmap.mmap(self._file.fileno(), size)
+
+    @classmethod
+    def for_stream(cls, stream: str, **kwargs) -> Optional["FrameRingWriter"]:
+        """Create the ring for ``stream`` if running under the desktop shell."""
+        directory = os.environ.get("PHI_FRAME_DIR")
+        if not directory:
+            return None
+        if not _STREAM_NAME.match(stream):
+            raise ValueError(f"invalid stream name: {stream!r}")
+        return cls(Path(directory) / f"{stream}.ring", **kwargs)
+
+    def write(
+        self,
+        data: bytes,
+        width: int = 0,
+        height: int = 0,
+        fourcc: str = "MJPG",
+        timestamp_ns: Optional[int] = None,
+    ) -> int:
+        """Publish one frame and return its frame number."""
+        if len(data) > self.slot_capacity:
+            raise ValueError(
+                f"frame of {len(data)} bytes exceeds slot capacity {self.slot_capacity}"
+            )
+        seq = self._seq + 1
+        slot = HEADER_LEN + (seq - 1) % self.slot_count * (
+            SLOT_HEADER_LEN + self.slot_capacity
+        )
+        if timestamp_ns is None:
+            timestamp_ns = time.time_ns()
+        code = fourcc.encode("ascii")[:4].ljust(4, b" ")
+
+        struct.pack_into("<Q", self._map, slot, seq * 2 + 1)
+        struct.pack_into(
+            "<I4sIIQ", self._map, slot + 8, len(data), code, width, height, timestamp_ns
+        )
+        payload = slot + SLOT_HEADER_LEN
+        self._map[payload : payload + len(data)] = data
+        struct.pack_into("<Q", self._map, slot, seq * 2)
+        struct.pack_into("<Q", self._map, LATEST_OFFSET, seq)
+        self._seq = seq
+        return seq
+
+    def close(self, unlink: bool = True) -> None:
+        self._map.close()
+        self._file.close()
+        if unlink:
+            self.path.unlink(missing_ok=True)
+
+    def __enter__(self) -> "FrameRingWriter":
+        return self
+
+    def __exit__(self, *exc) -> None:
+        self.close()
@@ -0,0 +1,63 @@
+from __future__ import annotations
+
+import struct
+
+import pytest
+
+from interfaces_backend.utils.frame_ring import (
+    HEADER_LEN,
+    LATEST_OFFSET,
+    MAGIC,
+    SLOT_HEADER_LEN,
+    FrameRingWriter,
+)
+
+
+def _read_slot(raw: bytes, slot_count: int, capacity: int, seq: int) -> tuple:
+    slot = HEADER_LEN + (seq - 1) % slot_count * (SLOT_HEADER_LEN + capacity)
+    lock, length, fourcc, width, height, ts = struct.unpack_from(
+        "<QI4sIIQ", raw, slot
+    )
+    payload = raw[slot + SLOT_HEADER_LEN : slot + SLOT_HEADER_LEN + length]
+    return lock, fourcc, width, height, ts, payload
+
+
+def test_frame_ring_layout_matches_reader(tmp_path) -> None:
+    path = tmp_path / "cam0.ring"
+    with FrameRingWriter(path, slot_count=2, slot_capacity=13) as ring:
+        assert ring.slot_capacity == 16
+        for index in range(3):
+            ring.write(bytes([index]) * 5, width=640, height=480, timestamp_ns=index)
+
+        raw = path.read_bytes()
+        magic, version, slot_count, capacity = struct.unpack_from("<8sIII", raw)
+        assert (magic, version, slot_count, capacity) == (MAGIC, 1, 2, 16)
+        assert struct.unpack_from("<Q", raw, LATEST_OFFSET)[0] == 3
+
+        lock, fourcc, width, height, ts, payload = _read_slot(raw, 2, 16, 3)
+        assert lock == 6
+        assert (fourcc, width, height, ts) == (b"MJPG", 640, 480, 2)
+        assert payload == b"\x02" * 5
+        # Frame 1 shared a slot with frame 3 and is gone.
+        assert _read_slot(raw, 2, 16, 1)[0] != 2
+
+    assert not path.exists()
+
+
+def test_frame_ring_rejects_oversized_frames(tmp_path) -> None:
+    with FrameRingWriter(tmp_path / "cam0.ring", slot_count=1, slot_capacity=8) as ring:
+        with pytest.raises(ValueError):
+            ring.write(b"x" * 9)
+
+
+def test_frame_ring_for_stream_requires_shell(tmp_path, monkeypatch) -> None:
+    monkeypatch.delenv("PHI_FRAME_DIR", raising=False)
+    assert FrameRingWriter.for_stream("cam0") is None
+
+    monkeypatch.setenv("PHI_FRAME_DIR", str(tmp_path))
+    with pytest.raises(ValueError):
+        FrameRingWriter.for_stream("../escape")
+    ring = FrameRingWriter.for_stream("cam0", slot_count=1, slot_capacity=8)
+    assert ring is not None
+    assert ring.path == tmp_path / "cam0.ring"
+    ring.close()
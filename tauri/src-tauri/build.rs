@@ -0,0 +1,50 @@
+fn main() {
+    compile_protos().expect("failed to compile protos");
+    sidecar_manifest().expect("failed to hash the bundled sidecars");
+    tauri_build::build()
+}
+
+/// Generates the gateway client with protox, so no `protoc` is needed.
+fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
+    let proto = "proto/percus/gateway/v1/gateway.proto";
+    println!("cargo:rerun-if-changed={proto}");
+    let descriptors = protox::compile([proto], ["proto"])?;
+    tonic_prost_build::configure()
+        .build_server(false)
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .message_attribute(".", "#[serde(default, rename_all = \"camelCase\")]")
+        .compile_fds(descriptors)?;
+    Ok(())
+}
+
+/// Writes `<name> <sha256>` for every `binaries/<name>-<target>` bundled via
+/// `externalBin`, for the shell to verify them against before launch.
+fn sidecar_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::{Digest, Sha256};
+
+    println!("cargo:rerun-if-changed=binaries");
+    let target = std::env::var("TARGET")?;
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR")?).join("sidecar-manifest.txt");
+    let mut manifest = String::new();
+    if let Ok(entries) = std::fs::read_dir("binaries") {
+        for entry in entries {
+            let path = entry?.path();
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            let stem = file.strip_suffix(".exe").unwrap_or(&file);
+            let Some(name) = stem.strip_suffix(&format!("-{target}")) else {
+                continue;
+            };
+            println!("cargo:rerun-if-changed={}", path.display());
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut std::fs::File::open(&path)?, &mut hasher)?;
+            let digest: String = hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            manifest.push_str(&format!("{name} {digest}\n"));
+        }
+    }
+    std::fs::write(out, manifest)?;
+    Ok(())
+}
@@ -0,0 +1,311 @@
+//! Opt-in, anonymized feature-usage and error metrics.
+//!
+//! Nothing is recorded until the operator turns `analytics.enabled` on with
+//! [`set_analytics_enabled`]; turning it off deletes what was buffered.
+//! Events are appended to `analytics.jsonl` in the app data directory and
+//! uploaded to `analytics.endpoint` in batches of `batchSize` every
+//! `flushIntervalSecs` while the shell is online (see [`crate::offline`]),
+//! or on [`flush_analytics`]. A batch is removed from the buffer only once
+//! the endpoint accepted it, so failed uploads are retried with the next
+//! flush. The buffer keeps the newest [`MAX_BUFFERED`] events.
+//!
+//! Events carry a name, a time and scalar properties, plus a random id that
+//! is the same for every event of this installation; [`WATCHED`] shell
+//! events are recorded by name only, with just the fields listed for them.
+//! The webview reports feature use with [`track_event`], whose string
+//! properties are limited to [`MAX_STRING`] characters.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Listener, Manager, State};
+
+use crate::backend_tls;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::offline;
+use crate::settings::SettingsStore;
+
+const BUFFER_FILE: &str = "analytics.jsonl";
+const ID_FILE: &str = "analytics-id";
+const MAX_BUFFERED: usize = 10_000;
+const MAX_STRING: usize = 64;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shell events recorded automatically, with the payload fields kept.
+/// `error` is reduced to whether there was one.
+const WATCHED: &[(&str, &[&str])] = &[
+    ("episode-event", &["event", "error"]),
+    ("estop-triggered", &["source"]),
+    ("backend-error", &[]),
+    ("backend-restarted", &[]),
+    ("watchdog-tripped", &[]),
+    ("calibration-solved", &[]),
+    ("code-detected", &[]),
+    ("hub-finished", &["error"]),
+    ("dataset-import-finished", &["error"]),
+    ("hdf5-export-finished", &["error"]),
+    ("model-download-finished", &["error"]),
+];
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    name: String,
+    at: DateTime<Utc>,
+    properties: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Batch<'a> {
+    anonymous_id: &'a str,
+    app_version: String,
+    os: &'static str,
+    events: &'a [Value],
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlushResult {
+    pub sent: usize,
+    pub remaining: usize,
+}
+
+pub struct Analytics {
+    path: PathBuf,
+    /// Guards the buffer file.
+    file: StdMutex<()>,
+    /// Serializes flushes.
+    flushing: tokio::sync::Mutex<()>,
+}
+
+/// Starts watching shell events and the periodic flush.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let dir = app.path().app_data_dir()?;
+    app.manage(Analytics {
+        path: dir.join(BUFFER_FILE),
+        file: StdMutex::new(()),
+        flushing: tokio::sync::Mutex::new(()),
+    });
+    for (event, fields) in WATCHED {
+        let handle = app.clone();
+        app.listen_any(*event, move |message| {
+            let Ok(Value::Object(payload)) = serde_json::from_str(message.payload()) else {
+                return record(&handle, event, Map::new());
+            };
+            let properties = fields
+                .iter()
+                .filter_map(|field| {
+                    let value = payload.get(*field)?;
+                    let value = match *field {
+                        "error" => Value::Bool(!value.is_null()),
+                        _ => scalar(value)?,
+                    };
+                    Some((field.to_string(), value))
+                })
+                .collect();
+            record(&handle, event, properties);
+        });
+    }
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let settings = handle.state::<SettingsStore>().get().analytics;
+            tokio::time::sleep(Duration::from_secs(settings.flush_interval_secs.max(1))).await;
+            if settings.enabled && offline::is_online(&handle) {
+                if let Err(err) = flush(&handle).await {
+                    tracing::debug!("analytics upload failed: {err}");
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Buffers event `name` when analytics are enabled.
+pub fn record(app: &AppHandle, name: &str, properties: Map<String, Value>) {
+    if !app.state::<SettingsStore>().get().analytics.enabled {
+        return;
+    }
+    let event = Event {
+        name: name.to_owned(),
+        at: Utc::now(),
+        properties,
+    };
+    let analytics = app.state::<Analytics>();
+    let _guard = analytics.file.lock().unwrap();
+    let appended = serde_json::to_string(&event)
+        .map_err(Error::from)
+        .and_then(|line| {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&analytics.path)?;
+            Ok(writeln!(file, "{line}")?)
+        });
+    if let Err(err) = appended {
+        tracing::debug!("analytics event not buffered: {err}");
+    }
+}
+
+/// Records a feature use reported by the webview.
+#[tauri::command]
+pub fn track_event(
+    app: AppHandle,
+    name: String,
+    properties: Option<Map<String, Value>>,
+) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_STRING
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_'));
+    if !valid {
+        return Err(Error::Invalid(format!("analytics event name {name}")));
+    }
+    let properties = properties
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| key.len() <= MAX_STRING)
+        .filter_map(|(key, value)| Some((key, scalar(&value)?)))
+        .collect();
+    record(&app, &name, properties);
+    Ok(())
+}
+
+/// Opts in or out; opting out deletes the buffered events.
+#[tauri::command]
+pub fn set_analytics_enabled(
+    app: AppHandle,
+    analytics: State<'_, Analytics>,
+    store: State<'_, SettingsStore>,
+    enabled: bool,
+) -> Result<()> {
+    store.update(&app, |settings| settings.analytics.enabled = enabled)?;
+    if !enabled {
+        let _guard = analytics.file.lock().unwrap();
+        match std::fs::remove_file(&analytics.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    tracing::info!(enabled, "usage analytics changed");
+    Ok(())
+}
+
+/// Uploads the buffered events now.
+#[tauri::command]
+pub async fn flush_analytics(app: AppHandle) -> Result<FlushResult> {
+    if !app.state::<SettingsStore>().get().analytics.enabled {
+        return Err(Error::Invalid("analytics are not enabled".into()));
+    }
+    flush(&app).await
+}
+
+async fn flush(app: &AppHandle) -> Result<FlushResult> {
+    let settings = app.state::<SettingsStore>().get().analytics;
+    let endpoint = settings
+        .endpoint
+        .ok_or_else(|| Error::Invalid("analytics.endpoint is not set".into()))?;
+    let analytics = app.state::<Analytics>();
+    let _flushing = analytics.flushing.lock().await;
+    let events = read_buffer(&analytics)?;
+    let anonymous_id = anonymous_id(app)?;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let mut sent = 0;
+    for batch in events.chunks(settings.batch_size.max(1)) {
+        let body = Batch {
+            anonymous_id: &anonymous_id,
+            app_version: app.package_info().version.to_string(),
+            os: std::env::consts::OS,
+            events: batch,
+        };
+        client
+            .post(&endpoint)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        sent += batch.len();
+        drop_sent(&analytics, batch.len())?;
+    }
+    Ok(FlushResult {
+        sent,
+        remaining: read_buffer(&analytics)?.len(),
+    })
+}
+
+/// Buffered events, oldest first, trimmed to [`MAX_BUFFERED`].
+fn read_buffer(analytics: &Analytics) -> Result<Vec<Value>> {
+    let _guard = analytics.file.lock().unwrap();
+    let text = match std::fs::read_to_string(&analytics.path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut events: Vec<Value> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if events.len() > MAX_BUFFERED {
+        events.drain(..events.len() - MAX_BUFFERED);
+        write_buffer(analytics, &events)?;
+    }
+    Ok(events)
+}
+
+/// Removes the `count` oldest events, keeping ones recorded since.
+fn drop_sent(analytics: &Analytics, count: usize) -> Result<()> {
+    let _guard = analytics.file.lock().unwrap();
+    let text = std::fs::read_to_string(&analytics.path)?;
+    let events: Vec<Value> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .skip(count)
+        .collect();
+    write_buffer(analytics, &events)
+}
+
+fn write_buffer(analytics: &Analytics, events: &[Value]) -> Result<()> {
+    let mut text = String::new();
+    for event in events {
+        text.push_str(&event.to_string());
+        text.push('\n');
+    }
+    Ok(fsutil::write_atomic(&analytics.path, text.as_bytes())?)
+}
+
+/// Random id of this installation, unrelated to any other identifier.
+fn anonymous_id(app: &AppHandle) -> Result<String> {
+    let dir = app.path().app_data_dir()?;
+    let path = dir.join(ID_FILE);
+    if let Ok(id) = std::fs::read_to_string(&path) {
+        if !id.trim().is_empty() {
+            return Ok(id.trim().to_owned());
+        }
+    }
+    let mut id = [0u8; 16];
+    getrandom::fill(&mut id).map_err(|err| Error::Invalid(format!("random: {err}")))?;
+    let id = hex::encode(id);
+    std::fs::create_dir_all(&dir)?;
+    backend_tls::write_private(&path, id.as_bytes())?;
+    Ok(id)
+}
+
+/// `value` when it is a number, a boolean or a short string.
+fn scalar(value: &Value) -> Option<Value> {
+    match value {
+        Value::Bool(_) | Value::Number(_) => Some(value.clone()),
+        Value::String(text) if text.chars().count() <= MAX_STRING => Some(value.clone()),
+        _ => None,
+    }
+}
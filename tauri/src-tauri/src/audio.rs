@@ -0,0 +1,307 @@
+//! Microphone capture, so operators can dictate notes while demonstrating.
+//!
+//! With `audio.enabled` set, every episode the recorder captures also gets a
+//! 16-bit WAV track of `audio.device` (the system's default input when
+//! unset). The track is aligned to the episode table: it starts at the first
+//! row and ends with the last, so a sample's time is the rows' `timestamp`.
+//! Microphone buffers are placed by their capture time on the shell's
+//! corrected clock; what arrived before the first row is dropped, and
+//! silence stands in when the microphone started late. A muted episode
+//! (see [`crate::recording::set_audio_muted`]) records silence for as long
+//! as it is muted, keeping the rest of the track in place.
+//!
+//! The input stream lives on its own thread because cpal streams may not be
+//! moved between threads on every platform.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, InputCallbackInfo, SampleFormat, SizedSample, Stream};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::error::{Error, Result};
+use crate::settings::AudioSettings;
+use crate::time_sync;
+
+/// How long buffers still in flight are waited for once the episode ended.
+const DRAIN: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDevice {
+    pub name: String,
+    pub is_default: bool,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Lists the input devices `audio.device` can name.
+#[tauri::command]
+pub async fn list_audio_devices() -> Result<Vec<AudioDevice>> {
+    // Enumerating ALSA devices can take a while.
+    tauri::async_runtime::spawn_blocking(|| {
+        let host = cpal::default_host();
+        let default = host.default_input_device().and_then(|d| d.name().ok());
+        let mut devices = Vec::new();
+        for device in host.input_devices().map_err(audio_error)? {
+            let (Ok(name), Ok(config)) = (device.name(), device.default_input_config()) else {
+                continue;
+            };
+            devices.push(AudioDevice {
+                is_default: default.as_deref() == Some(name.as_str()),
+                name,
+                sample_rate: config.sample_rate().0,
+                channels: config.channels(),
+            });
+        }
+        Ok(devices)
+    })
+    .await
+    .map_err(|err| Error::Audio(err.to_string()))?
+}
+
+/// Episode times the track is cut to, in nanoseconds on the corrected
+/// clock; 0 until known. The recorder sets them when it takes the first row
+/// and when the capture ends.
+#[derive(Default)]
+pub struct Timeline {
+    pub start_ns: AtomicI64,
+    pub end_ns: AtomicI64,
+}
+
+enum Message {
+    Samples { captured_ns: i64, samples: Vec<i16> },
+    Stop,
+}
+
+/// A track being recorded to `path`.
+pub struct Track {
+    path: PathBuf,
+    muted: Arc<AtomicBool>,
+    sender: Sender<Message>,
+    thread: JoinHandle<Result<u64>>,
+}
+
+impl Track {
+    /// Opens the configured microphone and records into `path` along
+    /// `timeline`.
+    pub fn start(
+        app: &AppHandle,
+        settings: &AudioSettings,
+        path: PathBuf,
+        timeline: Arc<Timeline>,
+        muted: bool,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(path.parent().expect("audio path has a parent"))?;
+        let muted = Arc::new(AtomicBool::new(muted));
+        let (sender, receiver) = mpsc::channel();
+        let (ready, opened) = mpsc::channel();
+        let thread = {
+            let app = app.clone();
+            let device = settings.device.clone();
+            let path = path.clone();
+            let muted = muted.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let (stream, spec) = match open(&app, device.as_deref(), sender) {
+                    Ok(opened) => opened,
+                    Err(err) => {
+                        let _ = ready.send(Err(err));
+                        return Ok(0);
+                    }
+                };
+                let _ = ready.send(Ok(()));
+                let written = write_track(&path, spec, &receiver, &timeline, &muted);
+                drop(stream);
+                written
+            })
+        };
+        opened
+            .recv()
+            .map_err(|_| Error::Audio("capture thread exited".into()))??;
+        Ok(Self {
+            path,
+            muted,
+            sender,
+            thread,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Closes the track once the buffers up to the timeline's end arrived.
+    /// Returns whether it holds any samples.
+    pub async fn finish(self) -> Result<bool> {
+        let _ = self.sender.send(Message::Stop);
+        let thread = self.thread;
+        let written = tauri::async_runtime::spawn_blocking(move || thread.join())
+            .await
+            .map_err(|err| Error::Audio(err.to_string()))?
+            .map_err(|_| Error::Audio("capture thread panicked".into()))??;
+        Ok(written > 0)
+    }
+}
+
+/// The input device called `name`, or the default one.
+pub fn input_device(name: Option<&str>) -> Result<Device> {
+    let host = cpal::default_host();
+    match name {
+        None => host.default_input_device(),
+        Some(name) => host
+            .input_devices()
+            .map_err(audio_error)?
+            .find(|device| device.name().is_ok_and(|n| n == name)),
+    }
+    .ok_or_else(|| Error::NotFound(format!("audio input {}", name.unwrap_or("default"))))
+}
+
+fn open(
+    app: &AppHandle,
+    name: Option<&str>,
+    sender: Sender<Message>,
+) -> Result<(Stream, hound::WavSpec)> {
+    let device = input_device(name)?;
+    let config = device.default_input_config().map_err(audio_error)?;
+    let spec = hound::WavSpec {
+        channels: config.channels(),
+        sample_rate: config.sample_rate().0,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let format = config.sample_format();
+    let config = config.config();
+    let stream = match format {
+        SampleFormat::I8 => input_stream::<i8>(app, &device, &config, sender),
+        SampleFormat::I16 => input_stream::<i16>(app, &device, &config, sender),
+        SampleFormat::I32 => input_stream::<i32>(app, &device, &config, sender),
+        SampleFormat::U8 => input_stream::<u8>(app, &device, &config, sender),
+        SampleFormat::U16 => input_stream::<u16>(app, &device, &config, sender),
+        SampleFormat::F32 => input_stream::<f32>(app, &device, &config, sender),
+        SampleFormat::F64 => input_stream::<f64>(app, &device, &config, sender),
+        other => Err(Error::Unsupported(format!("audio sample format {other}"))),
+    }?;
+    stream.play().map_err(audio_error)?;
+    Ok((stream, spec))
+}
+
+fn input_stream<T>(
+    app: &AppHandle,
+    device: &Device,
+    config: &cpal::StreamConfig,
+    sender: Sender<Message>,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    i16: FromSample<T>,
+{
+    let app = app.clone();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], info: &InputCallbackInfo| {
+                let stamp = info.timestamp();
+                let latency = stamp
+                    .callback
+                    .duration_since(&stamp.capture)
+                    .unwrap_or_default();
+                let captured_ns = time_sync::now_ns(&app) - latency.as_nanos() as i64;
+                let samples = data.iter().map(|sample| sample.to_sample()).collect();
+                let _ = sender.send(Message::Samples {
+                    captured_ns,
+                    samples,
+                });
+            },
+            |err| tracing::warn!("audio capture: {err}"),
+            None,
+        )
+        .map_err(audio_error)
+}
+
+/// Writes buffers between the timeline's start and end; returns the frames
+/// written.
+fn write_track(
+    path: &Path,
+    spec: hound::WavSpec,
+    receiver: &Receiver<Message>,
+    timeline: &Timeline,
+    muted: &AtomicBool,
+) -> Result<u64> {
+    let mut writer = hound::WavWriter::create(path, spec).map_err(audio_error)?;
+    let channels = usize::from(spec.channels.max(1));
+    let frames_in = |ns: i64| ns as i128 * i128::from(spec.sample_rate) / 1_000_000_000;
+    let mut written: i128 = 0;
+    let mut aligned = false;
+    let mut stopping: Option<Instant> = None;
+    loop {
+        let message = match stopping {
+            None => receiver.recv().ok(),
+            Some(at) => receiver
+                .recv_timeout(DRAIN.saturating_sub(at.elapsed()))
+                .ok(),
+        };
+        let (captured_ns, samples) = match message {
+            Some(Message::Samples {
+                captured_ns,
+                samples,
+            }) => (captured_ns, samples),
+            Some(Message::Stop) => {
+                stopping.get_or_insert_with(Instant::now);
+                continue;
+            }
+            None => break,
+        };
+        let start = timeline.start_ns.load(Ordering::Relaxed);
+        if start == 0 {
+            continue;
+        }
+        let frames = samples.len() / channels;
+        let mut skip = 0;
+        if !aligned {
+            // Position of the buffer's first frame on the episode's timeline.
+            let offset = frames_in(captured_ns - start);
+            if offset + (frames as i128) <= 0 {
+                continue;
+            }
+            for _ in 0..offset.max(0) as usize * channels {
+                writer.write_sample(0i16).map_err(audio_error)?;
+            }
+            written = offset.max(0);
+            skip = (-offset).max(0) as usize;
+            aligned = true;
+        }
+        let end = timeline.end_ns.load(Ordering::Relaxed);
+        let mut take = frames - skip;
+        if end != 0 {
+            let left = (frames_in(end - start) - written).max(0);
+            take = take.min(left as usize);
+        }
+        let silent = muted.load(Ordering::Relaxed);
+        for &sample in &samples[skip * channels..(skip + take) * channels] {
+            writer
+                .write_sample(if silent { 0 } else { sample })
+                .map_err(audio_error)?;
+        }
+        written += take as i128;
+        if end != 0 && written >= frames_in(end - start) {
+            break;
+        }
+    }
+    writer.finalize().map_err(audio_error)?;
+    Ok(written as u64)
+}
+
+pub fn audio_error(err: impl std::fmt::Display) -> Error {
+    Error::Audio(err.to_string())
+}
@@ -0,0 +1,489 @@
+//! Tamper-evident record of safety-relevant actions in the cell.
+//!
+//! [`record`] appends an [`AuditEntry`] to `audit.jsonl` in the app data
+//! directory: program starts and stops, emergency and safe stops, writes
+//! of robot variables and weld setpoints, settings changes (by section,
+//! never their values) and profile switches. Each entry holds the SHA-256
+//! of the previous one, and its own hash is signed with an Ed25519 key of
+//! this installation, kept in the keychain as the `audit-signing-key`
+//! secret. Editing, reordering or removing entries therefore breaks the
+//! chain, which [`verify_audit_log`] checks, with one exception: entries
+//! cut off the end leave a shorter chain that still verifies, which only
+//! an earlier export shows. [`export_audit_log`] writes the entries
+//! together with the public key and the verification result for the
+//! quality team.
+//!
+//! The actor is the signed-in user (see [`crate::users`]), otherwise the
+//! OS account running the shell, and whether operator mode was on.
+//! Without a keychain the entries are signed with a key that only lives
+//! for the session, so they fail verification after a restart.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex as StdMutex;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::kiosk;
+use crate::secrets;
+use crate::settings::Settings;
+use crate::users;
+
+const LOG_FILE: &str = "audit.jsonl";
+/// PKCS#8 document of the signing key, base64.
+pub const KEY_SECRET: &str = "audit-signing-key";
+/// `prevHash` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const DEFAULT_QUERY_LIMIT: usize = 500;
+
+/// A line of `audit.jsonl`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub actor: String,
+    pub operator_mode: bool,
+    /// Such as `programStart`, `estop` or `settingsChanged`.
+    pub action: String,
+    pub detail: serde_json::Value,
+    /// Hex SHA-256 of the previous entry.
+    pub prev_hash: String,
+    /// Hex SHA-256 over the fields above.
+    pub hash: String,
+    /// Base64 Ed25519 signature of the hash bytes.
+    pub signature: String,
+}
+
+/// The signed part of an entry, in a fixed field order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Signed<'a> {
+    seq: u64,
+    timestamp: &'a DateTime<Utc>,
+    actor: &'a str,
+    operator_mode: bool,
+    action: &'a str,
+    detail: &'a serde_json::Value,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    fn digest(&self) -> Result<[u8; 32]> {
+        let signed = Signed {
+            seq: self.seq,
+            timestamp: &self.timestamp,
+            actor: &self.actor,
+            operator_mode: self.operator_mode,
+            action: &self.action,
+            detail: &self.detail,
+            prev_hash: &self.prev_hash,
+        };
+        Ok(Sha256::digest(serde_json::to_vec(&signed)?).into())
+    }
+}
+
+/// Argument of [`query_audit_log`]; every field narrows the result.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// The most recent this many; 500 by default.
+    pub limit: Option<usize>,
+}
+
+/// Returned by [`verify_audit_log`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditVerification {
+    pub entries: u64,
+    pub valid: bool,
+    /// Line of the first entry that does not verify, from 1.
+    pub first_invalid_line: Option<usize>,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditExport<'a> {
+    exported_at: DateTime<Utc>,
+    /// Hex Ed25519 public key of this installation.
+    public_key: String,
+    verification: &'a AuditVerification,
+    entries: Vec<AuditEntry>,
+}
+
+struct Chain {
+    path: PathBuf,
+    key: Ed25519KeyPair,
+    seq: u64,
+    last_hash: String,
+}
+
+pub struct Audit(StdMutex<Chain>);
+
+/// Loads the signing key and continues the chain from the last entry.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_data_dir()?.join(LOG_FILE);
+    let key = signing_key().unwrap_or_else(|err| {
+        tracing::error!("audit signing key unavailable, using a session key: {err}");
+        generate_key().expect("the system RNG generates keys")
+    });
+    let (seq, last_hash) = match read_entries(&path)?.last() {
+        Some(Ok(entry)) => (entry.seq + 1, entry.hash.clone()),
+        Some(Err(_)) => {
+            tracing::error!("last audit entry is unreadable; the chain will not verify");
+            (0, GENESIS.to_string())
+        }
+        None => (0, GENESIS.to_string()),
+    };
+    app.manage(Audit(StdMutex::new(Chain {
+        path,
+        key,
+        seq,
+        last_hash,
+    })));
+    Ok(())
+}
+
+/// Appends an entry for `action`. Failures are logged, never returned, so
+/// the audited action itself is not held up.
+pub fn record(app: &AppHandle, action: &str, detail: serde_json::Value) {
+    let Some(audit) = app.try_state::<Audit>() else {
+        return;
+    };
+    // Looking up the user may record a timed-out session's logout, so it
+    // happens before the chain is locked.
+    let actor = users::current_name(app).unwrap_or_else(os_user);
+    let operator_mode = kiosk::is_active(app);
+    let mut chain = audit.0.lock().unwrap();
+    let mut entry = AuditEntry {
+        seq: chain.seq,
+        timestamp: Utc::now(),
+        actor,
+        operator_mode,
+        action: action.to_string(),
+        detail,
+        prev_hash: chain.last_hash.clone(),
+        hash: String::new(),
+        signature: String::new(),
+    };
+    let appended = entry.digest().and_then(|digest| {
+        entry.hash = hex::encode(digest);
+        entry.signature = STANDARD.encode(chain.key.sign(&digest));
+        append(&chain.path, &entry)
+    });
+    match appended {
+        Ok(()) => {
+            chain.seq += 1;
+            chain.last_hash = entry.hash;
+        }
+        Err(err) => tracing::error!(%action, "audit entry not written: {err}"),
+    }
+}
+
+/// The error of `result` for an entry's detail.
+pub fn outcome<T>(result: &Result<T>) -> Option<String> {
+    result.as_ref().err().map(ToString::to_string)
+}
+
+/// Records which sections differ between `old` and `new`.
+pub fn settings_changed(app: &AppHandle, old: &Settings, new: &Settings) {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return;
+    };
+    let sections: Vec<&String> = new
+        .iter()
+        .filter(|(section, value)| old.get(*section) != Some(value))
+        .map(|(section, _)| section)
+        .collect();
+    if !sections.is_empty() {
+        record(
+            app,
+            "settingsChanged",
+            serde_json::json!({ "sections": sections }),
+        );
+    }
+}
+
+#[tauri::command]
+pub async fn query_audit_log(app: AppHandle, query: Option<AuditQuery>) -> Result<Vec<AuditEntry>> {
+    let query = query.unwrap_or_default();
+    let path = app.state::<Audit>().0.lock().unwrap().path.clone();
+    let entries = tauri::async_runtime::spawn_blocking(move || read_entries(&path))
+        .await
+        .map_err(|err| Error::Audit(err.to_string()))??;
+    let matching: Vec<AuditEntry> = entries
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            query
+                .action
+                .as_ref()
+                .is_none_or(|action| entry.action == *action)
+        })
+        .filter(|entry| query.since.is_none_or(|since| entry.timestamp >= since))
+        .filter(|entry| query.until.is_none_or(|until| entry.timestamp < until))
+        .collect();
+    let skip = matching
+        .len()
+        .saturating_sub(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT));
+    Ok(matching.into_iter().skip(skip).collect())
+}
+
+#[tauri::command]
+pub async fn verify_audit_log(audit: State<'_, Audit>) -> Result<AuditVerification> {
+    let (path, public_key) = {
+        let chain = audit.0.lock().unwrap();
+        (chain.path.clone(), chain.key.public_key().as_ref().to_vec())
+    };
+    tauri::async_runtime::spawn_blocking(move || verify(&path, &public_key))
+        .await
+        .map_err(|err| Error::Audit(err.to_string()))?
+}
+
+/// Writes every entry, the public key and the verification result to
+/// `path` as JSON.
+#[tauri::command]
+pub async fn export_audit_log(audit: State<'_, Audit>, path: PathBuf) -> Result<AuditVerification> {
+    let (log, public_key) = {
+        let chain = audit.0.lock().unwrap();
+        (chain.path.clone(), chain.key.public_key().as_ref().to_vec())
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let verification = verify(&log, &public_key)?;
+        let export = AuditExport {
+            exported_at: Utc::now(),
+            public_key: hex::encode(&public_key),
+            verification: &verification,
+            entries: read_entries(&log)?.into_iter().filter_map(|entry| entry.ok()).collect(),
+        };
+        fsutil::write_atomic(&path, &serde_json::to_vec_pretty(&export)?)?;
+        tracing::info!(path = %path.display(), entries = verification.entries, "audit log exported");
+        Ok(verification)
+    })
+    .await
+    .map_err(|err| Error::Audit(err.to_string()))?
+}
+
+fn verify(path: &Path, public_key: &[u8]) -> Result<AuditVerification> {
+    let public_key = UnparsedPublicKey::new(&ED25519, public_key);
+    let mut prev_hash = GENESIS.to_string();
+    let mut entries = 0;
+    for (index, entry) in read_entries(path)?.into_iter().enumerate() {
+        let failure = match entry {
+            Err(err) => Some(format!("unreadable: {err}")),
+            Ok(entry) => {
+                let digest = entry.digest()?;
+                let signature = STANDARD.decode(&entry.signature).unwrap_or_default();
+                let failure = if entry.seq != entries {
+                    Some(format!(
+                        "sequence {} where {entries} was expected",
+                        entry.seq
+                    ))
+                } else if entry.prev_hash != prev_hash {
+                    Some("does not follow the previous entry".into())
+                } else if entry.hash != hex::encode(digest) {
+                    Some("contents do not match the hash".into())
+                } else if public_key.verify(&digest, &signature).is_err() {
+                    Some("signature does not verify".into())
+                } else {
+                    None
+                };
+                prev_hash = entry.hash;
+                failure
+            }
+        };
+        if let Some(reason) = failure {
+            return Ok(AuditVerification {
+                entries,
+                valid: false,
+                first_invalid_line: Some(index + 1),
+                reason: Some(reason),
+            });
+        }
+        entries += 1;
+    }
+    Ok(AuditVerification {
+        entries,
+        valid: true,
+        first_invalid_line: None,
+        reason: None,
+    })
+}
+
+type EntryLine = std::result::Result<AuditEntry, serde_json::Error>;
+
+fn read_entries(path: &Path) -> Result<Vec<EntryLine>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect())
+}
+
+fn append(path: &Path, entry: &AuditEntry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// The keychain's signing key, created on first use.
+fn signing_key() -> Result<Ed25519KeyPair> {
+    if let Some(stored) = secrets::get(KEY_SECRET)? {
+        let pkcs8 = STANDARD
+            .decode(stored)
+            .map_err(|err| Error::Audit(format!("signing key: {err}")))?;
+        return from_pkcs8(&pkcs8);
+    }
+    let pkcs8 = generate_pkcs8()?;
+    secrets::set(KEY_SECRET, &STANDARD.encode(&pkcs8))?;
+    tracing::info!("audit signing key created");
+    from_pkcs8(&pkcs8)
+}
+
+fn generate_key() -> Result<Ed25519KeyPair> {
+    from_pkcs8(&generate_pkcs8()?)
+}
+
+fn generate_pkcs8() -> Result<Vec<u8>> {
+    Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map(|pkcs8| pkcs8.as_ref().to_vec())
+        .map_err(|_| Error::Audit("signing key generation failed".into()))
+}
+
+fn from_pkcs8(pkcs8: &[u8]) -> Result<Ed25519KeyPair> {
+    Ed25519KeyPair::from_pkcs8(pkcs8).map_err(|err| Error::Audit(format!("signing key: {err}")))
+}
+
+fn os_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` entries signed with `key`, chained like [`record`] does.
+    fn chain(key: &Ed25519KeyPair, count: u64) -> Vec<AuditEntry> {
+        let mut prev_hash = GENESIS.to_string();
+        (0..count)
+            .map(|seq| {
+                let mut entry = AuditEntry {
+                    seq,
+                    timestamp: Utc::now(),
+                    actor: "tester".into(),
+                    operator_mode: false,
+                    action: "estop".into(),
+                    detail: serde_json::json!({ "seq": seq }),
+                    prev_hash: prev_hash.clone(),
+                    hash: String::new(),
+                    signature: String::new(),
+                };
+                let digest = entry.digest().unwrap();
+                entry.hash = hex::encode(digest);
+                entry.signature = STANDARD.encode(key.sign(&digest));
+                prev_hash = entry.hash.clone();
+                entry
+            })
+            .collect()
+    }
+
+    /// Writes `entries` to a fresh log and verifies it against `key`.
+    fn check(name: &str, entries: &[AuditEntry], key: &Ed25519KeyPair) -> AuditVerification {
+        let path = std::env::temp_dir().join(format!(
+            "percus-audit-test-{}-{name}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        for entry in entries {
+            append(&path, entry).unwrap();
+        }
+        let verification = verify(&path, key.public_key().as_ref()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        verification
+    }
+
+    #[test]
+    fn verifies_an_intact_chain() {
+        let key = generate_key().unwrap();
+        let verification = check("intact", &chain(&key, 3), &key);
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 3);
+        assert!(check("empty", &[], &key).valid);
+    }
+
+    #[test]
+    fn detects_edited_entries() {
+        let key = generate_key().unwrap();
+        let mut entries = chain(&key, 3);
+        entries[1].actor = "someone else".into();
+        let verification = check("edited", &entries, &key);
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid_line, Some(2));
+        assert_eq!(verification.entries, 1);
+    }
+
+    #[test]
+    fn detects_removed_and_reordered_entries() {
+        let key = generate_key().unwrap();
+        let mut entries = chain(&key, 3);
+        let removed = entries.remove(1);
+        assert_eq!(check("removed", &entries, &key).first_invalid_line, Some(2));
+        entries.insert(0, removed);
+        assert_eq!(
+            check("reordered", &entries, &key).first_invalid_line,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn detects_a_foreign_key() {
+        let key = generate_key().unwrap();
+        let other = generate_key().unwrap();
+        let verification = check("foreign", &chain(&key, 2), &other);
+        assert_eq!(verification.first_invalid_line, Some(1));
+        assert_eq!(
+            verification.reason.as_deref(),
+            Some("signature does not verify")
+        );
+    }
+
+    #[test]
+    fn cannot_tell_a_truncated_chain() {
+        // The limitation the module documentation states.
+        let key = generate_key().unwrap();
+        let mut entries = chain(&key, 3);
+        entries.pop();
+        assert!(check("truncated", &entries, &key).valid);
+    }
+}
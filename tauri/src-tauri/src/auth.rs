@@ -0,0 +1,480 @@
+//! OAuth sign-in to the cloud performed by the shell, so session tokens
+//! never live in the webview's storage.
+//!
+//! Two flows run against the provider configured in `auth`:
+//! [`start_device_login`] (RFC 8628) returns a code for the operator to
+//! enter on another device and polls the token endpoint in the background;
+//! [`start_browser_login`] opens the system browser with PKCE (RFC 7636) and
+//! receives the authorization code on a one-shot loopback listener
+//! (RFC 8252). Either ends with `auth-changed`.
+//!
+//! The refresh token is kept in the keychain as the `oauth-refresh-token`
+//! secret, the access token only in memory. [`get_access_token`] refreshes
+//! it when it expires within [`REFRESH_MARGIN`], storing the rotated refresh
+//! token, and the backend gets a fresh one in `auth.accessTokenEnv` on every
+//! spawn.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::error::{Error, Result};
+use crate::secrets;
+use crate::settings::{AuthSettings, SettingsStore};
+
+/// Keychain entry of the refresh token, which never reaches the webview.
+pub const REFRESH_SECRET: &str = "oauth-refresh-token";
+/// Access tokens this close to expiry are refreshed before being handed out.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the loopback listener waits for the browser's redirect.
+const BROWSER_TIMEOUT: Duration = Duration::from_secs(300);
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthChanged {
+    signed_in: bool,
+    error: Option<String>,
+}
+
+/// Returned by [`start_device_login`], to be shown to the operator.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLogin {
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Outcome of one token endpoint request.
+enum Grant {
+    Token(TokenResponse),
+    /// The provider's `error` code and description.
+    Denied(String, String),
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+pub struct Auth {
+    /// Async so concurrent callers wait for one refresh instead of racing.
+    token: tokio::sync::Mutex<Option<AccessToken>>,
+    /// The login flow in progress; starting another cancels it.
+    login: StdMutex<Option<JoinHandle<()>>>,
+}
+
+/// Starts the device authorization flow and returns the code to enter.
+#[tauri::command]
+pub async fn start_device_login(app: AppHandle, auth: State<'_, Auth>) -> Result<DeviceLogin> {
+    let settings = app.state::<SettingsStore>().get().auth;
+    let (client_id, _) = configured(&settings)?;
+    let endpoint = settings
+        .device_authorization_endpoint
+        .clone()
+        .ok_or_else(|| Error::Invalid("auth.deviceAuthorizationEndpoint is not set".into()))?;
+    let response = client()?
+        .post(endpoint)
+        .form(&[
+            ("client_id", client_id),
+            ("scope", &settings.scopes.join(" ")),
+        ])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::Auth(describe(response).await));
+    }
+    let device: DeviceAuthorization = response.json().await?;
+    let login = DeviceLogin {
+        user_code: device.user_code.clone(),
+        verification_uri: device.verification_uri.clone(),
+        verification_uri_complete: device.verification_uri_complete.clone(),
+        expires_in: device.expires_in,
+    };
+    let handle = app.clone();
+    replace_login(
+        &auth,
+        tauri::async_runtime::spawn(async move {
+            let result = poll_device(&settings, &device).await;
+            finish_login(&handle, result).await;
+        }),
+    );
+    Ok(login)
+}
+
+/// Opens the provider's sign-in page in the system browser and returns its
+/// URL, for showing when no browser could be opened.
+#[tauri::command]
+pub async fn start_browser_login(app: AppHandle, auth: State<'_, Auth>) -> Result<String> {
+    let settings = app.state::<SettingsStore>().get().auth;
+    let (client_id, _) = configured(&settings)?;
+    let authorization_endpoint = settings
+        .authorization_endpoint
+        .as_deref()
+        .ok_or_else(|| Error::Invalid("auth.authorizationEndpoint is not set".into()))?;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}/callback",
+        listener.local_addr()?.port()
+    );
+    let verifier = random_token()?;
+    let state = random_token()?;
+    let challenge = BASE64_URL.encode(Sha256::digest(verifier.as_bytes()));
+    let url = Url::parse_with_params(
+        authorization_endpoint,
+        [
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", &settings.scopes.join(" ")),
+            ("state", &state),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|err| Error::Invalid(format!("auth.authorizationEndpoint: {err}")))?
+    .to_string();
+
+    let handle = app.clone();
+    replace_login(
+        &auth,
+        tauri::async_runtime::spawn(async move {
+            let result = async {
+                let code = tokio::time::timeout(BROWSER_TIMEOUT, receive_code(&listener, &state))
+                    .await
+                    .map_err(|_| Error::Auth("sign-in timed out".into()))??;
+                let grant = request_token(
+                    &settings,
+                    &[
+                        ("grant_type", "authorization_code"),
+                        ("code", &code),
+                        ("redirect_uri", &redirect_uri),
+                        ("code_verifier", &verifier),
+                    ],
+                )
+                .await?;
+                match grant {
+                    Grant::Token(token) => Ok(token),
+                    Grant::Denied(error, description) => {
+                        Err(Error::Auth(format!("{error}: {description}")))
+                    }
+                }
+            }
+            .await;
+            finish_login(&handle, result).await;
+        }),
+    );
+    if let Err(err) = app.opener().open_url(&url, None::<&str>) {
+        tracing::warn!("cannot open the browser for sign-in: {err}");
+    }
+    Ok(url)
+}
+
+/// A valid access token, refreshed if needed; `None` when signed out.
+#[tauri::command]
+pub async fn get_access_token(app: AppHandle) -> Result<Option<String>> {
+    access_token(&app).await
+}
+
+/// Forgets the tokens.
+#[tauri::command]
+pub async fn logout(app: AppHandle, auth: State<'_, Auth>) -> Result<()> {
+    if let Some(login) = auth.login.lock().unwrap().take() {
+        login.abort();
+    }
+    auth.token.lock().await.take();
+    secrets::delete(REFRESH_SECRET)?;
+    emit_changed(&app, false, None);
+    Ok(())
+}
+
+pub async fn access_token(app: &AppHandle) -> Result<Option<String>> {
+    let auth = app.state::<Auth>();
+    let mut token = auth.token.lock().await;
+    if let Some(current) = token.as_ref() {
+        if current.expires_at > Instant::now() + REFRESH_MARGIN {
+            return Ok(Some(current.value.clone()));
+        }
+    }
+    let Some(refresh_token) = secrets::get(REFRESH_SECRET)? else {
+        return Ok(None);
+    };
+    let settings = app.state::<SettingsStore>().get().auth;
+    configured(&settings)?;
+    let grant = request_token(
+        &settings,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ],
+    )
+    .await?;
+    match grant {
+        Grant::Token(response) => {
+            let value = store(&mut token, response)?;
+            Ok(Some(value))
+        }
+        Grant::Denied(error, description) if error == "invalid_grant" => {
+            tracing::info!("refresh token rejected, signed out: {description}");
+            token.take();
+            secrets::delete(REFRESH_SECRET)?;
+            emit_changed(app, false, Some(description));
+            Ok(None)
+        }
+        Grant::Denied(error, description) => Err(Error::Auth(format!("{error}: {description}"))),
+    }
+}
+
+/// Claims of the signed-in identity from `auth.userinfoEndpoint`.
+pub async fn userinfo(app: &AppHandle) -> Result<serde_json::Value> {
+    let endpoint = app
+        .state::<SettingsStore>()
+        .get()
+        .auth
+        .userinfo_endpoint
+        .ok_or_else(|| Error::Invalid("auth.userinfoEndpoint must be set".into()))?;
+    let token = access_token(app)
+        .await?
+        .ok_or_else(|| Error::Auth("not signed in".into()))?;
+    let response = client()?
+        .get(endpoint)
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Environment carrying the access token to a newly spawned backend.
+pub async fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+    let settings = app.state::<SettingsStore>().get().auth;
+    if settings.client_id.is_none() {
+        return BTreeMap::new();
+    }
+    match access_token(app).await {
+        Ok(Some(token)) => BTreeMap::from([(settings.access_token_env, token)]),
+        Ok(None) => BTreeMap::new(),
+        Err(err) => {
+            tracing::warn!("backend started without an access token: {err}");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Client id and token endpoint, which every flow needs.
+fn configured(settings: &AuthSettings) -> Result<(&str, &str)> {
+    match (&settings.client_id, &settings.token_endpoint) {
+        (Some(client_id), Some(token_endpoint)) => Ok((client_id, token_endpoint)),
+        _ => Err(Error::Invalid(
+            "auth.clientId and auth.tokenEndpoint must be set".into(),
+        )),
+    }
+}
+
+fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?)
+}
+
+fn replace_login(auth: &Auth, login: JoinHandle<()>) {
+    if let Some(previous) = auth.login.lock().unwrap().replace(login) {
+        previous.abort();
+    }
+}
+
+async fn poll_device(
+    settings: &AuthSettings,
+    device: &DeviceAuthorization,
+) -> Result<TokenResponse> {
+    let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+    let mut interval = Duration::from_secs(device.interval.unwrap_or(5).max(1));
+    while Instant::now() < deadline {
+        tokio::time::sleep(interval).await;
+        let grant = request_token(
+            settings,
+            &[
+                ("grant_type", DEVICE_GRANT),
+                ("device_code", &device.device_code),
+            ],
+        )
+        .await?;
+        match grant {
+            Grant::Token(token) => return Ok(token),
+            Grant::Denied(error, _) if error == "authorization_pending" => {}
+            Grant::Denied(error, _) if error == "slow_down" => interval += Duration::from_secs(5),
+            Grant::Denied(error, description) => {
+                return Err(Error::Auth(format!("{error}: {description}")));
+            }
+        }
+    }
+    Err(Error::Auth("device code expired".into()))
+}
+
+async fn request_token(settings: &AuthSettings, params: &[(&str, &str)]) -> Result<Grant> {
+    let (client_id, token_endpoint) = configured(settings)?;
+    let mut form = params.to_vec();
+    form.push(("client_id", client_id));
+    let response = client()?.post(token_endpoint).form(&form).send().await?;
+    if response.status().is_success() {
+        return Ok(Grant::Token(response.json().await?));
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => Ok(Grant::Denied(
+            error.error,
+            error.error_description.unwrap_or_default(),
+        )),
+        Err(_) => Err(Error::Auth(format!("token endpoint answered {status}"))),
+    }
+}
+
+async fn describe(response: reqwest::Response) -> String {
+    let status = response.status();
+    match response.json::<ErrorResponse>().await {
+        Ok(error) => format!(
+            "{}: {}",
+            error.error,
+            error.error_description.unwrap_or_default()
+        ),
+        Err(_) => format!("provider answered {status}"),
+    }
+}
+
+/// Waits for the browser's redirect and returns the authorization code.
+async fn receive_code(listener: &TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = vec![0; 8192];
+        let len = stream.read(&mut request).await?;
+        let request = String::from_utf8_lossy(&request[..len]);
+        // Browsers also ask for /favicon.ico and the like.
+        let Some(target) = request
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("GET "))
+            .and_then(|line| line.split(' ').next())
+            .filter(|target| target.starts_with("/callback"))
+        else {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            continue;
+        };
+        let url = Url::parse(&format!("http://127.0.0.1{target}"))
+            .map_err(|err| Error::Auth(format!("redirect: {err}")))?;
+        let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+        let result = if query.get("state").map(String::as_str) != Some(state) {
+            Err(Error::Auth("redirect state does not match".into()))
+        } else if let Some(error) = query.get("error") {
+            Err(Error::Auth(format!(
+                "{error}: {}",
+                query
+                    .get("error_description")
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            )))
+        } else {
+            query
+                .get("code")
+                .cloned()
+                .ok_or_else(|| Error::Auth("redirect without a code".into()))
+        };
+        let page = match &result {
+            Ok(_) => "Signed in. You can close this window and return to Percus AI.",
+            Err(_) => "Sign-in failed. Return to Percus AI for details.",
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{page}",
+            page.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        return result;
+    }
+}
+
+async fn finish_login(app: &AppHandle, result: Result<TokenResponse>) {
+    let result = match result {
+        Ok(response) => {
+            let auth = app.state::<Auth>();
+            let mut token = auth.token.lock().await;
+            store(&mut token, response).map(drop)
+        }
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => {
+            tracing::info!("signed in");
+            emit_changed(app, true, None);
+        }
+        Err(err) => {
+            tracing::warn!("sign-in failed: {err}");
+            emit_changed(app, false, Some(err.to_string()));
+        }
+    }
+    app.state::<Auth>().login.lock().unwrap().take();
+}
+
+/// Keeps the access token and persists a rotated refresh token.
+fn store(token: &mut Option<AccessToken>, response: TokenResponse) -> Result<String> {
+    if let Some(refresh_token) = &response.refresh_token {
+        secrets::set(REFRESH_SECRET, refresh_token)?;
+    }
+    let lifetime = Duration::from_secs(response.expires_in.unwrap_or(3600));
+    *token = Some(AccessToken {
+        value: response.access_token.clone(),
+        expires_at: Instant::now() + lifetime,
+    });
+    Ok(response.access_token)
+}
+
+fn emit_changed(app: &AppHandle, signed_in: bool, error: Option<String>) {
+    let _ = app.emit("auth-changed", AuthChanged { signed_in, error });
+}
+
+/// 32 random bytes, base64url-encoded; used as PKCE verifier and state.
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|err| Error::Auth(format!("random: {err}")))?;
+    Ok(BASE64_URL.encode(bytes))
+}
@@ -0,0 +1,417 @@
+//! Opt-in automation server, so end-to-end suites can drive the shell
+//! without clicking through the UI.
+//!
+//! With `automation.enabled` set, or the shell started with [`FLAG`], the
+//! shell accepts JSON-RPC 2.0 on `automation.sock` in the app data directory
+//! (mode 0600), or the `\\.\pipe\percus-automation` named pipe on Windows.
+//! Requests and responses are one JSON object per line. The first request
+//! of a connection must be `authenticate` with `{"token": ...}`, the token
+//! the shell writes at startup to `automation-token` in the app data
+//! directory.
+//!
+//! Every other method is a Tauri command of the same name, dispatched by
+//! [`call`] to the command function with the parameters named as in
+//! `invoke` (camelCase). Only the commands listed there are available;
+//! `list_methods` returns them, and a command added to the webview needs an
+//! arm there too. Command errors are answered with code [`COMMAND_ERROR`]
+//! and the message the webview would see.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use subtle::ConstantTimeEq;
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::audio;
+use crate::backend_socket;
+use crate::backend_tls;
+use crate::bag::{self, Bag};
+use crate::compat;
+use crate::daihen_fd::{self, FdController};
+use crate::datasets;
+use crate::diagnostics;
+use crate::disk_guard;
+use crate::driver_plugins::{self, Plugins};
+use crate::error::{Error, Result};
+use crate::jog::{self, Jog};
+use crate::permissions;
+use crate::preflight;
+use crate::profiles::{self, Profiles};
+use crate::recording::{self, Recorder, RecoveredEpisodes};
+use crate::remote_assist;
+use crate::retention;
+use crate::scheduler::{self, Scheduler};
+use crate::screen_capture::{self, ScreenCapture};
+use crate::settings::{self, SettingsStore};
+use crate::sidecar::{self, Sessions, SidecarState};
+use crate::simulator::{self, Simulator};
+use crate::time_sync::{self, TimeSync};
+use crate::voice::{self, Voice};
+
+pub const FLAG: &str = "--automation";
+const TOKEN_FILE: &str = "automation-token";
+#[cfg(unix)]
+const SOCKET_FILE: &str = "automation.sock";
+#[cfg(windows)]
+const PIPE_NAME: &str = r"\\.\pipe\percus-automation";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// A command ran and returned an error.
+pub const COMMAND_ERROR: i64 = -32000;
+const UNAUTHENTICATED: i64 = -32001;
+
+const METHODS: &[&str] = &[
+    "backend_status",
+    "get_backend_port",
+    "get_backend_url",
+    "get_backend_compatibility",
+    "restart_backend",
+    "create_session",
+    "list_sessions",
+    "stop_session",
+    "list_profiles",
+    "switch_profile",
+    "get_settings",
+    "set_settings",
+    "start_recording",
+    "stop_recording",
+    "discard_episode",
+    "set_audio_muted",
+    "list_recovered_episodes",
+    "recover_episode",
+    "discard_recovered_episode",
+    "list_audio_devices",
+    "set_ptt",
+    "list_datasets",
+    "list_episodes",
+    "inspect_episode",
+    "start_bag",
+    "stop_bag",
+    "connect_robot",
+    "disconnect_robot",
+    "robot_status",
+    "start_program",
+    "stop_program",
+    "get_time_sync_status",
+    "list_plugins",
+    "invoke_plugin",
+    "run_network_preflight",
+    "get_permission_status",
+    "list_jobs",
+    "run_job_now",
+    "get_disk_status",
+    "preview_pruning",
+    "export_diagnostics",
+    "start_screen_capture",
+    "stop_screen_capture",
+    "get_remote_assist_status",
+    "end_remote_assist",
+    "start_sim",
+    "stop_sim",
+    "sim_status",
+    "jog",
+    "stop_jog",
+    "jog_status",
+];
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Starts the server when enabled in settings or on the command line.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let enabled = app.state::<SettingsStore>().get().automation.enabled
+        || std::env::args().skip(1).any(|arg| arg == FLAG);
+    if !enabled {
+        return Ok(());
+    }
+    let dir = app.path().app_data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let mut token = [0u8; 32];
+    getrandom::fill(&mut token).map_err(|err| Error::Invalid(format!("random: {err}")))?;
+    let token = hex::encode(token);
+    backend_tls::write_private(&dir.join(TOKEN_FILE), token.as_bytes())?;
+
+    #[cfg(unix)]
+    {
+        let path = dir.join(SOCKET_FILE);
+        // Left behind by a previous run that did not exit cleanly.
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        tracing::info!(path = %path.display(), "automation server listening");
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tauri::async_runtime::spawn(serve(app.clone(), stream, token.clone()));
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(PIPE_NAME)?;
+        tracing::info!(pipe = PIPE_NAME, "automation server listening");
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            while server.connect().await.is_ok() {
+                // A fresh instance takes the next client while this one is served.
+                let next = match ServerOptions::new().create(PIPE_NAME) {
+                    Ok(next) => next,
+                    Err(err) => return tracing::error!("automation pipe closed: {err}"),
+                };
+                let client = std::mem::replace(&mut server, next);
+                tauri::async_runtime::spawn(serve(app.clone(), client, token.clone()));
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answers requests on one connection until it closes.
+async fn serve<S: AsyncRead + AsyncWrite + Send + 'static>(
+    app: AppHandle,
+    stream: S,
+    token: String,
+) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let mut authenticated = false;
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, outcome) = match serde_json::from_str::<Request>(&line) {
+            Err(err) => (Value::Null, Err(rpc_error(PARSE_ERROR, err.to_string()))),
+            Ok(request) if request.jsonrpc != "2.0" => (
+                request.id.unwrap_or_default(),
+                Err(rpc_error(INVALID_REQUEST, "jsonrpc must be \"2.0\"")),
+            ),
+            Ok(request) => {
+                let outcome = if request.method == "authenticate" {
+                    authenticated = request
+                        .params
+                        .get("token")
+                        .and_then(Value::as_str)
+                        .is_some_and(|given| given.as_bytes().ct_eq(token.as_bytes()).into());
+                    if authenticated {
+                        Ok(Value::Null)
+                    } else {
+                        Err(rpc_error(UNAUTHENTICATED, "wrong token"))
+                    }
+                } else if !authenticated {
+                    Err(rpc_error(UNAUTHENTICATED, "authenticate first"))
+                } else {
+                    call(&app, &request.method, &request.params).await
+                };
+                let Some(id) = request.id else {
+                    continue;
+                };
+                (id, outcome)
+            }
+        };
+        let response = match outcome {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        };
+        let mut line = response.to_string();
+        line.push('\n');
+        if writer.write_all(line.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn rpc_error(code: i64, message: impl Into<String>) -> RpcError {
+    RpcError {
+        code,
+        message: message.into(),
+    }
+}
+
+/// The named parameter `name`; a missing one reads as `null`, so optional
+/// parameters may be left out.
+fn arg<T: DeserializeOwned>(params: &Value, name: &str) -> std::result::Result<T, RpcError> {
+    let value = params.get(name).cloned().unwrap_or_default();
+    serde_json::from_value(value).map_err(|err| rpc_error(INVALID_PARAMS, format!("{name}: {err}")))
+}
+
+fn reply<T: Serialize>(result: Result<T>) -> std::result::Result<Value, RpcError> {
+    match result.and_then(|value| Ok(serde_json::to_value(value)?)) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(rpc_error(COMMAND_ERROR, err.to_string())),
+    }
+}
+
+async fn call(
+    app: &AppHandle,
+    method: &str,
+    params: &Value,
+) -> std::result::Result<Value, RpcError> {
+    let app = app.clone();
+    match method {
+        "list_methods" => reply(Ok(METHODS)),
+        "backend_status" => reply(Ok(sidecar::backend_status(app.state::<SidecarState>()))),
+        "get_backend_port" => reply(Ok(sidecar::get_backend_port(app.state::<SidecarState>()))),
+        "get_backend_url" => reply(Ok(backend_socket::get_backend_url(app.state()))),
+        "get_backend_compatibility" => reply(Ok(compat::get_backend_compatibility(app.state()))),
+        "restart_backend" => {
+            sidecar::restart_backend(app).await;
+            reply(Ok(()))
+        }
+        "create_session" => reply(sidecar::create_session(
+            app.clone(),
+            app.state::<Sessions>(),
+            arg(params, "config")?,
+        )),
+        "list_sessions" => reply(Ok(sidecar::list_sessions(app.state::<Sessions>()))),
+        "stop_session" => reply(
+            sidecar::stop_session(app.clone(), app.state::<Sessions>(), arg(params, "id")?).await,
+        ),
+        "list_profiles" => reply(Ok(profiles::list_profiles(app.state::<Profiles>()))),
+        "switch_profile" => reply(profiles::switch_profile(app, arg(params, "name")?).await),
+        "get_settings" => reply(Ok(settings::get_settings(app.state::<SettingsStore>()))),
+        "set_settings" => reply(settings::set_settings(
+            app.clone(),
+            app.state::<SettingsStore>(),
+            arg(params, "settings")?,
+        )),
+        "start_recording" => reply(
+            recording::start_recording(
+                app.clone(),
+                app.state::<Recorder>(),
+                arg(params, "request")?,
+            )
+            .await,
+        ),
+        "stop_recording" => {
+            reply(recording::stop_recording(app.clone(), app.state::<Recorder>()).await)
+        }
+        "discard_episode" => {
+            reply(recording::discard_episode(app.clone(), app.state::<Recorder>()).await)
+        }
+        "set_audio_muted" => reply(recording::set_audio_muted(
+            app.state::<Recorder>(),
+            arg(params, "muted")?,
+        )),
+        "list_recovered_episodes" => reply(Ok(recording::list_recovered_episodes(
+            app.state::<RecoveredEpisodes>(),
+        ))),
+        "recover_episode" => reply(
+            recording::recover_episode(
+                app.clone(),
+                app.state::<Recorder>(),
+                app.state::<RecoveredEpisodes>(),
+                arg(params, "id")?,
+            )
+            .await,
+        ),
+        "discard_recovered_episode" => reply(recording::discard_recovered_episode(
+            app.state::<RecoveredEpisodes>(),
+            arg(params, "id")?,
+        )),
+        "list_audio_devices" => reply(audio::list_audio_devices().await),
+        "set_ptt" => reply(voice::set_ptt(app.state::<Voice>(), arg(params, "active")?)),
+        "list_datasets" => reply(datasets::list_datasets(app)),
+        "list_episodes" => reply(datasets::list_episodes(app, arg(params, "dataset")?)),
+        "inspect_episode" => reply(datasets::inspect_episode(
+            app,
+            arg(params, "dataset")?,
+            arg(params, "episodeIndex")?,
+        )),
+        "start_bag" => reply(bag::start_bag(
+            app.clone(),
+            app.state::<Bag>(),
+            arg(params, "name")?,
+        )),
+        "stop_bag" => reply(bag::stop_bag(app.clone(), app.state::<Bag>())),
+        "connect_robot" => {
+            reply(daihen_fd::connect_robot(app.clone(), app.state::<FdController>()).await)
+        }
+        "disconnect_robot" => {
+            reply(daihen_fd::disconnect_robot(app.clone(), app.state::<FdController>()).await)
+        }
+        "robot_status" => reply(Ok(daihen_fd::robot_status(app.state::<FdController>()))),
+        "start_program" => reply(
+            daihen_fd::start_program(
+                app.clone(),
+                app.state::<FdController>(),
+                arg(params, "number")?,
+            )
+            .await,
+        ),
+        "stop_program" => {
+            reply(daihen_fd::stop_program(app.clone(), app.state::<FdController>()).await)
+        }
+        "get_time_sync_status" => {
+            reply(Ok(time_sync::get_time_sync_status(app.state::<TimeSync>())))
+        }
+        "list_plugins" => reply(Ok(driver_plugins::list_plugins(app.state::<Plugins>()))),
+        "invoke_plugin" => reply(
+            driver_plugins::invoke_plugin(
+                app.state::<Plugins>(),
+                arg(params, "plugin")?,
+                arg(params, "command")?,
+                arg(params, "args")?,
+            )
+            .await,
+        ),
+        "run_network_preflight" => reply(Ok(preflight::run_network_preflight(app.clone()).await)),
+        "get_permission_status" => reply(Ok(permissions::get_permission_status())),
+        "list_jobs" => reply(Ok(scheduler::list_jobs(
+            app.clone(),
+            app.state::<Scheduler>(),
+        ))),
+        "run_job_now" => reply(scheduler::run_job_now(app.clone(), arg(params, "id")?).await),
+        "get_disk_status" => reply(disk_guard::get_disk_status(app.clone())),
+        "preview_pruning" => reply(retention::preview_pruning(app.clone()).await),
+        "start_screen_capture" => reply(
+            screen_capture::start_screen_capture(
+                app.clone(),
+                app.state::<ScreenCapture>(),
+                arg(params, "window")?,
+            )
+            .await,
+        ),
+        "stop_screen_capture" => reply(
+            screen_capture::stop_screen_capture(app.clone(), app.state::<ScreenCapture>()).await,
+        ),
+        "get_remote_assist_status" => {
+            reply(Ok(remote_assist::get_remote_assist_status(app.clone())))
+        }
+        "end_remote_assist" => reply(remote_assist::end_remote_assist(app.clone()).await),
+        "start_sim" => reply(simulator::start_sim(app.clone(), arg(params, "profile")?).await),
+        "stop_sim" => reply(simulator::stop_sim(app.clone()).await),
+        "sim_status" => reply(Ok(simulator::sim_status(app.state::<Simulator>()))),
+        "jog" => reply(jog::jog(app.clone(), arg(params, "command")?)),
+        "stop_jog" => reply(jog::stop_jog(app.clone(), app.state::<Jog>())),
+        "jog_status" => reply(jog::jog_status(app.clone(), app.state::<Jog>())),
+        "export_diagnostics" => {
+            reply(diagnostics::export_diagnostics(app.clone(), arg(params, "output")?).await)
+        }
+        _ => Err(rpc_error(METHOD_NOT_FOUND, format!("no method {method}"))),
+    }
+}
@@ -0,0 +1,163 @@
+//! Classification of fatal sidecar errors from stderr.
+//!
+//! Recognised failures are surfaced as `backend-error` events with a stable
+//! error code and as OS notifications, rate-limited per code so a crash loop
+//! does not flood the desktop. Other sessions' backends report as
+//! `session:<id>:backend-error`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::notifications::{self, Action, Category, Notice};
+use crate::sidecar;
+
+const TRACEBACK_HEADER: &str = "Traceback (most recent call last):";
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_TRACEBACK_LINES: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    PortInUse,
+    CudaOutOfMemory,
+    CudaError,
+    PythonException,
+}
+
+impl ErrorCode {
+    fn title(self) -> &'static str {
+        match self {
+            ErrorCode::PortInUse => "Backend port is already in use",
+            ErrorCode::CudaOutOfMemory => "GPU ran out of memory",
+            ErrorCode::CudaError => "GPU (CUDA) error",
+            ErrorCode::PythonException => "Backend error",
+        }
+    }
+
+    /// Matches a single line against the known fatal patterns.
+    fn match_line(line: &str) -> Option<Self> {
+        let lower = line.to_ascii_lowercase();
+        if lower.contains("address already in use") || lower.contains("[errno 98]") {
+            Some(ErrorCode::PortInUse)
+        } else if lower.contains("cuda out of memory") {
+            Some(ErrorCode::CudaOutOfMemory)
+        } else if lower.contains("cuda error")
+            || lower.contains("cudaerror")
+            || lower.contains("no cuda-capable device")
+        {
+            Some(ErrorCode::CudaError)
+        } else {
+            None
+        }
+    }
+}
+
+/// Payload of the `backend-error` event.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Full traceback, when the error was raised as a Python exception.
+    pub detail: Option<String>,
+}
+
+/// Watches a sidecar's stderr for fatal patterns, across its runs.
+pub struct ErrorClassifier {
+    app: AppHandle,
+    session: Option<String>,
+    traceback: Option<Vec<String>>,
+    last_notified: HashMap<ErrorCode, Instant>,
+}
+
+impl ErrorClassifier {
+    pub fn new(app: &AppHandle) -> Self {
+        Self {
+            app: app.clone(),
+            session: None,
+            traceback: None,
+            last_notified: HashMap::new(),
+        }
+    }
+
+    /// For the backend of session `id`.
+    pub fn for_session(app: &AppHandle, id: &str) -> Self {
+        Self {
+            session: Some(id.to_owned()),
+            ..Self::new(app)
+        }
+    }
+
+    /// Drops a traceback the previous run left unfinished.
+    pub fn restarted(&mut self) {
+        self.traceback = None;
+    }
+
+    pub fn line(&mut self, bytes: &[u8]) {
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end();
+
+        if line.starts_with(TRACEBACK_HEADER) {
+            self.traceback = Some(vec![line.to_owned()]);
+            return;
+        }
+        if let Some(frames) = self.traceback.as_mut() {
+            if line.starts_with([' ', '\t']) {
+                if frames.len() < MAX_TRACEBACK_LINES {
+                    frames.push(line.to_owned());
+                }
+                return;
+            }
+            // The first unindented line after the frames is the exception itself.
+            let mut frames = self.traceback.take().unwrap_or_default();
+            frames.push(line.to_owned());
+            let code = ErrorCode::match_line(line).unwrap_or(ErrorCode::PythonException);
+            self.report(BackendError {
+                code,
+                message: line.to_owned(),
+                detail: Some(frames.join("\n")),
+            });
+            return;
+        }
+
+        if let Some(code) = ErrorCode::match_line(line) {
+            self.report(BackendError {
+                code,
+                message: line.to_owned(),
+                detail: None,
+            });
+        }
+    }
+
+    fn report(&mut self, error: BackendError) {
+        tracing::error!(code = ?error.code, session = ?self.session, "{}", error.message);
+
+        let now = Instant::now();
+        let due = self
+            .last_notified
+            .get(&error.code)
+            .is_none_or(|last| now.duration_since(*last) >= NOTIFY_INTERVAL);
+        if due {
+            self.last_notified.insert(error.code, now);
+            let title = match &self.session {
+                Some(id) => format!("{} ({id})", error.code.title()),
+                None => error.code.title().to_owned(),
+            };
+            notifications::notify(
+                &self.app,
+                Notice::new(Category::Backend, title, &error.message)
+                    .action(Action::OpenLogs)
+                    .action(Action::RestartBackend),
+            );
+        }
+
+        let event = match &self.session {
+            Some(id) => sidecar::session_event(id, "backend-error"),
+            None => "backend-error".to_owned(),
+        };
+        let _ = self.app.emit(&event, error);
+    }
+}
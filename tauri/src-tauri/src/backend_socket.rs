@@ -0,0 +1,200 @@
+//! Local-socket transport between the webview and the primary backend.
+//!
+//! With `backend.transport` at `socket`, `percus-server` is spawned with
+//! [`spawn_args`] and serves the webview's API on a Unix domain socket,
+//! `backend.sock` in the app data directory, instead of a TCP port. The
+//! backend cannot serve the named pipe `\\.\pipe\percus-backend-<shell pid>`
+//! yet, so on Windows ([`SUPPORTED`]) it keeps serving TCP.
+//! Nothing listens on the network, so there are no firewall prompts and no
+//! port to clash over. The webview sends its requests to
+//! `backend://localhost/<path>` (`http://backend.localhost/<path>` on
+//! Windows), which [`handle`] forwards over the socket; responses are
+//! buffered whole. WebSockets go through [`crate::ws_proxy`], and the
+//! shell's own requests keep using the loopback TLS port of
+//! [`crate::backend_tls`].
+//!
+//! `tcp`, the default, keeps the plain port for setups where the backend
+//! cannot bind a socket; [`get_backend_url`] tells the webview which base
+//! URL to use.
+//! Session backends always serve TCP.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use http_body_util::{BodyExt, Full};
+use hyper_util::rt::TokioIo;
+use tauri::http::{header, HeaderValue, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Runtime, State, UriSchemeContext, UriSchemeResponder};
+
+use crate::error::{Error, Result};
+use crate::otel;
+use crate::sidecar::SidecarState;
+
+pub const SCHEME: &str = "backend";
+/// Whether the backend can serve the webview on [`path`].
+pub const SUPPORTED: bool = cfg!(unix);
+#[cfg(unix)]
+const SOCKET_FILE: &str = "backend.sock";
+/// Not forwarded in either direction.
+const HOP_BY_HOP: &[header::HeaderName] = &[
+    header::CONNECTION,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::TE,
+    header::TRAILER,
+];
+
+/// Where the primary backend is asked to listen.
+#[cfg(unix)]
+pub fn path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(SOCKET_FILE))
+}
+
+/// Where the primary backend is asked to listen; one pipe per shell, so a
+/// second instance cannot collide.
+#[cfg(windows)]
+pub fn path(_app: &AppHandle) -> Result<PathBuf> {
+    Ok(PathBuf::from(format!(
+        r"\\.\pipe\percus-backend-{}",
+        std::process::id()
+    )))
+}
+
+/// Arguments telling `percus-server` to serve the webview on `path`.
+pub fn spawn_args(path: &Path) -> Vec<String> {
+    let flag = if cfg!(windows) { "--pipe" } else { "--uds" };
+    vec![flag.into(), path.display().to_string()]
+}
+
+/// Removes the socket a previous run left behind, which would keep the
+/// backend from binding it.
+pub fn remove_stale(path: &Path) {
+    #[cfg(unix)]
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            tracing::warn!(path = %path.display(), "stale backend socket not removed: {err}");
+        }
+        _ => {}
+    }
+    #[cfg(windows)]
+    let _ = path;
+}
+
+/// Base URL of the webview's API requests to the primary backend.
+#[tauri::command]
+pub fn get_backend_url(state: State<'_, SidecarState>) -> String {
+    match state.socket() {
+        Some(_) if cfg!(windows) => format!("http://{SCHEME}.localhost"),
+        Some(_) => format!("{SCHEME}://localhost"),
+        None => format!("http://127.0.0.1:{}", state.port()),
+    }
+}
+
+/// Forwards a `backend://` request of the webview to the backend.
+pub fn handle<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let socket = ctx
+        .app_handle()
+        .try_state::<SidecarState>()
+        .and_then(|state| state.socket());
+    tauri::async_runtime::spawn(async move {
+        let Some(socket) = socket else {
+            return responder.respond(status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "the backend does not serve a socket; see get_backend_url",
+            ));
+        };
+        match forward(&socket, request).await {
+            Ok(response) => responder.respond(response),
+            Err(err) => {
+                tracing::debug!("backend request failed: {err}");
+                responder.respond(status(StatusCode::BAD_GATEWAY, &err.to_string()));
+            }
+        }
+    });
+}
+
+/// Sends `request` over a new connection to `socket`.
+#[tracing::instrument(skip_all, fields(method = %request.method(), path = request.uri().path()))]
+pub async fn forward(
+    socket: &Path,
+    request: Request<Vec<u8>>,
+) -> Result<Response<Cow<'static, [u8]>>> {
+    let (mut parts, body) = request.into_parts();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |path| path.as_str())
+        .to_owned();
+    parts.uri = path
+        .parse()
+        .map_err(|err| Error::Invalid(format!("backend request path {path}: {err}")))?;
+    for name in HOP_BY_HOP {
+        parts.headers.remove(name);
+    }
+    parts
+        .headers
+        .insert(header::HOST, HeaderValue::from_static("localhost"));
+    otel::inject(&mut parts.headers);
+    let request = Request::from_parts(parts, Full::new(bytes::Bytes::from(body)));
+
+    let stream = connect(socket).await?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(stream_error)?;
+    tauri::async_runtime::spawn(async move {
+        if let Err(err) = connection.await {
+            tracing::debug!("backend socket connection failed: {err}");
+        }
+    });
+    let response = sender.send_request(request).await.map_err(stream_error)?;
+    let (mut parts, body) = response.into_parts();
+    let body = body.collect().await.map_err(stream_error)?.to_bytes();
+    for name in HOP_BY_HOP {
+        parts.headers.remove(name);
+    }
+    // The webview's origin differs from the scheme's.
+    parts
+        .headers
+        .entry(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .or_insert(HeaderValue::from_static("*"));
+    Ok(Response::from_parts(parts, Cow::Owned(body.to_vec())))
+}
+
+#[cfg(unix)]
+async fn connect(socket: &Path) -> Result<tokio::net::UnixStream> {
+    Ok(tokio::net::UnixStream::connect(socket).await?)
+}
+
+#[cfg(windows)]
+async fn connect(socket: &Path) -> Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+    /// `ERROR_PIPE_BUSY`: every instance of the pipe is serving a client.
+    const PIPE_BUSY: i32 = 231;
+    loop {
+        match ClientOptions::new().open(socket) {
+            Ok(client) => return Ok(client),
+            Err(err) if err.raw_os_error() == Some(PIPE_BUSY) => {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+fn stream_error(err: hyper::Error) -> Error {
+    Error::Stream(format!("backend socket: {err}"))
+}
+
+fn status(code: StatusCode, message: &str) -> Response<Cow<'static, [u8]>> {
+    let mut response = Response::new(Cow::Owned(message.as_bytes().to_vec()));
+    *response.status_mut() = code;
+    response.headers_mut().insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    response
+}
@@ -0,0 +1,229 @@
+//! Mutually authenticated TLS for the shell's own requests to the sidecar.
+//!
+//! On first launch a per-install CA is generated under `tls/` in the app
+//! data directory and signs a server certificate for `127.0.0.1` and a
+//! client certificate for the shell. The CA key is never written to disk,
+//! so nothing else can ever be issued by it. The sidecar is spawned with
+//! [`spawn_args`] and serves the shell on its TLS port, requiring a client
+//! certificate from that CA; the shell in turn trusts only that CA, not the
+//! system roots, which pins the connection to this install's backend.
+//!
+//! Health checks, the WebSocket proxy, recording and relayed streams go
+//! through [`http_client`], [`ws_connect`] and [`url`]. The webview talks to
+//! the socket of [`crate::backend_socket`], or to the plain port.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::otel;
+use crate::sidecar::SidecarState;
+
+const DIR: &str = "tls";
+const CA: &str = "ca.pem";
+const SERVER_CERT: &str = "server.pem";
+const SERVER_KEY: &str = "server-key.pem";
+const CLIENT_CERT: &str = "client.pem";
+const CLIENT_KEY: &str = "client-key.pem";
+
+/// Certificate paths and the clients configured with them.
+pub struct BackendTls {
+    dir: PathBuf,
+    http: reqwest::Client,
+    ws: Arc<rustls::ClientConfig>,
+}
+
+/// Loads the install's certificates, generating them on first launch.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let dir = app.path().app_data_dir()?.join(DIR);
+    let tls = match load(&dir) {
+        Ok(tls) => tls,
+        Err(err) => {
+            if dir.join(CA).exists() {
+                tracing::warn!("regenerating backend certificates: {err}");
+            }
+            generate(&dir)?;
+            load(&dir)?
+        }
+    };
+    app.manage(tls);
+    Ok(())
+}
+
+/// Arguments telling `percus-server` where to serve the shell.
+pub fn spawn_args(app: &AppHandle, tls_port: u16) -> Vec<String> {
+    let dir = &app.state::<BackendTls>().dir;
+    let path = |name: &str| dir.join(name).display().to_string();
+    vec![
+        "--tls-port".into(),
+        tls_port.to_string(),
+        "--ssl-certfile".into(),
+        path(SERVER_CERT),
+        "--ssl-keyfile".into(),
+        path(SERVER_KEY),
+        "--ssl-ca-certs".into(),
+        path(CA),
+    ]
+}
+
+/// `https` or `wss` URL of `path` on the sidecar's TLS port.
+pub fn url(app: &AppHandle, scheme: &str, path: &str) -> String {
+    url_at(app.state::<SidecarState>().tls_port(), scheme, path)
+}
+
+/// `https` or `wss` URL of `path` on the backend serving TLS on `port`,
+/// e.g. one of [`crate::sidecar`]'s sessions.
+pub fn url_at(port: u16, scheme: &str, path: &str) -> String {
+    format!("{scheme}://127.0.0.1:{port}{path}")
+}
+
+/// HTTP client presenting the shell's certificate and trusting only the
+/// install's CA.
+pub fn http_client(app: &AppHandle) -> reqwest::Client {
+    app.state::<BackendTls>().http.clone()
+}
+
+/// Opens the backend WebSocket at `path` over mutual TLS.
+pub async fn ws_connect(
+    app: &AppHandle,
+    path: &str,
+) -> std::result::Result<
+    WebSocketStream<MaybeTlsStream<TcpStream>>,
+    tokio_tungstenite::tungstenite::Error,
+> {
+    let connector = Connector::Rustls(app.state::<BackendTls>().ws.clone());
+    let mut request = url(app, "wss", path).into_client_request()?;
+    otel::inject(request.headers_mut());
+    let (socket, _) =
+        tokio_tungstenite::connect_async_tls_with_config(request, None, false, Some(connector))
+            .await?;
+    Ok(socket)
+}
+
+/// FFmpeg input options for reading an `https` URL of the sidecar.
+pub fn ffmpeg_input_args(app: &AppHandle) -> Vec<String> {
+    let dir = &app.state::<BackendTls>().dir;
+    let path = |name: &str| dir.join(name).display().to_string();
+    vec![
+        "-tls_verify".into(),
+        "1".into(),
+        "-ca_file".into(),
+        path(CA),
+        "-cert_file".into(),
+        path(CLIENT_CERT),
+        "-key_file".into(),
+        path(CLIENT_KEY),
+    ]
+}
+
+fn load(dir: &Path) -> Result<BackendTls> {
+    let read = |name: &str| std::fs::read(dir.join(name));
+    let ca = read(CA)?;
+    let client_cert = read(CLIENT_CERT)?;
+    let client_key = read(CLIENT_KEY)?;
+    for name in [SERVER_CERT, SERVER_KEY] {
+        if !dir.join(name).is_file() {
+            return Err(Error::NotFound(format!("{name} in {}", dir.display())));
+        }
+    }
+
+    let identity = [client_cert.as_slice(), client_key.as_slice()].concat();
+    let http = reqwest::Client::builder()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca)?)
+        .identity(reqwest::Identity::from_pem(&identity)?)
+        .build()?;
+
+    let invalid =
+        |err: rustls::pki_types::pem::Error| Error::Invalid(format!("certificate: {err}"));
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(&ca) {
+        roots
+            .add(cert.map_err(invalid)?)
+            .map_err(|err| Error::Invalid(format!("ca certificate: {err}")))?;
+    }
+    let chain = CertificateDer::pem_slice_iter(&client_cert)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(invalid)?;
+    let key = PrivateKeyDer::from_pem_slice(&client_key).map_err(invalid)?;
+    let ws = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .and_then(|builder| {
+        builder
+            .with_root_certificates(roots)
+            .with_client_auth_cert(chain, key)
+    })
+    .map_err(|err| Error::Invalid(format!("backend tls: {err}")))?;
+
+    Ok(BackendTls {
+        dir: dir.to_owned(),
+        http,
+        ws: Arc::new(ws),
+    })
+}
+
+fn generate(dir: &Path) -> Result<()> {
+    let failed = |err: rcgen::Error| Error::Invalid(format!("certificate generation: {err}"));
+
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).map_err(failed)?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Percus AI local backend CA");
+    let ca_key = KeyPair::generate().map_err(failed)?;
+    let ca = ca_params.self_signed(&ca_key).map_err(failed)?;
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let leaf = |names: Vec<String>, common_name: &str, usage: ExtendedKeyUsagePurpose| {
+        let mut params = CertificateParams::new(names)?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![usage];
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &issuer)?;
+        Ok::<_, rcgen::Error>((cert.pem(), key.serialize_pem()))
+    };
+    let (server_cert, server_key) = leaf(
+        vec!["127.0.0.1".into(), "localhost".into()],
+        "percus-server",
+        ExtendedKeyUsagePurpose::ServerAuth,
+    )
+    .map_err(failed)?;
+    let (client_cert, client_key) = leaf(
+        Vec::new(),
+        "percus-shell",
+        ExtendedKeyUsagePurpose::ClientAuth,
+    )
+    .map_err(failed)?;
+
+    write_private(&dir.join(SERVER_KEY), server_key.as_bytes())?;
+    write_private(&dir.join(CLIENT_KEY), client_key.as_bytes())?;
+    fsutil::write_atomic(&dir.join(SERVER_CERT), server_cert.as_bytes())?;
+    fsutil::write_atomic(&dir.join(CLIENT_CERT), client_cert.as_bytes())?;
+    // Written last: its presence marks a complete set.
+    fsutil::write_atomic(&dir.join(CA), ca.pem().as_bytes())?;
+    tracing::info!(dir = %dir.display(), "generated backend certificates");
+    Ok(())
+}
+
+/// Writes a file readable by the user only, e.g. a private key.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    Ok(fsutil::write_atomic_private(path, contents)?)
+}
@@ -0,0 +1,274 @@
+//! Raw, replayable MCAP logs of the shell's traffic.
+//!
+//! While a bag is open every event named in `bag.channels` is written as a
+//! JSON message on the channel of the same name, timestamped when the shell
+//! saw it. Teleop samples forwarded over UDP never become events, so the
+//! input sink records them directly through [`record`]. Channels with a
+//! known payload get a JSON Schema registered (see [`SCHEMAS`]); any other
+//! event is recorded schemaless, which Foxglove and the `mcap` CLI still
+//! read.
+//!
+//! Files are named `<name>_000.mcap`, `<name>_001.mcap`, ... and a new one is
+//! started once the current one exceeds `splitSizeMb`, so a crash loses at
+//! most the unfinished file's index rather than the whole session.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+
+use mcap::records::MessageHeader;
+use mcap::{Compression, WriteOptions, Writer};
+use serde::Serialize;
+use tauri::{AppHandle, EventId, Listener, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+use crate::time_sync;
+
+/// JSON Schemas of the payloads the shell itself emits.
+const SCHEMAS: [(&str, &str, &str); 6] = [
+    (
+        "robot-status",
+        "percus.RobotStatusEvent",
+        r#"{"type":"object","properties":{"connected":{"type":"boolean"},"status":{"type":["object","null"],"properties":{"mode":{"type":["string","null"]},"servoOn":{"type":"boolean"},"running":{"type":"boolean"},"program":{"type":["integer","null"]},"step":{"type":["integer","null"]},"alarm":{"type":["integer","null"]}}},"error":{"type":["string","null"]}}}"#,
+    ),
+    (
+        "gamepad-state",
+        "percus.GamepadSample",
+        r#"{"type":"object","properties":{"seq":{"type":"integer"},"timestampMs":{"type":"integer"},"gamepads":{"type":"array","items":{"type":"object","properties":{"id":{"type":"integer"},"name":{"type":"string"},"axes":{"type":"object","additionalProperties":{"type":"number"}},"buttons":{"type":"object","additionalProperties":{"type":"boolean"}}}}}}}"#,
+    ),
+    (
+        "spacemouse-state",
+        "percus.SpaceMouseSample",
+        r#"{"type":"object","properties":{"seq":{"type":"integer"},"timestampMs":{"type":"integer"},"translation":{"type":"array","items":{"type":"number"},"minItems":3,"maxItems":3},"rotation":{"type":"array","items":{"type":"number"},"minItems":3,"maxItems":3},"buttons":{"type":"integer"}}}"#,
+    ),
+    (
+        "weld-telemetry",
+        "percus.WeldTelemetry",
+        r#"{"type":"object","properties":{"timestamp":{"type":"string","format":"date-time"},"values":{"type":"object","additionalProperties":{"type":"number"}},"errors":{"type":"object","additionalProperties":{"type":"string"}}}}"#,
+    ),
+    (
+        "backend-log",
+        "percus.BackendLog",
+        r#"{"type":"object","properties":{"stream":{"enum":["stdout","stderr"]},"level":{"enum":["debug","info","warning","error","critical"]},"timestamp":{"type":["string","null"]},"logger":{"type":["string","null"]},"message":{"type":"string"},"dropped":{"type":"integer"}}}"#,
+    ),
+    (
+        "ft-sample",
+        "percus.FtSample",
+        r#"{"type":"object","properties":{"rdtSequence":{"type":"integer"},"ftSequence":{"type":"integer"},"status":{"type":"integer"},"timestampNs":{"type":"integer"},"force":{"type":"array","items":{"type":"number"},"minItems":3,"maxItems":3},"torque":{"type":"array","items":{"type":"number"},"minItems":3,"maxItems":3}}}"#,
+    ),
+];
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BagSummary {
+    /// Files written so far, oldest first.
+    pub files: Vec<PathBuf>,
+    pub messages: u64,
+}
+
+/// File wrapper counting the bytes the MCAP writer has produced.
+struct Counted {
+    file: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Counted {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+struct Open {
+    base: PathBuf,
+    split_bytes: u64,
+    writer: Writer<Counted>,
+    written: Arc<AtomicU64>,
+    /// Channel ids of the current file, registered on first use.
+    channels: HashMap<String, u16>,
+    topics: Vec<String>,
+    listeners: Vec<EventId>,
+    files: Vec<PathBuf>,
+    sequence: u32,
+    messages: u64,
+}
+
+impl Open {
+    fn create_file(base: &Path, part: usize) -> Result<(PathBuf, Writer<Counted>, Arc<AtomicU64>)> {
+        let mut name = base.file_name().unwrap_or_default().to_os_string();
+        name.push(format!("_{part:03}.mcap"));
+        let path = base.with_file_name(name);
+        let written = Arc::new(AtomicU64::new(0));
+        let counted = Counted {
+            file: BufWriter::new(File::create(&path)?),
+            written: written.clone(),
+        };
+        let writer = WriteOptions::new()
+            .compression(Some(Compression::Zstd))
+            .create(counted)?;
+        Ok((path, writer, written))
+    }
+
+    fn channel(&mut self, topic: &str) -> Result<u16> {
+        if let Some(&id) = self.channels.get(topic) {
+            return Ok(id);
+        }
+        let schema = match SCHEMAS.iter().find(|(channel, ..)| *channel == topic) {
+            Some((_, name, schema)) => {
+                self.writer
+                    .add_schema(name, "jsonschema", schema.as_bytes())?
+            }
+            None => 0,
+        };
+        let id = self
+            .writer
+            .add_channel(schema, topic, "json", &BTreeMap::new())?;
+        self.channels.insert(topic.to_owned(), id);
+        Ok(id)
+    }
+
+    fn write(&mut self, topic: &str, timestamp_ns: u64, payload: &[u8]) -> Result<()> {
+        if self.split_bytes > 0 && self.written.load(Ordering::Relaxed) >= self.split_bytes {
+            self.split()?;
+        }
+        let channel_id = self.channel(topic)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        let header = MessageHeader {
+            channel_id,
+            sequence: self.sequence,
+            log_time: timestamp_ns,
+            publish_time: timestamp_ns,
+        };
+        self.writer.write_to_known_channel(&header, payload)?;
+        self.messages += 1;
+        Ok(())
+    }
+
+    fn split(&mut self) -> Result<()> {
+        let (path, writer, written) = Self::create_file(&self.base, self.files.len())?;
+        let mut previous = std::mem::replace(&mut self.writer, writer);
+        previous.finish()?;
+        self.written = written;
+        self.channels.clear();
+        self.files.push(path);
+        Ok(())
+    }
+
+    fn summary(&self) -> BagSummary {
+        BagSummary {
+            files: self.files.clone(),
+            messages: self.messages,
+        }
+    }
+}
+
+/// The bag being written, if any.
+#[derive(Default)]
+pub struct Bag(StdMutex<Option<Open>>);
+
+/// Opens a bag named `name`, or after the current time.
+#[tauri::command]
+pub fn start_bag(app: AppHandle, bag: State<'_, Bag>, name: Option<String>) -> Result<BagSummary> {
+    let mut open = bag.0.lock().unwrap();
+    if open.is_some() {
+        return Err(Error::DeviceBusy("a bag is already recording".into()));
+    }
+    let settings = app.state::<SettingsStore>().get().bag;
+    let name = name.unwrap_or_else(|| {
+        chrono::Local::now()
+            .format("percus-%Y%m%d-%H%M%S")
+            .to_string()
+    });
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(Error::Invalid(format!("bag name {name}")));
+    }
+    let dir = match settings.dir {
+        Some(dir) => dir,
+        None => app.path().app_data_dir()?.join("bags"),
+    };
+    std::fs::create_dir_all(&dir)?;
+    let base = dir.join(name);
+    let (path, writer, written) = Open::create_file(&base, 0)?;
+
+    let listeners = settings
+        .channels
+        .iter()
+        .map(|topic| {
+            let handle = app.clone();
+            let channel = topic.clone();
+            app.listen_any(topic, move |event| {
+                record(&handle, &channel, event.payload().as_bytes())
+            })
+        })
+        .collect();
+    let state = Open {
+        base,
+        split_bytes: settings.split_size_mb * 1024 * 1024,
+        writer,
+        written,
+        channels: HashMap::new(),
+        topics: settings.channels,
+        listeners,
+        files: vec![path],
+        sequence: 0,
+        messages: 0,
+    };
+    let summary = state.summary();
+    *open = Some(state);
+    tracing::info!(file = %summary.files[0].display(), "bag started");
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn stop_bag(app: AppHandle, bag: State<'_, Bag>) -> Result<BagSummary> {
+    let mut open = bag
+        .0
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::Invalid("no bag is recording".into()))?;
+    for listener in open.listeners.drain(..) {
+        app.unlisten(listener);
+    }
+    open.writer.finish()?;
+    let summary = open.summary();
+    tracing::info!(messages = summary.messages, "bag stopped");
+    Ok(summary)
+}
+
+/// Writes one JSON message to the open bag, if `topic` is being recorded.
+pub fn record(app: &AppHandle, topic: &str, payload: &[u8]) {
+    let Some(bag) = app.try_state::<Bag>() else {
+        return;
+    };
+    let mut open = bag.0.lock().unwrap();
+    let Some(open) = open.as_mut() else {
+        return;
+    };
+    if !open.topics.iter().any(|recorded| recorded == topic) {
+        return;
+    }
+    let timestamp_ns = time_sync::now_ns(app).max(0) as u64;
+    if let Err(err) = open.write(topic, timestamp_ns, payload) {
+        tracing::warn!(%topic, "bag message dropped: {err}");
+    }
+}
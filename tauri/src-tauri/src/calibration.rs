@@ -0,0 +1,837 @@
+//! Camera calibration against an AprilGrid board.
+//!
+//! [`start_calibration`] opens a session for one frame ring stream and board
+//! layout. Each [`capture_calibration_frame`] takes the stream's latest
+//! frame, detects the board's AprilTags and keeps their corners as a view,
+//! optionally together with the robot pose at that moment. ChArUco boards
+//! are not detected; there is no native ArUco decoder yet.
+//!
+//! [`solve_calibration`] estimates the intrinsics with Zhang's method and
+//! refines them, the lens distortion (OpenCV's `k1 k2 p1 p2 k3`) and every
+//! view's board pose by Levenberg–Marquardt on the reprojection error. With
+//! robot poses for at least [`MIN_HAND_EYE_VIEWS`] views it also solves the
+//! hand–eye problem (Park–Martin): the camera pose in the gripper frame for
+//! a camera on the arm, or in the robot base frame for a fixed camera with
+//! the board held by the gripper.
+//!
+//! Results are written to `<stream>.json` in the directory the backend gets
+//! as `PHI_CAMERA_CALIBRATION_DIR`. Every view is emitted as
+//! `calibration-view` and the solution as `calibration-solved`, both with
+//! their reprojection errors in pixels.
+
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+
+use aprilgrid::detector::TagDetector;
+use aprilgrid::TagFamily;
+use chrono::{DateTime, Utc};
+use nalgebra::{
+    DMatrix, DVector, Isometry3, Matrix3, Matrix6, Rotation3, Translation3, UnitQuaternion,
+    Vector2, Vector3, Vector6,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::frames::{self, FrameRings};
+use crate::fsutil;
+
+const CALIBRATION_DIR: &str = "camera-calibration";
+const MIN_VIEWS: usize = 3;
+const MIN_HAND_EYE_VIEWS: usize = 3;
+/// Corners a view needs, i.e. four tags, to be kept.
+const MIN_POINTS: usize = 16;
+const MAX_ITERATIONS: usize = 100;
+/// Intrinsic parameters: `fx fy cx cy k1 k2 p1 p2 k3`.
+const INTRINSICS: usize = 9;
+
+/// Layout of an AprilGrid (Kalibr) board.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BoardConfig {
+    /// `t16h5`, `t25h7`, `t25h9`, `t36h11` or `t36h11b1`.
+    pub family: String,
+    pub rows: u32,
+    pub cols: u32,
+    /// Edge of one tag in metres.
+    pub tag_size: f64,
+    /// Gap between tags as a fraction of the tag size.
+    pub tag_spacing: f64,
+    pub first_id: u32,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            family: "t36h11".into(),
+            rows: 6,
+            cols: 6,
+            tag_size: 0.088,
+            tag_spacing: 0.3,
+            first_id: 0,
+        }
+    }
+}
+
+/// Where the camera sits, for the hand–eye solution.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Mount {
+    /// On the arm, looking at a board fixed in the cell.
+    #[default]
+    Hand,
+    /// Fixed in the cell, looking at a board held by the gripper.
+    Fixed,
+}
+
+/// Gripper pose in the robot base frame: position in metres and fixed-axis
+/// rotations in degrees, applied X, then Y, then Z.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotPose {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub rx: f64,
+    pub ry: f64,
+    pub rz: f64,
+}
+
+impl RobotPose {
+    fn isometry(&self) -> Isometry3<f64> {
+        let rotation = UnitQuaternion::from_euler_angles(
+            self.rx.to_radians(),
+            self.ry.to_radians(),
+            self.rz.to_radians(),
+        );
+        Isometry3::from_parts(Translation3::new(self.x, self.y, self.z), rotation)
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturedView {
+    index: usize,
+    tags: usize,
+    /// Detected corners in pixels, for drawing over the frame.
+    corners: Vec<[f64; 2]>,
+    /// RMS distance of the corners from a plane-to-image homography fit,
+    /// in pixels; large values mean a blurred or misdetected board.
+    reprojection_error: f64,
+    views: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationResult {
+    stream: String,
+    image_size: [u32; 2],
+    /// Row-major `fx 0 cx / 0 fy cy / 0 0 1`.
+    camera_matrix: [[f64; 3]; 3],
+    /// `k1 k2 p1 p2 k3`.
+    dist_coeffs: [f64; 5],
+    /// RMS reprojection error over all views, in pixels.
+    reprojection_error: f64,
+    view_errors: Vec<f64>,
+    camera_to_robot: Option<HandEye>,
+    solved_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HandEye {
+    mount: Mount,
+    /// Row-major transform from camera to gripper (`hand`) or robot base
+    /// (`fixed`) coordinates, in metres.
+    matrix: [[f64; 4]; 4],
+    /// RMS scatter of the board position implied by each view, in metres.
+    consistency: f64,
+}
+
+struct Board {
+    config: BoardConfig,
+    family: TagFamily,
+}
+
+impl Board {
+    /// Board-plane coordinates of the four corners of tag `id`, in the
+    /// corner order of the detector.
+    fn corners(&self, id: u32) -> Option<[Vector2<f64>; 4]> {
+        let config = &self.config;
+        let index = id.checked_sub(config.first_id)?;
+        if index >= config.rows * config.cols {
+            return None;
+        }
+        let (row, col) = (
+            f64::from(index / config.cols),
+            f64::from(index % config.cols),
+        );
+        let pitch = config.tag_size * (1.0 + config.tag_spacing);
+        let (x, y, size) = (col * pitch, -row * pitch, config.tag_size);
+        Some([
+            Vector2::new(x, y),
+            Vector2::new(x + size, y),
+            Vector2::new(x + size, y - size),
+            Vector2::new(x, y - size),
+        ])
+    }
+}
+
+#[derive(Clone)]
+struct View {
+    /// Board-plane point and its pixel position.
+    points: Vec<(Vector2<f64>, Vector2<f64>)>,
+    robot: Option<Isometry3<f64>>,
+}
+
+struct Session {
+    stream: String,
+    board: Board,
+    mount: Mount,
+    image_size: Option<(u32, u32)>,
+    views: Vec<View>,
+}
+
+#[derive(Default)]
+pub struct Calibration(StdMutex<Option<Session>>);
+
+/// Directory of the calibration files handed to the backend.
+pub fn dir(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_config_dir()?.join(CALIBRATION_DIR))
+}
+
+/// Pinhole intrinsics `[fx, fy, cx, cy]` solved for `stream`, scaled to a
+/// `width` by `height` image; `None` if the stream was never calibrated.
+pub fn intrinsics(
+    app: &AppHandle,
+    stream: &str,
+    width: u32,
+    height: u32,
+) -> Result<Option<[f64; 4]>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Stored {
+        image_size: [u32; 2],
+        camera_matrix: [[f64; 3]; 3],
+    }
+
+    let path = dir(app)?.join(format!("{stream}.json"));
+    let stored: Stored = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let [calibrated_width, calibrated_height] = stored.image_size;
+    let sx = if calibrated_width == 0 {
+        1.0
+    } else {
+        f64::from(width) / f64::from(calibrated_width)
+    };
+    let sy = if calibrated_height == 0 {
+        1.0
+    } else {
+        f64::from(height) / f64::from(calibrated_height)
+    };
+    let k = stored.camera_matrix;
+    Ok(Some([
+        k[0][0] * sx,
+        k[1][1] * sy,
+        k[0][2] * sx,
+        k[1][2] * sy,
+    ]))
+}
+
+/// Starts a new session for frame ring `stream`, dropping any views taken.
+#[tauri::command]
+pub fn start_calibration(
+    calibration: State<'_, Calibration>,
+    stream: String,
+    board: Option<BoardConfig>,
+    mount: Option<Mount>,
+) -> Result<()> {
+    if !frames::valid_stream(&stream) {
+        return Err(Error::Invalid(format!("stream `{stream}`")));
+    }
+    let config = board.unwrap_or_default();
+    let family = config
+        .family
+        .parse()
+        .map_err(|_| Error::Invalid(format!("tag family `{}`", config.family)))?;
+    if config.rows == 0 || config.cols == 0 || config.tag_size <= 0.0 {
+        return Err(Error::Invalid("board layout".into()));
+    }
+    *calibration.0.lock().unwrap() = Some(Session {
+        stream,
+        board: Board { config, family },
+        mount: mount.unwrap_or_default(),
+        image_size: None,
+        views: Vec::new(),
+    });
+    Ok(())
+}
+
+/// Detects the board in the stream's latest frame and keeps it as a view.
+#[tauri::command]
+pub async fn capture_calibration_frame(
+    app: AppHandle,
+    calibration: State<'_, Calibration>,
+    robot_pose: Option<RobotPose>,
+) -> Result<CapturedView> {
+    let (stream, config, family) = {
+        let session = calibration.0.lock().unwrap();
+        let session = session.as_ref().ok_or_else(not_started)?;
+        (
+            session.stream.clone(),
+            session.board.config.clone(),
+            session.board.family,
+        )
+    };
+    let frame = app
+        .state::<FrameRings>()
+        .latest_frame(&stream)?
+        .ok_or_else(|| Error::NotFound(format!("frame on `{stream}`")))?;
+    let detected = tauri::async_runtime::spawn_blocking(move || -> Result<_> {
+        let image = frame.decode()?;
+        let board = Board { config, family };
+        let tags = TagDetector::new(&board.family, None).detect(&image);
+        let mut points = Vec::new();
+        for (id, corners) in &tags {
+            if let Some(plane) = board.corners(*id) {
+                for (plane, (u, v)) in plane.into_iter().zip(corners) {
+                    points.push((plane, Vector2::new(f64::from(*u), f64::from(*v))));
+                }
+            }
+        }
+        Ok(((image.width(), image.height()), points))
+    })
+    .await
+    .map_err(|err| Error::Stream(err.to_string()))??;
+    let (size, points) = detected;
+    if points.len() < MIN_POINTS {
+        return Err(Error::NotFound(format!(
+            "board in the frame ({} of at least {} corners)",
+            points.len(),
+            MIN_POINTS
+        )));
+    }
+    let homography =
+        homography(&points).ok_or_else(|| Error::Invalid("degenerate board detection".into()))?;
+    let error = rms(points
+        .iter()
+        .map(|(plane, pixel)| (apply(&homography, plane) - pixel).norm_squared()));
+
+    let view = {
+        let mut session = calibration.0.lock().unwrap();
+        let session = session.as_mut().ok_or_else(not_started)?;
+        if session.image_size.is_some_and(|expected| expected != size) {
+            return Err(Error::Invalid(format!(
+                "frame is {}x{}, earlier views were {:?}",
+                size.0, size.1, session.image_size
+            )));
+        }
+        session.image_size = Some(size);
+        let corners = points.iter().map(|(_, pixel)| [pixel.x, pixel.y]).collect();
+        session.views.push(View {
+            points,
+            robot: robot_pose.as_ref().map(RobotPose::isometry),
+        });
+        CapturedView {
+            index: session.views.len() - 1,
+            tags: session.views.last().map_or(0, |view| view.points.len() / 4),
+            corners,
+            reprojection_error: error,
+            views: session.views.len(),
+        }
+    };
+    let _ = app.emit("calibration-view", view.clone());
+    Ok(view)
+}
+
+/// Solves the session's views and saves the result for the backend.
+#[tauri::command]
+pub async fn solve_calibration(
+    app: AppHandle,
+    calibration: State<'_, Calibration>,
+) -> Result<CalibrationResult> {
+    let (stream, mount, size, views) = {
+        let session = calibration.0.lock().unwrap();
+        let session = session.as_ref().ok_or_else(not_started)?;
+        if session.views.len() < MIN_VIEWS {
+            return Err(Error::Invalid(format!(
+                "calibration needs at least {MIN_VIEWS} views, has {}",
+                session.views.len()
+            )));
+        }
+        (
+            session.stream.clone(),
+            session.mount,
+            session.image_size.unwrap_or_default(),
+            session.views.clone(),
+        )
+    };
+    let result = tauri::async_runtime::spawn_blocking(move || solve(stream, mount, size, &views))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))??;
+
+    let dir = dir(&app)?;
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", result.stream));
+    fsutil::write_atomic(&path, &serde_json::to_vec_pretty(&result)?)?;
+    tracing::info!(
+        stream = %result.stream,
+        rms = result.reprojection_error,
+        path = %path.display(),
+        "camera calibrated"
+    );
+    let _ = app.emit("calibration-solved", result.clone());
+    Ok(result)
+}
+
+fn not_started() -> Error {
+    Error::Invalid("no calibration session; call start_calibration first".into())
+}
+
+fn solve(
+    stream: String,
+    mount: Mount,
+    (width, height): (u32, u32),
+    views: &[View],
+) -> Result<CalibrationResult> {
+    let homographies: Vec<Matrix3<f64>> = views
+        .iter()
+        .map(|view| homography(&view.points))
+        .collect::<Option<_>>()
+        .ok_or_else(|| Error::Invalid("degenerate view".into()))?;
+    let centre = (f64::from(width) / 2.0, f64::from(height) / 2.0);
+    let k = zhang(&homographies)
+        .filter(|k| {
+            (0.0..f64::from(width)).contains(&k[(0, 2)])
+                && (0.0..f64::from(height)).contains(&k[(1, 2)])
+        })
+        .unwrap_or_else(|| focal_only(&homographies, centre));
+
+    let mut params = DVector::zeros(INTRINSICS + 6 * views.len());
+    params[0] = k[(0, 0)];
+    params[1] = k[(1, 1)];
+    params[2] = k[(0, 2)];
+    params[3] = k[(1, 2)];
+    for (index, h) in homographies.iter().enumerate() {
+        let pose = board_pose(&k, h);
+        params
+            .fixed_rows_mut::<6>(INTRINSICS + 6 * index)
+            .copy_from(&pose);
+    }
+    let params = refine(params, views);
+
+    let intrinsics = params.rows(0, INTRINSICS).clone_owned();
+    let view_errors: Vec<f64> = views
+        .iter()
+        .enumerate()
+        .map(|(index, view)| {
+            let pose = params.fixed_rows::<6>(INTRINSICS + 6 * index).into_owned();
+            rms(view_residuals(&intrinsics, &pose, view)
+                .chunks(2)
+                .map(|pair| pair[0] * pair[0] + pair[1] * pair[1]))
+        })
+        .collect();
+    let points: usize = views.iter().map(|view| view.points.len()).sum();
+    let reprojection_error = (views
+        .iter()
+        .zip(&view_errors)
+        .map(|(view, error)| error * error * view.points.len() as f64)
+        .sum::<f64>()
+        / points as f64)
+        .sqrt();
+
+    let poses: Vec<(Isometry3<f64>, Isometry3<f64>)> = views
+        .iter()
+        .enumerate()
+        .filter_map(|(index, view)| {
+            let robot = view.robot?;
+            let pose = params.fixed_rows::<6>(INTRINSICS + 6 * index).into_owned();
+            Some((robot, isometry(&pose)))
+        })
+        .collect();
+    let camera_to_robot = if poses.len() >= MIN_HAND_EYE_VIEWS {
+        Some(hand_eye(mount, &poses)?)
+    } else {
+        None
+    };
+
+    Ok(CalibrationResult {
+        stream,
+        image_size: [width, height],
+        camera_matrix: [
+            [intrinsics[0], 0.0, intrinsics[2]],
+            [0.0, intrinsics[1], intrinsics[3]],
+            [0.0, 0.0, 1.0],
+        ],
+        dist_coeffs: [
+            intrinsics[4],
+            intrinsics[5],
+            intrinsics[6],
+            intrinsics[7],
+            intrinsics[8],
+        ],
+        reprojection_error,
+        view_errors,
+        camera_to_robot,
+        solved_at: Utc::now(),
+    })
+}
+
+/// Plane-to-image homography by the normalised DLT.
+fn homography(points: &[(Vector2<f64>, Vector2<f64>)]) -> Option<Matrix3<f64>> {
+    if points.len() < 4 {
+        return None;
+    }
+    let plane = normalisation(points.iter().map(|(plane, _)| plane));
+    let pixel = normalisation(points.iter().map(|(_, pixel)| pixel));
+    let mut ata = DMatrix::<f64>::zeros(9, 9);
+    for (x, u) in points {
+        let x = apply(&plane, x);
+        let u = apply(&pixel, u);
+        let rows = [
+            [-x.x, -x.y, -1.0, 0.0, 0.0, 0.0, u.x * x.x, u.x * x.y, u.x],
+            [0.0, 0.0, 0.0, -x.x, -x.y, -1.0, u.y * x.x, u.y * x.y, u.y],
+        ];
+        for row in rows {
+            let row = DVector::from_row_slice(&row);
+            ata += &row * row.transpose();
+        }
+    }
+    let h = smallest_eigenvector(ata);
+    let normalised = Matrix3::from_row_slice(h.as_slice());
+    let h = pixel.try_inverse()? * normalised * plane;
+    (h[(2, 2)].abs() > f64::EPSILON).then(|| h / h[(2, 2)])
+}
+
+/// Similarity moving the points' centroid to the origin and their mean
+/// distance from it to √2.
+fn normalisation<'a>(points: impl Iterator<Item = &'a Vector2<f64>> + Clone) -> Matrix3<f64> {
+    let count = points.clone().count() as f64;
+    let centroid = points.clone().fold(Vector2::zeros(), |sum, p| sum + p) / count;
+    let spread = points.map(|p| (p - centroid).norm()).sum::<f64>() / count;
+    let scale = if spread > 0.0 {
+        std::f64::consts::SQRT_2 / spread
+    } else {
+        1.0
+    };
+    Matrix3::new(
+        scale,
+        0.0,
+        -scale * centroid.x,
+        0.0,
+        scale,
+        -scale * centroid.y,
+        0.0,
+        0.0,
+        1.0,
+    )
+}
+
+fn apply(h: &Matrix3<f64>, point: &Vector2<f64>) -> Vector2<f64> {
+    let p = h * Vector3::new(point.x, point.y, 1.0);
+    Vector2::new(p.x / p.z, p.y / p.z)
+}
+
+fn smallest_eigenvector(matrix: DMatrix<f64>) -> DVector<f64> {
+    let eigen = matrix.symmetric_eigen();
+    let smallest = eigen.eigenvalues.imin();
+    eigen.eigenvectors.column(smallest).clone_owned()
+}
+
+/// Closed-form intrinsics from three or more homographies (zero skew).
+fn zhang(homographies: &[Matrix3<f64>]) -> Option<Matrix3<f64>> {
+    let v = |h: &Matrix3<f64>, i: usize, j: usize| {
+        Vector6::new(
+            h[(0, i)] * h[(0, j)],
+            h[(0, i)] * h[(1, j)] + h[(1, i)] * h[(0, j)],
+            h[(1, i)] * h[(1, j)],
+            h[(2, i)] * h[(0, j)] + h[(0, i)] * h[(2, j)],
+            h[(2, i)] * h[(1, j)] + h[(1, i)] * h[(2, j)],
+            h[(2, i)] * h[(2, j)],
+        )
+    };
+    let mut vtv = Matrix6::zeros();
+    for h in homographies {
+        for row in [v(h, 0, 1), v(h, 0, 0) - v(h, 1, 1)] {
+            vtv += row * row.transpose();
+        }
+    }
+    let mut b = smallest_eigenvector(DMatrix::from_column_slice(6, 6, vtv.as_slice()));
+    if b[0] < 0.0 {
+        b = -b;
+    }
+    let (b11, b12, b22, b13, b23, b33) = (b[0], b[1], b[2], b[3], b[4], b[5]);
+    let denominator = b11 * b22 - b12 * b12;
+    let cy = (b12 * b13 - b11 * b23) / denominator;
+    let lambda = b33 - (b13 * b13 + cy * (b12 * b13 - b11 * b23)) / b11;
+    let fx = (lambda / b11).sqrt();
+    let fy = (lambda * b11 / denominator).sqrt();
+    let skew = -b12 * fx * fx * fy / lambda;
+    let cx = skew * cy / fy - b13 * fx * fx / lambda;
+    [fx, fy, cx, cy]
+        .iter()
+        .all(|value| value.is_finite())
+        .then(|| Matrix3::new(fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0, 1.0))
+}
+
+/// Focal length alone, with the principal point at the image centre; used
+/// when the closed form fails, e.g. for nearly parallel views.
+fn focal_only(homographies: &[Matrix3<f64>], (cx, cy): (f64, f64)) -> Matrix3<f64> {
+    // With the principal point removed, orthogonality of the first two
+    // rotation columns gives f² = -(a1·a2 + b1·b2) / (c1·c2) per view.
+    let shift = Matrix3::new(1.0, 0.0, -cx, 0.0, 1.0, -cy, 0.0, 0.0, 1.0);
+    let estimates: Vec<f64> = homographies
+        .iter()
+        .filter_map(|h| {
+            let h = shift * h;
+            let f2 = -(h[(0, 0)] * h[(0, 1)] + h[(1, 0)] * h[(1, 1)]) / (h[(2, 0)] * h[(2, 1)]);
+            (f2.is_finite() && f2 > 0.0).then(|| f2.sqrt())
+        })
+        .collect();
+    let f = if estimates.is_empty() {
+        cx.max(cy)
+    } else {
+        estimates.iter().sum::<f64>() / estimates.len() as f64
+    };
+    Matrix3::new(f, 0.0, cx, 0.0, f, cy, 0.0, 0.0, 1.0)
+}
+
+/// Board pose (rotation vector, translation) from a homography.
+fn board_pose(k: &Matrix3<f64>, h: &Matrix3<f64>) -> Vector6<f64> {
+    let k_inv = k.try_inverse().unwrap_or_else(Matrix3::identity);
+    let (mut r1, mut r2, mut t) = (
+        k_inv * h.column(0),
+        k_inv * h.column(1),
+        k_inv * h.column(2),
+    );
+    let scale = 1.0 / r1.norm();
+    r1 *= scale;
+    r2 *= scale;
+    t *= scale;
+    // The board lies in front of the camera.
+    if t.z < 0.0 {
+        r1 = -r1;
+        r2 = -r2;
+        t = -t;
+    }
+    let r = Matrix3::from_columns(&[r1, r2, r1.cross(&r2)]);
+    let rotation = Rotation3::from_matrix(&r);
+    let axis = rotation.scaled_axis();
+    Vector6::new(axis.x, axis.y, axis.z, t.x, t.y, t.z)
+}
+
+fn isometry(pose: &Vector6<f64>) -> Isometry3<f64> {
+    Isometry3::new(
+        Vector3::new(pose[3], pose[4], pose[5]),
+        Vector3::new(pose[0], pose[1], pose[2]),
+    )
+}
+
+/// Pixel residuals `(du, dv)` of one view under the given parameters.
+fn view_residuals(intrinsics: &DVector<f64>, pose: &Vector6<f64>, view: &View) -> Vec<f64> {
+    let (fx, fy, cx, cy) = (intrinsics[0], intrinsics[1], intrinsics[2], intrinsics[3]);
+    let (k1, k2, p1, p2, k3) = (
+        intrinsics[4],
+        intrinsics[5],
+        intrinsics[6],
+        intrinsics[7],
+        intrinsics[8],
+    );
+    let transform = isometry(pose);
+    let mut residuals = Vec::with_capacity(view.points.len() * 2);
+    for (plane, pixel) in &view.points {
+        let p = transform * nalgebra::Point3::new(plane.x, plane.y, 0.0);
+        let (x, y) = (p.x / p.z, p.y / p.z);
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+        let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+        residuals.push(fx * xd + cx - pixel.x);
+        residuals.push(fy * yd + cy - pixel.y);
+    }
+    residuals
+}
+
+/// Levenberg–Marquardt over the intrinsics and all board poses, using the
+/// block structure: each pose only moves its own view's residuals.
+fn refine(mut params: DVector<f64>, views: &[View]) -> DVector<f64> {
+    let count = params.len();
+    let cost = |params: &DVector<f64>| -> f64 {
+        let intrinsics = params.rows(0, INTRINSICS).clone_owned();
+        views
+            .iter()
+            .enumerate()
+            .map(|(index, view)| {
+                let pose = params.fixed_rows::<6>(INTRINSICS + 6 * index).into_owned();
+                view_residuals(&intrinsics, &pose, view)
+                    .iter()
+                    .map(|r| r * r)
+                    .sum::<f64>()
+            })
+            .sum()
+    };
+    let mut current = cost(&params);
+    let mut damping = 1e-3;
+    for _ in 0..MAX_ITERATIONS {
+        let mut jtj = DMatrix::<f64>::zeros(count, count);
+        let mut jtr = DVector::<f64>::zeros(count);
+        let intrinsics = params.rows(0, INTRINSICS).clone_owned();
+        for (index, view) in views.iter().enumerate() {
+            let offset = INTRINSICS + 6 * index;
+            let pose = params.fixed_rows::<6>(offset).into_owned();
+            let base = view_residuals(&intrinsics, &pose, view);
+            // Columns: the intrinsics, then this view's pose.
+            let mut jacobian = DMatrix::<f64>::zeros(base.len(), INTRINSICS + 6);
+            for column in 0..INTRINSICS + 6 {
+                let (mut intrinsics, mut pose) = (intrinsics.clone(), pose);
+                let value = if column < INTRINSICS {
+                    &mut intrinsics[column]
+                } else {
+                    &mut pose[column - INTRINSICS]
+                };
+                let step = 1e-6 * value.abs().max(1e-3);
+                *value += step;
+                let moved = view_residuals(&intrinsics, &pose, view);
+                for (row, (moved, base)) in moved.iter().zip(&base).enumerate() {
+                    jacobian[(row, column)] = (moved - base) / step;
+                }
+            }
+            let local_jtj = jacobian.transpose() * &jacobian;
+            let local_jtr = jacobian.transpose() * DVector::from_column_slice(&base);
+            let global = |local: usize| {
+                if local < INTRINSICS {
+                    local
+                } else {
+                    offset + local - INTRINSICS
+                }
+            };
+            for row in 0..INTRINSICS + 6 {
+                jtr[global(row)] += local_jtr[row];
+                for column in 0..INTRINSICS + 6 {
+                    jtj[(global(row), global(column))] += local_jtj[(row, column)];
+                }
+            }
+        }
+
+        let mut improved = false;
+        while damping < 1e12 {
+            let mut system = jtj.clone();
+            for i in 0..count {
+                system[(i, i)] += damping * jtj[(i, i)].max(1e-9);
+            }
+            let Some(step) = system.cholesky().map(|cholesky| cholesky.solve(&-&jtr)) else {
+                damping *= 10.0;
+                continue;
+            };
+            let candidate = &params + &step;
+            let candidate_cost = cost(&candidate);
+            if candidate_cost < current {
+                let relative = (current - candidate_cost) / current.max(f64::EPSILON);
+                params = candidate;
+                current = candidate_cost;
+                damping = (damping / 10.0).max(1e-12);
+                improved = relative > 1e-12;
+                break;
+            }
+            damping *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    params
+}
+
+/// Park–Martin solution of `A X = X B` over all pairs of views.
+///
+/// `poses` pairs the gripper pose in the base frame with the board pose in
+/// the camera frame. For a fixed camera the gripper poses are inverted,
+/// which turns the problem into the same form.
+fn hand_eye(mount: Mount, poses: &[(Isometry3<f64>, Isometry3<f64>)]) -> Result<HandEye> {
+    let robot: Vec<Isometry3<f64>> = poses
+        .iter()
+        .map(|(robot, _)| match mount {
+            Mount::Hand => *robot,
+            Mount::Fixed => robot.inverse(),
+        })
+        .collect();
+    let board: Vec<Isometry3<f64>> = poses.iter().map(|(_, board)| *board).collect();
+
+    let mut pairs = Vec::new();
+    for i in 0..poses.len() {
+        for j in i + 1..poses.len() {
+            let a = robot[j].inverse() * robot[i];
+            let b = board[j] * board[i].inverse();
+            pairs.push((a, b));
+        }
+    }
+    let mut m = Matrix3::<f64>::zeros();
+    for (a, b) in &pairs {
+        m += b.rotation.scaled_axis() * a.rotation.scaled_axis().transpose();
+    }
+    let eigen = (m.transpose() * m).symmetric_eigen();
+    if eigen.eigenvalues.min() < 1e-8 {
+        return Err(Error::Invalid(
+            "robot poses need rotations about at least two different axes".into(),
+        ));
+    }
+    let inverse_sqrt = eigen.eigenvectors
+        * Matrix3::from_diagonal(&eigen.eigenvalues.map(|value| 1.0 / value.sqrt()))
+        * eigen.eigenvectors.transpose();
+    let rotation = Rotation3::from_matrix(&(inverse_sqrt * m.transpose()));
+
+    let mut lhs = Matrix3::<f64>::zeros();
+    let mut rhs = Vector3::<f64>::zeros();
+    for (a, b) in &pairs {
+        let c = a.rotation.to_rotation_matrix().into_inner() - Matrix3::identity();
+        let d = rotation * b.translation.vector - a.translation.vector;
+        lhs += c.transpose() * c;
+        rhs += c.transpose() * d;
+    }
+    let translation = lhs
+        .try_inverse()
+        .ok_or_else(|| Error::Invalid("robot poses do not constrain the translation".into()))?
+        * rhs;
+    let x = Isometry3::from_parts(
+        Translation3::from(translation),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    );
+
+    // Where each view puts the board relative to the robot; constant for a
+    // perfect solution.
+    let positions: Vec<Vector3<f64>> = robot
+        .iter()
+        .zip(&board)
+        .map(|(robot, board)| (robot * x * board).translation.vector)
+        .collect();
+    let mean = positions.iter().sum::<Vector3<f64>>() / positions.len() as f64;
+    let consistency = rms(positions.iter().map(|p| (p - mean).norm_squared()));
+
+    let matrix = x.to_homogeneous();
+    let mut rows = [[0.0; 4]; 4];
+    for (r, row) in rows.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = matrix[(r, c)];
+        }
+    }
+    Ok(HandEye {
+        mount,
+        matrix: rows,
+        consistency,
+    })
+}
+
+/// Root mean of squared distances.
+fn rms(squares: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = squares.fold((0.0, 0usize), |(sum, count), square| {
+        (sum + square, count + 1)
+    });
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt()
+    }
+}
@@ -0,0 +1,88 @@
+//! Native camera enumeration for the setup wizard.
+//!
+//! Lists the cameras the OS exposes, with every pixel format, resolution and
+//! frame rate they advertise, and whether another process is already
+//! streaming from them. [`open_camera`] negotiates a format, captures a
+//! single frame and releases the device again, so checking a camera never
+//! keeps it from the backend.
+//!
+//! Linux talks to V4L2 directly; macOS and Windows go through nokhwa's
+//! AVFoundation and Media Foundation bindings.
+
+#[cfg(not(target_os = "linux"))]
+mod native;
+#[cfg(target_os = "linux")]
+mod v4l2;
+
+#[cfg(not(target_os = "linux"))]
+use native as backend;
+#[cfg(target_os = "linux")]
+use v4l2 as backend;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// One resolution of a pixel format and the frame rates offered for it.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraMode {
+    /// Four-character code such as `MJPG` or `YUYV`.
+    pub fourcc: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Vec<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraInfo {
+    /// Device path on Linux, backend index elsewhere; pass to [`open_camera`].
+    pub id: String,
+    pub name: String,
+    /// USB bus location when the OS reports one.
+    pub bus: Option<String>,
+    /// Another process is streaming from the device; `None` where that can
+    /// only be learned by opening it.
+    pub busy: Option<bool>,
+    pub modes: Vec<CameraMode>,
+}
+
+/// Format requested by [`open_camera`]; unset fields keep the driver default.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CameraRequest {
+    pub fourcc: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: Option<f64>,
+}
+
+/// Result of a successful test capture.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraProbe {
+    /// The format the driver actually applied.
+    pub fourcc: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<f64>,
+    pub frame_bytes: usize,
+    /// Time from opening the device to the first frame.
+    pub first_frame_ms: u64,
+}
+
+#[tauri::command]
+pub async fn list_cameras() -> Result<Vec<CameraInfo>> {
+    tauri::async_runtime::spawn_blocking(backend::list)
+        .await
+        .map_err(|err| std::io::Error::other(err.to_string()))?
+}
+
+#[tauri::command]
+pub async fn open_camera(id: String, request: Option<CameraRequest>) -> Result<CameraProbe> {
+    let request = request.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || backend::probe(&id, &request))
+        .await
+        .map_err(|err| std::io::Error::other(err.to_string()))?
+}
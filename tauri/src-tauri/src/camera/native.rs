@@ -0,0 +1,132 @@
+//! AVFoundation and Media Foundation access through nokhwa.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::{
+    ApiBackend, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType,
+    Resolution,
+};
+use nokhwa::{Camera, NokhwaError};
+
+use super::{CameraInfo, CameraMode, CameraProbe, CameraRequest};
+use crate::error::{Error, Result};
+
+fn fourcc(format: FrameFormat) -> &'static str {
+    match format {
+        FrameFormat::MJPEG => "MJPG",
+        FrameFormat::YUYV => "YUYV",
+        FrameFormat::NV12 => "NV12",
+        FrameFormat::GRAY => "GREY",
+        FrameFormat::RAWRGB => "RGB3",
+        FrameFormat::RAWBGR => "BGR3",
+    }
+}
+
+fn frame_format(fourcc: &str) -> Result<FrameFormat> {
+    Ok(match fourcc {
+        "MJPG" => FrameFormat::MJPEG,
+        "YUYV" => FrameFormat::YUYV,
+        "NV12" => FrameFormat::NV12,
+        "GREY" => FrameFormat::GRAY,
+        "RGB3" => FrameFormat::RAWRGB,
+        "BGR3" => FrameFormat::RAWBGR,
+        other => return Err(Error::Invalid(format!("pixel format {other:?}"))),
+    })
+}
+
+fn camera_error(err: NokhwaError) -> Error {
+    Error::Invalid(format!("camera: {err}"))
+}
+
+fn index(id: &str) -> CameraIndex {
+    match id.parse() {
+        Ok(index) => CameraIndex::Index(index),
+        Err(_) => CameraIndex::String(id.to_owned()),
+    }
+}
+
+pub fn list() -> Result<Vec<CameraInfo>> {
+    let devices = nokhwa::query(ApiBackend::Auto).map_err(camera_error)?;
+    Ok(devices
+        .into_iter()
+        .map(|device| {
+            let modes = Camera::new(
+                device.index().clone(),
+                RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+            )
+            .and_then(|mut camera| camera.compatible_camera_formats())
+            .map(group_modes)
+            .unwrap_or_default();
+            CameraInfo {
+                id: device.index().to_string(),
+                name: device.human_name(),
+                bus: Some(device.misc()).filter(|misc| !misc.is_empty()),
+                busy: None,
+                modes,
+            }
+        })
+        .collect())
+}
+
+fn group_modes(formats: Vec<CameraFormat>) -> Vec<CameraMode> {
+    let mut grouped: BTreeMap<(&'static str, u32, u32), Vec<f64>> = BTreeMap::new();
+    for format in formats {
+        grouped
+            .entry((fourcc(format.format()), format.width(), format.height()))
+            .or_default()
+            .push(f64::from(format.frame_rate()));
+    }
+    grouped
+        .into_iter()
+        .map(|((fourcc, width, height), mut fps)| {
+            fps.sort_by(|a, b| b.total_cmp(a));
+            fps.dedup();
+            CameraMode {
+                fourcc: fourcc.to_owned(),
+                width,
+                height,
+                fps,
+            }
+        })
+        .collect()
+}
+
+pub fn probe(id: &str, request: &CameraRequest) -> Result<CameraProbe> {
+    let started = Instant::now();
+    let requested = match (request.width, request.height) {
+        (Some(width), Some(height)) => {
+            let format = match &request.fourcc {
+                Some(fourcc) => frame_format(fourcc)?,
+                None => FrameFormat::MJPEG,
+            };
+            let fps = request.fps.map_or(30, |fps| fps.round() as u32);
+            RequestedFormatType::Closest(CameraFormat::new(
+                Resolution::new(width, height),
+                format,
+                fps,
+            ))
+        }
+        _ => RequestedFormatType::None,
+    };
+    let mut camera = Camera::new(index(id), RequestedFormat::new::<RgbFormat>(requested))
+        .map_err(camera_error)?;
+    // Opening the stream is where a camera held by another process fails.
+    camera
+        .open_stream()
+        .map_err(|_| Error::DeviceBusy(id.to_owned()))?;
+    let frame = camera.frame_raw().map(|frame| frame.len());
+    let _ = camera.stop_stream();
+    let frame_bytes = frame.map_err(camera_error)?;
+
+    let format = camera.camera_format();
+    Ok(CameraProbe {
+        fourcc: fourcc(format.format()).to_owned(),
+        width: format.width(),
+        height: format.height(),
+        fps: Some(f64::from(format.frame_rate())),
+        frame_bytes,
+        first_frame_ms: started.elapsed().as_millis() as u64,
+    })
+}
@@ -0,0 +1,530 @@
+//! Minimal V4L2 client: capability and format enumeration plus a
+//! single-frame memory-mapped capture.
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::{CameraInfo, CameraMode, CameraProbe, CameraRequest};
+use crate::error::{Error, Result};
+
+const BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const MEMORY_MMAP: u32 = 1;
+const CAP_VIDEO_CAPTURE: u32 = 0x0000_0001;
+const CAP_DEVICE_CAPS: u32 = 0x8000_0000;
+const FRMSIZE_TYPE_DISCRETE: u32 = 1;
+const FRMIVAL_TYPE_DISCRETE: u32 = 1;
+const BUFFER_COUNT: u32 = 2;
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[repr(C)]
+struct Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+struct FmtDesc {
+    index: u32,
+    kind: u32,
+    flags: u32,
+    description: [u8; 32],
+    pixelformat: u32,
+    mbus_code: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+struct FrmSizeEnum {
+    index: u32,
+    pixel_format: u32,
+    kind: u32,
+    /// `discrete` is `{width, height}`; `stepwise` uses all six words.
+    size: [u32; 6],
+    reserved: [u32; 2],
+}
+
+#[repr(C)]
+struct FrmIvalEnum {
+    index: u32,
+    pixel_format: u32,
+    width: u32,
+    height: u32,
+    kind: u32,
+    /// `discrete` is `{numerator, denominator}`.
+    interval: [u32; 6],
+    reserved: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+#[repr(C)]
+union FormatUnion {
+    pix: PixFormat,
+    raw: [u8; 200],
+    // Other members hold pointers, which sets the union's alignment.
+    _align: [u64; 25],
+}
+
+#[repr(C)]
+struct Format {
+    kind: u32,
+    fmt: FormatUnion,
+}
+
+#[repr(C)]
+struct StreamParm {
+    kind: u32,
+    capability: u32,
+    capturemode: u32,
+    /// `timeperframe` as `{numerator, denominator}`.
+    time_per_frame: [u32; 2],
+    extendedmode: u32,
+    readbuffers: u32,
+    reserved: [u32; 4],
+    padding: [u8; 160],
+}
+
+#[repr(C)]
+struct RequestBuffers {
+    count: u32,
+    kind: u32,
+    memory: u32,
+    capabilities: u32,
+    flags: u8,
+    reserved: [u8; 3],
+}
+
+#[repr(C)]
+struct Buffer {
+    index: u32,
+    kind: u32,
+    bytesused: u32,
+    flags: u32,
+    field: u32,
+    timestamp: libc::timeval,
+    timecode: [u32; 4],
+    sequence: u32,
+    memory: u32,
+    /// The `m` union; only `offset` is used with MMAP buffers.
+    offset: libc::c_ulong,
+    length: u32,
+    reserved2: u32,
+    request_fd: i32,
+}
+
+#[cfg(target_pointer_width = "64")]
+const _: () = {
+    assert!(size_of::<Capability>() == 104);
+    assert!(size_of::<Format>() == 208);
+    assert!(size_of::<StreamParm>() == 204);
+    assert!(size_of::<Buffer>() == 88);
+};
+
+const fn iowr<T>(nr: u8) -> libc::c_ulong {
+    ((3 << 30) | (size_of::<T>() << 16) | ((b'V' as usize) << 8) | nr as usize) as libc::c_ulong
+}
+
+const fn iow<T>(nr: u8) -> libc::c_ulong {
+    ((1 << 30) | (size_of::<T>() << 16) | ((b'V' as usize) << 8) | nr as usize) as libc::c_ulong
+}
+
+const fn ior<T>(nr: u8) -> libc::c_ulong {
+    ((2 << 30) | (size_of::<T>() << 16) | ((b'V' as usize) << 8) | nr as usize) as libc::c_ulong
+}
+
+const VIDIOC_QUERYCAP: libc::c_ulong = ior::<Capability>(0);
+const VIDIOC_ENUM_FMT: libc::c_ulong = iowr::<FmtDesc>(2);
+const VIDIOC_G_FMT: libc::c_ulong = iowr::<Format>(4);
+const VIDIOC_S_FMT: libc::c_ulong = iowr::<Format>(5);
+const VIDIOC_REQBUFS: libc::c_ulong = iowr::<RequestBuffers>(8);
+const VIDIOC_QUERYBUF: libc::c_ulong = iowr::<Buffer>(9);
+const VIDIOC_QBUF: libc::c_ulong = iowr::<Buffer>(15);
+const VIDIOC_DQBUF: libc::c_ulong = iowr::<Buffer>(17);
+const VIDIOC_STREAMON: libc::c_ulong = iow::<libc::c_int>(18);
+const VIDIOC_STREAMOFF: libc::c_ulong = iow::<libc::c_int>(19);
+const VIDIOC_G_PARM: libc::c_ulong = iowr::<StreamParm>(21);
+const VIDIOC_S_PARM: libc::c_ulong = iowr::<StreamParm>(22);
+const VIDIOC_ENUM_FRAMESIZES: libc::c_ulong = iowr::<FrmSizeEnum>(74);
+const VIDIOC_ENUM_FRAMEINTERVALS: libc::c_ulong = iowr::<FrmIvalEnum>(75);
+
+/// All-zero value of a plain-data ioctl struct.
+fn zeroed<T>() -> T {
+    // SAFETY: only used for the `repr(C)` integer structs above, for which
+    // all-zero is a valid value.
+    unsafe { std::mem::zeroed() }
+}
+
+fn ioctl<T>(file: &File, request: libc::c_ulong, arg: &mut T) -> io::Result<()> {
+    loop {
+        // SAFETY: `request` encodes the size of `T`, so the kernel stays
+        // within `arg`.
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as *mut T) };
+        if result != -1 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    CStr::from_bytes_until_nul(bytes)
+        .map(|value| value.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn fourcc_string(code: u32) -> String {
+    code.to_le_bytes()
+        .iter()
+        .map(|&byte| char::from(byte))
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+fn fourcc_code(fourcc: &str) -> Result<u32> {
+    let bytes = fourcc.as_bytes();
+    if bytes.is_empty() || bytes.len() > 4 {
+        return Err(Error::Invalid(format!("pixel format {fourcc:?}")));
+    }
+    let mut code = [b' '; 4];
+    code[..bytes.len()].copy_from_slice(bytes);
+    Ok(u32::from_le_bytes(code))
+}
+
+pub fn list() -> Result<Vec<CameraInfo>> {
+    let mut paths: Vec<_> = std::fs::read_dir("/dev")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("video"))
+        })
+        .collect();
+    paths.sort();
+
+    let mut cameras = Vec::new();
+    for path in paths {
+        match describe(&path) {
+            Ok(Some(camera)) => cameras.push(camera),
+            Ok(None) => {}
+            Err(err) => tracing::debug!(path = %path.display(), "skipping camera: {err}"),
+        }
+    }
+    Ok(cameras)
+}
+
+/// Describes a capture node; `None` for metadata and output-only nodes.
+fn describe(path: &Path) -> io::Result<Option<CameraInfo>> {
+    let file = open(path)?;
+    let mut cap: Capability = zeroed();
+    ioctl(&file, VIDIOC_QUERYCAP, &mut cap)?;
+    let caps = if cap.capabilities & CAP_DEVICE_CAPS != 0 {
+        cap.device_caps
+    } else {
+        cap.capabilities
+    };
+    if caps & CAP_VIDEO_CAPTURE == 0 {
+        return Ok(None);
+    }
+    let bus = c_string(&cap.bus_info);
+    Ok(Some(CameraInfo {
+        id: path.display().to_string(),
+        name: c_string(&cap.card),
+        bus: (!bus.is_empty()).then_some(bus),
+        busy: Some(is_busy(&file)),
+        modes: modes(&file),
+    }))
+}
+
+/// Requesting zero buffers fails with `EBUSY` while another file handle
+/// owns the device's streaming buffers.
+fn is_busy(file: &File) -> bool {
+    let mut request = RequestBuffers {
+        count: 0,
+        kind: BUF_TYPE_VIDEO_CAPTURE,
+        memory: MEMORY_MMAP,
+        ..zeroed()
+    };
+    matches!(
+        ioctl(file, VIDIOC_REQBUFS, &mut request),
+        Err(err) if err.raw_os_error() == Some(libc::EBUSY)
+    )
+}
+
+fn modes(file: &File) -> Vec<CameraMode> {
+    let mut modes = Vec::new();
+    for index in 0.. {
+        let mut desc = FmtDesc {
+            index,
+            kind: BUF_TYPE_VIDEO_CAPTURE,
+            ..zeroed()
+        };
+        if ioctl(file, VIDIOC_ENUM_FMT, &mut desc).is_err() {
+            break;
+        }
+        for (width, height) in frame_sizes(file, desc.pixelformat) {
+            modes.push(CameraMode {
+                fourcc: fourcc_string(desc.pixelformat),
+                width,
+                height,
+                fps: frame_rates(file, desc.pixelformat, width, height),
+            });
+        }
+    }
+    modes
+}
+
+/// Discrete sizes, or the minimum and maximum of a stepwise range.
+fn frame_sizes(file: &File, pixel_format: u32) -> Vec<(u32, u32)> {
+    let mut sizes = Vec::new();
+    for index in 0.. {
+        let mut size = FrmSizeEnum {
+            index,
+            pixel_format,
+            ..zeroed()
+        };
+        if ioctl(file, VIDIOC_ENUM_FRAMESIZES, &mut size).is_err() {
+            break;
+        }
+        if size.kind == FRMSIZE_TYPE_DISCRETE {
+            sizes.push((size.size[0], size.size[1]));
+        } else {
+            let [min_width, max_width, _, min_height, max_height, _] = size.size;
+            sizes.push((min_width, min_height));
+            sizes.push((max_width, max_height));
+            break;
+        }
+    }
+    sizes
+}
+
+fn frame_rates(file: &File, pixel_format: u32, width: u32, height: u32) -> Vec<f64> {
+    let mut rates = Vec::new();
+    for index in 0.. {
+        let mut interval = FrmIvalEnum {
+            index,
+            pixel_format,
+            width,
+            height,
+            ..zeroed()
+        };
+        if ioctl(file, VIDIOC_ENUM_FRAMEINTERVALS, &mut interval).is_err() {
+            break;
+        }
+        // Stepwise ranges report their fastest and slowest rates.
+        let fractions = if interval.kind == FRMIVAL_TYPE_DISCRETE {
+            &interval.interval[..2]
+        } else {
+            &interval.interval[..4]
+        };
+        for pair in fractions.chunks(2) {
+            if pair[0] != 0 {
+                rates.push(f64::from(pair[1]) / f64::from(pair[0]));
+            }
+        }
+        if interval.kind != FRMIVAL_TYPE_DISCRETE {
+            break;
+        }
+    }
+    rates
+}
+
+/// A memory-mapped capture buffer, unmapped on drop.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr`/`len` come from a successful `mmap`.
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+pub fn probe(id: &str, request: &CameraRequest) -> Result<CameraProbe> {
+    let path = Path::new(id);
+    if !path.starts_with("/dev") {
+        return Err(Error::Invalid(format!("camera id {id}")));
+    }
+    let started = Instant::now();
+    let file = open(path)?;
+    let busy = |err: io::Error| {
+        if err.raw_os_error() == Some(libc::EBUSY) {
+            Error::DeviceBusy(id.to_owned())
+        } else {
+            Error::Io(err)
+        }
+    };
+
+    let mut format = Format {
+        kind: BUF_TYPE_VIDEO_CAPTURE,
+        ..zeroed()
+    };
+    ioctl(&file, VIDIOC_G_FMT, &mut format)?;
+    // SAFETY: `pix` is the active member for the video capture type.
+    let mut pix = unsafe { format.fmt.pix };
+    if let Some(fourcc) = &request.fourcc {
+        pix.pixelformat = fourcc_code(fourcc)?;
+    }
+    pix.width = request.width.unwrap_or(pix.width);
+    pix.height = request.height.unwrap_or(pix.height);
+    format.fmt.pix = pix;
+    ioctl(&file, VIDIOC_S_FMT, &mut format).map_err(busy)?;
+    // SAFETY: as above; the driver wrote back the applied format.
+    let pix = unsafe { format.fmt.pix };
+
+    let mut parm = StreamParm {
+        kind: BUF_TYPE_VIDEO_CAPTURE,
+        ..zeroed()
+    };
+    if let Some(fps) = request.fps.filter(|fps| *fps > 0.0) {
+        parm.time_per_frame = [1000, (fps * 1000.0).round() as u32];
+        // Not every driver lets the rate be set; the probe reports what applies.
+        let _ = ioctl(&file, VIDIOC_S_PARM, &mut parm);
+    }
+    let fps = ioctl(&file, VIDIOC_G_PARM, &mut parm)
+        .ok()
+        .filter(|_| parm.time_per_frame[0] != 0)
+        .map(|_| f64::from(parm.time_per_frame[1]) / f64::from(parm.time_per_frame[0]));
+
+    let frame_bytes = capture_one(&file).map_err(busy)?;
+    Ok(CameraProbe {
+        fourcc: fourcc_string(pix.pixelformat),
+        width: pix.width,
+        height: pix.height,
+        fps,
+        frame_bytes,
+        first_frame_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Streams until one frame arrives and returns its size.
+fn capture_one(file: &File) -> io::Result<usize> {
+    let mut request = RequestBuffers {
+        count: BUFFER_COUNT,
+        kind: BUF_TYPE_VIDEO_CAPTURE,
+        memory: MEMORY_MMAP,
+        ..zeroed()
+    };
+    ioctl(file, VIDIOC_REQBUFS, &mut request)?;
+
+    let mut mappings = Vec::new();
+    for index in 0..request.count {
+        let mut buffer = Buffer {
+            index,
+            kind: BUF_TYPE_VIDEO_CAPTURE,
+            memory: MEMORY_MMAP,
+            ..zeroed()
+        };
+        ioctl(file, VIDIOC_QUERYBUF, &mut buffer)?;
+        // SAFETY: maps the driver buffer described by QUERYBUF; unmapped by `Mapping`.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                buffer.length as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                buffer.offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        mappings.push(Mapping {
+            ptr,
+            len: buffer.length as usize,
+        });
+        ioctl(file, VIDIOC_QBUF, &mut buffer)?;
+    }
+
+    let mut kind = BUF_TYPE_VIDEO_CAPTURE as libc::c_int;
+    ioctl(file, VIDIOC_STREAMON, &mut kind)?;
+    let result = wait_for_frame(file);
+    let _ = ioctl(file, VIDIOC_STREAMOFF, &mut kind);
+    drop(mappings);
+    let mut release = RequestBuffers {
+        count: 0,
+        ..request
+    };
+    let _ = ioctl(file, VIDIOC_REQBUFS, &mut release);
+    result
+}
+
+fn wait_for_frame(file: &File) -> io::Result<usize> {
+    let deadline = Instant::now() + FIRST_FRAME_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no frame from camera",
+            ));
+        }
+        let mut poll = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: a single valid pollfd.
+        let ready = unsafe { libc::poll(&mut poll, 1, remaining.as_millis() as libc::c_int) };
+        if ready == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if ready == 0 {
+            continue;
+        }
+        let mut buffer = Buffer {
+            kind: BUF_TYPE_VIDEO_CAPTURE,
+            memory: MEMORY_MMAP,
+            ..zeroed()
+        };
+        match ioctl(file, VIDIOC_DQBUF, &mut buffer) {
+            Ok(()) => return Ok(buffer.bytesused as usize),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,399 @@
+//! SocketCAN access for CAN-connected end effectors.
+//!
+//! Only available on Linux; elsewhere every command returns
+//! [`Error::Unsupported`]. Each opened interface gets a reader thread that
+//! emits `can-frame` events. [`subscribe_can`] installs kernel-side ID
+//! filters, and received frames are decoded with the DBC-style signal table
+//! from the `can` settings section.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_os = "linux")]
+pub use linux::*;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    /// Intel byte order: `startBit` is the least significant bit.
+    #[default]
+    Little,
+    /// Motorola byte order: `startBit` is the most significant bit.
+    Big,
+}
+
+/// One signal of a CAN message, as in a DBC `SG_` line.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanSignal {
+    pub name: String,
+    pub frame_id: u32,
+    pub start_bit: u16,
+    pub length: u16,
+    #[serde(default)]
+    pub byte_order: ByteOrder,
+    #[serde(default)]
+    pub signed: bool,
+    #[serde(default = "unit_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub unit: String,
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+impl CanSignal {
+    /// Extracts the physical value from a frame payload.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let length = usize::from(self.length);
+        if length == 0 || length > 64 {
+            return None;
+        }
+        let bit = |position: usize| -> Option<u64> {
+            let byte = data.get(position / 8)?;
+            Some(u64::from((byte >> (position % 8)) & 1))
+        };
+
+        let mut raw = 0u64;
+        match self.byte_order {
+            ByteOrder::Little => {
+                for i in 0..length {
+                    raw |= bit(usize::from(self.start_bit) + i)? << i;
+                }
+            }
+            ByteOrder::Big => {
+                // Walk the DBC "sawtooth" bit numbering from the MSB down.
+                let mut position = usize::from(self.start_bit);
+                for _ in 0..length {
+                    raw = (raw << 1) | bit(position)?;
+                    position = if position % 8 == 0 {
+                        position + 15
+                    } else {
+                        position - 1
+                    };
+                }
+            }
+        }
+
+        let value = if self.signed && length < 64 && raw & (1 << (length - 1)) != 0 {
+            (raw as i64 - (1i64 << length)) as f64
+        } else if self.signed {
+            raw as i64 as f64
+        } else {
+            raw as f64
+        };
+        Some(value * self.scale + self.offset)
+    }
+}
+
+/// Payload of the `can-frame` event.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanFrameEvent {
+    pub interface: String,
+    pub id: u32,
+    pub extended: bool,
+    pub data: Vec<u8>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub signals: BTreeMap<String, f64>,
+}
+
+/// Decodes every configured signal carried by frame `id`.
+pub fn decode_signals(table: &[CanSignal], id: u32, data: &[u8]) -> BTreeMap<String, f64> {
+    table
+        .iter()
+        .filter(|signal| signal.frame_id == id)
+        .filter_map(|signal| Some((signal.name.clone(), signal.decode(data)?)))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use socketcan::{
+        CanFilter, CanFrame, CanSocket, EmbeddedFrame, ExtendedId, Id, Socket, SocketOptions,
+        StandardId,
+    };
+    use tauri::{AppHandle, Manager, State};
+
+    use super::{decode_signals, CanFrameEvent};
+    use crate::error::{Error, Result};
+    use crate::settings::SettingsStore;
+    use crate::windows;
+
+    const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+    struct OpenInterface {
+        socket: Arc<CanSocket>,
+        stop: Arc<AtomicBool>,
+    }
+
+    /// CAN interfaces opened through [`open_can`], keyed by name.
+    #[derive(Default)]
+    pub struct CanInterfaces(Mutex<HashMap<String, OpenInterface>>);
+
+    impl CanInterfaces {
+        fn socket(&self, interface: &str) -> Result<Arc<CanSocket>> {
+            self.0
+                .lock()
+                .unwrap()
+                .get(interface)
+                .map(|open| open.socket.clone())
+                .ok_or_else(|| Error::NotFound(format!("open CAN interface {interface}")))
+        }
+    }
+
+    #[tauri::command]
+    pub fn open_can(
+        app: AppHandle,
+        interfaces: State<'_, CanInterfaces>,
+        interface: String,
+    ) -> Result<()> {
+        let mut open = interfaces.0.lock().unwrap();
+        if open.contains_key(&interface) {
+            return Err(Error::Invalid(format!("{interface} is already open")));
+        }
+        let socket = Arc::new(CanSocket::open(&interface)?);
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let (socket, stop, interface) = (socket.clone(), stop.clone(), interface.clone());
+            std::thread::Builder::new()
+                .name(format!("can {interface}"))
+                .spawn(move || read_loop(app, interface, socket, stop))?;
+        }
+        tracing::info!(%interface, "CAN interface opened");
+        open.insert(interface, OpenInterface { socket, stop });
+        Ok(())
+    }
+
+    #[tauri::command]
+    pub fn close_can(interfaces: State<'_, CanInterfaces>, interface: String) -> Result<()> {
+        let open = interfaces
+            .0
+            .lock()
+            .unwrap()
+            .remove(&interface)
+            .ok_or_else(|| Error::NotFound(format!("open CAN interface {interface}")))?;
+        open.stop.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    #[tauri::command]
+    pub fn send_can_frame(
+        interfaces: State<'_, CanInterfaces>,
+        interface: String,
+        id: u32,
+        extended: bool,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let id: Id = if extended {
+            ExtendedId::new(id).map(Id::Extended)
+        } else {
+            u16::try_from(id)
+                .ok()
+                .and_then(StandardId::new)
+                .map(Id::Standard)
+        }
+        .ok_or_else(|| Error::Invalid(format!("CAN id {id:#x}")))?;
+        let frame = CanFrame::new(id, &data)
+            .ok_or_else(|| Error::Unsupported("CAN FD payloads over 8 bytes".into()))?;
+        interfaces.socket(&interface)?.write_frame(&frame)?;
+        Ok(())
+    }
+
+    /// Restricts delivered frames to data frames with one of `ids`, extended
+    /// ids when `extended`; an empty list receives everything.
+    #[tauri::command]
+    pub fn subscribe_can(
+        interfaces: State<'_, CanInterfaces>,
+        interface: String,
+        ids: Vec<u32>,
+        extended: bool,
+    ) -> Result<()> {
+        let socket = interfaces.socket(&interface)?;
+        if ids.is_empty() {
+            socket.set_filter_accept_all()?;
+        } else {
+            let filters = ids
+                .iter()
+                .map(|&id| filter(id, extended))
+                .collect::<Result<Vec<_>>>()?;
+            socket.set_filters(&filters)?;
+        }
+        Ok(())
+    }
+
+    /// Matches `id` exactly: the mask covers the frame format and RTR bits,
+    /// so a standard id does not also let through the extended frame with
+    /// the same number, nor remote frames.
+    fn filter(id: u32, extended: bool) -> Result<CanFilter> {
+        let flags = libc::CAN_EFF_FLAG | libc::CAN_RTR_FLAG;
+        let valid = if extended {
+            ExtendedId::new(id).is_some()
+        } else {
+            u16::try_from(id).ok().and_then(StandardId::new).is_some()
+        };
+        if !valid {
+            return Err(Error::Invalid(format!("CAN id {id:#x}")));
+        }
+        Ok(if extended {
+            CanFilter::new(id | libc::CAN_EFF_FLAG, flags | libc::CAN_EFF_MASK)
+        } else {
+            CanFilter::new(id, flags | libc::CAN_SFF_MASK)
+        })
+    }
+
+    fn read_loop(app: AppHandle, interface: String, socket: Arc<CanSocket>, stop: Arc<AtomicBool>) {
+        while !stop.load(Ordering::Relaxed) {
+            let frame = match socket.read_frame_timeout(READ_TIMEOUT) {
+                Ok(CanFrame::Data(frame)) => frame,
+                Ok(_) => continue,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => {
+                    tracing::warn!(%interface, "CAN read failed: {err}");
+                    break;
+                }
+            };
+            let (id, extended) = match frame.id() {
+                Id::Standard(id) => (u32::from(id.as_raw()), false),
+                Id::Extended(id) => (id.as_raw(), true),
+            };
+            let table = app.state::<SettingsStore>().get().can.signals;
+            let event = CanFrameEvent {
+                interface: interface.clone(),
+                id,
+                extended,
+                data: frame.data().to_vec(),
+                timestamp: chrono::Utc::now(),
+                signals: decode_signals(&table, id, frame.data()),
+            };
+            windows::emit(&app, "can-frame", event);
+        }
+        if let Some(interfaces) = app.try_state::<CanInterfaces>() {
+            let mut open = interfaces.0.lock().unwrap();
+            // The interface may have been closed and opened again meanwhile.
+            if open
+                .get(&interface)
+                .is_some_and(|open| Arc::ptr_eq(&open.stop, &stop))
+            {
+                open.remove(&interface);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod fallback {
+    use crate::error::{Error, Result};
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::Unsupported(
+            "SocketCAN is only available on Linux".into(),
+        ))
+    }
+
+    #[derive(Default)]
+    pub struct CanInterfaces;
+
+    #[tauri::command]
+    pub fn open_can(_interface: String) -> Result<()> {
+        unsupported()
+    }
+
+    #[tauri::command]
+    pub fn close_can(_interface: String) -> Result<()> {
+        unsupported()
+    }
+
+    #[tauri::command]
+    pub fn send_can_frame(
+        _interface: String,
+        _id: u32,
+        _extended: bool,
+        _data: Vec<u8>,
+    ) -> Result<()> {
+        unsupported()
+    }
+
+    #[tauri::command]
+    pub fn subscribe_can(_interface: String, _ids: Vec<u32>, _extended: bool) -> Result<()> {
+        unsupported()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub use fallback::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(start_bit: u16, length: u16, byte_order: ByteOrder) -> CanSignal {
+        CanSignal {
+            name: "signal".into(),
+            frame_id: 0x100,
+            start_bit,
+            length,
+            byte_order,
+            signed: false,
+            scale: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+        }
+    }
+
+    #[test]
+    fn decodes_intel_signals() {
+        let signal = signal(8, 16, ByteOrder::Little);
+        assert_eq!(signal.decode(&[0x00, 0x34, 0x12]), Some(4660.0));
+    }
+
+    #[test]
+    fn decodes_motorola_signals() {
+        let signal = signal(7, 16, ByteOrder::Big);
+        assert_eq!(signal.decode(&[0x12, 0x34]), Some(4660.0));
+    }
+
+    #[test]
+    fn applies_sign_scale_and_offset() {
+        let signal = CanSignal {
+            signed: true,
+            scale: 0.5,
+            offset: 10.0,
+            ..signal(0, 8, ByteOrder::Little)
+        };
+        assert_eq!(signal.decode(&[0xFF]), Some(9.5));
+    }
+
+    #[test]
+    fn rejects_signals_outside_the_payload() {
+        assert_eq!(signal(0, 16, ByteOrder::Little).decode(&[0x01]), None);
+        assert_eq!(signal(0, 0, ByteOrder::Little).decode(&[0x01]), None);
+    }
+
+    #[test]
+    fn decodes_only_signals_of_the_frame() {
+        let table = [signal(0, 8, ByteOrder::Little)];
+        assert_eq!(
+            decode_signals(&table, 0x100, &[7]).get("signal"),
+            Some(&7.0)
+        );
+        assert!(decode_signals(&table, 0x101, &[7]).is_empty());
+    }
+}
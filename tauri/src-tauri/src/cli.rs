@@ -0,0 +1,159 @@
+//! Command-line options for scripted launches, e.g. from an MES.
+//!
+//! They apply to this run only and override the settings without changing
+//! them:
+//!
+//! | Option | Effect |
+//! | --- | --- |
+//! | `--profile <name>` | launches the backend with profile `name` |
+//! | `--backend-arg <arg>` | appends `arg` to the backend's arguments; repeatable |
+//! | `--backend-env <KEY=VALUE>` | sets a backend environment variable; repeatable |
+//! | `--port <port>` | serves the webview on TCP `port` instead of a socket |
+//! | `--data-dir <path>` | the backend's `PHYSICAL_AI_DATA_DIR` |
+//! | `--window <mode>` | `normal`, `maximized`, `fullscreen` or `hidden` |
+//! | `--headless`, `--automation` | see [`crate::headless`], [`crate::automation`] |
+//! | `-- <args>...` | appends every following argument to the backend's |
+//!
+//! Values may also be given as `--option=value`. Other arguments are files
+//! and `percus://` links, as file associations and deep links pass them.
+//! [`parse_env`] runs before the app is built: an unknown option or a bad
+//! value prints the usage and exits with status 2, before any window or
+//! backend starts, so a misconfigured launch script fails visibly.
+
+use std::path::PathBuf;
+
+use crate::automation;
+use crate::headless;
+
+const USAGE: &str = "\
+usage: percus [options] [files or percus:// links]
+
+options:
+  --profile <name>           launch profile of the backend
+  --backend-arg <arg>        extra backend argument (repeatable)
+  --backend-env <KEY=VALUE>  extra backend environment variable (repeatable)
+  --port <port>              serve the webview on this TCP port
+  --data-dir <path>          backend data directory
+  --window <mode>            normal, maximized, fullscreen or hidden
+  --headless                 run without windows
+  --automation               start the automation server
+  -- <args>...               pass the remaining arguments to the backend
+  -h, --help                 print this help";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WindowMode {
+    Normal,
+    Maximized,
+    Fullscreen,
+    /// The main window is created but stays hidden until shown from the
+    /// tray or a second launch.
+    Hidden,
+}
+
+/// Options of this launch; managed state.
+#[derive(Clone)]
+pub struct LaunchOptions {
+    pub profile: Option<String>,
+    pub backend_args: Vec<String>,
+    pub backend_env: Vec<(String, String)>,
+    pub port: Option<u16>,
+    pub data_dir: Option<PathBuf>,
+    pub window: WindowMode,
+    /// Files and links, in order.
+    pub positional: Vec<String>,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            backend_args: Vec::new(),
+            backend_env: Vec::new(),
+            port: None,
+            data_dir: None,
+            window: WindowMode::Normal,
+            positional: Vec::new(),
+        }
+    }
+}
+
+/// Parses the process arguments, exiting on `--help` or an error.
+pub fn parse_env() -> LaunchOptions {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        std::process::exit(0);
+    }
+    match parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("percus: {message}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn parse(args: Vec<String>) -> Result<LaunchOptions, String> {
+    let mut options = LaunchOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            options.backend_args.extend(args.by_ref());
+            break;
+        }
+        if arg == headless::FLAG || arg == automation::FLAG {
+            continue;
+        }
+        // Process serial numbers added by older macOS Finder launches.
+        if arg.starts_with("-psn_") {
+            continue;
+        }
+        let Some(option) = arg.strip_prefix("--") else {
+            options.positional.push(arg);
+            continue;
+        };
+        let (name, inline) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (option, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("--{name} needs a value"))
+        };
+        match name {
+            "profile" => options.profile = Some(value()?),
+            "backend-arg" => options.backend_args.push(value()?),
+            "backend-env" => {
+                let pair = value()?;
+                let (key, val) = pair
+                    .split_once('=')
+                    .filter(|(key, _)| !key.is_empty())
+                    .ok_or_else(|| format!("--backend-env {pair}: expected KEY=VALUE"))?;
+                options.backend_env.push((key.to_owned(), val.to_owned()));
+            }
+            "port" => {
+                let port = value()?;
+                options.port = Some(
+                    port.parse()
+                        .ok()
+                        .filter(|port| *port != 0)
+                        .ok_or_else(|| format!("--port {port}: not a port number"))?,
+                );
+            }
+            "data-dir" => options.data_dir = Some(PathBuf::from(value()?)),
+            "window" => {
+                options.window = match value()?.as_str() {
+                    "normal" => WindowMode::Normal,
+                    "maximized" => WindowMode::Maximized,
+                    "fullscreen" => WindowMode::Fullscreen,
+                    "hidden" => WindowMode::Hidden,
+                    other => return Err(format!("--window {other}: unknown mode")),
+                }
+            }
+            _ => return Err(format!("unknown option --{name}")),
+        }
+    }
+    Ok(options)
+}
@@ -0,0 +1,185 @@
+//! QR code scanning of a camera stream, for the job codes on incoming parts.
+//!
+//! [`start_code_scan`] decodes the stream's new frames until a code is read
+//! or the timeout passes. A read is reported as `code-detected` with the
+//! payload and where the code sits in the frame, and ends the scan; every
+//! scan ends with `code-scan-ended` giving the reason. One scan runs at a
+//! time.
+
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::frames::{self, FrameRings};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Frames newer than the last one are looked at this often.
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeScanRequest {
+    /// Frame ring stream to scan.
+    pub stream: String,
+    /// 30 s when unset.
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoundingBox {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeDetected {
+    pub stream: String,
+    pub payload: String,
+    /// Corners in frame pixels, clockwise from the top left of the code.
+    pub corners: [Point; 4],
+    pub bounding_box: BoundingBox,
+    pub timestamp_ns: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScanEnded {
+    stream: String,
+    /// `matched`, `timeout`, `stopped` or `error`.
+    reason: &'static str,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct CodeScanner(StdMutex<Option<(String, JoinHandle<()>)>>);
+
+/// Scans `stream` until a code is read or the timeout passes.
+#[tauri::command]
+pub fn start_code_scan(
+    app: AppHandle,
+    scanner: State<'_, CodeScanner>,
+    request: CodeScanRequest,
+) -> Result<()> {
+    if !frames::valid_stream(&request.stream) {
+        return Err(Error::Invalid(format!("stream {}", request.stream)));
+    }
+    let mut running = scanner.0.lock().unwrap();
+    if running
+        .as_ref()
+        .is_some_and(|(_, task)| !task.inner().is_finished())
+    {
+        return Err(Error::DeviceBusy("a code scan is already running".into()));
+    }
+    let timeout = request
+        .timeout_ms
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+    let stream = request.stream.clone();
+    let task = tauri::async_runtime::spawn({
+        let app = app.clone();
+        let stream = stream.clone();
+        async move {
+            let (reason, error) = match tokio::time::timeout(timeout, scan(&app, &stream)).await {
+                Ok(Ok(())) => ("matched", None),
+                Ok(Err(err)) => ("error", Some(err.to_string())),
+                Err(_) => ("timeout", None),
+            };
+            end(&app, stream, reason, error);
+        }
+    });
+    *running = Some((stream, task));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_code_scan(app: AppHandle, scanner: State<'_, CodeScanner>) {
+    if let Some((stream, task)) = scanner.0.lock().unwrap().take() {
+        if !task.inner().is_finished() {
+            task.abort();
+            end(&app, stream, "stopped", None);
+        }
+    }
+}
+
+fn end(app: &AppHandle, stream: String, reason: &'static str, error: Option<String>) {
+    let _ = app.emit(
+        "code-scan-ended",
+        ScanEnded {
+            stream,
+            reason,
+            error,
+        },
+    );
+}
+
+/// Decodes new frames until one holds a code.
+async fn scan(app: &AppHandle, stream: &str) -> Result<()> {
+    let mut ticker = tokio::time::interval(POLL);
+    let mut last = None;
+    loop {
+        ticker.tick().await;
+        let Some(frame) = app.state::<FrameRings>().latest_frame(stream)? else {
+            continue;
+        };
+        if last == Some(frame.seq) {
+            continue;
+        }
+        last = Some(frame.seq);
+        let timestamp_ns = frame.timestamp_ns;
+        let found = tauri::async_runtime::spawn_blocking(move || -> Result<_> {
+            Ok(detect(&frame.decode()?.to_luma8()))
+        })
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))??;
+        if let Some((payload, corners)) = found {
+            let xs = corners.map(|point| point.x);
+            let ys = corners.map(|point| point.y);
+            let (x, y) = (*xs.iter().min().unwrap(), *ys.iter().min().unwrap());
+            let detected = CodeDetected {
+                stream: stream.to_owned(),
+                payload,
+                corners,
+                bounding_box: BoundingBox {
+                    x,
+                    y,
+                    width: xs.iter().max().unwrap() - x,
+                    height: ys.iter().max().unwrap() - y,
+                },
+                timestamp_ns,
+            };
+            tracing::info!(stream, payload = %detected.payload, "code detected");
+            let _ = app.emit("code-detected", detected);
+            return Ok(());
+        }
+    }
+}
+
+/// The first QR code in `image` that decodes.
+fn detect(image: &image::GrayImage) -> Option<(String, [Point; 4])> {
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32).0[0],
+    );
+    prepared.detect_grids().into_iter().find_map(|grid| {
+        let (_, payload) = grid.decode().ok()?;
+        let corners = grid.bounds.map(|point| Point {
+            x: point.x,
+            y: point.y,
+        });
+        Some((payload, corners))
+    })
+}
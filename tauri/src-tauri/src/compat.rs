@@ -0,0 +1,179 @@
+//! Version handshake between the shell and `percus-server`.
+//!
+//! A partial update can leave a webview and shell of one release with a
+//! backend of another, which then disagree on the API in confusing ways.
+//! Once a backend answers `/health`, [`check`] reads `GET /version`:
+//! `{"version": "0.9.1", "apiVersion": "2.3", "capabilities": [...]}`. The
+//! backend is compatible when its API version is [`MIN_API`] or a later
+//! minor version of the same major; a backend without the endpoint predates
+//! the handshake and is not.
+//!
+//! An incompatible primary backend is not announced as `backend-ready`;
+//! `backend-incompatible` carries what was found and the ways out instead,
+//! and [`get_backend_compatibility`] returns the same for a UI loading
+//! later. Sessions report as `session:<id>:backend-incompatible`.
+
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::backend_tls;
+use crate::profiles;
+
+/// Oldest API version, `(major, minor)`, this shell works with.
+const MIN_API: (u32, u32) = (1, 0);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct VersionInfo {
+    version: Option<String>,
+    api_version: Option<String>,
+    capabilities: Vec<String>,
+}
+
+/// Something the user can do about an incompatible backend.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Remediation {
+    /// `update-app`, `switch-profile`, `restart-backend` or `reinstall`.
+    pub action: &'static str,
+    pub label: &'static str,
+    /// Command carrying it out, when the shell has one.
+    pub command: Option<&'static str>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendCompatibility {
+    pub compatible: bool,
+    pub shell_version: String,
+    /// e.g. `1.0 – 1.x`.
+    pub supported_api: String,
+    pub backend_version: Option<String>,
+    pub api_version: Option<String>,
+    pub capabilities: Vec<String>,
+    pub reason: Option<String>,
+    pub remediations: Vec<Remediation>,
+}
+
+/// Result of the primary backend's last handshake.
+#[derive(Default)]
+pub struct Compatibility(StdMutex<Option<BackendCompatibility>>);
+
+#[tauri::command]
+pub fn get_backend_compatibility(
+    compatibility: State<'_, Compatibility>,
+) -> Option<BackendCompatibility> {
+    compatibility.0.lock().unwrap().clone()
+}
+
+/// Runs the handshake with the backend serving TLS on `tls_port`.
+pub async fn check(app: &AppHandle, tls_port: u16) -> BackendCompatibility {
+    let info = fetch(app, tls_port).await;
+    let mut result = BackendCompatibility {
+        compatible: false,
+        shell_version: app.package_info().version.to_string(),
+        supported_api: format!("{}.{} – {}.x", MIN_API.0, MIN_API.1, MIN_API.0),
+        backend_version: None,
+        api_version: None,
+        capabilities: Vec::new(),
+        reason: None,
+        remediations: Vec::new(),
+    };
+    let reason = match info {
+        Err(reason) => Some(reason),
+        Ok(info) => {
+            let reason = match info.api_version.as_deref().map(parse) {
+                None => Some("the backend reports no API version".to_owned()),
+                Some(None) => Some(format!(
+                    "the backend reports API version {:?}, which is not `major.minor`",
+                    info.api_version.as_deref().unwrap_or_default()
+                )),
+                Some(Some((major, minor))) if major != MIN_API.0 || (major, minor) < MIN_API => {
+                    Some(format!(
+                        "the backend speaks API {major}.{minor}; this app needs {}",
+                        result.supported_api
+                    ))
+                }
+                Some(Some(_)) => None,
+            };
+            result.backend_version = info.version;
+            result.api_version = info.api_version;
+            result.capabilities = info.capabilities;
+            reason
+        }
+    };
+    result.compatible = reason.is_none();
+    if let Some(reason) = &reason {
+        tracing::error!(
+            backend = ?result.backend_version,
+            api = ?result.api_version,
+            "backend is incompatible: {reason}"
+        );
+        result.remediations = remediations(app);
+    }
+    result.reason = reason;
+    result
+}
+
+/// Records the primary backend's handshake for [`get_backend_compatibility`].
+pub fn record(app: &AppHandle, result: &BackendCompatibility) {
+    *app.state::<Compatibility>().0.lock().unwrap() = Some(result.clone());
+}
+
+async fn fetch(app: &AppHandle, tls_port: u16) -> std::result::Result<VersionInfo, String> {
+    let url = backend_tls::url_at(tls_port, "https", "/version");
+    let response = backend_tls::http_client(app)
+        .get(&url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|err| format!("the backend's version could not be read: {err}"))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err("the backend predates the version handshake".into());
+    }
+    let response = response
+        .error_for_status()
+        .map_err(|err| format!("the backend's version could not be read: {err}"))?;
+    response
+        .json()
+        .await
+        .map_err(|err| format!("the backend's version is malformed: {err}"))
+}
+
+/// `major.minor`, where a bare major means minor 0.
+fn parse(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |minor| minor.parse().ok())?;
+    Some((major, minor))
+}
+
+fn remediations(app: &AppHandle) -> Vec<Remediation> {
+    let mut remediations = vec![Remediation {
+        action: "update-app",
+        label: "Update the app so the shell and backend match",
+        command: Some("check_for_updates"),
+    }];
+    if profiles::list_profiles(app.state()).len() > 1 {
+        remediations.push(Remediation {
+            action: "switch-profile",
+            label: "Switch to a launch profile with a matching backend",
+            command: Some("switch_profile"),
+        });
+    }
+    remediations.push(Remediation {
+        action: "restart-backend",
+        label: "Restart the backend after replacing it",
+        command: Some("restart_backend"),
+    });
+    remediations.push(Remediation {
+        action: "reinstall",
+        label: "Reinstall the app from the latest installer",
+        command: None,
+    });
+    remediations
+}
@@ -0,0 +1,156 @@
+//! Crash reports for shell panics and sidecar crashes.
+//!
+//! Each crash is written as a bundle directory under `crashes/` in the app
+//! data directory, containing `report.json` and the most recent sidecar
+//! output in `backend.log`. The UI lists bundles via [`list_crash_reports`]
+//! and prompts the user to submit them.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_shell::process::TerminatedPayload;
+
+use crate::error::Result;
+use crate::logging::LogState;
+
+const CRASH_DIR: &str = "crashes";
+const REPORT_FILE: &str = "report.json";
+const BACKEND_LOG_FILE: &str = "backend.log";
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+    ShellPanic,
+    SidecarCrash,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub id: String,
+    pub kind: CrashKind,
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub backtrace: Option<String>,
+    pub app_version: String,
+    pub os: String,
+    /// Bundle directory, filled in when listing.
+    #[serde(default, skip_deserializing)]
+    pub path: PathBuf,
+}
+
+/// Installs a panic hook that writes a crash bundle before the default hook runs.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let dir = crash_dir(app)?;
+    std::fs::create_dir_all(&dir)?;
+
+    let app = app.clone();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let report = new_report(
+            &app,
+            CrashKind::ShellPanic,
+            info.to_string(),
+            Some(std::backtrace::Backtrace::force_capture().to_string()),
+        );
+        let _ = write_bundle(&app, &dir, report);
+        default_hook(info);
+    }));
+    Ok(())
+}
+
+/// Records an unexpected sidecar exit.
+pub fn record_sidecar_crash(app: &AppHandle, exit: Option<&TerminatedPayload>) {
+    let mut report = new_report(
+        app,
+        CrashKind::SidecarCrash,
+        match exit {
+            Some(exit) => format!(
+                "backend exited with code {:?}, signal {:?}",
+                exit.code, exit.signal
+            ),
+            None => "backend output closed unexpectedly".into(),
+        },
+        None,
+    );
+    report.exit_code = exit.and_then(|exit| exit.code);
+    report.signal = exit.and_then(|exit| exit.signal);
+
+    let written = crash_dir(app).and_then(|dir| write_bundle(app, &dir, report));
+    match written {
+        Ok(path) => tracing::warn!(path = %path.display(), "wrote sidecar crash report"),
+        Err(err) => tracing::error!("failed to write sidecar crash report: {err}"),
+    }
+}
+
+fn new_report(
+    app: &AppHandle,
+    kind: CrashKind,
+    message: String,
+    backtrace: Option<String>,
+) -> CrashReport {
+    let timestamp = Utc::now();
+    let suffix = match kind {
+        CrashKind::ShellPanic => "panic",
+        CrashKind::SidecarCrash => "sidecar",
+    };
+    CrashReport {
+        id: format!("{}-{suffix}", timestamp.format("%Y%m%dT%H%M%S%.3fZ")),
+        kind,
+        timestamp,
+        message,
+        exit_code: None,
+        signal: None,
+        backtrace,
+        app_version: app.package_info().version.to_string(),
+        os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        path: PathBuf::new(),
+    }
+}
+
+fn write_bundle(app: &AppHandle, dir: &Path, report: CrashReport) -> Result<PathBuf> {
+    let bundle = dir.join(&report.id);
+    std::fs::create_dir_all(&bundle)?;
+    std::fs::write(
+        bundle.join(REPORT_FILE),
+        serde_json::to_vec_pretty(&report)?,
+    )?;
+    if let Some(logs) = app.try_state::<LogState>() {
+        std::fs::write(
+            bundle.join(BACKEND_LOG_FILE),
+            logs.recent_lines().join("\n"),
+        )?;
+    }
+    Ok(bundle)
+}
+
+/// Where crash bundles are written.
+pub fn crash_dir(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(CRASH_DIR))
+}
+
+/// Lists crash bundles, newest first.
+#[tauri::command]
+pub fn list_crash_reports(app: AppHandle) -> Result<Vec<CrashReport>> {
+    let dir = crash_dir(&app)?;
+    let mut reports = Vec::new();
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(reports);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(bytes) = std::fs::read(path.join(REPORT_FILE)) else {
+            continue;
+        };
+        if let Ok(mut report) = serde_json::from_slice::<CrashReport>(&bytes) {
+            report.path = path;
+            reports.push(report);
+        }
+    }
+    reports.sort_by_key(|report| std::cmp::Reverse(report.timestamp));
+    Ok(reports)
+}
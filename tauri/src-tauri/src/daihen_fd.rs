@@ -0,0 +1,470 @@
+//! Native TCP client for the Daihen FD-series robot controller.
+//!
+//! Talks to the controller directly so robot state is available with low
+//! latency and even while the ML backend is down. Requests and responses are
+//! CR/LF-terminated ASCII lines; a response starts with `OK` followed by the
+//! payload, or `NG` followed by an error code. Every command the shell sends
+//! is encoded in [`FdCommand::encode`], so adapting to a controller firmware
+//! revision only touches that function, [`RobotStatus::parse`],
+//! [`parse_pose`] and [`parse_clock`].
+//!
+//! While connected, a polling task emits `robot-status` events at the
+//! interval configured in settings. Stops go through [`FdController::urgent`]
+//! on a connection of their own, so they never wait behind a poll.
+
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::audit;
+use crate::error::{Error, Result};
+use crate::menu;
+use crate::settings::SettingsStore;
+use crate::tunnels;
+use crate::users::{self, Capability};
+use crate::windows;
+
+/// Controller variable banks addressable by the variable commands.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableKind {
+    Integer,
+    Real,
+    String,
+}
+
+impl VariableKind {
+    fn code(self) -> &'static str {
+        match self {
+            VariableKind::Integer => "I",
+            VariableKind::Real => "R",
+            VariableKind::String => "S",
+        }
+    }
+}
+
+pub enum FdCommand<'a> {
+    Status,
+    /// The controller's real-time clock.
+    Clock,
+    /// The current pose and joint angles.
+    Position,
+    StartProgram(u32),
+    StopProgram,
+    ReadVariable(VariableKind, u32),
+    WriteVariable(VariableKind, u32, &'a str),
+    /// A line sent verbatim, such as the configured watchdog safe stop.
+    Raw(&'a str),
+}
+
+impl FdCommand<'_> {
+    fn encode(&self) -> String {
+        match self {
+            FdCommand::Status => "STATUS".into(),
+            FdCommand::Clock => "TIME".into(),
+            FdCommand::Position => "GETPOS".into(),
+            FdCommand::StartProgram(number) => format!("START {number}"),
+            FdCommand::StopProgram => "STOP".into(),
+            FdCommand::ReadVariable(kind, index) => format!("GETVAR {} {index}", kind.code()),
+            FdCommand::WriteVariable(kind, index, value) => {
+                format!("SETVAR {} {index} {value}", kind.code())
+            }
+            FdCommand::Raw(line) => (*line).to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotStatus {
+    pub mode: Option<String>,
+    pub servo_on: bool,
+    pub running: bool,
+    pub program: Option<u32>,
+    pub step: Option<u32>,
+    pub alarm: Option<u32>,
+}
+
+/// Base-frame pose (mm, deg) and joint angles (deg) of the robot.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotPose {
+    pub pose: [f64; 6],
+    pub joints: [f64; 6],
+}
+
+impl RobotStatus {
+    /// Parses a `KEY=VALUE ...` status payload; unknown keys are ignored.
+    fn parse(payload: &str) -> Self {
+        let fields: HashMap<&str, &str> = payload
+            .split_whitespace()
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let number = |key: &str| fields.get(key).and_then(|value| value.parse().ok());
+        RobotStatus {
+            mode: fields.get("MODE").map(|mode| mode.to_string()),
+            servo_on: fields.get("SERVO") == Some(&"ON"),
+            running: fields.get("RUN") == Some(&"1"),
+            program: number("PROG"),
+            step: number("STEP"),
+            alarm: number("ALARM").filter(|&code| code != 0),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RobotStatusEvent {
+    connected: bool,
+    status: Option<RobotStatus>,
+    error: Option<String>,
+}
+
+struct Connection {
+    reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    writer: tokio::net::tcp::OwnedWriteHalf,
+}
+
+/// Connection to the controller shared by commands and the polling task.
+#[derive(Default)]
+pub struct FdController {
+    connection: Mutex<Option<Connection>>,
+    address: StdMutex<Option<(String, u16)>>,
+    timeout: StdMutex<Duration>,
+    poller: StdMutex<Option<JoinHandle<()>>>,
+    last_status: StdMutex<Option<RobotStatus>>,
+}
+
+impl FdController {
+    /// Sends one command and returns the `OK` payload.
+    ///
+    /// The connection is re-established lazily after any I/O failure.
+    #[tracing::instrument(level = "debug", name = "fd.request", skip_all, fields(command = %command.encode()))]
+    pub async fn request(&self, command: FdCommand<'_>) -> Result<String> {
+        let address = self
+            .address
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| Error::Robot("not connected".into()))?;
+        let timeout = *self.timeout.lock().unwrap();
+
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            let stream = tokio::time::timeout(timeout, TcpStream::connect(&address))
+                .await
+                .map_err(|_| {
+                    Error::Robot(format!("connect to {}:{} timed out", address.0, address.1))
+                })??;
+            stream.set_nodelay(true)?;
+            let (reader, writer) = stream.into_split();
+            *connection = Some(Connection {
+                reader: BufReader::new(reader),
+                writer,
+            });
+        }
+
+        let result =
+            tokio::time::timeout(timeout, exchange(connection.as_mut().unwrap(), &command)).await;
+        match result {
+            Ok(Ok(response)) => parse_response(&response),
+            Ok(Err(err)) => {
+                *connection = None;
+                Err(err.into())
+            }
+            Err(_) => {
+                *connection = None;
+                Err(Error::Robot(format!("`{}` timed out", command.encode())))
+            }
+        }
+    }
+
+    pub async fn status(&self) -> Result<RobotStatus> {
+        let status = RobotStatus::parse(&self.request(FdCommand::Status).await?);
+        *self.last_status.lock().unwrap() = Some(status.clone());
+        Ok(status)
+    }
+
+    /// Reads the controller clock as Unix milliseconds.
+    pub async fn clock(&self) -> Result<i64> {
+        let payload = self.request(FdCommand::Clock).await?;
+        parse_clock(&payload)
+            .ok_or_else(|| Error::Robot(format!("unexpected clock payload `{payload}`")))
+    }
+
+    /// Reads the current pose and joint angles.
+    pub async fn position(&self) -> Result<RobotPose> {
+        let payload = self.request(FdCommand::Position).await?;
+        parse_pose(&payload)
+            .ok_or_else(|| Error::Robot(format!("unexpected position payload `{payload}`")))
+    }
+
+    /// Stops the running program.
+    pub async fn stop(&self) -> Result<()> {
+        self.request(FdCommand::StopProgram).await.map(drop)
+    }
+
+    /// Sends `command` on a fresh connection instead of the shared one, so
+    /// it is not held up by a poll or connect in flight, and reaches the
+    /// controller configured in settings even before [`connect_robot`].
+    pub async fn urgent(&self, app: &AppHandle, command: FdCommand<'_>) -> Result<String> {
+        let settings = app.state::<SettingsStore>().get();
+        let address = match self.address.lock().unwrap().clone() {
+            Some(address) => address,
+            None => {
+                let host = settings.robot_host().ok_or_else(|| {
+                    Error::Invalid("no robot controller address configured".into())
+                })?;
+                tunnels::resolve(app, &host, settings.robot.port)
+            }
+        };
+        let timeout = Duration::from_millis(settings.robot.timeout_ms);
+        let exchanged = tokio::time::timeout(timeout, async {
+            let stream = TcpStream::connect(&address).await?;
+            stream.set_nodelay(true)?;
+            let (reader, writer) = stream.into_split();
+            let mut connection = Connection {
+                reader: BufReader::new(reader),
+                writer,
+            };
+            exchange(&mut connection, &command).await
+        })
+        .await;
+        match exchanged {
+            Ok(Ok(response)) => parse_response(&response),
+            Ok(Err(err)) => Err(err.into()),
+            Err(_) => Err(Error::Robot(format!(
+                "`{}` to {}:{} timed out",
+                command.encode(),
+                address.0,
+                address.1
+            ))),
+        }
+    }
+
+    /// Whether [`connect_robot`] configured a controller to talk to.
+    pub fn is_connected(&self) -> bool {
+        self.address.lock().unwrap().is_some()
+    }
+}
+
+async fn exchange(connection: &mut Connection, command: &FdCommand<'_>) -> std::io::Result<String> {
+    let mut line = command.encode();
+    line.push_str("\r\n");
+    connection.writer.write_all(line.as_bytes()).await?;
+
+    let mut response = String::new();
+    if connection.reader.read_line(&mut response).await? == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(response.trim_end().to_owned())
+}
+
+/// Parses a `TIME=<unix ms>` payload.
+fn parse_clock(payload: &str) -> Option<i64> {
+    payload
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("TIME="))?
+        .parse()
+        .ok()
+}
+
+/// Parses `BASE x y z rx ry rz JOINT j1 .. j6`, the position payload also
+/// used by the jog stream.
+pub fn parse_pose(payload: &str) -> Option<RobotPose> {
+    let mut fields = payload.split_whitespace();
+    let mut read = |label: &str| -> Option<[f64; 6]> {
+        if fields.next()? != label {
+            return None;
+        }
+        let mut values = [0.0; 6];
+        for value in &mut values {
+            *value = fields.next()?.parse().ok()?;
+        }
+        Some(values)
+    };
+    let pose = read("BASE")?;
+    let joints = read("JOINT")?;
+    Some(RobotPose { pose, joints })
+}
+
+fn parse_response(response: &str) -> Result<String> {
+    match response.split_once(' ').unwrap_or((response, "")) {
+        ("OK", payload) => Ok(payload.to_owned()),
+        ("NG", code) => Err(Error::Robot(format!(
+            "controller rejected command (NG {code})"
+        ))),
+        _ => Err(Error::Robot(format!("unexpected response `{response}`"))),
+    }
+}
+
+async fn poll(app: AppHandle, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let controller = app.state::<FdController>();
+        let event = match controller.status().await {
+            Ok(status) => RobotStatusEvent {
+                connected: true,
+                status: Some(status),
+                error: None,
+            },
+            Err(err) => RobotStatusEvent {
+                connected: false,
+                status: None,
+                error: Some(err.to_string()),
+            },
+        };
+        windows::emit(&app, "robot-status", event);
+    }
+}
+
+/// Connects to the controller configured in settings and starts status polling.
+#[tauri::command]
+pub async fn connect_robot(
+    app: AppHandle,
+    controller: State<'_, FdController>,
+) -> Result<RobotStatus> {
+    let settings = app.state::<SettingsStore>().get();
+    let host = settings
+        .robot_host()
+        .ok_or_else(|| Error::Invalid("no robot controller address configured".into()))?;
+    let address = tunnels::resolve(&app, &host, settings.robot.port);
+    *controller.address.lock().unwrap() = Some(address);
+    menu::sync(&app);
+    *controller.timeout.lock().unwrap() = Duration::from_millis(settings.robot.timeout_ms);
+    controller.connection.lock().await.take();
+
+    let status = controller.status().await?;
+    let interval = Duration::from_millis(settings.robot.poll_interval_ms.max(10));
+    let poller = tauri::async_runtime::spawn(poll(app.clone(), interval));
+    if let Some(previous) = controller.poller.lock().unwrap().replace(poller) {
+        previous.abort();
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn disconnect_robot(app: AppHandle, controller: State<'_, FdController>) -> Result<()> {
+    if let Some(poller) = controller.poller.lock().unwrap().take() {
+        poller.abort();
+    }
+    controller.address.lock().unwrap().take();
+    controller.connection.lock().await.take();
+    controller.last_status.lock().unwrap().take();
+    menu::sync(&app);
+    Ok(())
+}
+
+/// Latest polled status, without a round trip to the controller.
+#[tauri::command]
+pub fn robot_status(controller: State<'_, FdController>) -> Option<RobotStatus> {
+    controller.last_status.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub async fn start_program(
+    app: AppHandle,
+    controller: State<'_, FdController>,
+    number: u32,
+) -> Result<()> {
+    users::require(&app, Capability::RunPrograms)?;
+    let result = controller
+        .request(FdCommand::StartProgram(number))
+        .await
+        .map(drop);
+    let error = audit::outcome(&result);
+    audit::record(
+        &app,
+        "programStart",
+        serde_json::json!({ "number": number, "error": error }),
+    );
+    result
+}
+
+#[tauri::command]
+pub async fn stop_program(app: AppHandle, controller: State<'_, FdController>) -> Result<()> {
+    let result = controller.stop().await;
+    audit::record(
+        &app,
+        "programStop",
+        serde_json::json!({ "error": audit::outcome(&result) }),
+    );
+    result
+}
+
+#[tauri::command]
+pub async fn read_robot_variable(
+    controller: State<'_, FdController>,
+    kind: VariableKind,
+    index: u32,
+) -> Result<String> {
+    controller
+        .request(FdCommand::ReadVariable(kind, index))
+        .await
+}
+
+#[tauri::command]
+pub async fn write_robot_variable(
+    app: AppHandle,
+    controller: State<'_, FdController>,
+    kind: VariableKind,
+    index: u32,
+    value: String,
+) -> Result<()> {
+    users::require(&app, Capability::WriteRobotVariables)?;
+    if value.contains(['\r', '\n']) {
+        return Err(Error::Invalid(
+            "variable value must be a single line".into(),
+        ));
+    }
+    let result = controller
+        .request(FdCommand::WriteVariable(kind, index, &value))
+        .await
+        .map(drop);
+    let detail = serde_json::json!({
+        "kind": kind.code(),
+        "index": index,
+        "value": value,
+        "error": audit::outcome(&result),
+    });
+    audit::record(&app, "robotVariableWrite", detail);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pose_and_joints() {
+        let pose = parse_pose("BASE 1 2 3 4.5 -6 7 JOINT 10 20 30 40 50 60.25").unwrap();
+        assert_eq!(pose.pose, [1.0, 2.0, 3.0, 4.5, -6.0, 7.0]);
+        assert_eq!(pose.joints, [10.0, 20.0, 30.0, 40.0, 50.0, 60.25]);
+    }
+
+    #[test]
+    fn rejects_incomplete_poses() {
+        assert!(parse_pose("BASE 1 2 3 4 5 6").is_none());
+        assert!(parse_pose("BASE 1 2 3 4 5 JOINT 1 2 3 4 5 6").is_none());
+        assert!(parse_pose("JOINT 1 2 3 4 5 6 BASE 1 2 3 4 5 6").is_none());
+        assert!(parse_pose("BASE 1 2 3 4 5 x JOINT 1 2 3 4 5 6").is_none());
+    }
+
+    #[test]
+    fn parses_responses() {
+        assert_eq!(parse_response("OK STATE=RUN").unwrap(), "STATE=RUN");
+        assert_eq!(parse_response("OK").unwrap(), "");
+        let rejected = parse_response("NG 12").unwrap_err();
+        assert!(rejected.to_string().contains("NG 12"));
+        assert!(parse_response("HELLO").is_err());
+    }
+}
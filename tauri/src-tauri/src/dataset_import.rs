@@ -0,0 +1,369 @@
+//! Importing exported datasets by dropping them onto the main window.
+//!
+//! A dropped folder or `.zip` holding a LeRobot dataset (at its top level or
+//! in a single folder inside) is validated with
+//! [`datasets::validate_dataset`], staged in a hidden directory under the
+//! recording root and then renamed into place, so a failed import never
+//! leaves a partial dataset behind. The dataset is named after its folder,
+//! or the archive if it has none, with `-2`, `-3`, ... appended when the
+//! name is taken. [`import_dataset`] does the same for a path picked in the
+//! UI. Each import emits `dataset-import-started`, `dataset-import-progress`
+//! and finally `dataset-import-finished`.
+//!
+//! `.percus` episode archives are zips of an exported dataset. When the app
+//! is launched with one, or a running instance is asked to open one, the
+//! archive is verified (entry checksums while extracting, then the dataset
+//! structure), imported, and the main window is asked to replay it with
+//! `open-replay`. The replay requested at launch is also kept until
+//! collected with [`take_pending_replay`].
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex as StdMutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, DragDropEvent, Emitter, Manager, State, WindowEvent};
+use zip::ZipArchive;
+
+use crate::datasets;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::instance::focus_main_window;
+use crate::recording;
+
+const STAGING_PREFIX: &str = ".import-";
+/// Extension of episode archives, registered with the OS.
+const ARCHIVE_EXTENSION: &str = "percus";
+/// Smallest progress step worth an event.
+const PROGRESS_STEP: f64 = 0.01;
+
+#[derive(Default)]
+pub struct DatasetImports {
+    next_id: AtomicU32,
+    /// Replay requested before the frontend was listening.
+    pending_replay: StdMutex<Option<Replay>>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub dataset: String,
+    pub episode_index: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportStarted {
+    id: u32,
+    path: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportProgress {
+    id: u32,
+    /// 0 to 1.
+    fraction: f64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportFinished {
+    id: u32,
+    /// Name of the imported dataset.
+    dataset: Option<String>,
+    error: Option<String>,
+}
+
+/// Imports whatever is dropped onto the main window.
+pub fn init(app: &AppHandle) -> Result<()> {
+    app.manage(DatasetImports::default());
+    let window = app
+        .get_webview_window("main")
+        .ok_or_else(|| Error::NotFound("main window".into()))?;
+    let handle = app.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event {
+            for path in paths {
+                start(&handle, path.clone(), false);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Imports the dataset folder or archive at `path`; returns the import id.
+#[tauri::command]
+pub fn import_dataset(app: AppHandle, path: PathBuf) -> u32 {
+    start(&app, path, false)
+}
+
+#[tauri::command]
+pub fn take_pending_replay(imports: State<'_, DatasetImports>) -> Option<Replay> {
+    imports.pending_replay.lock().unwrap().take()
+}
+
+/// Imports and replays the `.percus` archives among `args`, which are
+/// resolved against `cwd`; other arguments are ignored.
+pub fn open_archives<S: AsRef<Path>>(
+    app: &AppHandle,
+    args: impl IntoIterator<Item = S>,
+    cwd: &Path,
+) {
+    for arg in args {
+        let path = cwd.join(arg);
+        let is_archive = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case(ARCHIVE_EXTENSION));
+        if is_archive && path.is_file() {
+            start(app, path, true);
+        }
+    }
+}
+
+/// Imports `source`, then asks the main window to replay it if `replay`.
+fn start(app: &AppHandle, source: PathBuf, replay: bool) -> u32 {
+    let id = app
+        .state::<DatasetImports>()
+        .next_id
+        .fetch_add(1, Ordering::Relaxed);
+    tracing::info!(id, path = %source.display(), "importing dataset");
+    let _ = app.emit(
+        "dataset-import-started",
+        ImportStarted {
+            id,
+            path: source.clone(),
+        },
+    );
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut progress = Progress::new(&app, id);
+        let result = import(&app, id, &source, &mut progress);
+        progress.finish();
+        let finished = match result {
+            Ok(dataset) => {
+                tracing::info!(id, %dataset, "dataset imported");
+                if replay {
+                    open_replay(&app, dataset.clone());
+                }
+                ImportFinished {
+                    id,
+                    dataset: Some(dataset),
+                    error: None,
+                }
+            }
+            Err(err) => {
+                tracing::warn!(id, path = %source.display(), "dataset import failed: {err}");
+                ImportFinished {
+                    id,
+                    dataset: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        let _ = app.emit("dataset-import-finished", finished);
+    });
+    id
+}
+
+fn open_replay(app: &AppHandle, dataset: String) {
+    let replay = Replay {
+        dataset,
+        episode_index: 0,
+    };
+    focus_main_window(app);
+    *app.state::<DatasetImports>().pending_replay.lock().unwrap() = Some(replay.clone());
+    let _ = app.emit("open-replay", replay);
+}
+
+/// Imports `source`; returns the new dataset's name.
+fn import(app: &AppHandle, id: u32, source: &Path, progress: &mut Progress) -> Result<String> {
+    let root = recording::datasets_root(app)?;
+    std::fs::create_dir_all(&root)?;
+    let staging = root.join(format!("{STAGING_PREFIX}{id}"));
+    // Left over from an import interrupted in an earlier run.
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let result = stage(source, &staging, progress).and_then(|(staged, name)| {
+        let name = free_name(&root, &sanitize(&name));
+        std::fs::rename(&staged, root.join(&name))?;
+        Ok(name)
+    });
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// Copies or extracts `source` into `staging`; returns the validated dataset
+/// directory within it and the name suggested by the source.
+fn stage(source: &Path, staging: &Path, progress: &mut Progress) -> Result<(PathBuf, String)> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    if source.is_dir() {
+        let dir = locate(source)?;
+        datasets::validate_dataset(&dir)?;
+        copy_tree(&dir, staging, progress)?;
+        return Ok((staging.to_owned(), file_name(&dir)));
+    }
+    let is_zip = source.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("zip") || extension.eq_ignore_ascii_case(ARCHIVE_EXTENSION)
+    });
+    if !is_zip {
+        return Err(Error::Invalid(format!(
+            "{} is neither a dataset folder nor an archive",
+            source.display()
+        )));
+    }
+    extract(source, staging, progress)?;
+    let dir = locate(staging)?;
+    datasets::validate_dataset(&dir)?;
+    let name = if dir == staging {
+        source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        file_name(&dir)
+    };
+    Ok((dir, name))
+}
+
+/// `dir` if it is a dataset, or its only subdirectory that is.
+fn locate(dir: &Path) -> Result<PathBuf> {
+    if dir.join("meta/info.json").is_file() {
+        return Ok(dir.to_owned());
+    }
+    let mut found = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| entry.path())
+        .filter(|path| path.join("meta/info.json").is_file());
+    match (found.next(), found.next()) {
+        (Some(dataset), None) => Ok(dataset),
+        (Some(_), Some(_)) => Err(Error::Invalid(format!(
+            "{} holds more than one dataset",
+            dir.display()
+        ))),
+        (None, _) => Err(Error::Invalid(format!(
+            "no meta/info.json in {}",
+            dir.display()
+        ))),
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path, progress: &mut Progress) -> Result<()> {
+    let files = fsutil::walk_files(from)?;
+    let sizes: Vec<u64> = files
+        .iter()
+        .map(|file| std::fs::metadata(file).map(|metadata| metadata.len()))
+        .collect::<std::io::Result<_>>()?;
+    progress.total = sizes.iter().sum();
+    for (file, size) in files.iter().zip(sizes) {
+        let target = to.join(file.strip_prefix(from).unwrap_or(file));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(file, &target)?;
+        progress.advance(size);
+    }
+    Ok(())
+}
+
+fn extract(archive: &Path, to: &Path, progress: &mut Progress) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    progress.total = (0..archive.len())
+        .filter_map(|index| archive.by_index_raw(index).ok().map(|entry| entry.size()))
+        .sum();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(relative) = entry.enclosed_name() else {
+            return Err(Error::Invalid(format!(
+                "archive entry {} escapes the archive",
+                String::from_utf8_lossy(entry.name_raw())
+            )));
+        };
+        let target = to.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(&target)?)?;
+        progress.advance(entry.size());
+    }
+    Ok(())
+}
+
+/// `name` reduced to a valid single-part dataset name.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "imported".into()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// `name`, or the first of `name-2`, `name-3`, ... not yet under `root`.
+fn free_name(root: &Path, name: &str) -> String {
+    std::iter::once(name.to_owned())
+        .chain((2..).map(|n| format!("{name}-{n}")))
+        .find(|candidate| !root.join(candidate).exists())
+        .expect("unbounded candidates")
+}
+
+/// Bytes copied so far, emitted in steps of [`PROGRESS_STEP`].
+struct Progress<'a> {
+    app: &'a AppHandle,
+    id: u32,
+    total: u64,
+    done: u64,
+    emitted: f64,
+}
+
+impl<'a> Progress<'a> {
+    fn new(app: &'a AppHandle, id: u32) -> Self {
+        Self {
+            app,
+            id,
+            total: 0,
+            done: 0,
+            emitted: 0.0,
+        }
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.done += bytes;
+        let fraction = self.done as f64 / self.total.max(1) as f64;
+        if fraction - self.emitted >= PROGRESS_STEP {
+            self.emit(fraction.min(1.0));
+        }
+    }
+
+    fn finish(&mut self) {
+        self.emit(1.0);
+    }
+
+    fn emit(&mut self, fraction: f64) {
+        self.emitted = fraction;
+        let id = self.id;
+        let _ = self
+            .app
+            .emit("dataset-import-progress", ImportProgress { id, fraction });
+    }
+}
@@ -0,0 +1,242 @@
+//! Recorded dataset files served to the webview as `dataset://`.
+//!
+//! Episode videos and tables are read straight from the recording root
+//! instead of through the backend's HTTP API, which buffers every byte in
+//! Python. The webview loads `dataset://localhost/<dataset>/<file>`
+//! (`http://dataset.localhost/...` on Windows; [`episode_media_url`] builds
+//! either), e.g. `<dataset>/videos/chunk-000/<key>/episode_000000.mp4`.
+//!
+//! Single `Range` requests are answered with `206` so a video element can
+//! seek; each response carries at most [`MAX_CHUNK`] bytes, so an
+//! open-ended range or a plain `GET` of a large file comes back partial and
+//! the element asks for the rest. Only media, table and metadata files
+//! below the recording root are served.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use tauri::http::{header, Method, Request, Response, StatusCode};
+use tauri::{AppHandle, UriSchemeContext, UriSchemeResponder, Wry};
+
+use crate::error::{Error, Result};
+use crate::recording;
+
+pub const SCHEME: &str = "dataset";
+/// Largest body of one response.
+pub const MAX_CHUNK: u64 = 8 * 1024 * 1024;
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mkv", "video/x-matroska"),
+    ("parquet", "application/vnd.apache.parquet"),
+    ("json", "application/json"),
+    ("jsonl", "application/jsonl"),
+];
+
+/// URL of episode `episode`'s video of `key`, or of its table without one.
+#[tauri::command]
+pub fn episode_media_url(
+    app: AppHandle,
+    dataset: String,
+    episode: usize,
+    key: Option<String>,
+) -> Result<String> {
+    recording::dataset_dir(&app, &dataset)?;
+    let file = match &key {
+        Some(key) => recording::episode_video_path(Path::new(""), episode, key),
+        None => recording::episode_data_path(Path::new(""), episode),
+    };
+    let base = if cfg!(windows) {
+        format!("http://{SCHEME}.localhost")
+    } else {
+        format!("{SCHEME}://localhost")
+    };
+    Ok(format!(
+        "{base}/{dataset}/{}",
+        file.to_string_lossy().replace('\\', "/")
+    ))
+}
+
+/// Serves a `dataset://` request of the webview.
+pub fn handle(
+    ctx: UriSchemeContext<'_, Wry>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let root = recording::datasets_root(ctx.app_handle());
+    tauri::async_runtime::spawn_blocking(move || {
+        let response = match root {
+            Ok(root) => serve(&root, &request),
+            Err(err) => {
+                tracing::debug!("dataset request without a recording root: {err}");
+                status(StatusCode::SERVICE_UNAVAILABLE)
+            }
+        };
+        responder.respond(response);
+    });
+}
+
+fn serve(root: &Path, request: &Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return status(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let Some((path, content_type)) = resolve(root, request.uri().path()) else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return status(StatusCode::NOT_FOUND),
+    };
+    let size = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return status(StatusCode::NOT_FOUND),
+    };
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let (start, end) = match range.map(|range| parse_range(range, size)) {
+        Some(Some(range)) => range,
+        Some(None) => {
+            let mut response = status(StatusCode::RANGE_NOT_SATISFIABLE);
+            if let Ok(value) = format!("bytes */{size}").parse() {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            return response;
+        }
+        None if size == 0 => (0, 0),
+        None => (0, size - 1),
+    };
+    let end = end.min(start.saturating_add(MAX_CHUNK - 1));
+    let len = if size == 0 { 0 } else { end - start + 1 };
+
+    let body = if request.method() == Method::HEAD {
+        Vec::new()
+    } else {
+        match read_span(&mut file, start, len) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::debug!(path = %path.display(), "dataset read failed: {err}");
+                return status(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    };
+    let partial = range.is_some() || len < size;
+    let mut builder = Response::builder()
+        .status(if partial {
+            StatusCode::PARTIAL_CONTENT
+        } else {
+            StatusCode::OK
+        })
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "content-range, content-length, accept-ranges",
+        );
+    if partial {
+        builder = builder.header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+    }
+    builder
+        .body(Cow::Owned(body))
+        .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// The served file at URL path `path`, if there is one, and its type.
+fn resolve(root: &Path, path: &str) -> Option<(PathBuf, &'static str)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let valid = segments.iter().all(|segment| {
+        !segment.is_empty()
+            && !segment.starts_with('.')
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    });
+    if !valid {
+        return None;
+    }
+    let extension = segments.last()?.rsplit_once('.')?.1;
+    let content_type = CONTENT_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))?
+        .1;
+    // Symlinks must not lead out of the recording root either.
+    let root = root.canonicalize().ok()?;
+    let file = segments
+        .iter()
+        .fold(root.clone(), |path, segment| path.join(segment))
+        .canonicalize()
+        .ok()?;
+    (file.starts_with(&root) && file.is_file()).then_some((file, content_type))
+}
+
+/// First and last byte of a single `bytes=` range within `size`.
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    // Only the first of several ranges is served.
+    let (first, last) = spec.split(',').next()?.trim().split_once('-')?;
+    let (start, end) = match (first.trim(), last.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.checked_sub(suffix.min(size))?, size.checked_sub(1)?)
+        }
+        (first, "") => (first.parse().ok()?, size.checked_sub(1)?),
+        (first, last) => {
+            let last: u64 = last.parse().ok()?;
+            (first.parse().ok()?, last.min(size.checked_sub(1)?))
+        }
+    };
+    (start <= end).then_some((start, end))
+}
+
+fn read_span(file: &mut File, start: u64, len: u64) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut body = Vec::with_capacity(len as usize);
+    file.by_ref().take(len).read_to_end(&mut body)?;
+    if (body.len() as u64) < len {
+        return Err(Error::Invalid("file shrank while being read".into()));
+    }
+    Ok(body)
+}
+
+fn status(code: StatusCode) -> Response<Cow<'static, [u8]>> {
+    let mut response = Response::new(Cow::Borrowed(&[][..]));
+    *response.status_mut() = code;
+    response.headers_mut().insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        header::HeaderValue::from_static("*"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some((0, 9)));
+        assert_eq!(parse_range("bytes=90-", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=50-500", 100), Some((50, 99)));
+        assert_eq!(parse_range("bytes=0-9, 20-29", 100), Some((0, 9)));
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-200", 100), Some((0, 99)));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=10-5", 100), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("items=0-9", 100), None);
+        assert_eq!(parse_range("bytes=a-9", 100), None);
+    }
+}
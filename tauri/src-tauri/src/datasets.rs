@@ -0,0 +1,533 @@
+//! Browsing and housekeeping of the LeRobot datasets under the recording
+//! root, so the frontend never touches the filesystem itself.
+//!
+//! Deleted episodes and datasets go to the desktop trash rather than being
+//! unlinked. LeRobot expects episode indices `0..total_episodes` without
+//! gaps, so deleting an episode renumbers the later ones: their tables are
+//! rewritten with the new `episode_index` and shifted global `index`, their
+//! videos renamed, and the metadata rewritten last. Datasets an episode is
+//! being recorded into cannot be modified.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, Int64Array, RecordBatch};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tauri::{AppHandle, State};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::recording::{self, Recorder, CHUNK_SIZE};
+use crate::users::{self, Capability};
+
+const CAMERA_PREFIX: &str = "observation.images.";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetSummary {
+    /// `name` or `owner/name`.
+    pub name: String,
+    pub path: PathBuf,
+    pub fps: u32,
+    pub robot_type: Option<String>,
+    pub episodes: usize,
+    pub frames: usize,
+    pub cameras: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodeMeta {
+    pub episode_index: usize,
+    pub task: Option<String>,
+    pub frames: usize,
+    pub duration_secs: f64,
+    pub cameras: Vec<String>,
+    /// Table and videos together.
+    pub size_bytes: u64,
+    pub data_path: PathBuf,
+    pub video_paths: Vec<PathBuf>,
+    /// Per-feature statistics; only filled in by [`inspect_episode`].
+    pub stats: Option<Value>,
+}
+
+/// Datasets found under the recording root, by name.
+#[tauri::command]
+pub fn list_datasets(app: AppHandle) -> Result<Vec<DatasetSummary>> {
+    let root = recording::datasets_root(&app)?;
+    let mut datasets = Vec::new();
+    for (name, dir) in subdirs(&root) {
+        if dir.join("meta/info.json").is_file() {
+            datasets.extend(summary(&name, &dir));
+            continue;
+        }
+        // `owner/name` datasets one level further down.
+        for (child, dir) in subdirs(&dir) {
+            if dir.join("meta/info.json").is_file() {
+                datasets.extend(summary(&format!("{name}/{child}"), &dir));
+            }
+        }
+    }
+    datasets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(datasets)
+}
+
+#[tauri::command]
+pub fn list_episodes(app: AppHandle, dataset: String) -> Result<Vec<EpisodeMeta>> {
+    let dir = existing_dataset(&app, &dataset)?;
+    let info = read_info(&dir)?;
+    Ok(read_lines(&dir.join("meta/episodes.jsonl"))?
+        .iter()
+        .filter_map(|line| episode_meta(&dir, &info, line))
+        .collect())
+}
+
+/// Metadata and statistics of one episode.
+#[tauri::command]
+pub fn inspect_episode(
+    app: AppHandle,
+    dataset: String,
+    episode_index: usize,
+) -> Result<EpisodeMeta> {
+    let dir = existing_dataset(&app, &dataset)?;
+    let info = read_info(&dir)?;
+    let mut meta = read_lines(&dir.join("meta/episodes.jsonl"))?
+        .iter()
+        .filter(|line| line_episode(line) == Some(episode_index))
+        .find_map(|line| episode_meta(&dir, &info, line))
+        .ok_or_else(|| Error::NotFound(format!("episode {episode_index} of {dataset}")))?;
+    meta.stats = read_lines(&dir.join("meta/episodes_stats.jsonl"))?
+        .into_iter()
+        .find(|line| line_episode(line) == Some(episode_index))
+        .and_then(|mut line| line.get_mut("stats").map(Value::take));
+    Ok(meta)
+}
+
+/// Renames a dataset within the recording root.
+#[tauri::command]
+pub fn rename_dataset(
+    app: AppHandle,
+    recorder: State<'_, Recorder>,
+    dataset: String,
+    new_name: String,
+) -> Result<DatasetSummary> {
+    let dir = modifiable_dataset(&app, &recorder, &dataset)?;
+    let target = recording::dataset_dir(&app, &new_name)?;
+    if target.exists() {
+        return Err(Error::Invalid(format!("dataset {new_name} already exists")));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&dir, &target)?;
+    tracing::info!(%dataset, %new_name, "dataset renamed");
+    summary(&new_name, &target)
+        .ok_or_else(|| Error::Invalid(format!("dataset {new_name} has no readable metadata")))
+}
+
+/// Moves a whole dataset to the trash.
+#[tauri::command]
+pub fn delete_dataset(
+    app: AppHandle,
+    recorder: State<'_, Recorder>,
+    dataset: String,
+) -> Result<()> {
+    users::require(&app, Capability::DeleteDatasets)?;
+    let dir = modifiable_dataset(&app, &recorder, &dataset)?;
+    trash::delete(&dir)?;
+    tracing::info!(%dataset, "dataset moved to trash");
+    Ok(())
+}
+
+/// Moves one episode to the trash and renumbers the episodes after it.
+#[tauri::command]
+pub async fn delete_episode(
+    app: AppHandle,
+    recorder: State<'_, Recorder>,
+    dataset: String,
+    episode_index: usize,
+) -> Result<DatasetSummary> {
+    users::require(&app, Capability::DeleteDatasets)?;
+    let dir = modifiable_dataset(&app, &recorder, &dataset)?;
+    let name = dataset.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        remove_episode(&dir, episode_index)?;
+        tracing::info!(dataset = %name, episode = episode_index, "episode moved to trash");
+        summary(&name, &dir)
+            .ok_or_else(|| Error::Invalid(format!("dataset {name} has no readable metadata")))
+    })
+    .await
+    .map_err(|err| Error::Invalid(err.to_string()))?
+}
+
+/// Checks that `dir` holds a complete LeRobot dataset: readable metadata,
+/// episodes `0..total_episodes` adding up to `total_frames`, and the table
+/// and videos of every episode.
+pub fn validate_dataset(dir: &Path) -> Result<()> {
+    let invalid = |what: String| Error::Invalid(format!("dataset at {}: {what}", dir.display()));
+    let info = read_info(dir).map_err(|err| invalid(format!("meta/info.json: {err}")))?;
+    if info
+        .get("codebase_version")
+        .and_then(Value::as_str)
+        .is_none()
+    {
+        return Err(invalid("no codebase_version".into()));
+    }
+    if !info
+        .get("fps")
+        .and_then(Value::as_f64)
+        .is_some_and(|fps| fps > 0.0)
+    {
+        return Err(invalid("no valid fps".into()));
+    }
+    if info
+        .get("features")
+        .and_then(Value::as_object)
+        .is_none_or(|features| features.is_empty())
+    {
+        return Err(invalid("no features".into()));
+    }
+    let total = |key: &str| {
+        info.get(key)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid(format!("no {key}")))
+    };
+    let (total_episodes, total_frames) = (total("total_episodes")?, total("total_frames")?);
+
+    let episodes = read_lines(&dir.join("meta/episodes.jsonl"))
+        .map_err(|err| invalid(format!("meta/episodes.jsonl: {err}")))?;
+    let mut indices: Vec<usize> = episodes.iter().filter_map(line_episode).collect();
+    indices.sort_unstable();
+    if indices.len() != episodes.len() || !indices.iter().copied().eq(0..total_episodes as usize) {
+        return Err(invalid(format!(
+            "episodes.jsonl does not list episodes 0..{total_episodes}"
+        )));
+    }
+    let frames: u64 = episodes
+        .iter()
+        .filter_map(|line| line.get("length").and_then(Value::as_u64))
+        .sum();
+    if frames != total_frames {
+        return Err(invalid(format!(
+            "episodes hold {frames} frames, info.json says {total_frames}"
+        )));
+    }
+    let keys = video_keys(&info);
+    for episode in indices {
+        let data = recording::episode_data_path(dir, episode);
+        let missing = std::iter::once(data)
+            .chain(
+                keys.iter()
+                    .map(|key| recording::episode_video_path(dir, episode, key)),
+            )
+            .find(|path| !path.is_file());
+        if let Some(path) = missing {
+            let path = path.strip_prefix(dir).unwrap_or(&path);
+            return Err(invalid(format!("missing {}", path.display())));
+        }
+    }
+    Ok(())
+}
+
+fn existing_dataset(app: &AppHandle, dataset: &str) -> Result<PathBuf> {
+    let dir = recording::dataset_dir(app, dataset)?;
+    if !dir.join("meta/info.json").is_file() {
+        return Err(Error::NotFound(format!("dataset {dataset}")));
+    }
+    Ok(dir)
+}
+
+fn modifiable_dataset(app: &AppHandle, recorder: &Recorder, dataset: &str) -> Result<PathBuf> {
+    if recorder.recording_dataset().as_deref() == Some(dataset) {
+        return Err(Error::DeviceBusy(format!(
+            "an episode is being recorded into {dataset}"
+        )));
+    }
+    existing_dataset(app, dataset)
+}
+
+/// Directories below `dir` whose names are valid dataset parts.
+fn subdirs(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            recording::valid_dataset(&name).then(|| (name, entry.path()))
+        })
+        .collect()
+}
+
+fn summary(name: &str, dir: &Path) -> Option<DatasetSummary> {
+    let info = read_info(dir).ok()?;
+    let total = |key: &str| info.get(key).and_then(Value::as_u64).unwrap_or(0) as usize;
+    Some(DatasetSummary {
+        name: name.to_owned(),
+        path: dir.to_owned(),
+        fps: fps(&info) as u32,
+        robot_type: info
+            .get("robot_type")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        episodes: total("total_episodes"),
+        frames: total("total_frames"),
+        cameras: video_keys(&info)
+            .iter()
+            .map(|key| camera_name(key).to_owned())
+            .collect(),
+    })
+}
+
+fn episode_meta(dir: &Path, info: &Map<String, Value>, line: &Value) -> Option<EpisodeMeta> {
+    let episode = line_episode(line)?;
+    let frames = line.get("length").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let keys = video_keys(info);
+    let data_path = recording::episode_data_path(dir, episode);
+    let video_paths: Vec<PathBuf> = keys
+        .iter()
+        .map(|key| recording::episode_video_path(dir, episode, key))
+        .collect();
+    let size_bytes = std::iter::once(&data_path)
+        .chain(&video_paths)
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    Some(EpisodeMeta {
+        episode_index: episode,
+        task: line
+            .get("tasks")
+            .and_then(|tasks| tasks.get(0))
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        frames,
+        duration_secs: frames as f64 / fps(info),
+        cameras: keys.iter().map(|key| camera_name(key).to_owned()).collect(),
+        size_bytes,
+        data_path,
+        video_paths,
+        stats: None,
+    })
+}
+
+fn remove_episode(dir: &Path, episode: usize) -> Result<()> {
+    let mut info = read_info(dir)?;
+    let mut episodes = read_lines(&dir.join("meta/episodes.jsonl"))?;
+    let mut stats = read_lines(&dir.join("meta/episodes_stats.jsonl"))?;
+    let Some(position) = episodes
+        .iter()
+        .position(|line| line_episode(line) == Some(episode))
+    else {
+        return Err(Error::NotFound(format!("episode {episode}")));
+    };
+    let removed = episodes.remove(position);
+    let length = removed.get("length").and_then(Value::as_u64).unwrap_or(0) as usize;
+    stats.retain(|line| line_episode(line) != Some(episode));
+
+    let keys = video_keys(&info);
+    let mut doomed = vec![recording::episode_data_path(dir, episode)];
+    doomed.extend(
+        keys.iter()
+            .map(|key| recording::episode_video_path(dir, episode, key)),
+    );
+    doomed.retain(|path| path.exists());
+    if !doomed.is_empty() {
+        trash::delete_all(&doomed)?;
+    }
+
+    // Later episodes move down by one, in ascending order so every target
+    // path is free by the time it is written.
+    let mut later: Vec<usize> = episodes
+        .iter()
+        .filter_map(line_episode)
+        .filter(|&index| index > episode)
+        .collect();
+    later.sort_unstable();
+    for index in later {
+        renumber_table(dir, index, length)?;
+        for key in &keys {
+            let from = recording::episode_video_path(dir, index, key);
+            if from.exists() {
+                let to = recording::episode_video_path(dir, index - 1, key);
+                if let Some(parent) = to.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(from, to)?;
+            }
+        }
+    }
+    for line in episodes.iter_mut() {
+        if let Some(index) = line_episode(line).filter(|&index| index > episode) {
+            line["episode_index"] = (index - 1).into();
+        }
+    }
+    for line in stats.iter_mut() {
+        if let Some(index) = line_episode(line).filter(|&index| index > episode) {
+            line["episode_index"] = (index - 1).into();
+            if let Some(stats) = line.get_mut("stats") {
+                shift_stats(stats.get_mut("episode_index"), 1.0);
+                shift_stats(stats.get_mut("index"), length as f64);
+            }
+        }
+    }
+
+    let total = |info: &Map<String, Value>, key: &str| {
+        info.get(key).and_then(Value::as_u64).unwrap_or(0) as usize
+    };
+    let count = episodes.len();
+    let frames = total(&info, "total_frames").saturating_sub(length);
+    let videos = total(&info, "total_videos").saturating_sub(keys.len());
+    info.insert("total_episodes".into(), count.into());
+    info.insert("total_frames".into(), frames.into());
+    info.insert("total_videos".into(), videos.into());
+    info.insert("total_chunks".into(), count.div_ceil(CHUNK_SIZE).into());
+    info.insert("splits".into(), json!({ "train": format!("0:{count}") }));
+    write_lines(&dir.join("meta/episodes.jsonl"), &episodes)?;
+    write_lines(&dir.join("meta/episodes_stats.jsonl"), &stats)?;
+    fsutil::write_atomic(
+        &dir.join("meta/info.json"),
+        &serde_json::to_vec_pretty(&info)?,
+    )?;
+    Ok(())
+}
+
+/// Rewrites episode `episode`'s table as `episode - 1`, its global frame
+/// indices lowered by `shift`.
+fn renumber_table(dir: &Path, episode: usize, shift: usize) -> Result<()> {
+    let from = recording::episode_data_path(dir, episode);
+    let to = recording::episode_data_path(dir, episode - 1);
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&from)?)?.build()?;
+    let tmp = to.with_extension("parquet.tmp");
+    let mut writer: Option<ArrowWriter<File>> = None;
+    for batch in reader {
+        let batch = batch?;
+        let schema = batch.schema();
+        let columns = schema
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, column)| match field.name().as_str() {
+                "episode_index" => {
+                    Arc::new(Int64Array::from_value(episode as i64 - 1, column.len())) as ArrayRef
+                }
+                "index" => match column.as_any().downcast_ref::<Int64Array>() {
+                    Some(index) => Arc::new(Int64Array::from_iter_values(
+                        index.values().iter().map(|&i| i - shift as i64),
+                    )),
+                    None => column.clone(),
+                },
+                _ => column.clone(),
+            })
+            .collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        if writer.is_none() {
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            writer = Some(ArrowWriter::try_new(
+                File::create(&tmp)?,
+                schema,
+                Some(properties),
+            )?);
+        }
+        if let Some(writer) = writer.as_mut() {
+            writer.write(&batch)?;
+        }
+    }
+    let Some(writer) = writer else {
+        return Err(Error::Invalid(format!("{} is empty", from.display())));
+    };
+    writer.close()?;
+    std::fs::rename(&tmp, &to)?;
+    std::fs::remove_file(&from)?;
+    Ok(())
+}
+
+/// Lowers the min, max and mean of a column's statistics by `shift`.
+fn shift_stats(stats: Option<&mut Value>, shift: f64) {
+    let Some(stats) = stats else {
+        return;
+    };
+    for key in ["min", "max", "mean"] {
+        if let Some(Value::Array(values)) = stats.get_mut(key) {
+            for value in values.iter_mut() {
+                if let Some(number) = value.as_f64() {
+                    *value = json!(number - shift);
+                }
+            }
+        }
+    }
+}
+
+fn read_info(dir: &Path) -> Result<Map<String, Value>> {
+    Ok(serde_json::from_slice(&std::fs::read(
+        dir.join("meta/info.json"),
+    )?)?)
+}
+
+fn read_lines(path: &Path) -> Result<Vec<Value>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(lines)
+}
+
+fn write_lines(path: &Path, lines: &[Value]) -> Result<()> {
+    let mut bytes = Vec::new();
+    for line in lines {
+        serde_json::to_writer(&mut bytes, line)?;
+        bytes.push(b'\n');
+    }
+    fsutil::write_atomic(path, &bytes)?;
+    Ok(())
+}
+
+fn line_episode(line: &Value) -> Option<usize> {
+    line.get("episode_index")
+        .and_then(Value::as_u64)
+        .map(|index| index as usize)
+}
+
+fn fps(info: &Map<String, Value>) -> f64 {
+    info.get("fps")
+        .and_then(Value::as_f64)
+        .filter(|fps| *fps > 0.0)
+        .unwrap_or(1.0)
+}
+
+/// Feature keys stored as videos, e.g. `observation.images.front`.
+fn video_keys(info: &Map<String, Value>) -> Vec<String> {
+    let Some(features) = info.get("features").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    features
+        .iter()
+        .filter(|(_, feature)| feature.get("dtype").and_then(Value::as_str) == Some("video"))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+fn camera_name(key: &str) -> &str {
+    key.strip_prefix(CAMERA_PREFIX).unwrap_or(key)
+}
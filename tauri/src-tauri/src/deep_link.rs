@@ -0,0 +1,100 @@
+//! `percus://` links from the web dashboard.
+//!
+//! Links have the form `percus://robot/<robotId>` or
+//! `percus://robot/<robotId>/session/<sessionId>`. Each valid link brings
+//! the main window to the front and is emitted as `deep-link`; the link
+//! that launched the app arrives before the frontend listens, so it is also
+//! kept until collected with [`take_deep_link`]. Links opened while the app
+//! is running reach this process through the single-instance plugin.
+
+use std::sync::Mutex as StdMutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+use crate::instance::focus_main_window;
+
+const SCHEME: &str = "percus";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeepLink {
+    pub url: String,
+    pub robot_id: String,
+    pub session_id: Option<String>,
+}
+
+/// Most recent link not yet collected by the frontend.
+#[derive(Default)]
+pub struct PendingDeepLink(StdMutex<Option<DeepLink>>);
+
+pub fn init(app: &AppHandle) {
+    app.manage(PendingDeepLink::default());
+    // Installers register the scheme; this covers portable and dev builds.
+    #[cfg(any(windows, target_os = "linux"))]
+    if let Err(err) = app.deep_link().register_all() {
+        tracing::warn!("cannot register {SCHEME}:// links: {err}");
+    }
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            open(&handle, &url);
+        }
+    });
+    match app.deep_link().get_current() {
+        Ok(urls) => {
+            for url in urls.unwrap_or_default() {
+                open(app, &url);
+            }
+        }
+        Err(err) => tracing::warn!("cannot read launch link: {err}"),
+    }
+}
+
+#[tauri::command]
+pub fn take_deep_link(pending: State<'_, PendingDeepLink>) -> Option<DeepLink> {
+    pending.0.lock().unwrap().take()
+}
+
+fn open(app: &AppHandle, url: &Url) {
+    // Files opened with the app on macOS; see `dataset_import`.
+    if url.scheme() == "file" {
+        return;
+    }
+    let Some(link) = parse(url) else {
+        tracing::warn!(%url, "ignoring unrecognised deep link");
+        return;
+    };
+    tracing::info!(%url, "opening deep link");
+    focus_main_window(app);
+    *app.state::<PendingDeepLink>().0.lock().unwrap() = Some(link.clone());
+    let _ = app.emit("deep-link", link);
+}
+
+fn parse(url: &Url) -> Option<DeepLink> {
+    if url.scheme() != SCHEME || url.host_str() != Some("robot") {
+        return None;
+    }
+    let valid = |id: &&str| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (robot_id, session_id) = match segments.as_slice() {
+        [robot] => (*robot, None),
+        [robot, "session", session] => (*robot, Some(*session)),
+        _ => return None,
+    };
+    if !valid(&robot_id) || !session_id.as_ref().is_none_or(valid) {
+        return None;
+    }
+    Some(DeepLink {
+        url: url.to_string(),
+        robot_id: robot_id.to_owned(),
+        session_id: session_id.map(str::to_owned),
+    })
+}
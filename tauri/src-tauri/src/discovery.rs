@@ -0,0 +1,312 @@
+//! Discovery of robot controllers, remote backends and cameras on the LAN.
+//!
+//! [`start_discovery`] browses mDNS for the service types in
+//! [`MDNS_SERVICES`] and repeats an SSDP `M-SEARCH` every
+//! [`SSDP_INTERVAL`], keeping answers whose headers identify one of the
+//! known device kinds. Devices are reported as `device-discovered` when they
+//! appear or change and `device-lost` when they deregister (mDNS) or their
+//! announcement expires (SSDP); [`get_discovered_devices`] returns the
+//! current list, e.g. to fill the controller address picker.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::net::UdpSocket;
+
+use crate::error::Result;
+
+/// mDNS service types browsed, and what they advertise.
+const MDNS_SERVICES: [(&str, DeviceKind); 4] = [
+    ("_daihen-fd._tcp.local.", DeviceKind::RobotController),
+    ("_percus-server._tcp.local.", DeviceKind::Backend),
+    ("_rtsp._tcp.local.", DeviceKind::Camera),
+    ("_axis-video._tcp.local.", DeviceKind::Camera),
+];
+const SSDP_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const SSDP_INTERVAL: Duration = Duration::from_secs(30);
+/// Lifetime of an SSDP answer without `CACHE-CONTROL: max-age`.
+const SSDP_DEFAULT_MAX_AGE: Duration = Duration::from_secs(1800);
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceKind {
+    RobotController,
+    Backend,
+    Camera,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscoverySource {
+    Mdns,
+    Ssdp,
+}
+
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredDevice {
+    /// mDNS full name or SSDP device UUID; stable while the device stays up.
+    pub id: String,
+    pub kind: DeviceKind,
+    pub source: DiscoverySource,
+    pub name: String,
+    pub host: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: Option<u16>,
+    /// TXT records (mDNS), or `location` and `server` (SSDP).
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceLost {
+    id: String,
+}
+
+struct Entry {
+    device: DiscoveredDevice,
+    /// Set for SSDP devices, which never say goodbye reliably.
+    expires: Option<Instant>,
+}
+
+struct Running {
+    daemon: ServiceDaemon,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// Devices found so far and the browsers finding them.
+#[derive(Default)]
+pub struct Discovery {
+    devices: StdMutex<HashMap<String, Entry>>,
+    running: StdMutex<Option<Running>>,
+}
+
+impl Discovery {
+    fn upsert(&self, app: &AppHandle, device: DiscoveredDevice, expires: Option<Instant>) {
+        let mut devices = self.devices.lock().unwrap();
+        let changed = devices
+            .get(&device.id)
+            .is_none_or(|entry| entry.device != device);
+        devices.insert(
+            device.id.clone(),
+            Entry {
+                device: device.clone(),
+                expires,
+            },
+        );
+        drop(devices);
+        if changed {
+            let _ = app.emit("device-discovered", device);
+        }
+    }
+
+    fn remove(&self, app: &AppHandle, id: &str) {
+        if self.devices.lock().unwrap().remove(id).is_some() {
+            let _ = app.emit("device-lost", DeviceLost { id: id.to_owned() });
+        }
+    }
+
+    fn expire(&self, app: &AppHandle) {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.expires.is_some_and(|expires| expires <= now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            self.remove(app, &id);
+        }
+    }
+}
+
+/// Starts browsing; a no-op while discovery is already running.
+#[tauri::command]
+pub fn start_discovery(app: AppHandle, discovery: State<'_, Discovery>) -> Result<()> {
+    let mut running = discovery.running.lock().unwrap();
+    if running.is_some() {
+        return Ok(());
+    }
+    let daemon = ServiceDaemon::new()?;
+    let mut tasks = Vec::new();
+    for (service_type, kind) in MDNS_SERVICES {
+        let events = daemon.browse(service_type)?;
+        let app = app.clone();
+        tasks.push(tauri::async_runtime::spawn(async move {
+            while let Ok(event) = events.recv_async().await {
+                handle_mdns(&app, kind, event);
+            }
+        }));
+    }
+    tasks.push(tauri::async_runtime::spawn(search_ssdp(app.clone())));
+    *running = Some(Running { daemon, tasks });
+    Ok(())
+}
+
+/// Stops browsing; devices found so far stay listed.
+#[tauri::command]
+pub fn stop_discovery(discovery: State<'_, Discovery>) -> Result<()> {
+    if let Some(running) = discovery.running.lock().unwrap().take() {
+        for task in running.tasks {
+            task.abort();
+        }
+        running.daemon.shutdown()?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_discovered_devices(discovery: State<'_, Discovery>) -> Vec<DiscoveredDevice> {
+    let mut devices: Vec<DiscoveredDevice> = discovery
+        .devices
+        .lock()
+        .unwrap()
+        .values()
+        .map(|entry| entry.device.clone())
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+fn handle_mdns(app: &AppHandle, kind: DeviceKind, event: ServiceEvent) {
+    let discovery = app.state::<Discovery>();
+    match event {
+        ServiceEvent::ServiceResolved(service) => {
+            let name = service
+                .fullname
+                .strip_suffix(&service.ty_domain)
+                .unwrap_or(&service.fullname)
+                .trim_end_matches('.')
+                .to_owned();
+            let mut addresses: Vec<IpAddr> =
+                service.addresses.iter().map(|ip| ip.to_ip_addr()).collect();
+            addresses.sort();
+            let properties = service
+                .txt_properties
+                .iter()
+                .map(|property| (property.key().to_owned(), property.val_str().to_owned()))
+                .collect();
+            let device = DiscoveredDevice {
+                id: service.fullname.clone(),
+                kind,
+                source: DiscoverySource::Mdns,
+                name,
+                host: service.host.trim_end_matches('.').to_owned(),
+                addresses,
+                port: Some(service.port),
+                properties,
+            };
+            discovery.upsert(app, device, None);
+        }
+        ServiceEvent::ServiceRemoved(_, fullname) => discovery.remove(app, &fullname),
+        _ => {}
+    }
+}
+
+async fn search_ssdp(app: AppHandle) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            tracing::warn!("SSDP discovery unavailable: {err}");
+            return;
+        }
+    };
+    let request = "M-SEARCH * HTTP/1.1\r\n\
+                   HOST: 239.255.255.250:1900\r\n\
+                   MAN: \"ssdp:discover\"\r\n\
+                   MX: 2\r\n\
+                   ST: ssdp:all\r\n\r\n";
+    let mut ticker = tokio::time::interval(SSDP_INTERVAL);
+    let mut buf = vec![0u8; 2048];
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if let Err(err) = socket.send_to(request.as_bytes(), SSDP_ADDR).await {
+                    tracing::debug!("SSDP search failed: {err}");
+                }
+                app.state::<Discovery>().expire(&app);
+            }
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, from)) = received else { continue };
+                let response = String::from_utf8_lossy(&buf[..len]);
+                if let Some((device, max_age)) = parse_ssdp(&response, from) {
+                    app.state::<Discovery>().upsert(&app, device, Some(Instant::now() + max_age));
+                }
+            }
+        }
+    }
+}
+
+/// Parses an M-SEARCH response, keeping only the known device kinds.
+fn parse_ssdp(response: &str, from: SocketAddr) -> Option<(DiscoveredDevice, Duration)> {
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+    let headers: BTreeMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim().to_owned()))
+        .collect();
+    let usn = headers.get("usn")?.clone();
+    let signature = [headers.get("st"), headers.get("server"), headers.get("usn")]
+        .into_iter()
+        .flatten()
+        .map(|value| value.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let kind = if signature.contains("percus") {
+        DeviceKind::Backend
+    } else if signature.contains("daihen") {
+        DeviceKind::RobotController
+    } else if ["camera", "networkvideo", "onvif"]
+        .iter()
+        .any(|needle| signature.contains(needle))
+    {
+        DeviceKind::Camera
+    } else {
+        return None;
+    };
+    let max_age = headers
+        .get("cache-control")
+        .and_then(|value| {
+            value
+                .split(',')
+                .find_map(|part| part.trim().strip_prefix("max-age="))
+        })
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(SSDP_DEFAULT_MAX_AGE);
+    let port = headers
+        .get("location")
+        .and_then(|location| location.split("://").nth(1))
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|authority| authority.rsplit_once(':'))
+        .and_then(|(_, port)| port.parse().ok());
+    let name = headers
+        .get("server")
+        .cloned()
+        .unwrap_or_else(|| from.ip().to_string());
+    let device = DiscoveredDevice {
+        // The same device answers once per advertised type; key on the UUID.
+        id: usn.split("::").next().unwrap_or(&usn).to_owned(),
+        kind,
+        source: DiscoverySource::Ssdp,
+        name,
+        host: from.ip().to_string(),
+        addresses: vec![from.ip()],
+        port,
+        properties: headers
+            .into_iter()
+            .filter(|(key, _)| key == "location" || key == "server")
+            .collect(),
+    };
+    Some((device, max_age))
+}
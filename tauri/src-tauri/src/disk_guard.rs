@@ -0,0 +1,240 @@
+//! Free-space guard for the volumes recordings and telemetry are written to.
+//!
+//! [`check_start`] refuses `start_recording` while the datasets volume has
+//! less than `diskGuard.minFreeGb` free. While an episode records, the
+//! volume is checked every `intervalSecs` and `disk-space` is emitted with
+//! the free space, the level (`ok`, `low` below `warnFreeGb`, `critical`
+//! below `criticalFreeGb`) and, from the rate it is shrinking at, the
+//! seconds left. Each step down is also a notification. Below `stopFreeGb`
+//! the episode is stopped and saved like `stop_recording` while there is
+//! still room to finalise it, and `disk-guard-stopped` is emitted.
+//!
+//! Telemetry skips writing a file while its volume is below
+//! `criticalFreeGb`, so it cannot take the last space a recording needs.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sysinfo::Disks;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::formatting;
+use crate::notifications::{self, Action, Category, Notice};
+use crate::recording::{self, Recorder};
+use crate::settings::{DiskGuardSettings, SettingsStore};
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Ok,
+    Low,
+    Critical,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskStatus {
+    pub path: PathBuf,
+    pub mount_point: PathBuf,
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+    pub level: Level,
+    /// Until `stopFreeGb` is reached at the current write rate; only while
+    /// recording.
+    pub seconds_left: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiskGuardStopped {
+    dataset: String,
+    episode_index: Option<usize>,
+    free_bytes: u64,
+    error: Option<String>,
+}
+
+/// Starts watching recordings.
+pub fn init(app: &AppHandle) {
+    tauri::async_runtime::spawn(watch(app.clone()));
+}
+
+/// Free space on the datasets volume.
+#[tauri::command]
+pub fn get_disk_status(app: AppHandle) -> Result<DiskStatus> {
+    let settings = app.state::<SettingsStore>().get().disk_guard;
+    status(&recording::datasets_root(&app)?, &settings)
+}
+
+/// Fails when the datasets volume is too full to start an episode.
+pub fn check_start(app: &AppHandle) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().disk_guard;
+    if !settings.enabled {
+        return Ok(());
+    }
+    let status = status(&recording::datasets_root(app)?, &settings)?;
+    let needed = (settings.min_free_gb * GB) as u64;
+    if status.free_bytes < needed {
+        return Err(Error::DiskSpace(format!(
+            "{:.1} GB free on {}; recording needs at least {:.1} GB",
+            status.free_bytes as f64 / GB,
+            status.mount_point.display(),
+            settings.min_free_gb
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `path` may be written to without eating into the space
+/// recordings need.
+pub fn has_room(app: &AppHandle, path: &Path) -> bool {
+    let settings = app.state::<SettingsStore>().get().disk_guard;
+    if !settings.enabled {
+        return true;
+    }
+    // A volume that cannot be inspected is not blocked.
+    status(path, &settings).map_or(true, |status| status.level != Level::Critical)
+}
+
+async fn watch(app: AppHandle) {
+    // Level and free space at the previous check of the current episode.
+    let mut previous: Option<(Level, u64, Instant)> = None;
+    let mut rate: Option<f64> = None;
+    loop {
+        let settings = app.state::<SettingsStore>().get().disk_guard;
+        tokio::time::sleep(Duration::from_secs(settings.interval_secs.max(1))).await;
+        let dataset = app.state::<Recorder>().recording_dataset();
+        let Some(dataset) = dataset.filter(|_| settings.enabled) else {
+            previous = None;
+            rate = None;
+            continue;
+        };
+        let mut status =
+            match recording::dataset_dir(&app, &dataset).and_then(|dir| status(&dir, &settings)) {
+                Ok(status) => status,
+                Err(err) => {
+                    tracing::warn!(%dataset, "free space unknown: {err}");
+                    continue;
+                }
+            };
+        let stop_bytes = settings.stop_free_gb * GB;
+        if let Some((_, free, at)) = previous {
+            let used = free.saturating_sub(status.free_bytes) as f64;
+            let sample = used / at.elapsed().as_secs_f64().max(0.001);
+            // Smoothed, since writes arrive in bursts.
+            let smoothed = rate.map_or(sample, |rate| rate * 0.8 + sample * 0.2);
+            rate = Some(smoothed);
+            if smoothed > 0.0 {
+                let left = (status.free_bytes as f64 - stop_bytes).max(0.0) / smoothed;
+                status.seconds_left = Some(left as u64);
+            }
+        }
+        let escalated = previous.is_none_or(|(level, _, _)| status.level > level);
+        if escalated && status.level != Level::Ok {
+            warn(&app, &dataset, &status);
+        }
+        previous = Some((status.level, status.free_bytes, Instant::now()));
+        let _ = app.emit("disk-space", &status);
+
+        if settings.stop_free_gb > 0.0 && (status.free_bytes as f64) < stop_bytes {
+            stop(&app, dataset, status.free_bytes).await;
+            previous = None;
+            rate = None;
+        }
+    }
+}
+
+fn warn(app: &AppHandle, dataset: &str, status: &DiskStatus) {
+    let free = status.free_bytes as f64 / GB;
+    tracing::warn!(
+        dataset,
+        free_gb = free,
+        seconds_left = ?status.seconds_left,
+        "disk space is running out"
+    );
+    let title = match status.level {
+        Level::Critical => "Disk almost full",
+        _ => "Disk space low",
+    };
+    let free = formatting::preferences(app).number(free, 1);
+    let body = match status.seconds_left {
+        Some(seconds) => format!(
+            "{free} GB free on {}; recording stops in about {} min",
+            status.mount_point.display(),
+            seconds / 60
+        ),
+        None => format!("{free} GB free on {}", status.mount_point.display()),
+    };
+    notifications::notify(
+        app,
+        Notice::new(Category::Recording, title, body).action(Action::ShowWindow),
+    );
+}
+
+async fn stop(app: &AppHandle, dataset: String, free_bytes: u64) {
+    tracing::error!(%dataset, free_bytes, "disk nearly full; stopping the recording");
+    let recorder: State<'_, Recorder> = app.state();
+    let result = recording::stop_recording(app.clone(), recorder).await;
+    let (episode_index, error) = match result {
+        Ok(info) => (Some(info.episode_index), None),
+        Err(err) => {
+            tracing::error!("recording stopped by the disk guard failed to save: {err}");
+            (None, Some(err.to_string()))
+        }
+    };
+    notifications::notify(
+        app,
+        Notice::new(
+            Category::Recording,
+            "Recording stopped: disk full",
+            match &error {
+                Some(error) => format!("The episode could not be saved: {error}"),
+                None => format!("The episode of {dataset} was saved"),
+            },
+        )
+        .action(Action::ShowWindow),
+    );
+    let stopped = DiskGuardStopped {
+        dataset,
+        episode_index,
+        free_bytes,
+        error,
+    };
+    let _ = app.emit("disk-guard-stopped", stopped);
+}
+
+/// Free space on the volume holding `path`, which need not exist yet.
+fn status(path: &Path, settings: &DiskGuardSettings) -> Result<DiskStatus> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| Error::NotFound(format!("volume of {}", path.display())))?;
+    let resolved = existing.canonicalize()?;
+    let disks = Disks::new_with_refreshed_list();
+    let disk = disks
+        .list()
+        .iter()
+        .filter(|disk| resolved.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .ok_or_else(|| Error::NotFound(format!("volume of {}", path.display())))?;
+    let free_bytes = disk.available_space();
+    let free = free_bytes as f64 / GB;
+    let level = if free < settings.critical_free_gb {
+        Level::Critical
+    } else if free < settings.warn_free_gb {
+        Level::Low
+    } else {
+        Level::Ok
+    };
+    Ok(DiskStatus {
+        path: path.to_owned(),
+        mount_point: disk.mount_point().to_owned(),
+        free_bytes,
+        total_bytes: disk.total_space(),
+        level,
+        seconds_left: None,
+    })
+}
@@ -0,0 +1,302 @@
+//! Policy checkpoint downloads into the backend's model directory.
+//!
+//! [`download_models`] takes a manifest of `{url, sha256, destination}`
+//! entries, `destination` relative to `backend.modelDir`, and fetches them
+//! one after another in a background task. Each file is streamed to
+//! `.downloads/<sha256>.part` inside the model directory; an interrupted
+//! download, whether by the network, [`cancel_download`] or a restart,
+//! continues from the partial file with an HTTP range request the next time
+//! the same file is requested. Nothing is installed until every file of the
+//! manifest has been downloaded and its SHA-256 verified; then each is
+//! renamed into place, so the backend never sees a partial checkpoint.
+//! Files already installed with the right checksum are skipped.
+//!
+//! Progress is reported as `model-download-progress`, the outcome as
+//! `model-download-finished`.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+
+/// Attempts per file before the download fails; each resumes the last.
+const ATTEMPTS: u32 = 5;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const STAGING_DIR: &str = ".downloads";
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub url: String,
+    /// Hex-encoded.
+    pub sha256: String,
+    /// Relative to the model directory, e.g. `act-weld/model.safetensors`.
+    pub destination: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress {
+    id: u32,
+    /// Index of the file being downloaded within the manifest.
+    file: usize,
+    files: usize,
+    destination: PathBuf,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadFinished {
+    id: u32,
+    installed: Vec<PathBuf>,
+    cancelled: bool,
+    error: Option<String>,
+}
+
+/// Downloads in progress, by id.
+#[derive(Default)]
+pub struct Downloads {
+    next_id: AtomicU32,
+    cancel: StdMutex<HashMap<u32, Arc<AtomicBool>>>,
+}
+
+/// Starts downloading `manifest` and returns the download id.
+#[tauri::command]
+pub fn download_models(
+    app: AppHandle,
+    downloads: State<'_, Downloads>,
+    manifest: Vec<ManifestEntry>,
+) -> Result<u32> {
+    let models = models_dir(&app)?;
+    if manifest.is_empty() {
+        return Err(Error::Invalid("empty model manifest".into()));
+    }
+    for entry in &manifest {
+        if !valid_destination(&entry.destination) {
+            return Err(Error::Invalid(format!(
+                "model destination {}",
+                entry.destination.display()
+            )));
+        }
+        if entry.sha256.len() != 64 || !entry.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::Invalid(format!("sha256 of {}", entry.url)));
+        }
+    }
+
+    let id = downloads.next_id.fetch_add(1, Ordering::Relaxed);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    downloads
+        .cancel
+        .lock()
+        .unwrap()
+        .insert(id, cancelled.clone());
+    tauri::async_runtime::spawn(async move {
+        let result = run(&app, id, &models, &manifest, &cancelled).await;
+        app.state::<Downloads>().cancel.lock().unwrap().remove(&id);
+        let cancelled = cancelled.load(Ordering::Relaxed);
+        let (installed, error) = match result {
+            Ok(installed) => (installed, None),
+            Err(_) if cancelled => (Vec::new(), None),
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+        match &error {
+            Some(error) => tracing::warn!(id, "model download failed: {error}"),
+            None if cancelled => tracing::info!(id, "model download cancelled"),
+            None => tracing::info!(id, files = installed.len(), "models installed"),
+        }
+        let finished = DownloadFinished {
+            id,
+            installed,
+            cancelled,
+            error,
+        };
+        let _ = app.emit("model-download-finished", finished);
+    });
+    Ok(id)
+}
+
+/// Stops a download; what was fetched so far is kept for resuming.
+#[tauri::command]
+pub fn cancel_download(downloads: State<'_, Downloads>, id: u32) -> Result<()> {
+    let cancel = downloads.cancel.lock().unwrap();
+    let flag = cancel
+        .get(&id)
+        .ok_or_else(|| Error::NotFound(format!("download {id}")))?;
+    flag.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Directory the backend loads models from (`backend.modelDir`).
+pub fn models_dir(app: &AppHandle) -> Result<PathBuf> {
+    app.state::<SettingsStore>()
+        .get()
+        .backend
+        .model_dir
+        .ok_or_else(|| Error::Invalid("backend.modelDir is not set".into()))
+}
+
+fn valid_destination(path: &Path) -> bool {
+    path.components()
+        .all(|part| matches!(part, Component::Normal(_)))
+        && path.components().next().is_some()
+        && !path.starts_with(STAGING_DIR)
+}
+
+async fn run(
+    app: &AppHandle,
+    id: u32,
+    models: &Path,
+    manifest: &[ManifestEntry],
+    cancelled: &AtomicBool,
+) -> Result<Vec<PathBuf>> {
+    let staging = models.join(STAGING_DIR);
+    std::fs::create_dir_all(&staging)?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .read_timeout(Duration::from_secs(60))
+        .build()?;
+
+    let mut staged = Vec::new();
+    for (index, entry) in manifest.iter().enumerate() {
+        let sha256 = entry.sha256.to_ascii_lowercase();
+        let target = models.join(&entry.destination);
+        if file_sha256(&target).await.ok().as_deref() == Some(sha256.as_str()) {
+            tracing::debug!(file = %target.display(), "model file already installed");
+            continue;
+        }
+        let part = staging.join(format!("{sha256}.part"));
+        let mut attempt = 0;
+        loop {
+            let progress = |downloaded: u64, total: Option<u64>| {
+                let progress = DownloadProgress {
+                    id,
+                    file: index,
+                    files: manifest.len(),
+                    destination: entry.destination.clone(),
+                    downloaded,
+                    total,
+                };
+                let _ = app.emit("model-download-progress", progress);
+            };
+            match fetch(client.get(&entry.url), &part, cancelled, progress).await {
+                Ok(()) => break,
+                Err(err) if cancelled.load(Ordering::Relaxed) => return Err(err),
+                Err(err @ Error::Http(_)) if attempt + 1 < ATTEMPTS => {
+                    attempt += 1;
+                    tracing::info!(url = %entry.url, attempt, "model download interrupted, resuming: {err}");
+                    tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let actual = file_sha256(&part).await?;
+        if actual != sha256 {
+            let _ = std::fs::remove_file(&part);
+            return Err(Error::Invalid(format!(
+                "{} has sha256 {actual}, expected {sha256}",
+                entry.url
+            )));
+        }
+        staged.push((part, target));
+    }
+
+    let mut installed = Vec::new();
+    for (part, target) in staged {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&part, &target)?;
+        installed.push(target);
+    }
+    Ok(installed)
+}
+
+/// Downloads the GET `request` into `part`, continuing from its current
+/// length.
+pub async fn fetch(
+    request: reqwest::RequestBuilder,
+    part: &Path,
+    cancelled: &AtomicBool,
+    progress: impl Fn(u64, Option<u64>),
+) -> Result<()> {
+    let offset = std::fs::metadata(part).map_or(0, |metadata| metadata.len());
+    let mut request = request;
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let response = request.send().await?;
+    let (mut downloaded, append) = match response.status() {
+        StatusCode::PARTIAL_CONTENT => (offset, true),
+        // The partial file is already complete.
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        status if status.is_success() => (0, false),
+        status => {
+            return Err(Error::NotFound(format!(
+                "{} answered {status}",
+                response.url()
+            )));
+        }
+    };
+    let total = response.content_length().map(|len| len + downloaded);
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await?;
+    let mut body = response.bytes_stream();
+    let mut last_progress = Instant::now();
+    progress(downloaded, total);
+    while let Some(chunk) = body.next().await {
+        if cancelled.load(Ordering::Relaxed) {
+            file.flush().await?;
+            return Err(Error::Stream("download cancelled".into()));
+        }
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            progress(downloaded, total);
+        }
+    }
+    file.flush().await?;
+    progress(downloaded, total);
+    Ok(())
+}
+
+/// Hex SHA-256 of a file's contents.
+pub async fn file_sha256(path: &Path) -> Result<String> {
+    let path = path.to_owned();
+    tauri::async_runtime::spawn_blocking(move || -> Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|err| Error::Stream(err.to_string()))?
+}
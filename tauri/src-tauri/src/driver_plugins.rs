@@ -0,0 +1,450 @@
+//! Hardware drivers loaded from dynamic libraries, so a new sensor does not
+//! need a shell rebuild.
+//!
+//! A plugin is a `.so`, `.dylib` or `.dll` exporting
+//!
+//! ```c
+//! const PluginApi *percus_driver_plugin(const HostApi *host);
+//! ```
+//!
+//! which returns null on failure. [`HostApi`] and [`PluginApi`] are the
+//! `#[repr(C)]` tables below; both carry [`ABI_VERSION`] and a plugin built
+//! for another version is refused. All strings are NUL-terminated UTF-8.
+//!
+//! - `manifest` returns JSON describing the plugin (see [`Manifest`]). The
+//!   string stays owned by the plugin and must live until `shutdown`.
+//! - `invoke` runs one of the manifest's `commands` with JSON arguments and
+//!   returns `{"ok": <value>}` or `{"error": "<message>"}`. The host hands
+//!   the string back through `free_string`. Calls into one plugin are
+//!   serialised but may come from any thread.
+//! - `shutdown` stops the plugin's threads; no host function may be called
+//!   after it returns.
+//!
+//! The host's `emit` publishes one of the manifest's `events` with a JSON
+//! payload as `plugin:<name>/<event>`, returning 0 or -1 when the event was
+//! not declared or the payload is not JSON; `log` writes to the shell log.
+//! Both may be called from any thread between `percus_driver_plugin`
+//! returning and `shutdown`.
+//!
+//! Every library in `plugins` under the app data directory is loaded at
+//! startup; more can be loaded from there with [`load_plugin`], by users
+//! allowed to edit settings, and driven with [`invoke_plugin`]. The
+//! directory is fixed and libraries elsewhere are never loaded, so only
+//! what the cell's administrator installed there runs, whatever the
+//! settings say. A loaded plugin stays until the shell exits; there is no
+//! unloading, since threads it spawned may still be running its code.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+use crate::users::{self, Capability};
+
+/// Version of [`HostApi`] and [`PluginApi`].
+pub const ABI_VERSION: u32 = 1;
+const ENTRY_SYMBOL: &[u8] = b"percus_driver_plugin\0";
+const DIR: &str = "plugins";
+
+/// Functions the shell provides to a plugin.
+#[repr(C)]
+pub struct HostApi {
+    pub abi_version: u32,
+    /// Passed back as the first argument of every host function.
+    pub context: *mut c_void,
+    pub emit: unsafe extern "C" fn(
+        context: *mut c_void,
+        event: *const c_char,
+        payload: *const c_char,
+    ) -> i32,
+    /// `level`: 0 error, 1 warning, 2 info, 3 debug, 4 trace.
+    pub log: unsafe extern "C" fn(context: *mut c_void, level: u32, message: *const c_char),
+}
+
+/// Functions a plugin provides to the shell.
+#[repr(C)]
+pub struct PluginApi {
+    pub abi_version: u32,
+    /// Passed back as the first argument of every plugin function.
+    pub instance: *mut c_void,
+    pub manifest: unsafe extern "C" fn(instance: *mut c_void) -> *const c_char,
+    pub invoke: unsafe extern "C" fn(
+        instance: *mut c_void,
+        command: *const c_char,
+        args: *const c_char,
+    ) -> *mut c_char,
+    pub free_string: unsafe extern "C" fn(instance: *mut c_void, string: *mut c_char),
+    pub shutdown: unsafe extern "C" fn(instance: *mut c_void),
+}
+
+type Entry = unsafe extern "C" fn(host: *const HostApi) -> *const PluginApi;
+
+/// What a plugin declares about itself.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    /// Letters, digits, `-` and `_`; unique among loaded plugins.
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Kinds of device the plugin drives, e.g. `laser-profiler`.
+    #[serde(default)]
+    pub device_types: Vec<String>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// Events the plugin may emit, named like `name`.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginInfo {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub manifest: Manifest,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Reply {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// State behind [`HostApi::context`].
+struct HostContext {
+    app: AppHandle,
+    manifest: OnceLock<Manifest>,
+}
+
+struct Loaded {
+    api: *const PluginApi,
+    // Kept alive and at a stable address for the plugin's callbacks.
+    _host: Box<HostApi>,
+    context: Box<HostContext>,
+    _library: Library,
+}
+
+// SAFETY: the ABI lets the plugin be called from any thread, and `Plugin`
+// serialises the calls.
+unsafe impl Send for Loaded {}
+
+impl Loaded {
+    fn invoke(&self, command: &str, args: &serde_json::Value) -> Result<serde_json::Value> {
+        let name = self
+            .context
+            .manifest
+            .get()
+            .map_or("plugin", |manifest| &manifest.name);
+        let command = CString::new(command).map_err(|_| Error::Invalid("command name".into()))?;
+        let args = CString::new(serde_json::to_string(args)?)
+            .map_err(|_| Error::Invalid("arguments contain NUL".into()))?;
+        // SAFETY: `api` stays valid until `shutdown`, which only `Drop` calls.
+        let reply = unsafe {
+            let api = &*self.api;
+            let reply = (api.invoke)(api.instance, command.as_ptr(), args.as_ptr());
+            if reply.is_null() {
+                return Err(Error::Plugin(format!("{name} returned nothing")));
+            }
+            let text = CStr::from_ptr(reply).to_string_lossy().into_owned();
+            (api.free_string)(api.instance, reply);
+            text
+        };
+        match serde_json::from_str(&reply)? {
+            Reply::Ok(value) => Ok(value),
+            Reply::Error(message) => Err(Error::Plugin(message)),
+        }
+    }
+}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        // SAFETY: as in `invoke`; nothing calls into the plugin afterwards.
+        unsafe {
+            let api = &*self.api;
+            (api.shutdown)(api.instance);
+        }
+    }
+}
+
+struct Plugin {
+    info: PluginInfo,
+    loaded: StdMutex<Loaded>,
+}
+
+#[derive(Default)]
+pub struct Plugins(StdMutex<Vec<Arc<Plugin>>>);
+
+/// Loads the plugins directory, unless `loadAtStartup` is off.
+pub fn init(app: &AppHandle) {
+    app.manage(Plugins::default());
+    let settings = app.state::<SettingsStore>().get().driver_plugins;
+    if !settings.load_at_startup {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let dir = match plugins_dir(&app) {
+            Ok(dir) => dir,
+            Err(err) => return tracing::warn!("plugins directory unavailable: {err}"),
+        };
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)
+            })
+            .collect();
+        paths.sort();
+        for path in paths {
+            match load(&app, &path) {
+                Ok(info) => tracing::info!(
+                    plugin = %info.manifest.name,
+                    version = %info.manifest.version,
+                    "driver plugin loaded"
+                ),
+                Err(err) => {
+                    tracing::warn!(path = %path.display(), "driver plugin not loaded: {err}")
+                }
+            }
+        }
+    });
+}
+
+fn plugins_dir(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(DIR))
+}
+
+#[tauri::command]
+pub fn list_plugins(plugins: State<'_, Plugins>) -> Vec<PluginInfo> {
+    plugins
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|plugin| plugin.info.clone())
+        .collect()
+}
+
+/// Loads `<name>.<ext>` from the plugins directory.
+#[tauri::command]
+pub async fn load_plugin(app: AppHandle, name: String) -> Result<PluginInfo> {
+    users::require(&app, Capability::EditSettings)?;
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(Error::Invalid(format!("plugin name `{name}`")));
+    }
+    let dir = plugins_dir(&app)?.canonicalize()?;
+    let path = dir
+        .join(format!("{name}.{}", std::env::consts::DLL_EXTENSION))
+        .canonicalize()?;
+    // A symlink in the directory must not lead elsewhere either.
+    if !path.starts_with(&dir) {
+        return Err(Error::Invalid(format!(
+            "plugin `{name}` is outside {}",
+            dir.display()
+        )));
+    }
+    tauri::async_runtime::spawn_blocking(move || load(&app, &path))
+        .await
+        .map_err(|err| Error::Plugin(err.to_string()))?
+}
+
+/// Runs `command` of `plugin` with `args`.
+#[tauri::command]
+pub async fn invoke_plugin(
+    plugins: State<'_, Plugins>,
+    plugin: String,
+    command: String,
+    args: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let found = plugins
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|found| found.info.manifest.name == plugin)
+        .cloned()
+        .ok_or_else(|| Error::NotFound(format!("plugin {plugin}")))?;
+    if !found.info.manifest.commands.contains(&command) {
+        return Err(Error::NotFound(format!(
+            "command {command} of plugin {plugin}"
+        )));
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        found
+            .loaded
+            .lock()
+            .unwrap()
+            .invoke(&command, &args.unwrap_or(serde_json::Value::Null))
+    })
+    .await
+    .map_err(|err| Error::Plugin(err.to_string()))?
+}
+
+fn load(app: &AppHandle, path: &Path) -> Result<PluginInfo> {
+    let path = path.canonicalize()?;
+    let plugins = app.state::<Plugins>();
+    let loaded_at = |list: &[Arc<Plugin>]| list.iter().any(|plugin| plugin.info.path == path);
+    if loaded_at(&plugins.0.lock().unwrap()) {
+        return Err(Error::DeviceBusy(format!(
+            "{} is already loaded",
+            path.display()
+        )));
+    }
+
+    // SAFETY: loading runs the library's initialisers; plugins are trusted
+    // code, loaded only from the directory the administrator installs them in.
+    let library = unsafe { Library::new(&path)? };
+    // SAFETY: the symbol's type is fixed by the ABI.
+    let entry: Entry = unsafe { *library.get::<Entry>(ENTRY_SYMBOL)? };
+    let context = Box::new(HostContext {
+        app: app.clone(),
+        manifest: OnceLock::new(),
+    });
+    let host = Box::new(HostApi {
+        abi_version: ABI_VERSION,
+        context: (&*context as *const HostContext).cast_mut().cast(),
+        emit: host_emit,
+        log: host_log,
+    });
+    // SAFETY: `host` and `context` outlive the plugin, see `Loaded`.
+    let api = unsafe { entry(&*host) };
+    if api.is_null() {
+        return Err(Error::Plugin(format!("{} failed to start", path.display())));
+    }
+    // SAFETY: a non-null return points at the plugin's table.
+    let abi_version = unsafe { (*api).abi_version };
+    if abi_version != ABI_VERSION {
+        // The rest of the table cannot be trusted, not even `shutdown`, so
+        // the library stays mapped in case it started threads.
+        std::mem::forget((library, host, context));
+        return Err(Error::Unsupported(format!(
+            "{} targets plugin ABI {abi_version}, the shell {ABI_VERSION}",
+            path.display()
+        )));
+    }
+    // Shuts the plugin down again on any error below.
+    let loaded = Loaded {
+        api,
+        _host: host,
+        context,
+        _library: library,
+    };
+    // SAFETY: as in `Loaded::invoke`.
+    let manifest = unsafe {
+        let api = &*api;
+        let manifest = (api.manifest)(api.instance);
+        if manifest.is_null() {
+            return Err(Error::Plugin(format!("{} has no manifest", path.display())));
+        }
+        CStr::from_ptr(manifest).to_string_lossy().into_owned()
+    };
+    let manifest: Manifest = serde_json::from_str(&manifest)?;
+    for name in std::iter::once(&manifest.name).chain(&manifest.events) {
+        if !valid_name(name) {
+            return Err(Error::Invalid(format!("plugin or event name {name:?}")));
+        }
+    }
+
+    let mut list = plugins.0.lock().unwrap();
+    if loaded_at(&list) {
+        return Err(Error::DeviceBusy(format!(
+            "{} is already loaded",
+            path.display()
+        )));
+    }
+    if list
+        .iter()
+        .any(|plugin| plugin.info.manifest.name == manifest.name)
+    {
+        return Err(Error::DeviceBusy(format!(
+            "a plugin named {} is loaded",
+            manifest.name
+        )));
+    }
+    let _ = loaded.context.manifest.set(manifest.clone());
+    let info = PluginInfo { path, manifest };
+    list.push(Arc::new(Plugin {
+        info: info.clone(),
+        loaded: StdMutex::new(loaded),
+    }));
+    Ok(info)
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// Reads a string from a plugin, `None` when null or not UTF-8.
+///
+/// # Safety
+///
+/// `string` must be null or NUL-terminated.
+unsafe fn plugin_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+unsafe extern "C" fn host_emit(
+    context: *mut c_void,
+    event: *const c_char,
+    payload: *const c_char,
+) -> i32 {
+    let context = &*context.cast::<HostContext>();
+    // Events before the manifest was read cannot be checked.
+    let Some(manifest) = context.manifest.get() else {
+        return -1;
+    };
+    let (Some(event), Some(payload)) = (plugin_str(event), plugin_str(payload)) else {
+        return -1;
+    };
+    if !manifest.events.iter().any(|declared| declared == event) {
+        return -1;
+    }
+    let Ok(payload) = serde_json::from_str::<serde_json::Value>(payload) else {
+        return -1;
+    };
+    match context
+        .app
+        .emit(&format!("plugin:{}/{event}", manifest.name), payload)
+    {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn host_log(context: *mut c_void, level: u32, message: *const c_char) {
+    let context = &*context.cast::<HostContext>();
+    let plugin = context
+        .manifest
+        .get()
+        .map_or("(loading)", |manifest| manifest.name.as_str());
+    let message = plugin_str(message).unwrap_or_default();
+    match level {
+        0 => tracing::error!(plugin, "{message}"),
+        1 => tracing::warn!(plugin, "{message}"),
+        2 => tracing::info!(plugin, "{message}"),
+        3 => tracing::debug!(plugin, "{message}"),
+        _ => tracing::trace!(plugin, "{message}"),
+    }
+}
@@ -0,0 +1,144 @@
+//! ffmpeg video encoder selection, preferring the GPU's.
+//!
+//! [`pick`] tries the hardware encoders of a codec in order (NVENC, Quick
+//! Sync, VideoToolbox, AMF, and Media Foundation for H.264) by encoding a
+//! test frame, and falls back to the software encoder when none works. The
+//! choice is made once per ffmpeg executable and codec. [`quality_args`] and
+//! [`pix_fmt`] translate the shell's settings into each encoder's options,
+//! so callers only name the codec.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Codec {
+    H264,
+    Hevc,
+}
+
+impl Codec {
+    /// `h264` or `hevc`, as used in settings and dataset metadata.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "h264" => Some(Self::H264),
+            "hevc" => Some(Self::Hevc),
+            _ => None,
+        }
+    }
+
+    fn hardware(self) -> &'static [&'static str] {
+        match self {
+            Self::H264 => &[
+                "h264_nvenc",
+                "h264_qsv",
+                "h264_videotoolbox",
+                "h264_amf",
+                "h264_mf",
+            ],
+            Self::Hevc => &["hevc_nvenc", "hevc_qsv", "hevc_videotoolbox", "hevc_amf"],
+        }
+    }
+
+    fn software(self) -> &'static str {
+        match self {
+            Self::H264 => "libx264",
+            Self::Hevc => "libx265",
+        }
+    }
+}
+
+/// Encoders chosen so far, by ffmpeg executable and codec.
+#[derive(Default)]
+pub struct Encoders(Mutex<HashMap<(String, Codec), String>>);
+
+/// The encoder to use for `codec` with `ffmpeg`.
+pub async fn pick(app: &AppHandle, ffmpeg: &str, codec: Codec) -> String {
+    let encoders = app.state::<Encoders>();
+    // Held while probing so concurrent callers wait for one probe.
+    let mut chosen = encoders.0.lock().await;
+    if let Some(encoder) = chosen.get(&(ffmpeg.to_owned(), codec)) {
+        return encoder.clone();
+    }
+    let mut encoder = None;
+    for candidate in codec.hardware() {
+        if probe(ffmpeg, candidate).await {
+            tracing::info!(encoder = candidate, "using hardware {codec:?} encoder");
+            encoder = Some(candidate.to_string());
+            break;
+        }
+    }
+    let encoder = encoder.unwrap_or_else(|| {
+        let software = codec.software();
+        tracing::info!("no hardware {codec:?} encoder available; using {software}");
+        software.to_owned()
+    });
+    chosen.insert((ffmpeg.to_owned(), codec), encoder.clone());
+    encoder
+}
+
+/// Whether `encoder` can encode a test frame on this machine.
+async fn probe(ffmpeg: &str, encoder: &str) -> bool {
+    let probe = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error"])
+        .args([
+            "-f",
+            "lavfi",
+            "-i",
+            "color=black:s=256x256",
+            "-frames:v",
+            "1",
+        ])
+        .args([
+            "-c:v",
+            encoder,
+            "-pix_fmt",
+            pix_fmt(encoder),
+            "-f",
+            "null",
+            "-",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    probe.is_ok_and(|status| status.success())
+}
+
+pub fn is_hardware(encoder: &str) -> bool {
+    [Codec::H264, Codec::Hevc]
+        .iter()
+        .any(|codec| codec.hardware().contains(&encoder))
+}
+
+/// Input pixel format `encoder` accepts; the hardware encoders all take NV12.
+pub fn pix_fmt(encoder: &str) -> &'static str {
+    if is_hardware(encoder) {
+        "nv12"
+    } else {
+        "yuv420p"
+    }
+}
+
+/// Constant-quality options equivalent to the software CRF `crf` (0-51,
+/// lower is better).
+pub fn quality_args(encoder: &str, crf: u8) -> Vec<String> {
+    let crf = crf.min(51);
+    // VideoToolbox and Media Foundation count quality up from 0 to 100.
+    let quality = (100 - u32::from(crf) * 2).max(1).to_string();
+    let crf = crf.to_string();
+    let args: Vec<&str> = match encoder.split_once('_').map_or("", |(_, api)| api) {
+        "nvenc" => vec!["-rc", "vbr", "-cq", &crf, "-b:v", "0"],
+        "qsv" => vec!["-global_quality", &crf],
+        "videotoolbox" => vec!["-q:v", &quality],
+        "amf" => vec!["-rc", "cqp", "-qp_i", &crf, "-qp_p", &crf],
+        "mf" => vec!["-rate_control", "quality", "-quality", &quality],
+        _ => vec!["-crf", &crf],
+    };
+    args.into_iter().map(str::to_owned).collect()
+}
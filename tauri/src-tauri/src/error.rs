@@ -0,0 +1,116 @@
+//! Error type returned by the shell's Tauri commands.
+
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[error(transparent)]
+    Shell(#[from] tauri_plugin_shell::Error),
+    #[error("invalid config: {0}")]
+    ConfigParse(#[from] toml::de::Error),
+    #[error(transparent)]
+    ConfigWrite(#[from] toml::ser::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("keychain: {0}")]
+    Keychain(#[from] keyring::Error),
+    #[error(transparent)]
+    Mcap(#[from] mcap::McapError),
+    #[error(transparent)]
+    Mdns(#[from] mdns_sd::Error),
+    #[error(transparent)]
+    Modbus(#[from] tokio_modbus::Error),
+    #[error("modbus exception: {0}")]
+    ModbusException(#[from] tokio_modbus::ExceptionCode),
+    #[error(transparent)]
+    Mqtt(#[from] rumqttc::ClientError),
+    #[error("opc ua: {0}")]
+    OpcUa(String),
+    #[error("s3: {0}")]
+    S3(String),
+    #[error("auth: {0}")]
+    Auth(String),
+    #[error("offline: {0}")]
+    Offline(String),
+    #[error("kiosk: {0}")]
+    Kiosk(String),
+    #[error("hub: {0}")]
+    Hub(String),
+    #[error("firmware: {0}")]
+    Firmware(String),
+    #[error("plugin: {0}")]
+    Plugin(String),
+    #[error(transparent)]
+    Library(#[from] libloading::Error),
+    #[error("audio: {0}")]
+    Audio(String),
+    #[error("license: {0}")]
+    License(String),
+    #[error("integrity: {0}")]
+    Integrity(String),
+    #[error("disk space: {0}")]
+    DiskSpace(String),
+    #[error("audit: {0}")]
+    Audit(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Serial(#[from] serialport::Error),
+    #[error("zip: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("urdf: {0}")]
+    Urdf(#[from] urdf_rs::UrdfError),
+    #[error("trash: {0}")]
+    Trash(#[from] trash::Error),
+    #[error("global shortcut: {0}")]
+    Shortcut(#[from] tauri_plugin_global_shortcut::Error),
+    #[error(transparent)]
+    Updater(#[from] tauri_plugin_updater::Error),
+    #[error(transparent)]
+    GrpcTransport(#[from] tonic::transport::Error),
+    #[error("gateway: {}", .0.message())]
+    Grpc(#[from] tonic::Status),
+    #[cfg(feature = "ros2")]
+    #[error("ros2: {0}")]
+    Ros2(#[from] rclrs::RclrsError),
+    #[error(transparent)]
+    WebRtc(#[from] webrtc::Error),
+    #[error("usb: {0}")]
+    Usb(#[from] nusb::Error),
+    #[error("dfu: {0}")]
+    Dfu(#[from] dfu_nusb::Error),
+    #[error("device busy: {0}")]
+    DeviceBusy(String),
+    #[error("gamepad: {0}")]
+    Gamepad(String),
+    #[error("stream: {0}")]
+    Stream(String),
+    #[error("robot controller: {0}")]
+    Robot(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("invalid: {0}")]
+    Invalid(String),
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
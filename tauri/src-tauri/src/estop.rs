@@ -0,0 +1,120 @@
+//! System-wide emergency-stop hotkey.
+//!
+//! `estop.shortcut` is registered with the OS, so it works whichever window
+//! has focus and even while the webview or the backend is hung. Pressing it
+//! sends the stop command on a dedicated connection to the FD controller
+//! (see [`FdController::urgent`]), which neither waits behind status polls
+//! nor needs the robot to be connected first, and emits `estop-triggered`
+//! for the UI; [`trigger`] is the same path for the
+//! hardware buttons in [`crate::input::estop_button`]. The shortcut is
+//! re-registered when settings change.
+
+use std::sync::Mutex as StdMutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Listener, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::audit;
+use crate::daihen_fd::{FdCommand, FdController};
+use crate::error::{Error, Result};
+use crate::jog;
+use crate::settings::{EstopSettings, Settings, SettingsStore};
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EstopTriggered {
+    /// `shortcut`, `button`, `ft-sensor` or `admin-api`.
+    source: &'static str,
+    /// The shortcut pressed, or the button's device name.
+    detail: String,
+    /// Why the stop command could not be delivered.
+    error: Option<String>,
+}
+
+/// The registered shortcut, if any.
+#[derive(Default)]
+struct Estop(StdMutex<Option<Shortcut>>);
+
+/// Registers the configured shortcut and follows settings changes.
+pub fn init(app: &AppHandle) {
+    app.manage(Estop::default());
+    apply(app, &app.state::<SettingsStore>().get().estop);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            apply(&handle, &settings.estop);
+        }
+    });
+}
+
+/// Global shortcut handler passed to the plugin builder.
+pub fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state != ShortcutState::Pressed {
+        return;
+    }
+    let registered = app
+        .try_state::<Estop>()
+        .is_some_and(|estop| estop.0.lock().unwrap().as_ref() == Some(shortcut));
+    if !registered {
+        return;
+    }
+    trigger(app, "shortcut", shortcut.to_string());
+}
+
+/// Stops the robot over the shell's controller connection and emits
+/// `estop-triggered`; shared by every emergency-stop input.
+pub fn trigger(app: &AppHandle, source: &'static str, detail: String) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tracing::warn!(source, %detail, "emergency stop requested");
+        jog::halt(&app);
+        let stopped = app
+            .state::<FdController>()
+            .urgent(&app, FdCommand::StopProgram)
+            .await;
+        let error = stopped.err().map(|err| {
+            tracing::error!("emergency stop failed: {err}");
+            err.to_string()
+        });
+        let entry = serde_json::json!({ "source": source, "detail": detail, "error": error });
+        audit::record(&app, "estop", entry);
+        let triggered = EstopTriggered {
+            source,
+            detail,
+            error,
+        };
+        let _ = app.emit("estop-triggered", triggered);
+    });
+}
+
+fn apply(app: &AppHandle, settings: &EstopSettings) {
+    if let Err(err) = register(app, settings) {
+        tracing::error!(shortcut = %settings.shortcut, "emergency stop hotkey unavailable: {err}");
+    }
+}
+
+fn register(app: &AppHandle, settings: &EstopSettings) -> Result<()> {
+    let wanted = if settings.enabled {
+        Some(settings.shortcut.parse::<Shortcut>().map_err(|err| {
+            Error::Invalid(format!("estop.shortcut `{}`: {err}", settings.shortcut))
+        })?)
+    } else {
+        None
+    };
+    let estop = app.state::<Estop>();
+    let mut registered = estop.0.lock().unwrap();
+    if *registered == wanted {
+        return Ok(());
+    }
+    if let Some(previous) = registered.take() {
+        app.global_shortcut().unregister(previous)?;
+    }
+    if let Some(shortcut) = wanted {
+        app.global_shortcut().register(shortcut)?;
+        tracing::info!(%shortcut, "emergency stop hotkey registered");
+        *registered = Some(shortcut);
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! Coalescing of high-rate events into fixed-interval batches.
+//!
+//! A sample per event at a few hundred hertz (joint states, gamepads) costs
+//! the IPC bridge and the webview far more than the data itself. Events
+//! named in `eventBatching.topics` are therefore queued by
+//! [`crate::windows::emit`] instead of being sent, and every
+//! `intervalMs` the queue is sent at once as `<topic>-batch`
+//! ([`EventBatch`]), routed to the windows subscribed to the topic. A
+//! topic's queue holds at most `capacity` samples; when the webview side
+//! cannot keep up, the oldest are dropped and counted in the next batch.
+//!
+//! [`event_batch_stats`] reports per topic what was offered, delivered and
+//! dropped, for tuning the intervals and capacities.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Listener, Manager, State};
+use tokio::time::MissedTickBehavior;
+
+use crate::settings::{EventBatchingSettings, Settings, SettingsStore};
+use crate::windows;
+
+/// An entry of `eventBatching.topics`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BatchTopic {
+    pub interval_ms: u64,
+    /// Samples kept between batches; older ones are dropped beyond it.
+    pub capacity: usize,
+}
+
+impl Default for BatchTopic {
+    fn default() -> Self {
+        Self {
+            interval_ms: 33,
+            capacity: 64,
+        }
+    }
+}
+
+/// Payload of `<topic>-batch`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventBatch {
+    /// Oldest first, each as the topic's own event would have carried it.
+    pub samples: Vec<serde_json::Value>,
+    /// Samples dropped since the previous batch.
+    pub dropped: u64,
+}
+
+/// Returned by [`event_batch_stats`].
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicStats {
+    pub topic: String,
+    pub interval_ms: u64,
+    pub capacity: usize,
+    pub offered: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub batches: u64,
+    /// Samples waiting for the next batch.
+    pub queued: usize,
+    /// Largest batch sent so far.
+    pub max_batch: usize,
+}
+
+struct Topic {
+    config: BatchTopic,
+    queue: VecDeque<serde_json::Value>,
+    /// Dropped since the last batch.
+    dropped: u64,
+    stats: TopicStats,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Queues of the batched topics, by event name.
+#[derive(Default)]
+pub struct EventBatching(StdMutex<HashMap<String, Topic>>);
+
+pub fn init(app: &AppHandle) {
+    app.manage(EventBatching::default());
+    apply(app, &app.state::<SettingsStore>().get().event_batching);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            apply(&handle, &settings.event_batching);
+        }
+    });
+}
+
+/// Queues `payload` if `event` is batched; returns whether it was.
+pub fn offer<S: Serialize>(app: &AppHandle, event: &str, payload: &S) -> bool {
+    let Some(batching) = app.try_state::<EventBatching>() else {
+        return false;
+    };
+    let mut topics = batching.0.lock().unwrap();
+    let Some(topic) = topics.get_mut(event) else {
+        return false;
+    };
+    match serde_json::to_value(payload) {
+        Ok(sample) => topic.queue.push_back(sample),
+        Err(err) => {
+            tracing::debug!("{event} not batched: {err}");
+            return true;
+        }
+    }
+    topic.stats.offered += 1;
+    while topic.queue.len() > topic.config.capacity.max(1) {
+        topic.queue.pop_front();
+        topic.dropped += 1;
+        topic.stats.dropped += 1;
+    }
+    true
+}
+
+#[tauri::command]
+pub fn event_batch_stats(batching: State<'_, EventBatching>) -> Vec<TopicStats> {
+    let topics = batching.0.lock().unwrap();
+    let mut stats: Vec<TopicStats> = topics
+        .values()
+        .map(|topic| TopicStats {
+            queued: topic.queue.len(),
+            ..topic.stats.clone()
+        })
+        .collect();
+    stats.sort_by(|a, b| a.topic.cmp(&b.topic));
+    stats
+}
+
+/// Starts, retunes or stops the topics' flush tasks; counters survive a
+/// change of a topic's configuration.
+fn apply(app: &AppHandle, settings: &EventBatchingSettings) {
+    let batching = app.state::<EventBatching>();
+    let mut topics = batching.0.lock().unwrap();
+    let wanted = if settings.enabled {
+        settings.topics.clone()
+    } else {
+        Default::default()
+    };
+    let mut retuned = HashMap::new();
+    topics.retain(|name, topic| {
+        let keep = wanted.get(name) == Some(&topic.config);
+        if !keep {
+            if let Some(task) = topic.task.take() {
+                task.abort();
+            }
+            retuned.insert(name.clone(), topic.stats.clone());
+        }
+        keep
+    });
+    for (name, config) in wanted {
+        if topics.contains_key(&name) {
+            continue;
+        }
+        let task =
+            tauri::async_runtime::spawn(flush(app.clone(), name.clone(), config.interval_ms));
+        let stats = retuned.remove(&name).unwrap_or_else(|| TopicStats {
+            topic: name.clone(),
+            ..Default::default()
+        });
+        topics.insert(
+            name,
+            Topic {
+                stats: TopicStats {
+                    interval_ms: config.interval_ms,
+                    capacity: config.capacity,
+                    ..stats
+                },
+                config,
+                queue: VecDeque::new(),
+                dropped: 0,
+                task: Some(task),
+            },
+        );
+    }
+}
+
+async fn flush(app: AppHandle, topic: String, interval_ms: u64) {
+    let batch_event = format!("{topic}-batch");
+    let mut interval = tokio::time::interval(Duration::from_millis(interval_ms.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let batch = {
+            let batching = app.state::<EventBatching>();
+            let mut topics = batching.0.lock().unwrap();
+            let Some(state) = topics.get_mut(&topic) else {
+                return;
+            };
+            if state.queue.is_empty() && state.dropped == 0 {
+                continue;
+            }
+            let batch = EventBatch {
+                samples: state.queue.drain(..).collect(),
+                dropped: std::mem::take(&mut state.dropped),
+            };
+            state.stats.batches += 1;
+            state.stats.delivered += batch.samples.len() as u64;
+            state.stats.max_batch = state.stats.max_batch.max(batch.samples.len());
+            batch
+        };
+        windows::deliver(&app, &topic, &batch_event, batch);
+    }
+}
@@ -0,0 +1,679 @@
+//! Firmware updates for grippers and pendants, over the STM32 serial
+//! bootloader (AN3155) or USB DFU.
+//!
+//! [`list_firmware_devices`] reports DFU-mode USB devices and the USB serial
+//! ports a bootloader may be listening on. Flashing takes two steps so that
+//! nothing is written without the operator confirming exactly what:
+//!
+//! 1. [`prepare_firmware_flash`] reads and verifies the image (its SHA-256,
+//!    when given, and the CRC and vendor/product ids of a DFU suffix),
+//!    checks that the device is there (for serial, that a bootloader
+//!    answers the sync byte) and returns a [`FlashPlan`] with a one-time
+//!    confirmation token valid for [`TOKEN_TTL`].
+//! 2. [`flash_firmware`] with that token writes the verified image. Over
+//!    serial the flash is mass-erased, written, read back and compared, and
+//!    the new firmware started; over DFU the device resets itself.
+//!
+//! Progress is emitted as `firmware-flash-progress`, the outcome as
+//! `firmware-flash-finished`. Only one flash runs at a time.
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use dfu_nusb::DfuNusb;
+use serde::{Deserialize, Serialize};
+use serialport::{ClearBuffer, SerialPort, SerialPortType};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::serial::{ParityConfig, SerialConfig, SerialPorts};
+use crate::users::{self, Capability};
+
+/// How long a confirmation token stays valid.
+const TOKEN_TTL: Duration = Duration::from_secs(120);
+/// Where images are written over serial unless told otherwise (STM32 flash).
+const DEFAULT_FLASH_ADDRESS: u32 = 0x0800_0000;
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(1);
+const ERASE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest block the serial bootloader writes or reads at once.
+const SERIAL_BLOCK: usize = 256;
+/// USB interface class and subclass of DFU.
+const DFU_CLASS: (u8, u8) = (0xFE, 0x01);
+const DFU_SUFFIX_LEN: usize = 16;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FirmwareTarget {
+    /// A USB device exposing a DFU interface.
+    #[serde(rename_all = "camelCase")]
+    Dfu {
+        bus_id: String,
+        address: u8,
+        interface: u8,
+    },
+    /// A serial port with an STM32 bootloader behind it.
+    #[serde(rename_all = "camelCase")]
+    Serial { path: String },
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirmwareDevice {
+    #[serde(flatten)]
+    target: FirmwareTarget,
+    name: Option<String>,
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    serial_number: Option<String>,
+}
+
+/// What [`flash_firmware`] will do once confirmed.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashPlan {
+    token: String,
+    target: FirmwareTarget,
+    path: PathBuf,
+    /// Bytes written, without any DFU suffix.
+    size: usize,
+    sha256: String,
+    address: Option<u32>,
+    /// What answered on a serial port, e.g. `bootloader 3.1, chip 0x0413`.
+    bootloader: Option<String>,
+    expires_in_secs: u64,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Stage {
+    Erasing,
+    Writing,
+    Verifying,
+    Starting,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FlashProgress {
+    stage: Stage,
+    /// 0 to 1, when known.
+    fraction: Option<f64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FlashFinished {
+    target: FirmwareTarget,
+    error: Option<String>,
+}
+
+struct Pending {
+    plan: FlashPlan,
+    data: Vec<u8>,
+    created: Instant,
+}
+
+#[derive(Default)]
+pub struct Firmware {
+    /// The last prepared flash; preparing another replaces it.
+    pending: StdMutex<Option<Pending>>,
+    flashing: AtomicBool,
+}
+
+#[tauri::command]
+pub async fn list_firmware_devices() -> Result<Vec<FirmwareDevice>> {
+    let mut devices = Vec::new();
+    for info in nusb::list_devices().await? {
+        let Some(interface) = info
+            .interfaces()
+            .find(|interface| (interface.class(), interface.subclass()) == DFU_CLASS)
+        else {
+            continue;
+        };
+        devices.push(FirmwareDevice {
+            target: FirmwareTarget::Dfu {
+                bus_id: info.bus_id().to_owned(),
+                address: info.device_address(),
+                interface: interface.interface_number(),
+            },
+            name: info.product_string().map(str::to_owned),
+            vendor_id: Some(info.vendor_id()),
+            product_id: Some(info.product_id()),
+            serial_number: info.serial_number().map(str::to_owned),
+        });
+    }
+    for port in serialport::available_ports()? {
+        if let SerialPortType::UsbPort(usb) = port.port_type {
+            devices.push(FirmwareDevice {
+                target: FirmwareTarget::Serial {
+                    path: port.port_name,
+                },
+                name: usb.product,
+                vendor_id: Some(usb.vid),
+                product_id: Some(usb.pid),
+                serial_number: usb.serial_number,
+            });
+        }
+    }
+    Ok(devices)
+}
+
+/// Verifies `path` for `target` and returns the plan to confirm.
+/// `address` overrides where the image is written (serial, DfuSe).
+#[tauri::command]
+pub async fn prepare_firmware_flash(
+    app: AppHandle,
+    firmware: State<'_, Firmware>,
+    target: FirmwareTarget,
+    path: PathBuf,
+    sha256: Option<String>,
+    address: Option<u32>,
+) -> Result<FlashPlan> {
+    let bytes = tokio::fs::read(&path).await?;
+    let digest = hex::encode(Sha256::digest(&bytes));
+    if let Some(expected) = &sha256 {
+        if !expected.eq_ignore_ascii_case(&digest) {
+            return Err(Error::Firmware(format!(
+                "{} has SHA-256 {digest}, expected {expected}",
+                path.display()
+            )));
+        }
+    }
+    let image = parse_image(bytes)?;
+    if image.data.is_empty() {
+        return Err(Error::Firmware(format!("{} is empty", path.display())));
+    }
+
+    let (address, bootloader) = match &target {
+        FirmwareTarget::Dfu {
+            bus_id,
+            address: device_address,
+            ..
+        } => {
+            let info = find_dfu(bus_id, *device_address).await?;
+            if let Some((vendor, product)) = image.ids {
+                let matches = |expected: u16, actual: u16| expected == 0xFFFF || expected == actual;
+                if !matches(vendor, info.vendor_id()) || !matches(product, info.product_id()) {
+                    return Err(Error::Firmware(format!(
+                        "image is for {vendor:04x}:{product:04x}, device is {:04x}:{:04x}",
+                        info.vendor_id(),
+                        info.product_id()
+                    )));
+                }
+            }
+            (address.or(image.address), None)
+        }
+        FirmwareTarget::Serial { path } => {
+            if app.state::<SerialPorts>().is_open(path) {
+                return Err(Error::DeviceBusy(format!("{path} is open; close it first")));
+            }
+            let path = path.clone();
+            let described = blocking(move || {
+                let mut bootloader = Bootloader::connect(&path)?;
+                let (version, _) = bootloader.get()?;
+                let chip = bootloader.get_id()?;
+                Ok(format!(
+                    "bootloader {}.{}, chip {chip:#06x}",
+                    version >> 4,
+                    version & 0x0F
+                ))
+            })
+            .await?;
+            (
+                Some(address.unwrap_or(DEFAULT_FLASH_ADDRESS)),
+                Some(described),
+            )
+        }
+    };
+
+    let mut token = [0u8; 16];
+    getrandom::fill(&mut token).map_err(|err| Error::Firmware(format!("random: {err}")))?;
+    let plan = FlashPlan {
+        token: hex::encode(token),
+        target,
+        path,
+        size: image.data.len(),
+        sha256: digest,
+        address,
+        bootloader,
+        expires_in_secs: TOKEN_TTL.as_secs(),
+    };
+    *firmware.pending.lock().unwrap() = Some(Pending {
+        plan: plan.clone(),
+        data: image.data,
+        created: Instant::now(),
+    });
+    Ok(plan)
+}
+
+/// Flashes the image confirmed with `token` and waits until it is done.
+#[tauri::command]
+pub async fn flash_firmware(
+    app: AppHandle,
+    firmware: State<'_, Firmware>,
+    token: String,
+) -> Result<()> {
+    users::require(&app, Capability::FlashFirmware)?;
+    let pending = {
+        let mut pending = firmware.pending.lock().unwrap();
+        match pending.take() {
+            Some(plan) if plan.plan.token == token && plan.created.elapsed() < TOKEN_TTL => plan,
+            other => {
+                *pending = other;
+                return Err(Error::Invalid(
+                    "firmware confirmation token is invalid or expired".into(),
+                ));
+            }
+        }
+    };
+    if firmware.flashing.swap(true, Ordering::SeqCst) {
+        return Err(Error::DeviceBusy(
+            "a firmware flash is already running".into(),
+        ));
+    }
+    let target = pending.plan.target.clone();
+    tracing::info!(
+        path = %pending.plan.path.display(),
+        sha256 = %pending.plan.sha256,
+        "flashing firmware"
+    );
+    let result = flash(&app, pending).await;
+    firmware.flashing.store(false, Ordering::SeqCst);
+    match &result {
+        Ok(()) => tracing::info!("firmware flashed"),
+        Err(err) => tracing::error!("firmware flash failed: {err}"),
+    }
+    let _ = app.emit(
+        "firmware-flash-finished",
+        FlashFinished {
+            target,
+            error: result.as_ref().err().map(ToString::to_string),
+        },
+    );
+    result
+}
+
+async fn flash(app: &AppHandle, pending: Pending) -> Result<()> {
+    let Pending { plan, data, .. } = pending;
+    match plan.target {
+        FirmwareTarget::Serial { path } => {
+            let address = plan.address.unwrap_or(DEFAULT_FLASH_ADDRESS);
+            let app = app.clone();
+            blocking(move || flash_serial(&app, &path, address, &data)).await
+        }
+        FirmwareTarget::Dfu {
+            bus_id,
+            address,
+            interface,
+        } => {
+            let info = find_dfu(&bus_id, address).await?;
+            let device = info.open().await?;
+            let interface = device.claim_interface(interface).await?;
+            let dfu = DfuNusb::open(device, interface, 0).await?;
+            let app = app.clone();
+            blocking(move || flash_dfu(&app, dfu, plan.address, &data)).await
+        }
+    }
+}
+
+fn flash_dfu(app: &AppHandle, dfu: DfuNusb, address: Option<u32>, data: &[u8]) -> Result<()> {
+    let mut dfu = dfu.into_sync_dfu();
+    if let Some(address) = address {
+        dfu.override_address(address);
+    }
+    let total = data.len();
+    let mut written = 0;
+    let progress_app = app.clone();
+    dfu.with_progress(move |bytes| {
+        written += bytes;
+        progress(
+            &progress_app,
+            Stage::Writing,
+            Some(written as f64 / total as f64),
+        );
+    });
+    if let Some(dfu) = dfu.download_from_slice(data)? {
+        progress(app, Stage::Starting, None);
+        // Some bootloaders only boot the new image after a detach.
+        let _ = dfu.detach();
+        dfu.usb_reset()?;
+    }
+    Ok(())
+}
+
+fn flash_serial(app: &AppHandle, path: &str, address: u32, data: &[u8]) -> Result<()> {
+    let mut bootloader = Bootloader::connect(path)?;
+    let (_, commands) = bootloader.get()?;
+
+    progress(app, Stage::Erasing, None);
+    bootloader.erase_all(commands.contains(&EXTENDED_ERASE))?;
+
+    let blocks = data.len().div_ceil(SERIAL_BLOCK);
+    for (index, block) in data.chunks(SERIAL_BLOCK).enumerate() {
+        let mut block = block.to_vec();
+        // Writes must be whole 32-bit words.
+        block.resize(block.len().next_multiple_of(4), 0xFF);
+        bootloader.write(block_address(address, index), &block)?;
+        progress(
+            app,
+            Stage::Writing,
+            Some((index + 1) as f64 / blocks as f64),
+        );
+    }
+    for (index, block) in data.chunks(SERIAL_BLOCK).enumerate() {
+        let address = block_address(address, index);
+        if bootloader.read(address, block.len())? != block {
+            return Err(Error::Firmware(format!(
+                "verification failed at {address:#010x}"
+            )));
+        }
+        progress(
+            app,
+            Stage::Verifying,
+            Some((index + 1) as f64 / blocks as f64),
+        );
+    }
+
+    progress(app, Stage::Starting, None);
+    bootloader.go(address)
+}
+
+fn block_address(start: u32, index: usize) -> u32 {
+    start + (index * SERIAL_BLOCK) as u32
+}
+
+fn progress(app: &AppHandle, stage: Stage, fraction: Option<f64>) {
+    let _ = app.emit(
+        "firmware-flash-progress",
+        FlashProgress {
+            stage,
+            fraction: fraction.map(|fraction| fraction.min(1.0)),
+        },
+    );
+}
+
+async fn find_dfu(bus_id: &str, address: u8) -> Result<nusb::DeviceInfo> {
+    nusb::list_devices()
+        .await?
+        .find(|info| info.bus_id() == bus_id && info.device_address() == address)
+        .ok_or_else(|| Error::NotFound(format!("USB device {bus_id}:{address}")))
+}
+
+/// An image ready to write.
+struct Image {
+    data: Vec<u8>,
+    /// Vendor and product id from a DFU suffix; `0xFFFF` matches any.
+    ids: Option<(u16, u16)>,
+    /// Load address from a DfuSe file.
+    address: Option<u32>,
+}
+
+/// Strips and checks a DFU suffix and unwraps a single-element DfuSe file;
+/// anything else is a raw binary.
+fn parse_image(mut bytes: Vec<u8>) -> Result<Image> {
+    let mut ids = None;
+    let has_suffix = bytes.len() >= DFU_SUFFIX_LEN && {
+        let suffix = &bytes[bytes.len() - DFU_SUFFIX_LEN..];
+        &suffix[8..11] == b"UFD" && usize::from(suffix[11]) == DFU_SUFFIX_LEN
+    };
+    if has_suffix {
+        let (body, crc) = bytes.split_at(bytes.len() - 4);
+        let stored = u32::from_le_bytes(crc.try_into().expect("4 bytes"));
+        // The suffix holds the CRC without the final inversion.
+        if !crc32fast::hash(body) != stored {
+            return Err(Error::Firmware("DFU suffix CRC does not match".into()));
+        }
+        let suffix = &bytes[bytes.len() - DFU_SUFFIX_LEN..];
+        let le = |at: usize| u16::from_le_bytes([suffix[at], suffix[at + 1]]);
+        ids = Some((le(4), le(2)));
+        bytes.truncate(bytes.len() - DFU_SUFFIX_LEN);
+    }
+
+    if !bytes.starts_with(b"DfuSe") {
+        return Ok(Image {
+            data: bytes,
+            ids,
+            address: None,
+        });
+    }
+    // Prefix (11 bytes), one target prefix (274) and one element header (8).
+    let invalid = |what: &str| Error::Firmware(format!("DfuSe file: {what}"));
+    if bytes.len() < 11 + 274 + 8 || &bytes[11..17] != b"Target" {
+        return Err(invalid("truncated"));
+    }
+    let le32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"));
+    if bytes[10] != 1 || le32(11 + 270) != 1 {
+        return Err(Error::Unsupported(
+            "DfuSe files with more than one target or element".into(),
+        ));
+    }
+    let element = 11 + 274;
+    let address = le32(element);
+    let size = le32(element + 4) as usize;
+    let start = element + 8;
+    let data = bytes
+        .get(start..start + size)
+        .ok_or_else(|| invalid("element larger than the file"))?
+        .to_vec();
+    Ok(Image {
+        data,
+        ids,
+        address: Some(address),
+    })
+}
+
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1F;
+const SYNC: u8 = 0x7F;
+const CMD_GET: u8 = 0x00;
+const CMD_GET_ID: u8 = 0x02;
+const CMD_READ: u8 = 0x11;
+const CMD_GO: u8 = 0x21;
+const CMD_WRITE: u8 = 0x31;
+const CMD_ERASE: u8 = 0x43;
+const EXTENDED_ERASE: u8 = 0x44;
+
+/// The STM32 system-memory bootloader on a serial port (ST AN3155).
+struct Bootloader {
+    port: Box<dyn SerialPort>,
+    path: String,
+}
+
+impl Bootloader {
+    fn connect(path: &str) -> Result<Self> {
+        let config = SerialConfig {
+            parity: ParityConfig::Even,
+            ..SerialConfig::default()
+        };
+        let mut port = config.open(path, SERIAL_TIMEOUT)?;
+        port.clear(ClearBuffer::All)?;
+        port.write_all(&[SYNC])?;
+        let mut bootloader = Self {
+            port,
+            path: path.to_owned(),
+        };
+        // A NACK means the bootloader was already synchronised.
+        match bootloader.read_byte() {
+            Ok(ACK | NACK) => Ok(bootloader),
+            _ => Err(Error::NotFound(format!(
+                "no bootloader answers on {path}; put the device in bootloader mode"
+            ))),
+        }
+    }
+
+    /// Bootloader version and supported commands.
+    fn get(&mut self) -> Result<(u8, Vec<u8>)> {
+        self.command(CMD_GET)?;
+        let count = usize::from(self.read_byte()?) + 1;
+        let reply = self.read_exact(count)?;
+        self.ack()?;
+        Ok((reply[0], reply[1..].to_vec()))
+    }
+
+    fn get_id(&mut self) -> Result<u16> {
+        self.command(CMD_GET_ID)?;
+        let count = usize::from(self.read_byte()?) + 1;
+        let reply = self.read_exact(count)?;
+        self.ack()?;
+        Ok(reply
+            .get(..2)
+            .map_or(0, |id| u16::from_be_bytes([id[0], id[1]])))
+    }
+
+    fn erase_all(&mut self, extended: bool) -> Result<()> {
+        if extended {
+            self.command(EXTENDED_ERASE)?;
+            self.port.write_all(&[0xFF, 0xFF, 0x00])?;
+        } else {
+            self.command(CMD_ERASE)?;
+            self.port.write_all(&[0xFF, 0x00])?;
+        }
+        self.port.set_timeout(ERASE_TIMEOUT)?;
+        let result = self.ack();
+        self.port.set_timeout(SERIAL_TIMEOUT)?;
+        result
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.command(CMD_WRITE)?;
+        self.send_checked(&address.to_be_bytes())?;
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push((data.len() - 1) as u8);
+        frame.extend_from_slice(data);
+        self.send_checked(&frame)
+    }
+
+    fn read(&mut self, address: u32, len: usize) -> Result<Vec<u8>> {
+        self.command(CMD_READ)?;
+        self.send_checked(&address.to_be_bytes())?;
+        let count = (len - 1) as u8;
+        self.port.write_all(&[count, !count])?;
+        self.ack()?;
+        self.read_exact(len)
+    }
+
+    fn go(&mut self, address: u32) -> Result<()> {
+        self.command(CMD_GO)?;
+        self.send_checked(&address.to_be_bytes())
+    }
+
+    fn command(&mut self, code: u8) -> Result<()> {
+        self.port.write_all(&[code, !code])?;
+        self.ack()
+    }
+
+    /// Sends `bytes` followed by their XOR checksum and waits for the ACK.
+    fn send_checked(&mut self, bytes: &[u8]) -> Result<()> {
+        let checksum = bytes.iter().fold(0, |sum, byte| sum ^ byte);
+        self.port.write_all(bytes)?;
+        self.port.write_all(&[checksum])?;
+        self.ack()
+    }
+
+    fn ack(&mut self) -> Result<()> {
+        match self.read_byte()? {
+            ACK => Ok(()),
+            NACK => Err(Error::Firmware(format!(
+                "bootloader on {} refused the command",
+                self.path
+            ))),
+            other => Err(Error::Firmware(format!(
+                "unexpected reply {other:#04x} from {}",
+                self.path
+            ))),
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        Ok(self.read_exact(1)?[0])
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; len];
+        self.port.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// Runs serial I/O off the async runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `body` with a DFU suffix for `vendor`:`product`.
+    fn with_suffix(body: &[u8], vendor: u16, product: u16) -> Vec<u8> {
+        let mut bytes = body.to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xFF]);
+        bytes.extend_from_slice(&product.to_le_bytes());
+        bytes.extend_from_slice(&vendor.to_le_bytes());
+        bytes.extend_from_slice(&[0x1A, 0x01, b'U', b'F', b'D', DFU_SUFFIX_LEN as u8]);
+        let crc = !crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// A DfuSe file of one target with `elements` elements of `data`.
+    fn dfuse(address: u32, data: &[u8], elements: u32) -> Vec<u8> {
+        let mut bytes = b"DfuSe\x01".to_vec();
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.push(1);
+        let mut target = vec![0u8; 274];
+        target[..6].copy_from_slice(b"Target");
+        target[270..].copy_from_slice(&elements.to_le_bytes());
+        bytes.extend_from_slice(&target);
+        bytes.extend_from_slice(&address.to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn passes_raw_binaries_through() {
+        let image = parse_image(vec![1, 2, 3]).unwrap();
+        assert_eq!(image.data, vec![1, 2, 3]);
+        assert_eq!(image.ids, None);
+        assert_eq!(image.address, None);
+    }
+
+    #[test]
+    fn strips_the_dfu_suffix() {
+        let image = parse_image(with_suffix(&[1, 2, 3], 0x0483, 0xDF11)).unwrap();
+        assert_eq!(image.data, vec![1, 2, 3]);
+        assert_eq!(image.ids, Some((0x0483, 0xDF11)));
+    }
+
+    #[test]
+    fn rejects_a_corrupt_suffix() {
+        let mut bytes = with_suffix(&[1, 2, 3], 0x0483, 0xDF11);
+        bytes[0] ^= 0xFF;
+        assert!(matches!(parse_image(bytes), Err(Error::Firmware(_))));
+    }
+
+    #[test]
+    fn unwraps_dfuse_files() {
+        let bytes = with_suffix(&dfuse(0x0800_0000, &[4, 5, 6], 1), 0x0483, 0xDF11);
+        let image = parse_image(bytes).unwrap();
+        assert_eq!(image.data, vec![4, 5, 6]);
+        assert_eq!(image.address, Some(0x0800_0000));
+        assert_eq!(image.ids, Some((0x0483, 0xDF11)));
+    }
+
+    #[test]
+    fn rejects_unsupported_dfuse_files() {
+        let several = dfuse(0x0800_0000, &[4, 5, 6], 2);
+        assert!(matches!(parse_image(several), Err(Error::Unsupported(_))));
+        let mut truncated = dfuse(0x0800_0000, &[4, 5, 6], 1);
+        truncated.truncate(truncated.len() - 1);
+        assert!(matches!(parse_image(truncated), Err(Error::Firmware(_))));
+    }
+}
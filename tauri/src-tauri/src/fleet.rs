@@ -0,0 +1,436 @@
+//! Opt-in agent reporting this cell to the fleet dashboard.
+//!
+//! While `fleet.enabled`, the shell keeps an outbound WebSocket to
+//! `fleet.url`, which must be `wss://` since it carries the bearer token,
+//! authenticated with the signed-in user's access token (see
+//! [`crate::auth`]) and reconnected every [`RECONNECT`] after a failure.
+//! It introduces itself with `{"type": "hello", "installId", "version",
+//! "host"}` and then sends `{"type": "heartbeat", "status"}` every
+//! `heartbeatSecs`, where `status` ([`CellStatus`]) covers the backend,
+//! the robot, recording, operator mode, the signed-in user and the
+//! license.
+//!
+//! The cloud can ask for a few things only, as
+//! `{"type": "command", "id", "command", ...}`:
+//!
+//! - `fetchDiagnostics` builds the diagnostic bundle (see
+//!   [`crate::diagnostics`]) and uploads it with a PUT to the command's
+//!   `uploadUrl`, which must be `https://` on the host and port of
+//!   `fleet.url`;
+//! - `checkUpdates` runs an update check and announces an update found.
+//!
+//! Each has a consent policy in `fleet`: `deny`, `allow`, or `ask`, which
+//! brings up the main window, emits `fleet-command-request` (with the
+//! upload's destination, if any) and waits up to
+//! `consentTimeoutSecs` for [`respond_fleet_command`]. The answer is sent
+//! back as `{"type": "result", "id", "ok", "result" | "error"}`; anything
+//! else is refused. Remote commands are recorded in [`crate::audit`], and
+//! `fleet-status` reports the connection.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::audit;
+use crate::auth;
+use crate::daihen_fd::{self, FdController, RobotStatus};
+use crate::diagnostics;
+use crate::error::{Error, Result};
+use crate::instance::focus_main_window;
+use crate::kiosk;
+use crate::licensing;
+use crate::recording::Recorder;
+use crate::settings::{FleetSettings, Settings, SettingsStore};
+use crate::sidecar::{BackendStatus, SidecarState};
+use crate::updater;
+use crate::users;
+
+const RECONNECT: Duration = Duration::from_secs(15);
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What a remote command may do without asking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsentPolicy {
+    Deny,
+    #[default]
+    Ask,
+    Allow,
+}
+
+/// Sent with every heartbeat.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellStatus {
+    pub version: String,
+    pub backend: BackendStatus,
+    pub robot: Option<RobotStatus>,
+    /// Dataset an episode is being recorded into.
+    pub recording: Option<String>,
+    pub operator_mode: bool,
+    pub user: Option<String>,
+    /// License state, as in [`licensing::LicenseStatus`].
+    pub license: &'static str,
+}
+
+/// Returned by [`get_fleet_status`] and emitted as `fleet-status`.
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FleetStatus {
+    pub enabled: bool,
+    pub connected: bool,
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CommandRequest {
+    id: u32,
+    command: String,
+    /// Where the command sends data, without the query.
+    destination: Option<String>,
+    expires_in_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Incoming {
+    #[serde(rename_all = "camelCase")]
+    Command {
+        id: Value,
+        command: String,
+        #[serde(default)]
+        upload_url: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Default)]
+pub struct Fleet {
+    next_id: AtomicU32,
+    /// Commands waiting for the operator, by request id.
+    pending: StdMutex<HashMap<u32, oneshot::Sender<bool>>>,
+    /// Settings the agent runs with and its task.
+    agent: StdMutex<Option<(FleetSettings, JoinHandle<()>)>>,
+    status: StdMutex<FleetStatus>,
+}
+
+/// Starts the agent when enabled, and follows the settings.
+pub fn init(app: &AppHandle) {
+    app.manage(Fleet::default());
+    apply(app, &app.state::<SettingsStore>().get().fleet);
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            apply(&handle, &settings.fleet);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_fleet_status(fleet: State<'_, Fleet>) -> FleetStatus {
+    fleet.status.lock().unwrap().clone()
+}
+
+/// The operator's answer to remote command request `id`.
+#[tauri::command]
+pub fn respond_fleet_command(fleet: State<'_, Fleet>, id: u32, accept: bool) -> Result<()> {
+    let reply = fleet
+        .pending
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("fleet command request {id}")))?;
+    let _ = reply.send(accept);
+    Ok(())
+}
+
+/// The status reported in heartbeats.
+pub fn cell_status(app: &AppHandle) -> CellStatus {
+    CellStatus {
+        version: app.package_info().version.to_string(),
+        backend: app.state::<SidecarState>().status(),
+        robot: daihen_fd::robot_status(app.state::<FdController>()),
+        recording: app.state::<Recorder>().recording_dataset(),
+        operator_mode: kiosk::is_active(app),
+        user: users::current_name(app),
+        license: licensing::get_license_status(app.clone()).state,
+    }
+}
+
+/// Restarts the agent when its settings changed.
+fn apply(app: &AppHandle, settings: &FleetSettings) {
+    let fleet = app.state::<Fleet>();
+    let mut agent = fleet.agent.lock().unwrap();
+    let wanted = (settings.enabled && settings.url.is_some()).then(|| settings.clone());
+    if agent.as_ref().map(|(current, _)| current) == wanted.as_ref() {
+        return;
+    }
+    if let Some((_, task)) = agent.take() {
+        task.abort();
+    }
+    *fleet.status.lock().unwrap() = FleetStatus {
+        enabled: wanted.is_some(),
+        ..Default::default()
+    };
+    if let Some(settings) = wanted {
+        let task = tauri::async_runtime::spawn(run(app.clone(), settings.clone()));
+        *agent = Some((settings, task));
+    }
+    drop(agent);
+    emit_status(app);
+}
+
+async fn run(app: AppHandle, settings: FleetSettings) {
+    let Some(url) = settings.url.clone() else {
+        return;
+    };
+    if !url.starts_with("wss://") {
+        tracing::error!(%url, "fleet.url must use wss://");
+        let fleet = app.state::<Fleet>();
+        fleet.status.lock().unwrap().last_error =
+            Some("fleet.url must use wss://, it carries the access token".into());
+        return emit_status(&app);
+    }
+    loop {
+        let error = match session(&app, &url, &settings).await {
+            Ok(()) => {
+                tracing::info!(%url, "fleet server closed the connection");
+                None
+            }
+            Err(err) => {
+                tracing::warn!(%url, "fleet agent: {err}");
+                Some(err.to_string())
+            }
+        };
+        {
+            let fleet = app.state::<Fleet>();
+            let mut status = fleet.status.lock().unwrap();
+            status.connected = false;
+            status.last_error = error;
+        }
+        emit_status(&app);
+        tokio::time::sleep(RECONNECT).await;
+    }
+}
+
+async fn session(app: &AppHandle, url: &str, settings: &FleetSettings) -> Result<()> {
+    let token = auth::access_token(app)
+        .await?
+        .ok_or_else(|| Error::Auth("sign in to report to the fleet".into()))?;
+    let mut handshake = url
+        .into_client_request()
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    let bearer = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|err| Error::Auth(err.to_string()))?;
+    handshake.headers_mut().insert("Authorization", bearer);
+    let (socket, _) = tokio_tungstenite::connect_async(handshake)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    tracing::info!(%url, "connected to the fleet server");
+    {
+        let fleet = app.state::<Fleet>();
+        let mut status = fleet.status.lock().unwrap();
+        status.connected = true;
+        status.last_error = None;
+    }
+    emit_status(app);
+
+    let (mut sink, mut stream) = socket.split();
+    let hello = json!({
+        "type": "hello",
+        "installId": licensing::get_license_status(app.clone()).install_id,
+        "version": app.package_info().version.to_string(),
+        "host": sysinfo::System::host_name(),
+    });
+    send(&mut sink, &hello).await?;
+
+    let (replies, mut outgoing) = mpsc::unbounded_channel::<Value>();
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(settings.heartbeat_secs.max(1)));
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                let beat = json!({ "type": "heartbeat", "status": cell_status(app) });
+                send(&mut sink, &beat).await?;
+                app.state::<Fleet>().status.lock().unwrap().last_heartbeat = Some(Utc::now());
+                emit_status(app);
+            }
+            message = stream.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(Error::Stream(err.to_string())),
+                };
+                let Ok(Incoming::Command { id, command, upload_url }) = serde_json::from_str(&text)
+                else {
+                    continue;
+                };
+                // Consent can take a while; keep the heartbeat going meanwhile.
+                let app = app.clone();
+                let settings = settings.clone();
+                let replies = replies.clone();
+                tauri::async_runtime::spawn(async move {
+                    let result = execute(&app, &settings, &command, upload_url).await;
+                    let entry = json!({ "command": command, "error": audit::outcome(&result) });
+                    audit::record(&app, "fleetCommand", entry);
+                    let reply = match result {
+                        Ok(result) => json!({ "type": "result", "id": id, "ok": true, "result": result }),
+                        Err(err) => json!({ "type": "result", "id": id, "ok": false, "error": err.to_string() }),
+                    };
+                    let _ = replies.send(reply);
+                });
+            }
+            Some(reply) = outgoing.recv() => send(&mut sink, &reply).await?,
+        }
+    }
+}
+
+async fn send<S>(sink: &mut S, message: &Value) -> Result<()>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    sink.send(Message::Text(message.to_string().into()))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))
+}
+
+/// Runs remote `command` if its policy and the operator allow it.
+async fn execute(
+    app: &AppHandle,
+    settings: &FleetSettings,
+    command: &str,
+    upload_url: Option<String>,
+) -> Result<Value> {
+    let policy = match command {
+        "fetchDiagnostics" => settings.diagnostics,
+        "checkUpdates" => settings.update_check,
+        _ => return Err(Error::Unsupported(format!("remote command `{command}`"))),
+    };
+    tracing::info!(%command, ?policy, "fleet command received");
+    if policy == ConsentPolicy::Deny {
+        return Err(Error::Forbidden(format!("`{command}` is not allowed here")));
+    }
+    let upload_url = match command {
+        "fetchDiagnostics" => {
+            let upload_url = upload_url
+                .ok_or_else(|| Error::Invalid("fetchDiagnostics needs an uploadUrl".into()))?;
+            Some(upload_target(settings, &upload_url)?)
+        }
+        _ => None,
+    };
+    if policy == ConsentPolicy::Ask {
+        let destination = upload_url.as_ref().map(|url| {
+            let mut shown = url.clone();
+            shown.set_query(None);
+            shown.to_string()
+        });
+        consent(app, settings, command, destination).await?;
+    }
+    match upload_url {
+        Some(upload_url) => {
+            let size = upload_diagnostics(app, upload_url).await?;
+            Ok(json!({ "size": size }))
+        }
+        _ => {
+            let info = updater::check(app).await?;
+            if let Some(info) = &info {
+                updater::announce(app, info);
+            }
+            Ok(json!({ "update": info }))
+        }
+    }
+}
+
+/// `upload_url` if it is `https://` on the host and port of `fleet.url`.
+fn upload_target(settings: &FleetSettings, upload_url: &str) -> Result<reqwest::Url> {
+    let invalid = |reason: &str| Error::Invalid(format!("uploadUrl {reason}"));
+    let upload = reqwest::Url::parse(upload_url).map_err(|err| invalid(&err.to_string()))?;
+    if upload.scheme() != "https" {
+        return Err(invalid("must use https://"));
+    }
+    let fleet = settings
+        .url
+        .as_deref()
+        .and_then(|url| reqwest::Url::parse(url).ok())
+        .ok_or_else(|| invalid("cannot be checked without fleet.url"))?;
+    let same_origin = upload.host_str() == fleet.host_str()
+        && upload.port_or_known_default() == fleet.port_or_known_default();
+    if !same_origin {
+        return Err(invalid("must be on the fleet server's host and port"));
+    }
+    Ok(upload)
+}
+
+/// Waits for the operator to allow `command`.
+async fn consent(
+    app: &AppHandle,
+    settings: &FleetSettings,
+    command: &str,
+    destination: Option<String>,
+) -> Result<()> {
+    let fleet = app.state::<Fleet>();
+    let id = fleet.next_id.fetch_add(1, Ordering::Relaxed);
+    let (reply, answered) = oneshot::channel();
+    fleet.pending.lock().unwrap().insert(id, reply);
+    focus_main_window(app);
+    let request = CommandRequest {
+        id,
+        command: command.to_string(),
+        destination,
+        expires_in_secs: settings.consent_timeout_secs,
+    };
+    let _ = app.emit("fleet-command-request", request);
+
+    let timeout = Duration::from_secs(settings.consent_timeout_secs.max(1));
+    let answer = tokio::time::timeout(timeout, answered).await;
+    fleet.pending.lock().unwrap().remove(&id);
+    match answer {
+        Ok(Ok(true)) => Ok(()),
+        Ok(_) => Err(Error::Forbidden("the operator declined".into())),
+        Err(_) => Err(Error::Forbidden("the operator did not answer".into())),
+    }
+}
+
+async fn upload_diagnostics(app: &AppHandle, url: reqwest::Url) -> Result<usize> {
+    let output = std::env::temp_dir().join(format!(
+        "percus-fleet-diagnostics-{}.zip",
+        Utc::now().timestamp_millis()
+    ));
+    let path = diagnostics::export_diagnostics(app.clone(), output).await?;
+    let bundle = tokio::fs::read(&path).await;
+    let _ = tokio::fs::remove_file(&path).await;
+    let bundle = bundle?;
+    let size = bundle.len();
+    reqwest::Client::builder()
+        .timeout(UPLOAD_TIMEOUT)
+        .build()?
+        .put(url)
+        .header("Content-Type", "application/zip")
+        .body(bundle)
+        .send()
+        .await?
+        .error_for_status()?;
+    tracing::info!(size, "diagnostics uploaded to the fleet server");
+    Ok(size)
+}
+
+fn emit_status(app: &AppHandle) {
+    let status = app.state::<Fleet>().status.lock().unwrap().clone();
+    let _ = app.emit("fleet-status", status);
+}
@@ -0,0 +1,364 @@
+//! Locale-aware number, date and unit formatting.
+//!
+//! `formatting.locale` (a BCP 47 tag, by default from `LC_ALL`,
+//! `LC_NUMERIC` or `LANG`) picks the decimal and grouping separators and the
+//! date and time patterns, which `dateFormat` and `timeFormat` (strftime)
+//! override. `unitSystem` is `metric` or `imperial`, by default imperial
+//! only for US, Liberian and Myanmar locales; [`display_unit`] maps a unit
+//! to its counterpart in the system and [`convert`] converts between units
+//! of one quantity.
+//!
+//! The webview reads the result with [`get_format_preferences`] so that
+//! on-screen telemetry matches exported files: HDF5 exports carry
+//! `locale` and `unit_system` and convert unit-tagged signals, and
+//! diagnostic bundles record the preferences and a local export time.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::settings::{FormattingSettings, SettingsStore};
+
+/// Languages writing `1.234,5`.
+const COMMA_DECIMAL: &[&str] = &[
+    "cs", "da", "de", "el", "es", "fi", "fr", "hu", "id", "it", "nb", "nl", "nn", "no", "pl", "pt",
+    "ro", "ru", "sk", "sv", "tr", "uk", "vi",
+];
+/// Of those, languages grouping thousands with a space rather than a dot.
+const SPACE_GROUPING: &[&str] = &[
+    "cs", "fi", "fr", "hu", "nb", "nn", "no", "pl", "ru", "sk", "sv", "uk",
+];
+/// Regions measuring in inches and pounds.
+const IMPERIAL_REGIONS: &[&str] = &["US", "LR", "MM"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitSystem::Metric => "metric",
+            UnitSystem::Imperial => "imperial",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Quantity {
+    Length,
+    Speed,
+    Force,
+    Torque,
+    Mass,
+    Temperature,
+    Pressure,
+    Flow,
+}
+
+/// A known unit: its SI value is `value * scale + offset`.
+struct Unit {
+    name: &'static str,
+    quantity: Quantity,
+    scale: f64,
+    offset: f64,
+    metric: &'static str,
+    imperial: &'static str,
+}
+
+const fn unit(
+    name: &'static str,
+    quantity: Quantity,
+    scale: f64,
+    metric: &'static str,
+    imperial: &'static str,
+) -> Unit {
+    Unit {
+        name,
+        quantity,
+        scale,
+        offset: 0.0,
+        metric,
+        imperial,
+    }
+}
+
+const UNITS: &[Unit] = &[
+    unit("m", Quantity::Length, 1.0, "m", "ft"),
+    unit("cm", Quantity::Length, 0.01, "cm", "in"),
+    unit("mm", Quantity::Length, 0.001, "mm", "in"),
+    unit("in", Quantity::Length, 0.0254, "mm", "in"),
+    unit("ft", Quantity::Length, 0.3048, "m", "ft"),
+    unit("m/s", Quantity::Speed, 1.0, "m/s", "ft/s"),
+    unit("mm/s", Quantity::Speed, 0.001, "mm/s", "in/s"),
+    unit("m/min", Quantity::Speed, 1.0 / 60.0, "m/min", "in/min"),
+    unit("cm/min", Quantity::Speed, 0.01 / 60.0, "cm/min", "in/min"),
+    unit("ft/s", Quantity::Speed, 0.3048, "m/s", "ft/s"),
+    unit("in/s", Quantity::Speed, 0.0254, "mm/s", "in/s"),
+    unit("in/min", Quantity::Speed, 0.0254 / 60.0, "m/min", "in/min"),
+    unit("N", Quantity::Force, 1.0, "N", "lbf"),
+    unit("kN", Quantity::Force, 1000.0, "kN", "lbf"),
+    unit("lbf", Quantity::Force, 4.448_221_615_260_5, "N", "lbf"),
+    unit("N·m", Quantity::Torque, 1.0, "N·m", "lbf·ft"),
+    unit("Nm", Quantity::Torque, 1.0, "Nm", "lbf·ft"),
+    unit(
+        "lbf·ft",
+        Quantity::Torque,
+        1.355_817_948_331_400_4,
+        "N·m",
+        "lbf·ft",
+    ),
+    unit(
+        "lbf·in",
+        Quantity::Torque,
+        0.112_984_829_027_616_7,
+        "N·m",
+        "lbf·in",
+    ),
+    unit("kg", Quantity::Mass, 1.0, "kg", "lb"),
+    unit("g", Quantity::Mass, 0.001, "g", "oz"),
+    unit("lb", Quantity::Mass, 0.453_592_37, "kg", "lb"),
+    unit("oz", Quantity::Mass, 0.028_349_523_125, "g", "oz"),
+    unit("K", Quantity::Temperature, 1.0, "K", "K"),
+    Unit {
+        offset: 273.15,
+        ..unit("°C", Quantity::Temperature, 1.0, "°C", "°F")
+    },
+    Unit {
+        offset: 459.67 * 5.0 / 9.0,
+        ..unit("°F", Quantity::Temperature, 5.0 / 9.0, "°C", "°F")
+    },
+    unit("Pa", Quantity::Pressure, 1.0, "Pa", "psi"),
+    unit("kPa", Quantity::Pressure, 1e3, "kPa", "psi"),
+    unit("MPa", Quantity::Pressure, 1e6, "MPa", "psi"),
+    unit("bar", Quantity::Pressure, 1e5, "bar", "psi"),
+    unit("psi", Quantity::Pressure, 6_894.757_293_168, "bar", "psi"),
+    unit("L/min", Quantity::Flow, 1.0 / 60_000.0, "L/min", "ft³/h"),
+    unit(
+        "ft³/h",
+        Quantity::Flow,
+        0.028_316_846_592 / 3600.0,
+        "L/min",
+        "ft³/h",
+    ),
+];
+
+/// Units telemetry is shown in, by quantity.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayUnits {
+    pub length: &'static str,
+    pub speed: &'static str,
+    /// Wire feed and travel speeds.
+    pub feed_speed: &'static str,
+    pub force: &'static str,
+    pub torque: &'static str,
+    pub mass: &'static str,
+    pub temperature: &'static str,
+    pub pressure: &'static str,
+    pub flow: &'static str,
+}
+
+impl DisplayUnits {
+    fn of(system: UnitSystem) -> Self {
+        let pick = |base: &str| display_unit(base, system).unwrap_or("");
+        Self {
+            length: pick("mm"),
+            speed: pick("mm/s"),
+            feed_speed: pick("m/min"),
+            force: pick("N"),
+            torque: pick("N·m"),
+            mass: pick("kg"),
+            temperature: pick("°C"),
+            pressure: pick("bar"),
+            flow: pick("L/min"),
+        }
+    }
+}
+
+/// Returned by [`get_format_preferences`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatPreferences {
+    pub locale: String,
+    pub unit_system: UnitSystem,
+    pub decimal_separator: char,
+    pub grouping_separator: char,
+    /// strftime pattern.
+    pub date_format: String,
+    /// strftime pattern.
+    pub time_format: String,
+    pub units: DisplayUnits,
+}
+
+impl FormatPreferences {
+    pub fn from_settings(settings: &FormattingSettings) -> Self {
+        let locale = settings
+            .locale
+            .clone()
+            .filter(|locale| !locale.trim().is_empty())
+            .unwrap_or_else(system_locale);
+        let (language, region) = split_locale(&locale);
+        let unit_system = settings.unit_system.unwrap_or(
+            if region
+                .as_deref()
+                .is_some_and(|region| IMPERIAL_REGIONS.contains(&region))
+            {
+                UnitSystem::Imperial
+            } else {
+                UnitSystem::Metric
+            },
+        );
+        let comma = COMMA_DECIMAL.contains(&language.as_str());
+        let grouping_separator = match comma {
+            true if SPACE_GROUPING.contains(&language.as_str()) => '\u{a0}',
+            true => '.',
+            false => ',',
+        };
+        let (date_format, time_format) = default_patterns(&language, region.as_deref());
+        let pattern = |custom: &Option<String>, default: &str| {
+            custom
+                .clone()
+                .filter(|pattern| valid_pattern(pattern))
+                .unwrap_or_else(|| default.to_owned())
+        };
+        Self {
+            unit_system,
+            decimal_separator: if comma { ',' } else { '.' },
+            grouping_separator,
+            date_format: pattern(&settings.date_format, date_format),
+            time_format: pattern(&settings.time_format, time_format),
+            units: DisplayUnits::of(unit_system),
+            locale,
+        }
+    }
+
+    /// `value` with `decimals` fraction digits and grouped thousands.
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let fixed = format!("{:.*}", decimals, value.abs());
+        let (int, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let mut out = String::new();
+        if value < 0.0 && fixed.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+            out.push('-');
+        }
+        for (i, digit) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i).is_multiple_of(3) {
+                out.push(self.grouping_separator);
+            }
+            out.push(digit);
+        }
+        if !fraction.is_empty() {
+            out.push(self.decimal_separator);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// `value` in `unit`, converted to the unit system and formatted with
+    /// its unit; unknown units are kept.
+    pub fn quantity(&self, value: f64, unit: &str, decimals: usize) -> String {
+        let (value, unit) = self.to_display(value, unit);
+        format!("{} {unit}", self.number(value, decimals))
+    }
+
+    /// `value` in `unit` converted to the unit system, with the unit used.
+    pub fn to_display<'a>(&self, value: f64, unit: &'a str) -> (f64, &'a str) {
+        match display_unit(unit, self.unit_system) {
+            Some(shown) if shown != unit => match convert(value, unit, shown) {
+                Some(converted) => (converted, shown),
+                None => (value, unit),
+            },
+            _ => (value, unit),
+        }
+    }
+
+    /// `time` in local time, as date and time.
+    pub fn date_time(&self, time: DateTime<Utc>) -> String {
+        let pattern = format!("{} {}", self.date_format, self.time_format);
+        time.with_timezone(&Local).format(&pattern).to_string()
+    }
+}
+
+/// The effective preferences.
+pub fn preferences(app: &AppHandle) -> FormatPreferences {
+    FormatPreferences::from_settings(&app.state::<SettingsStore>().get().formatting)
+}
+
+#[tauri::command]
+pub fn get_format_preferences(app: AppHandle) -> FormatPreferences {
+    preferences(&app)
+}
+
+/// `value` in `from` expressed in `to`, if both are known units of the same
+/// quantity.
+pub fn convert(value: f64, from: &str, to: &str) -> Option<f64> {
+    let (from, to) = (lookup(from)?, lookup(to)?);
+    (from.quantity == to.quantity)
+        .then(|| (value * from.scale + from.offset - to.offset) / to.scale)
+}
+
+/// Counterpart of `unit` in `system`, if it is a known unit.
+pub fn display_unit(unit: &str, system: UnitSystem) -> Option<&'static str> {
+    let unit = lookup(unit)?;
+    Some(match system {
+        UnitSystem::Metric => unit.metric,
+        UnitSystem::Imperial => unit.imperial,
+    })
+}
+
+fn lookup(name: &str) -> Option<&'static Unit> {
+    let name = name.trim();
+    UNITS.iter().find(|unit| unit.name == name)
+}
+
+/// Locale of the environment, as a BCP 47 tag.
+fn system_locale() -> String {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .map(|value| {
+            value
+                .split(['.', '@'])
+                .next()
+                .unwrap_or_default()
+                .replace('_', "-")
+        })
+        .find(|tag| !tag.is_empty() && tag != "C" && tag != "POSIX")
+        .unwrap_or_else(|| "en-US".into())
+}
+
+/// Lowercase language and uppercase region of `locale`.
+fn split_locale(locale: &str) -> (String, Option<String>) {
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+    // Script subtags (`zh-Hant-TW`) are four letters; regions two or three.
+    let region = parts
+        .find(|part| part.len() != 4)
+        .map(str::to_ascii_uppercase);
+    (language, region)
+}
+
+fn default_patterns(language: &str, region: Option<&str>) -> (&'static str, &'static str) {
+    match (language, region) {
+        ("en", Some("US")) | ("en", None) => ("%m/%d/%Y", "%I:%M:%S %p"),
+        ("ja" | "zh" | "ko", _) => ("%Y/%m/%d", "%H:%M:%S"),
+        ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "nn" | "no" | "da" | "tr" | "uk", _) => {
+            ("%d.%m.%Y", "%H:%M:%S")
+        }
+        ("fr" | "es" | "it" | "pt" | "el" | "en" | "vi" | "id", _) => ("%d/%m/%Y", "%H:%M:%S"),
+        ("nl", _) => ("%d-%m-%Y", "%H:%M:%S"),
+        _ => ("%Y-%m-%d", "%H:%M:%S"),
+    }
+}
+
+/// Whether `pattern` formats without error; chrono panics on bad ones.
+fn valid_pattern(pattern: &str) -> bool {
+    !StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error))
+}
@@ -0,0 +1,428 @@
+//! Shared-memory frame rings written by the backend, served as `frame://`.
+//!
+//! The backend writes each camera's frames into `<stream>.ring` under the
+//! directory passed to it as `PHI_FRAME_DIR` (tmpfs on Linux). A ring is a
+//! 64-byte header followed by fixed-size slots, each guarded by a sequence
+//! lock, so frames never travel through the HTTP API or base64. The webview
+//! fetches `frame://localhost/<stream>/latest` (`http://frame.localhost/...`
+//! on Windows); `?after=<seq>` answers `204` until a newer frame exists.
+//! The frame is copied out of the mapping once, under the sequence lock,
+//! straight into the response body.
+//!
+//! Layout, little-endian, shared with `interfaces_backend.utils.frame_ring`:
+//!
+//! - header: magic `PRCSRING`, version `u32`, slot count `u32`, slot
+//!   capacity `u32`, reserved `u32`, latest frame number `u64` at offset 24
+//! - slot header (32 bytes): sequence `u64` (`2n + 1` while frame `n` is
+//!   written, `2n` once complete), length `u32`, fourcc `u32`, width `u32`,
+//!   height `u32`, timestamp in ns `u64`; then `capacity` payload bytes
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use image::DynamicImage;
+use memmap2::Mmap;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Runtime, UriSchemeContext};
+
+use crate::error::{Error, Result};
+
+pub const SCHEME: &str = "frame";
+const MAGIC: &[u8; 8] = b"PRCSRING";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 64;
+const SLOT_HEADER_LEN: usize = 32;
+const LATEST_OFFSET: usize = 24;
+/// Attempts before giving up on a slot the writer keeps overwriting.
+const READ_ATTEMPTS: usize = 4;
+
+/// One copied-out frame.
+pub struct Frame {
+    pub seq: u64,
+    pub fourcc: [u8; 4],
+    /// 0 when the writer did not record the size.
+    pub width: u32,
+    pub height: u32,
+    pub timestamp_ns: u64,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Decodes JPEG, PNG, grey and RGB/BGR frames.
+    pub fn decode(&self) -> Result<DynamicImage> {
+        let raw = |channels: u32| {
+            let expected = self.width as usize * self.height as usize * channels as usize;
+            (self.width > 0 && self.data.len() >= expected).then(|| self.data[..expected].to_vec())
+        };
+        let image = match &self.fourcc {
+            b"MJPG" | b"JPEG" | b"PNG " => Some(image::load_from_memory(&self.data)?),
+            b"GREY" => raw(1)
+                .and_then(|data| image::GrayImage::from_raw(self.width, self.height, data))
+                .map(DynamicImage::ImageLuma8),
+            b"RGB3" | b"BGR3" => raw(3)
+                .and_then(|mut data| {
+                    if &self.fourcc == b"BGR3" {
+                        data.chunks_exact_mut(3).for_each(|pixel| pixel.swap(0, 2));
+                    }
+                    image::RgbImage::from_raw(self.width, self.height, data)
+                })
+                .map(DynamicImage::ImageRgb8),
+            _ => {
+                return Err(Error::Unsupported(format!(
+                    "frame format {}",
+                    String::from_utf8_lossy(&self.fourcc)
+                )))
+            }
+        };
+        image.ok_or_else(|| Error::Invalid("frame size does not match its data".into()))
+    }
+}
+
+struct Ring {
+    map: Mmap,
+    identity: u64,
+    slot_count: usize,
+    capacity: usize,
+}
+
+impl Ring {
+    fn open(path: &Path, identity: u64) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the backend only writes into the mapping through the
+        // sequence-lock protocol and never truncates a published ring.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC || read_u32(&map, 8) != VERSION {
+            return Err(Error::Invalid(format!(
+                "not a frame ring: {}",
+                path.display()
+            )));
+        }
+        let slot_count = read_u32(&map, 12) as usize;
+        let capacity = read_u32(&map, 16) as usize;
+        let needed = HEADER_LEN + slot_count * (SLOT_HEADER_LEN + capacity);
+        if slot_count == 0 || !capacity.is_multiple_of(8) || map.len() < needed {
+            return Err(Error::Invalid(format!(
+                "corrupt frame ring: {}",
+                path.display()
+            )));
+        }
+        Ok(Self {
+            map,
+            identity,
+            slot_count,
+            capacity,
+        })
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        let ptr = self.map[offset..offset + 8].as_ptr();
+        debug_assert_eq!(ptr as usize % 8, 0);
+        // SAFETY: in bounds and 8-byte aligned (page-aligned mapping, offsets
+        // multiples of 8); the writer updates these words atomically.
+        unsafe { &*(ptr as *const AtomicU64) }
+    }
+
+    fn latest(&self) -> u64 {
+        self.atomic(LATEST_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// Copies frame `seq` out, or returns `None` once it was overwritten.
+    fn read(&self, seq: u64) -> Option<Frame> {
+        let slot =
+            HEADER_LEN + ((seq - 1) as usize % self.slot_count) * (SLOT_HEADER_LEN + self.capacity);
+        let lock = self.atomic(slot);
+        if lock.load(Ordering::Acquire) != seq * 2 {
+            return None;
+        }
+        let len = (read_u32(&self.map, slot + 8) as usize).min(self.capacity);
+        let payload = slot + SLOT_HEADER_LEN;
+        let frame = Frame {
+            seq,
+            fourcc: self.map[slot + 12..slot + 16].try_into().unwrap(),
+            width: read_u32(&self.map, slot + 16),
+            height: read_u32(&self.map, slot + 20),
+            timestamp_ns: u64::from_le_bytes(self.map[slot + 24..slot + 32].try_into().unwrap()),
+            data: self.map[payload..payload + len].to_vec(),
+        };
+        fence(Ordering::Acquire);
+        (lock.load(Ordering::Relaxed) == seq * 2).then_some(frame)
+    }
+
+    fn read_latest(&self) -> Option<Frame> {
+        for _ in 0..READ_ATTEMPTS {
+            let seq = self.latest();
+            if seq == 0 {
+                return None;
+            }
+            if let Some(frame) = self.read(seq) {
+                return Some(frame);
+            }
+        }
+        None
+    }
+}
+
+fn read_u32(map: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(map[offset..offset + 4].try_into().unwrap())
+}
+
+/// Rings mapped so far, keyed by stream name.
+pub struct FrameRings {
+    dir: PathBuf,
+    rings: Mutex<HashMap<String, Arc<Ring>>>,
+}
+
+impl FrameRings {
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copies out the stream's most recent frame, if it has one yet.
+    pub fn latest_frame(&self, stream: &str) -> Result<Option<Frame>> {
+        Ok(self.ring(stream)?.read_latest())
+    }
+
+    /// Maps the stream's ring, remapping when the backend has replaced it.
+    fn ring(&self, stream: &str) -> Result<Arc<Ring>> {
+        let path = self.dir.join(format!("{stream}.ring"));
+        let identity = file_identity(&std::fs::metadata(&path)?);
+        let mut rings = self.rings.lock().unwrap();
+        if let Some(ring) = rings.get(stream).filter(|ring| ring.identity == identity) {
+            return Ok(ring.clone());
+        }
+        let ring = Arc::new(Ring::open(&path, identity)?);
+        rings.insert(stream.to_owned(), ring.clone());
+        Ok(ring)
+    }
+}
+
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+/// Windows cannot replace a mapped file, so the size is enough to notice a
+/// ring recreated with different dimensions.
+#[cfg(not(unix))]
+fn file_identity(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+pub fn valid_stream(stream: &str) -> bool {
+    !stream.is_empty()
+        && stream
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Ring written by the shell itself, for frames that do not come from the
+/// backend (ROS 2 image topics, episode replay). Same protocol as the
+/// backend's writer.
+pub struct RingWriter {
+    map: memmap2::MmapMut,
+    path: PathBuf,
+    slot_count: usize,
+    capacity: usize,
+    seq: u64,
+}
+
+impl RingWriter {
+    pub fn create(
+        rings: &FrameRings,
+        stream: &str,
+        slot_count: usize,
+        capacity: usize,
+    ) -> Result<Self> {
+        if !valid_stream(stream) || slot_count == 0 {
+            return Err(Error::Invalid(format!("frame stream {stream}")));
+        }
+        let capacity = capacity.div_ceil(8) * 8;
+        let size = HEADER_LEN + slot_count * (SLOT_HEADER_LEN + capacity);
+        let path = rings.dir.join(format!("{stream}.ring"));
+        let tmp = path.with_extension("ring.tmp");
+        {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp)?;
+            file.set_len(size as u64)?;
+            // SAFETY: the file is private to this writer until renamed.
+            let mut map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+            map[..8].copy_from_slice(MAGIC);
+            map[8..12].copy_from_slice(&VERSION.to_le_bytes());
+            map[12..16].copy_from_slice(&(slot_count as u32).to_le_bytes());
+            map[16..20].copy_from_slice(&(capacity as u32).to_le_bytes());
+            map.flush()?;
+        }
+        // Publish the ring only once its header is complete.
+        std::fs::rename(&tmp, &path)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        // SAFETY: this writer is the only one mapping the file writably.
+        let map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        Ok(Self {
+            map,
+            path,
+            slot_count,
+            capacity,
+            seq: 0,
+        })
+    }
+
+    #[cfg(feature = "ros2")]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        let ptr = self.map[offset..offset + 8].as_ptr();
+        // SAFETY: as in `Ring::atomic`.
+        unsafe { &*(ptr as *const AtomicU64) }
+    }
+
+    /// Publishes one frame and returns its frame number.
+    pub fn write(
+        &mut self,
+        data: &[u8],
+        fourcc: [u8; 4],
+        width: u32,
+        height: u32,
+        timestamp_ns: u64,
+    ) -> Result<u64> {
+        if data.len() > self.capacity {
+            return Err(Error::Invalid(format!(
+                "frame of {} bytes exceeds slot capacity {}",
+                data.len(),
+                self.capacity
+            )));
+        }
+        let seq = self.seq + 1;
+        let slot =
+            HEADER_LEN + ((seq - 1) as usize % self.slot_count) * (SLOT_HEADER_LEN + self.capacity);
+        self.atomic(slot).store(seq * 2 + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let header = &mut self.map[slot + 8..slot + SLOT_HEADER_LEN];
+        header[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        header[4..8].copy_from_slice(&fourcc);
+        header[8..12].copy_from_slice(&width.to_le_bytes());
+        header[12..16].copy_from_slice(&height.to_le_bytes());
+        header[16..24].copy_from_slice(&timestamp_ns.to_le_bytes());
+        let payload = slot + SLOT_HEADER_LEN;
+        self.map[payload..payload + data.len()].copy_from_slice(data);
+        self.atomic(slot).store(seq * 2, Ordering::Release);
+        self.atomic(LATEST_OFFSET).store(seq, Ordering::Release);
+        self.seq = seq;
+        Ok(seq)
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Creates the ring directory; must run before the sidecar is spawned.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let dir = ring_dir(app)?;
+    std::fs::create_dir_all(&dir)?;
+    app.manage(FrameRings {
+        dir,
+        rings: Mutex::new(HashMap::new()),
+    });
+    Ok(())
+}
+
+fn ring_dir(app: &AppHandle) -> Result<PathBuf> {
+    let shm = Path::new("/dev/shm");
+    if cfg!(target_os = "linux") && shm.is_dir() {
+        return Ok(shm.join(format!("{}-frames", app.config().identifier)));
+    }
+    Ok(app.path().app_cache_dir()?.join("frames"))
+}
+
+/// Handler for the `frame://` scheme.
+pub fn handle<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+) -> Response<Cow<'static, [u8]>> {
+    let Some(rings) = ctx.app_handle().try_state::<FrameRings>() else {
+        return status(StatusCode::SERVICE_UNAVAILABLE);
+    };
+    let mut segments = request.uri().path().trim_matches('/').split('/');
+    let (Some(stream), Some(which), None) = (segments.next(), segments.next(), segments.next())
+    else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    if !valid_stream(stream) {
+        return status(StatusCode::BAD_REQUEST);
+    }
+    let ring = match rings.ring(stream) {
+        Ok(ring) => ring,
+        Err(_) => return status(StatusCode::NOT_FOUND),
+    };
+
+    let after = request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("after="))
+        .and_then(|value| value.parse::<u64>().ok());
+    let frame = match which {
+        "latest" => {
+            if after.is_some_and(|after| ring.latest() <= after) {
+                return status(StatusCode::NO_CONTENT);
+            }
+            ring.read_latest()
+        }
+        seq => match seq.parse::<u64>() {
+            Ok(seq) if seq > 0 => ring.read(seq),
+            _ => return status(StatusCode::BAD_REQUEST),
+        },
+    };
+    match frame {
+        Some(frame) => frame_response(frame),
+        None => status(StatusCode::GONE),
+    }
+}
+
+fn frame_response(frame: Frame) -> Response<Cow<'static, [u8]>> {
+    let content_type = match &frame.fourcc {
+        b"MJPG" | b"JPEG" => "image/jpeg",
+        b"PNG " => "image/png",
+        _ => "application/octet-stream",
+    };
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "x-frame-seq, x-frame-format, x-frame-width, x-frame-height, x-frame-timestamp",
+        )
+        .header("x-frame-seq", frame.seq)
+        .header(
+            "x-frame-format",
+            String::from_utf8_lossy(&frame.fourcc).trim_end(),
+        )
+        .header("x-frame-width", frame.width)
+        .header("x-frame-height", frame.height)
+        .header("x-frame-timestamp", frame.timestamp_ns)
+        .body(Cow::Owned(frame.data))
+        .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+fn status(code: StatusCode) -> Response<Cow<'static, [u8]>> {
+    let mut response = Response::new(Cow::Borrowed(&[][..]));
+    *response.status_mut() = code;
+    response
+}
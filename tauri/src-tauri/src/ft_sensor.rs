@@ -0,0 +1,449 @@
+//! ATI Net F/T force/torque sensor, streamed over UDP with RDT.
+//!
+//! [`start_ft_sensor`] asks the sensor at `ftSensor.host` for continuous
+//! high-speed RDT output (up to 7 kHz, usually configured for 1 kHz) and
+//! receives it on its own thread, which the backend never sees. Counts are
+//! converted with the calibration's `countsPerForce`/`countsPerTorque` and
+//! the software bias taken by [`tare_ft_sensor`] is subtracted, so the raw
+//! sensor bias is left alone.
+//!
+//! - Every sample is written to an open MCAP bag on the `ft-sample` channel
+//!   (see [`crate::bag`]), at the full rate.
+//! - `ft-sensor-state` carries the mean and peak of each
+//!   `displayHz` window for the UI, with the count of lost packets.
+//! - `ft-alarm` fires when the force or torque magnitude exceeds the
+//!   `ftSensor.alarm` limits, and once more when it falls back below 90 %
+//!   of them; with `stopRobot` set it also triggers
+//!   [`crate::estop::trigger`].
+//!
+//! The stream is requested again if it stays silent, e.g. after the sensor
+//! rebooted.
+
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+use tokio::sync::oneshot;
+
+use crate::bag;
+use crate::error::{Error, Result};
+use crate::estop;
+use crate::formatting;
+use crate::settings::{FtAlarmSettings, FtSensorSettings, Settings, SettingsStore};
+use crate::time_sync;
+use crate::windows;
+
+const RDT_HEADER: u16 = 0x1234;
+const COMMAND_STOP: u16 = 0x0000;
+const COMMAND_START_HIGH_SPEED: u16 = 0x0002;
+/// `rdt_sequence ft_sequence status Fx Fy Fz Tx Ty Tz`, big-endian.
+const RECORD_LEN: usize = 36;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Silence after which streaming is requested again.
+const RESTART_AFTER: Duration = Duration::from_secs(1);
+const DEFAULT_TARE_SAMPLES: u32 = 100;
+const TARE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Fraction of a limit a reading must fall below to clear its alarm.
+const ALARM_HYSTERESIS: f64 = 0.9;
+
+/// Force in newtons and torque in newton metres.
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Wrench {
+    pub force: [f64; 3],
+    pub torque: [f64; 3],
+}
+
+impl Wrench {
+    fn force_norm(&self) -> f64 {
+        norm(&self.force)
+    }
+
+    fn torque_norm(&self) -> f64 {
+        norm(&self.torque)
+    }
+}
+
+fn norm(v: &[f64; 3]) -> f64 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FtSample {
+    rdt_sequence: u32,
+    ft_sequence: u32,
+    /// Sensor status word; 0 when healthy.
+    status: u32,
+    timestamp_ns: u64,
+    #[serde(flatten)]
+    wrench: Wrench,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FtSensorState {
+    #[serde(flatten)]
+    mean: Wrench,
+    peak_force: f64,
+    peak_torque: f64,
+    samples: u32,
+    /// Packets lost since the stream started.
+    dropped: u64,
+    status: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FtAlarm {
+    /// The limit is exceeded; `false` when it clears.
+    active: bool,
+    force: f64,
+    torque: f64,
+    max_force: Option<f64>,
+    max_torque: Option<f64>,
+}
+
+struct Tare {
+    remaining: u32,
+    count: u32,
+    sum: Wrench,
+    done: oneshot::Sender<Wrench>,
+}
+
+struct Running {
+    stop: Arc<AtomicBool>,
+    tare: Arc<StdMutex<Option<Tare>>>,
+}
+
+#[derive(Default)]
+pub struct FtSensor {
+    running: StdMutex<Option<Running>>,
+    /// The current `ftSensor.alarm` section.
+    alarm: Arc<StdMutex<FtAlarmSettings>>,
+}
+
+/// Follows the alarm limits as settings change.
+pub fn init(app: &AppHandle) {
+    let sensor = FtSensor::default();
+    *sensor.alarm.lock().unwrap() = app.state::<SettingsStore>().get().ft_sensor.alarm;
+    let alarm = sensor.alarm.clone();
+    app.manage(sensor);
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            *alarm.lock().unwrap() = settings.ft_sensor.alarm;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn start_ft_sensor(
+    app: AppHandle,
+    sensor: State<'_, FtSensor>,
+    store: State<'_, SettingsStore>,
+) -> Result<()> {
+    let mut running = sensor.running.lock().unwrap();
+    if running
+        .as_ref()
+        .is_some_and(|running| !running.stop.load(Ordering::Relaxed))
+    {
+        return Err(Error::DeviceBusy(
+            "the force/torque sensor is already streaming".into(),
+        ));
+    }
+    let settings = store.get().ft_sensor;
+    let host = settings
+        .host
+        .clone()
+        .ok_or_else(|| Error::Invalid("ftSensor.host is not set".into()))?;
+    if settings.counts_per_force <= 0.0 || settings.counts_per_torque <= 0.0 {
+        return Err(Error::Invalid("ftSensor counts per unit".into()));
+    }
+    let address = (host.as_str(), settings.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::NotFound(format!("address of {host}")))?;
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })?;
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+    send_command(&socket, COMMAND_START_HIGH_SPEED)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let tare = Arc::new(StdMutex::new(None));
+    let receiver = Receiver {
+        app: app.clone(),
+        socket,
+        settings,
+        alarm: sensor.alarm.clone(),
+        stop: stop.clone(),
+        tare: tare.clone(),
+    };
+    std::thread::Builder::new()
+        .name("ft-sensor".into())
+        .spawn(move || receiver.run())?;
+    tracing::info!(%address, "force/torque sensor streaming");
+    *running = Some(Running { stop, tare });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_ft_sensor(sensor: State<'_, FtSensor>) -> Result<()> {
+    let running = sensor
+        .running
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::NotFound("running force/torque sensor".into()))?;
+    running.stop.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Averages the next `samples` readings, 100 by default, and subtracts
+/// them from everything after; returns the new bias.
+#[tauri::command]
+pub async fn tare_ft_sensor(sensor: State<'_, FtSensor>, samples: Option<u32>) -> Result<Wrench> {
+    let (done, bias) = oneshot::channel();
+    {
+        let running = sensor.running.lock().unwrap();
+        let running = running
+            .as_ref()
+            .filter(|running| !running.stop.load(Ordering::Relaxed))
+            .ok_or_else(|| Error::NotFound("running force/torque sensor".into()))?;
+        *running.tare.lock().unwrap() = Some(Tare {
+            remaining: samples.unwrap_or(DEFAULT_TARE_SAMPLES).max(1),
+            count: 0,
+            sum: Wrench::default(),
+            done,
+        });
+    }
+    tokio::time::timeout(TARE_TIMEOUT, bias)
+        .await
+        .map_err(|_| Error::Stream("no force/torque samples to tare with".into()))?
+        .map_err(|_| Error::Stream("the force/torque stream stopped".into()))
+}
+
+/// Replaces the `ftSensor.alarm` limits.
+#[tauri::command]
+pub fn set_ft_alarm(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    alarm: FtAlarmSettings,
+) -> Result<()> {
+    if alarm.max_force.is_some_and(|limit| limit <= 0.0)
+        || alarm.max_torque.is_some_and(|limit| limit <= 0.0)
+    {
+        return Err(Error::Invalid("alarm limits must be positive".into()));
+    }
+    store.update(&app, |settings| settings.ft_sensor.alarm = alarm)
+}
+
+fn send_command(socket: &UdpSocket, command: u16) -> std::io::Result<()> {
+    let mut request = [0u8; 8];
+    request[..2].copy_from_slice(&RDT_HEADER.to_be_bytes());
+    request[2..4].copy_from_slice(&command.to_be_bytes());
+    // Sample count 0 streams until stopped.
+    socket.send(&request).map(|_| ())
+}
+
+struct Receiver {
+    app: AppHandle,
+    socket: UdpSocket,
+    settings: FtSensorSettings,
+    alarm: Arc<StdMutex<FtAlarmSettings>>,
+    stop: Arc<AtomicBool>,
+    tare: Arc<StdMutex<Option<Tare>>>,
+}
+
+/// Readings of the current display window.
+#[derive(Default)]
+struct Window {
+    sum: Wrench,
+    peak_force: f64,
+    peak_torque: f64,
+    samples: u32,
+}
+
+impl Receiver {
+    fn run(self) {
+        let display_interval = Duration::from_secs_f64(1.0 / self.settings.display_hz.max(1.0));
+        let mut buffer = [0u8; 1500];
+        let mut bias = Wrench::default();
+        let mut last_sequence = None;
+        let mut dropped = 0u64;
+        let mut last_packet = Instant::now();
+        let mut window = Window::default();
+        let mut window_start = Instant::now();
+        let mut alarm_active = false;
+        while !self.stop.load(Ordering::Relaxed) {
+            let len = match self.socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if last_packet.elapsed() > RESTART_AFTER {
+                        tracing::debug!("force/torque stream silent; requesting it again");
+                        let _ = send_command(&self.socket, COMMAND_START_HIGH_SPEED);
+                        last_packet = Instant::now();
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    // ICMP unreachable while the sensor boots; keep asking.
+                    tracing::debug!("force/torque receive failed: {err}");
+                    std::thread::sleep(READ_TIMEOUT);
+                    continue;
+                }
+            };
+            let Some(record) = buffer[..len].get(..RECORD_LEN) else {
+                continue;
+            };
+            last_packet = Instant::now();
+            let word = |index: usize| {
+                u32::from_be_bytes(record[index * 4..index * 4 + 4].try_into().unwrap())
+            };
+            let counts = |index: usize| f64::from(word(index) as i32);
+            let rdt_sequence = word(0);
+            if let Some(last) = last_sequence {
+                dropped += u64::from(rdt_sequence.wrapping_sub(last).saturating_sub(1));
+            }
+            last_sequence = Some(rdt_sequence);
+            let (cpf, cpt) = (
+                self.settings.counts_per_force,
+                self.settings.counts_per_torque,
+            );
+            let raw = Wrench {
+                force: [counts(3) / cpf, counts(4) / cpf, counts(5) / cpf],
+                torque: [counts(6) / cpt, counts(7) / cpt, counts(8) / cpt],
+            };
+
+            if let Some(tared) = self.take_tare(&raw) {
+                bias = tared;
+            }
+            let wrench = subtract(&raw, &bias);
+            let sample = FtSample {
+                rdt_sequence,
+                ft_sequence: word(1),
+                status: word(2),
+                timestamp_ns: time_sync::now_ns(&self.app).max(0) as u64,
+                wrench,
+            };
+            if let Ok(json) = serde_json::to_vec(&sample) {
+                bag::record(&self.app, "ft-sample", &json);
+            }
+            alarm_active = self.check_alarm(&wrench, alarm_active);
+
+            for axis in 0..3 {
+                window.sum.force[axis] += wrench.force[axis];
+                window.sum.torque[axis] += wrench.torque[axis];
+            }
+            window.peak_force = window.peak_force.max(wrench.force_norm());
+            window.peak_torque = window.peak_torque.max(wrench.torque_norm());
+            window.samples += 1;
+            if window_start.elapsed() >= display_interval {
+                let n = f64::from(window.samples);
+                let state = FtSensorState {
+                    mean: Wrench {
+                        force: window.sum.force.map(|value| value / n),
+                        torque: window.sum.torque.map(|value| value / n),
+                    },
+                    peak_force: window.peak_force,
+                    peak_torque: window.peak_torque,
+                    samples: window.samples,
+                    dropped,
+                    status: sample.status,
+                };
+                windows::emit(&self.app, "ft-sensor-state", state);
+                window = Window::default();
+                window_start = Instant::now();
+            }
+        }
+        let _ = send_command(&self.socket, COMMAND_STOP);
+        tracing::info!(dropped, "force/torque sensor stopped");
+    }
+
+    /// Adds `raw` to a pending tare; returns the bias once it is complete.
+    fn take_tare(&self, raw: &Wrench) -> Option<Wrench> {
+        let mut pending = self.tare.lock().unwrap();
+        let tare = pending.as_mut()?;
+        for axis in 0..3 {
+            tare.sum.force[axis] += raw.force[axis];
+            tare.sum.torque[axis] += raw.torque[axis];
+        }
+        tare.count += 1;
+        tare.remaining -= 1;
+        if tare.remaining > 0 {
+            return None;
+        }
+        let tare = pending.take()?;
+        let n = f64::from(tare.count);
+        let bias = Wrench {
+            force: tare.sum.force.map(|value| value / n),
+            torque: tare.sum.torque.map(|value| value / n),
+        };
+        tracing::info!(force = ?bias.force, torque = ?bias.torque, "force/torque sensor tared");
+        let _ = tare.done.send(bias);
+        Some(bias)
+    }
+
+    /// Emits `ft-alarm` on crossing the limits; returns whether the alarm
+    /// is active.
+    fn check_alarm(&self, wrench: &Wrench, active: bool) -> bool {
+        let alarm = self.alarm.lock().unwrap().clone();
+        let (force, torque) = (wrench.force_norm(), wrench.torque_norm());
+        let over = |value: f64, limit: Option<f64>, scale: f64| {
+            limit.is_some_and(|limit| value > limit * scale)
+        };
+        let now_active = if active {
+            over(force, alarm.max_force, ALARM_HYSTERESIS)
+                || over(torque, alarm.max_torque, ALARM_HYSTERESIS)
+        } else {
+            over(force, alarm.max_force, 1.0) || over(torque, alarm.max_torque, 1.0)
+        };
+        if now_active == active {
+            return active;
+        }
+        if now_active {
+            tracing::warn!(force, torque, "force/torque limit exceeded");
+            if alarm.stop_robot {
+                let format = formatting::preferences(&self.app);
+                estop::trigger(
+                    &self.app,
+                    "ft-sensor",
+                    format!(
+                        "force {}, torque {}",
+                        format.quantity(force, "N", 1),
+                        format.quantity(torque, "N·m", 2)
+                    ),
+                );
+            }
+        }
+        let _ = self.app.emit(
+            "ft-alarm",
+            FtAlarm {
+                active: now_active,
+                force,
+                torque,
+                max_force: alarm.max_force,
+                max_torque: alarm.max_torque,
+            },
+        );
+        now_active
+    }
+}
+
+fn subtract(a: &Wrench, b: &Wrench) -> Wrench {
+    Wrench {
+        force: [0, 1, 2].map(|axis| a.force[axis] - b.force[axis]),
+        torque: [0, 1, 2].map(|axis| a.torque[axis] - b.torque[axis]),
+    }
+}
@@ -0,0 +1,241 @@
+//! Native gamepad capture for teleoperation.
+//!
+//! Reading the Gamepad API in the webview adds tens of milliseconds of
+//! jitter, so the shell samples controllers itself on a dedicated thread at
+//! `gamepad.rateHz`. Axes get the configured deadzone, scale and inversion,
+//! and each sample goes out either as a `gamepad-state` event or as a JSON
+//! datagram to `gamepad.udpTarget`. Samples are sent when the state changes
+//! and at least every [`HEARTBEAT`], so receivers can treat silence as a lost
+//! controller.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gilrs::{Axis, Button, Gamepad, GilrsBuilder};
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::error::{Error, Result};
+use crate::input::Sink;
+use crate::jog::{self, JogCommand, JogFrame};
+use crate::settings::{GamepadSettings, SettingsStore};
+
+const HEARTBEAT: Duration = Duration::from_millis(100);
+
+const AXES: [(Axis, &str); 6] = [
+    (Axis::LeftStickX, "leftStickX"),
+    (Axis::LeftStickY, "leftStickY"),
+    (Axis::RightStickX, "rightStickX"),
+    (Axis::RightStickY, "rightStickY"),
+    (Axis::DPadX, "dpadX"),
+    (Axis::DPadY, "dpadY"),
+];
+
+/// Analog triggers, reported as axes in `0.0..=1.0`.
+const TRIGGERS: [(Button, &str); 2] = [
+    (Button::LeftTrigger2, "leftTrigger"),
+    (Button::RightTrigger2, "rightTrigger"),
+];
+
+const BUTTONS: [(Button, &str); 15] = [
+    (Button::South, "south"),
+    (Button::East, "east"),
+    (Button::North, "north"),
+    (Button::West, "west"),
+    (Button::LeftTrigger, "leftBumper"),
+    (Button::RightTrigger, "rightBumper"),
+    (Button::Select, "select"),
+    (Button::Start, "start"),
+    (Button::Mode, "mode"),
+    (Button::LeftThumb, "leftThumb"),
+    (Button::RightThumb, "rightThumb"),
+    (Button::DPadUp, "dpadUp"),
+    (Button::DPadDown, "dpadDown"),
+    (Button::DPadLeft, "dpadLeft"),
+    (Button::DPadRight, "dpadRight"),
+];
+
+/// One sample of a controller, as emitted or sent over UDP.
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamepadState {
+    pub id: usize,
+    pub name: String,
+    pub axes: BTreeMap<&'static str, f32>,
+    pub buttons: BTreeMap<&'static str, bool>,
+}
+
+/// Payload of the `gamepad-state` event and the UDP datagrams.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GamepadSample<'a> {
+    seq: u64,
+    timestamp_ms: i64,
+    gamepads: &'a [GamepadState],
+}
+
+/// Stop flag of the running capture thread.
+#[derive(Default)]
+pub struct GamepadCapture(Mutex<Option<Arc<AtomicBool>>>);
+
+/// Starts capturing with the current `gamepad` settings, replacing any
+/// running capture.
+#[tauri::command]
+pub fn start_gamepad(
+    app: AppHandle,
+    capture: State<'_, GamepadCapture>,
+    settings: State<'_, SettingsStore>,
+) -> Result<()> {
+    let config = settings.get().gamepad;
+    let jog = settings.get().jog;
+    let jog = jog.gamepad.then(|| (app.clone(), jog.deadman_button));
+    let sink = Sink::new(app, config.output, &config.udp_target)?;
+
+    let mut running = capture.0.lock().unwrap();
+    if let Some(stop) = running.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let (ready, started) = mpsc::channel();
+    {
+        let stop = stop.clone();
+        std::thread::Builder::new()
+            .name("gamepad".into())
+            .spawn(move || capture_loop(config, sink, jog, stop, ready))?;
+    }
+    started
+        .recv()
+        .map_err(|_| Error::Gamepad("capture thread exited".into()))??;
+    *running = Some(stop);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_gamepad(capture: State<'_, GamepadCapture>) {
+    if let Some(stop) = capture.0.lock().unwrap().take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs on its own thread because the gilrs context is not `Send`.
+///
+/// With `jog`, the first controller also jogs the robot while the named
+/// deadman button is held.
+fn capture_loop(
+    config: GamepadSettings,
+    sink: Sink,
+    jog: Option<(AppHandle, String)>,
+    stop: Arc<AtomicBool>,
+    ready: mpsc::Sender<Result<()>>,
+) {
+    // Deadzone handling is ours, so the built-in filters are disabled.
+    let mut gilrs = match GilrsBuilder::new().with_default_filters(false).build() {
+        Ok(gilrs) => {
+            let _ = ready.send(Ok(()));
+            gilrs
+        }
+        Err(err) => {
+            let _ = ready.send(Err(Error::Gamepad(err.to_string())));
+            return;
+        }
+    };
+    tracing::info!(rate_hz = config.rate_hz, "gamepad capture started");
+
+    let period = Duration::from_secs_f64(1.0 / config.rate_hz.clamp(1.0, 1000.0));
+    let mut next_tick = Instant::now();
+    let mut last_sent = Instant::now() - HEARTBEAT;
+    let mut previous = Vec::new();
+    let mut seq = 0;
+    let mut jogging = false;
+    while !stop.load(Ordering::Relaxed) {
+        while gilrs.next_event().is_some() {}
+
+        let gamepads: Vec<GamepadState> = gilrs
+            .gamepads()
+            .map(|(_, gamepad)| sample(&gamepad, &config))
+            .collect();
+        if let Some((app, deadman)) = &jog {
+            let held = gamepads
+                .first()
+                .filter(|state| state.buttons.get(deadman.as_str()) == Some(&true));
+            if held.is_some() || jogging {
+                let command = held.map(jog_command).unwrap_or_default();
+                if let Err(err) = jog::submit(app, command) {
+                    tracing::debug!("gamepad jog: {err}");
+                }
+            }
+            jogging = held.is_some();
+        }
+        if gamepads != previous || last_sent.elapsed() >= HEARTBEAT {
+            seq += 1;
+            sink.send(
+                "gamepad-state",
+                &GamepadSample {
+                    seq,
+                    timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                    gamepads: &gamepads,
+                },
+            );
+            last_sent = Instant::now();
+            previous = gamepads;
+        }
+
+        next_tick += period;
+        match next_tick.checked_duration_since(Instant::now()) {
+            Some(wait) => std::thread::sleep(wait),
+            None => next_tick = Instant::now(),
+        }
+    }
+    tracing::info!("gamepad capture stopped");
+}
+
+fn sample(gamepad: &Gamepad<'_>, config: &GamepadSettings) -> GamepadState {
+    let mut axes = BTreeMap::new();
+    for (axis, name) in AXES {
+        let value = shape(gamepad.value(axis), config);
+        let inverted = config.inverted_axes.iter().any(|axis| axis == name);
+        axes.insert(name, if inverted { -value } else { value });
+    }
+    for (button, name) in TRIGGERS {
+        let value = gamepad.button_data(button).map_or(0.0, |data| data.value());
+        axes.insert(name, shape(value, config));
+    }
+    let buttons = BUTTONS
+        .iter()
+        .map(|&(button, name)| (name, gamepad.is_pressed(button)))
+        .collect();
+    GamepadState {
+        id: gamepad.id().into(),
+        name: gamepad.name().to_owned(),
+        axes,
+        buttons,
+    }
+}
+
+/// The sticks as a base-frame jog.
+fn jog_command(state: &GamepadState) -> JogCommand {
+    let axis = |name: &str| f64::from(state.axes.get(name).copied().unwrap_or(0.0));
+    JogCommand {
+        frame: JogFrame::Base,
+        velocity: [
+            axis("leftStickX"),
+            axis("leftStickY"),
+            axis("rightStickY"),
+            0.0,
+            0.0,
+            axis("rightStickX"),
+        ],
+    }
+}
+
+/// Applies the deadzone, rescaling the remaining travel back to full range.
+fn shape(value: f32, config: &GamepadSettings) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= config.deadzone {
+        return 0.0;
+    }
+    let live = (magnitude - config.deadzone) / (1.0 - config.deadzone).max(f32::EPSILON);
+    value.signum() * live.min(1.0) * config.scale
+}
@@ -0,0 +1,382 @@
+//! Robotiq 2F-85/2F-140 gripper over Modbus RTU, independent of the backend.
+//!
+//! The gripper on `gripper.port` is driven through its robot output
+//! registers at 1000 (action request, position, speed, force) and read back
+//! from its input registers at 2000 (status, fault, position, current), as
+//! in the Robotiq 2F instruction manual. Positions, speeds and forces are
+//! the gripper's own 0–255 scale; position 0 is fully open.
+//!
+//! [`activate_gripper`] runs the activation sequence, which the gripper
+//! needs after every power cycle, and [`set_gripper_position`] moves it.
+//! [`start_gripper_polling`] emits `gripper-status` at `gripper.pollHz`, with
+//! faults decoded into the manual's messages.
+
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::Mutex;
+use tokio_modbus::client::Context;
+use tokio_modbus::prelude::*;
+use tokio_serial::SerialPortBuilderExt;
+
+use crate::error::{Error, Result};
+use crate::serial::SerialPorts;
+use crate::settings::{GripperSettings, SettingsStore};
+use crate::windows;
+
+const OUTPUT_REGISTERS: u16 = 1000;
+const INPUT_REGISTERS: u16 = 2000;
+/// `rACT`: activate.
+const ACTION_ACTIVATE: u8 = 0x01;
+/// `rGTO`: go to the requested position.
+const ACTION_GO_TO: u8 = 0x08;
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(10);
+const MOVE_TIMEOUT: Duration = Duration::from_secs(5);
+const STATUS_INTERVAL: Duration = Duration::from_millis(50);
+/// RTU has no end of its own to a request the gripper never answers.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_SPEED: u8 = 255;
+const DEFAULT_FORCE: u8 = 150;
+
+/// `gSTA`.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Activation {
+    Reset,
+    Activating,
+    Active,
+}
+
+/// `gOBJ`: how the last motion ended.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Motion {
+    Moving,
+    /// Stopped by an object while opening.
+    ContactOpening,
+    /// Stopped by an object while closing, i.e. something is gripped.
+    ContactClosing,
+    AtPosition,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FaultSeverity {
+    /// The command is held until the condition clears.
+    Priority,
+    Minor,
+    /// Needs reactivation.
+    Major,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GripperFault {
+    /// `gFLT`.
+    pub code: u8,
+    pub severity: FaultSeverity,
+    pub message: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GripperStatus {
+    pub activation: Activation,
+    /// `gGTO`: a position request is being followed.
+    pub go_to: bool,
+    pub motion: Motion,
+    pub fault: Option<GripperFault>,
+    pub requested_position: u8,
+    pub position: u8,
+    /// Motor current in milliamperes.
+    pub current_ma: u16,
+}
+
+impl GripperStatus {
+    fn decode(words: &[u16]) -> Self {
+        let [status, _] = words[0].to_be_bytes();
+        let [fault, requested_position] = words[1].to_be_bytes();
+        let [position, current] = words[2].to_be_bytes();
+        GripperStatus {
+            activation: match (status >> 4) & 0x03 {
+                0 => Activation::Reset,
+                3 => Activation::Active,
+                _ => Activation::Activating,
+            },
+            go_to: status & ACTION_GO_TO != 0,
+            motion: match status >> 6 {
+                0 => Motion::Moving,
+                1 => Motion::ContactOpening,
+                2 => Motion::ContactClosing,
+                _ => Motion::AtPosition,
+            },
+            fault: decode_fault(fault & 0x0f),
+            requested_position,
+            position,
+            current_ma: u16::from(current) * 10,
+        }
+    }
+}
+
+/// Messages of the manual's `gFLT` table.
+fn decode_fault(code: u8) -> Option<GripperFault> {
+    let (severity, message) = match code {
+        0x00 => return None,
+        0x05 => (
+            FaultSeverity::Priority,
+            "action delayed; activation must complete first",
+        ),
+        0x07 => (
+            FaultSeverity::Priority,
+            "the activation bit must be set before an action",
+        ),
+        0x08 => (
+            FaultSeverity::Minor,
+            "maximum operating temperature exceeded; wait for it to cool down",
+        ),
+        0x09 => (
+            FaultSeverity::Minor,
+            "no communication for at least 1 second",
+        ),
+        0x0A => (FaultSeverity::Major, "under minimum operating voltage"),
+        0x0B => (FaultSeverity::Major, "automatic release in progress"),
+        0x0C => (
+            FaultSeverity::Major,
+            "internal fault; contact Robotiq support",
+        ),
+        0x0D => (
+            FaultSeverity::Major,
+            "activation fault; check that nothing obstructs the fingers",
+        ),
+        0x0E => (FaultSeverity::Major, "overcurrent triggered"),
+        0x0F => (FaultSeverity::Major, "automatic release completed"),
+        _ => (FaultSeverity::Minor, "unknown fault"),
+    };
+    Some(GripperFault {
+        code,
+        severity,
+        message,
+    })
+}
+
+/// Serial link to the gripper shared by commands and the poller.
+#[derive(Default)]
+pub struct Gripper {
+    context: Mutex<Option<Context>>,
+    poller: StdMutex<Option<JoinHandle<()>>>,
+}
+
+impl Gripper {
+    async fn ensure_connected(&self, app: &AppHandle, settings: &GripperSettings) -> Result<()> {
+        let mut context = self.context.lock().await;
+        if context.is_none() {
+            let path = settings
+                .port
+                .as_deref()
+                .ok_or_else(|| Error::Invalid("no gripper port configured".into()))?;
+            if app.state::<SerialPorts>().is_open(path) {
+                return Err(Error::DeviceBusy(format!(
+                    "{path} is open in the serial console"
+                )));
+            }
+            // 8N1 is the gripper's only framing.
+            let port = tokio_serial::new(path, settings.baud_rate).open_native_async()?;
+            *context = Some(rtu::attach_slave(port, Slave(settings.slave_id)));
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", name = "gripper.status", skip_all)]
+    async fn status(&self) -> Result<GripperStatus> {
+        let mut guard = self.context.lock().await;
+        let context = guard
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("gripper not connected".into()))?;
+        let read = context.read_input_registers(INPUT_REGISTERS, 3);
+        match tokio::time::timeout(RESPONSE_TIMEOUT, read).await {
+            Ok(Ok(words)) => Ok(GripperStatus::decode(&words?)),
+            // Transport failures invalidate the port; reopen next time.
+            Ok(Err(err)) => {
+                guard.take();
+                Err(err.into())
+            }
+            Err(_) => {
+                guard.take();
+                Err(no_response())
+            }
+        }
+    }
+
+    /// Writes the action request, position, speed and force bytes.
+    #[tracing::instrument(level = "debug", name = "gripper.request", skip(self))]
+    async fn request(&self, action: u8, position: u8, speed: u8, force: u8) -> Result<()> {
+        let mut guard = self.context.lock().await;
+        let context = guard
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("gripper not connected".into()))?;
+        let words = [
+            u16::from_be_bytes([action, 0]),
+            u16::from_be_bytes([0, position]),
+            u16::from_be_bytes([speed, force]),
+        ];
+        let write = context.write_multiple_registers(OUTPUT_REGISTERS, &words);
+        match tokio::time::timeout(RESPONSE_TIMEOUT, write).await {
+            Ok(Ok(result)) => Ok(result?),
+            Ok(Err(err)) => {
+                guard.take();
+                Err(err.into())
+            }
+            Err(_) => {
+                guard.take();
+                Err(no_response())
+            }
+        }
+    }
+
+    /// Polls the status until `done` accepts it.
+    async fn wait_for(
+        &self,
+        timeout: Duration,
+        what: &str,
+        done: impl Fn(&GripperStatus) -> bool,
+    ) -> Result<GripperStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.status().await?;
+            if let Some(fault) = status
+                .fault
+                .as_ref()
+                .filter(|fault| matches!(fault.severity, FaultSeverity::Major))
+            {
+                return Err(Error::Robot(format!(
+                    "gripper fault {:#04x}: {}",
+                    fault.code, fault.message
+                )));
+            }
+            if done(&status) {
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Robot(format!("gripper {what} timed out")));
+            }
+            tokio::time::sleep(STATUS_INTERVAL).await;
+        }
+    }
+}
+
+fn no_response() -> Error {
+    Error::Robot("the gripper did not respond".into())
+}
+
+/// Resets and activates the gripper; it opens and closes fully once to
+/// find its stroke.
+#[tauri::command]
+pub async fn activate_gripper(
+    app: AppHandle,
+    gripper: State<'_, Gripper>,
+) -> Result<GripperStatus> {
+    let settings = app.state::<SettingsStore>().get().gripper;
+    gripper.ensure_connected(&app, &settings).await?;
+    gripper.request(0, 0, 0, 0).await?;
+    gripper
+        .wait_for(ACTIVATION_TIMEOUT, "reset", |status| {
+            status.activation == Activation::Reset
+        })
+        .await?;
+    gripper.request(ACTION_ACTIVATE, 0, 0, 0).await?;
+    let status = gripper
+        .wait_for(ACTIVATION_TIMEOUT, "activation", |status| {
+            status.activation == Activation::Active
+        })
+        .await?;
+    tracing::info!("gripper activated");
+    Ok(status)
+}
+
+/// Moves to `position` (0 open, 255 closed). With `wait` it returns once
+/// the fingers stopped, at the position or on an object.
+#[tauri::command]
+pub async fn set_gripper_position(
+    app: AppHandle,
+    gripper: State<'_, Gripper>,
+    position: u8,
+    speed: Option<u8>,
+    force: Option<u8>,
+    wait: Option<bool>,
+) -> Result<GripperStatus> {
+    let settings = app.state::<SettingsStore>().get().gripper;
+    gripper.ensure_connected(&app, &settings).await?;
+    if gripper.status().await?.activation != Activation::Active {
+        return Err(Error::Invalid("the gripper is not activated".into()));
+    }
+    gripper
+        .request(
+            ACTION_ACTIVATE | ACTION_GO_TO,
+            position,
+            speed.unwrap_or(DEFAULT_SPEED),
+            force.unwrap_or(DEFAULT_FORCE),
+        )
+        .await?;
+    if !wait.unwrap_or(false) {
+        return gripper.status().await;
+    }
+    gripper
+        .wait_for(MOVE_TIMEOUT, "move", |status| {
+            status.requested_position == position && status.motion != Motion::Moving
+        })
+        .await
+}
+
+#[tauri::command]
+pub async fn get_gripper_status(
+    app: AppHandle,
+    gripper: State<'_, Gripper>,
+) -> Result<GripperStatus> {
+    let settings = app.state::<SettingsStore>().get().gripper;
+    gripper.ensure_connected(&app, &settings).await?;
+    gripper.status().await
+}
+
+async fn poll(app: AppHandle, settings: GripperSettings) {
+    let period = Duration::from_secs_f64(1.0 / settings.poll_hz.clamp(0.1, 100.0));
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let gripper = app.state::<Gripper>();
+        let status = match gripper.ensure_connected(&app, &settings).await {
+            Ok(()) => gripper.status().await,
+            Err(err) => Err(err),
+        };
+        match status {
+            Ok(status) => windows::emit(&app, "gripper-status", status),
+            Err(err) => tracing::debug!("gripper status unavailable: {err}"),
+        }
+    }
+}
+
+/// Emits `gripper-status` at `gripper.pollHz` until stopped.
+#[tauri::command]
+pub async fn start_gripper_polling(app: AppHandle, gripper: State<'_, Gripper>) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().gripper;
+    gripper.context.lock().await.take();
+    gripper.ensure_connected(&app, &settings).await?;
+    let poller = tauri::async_runtime::spawn(poll(app.clone(), settings));
+    if let Some(previous) = gripper.poller.lock().unwrap().replace(poller) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+/// Stops polling and releases the serial port.
+#[tauri::command]
+pub async fn stop_gripper_polling(gripper: State<'_, Gripper>) -> Result<()> {
+    if let Some(poller) = gripper.poller.lock().unwrap().take() {
+        poller.abort();
+    }
+    gripper.context.lock().await.take();
+    Ok(())
+}
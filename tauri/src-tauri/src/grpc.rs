@@ -0,0 +1,253 @@
+//! Client of the on-prem robot gateway's gRPC API.
+//!
+//! The client is generated at build time from `proto/` (see `build.rs`).
+//! Unary calls are plain commands bounded by the configured deadline, which
+//! is sent to the gateway as `grpc-timeout`. Server streams are opened by
+//! `grpc_subscribe_*`, which returns a subscription id; messages arrive as
+//! `grpc-robot-state` / `grpc-gateway-event` events until
+//! `grpc_unsubscribe`, and `grpc-stream-ended` reports a stream the gateway
+//! closed or failed.
+//!
+//! The channel is created lazily from `settings.grpc` and recreated when
+//! those settings change, so edits apply to the next call.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic::Streaming;
+
+use crate::error::{Error, Result};
+use crate::licensing::{self, Feature};
+use crate::settings::{GrpcSettings, SettingsStore};
+use crate::windows;
+
+pub mod proto {
+    tonic::include_proto!("percus.gateway.v1");
+}
+
+use proto::robot_gateway_client::RobotGatewayClient;
+use proto::{
+    GetRobotStateRequest, ListRobotsRequest, ListRobotsResponse, RobotState, SendCommandRequest,
+    SendCommandResponse, StreamEventsRequest, StreamRobotStateRequest,
+};
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamMessage<T> {
+    subscription: u32,
+    message: T,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamEnded {
+    subscription: u32,
+    error: Option<String>,
+}
+
+/// Channel to the gateway and the open server streams.
+#[derive(Default)]
+pub struct Gateway {
+    channel: StdMutex<Option<(GrpcSettings, Channel)>>,
+    subscriptions: StdMutex<HashMap<u32, JoinHandle<()>>>,
+    next_id: AtomicU32,
+}
+
+impl Gateway {
+    fn client(&self, settings: &GrpcSettings) -> Result<RobotGatewayClient<Channel>> {
+        let mut cached = self.channel.lock().unwrap();
+        if let Some((_, channel)) = cached.as_ref().filter(|(used, _)| used == settings) {
+            return Ok(RobotGatewayClient::new(channel.clone()));
+        }
+        let channel = connect(settings)?;
+        *cached = Some((settings.clone(), channel.clone()));
+        Ok(RobotGatewayClient::new(channel))
+    }
+}
+
+fn connect(settings: &GrpcSettings) -> Result<Channel> {
+    let url = settings
+        .endpoint
+        .clone()
+        .ok_or_else(|| Error::Invalid("no gateway endpoint configured".into()))?;
+    let tls = url.starts_with("https://");
+    let mut endpoint = Endpoint::from_shared(url)?
+        .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .keep_alive_while_idle(true);
+    if tls {
+        let mut config = ClientTlsConfig::new().with_native_roots();
+        if let Some(path) = &settings.ca_cert {
+            config = config.ca_certificate(Certificate::from_pem(std::fs::read(path)?));
+        }
+        if let Some(domain) = &settings.domain_name {
+            config = config.domain_name(domain.clone());
+        }
+        endpoint = endpoint.tls_config(config)?;
+    }
+    Ok(endpoint.connect_lazy())
+}
+
+/// Wraps a unary request with the configured deadline.
+fn unary<T>(message: T, settings: &GrpcSettings) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request.set_timeout(Duration::from_millis(settings.deadline_ms));
+    request
+}
+
+#[tauri::command]
+pub async fn grpc_list_robots(
+    app: AppHandle,
+    gateway: State<'_, Gateway>,
+    settings: State<'_, SettingsStore>,
+    cell: Option<String>,
+) -> Result<ListRobotsResponse> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let settings = settings.get().grpc;
+    let mut client = gateway.client(&settings)?;
+    let request = ListRobotsRequest {
+        cell: cell.unwrap_or_default(),
+    };
+    Ok(client
+        .list_robots(unary(request, &settings))
+        .await?
+        .into_inner())
+}
+
+#[tauri::command]
+pub async fn grpc_get_robot_state(
+    app: AppHandle,
+    gateway: State<'_, Gateway>,
+    settings: State<'_, SettingsStore>,
+    robot_id: String,
+) -> Result<RobotState> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let settings = settings.get().grpc;
+    let mut client = gateway.client(&settings)?;
+    let request = GetRobotStateRequest { robot_id };
+    Ok(client
+        .get_robot_state(unary(request, &settings))
+        .await?
+        .into_inner())
+}
+
+#[tauri::command]
+pub async fn grpc_send_command(
+    app: AppHandle,
+    gateway: State<'_, Gateway>,
+    settings: State<'_, SettingsStore>,
+    request: SendCommandRequest,
+) -> Result<SendCommandResponse> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let settings = settings.get().grpc;
+    let mut client = gateway.client(&settings)?;
+    Ok(client
+        .send_command(unary(request, &settings))
+        .await?
+        .into_inner())
+}
+
+/// Streams a robot's state as `grpc-robot-state`; returns the subscription id.
+#[tauri::command]
+pub async fn grpc_subscribe_robot_state(
+    app: AppHandle,
+    gateway: State<'_, Gateway>,
+    settings: State<'_, SettingsStore>,
+    robot_id: String,
+    rate_hz: Option<f64>,
+) -> Result<u32> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let mut client = gateway.client(&settings.get().grpc)?;
+    let request = StreamRobotStateRequest {
+        robot_id,
+        rate_hz: rate_hz.unwrap_or_default(),
+    };
+    let stream = client.stream_robot_state(request).await?.into_inner();
+    Ok(subscribe(&app, &gateway, stream, "grpc-robot-state"))
+}
+
+/// Streams gateway events as `grpc-gateway-event`; all robots when
+/// `robot_ids` is empty.
+#[tauri::command]
+pub async fn grpc_subscribe_events(
+    app: AppHandle,
+    gateway: State<'_, Gateway>,
+    settings: State<'_, SettingsStore>,
+    robot_ids: Vec<String>,
+) -> Result<u32> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let mut client = gateway.client(&settings.get().grpc)?;
+    let request = StreamEventsRequest { robot_ids };
+    let stream = client.stream_events(request).await?.into_inner();
+    Ok(subscribe(&app, &gateway, stream, "grpc-gateway-event"))
+}
+
+#[tauri::command]
+pub fn grpc_unsubscribe(gateway: State<'_, Gateway>, subscription: u32) -> Result<()> {
+    let task = gateway
+        .subscriptions
+        .lock()
+        .unwrap()
+        .remove(&subscription)
+        .ok_or_else(|| Error::NotFound(format!("grpc subscription {subscription}")))?;
+    task.abort();
+    Ok(())
+}
+
+fn subscribe<T>(
+    app: &AppHandle,
+    gateway: &Gateway,
+    stream: Streaming<T>,
+    event: &'static str,
+) -> u32
+where
+    T: Serialize + Clone + Send + 'static,
+{
+    let id = gateway.next_id.fetch_add(1, Ordering::Relaxed);
+    // Registered under the lock so a stream that ends at once still finds
+    // its own entry to remove.
+    let mut subscriptions = gateway.subscriptions.lock().unwrap();
+    let task = tauri::async_runtime::spawn(pump(app.clone(), id, stream, event));
+    subscriptions.insert(id, task);
+    id
+}
+
+async fn pump<T>(app: AppHandle, subscription: u32, mut stream: Streaming<T>, event: &'static str)
+where
+    T: Serialize + Clone,
+{
+    let error = loop {
+        match stream.message().await {
+            Ok(Some(message)) => {
+                let payload = StreamMessage {
+                    subscription,
+                    message,
+                };
+                windows::emit(&app, event, payload);
+            }
+            Ok(None) => break None,
+            Err(status) => break Some(status.message().to_owned()),
+        }
+    };
+    if let Some(error) = &error {
+        tracing::warn!(subscription, "gateway stream failed: {error}");
+    }
+    app.state::<Gateway>()
+        .subscriptions
+        .lock()
+        .unwrap()
+        .remove(&subscription);
+    let _ = app.emit(
+        "grpc-stream-ended",
+        StreamEnded {
+            subscription,
+            error,
+        },
+    );
+}
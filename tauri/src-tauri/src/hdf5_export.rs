@@ -0,0 +1,418 @@
+//! HDF5 export of recorded episodes and bag telemetry, for analysis in h5py.
+//!
+//! [`export_hdf5`] returns an export id at once and converts in a blocking
+//! task, reporting `hdf5-export-progress` and finally `hdf5-export-finished`.
+//!
+//! An episode becomes one dataset per table column (`observation.state` is
+//! `frames × dims`) plus the dataset's `fps`, `task` and feature names as
+//! attributes. Bags are flattened per channel: every numeric leaf of the
+//! JSON messages (`values.current`, `translation.0`, ...) becomes a dataset
+//! aligned with the channel's `timestamp_ns`, holding NaN where a message
+//! lacked the field. Weld registers carry their configured `units`,
+//! converted to `formatting.unitSystem` with the original kept as
+//! `source_units`; files record the `locale` and `unit_system` they were
+//! exported with (see [`crate::formatting`]).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use arrow_array::{Array, FixedSizeListArray, Float32Array, Int64Array};
+use hdf5_pure::{AttrValue, FileBuilder};
+use memmap2::Mmap;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::formatting::{self, FormatPreferences};
+use crate::notifications::{self, Action, Category, Notice};
+use crate::recording;
+use crate::settings::SettingsStore;
+use crate::telemetry;
+
+/// Messages between progress events.
+const PROGRESS_EVERY: u64 = 10_000;
+
+#[derive(Deserialize)]
+#[serde(tag = "source", rename_all = "camelCase")]
+pub enum ExportSource {
+    /// An episode recorded by the shell.
+    #[serde(rename_all = "camelCase")]
+    Episode {
+        dataset: String,
+        episode_index: usize,
+    },
+    /// Telemetry from MCAP bags, optionally limited to a time range.
+    #[serde(rename_all = "camelCase")]
+    Bag {
+        files: Vec<PathBuf>,
+        /// Unix time in ns; inclusive.
+        start_ns: Option<u64>,
+        /// Unix time in ns; exclusive.
+        end_ns: Option<u64>,
+        /// Channels to export; all when unset.
+        channels: Option<Vec<String>>,
+    },
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportProgress {
+    id: u32,
+    /// 0 to 1.
+    fraction: f64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportFinished {
+    id: u32,
+    path: PathBuf,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Hdf5Exports {
+    next_id: AtomicU32,
+}
+
+/// Starts converting `source` into the HDF5 file `output`.
+#[tauri::command]
+pub fn export_hdf5(
+    app: AppHandle,
+    exports: State<'_, Hdf5Exports>,
+    source: ExportSource,
+    output: PathBuf,
+) -> Result<u32> {
+    let id = exports.next_id.fetch_add(1, Ordering::Relaxed);
+    let units = weld_units(&app);
+    let format = formatting::preferences(&app);
+    let source = match source {
+        ExportSource::Episode {
+            dataset,
+            episode_index,
+        } => Resolved::Episode {
+            dir: recording::dataset_dir(&app, &dataset)?,
+            dataset,
+            episode_index,
+        },
+        ExportSource::Bag {
+            files,
+            start_ns,
+            end_ns,
+            channels,
+        } => Resolved::Bag {
+            files,
+            range: start_ns.unwrap_or(0)..end_ns.unwrap_or(u64::MAX),
+            channels,
+        },
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let progress = |fraction: f64| {
+            let _ = app.emit("hdf5-export-progress", ExportProgress { id, fraction });
+        };
+        let mut builder = match source {
+            Resolved::Episode {
+                dir,
+                dataset,
+                episode_index,
+            } => export_episode(&dir, &dataset, episode_index),
+            Resolved::Bag {
+                files,
+                range,
+                channels,
+            } => export_bag(
+                &files,
+                range,
+                channels.as_deref(),
+                &units,
+                &format,
+                &progress,
+            ),
+        };
+        if let Ok(builder) = &mut builder {
+            builder.set_attr("locale", AttrValue::String(format.locale.clone()));
+            let system = format.unit_system.name();
+            builder.set_attr("unit_system", AttrValue::String(system.into()));
+        }
+        let result = builder.and_then(|builder| {
+            builder
+                .write(&output)
+                .map_err(|err| Error::Invalid(format!("hdf5: {err}")))
+        });
+        progress(1.0);
+        let error = result.err().map(|err| err.to_string());
+        let notice = match &error {
+            Some(error) => {
+                tracing::warn!(id, "hdf5 export failed: {error}");
+                Notice::new(Category::Exports, "HDF5 export failed", error)
+                    .action(Action::ShowWindow)
+            }
+            None => {
+                tracing::info!(id, path = %output.display(), "hdf5 export finished");
+                let name = output.file_name().unwrap_or_default().to_string_lossy();
+                let notice = Notice::new(Category::Exports, "HDF5 export finished", name);
+                match output.parent() {
+                    Some(dir) => notice.action(Action::OpenFolder(dir.to_owned())),
+                    None => notice,
+                }
+            }
+        };
+        notifications::notify(&app, notice);
+        let finished = ExportFinished {
+            id,
+            path: output,
+            error,
+        };
+        let _ = app.emit("hdf5-export-finished", finished);
+    });
+    Ok(id)
+}
+
+/// One table column; `ints` is used for the index columns, `floats` otherwise.
+struct Column {
+    width: usize,
+    floats: Vec<f64>,
+    ints: Vec<i64>,
+}
+
+enum Resolved {
+    Episode {
+        dir: PathBuf,
+        dataset: String,
+        episode_index: usize,
+    },
+    Bag {
+        files: Vec<PathBuf>,
+        range: Range<u64>,
+        channels: Option<Vec<String>>,
+    },
+}
+
+fn export_episode(dir: &Path, dataset: &str, episode: usize) -> Result<FileBuilder> {
+    let info: Value = serde_json::from_slice(&std::fs::read(dir.join("meta/info.json"))?)?;
+    let path = recording::episode_data_path(dir, episode);
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?.build()?;
+
+    let mut columns: BTreeMap<String, Column> = BTreeMap::new();
+    for batch in reader {
+        let batch = batch?;
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            let any = column.as_any();
+            let (width, floats, ints) = if let Some(list) = any.downcast_ref::<FixedSizeListArray>()
+            {
+                let values = list.values().as_any().downcast_ref::<Float32Array>();
+                let values = values.into_iter().flat_map(|values| values.values().iter());
+                (
+                    list.value_length() as usize,
+                    values.map(|&v| f64::from(v)).collect(),
+                    Vec::new(),
+                )
+            } else if let Some(values) = any.downcast_ref::<Float32Array>() {
+                (
+                    1,
+                    values.values().iter().map(|&v| f64::from(v)).collect(),
+                    Vec::new(),
+                )
+            } else if let Some(values) = any.downcast_ref::<Int64Array>() {
+                (1, Vec::new(), values.values().to_vec())
+            } else {
+                continue;
+            };
+            let entry = columns
+                .entry(field.name().clone())
+                .or_insert_with(|| Column {
+                    width,
+                    floats: Vec::new(),
+                    ints: Vec::new(),
+                });
+            entry.floats.extend(floats);
+            entry.ints.extend(ints);
+        }
+    }
+
+    let mut builder = FileBuilder::new();
+    builder.set_attr("source", AttrValue::String("episode".into()));
+    builder.set_attr("dataset", AttrValue::String(dataset.into()));
+    builder.set_attr("episode_index", AttrValue::I64(episode as i64));
+    if let Some(fps) = info.get("fps").and_then(Value::as_f64) {
+        builder.set_attr("fps", AttrValue::F64(fps));
+    }
+    if let Some(task) = episode_task(dir, episode) {
+        builder.set_attr("task", AttrValue::String(task));
+    }
+    for (name, column) in &columns {
+        let width = column.width.max(1);
+        let dataset = builder.create_dataset(name);
+        if column.ints.is_empty() {
+            dataset.with_f64_data(&column.floats);
+        } else {
+            dataset.with_i64_data(&column.ints);
+        }
+        if width > 1 {
+            let rows = (column.floats.len() / width) as u64;
+            dataset.with_shape(&[rows, width as u64]);
+        }
+        dataset.with_deflate(4);
+        if name == "timestamp" {
+            dataset.set_attr("units", AttrValue::String("s".into()));
+        }
+        let names = info
+            .pointer(&format!("/features/{}/names", name.replace('/', "~1")))
+            .and_then(Value::as_array)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| name.as_str().map(str::to_owned))
+                    .collect::<Vec<_>>()
+            });
+        if let Some(names) = names.filter(|names| names.len() == width) {
+            dataset.set_attr("names", AttrValue::StringArray(names));
+        }
+    }
+    Ok(builder)
+}
+
+fn episode_task(dir: &Path, episode: usize) -> Option<String> {
+    let episodes = std::fs::read_to_string(dir.join("meta/episodes.jsonl")).ok()?;
+    episodes
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|entry| entry.get("episode_index").and_then(Value::as_u64) == Some(episode as u64))
+        .and_then(|entry| entry.get("tasks")?.get(0)?.as_str().map(str::to_owned))
+}
+
+/// Timestamps and flattened numeric fields of one channel.
+#[derive(Default)]
+struct Signals {
+    timestamps: Vec<i64>,
+    values: BTreeMap<String, Vec<f64>>,
+}
+
+impl Signals {
+    fn push(&mut self, timestamp_ns: u64, message: &Value) {
+        let row = self.timestamps.len();
+        self.timestamps.push(timestamp_ns as i64);
+        for (path, value) in telemetry::numeric_leaves(message) {
+            let column = self
+                .values
+                .entry(path)
+                .or_insert_with(|| vec![f64::NAN; row]);
+            column.push(value);
+        }
+        for column in self.values.values_mut() {
+            column.resize(row + 1, f64::NAN);
+        }
+    }
+}
+
+fn export_bag(
+    files: &[PathBuf],
+    range: Range<u64>,
+    channels: Option<&[String]>,
+    units: &HashMap<String, String>,
+    format: &FormatPreferences,
+    progress: &impl Fn(f64),
+) -> Result<FileBuilder> {
+    let maps = files
+        .iter()
+        .map(|path| {
+            let file = File::open(path)?;
+            // SAFETY: bags are not modified once their writer finished them.
+            Ok(unsafe { Mmap::map(&file)? })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let total: u64 = maps
+        .iter()
+        .filter_map(|map| mcap::Summary::read(map).ok().flatten()?.stats)
+        .map(|stats| stats.message_count)
+        .sum();
+
+    let mut signals: BTreeMap<String, Signals> = BTreeMap::new();
+    let mut seen = 0u64;
+    for map in &maps {
+        // Bags cut short by a crash lack the trailing magic but are readable.
+        let options = mcap::read::Options::IgnoreEndMagic.into();
+        for message in mcap::MessageStream::new_with_options(map, options)? {
+            let message = message?;
+            seen += 1;
+            if seen.is_multiple_of(PROGRESS_EVERY) && total > 0 {
+                progress((seen as f64 / total as f64).min(0.99));
+            }
+            let topic = &message.channel.topic;
+            if !range.contains(&message.log_time)
+                || channels.is_some_and(|channels| !channels.contains(topic))
+            {
+                continue;
+            }
+            let Ok(value) = serde_json::from_slice::<Value>(&message.data) else {
+                continue;
+            };
+            signals
+                .entry(topic.clone())
+                .or_default()
+                .push(message.log_time, &value);
+        }
+    }
+
+    let mut builder = FileBuilder::new();
+    builder.set_attr("source", AttrValue::String("bag".into()));
+    builder.set_attr(
+        "files",
+        AttrValue::StringArray(files.iter().map(|f| f.display().to_string()).collect()),
+    );
+    for (channel, signals) in signals {
+        let mut group = builder.create_group(&channel);
+        group
+            .create_dataset("timestamp_ns")
+            .with_i64_data(&signals.timestamps)
+            .with_deflate(4)
+            .set_attr("units", AttrValue::String("ns".into()))
+            .set_attr("epoch", AttrValue::String("1970-01-01T00:00:00Z".into()));
+        for (path, values) in &signals.values {
+            let unit = (channel == "weld-telemetry")
+                .then(|| path.strip_prefix("values."))
+                .flatten()
+                .and_then(|register| units.get(register));
+            let shown = unit.map(|unit| format.to_display(1.0, unit).1);
+            let dataset = group.create_dataset(path);
+            match (unit, shown) {
+                (Some(unit), Some(shown)) if shown != unit => {
+                    let converted: Vec<f64> = values
+                        .iter()
+                        .map(|&value| format.to_display(value, unit).0)
+                        .collect();
+                    dataset
+                        .with_f64_data(&converted)
+                        .with_deflate(4)
+                        .set_attr("units", AttrValue::String(shown.into()))
+                        .set_attr("source_units", AttrValue::String(unit.clone()));
+                }
+                _ => {
+                    dataset.with_f64_data(values).with_deflate(4);
+                    if let Some(unit) = unit {
+                        dataset.set_attr("units", AttrValue::String(unit.clone()));
+                    }
+                }
+            }
+        }
+        builder.add_group(group.finish());
+    }
+    Ok(builder)
+}
+
+/// Units of the configured weld registers, by signal name.
+fn weld_units(app: &AppHandle) -> HashMap<String, String> {
+    app.state::<SettingsStore>()
+        .get()
+        .weld
+        .registers
+        .into_iter()
+        .filter(|register| !register.unit.is_empty())
+        .map(|register| (register.name, register.unit))
+        .collect()
+}
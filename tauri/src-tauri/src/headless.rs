@@ -0,0 +1,282 @@
+//! `--headless` runs for CI and gateways: the supervised backend and the
+//! shell's native services without any window, tray or splash.
+//!
+//! Instead of the webview, a small local admin API controls the shell. Every
+//! endpoint answers JSON; failures carry `{"error": "<message>"}`.
+//!
+//! | Request | Action |
+//! | --- | --- |
+//! | `GET /status` | backend status, recording dataset and robot status |
+//! | `GET /settings` | the current settings |
+//! | `POST /backend/restart` | restarts the backend |
+//! | `POST /recording/start` | starts an episode; body as `start_recording` |
+//! | `POST /recording/stop` | saves the episode |
+//! | `POST /recording/discard` | drops the episode |
+//! | `POST /bag/start` | opens a bag; optional body `{"name": ...}` |
+//! | `POST /bag/stop` | closes the bag |
+//! | `POST /estop` | emergency stop of the robot |
+//! | `POST /quit` | stops the backend and exits |
+//!
+//! It listens on `admin.sock` in the app data directory (Unix only, mode
+//! 0600) and on `127.0.0.1:<headless.adminPort>`. TCP requests need
+//! `Authorization: Bearer <token>`, with the token generated at startup
+//! into `admin-token` in the same directory. On Linux the webview toolkit still
+//! needs a display, so CI runs under Xvfb even though nothing is drawn.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::backend_tls;
+use crate::bag::{self, Bag};
+use crate::daihen_fd::{self, FdController, RobotStatus};
+use crate::error::{Error, Result};
+use crate::estop;
+use crate::recording::{self, Recorder, RecordingRequest};
+use crate::settings::SettingsStore;
+use crate::sidecar::{self, BackendStatus, SidecarState};
+
+pub const FLAG: &str = "--headless";
+const TOKEN_FILE: &str = "admin-token";
+#[cfg(unix)]
+const SOCKET_FILE: &str = "admin.sock";
+/// Larger requests are refused; no endpoint takes more than a small JSON body.
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// Whether the shell was started with [`FLAG`].
+pub fn requested() -> bool {
+    std::env::args().skip(1).any(|arg| arg == FLAG)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    backend: BackendStatus,
+    /// Dataset an episode is being recorded into.
+    recording: Option<String>,
+    robot: Option<RobotStatus>,
+}
+
+#[derive(Default, Deserialize)]
+struct BagRequest {
+    name: Option<String>,
+}
+
+/// Starts the admin API listeners.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().headless;
+    let dir = app.path().app_data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+
+    if let Some(port) = settings.admin_port {
+        let mut token = [0u8; 32];
+        getrandom::fill(&mut token).map_err(|err| Error::Invalid(format!("random: {err}")))?;
+        let token = hex::encode(token);
+        backend_tls::write_private(&dir.join(TOKEN_FILE), token.as_bytes())?;
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+                Ok(listener) => listener,
+                Err(err) => return tracing::error!(port, "admin API not started: {err}"),
+            };
+            tracing::info!(port, "admin API listening");
+            while let Ok((stream, _)) = listener.accept().await {
+                let app = app.clone();
+                let token = token.clone();
+                tauri::async_runtime::spawn(async move { serve(&app, stream, Some(&token)).await });
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    if settings.admin_socket {
+        let path = dir.join(SOCKET_FILE);
+        // Left behind by a previous run that did not exit cleanly.
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        tracing::info!(path = %path.display(), "admin API listening");
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move { serve(&app, stream, None).await });
+            }
+        });
+    }
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Answers one request; connections are not kept alive.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+    app: &AppHandle,
+    mut stream: S,
+    token: Option<&str>,
+) {
+    let (status, body) = match read_request(&mut stream).await {
+        Ok(request) => {
+            let authorized = token.is_none_or(|token| {
+                request.authorization.as_deref() == Some(&format!("Bearer {token}"))
+            });
+            if authorized {
+                match handle(app, &request).await {
+                    Ok(body) => (200, body),
+                    Err(err) => (status_code(&err), error_body(&err.to_string())),
+                }
+            } else {
+                (401, error_body("missing or wrong admin token"))
+            }
+        }
+        Err(err) => (400, error_body(&err.to_string())),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_len = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST {
+            return Err(Error::Invalid("request too large".into()));
+        }
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            return Err(Error::Invalid("incomplete request".into()));
+        }
+        buffer.extend_from_slice(&chunk[..len]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_len]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| Error::Invalid("Content-Length".into()))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_owned());
+        }
+    }
+    if head_len + content_length > MAX_REQUEST {
+        return Err(Error::Invalid("request too large".into()));
+    }
+    let mut body = buffer.split_off(head_len);
+    while body.len() < content_length {
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            return Err(Error::Invalid("incomplete body".into()));
+        }
+        body.extend_from_slice(&chunk[..len]);
+    }
+    body.truncate(content_length);
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+async fn handle(app: &AppHandle, request: &Request) -> Result<String> {
+    let body = || -> Result<serde_json::Value> {
+        if request.body.is_empty() {
+            Ok(serde_json::Value::Null)
+        } else {
+            Ok(serde_json::from_slice(&request.body)?)
+        }
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => json(&Status {
+            backend: app.state::<SidecarState>().status(),
+            recording: app.state::<Recorder>().recording_dataset(),
+            robot: daihen_fd::robot_status(app.state::<FdController>()),
+        }),
+        ("GET", "/settings") => json(&app.state::<SettingsStore>().get()),
+        ("POST", "/backend/restart") => {
+            sidecar::restart(app).await;
+            json(&())
+        }
+        ("POST", "/recording/start") => {
+            let request: RecordingRequest = serde_json::from_value(body()?)?;
+            let info =
+                recording::start_recording(app.clone(), app.state::<Recorder>(), request).await?;
+            json(&info)
+        }
+        ("POST", "/recording/stop") => {
+            json(&recording::stop_recording(app.clone(), app.state::<Recorder>()).await?)
+        }
+        ("POST", "/recording/discard") => {
+            recording::discard_episode(app.clone(), app.state::<Recorder>()).await?;
+            json(&())
+        }
+        ("POST", "/bag/start") => {
+            let request: Option<BagRequest> = serde_json::from_value(body()?)?;
+            let name = request.unwrap_or_default().name;
+            json(&bag::start_bag(app.clone(), app.state::<Bag>(), name)?)
+        }
+        ("POST", "/bag/stop") => json(&bag::stop_bag(app.clone(), app.state::<Bag>())?),
+        ("POST", "/estop") => {
+            estop::trigger(app, "admin-api", "POST /estop".into());
+            json(&())
+        }
+        ("POST", "/quit") => {
+            // Goes through `RunEvent::ExitRequested`, which stops the sidecar first.
+            app.exit(0);
+            json(&())
+        }
+        (_, path) => Err(Error::NotFound(format!("{} {path}", request.method))),
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+fn status_code(err: &Error) -> u16 {
+    match err {
+        Error::NotFound(_) => 404,
+        Error::Invalid(_) | Error::Json(_) => 400,
+        Error::DeviceBusy(_) => 409,
+        _ => 500,
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
@@ -0,0 +1,752 @@
+//! Hugging Face Hub transfers done by the shell itself, so sharing a
+//! dataset or fetching a policy does not depend on the backend.
+//!
+//! The access token is kept in the keychain as the `hub-token` secret, never
+//! in settings. A token file left by earlier versions in the app data
+//! directory is moved there on first use.
+//!
+//! [`push_dataset_to_hub`] creates the dataset repository if needed and
+//! makes one commit of the whole dataset directory. The Hub's preupload
+//! endpoint decides which files go through Git LFS; those are uploaded to
+//! the storage URLs the LFS batch API hands out, in parts when the Hub asks
+//! for a multipart transfer, and the commit then only references them by
+//! SHA-256. Objects the Hub already has are not sent again.
+//!
+//! [`pull_model_from_hub`] lists a model revision and downloads every file
+//! into a staging directory, resuming partial files, verifying LFS files
+//! against their SHA-256, and then swaps the directory into the model
+//! directory as `<owner>--<name>`.
+//!
+//! Both report `hub-progress` and finally `hub-finished`.
+
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, LINK};
+use reqwest::{RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::downloads;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::licensing::{self, Feature};
+use crate::recording;
+use crate::secrets;
+use crate::settings::SettingsStore;
+
+const LFS_JSON: &str = "application/vnd.git-lfs+json";
+/// Files per preupload and LFS batch request.
+const BATCH: usize = 256;
+const CHUNK: usize = 1 << 20;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const STAGING_DIR: &str = ".downloads";
+const TOKEN_SECRET: &str = "hub-token";
+/// Token file used before the keychain, under the app data directory.
+const LEGACY_TOKEN_FILE: &str = "hub-token";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HubProgress {
+    id: u32,
+    operation: &'static str,
+    repo_id: String,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HubFinished {
+    id: u32,
+    operation: &'static str,
+    repo_id: String,
+    /// Installed model directory, for pulls.
+    path: Option<PathBuf>,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Hub {
+    next_id: AtomicU32,
+}
+
+/// Stores (or with `None` forgets) the access token and returns the user
+/// it belongs to.
+#[tauri::command]
+pub async fn set_hub_token(app: AppHandle, token: Option<String>) -> Result<Option<String>> {
+    let Some(token) = token.map(|token| token.trim().to_owned()) else {
+        secrets::delete(TOKEN_SECRET)?;
+        return Ok(None);
+    };
+    let client = HubClient::new(&app, Some(token.clone()))?;
+    let user = client.whoami().await?;
+    secrets::set(TOKEN_SECRET, &token)?;
+    tracing::info!(%user, "hub token stored");
+    Ok(Some(user))
+}
+
+/// User of the stored token, if one is stored.
+#[tauri::command]
+pub async fn hub_whoami(app: AppHandle) -> Result<Option<String>> {
+    let Some(token) = read_token(&app)? else {
+        return Ok(None);
+    };
+    Ok(Some(HubClient::new(&app, Some(token))?.whoami().await?))
+}
+
+/// Uploads a recorded dataset to the dataset repository `repo_id`.
+#[tauri::command]
+pub fn push_dataset_to_hub(
+    app: AppHandle,
+    hub: State<'_, Hub>,
+    dataset: String,
+    repo_id: String,
+    private: bool,
+) -> Result<u32> {
+    licensing::require(&app, Feature::CloudSync)?;
+    valid_repo_id(&repo_id)?;
+    let dir = recording::dataset_dir(&app, &dataset)?;
+    if !dir.join("meta/info.json").is_file() {
+        return Err(Error::NotFound(format!("dataset {dataset}")));
+    }
+    let token = read_token(&app)?
+        .ok_or_else(|| Error::Invalid("no Hugging Face token is stored".into()))?;
+    let client = HubClient::new(&app, Some(token))?;
+    let id = hub.next_id.fetch_add(1, Ordering::Relaxed);
+    tauri::async_runtime::spawn(async move {
+        let progress = Progress::new(&app, id, "push", &repo_id);
+        let result = push(&client, &progress, &dir, &repo_id, private).await;
+        progress.finish(result.map(|()| None));
+    });
+    Ok(id)
+}
+
+/// Downloads model `repo_id` at `revision` (default `main`) into the model
+/// directory.
+#[tauri::command]
+pub fn pull_model_from_hub(
+    app: AppHandle,
+    hub: State<'_, Hub>,
+    repo_id: String,
+    revision: Option<String>,
+) -> Result<u32> {
+    licensing::require(&app, Feature::CloudSync)?;
+    valid_repo_id(&repo_id)?;
+    let models = downloads::models_dir(&app)?;
+    let client = HubClient::new(&app, read_token(&app)?)?;
+    let revision = revision.unwrap_or_else(|| "main".into());
+    let id = hub.next_id.fetch_add(1, Ordering::Relaxed);
+    tauri::async_runtime::spawn(async move {
+        let progress = Progress::new(&app, id, "pull", &repo_id);
+        let result = pull(&client, &progress, &models, &repo_id, &revision).await;
+        progress.finish(result.map(Some));
+    });
+    Ok(id)
+}
+
+fn read_token(app: &AppHandle) -> Result<Option<String>> {
+    if let Some(token) = secrets::get(TOKEN_SECRET)? {
+        return Ok(Some(token));
+    }
+    let legacy = app.path().app_data_dir()?.join(LEGACY_TOKEN_FILE);
+    let token = match std::fs::read_to_string(&legacy) {
+        Ok(token) => token.trim().to_owned(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if !token.is_empty() {
+        secrets::set(TOKEN_SECRET, &token)?;
+        tracing::info!("moved hub token into the keychain");
+    }
+    std::fs::remove_file(&legacy)?;
+    Ok(Some(token).filter(|token| !token.is_empty()))
+}
+
+/// `name` or `owner/name`.
+fn valid_repo_id(repo_id: &str) -> Result<()> {
+    let parts: Vec<&str> = repo_id.split('/').collect();
+    let valid = parts.len() <= 2
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with(['.', '-'])
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Invalid(format!("hub repository {repo_id}")))
+    }
+}
+
+/// Bytes moved so far, reported at most every [`PROGRESS_INTERVAL`].
+struct Progress {
+    app: AppHandle,
+    id: u32,
+    operation: &'static str,
+    repo_id: String,
+    done: Arc<AtomicU64>,
+    total: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Progress {
+    fn new(app: &AppHandle, id: u32, operation: &'static str, repo_id: &str) -> Self {
+        let progress = Self {
+            app: app.clone(),
+            id,
+            operation,
+            repo_id: repo_id.to_owned(),
+            done: Arc::default(),
+            total: Arc::default(),
+            stop: Arc::default(),
+        };
+        let (app, done, total, stop) = (
+            app.clone(),
+            progress.done.clone(),
+            progress.total.clone(),
+            progress.stop.clone(),
+        );
+        let payload = progress.payload();
+        tauri::async_runtime::spawn(async move {
+            let mut last = u64::MAX;
+            while !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+                let bytes_done = done.load(Ordering::Relaxed);
+                if bytes_done != last {
+                    last = bytes_done;
+                    let progress = HubProgress {
+                        bytes_done,
+                        bytes_total: total.load(Ordering::Relaxed),
+                        ..payload.clone()
+                    };
+                    let _ = app.emit("hub-progress", progress);
+                }
+            }
+        });
+        progress
+    }
+
+    fn payload(&self) -> HubProgress {
+        HubProgress {
+            id: self.id,
+            operation: self.operation,
+            repo_id: self.repo_id.clone(),
+            bytes_done: self.done.load(Ordering::Relaxed),
+            bytes_total: self.total.load(Ordering::Relaxed),
+        }
+    }
+
+    fn finish(self, result: Result<Option<PathBuf>>) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.app.emit("hub-progress", self.payload());
+        let (path, error) = match result {
+            Ok(path) => (path, None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        match &error {
+            Some(error) => {
+                tracing::warn!(repo = %self.repo_id, "hub {} failed: {error}", self.operation)
+            }
+            None => tracing::info!(repo = %self.repo_id, "hub {} finished", self.operation),
+        }
+        let finished = HubFinished {
+            id: self.id,
+            operation: self.operation,
+            repo_id: self.repo_id,
+            path,
+            error,
+        };
+        let _ = self.app.emit("hub-finished", finished);
+    }
+}
+
+struct HubClient {
+    http: reqwest::Client,
+    endpoint: String,
+    token: Option<String>,
+}
+
+impl HubClient {
+    fn new(app: &AppHandle, token: Option<String>) -> Result<Self> {
+        let endpoint = app.state::<SettingsStore>().get().hub.endpoint;
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(120))
+            .build()?;
+        Ok(Self {
+            http,
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            token,
+        })
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.authorized(self.http.get(url))
+    }
+
+    fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.authorized(self.http.post(url))
+    }
+
+    async fn whoami(&self) -> Result<String> {
+        let url = format!("{}/api/whoami-v2", self.endpoint);
+        let user: Value = checked(self.get(url).send().await?).await?.json().await?;
+        user.get("name")
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .ok_or_else(|| Error::Hub("whoami answered without a name".into()))
+    }
+}
+
+/// Turns non-2xx responses into errors carrying the Hub's message.
+async fn checked(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|body| body.get("error")?.as_str().map(str::to_owned))
+        .unwrap_or(body);
+    Err(Error::Hub(format!("{status}: {message}")))
+}
+
+struct LocalFile {
+    path: PathBuf,
+    /// Path within the repository.
+    repo_path: String,
+    size: u64,
+    lfs: bool,
+    sha256: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PreuploadFile {
+    path: String,
+    upload_mode: String,
+    #[serde(default)]
+    should_ignore: bool,
+}
+
+#[derive(Deserialize)]
+struct LfsAction {
+    href: String,
+    #[serde(default)]
+    header: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct LfsObject {
+    oid: String,
+    #[serde(default)]
+    actions: BTreeMap<String, LfsAction>,
+    error: Option<Value>,
+}
+
+async fn push(
+    client: &HubClient,
+    progress: &Progress,
+    dir: &Path,
+    repo_id: &str,
+    private: bool,
+) -> Result<()> {
+    create_dataset_repo(client, repo_id, private).await?;
+
+    let mut files = Vec::new();
+    for path in fsutil::walk_files(dir)? {
+        let relative = path.strip_prefix(dir).unwrap_or(&path);
+        let parts: Vec<String> = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        if parts.iter().any(|part| part.starts_with('.')) {
+            continue;
+        }
+        files.push(LocalFile {
+            size: std::fs::metadata(&path)?.len(),
+            path,
+            repo_path: parts.join("/"),
+            lfs: false,
+            sha256: String::new(),
+        });
+    }
+    progress
+        .total
+        .store(files.iter().map(|file| file.size).sum(), Ordering::Relaxed);
+
+    let api = format!("{}/api/datasets/{repo_id}", client.endpoint);
+    let mut ignored = Vec::new();
+    for batch in files.chunks_mut(BATCH) {
+        let mut entries = Vec::new();
+        for file in batch.iter() {
+            let mut sample = vec![0; 512];
+            let mut handle = tokio::fs::File::open(&file.path).await?;
+            let n = handle.read(&mut sample).await?;
+            sample.truncate(n);
+            entries.push(json!({
+                "path": file.repo_path,
+                "size": file.size,
+                "sample": BASE64.encode(&sample),
+            }));
+        }
+        let request = client
+            .post(format!("{api}/preupload/main"))
+            .json(&json!({ "files": entries }));
+        let answer: Value = checked(request.send().await?).await?.json().await?;
+        let modes: Vec<PreuploadFile> =
+            serde_json::from_value(answer.get("files").cloned().unwrap_or_default())?;
+        for mode in modes {
+            if let Some(file) = batch.iter_mut().find(|file| file.repo_path == mode.path) {
+                file.lfs = mode.upload_mode == "lfs";
+                if mode.should_ignore {
+                    ignored.push(mode.path);
+                }
+            }
+        }
+    }
+    files.retain(|file| !ignored.contains(&file.repo_path));
+
+    for file in files.iter_mut().filter(|file| file.lfs) {
+        file.sha256 = downloads::file_sha256(&file.path).await?;
+    }
+    let lfs: Vec<&LocalFile> = files.iter().filter(|file| file.lfs).collect();
+    for batch in lfs.chunks(BATCH) {
+        upload_lfs(client, progress, repo_id, batch).await?;
+    }
+
+    let mut commit = Vec::new();
+    let header = json!({
+        "key": "header",
+        "value": { "summary": "Upload dataset from Percus", "description": "" },
+    });
+    serde_json::to_writer(&mut commit, &header)?;
+    commit.push(b'\n');
+    for file in &files {
+        let line = if file.lfs {
+            json!({
+                "key": "lfsFile",
+                "value": {
+                    "path": file.repo_path,
+                    "algo": "sha256",
+                    "oid": file.sha256,
+                    "size": file.size,
+                },
+            })
+        } else {
+            let content = tokio::fs::read(&file.path).await?;
+            progress.done.fetch_add(file.size, Ordering::Relaxed);
+            json!({
+                "key": "file",
+                "value": {
+                    "path": file.repo_path,
+                    "content": BASE64.encode(content),
+                    "encoding": "base64",
+                },
+            })
+        };
+        serde_json::to_writer(&mut commit, &line)?;
+        commit.push(b'\n');
+    }
+    let request = client
+        .post(format!("{api}/commit/main"))
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(commit);
+    checked(request.send().await?).await?;
+    Ok(())
+}
+
+async fn create_dataset_repo(client: &HubClient, repo_id: &str, private: bool) -> Result<()> {
+    let (organization, name) = match repo_id.split_once('/') {
+        Some((organization, name)) => (Some(organization), name),
+        None => (None, repo_id),
+    };
+    let request = client
+        .post(format!("{}/api/repos/create", client.endpoint))
+        .json(&json!({
+            "type": "dataset",
+            "name": name,
+            "organization": organization,
+            "private": private,
+        }));
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        return Ok(());
+    }
+    checked(response).await?;
+    Ok(())
+}
+
+async fn upload_lfs(
+    client: &HubClient,
+    progress: &Progress,
+    repo_id: &str,
+    files: &[&LocalFile],
+) -> Result<()> {
+    let objects: Vec<Value> = files
+        .iter()
+        .map(|file| json!({ "oid": file.sha256, "size": file.size }))
+        .collect();
+    let request = client
+        .post(format!(
+            "{}/datasets/{repo_id}.git/info/lfs/objects/batch",
+            client.endpoint
+        ))
+        .header(ACCEPT, LFS_JSON)
+        .header(CONTENT_TYPE, LFS_JSON)
+        .json(&json!({
+            "operation": "upload",
+            "transfers": ["basic", "multipart"],
+            "objects": objects,
+            "hash_algo": "sha256",
+            "ref": { "name": "refs/heads/main" },
+        }));
+    let answer: Value = checked(request.send().await?).await?.json().await?;
+    let objects: Vec<LfsObject> =
+        serde_json::from_value(answer.get("objects").cloned().unwrap_or_default())?;
+
+    for object in objects {
+        let Some(file) = files.iter().find(|file| file.sha256 == object.oid) else {
+            continue;
+        };
+        if let Some(error) = object.error {
+            return Err(Error::Hub(format!("lfs {}: {error}", file.repo_path)));
+        }
+        let Some(upload) = object.actions.get("upload") else {
+            // The Hub has this object already.
+            progress.done.fetch_add(file.size, Ordering::Relaxed);
+            continue;
+        };
+        match upload.header.get("chunk_size").and_then(header_u64) {
+            Some(chunk_size) => {
+                upload_multipart(client, progress, file, upload, chunk_size).await?
+            }
+            None => {
+                let mut request = client.http.put(&upload.href);
+                for (name, value) in &upload.header {
+                    if let Some(value) = value.as_str() {
+                        request = request.header(name, value);
+                    }
+                }
+                let body = file_body(&file.path, 0, file.size, progress.done.clone()).await?;
+                let request = request.header(CONTENT_LENGTH, file.size).body(body);
+                checked(request.send().await?).await?;
+            }
+        }
+        if let Some(verify) = object.actions.get("verify") {
+            let mut request = client
+                .post(&verify.href)
+                .header(ACCEPT, LFS_JSON)
+                .header(CONTENT_TYPE, LFS_JSON);
+            for (name, value) in &verify.header {
+                if let Some(value) = value.as_str() {
+                    request = request.header(name, value);
+                }
+            }
+            let request = request.json(&json!({ "oid": file.sha256, "size": file.size }));
+            checked(request.send().await?).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Multipart LFS transfer: the action's numbered headers are presigned URLs
+/// for consecutive `chunk_size` parts, completed by posting their ETags.
+async fn upload_multipart(
+    client: &HubClient,
+    progress: &Progress,
+    file: &LocalFile,
+    upload: &LfsAction,
+    chunk_size: u64,
+) -> Result<()> {
+    let mut urls: Vec<(u64, &str)> = upload
+        .header
+        .iter()
+        .filter_map(|(name, value)| Some((name.parse().ok()?, value.as_str()?)))
+        .collect();
+    urls.sort_by_key(|(number, _)| *number);
+    let mut parts = Vec::new();
+    for (number, url) in urls {
+        let offset = (number - 1) * chunk_size;
+        let len = chunk_size.min(file.size.saturating_sub(offset));
+        let body = file_body(&file.path, offset, len, progress.done.clone()).await?;
+        let request = client.http.put(url).header(CONTENT_LENGTH, len).body(body);
+        let response = checked(request.send().await?).await?;
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|etag| etag.to_str().ok())
+            .ok_or_else(|| Error::Hub(format!("no ETag for part {number} of {}", file.repo_path)))?
+            .to_owned();
+        parts.push(json!({ "partNumber": number, "etag": etag }));
+    }
+    let request = client
+        .http
+        .post(&upload.href)
+        .header(ACCEPT, LFS_JSON)
+        .header(CONTENT_TYPE, LFS_JSON)
+        .json(&json!({ "oid": file.sha256, "parts": parts }));
+    checked(request.send().await?).await?;
+    Ok(())
+}
+
+fn header_u64(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+}
+
+/// `len` bytes of `path` from `offset` as a streamed body, counting the
+/// bytes sent into `sent`.
+async fn file_body(
+    path: &Path,
+    offset: u64,
+    len: u64,
+    sent: Arc<AtomicU64>,
+) -> Result<reqwest::Body> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let stream =
+        futures_util::stream::unfold((file.take(len), sent), |(mut reader, sent)| async move {
+            let mut buffer = vec![0; CHUNK];
+            match reader.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    sent.fetch_add(n as u64, Ordering::Relaxed);
+                    Some((Ok(Bytes::from(buffer)), (reader, sent)))
+                }
+                Err(err) => Some((Err(err), (reader, sent))),
+            }
+        });
+    Ok(reqwest::Body::wrap_stream(stream))
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+    #[serde(default)]
+    size: u64,
+    lfs: Option<TreeLfs>,
+}
+
+#[derive(Deserialize)]
+struct TreeLfs {
+    oid: String,
+}
+
+async fn pull(
+    client: &HubClient,
+    progress: &Progress,
+    models: &Path,
+    repo_id: &str,
+    revision: &str,
+) -> Result<PathBuf> {
+    let mut url = Url::parse(&format!("{}/api/models/{repo_id}/tree", client.endpoint))
+        .map_err(|err| Error::Invalid(format!("hub endpoint: {err}")))?;
+    url.path_segments_mut()
+        .map_err(|()| Error::Invalid("hub endpoint".into()))?
+        .push(revision);
+    url.set_query(Some("recursive=true"));
+    let mut files = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next.take() {
+        let response = checked(client.get(url).send().await?).await?;
+        next = next_page(&response);
+        let page: Vec<TreeEntry> = response.json().await?;
+        files.extend(page.into_iter().filter(|entry| entry.kind == "file"));
+    }
+    progress
+        .total
+        .store(files.iter().map(|file| file.size).sum(), Ordering::Relaxed);
+
+    let name = repo_id.replace('/', "--");
+    let staging = models
+        .join(STAGING_DIR)
+        .join(format!("hub-{name}-{}", revision.replace('/', "--")));
+    let cancelled = AtomicBool::new(false);
+    for file in &files {
+        if !file
+            .path
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+        {
+            return Err(Error::Hub(format!(
+                "unsafe path {} in {repo_id}",
+                file.path
+            )));
+        }
+        let target = staging.join(&file.path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut url = Url::parse(&format!("{}/{repo_id}/resolve", client.endpoint))
+            .map_err(|err| Error::Invalid(format!("hub endpoint: {err}")))?;
+        url.path_segments_mut()
+            .map_err(|()| Error::Invalid("hub endpoint".into()))?
+            .push(revision)
+            .extend(file.path.split('/'));
+        let before = progress.done.load(Ordering::Relaxed);
+        let done = progress.done.clone();
+        downloads::fetch(client.get(url), &target, &cancelled, |downloaded, _| {
+            done.store(before + downloaded, Ordering::Relaxed);
+        })
+        .await?;
+        progress.done.store(before + file.size, Ordering::Relaxed);
+
+        let size = std::fs::metadata(&target)?.len();
+        let matches = match &file.lfs {
+            Some(lfs) => downloads::file_sha256(&target).await? == lfs.oid,
+            None => size == file.size,
+        };
+        if !matches {
+            let _ = std::fs::remove_file(&target);
+            return Err(Error::Hub(format!("{} failed verification", file.path)));
+        }
+    }
+
+    let target = models.join(&name);
+    let replaced = models.join(STAGING_DIR).join(format!("{name}.replaced"));
+    if target.exists() {
+        let _ = std::fs::remove_dir_all(&replaced);
+        std::fs::rename(&target, &replaced)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+    std::fs::rename(&staging, &target)?;
+    let _ = std::fs::remove_dir_all(&replaced);
+    Ok(target)
+}
+
+/// `rel="next"` URL of a paginated listing.
+fn next_page(response: &Response) -> Option<Url> {
+    let link = response.headers().get(LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params
+            .contains("rel=\"next\"")
+            .then(|| url.trim().trim_start_matches('<').trim_end_matches('>'))
+            .and_then(|url| Url::parse(url).ok())
+    })
+}
@@ -0,0 +1,185 @@
+//! USB HID emergency-stop buttons on the operator desks.
+//!
+//! An `estop-buttons` thread opens every plugged-in device listed in
+//! `estopButtons.devices`, rescanning every [`SCAN_INTERVAL`] so buttons can
+//! be plugged and unplugged at any time. Reports are polled every
+//! [`POLL_INTERVAL`]; the button counts as pressed when
+//! `report[reportByte] & pressedMask` is non-zero (or zero with `activeLow`).
+//! Pressing a button, or unplugging one with `stopOnDisconnect`, goes
+//! through [`estop::trigger`]. Device changes are emitted as
+//! `estop-device-status` with the full list, as returned by
+//! [`get_estop_device_status`].
+
+use std::ffi::CString;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use hidapi::{HidApi, HidDevice};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::Result;
+use crate::estop;
+use crate::settings::{EstopButtonSettings, SettingsStore};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EstopDeviceStatus {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub path: String,
+    pub pressed: bool,
+}
+
+/// Buttons currently plugged in.
+#[derive(Default)]
+pub struct EstopButtons(StdMutex<Vec<EstopDeviceStatus>>);
+
+struct Button {
+    device: HidDevice,
+    path: CString,
+    status: EstopDeviceStatus,
+}
+
+/// Starts the monitoring thread.
+pub fn init(app: &AppHandle) -> Result<()> {
+    app.manage(EstopButtons::default());
+    let app = app.clone();
+    std::thread::Builder::new()
+        .name("estop-buttons".into())
+        .spawn(move || monitor(&app))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_estop_device_status(buttons: State<'_, EstopButtons>) -> Vec<EstopDeviceStatus> {
+    buttons.0.lock().unwrap().clone()
+}
+
+fn monitor(app: &AppHandle) {
+    let mut api = match HidApi::new() {
+        Ok(api) => api,
+        Err(err) => {
+            tracing::error!("HID unavailable; e-stop buttons disabled: {err}");
+            return;
+        }
+    };
+    let mut config = EstopButtonSettings::default();
+    let mut buttons: Vec<Button> = Vec::new();
+    let mut last_scan: Option<Instant> = None;
+    let mut report = [0u8; 64];
+    loop {
+        let mut changed = false;
+        if last_scan.is_none_or(|scanned| scanned.elapsed() >= SCAN_INTERVAL) {
+            last_scan = Some(Instant::now());
+            config = app.state::<SettingsStore>().get().estop_buttons;
+            changed |= scan(&mut api, &config, &mut buttons);
+        }
+
+        let mut index = 0;
+        while index < buttons.len() {
+            let button = &mut buttons[index];
+            match button.device.read(&mut report) {
+                Ok(0) => {}
+                Ok(len) => {
+                    let pressed = pressed(&report[..len], &config, button.status.pressed);
+                    if pressed != button.status.pressed {
+                        button.status.pressed = pressed;
+                        changed = true;
+                        if pressed {
+                            estop::trigger(app, "button", button.status.name.clone());
+                        }
+                    }
+                }
+                Err(err) => {
+                    let button = buttons.remove(index);
+                    tracing::warn!(name = %button.status.name, "e-stop button disconnected: {err}");
+                    if config.stop_on_disconnect {
+                        estop::trigger(app, "button", button.status.name);
+                    }
+                    changed = true;
+                    continue;
+                }
+            }
+            index += 1;
+        }
+
+        if changed {
+            let statuses: Vec<EstopDeviceStatus> =
+                buttons.iter().map(|button| button.status.clone()).collect();
+            *app.state::<EstopButtons>().0.lock().unwrap() = statuses.clone();
+            let _ = app.emit("estop-device-status", statuses);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Opens newly plugged-in buttons and closes those no longer configured;
+/// returns whether the set changed.
+fn scan(api: &mut HidApi, config: &EstopButtonSettings, buttons: &mut Vec<Button>) -> bool {
+    let configured = |vendor_id: u16, product_id: u16| {
+        config.enabled
+            && config
+                .devices
+                .iter()
+                .any(|id| id.vendor_id == vendor_id && id.product_id == product_id)
+    };
+    let before = buttons.len();
+    buttons.retain(|button| configured(button.status.vendor_id, button.status.product_id));
+    let mut changed = buttons.len() != before;
+    if !config.enabled || config.devices.is_empty() {
+        return changed;
+    }
+    if let Err(err) = api.refresh_devices() {
+        tracing::debug!("HID enumeration failed: {err}");
+        return changed;
+    }
+    for info in api.device_list() {
+        if !configured(info.vendor_id(), info.product_id())
+            || buttons
+                .iter()
+                .any(|button| button.path.as_c_str() == info.path())
+        {
+            continue;
+        }
+        let device = match info.open_device(api) {
+            Ok(device) => device,
+            Err(err) => {
+                tracing::debug!(path = ?info.path(), "cannot open e-stop button: {err}");
+                continue;
+            }
+        };
+        if let Err(err) = device.set_blocking_mode(false) {
+            tracing::debug!(path = ?info.path(), "e-stop button not pollable: {err}");
+            continue;
+        }
+        let name = info.product_string().unwrap_or("E-stop button").to_owned();
+        tracing::info!(%name, "e-stop button connected");
+        buttons.push(Button {
+            device,
+            path: info.path().to_owned(),
+            status: EstopDeviceStatus {
+                name,
+                vendor_id: info.vendor_id(),
+                product_id: info.product_id(),
+                path: info.path().to_string_lossy().into_owned(),
+                pressed: false,
+            },
+        });
+        changed = true;
+    }
+    changed
+}
+
+/// Button state encoded in `report`; reports too short to carry it leave
+/// the state unchanged.
+fn pressed(report: &[u8], config: &EstopButtonSettings, previous: bool) -> bool {
+    match report.get(config.report_byte) {
+        Some(byte) => (byte & config.pressed_mask != 0) != config.active_low,
+        None => previous,
+    }
+}
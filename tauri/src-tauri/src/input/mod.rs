@@ -0,0 +1,54 @@
+//! Teleoperation input devices read natively by the shell.
+
+pub mod estop_button;
+pub mod spacemouse;
+
+use std::net::UdpSocket;
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::settings::InputForwarding;
+use crate::windows;
+
+/// Destination of input samples: webview events or UDP datagrams.
+pub enum Sink {
+    Events(AppHandle),
+    /// Datagrams are also written to the open bag, if any.
+    Udp(UdpSocket, AppHandle),
+}
+
+impl Sink {
+    pub fn new(
+        app: AppHandle,
+        forwarding: InputForwarding,
+        udp_target: &str,
+    ) -> std::io::Result<Self> {
+        Ok(match forwarding {
+            InputForwarding::Events => Sink::Events(app),
+            InputForwarding::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(udp_target)?;
+                Sink::Udp(socket, app)
+            }
+        })
+    }
+
+    /// Delivers one sample; UDP sends are best-effort.
+    pub fn send<T: Serialize>(&self, event: &str, sample: &T) {
+        match self {
+            Sink::Events(app) => windows::emit(app, event, sample),
+            Sink::Udp(socket, app) => {
+                let result = serde_json::to_vec(sample)
+                    .map_err(std::io::Error::from)
+                    .and_then(|datagram| {
+                        crate::bag::record(app, event, &datagram);
+                        socket.send(&datagram)
+                    });
+                if let Err(err) = result {
+                    tracing::debug!("{event} datagram not sent: {err}");
+                }
+            }
+        }
+    }
+}
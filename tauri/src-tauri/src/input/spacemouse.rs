@@ -0,0 +1,221 @@
+//! 3Dconnexion SpaceMouse support over raw HID.
+//!
+//! Once started, a background thread scans for a supported device every
+//! [`SCAN_INTERVAL`], reads its reports while it stays plugged in and goes
+//! back to scanning when it disappears, emitting `spacemouse-connected` and
+//! `spacemouse-disconnected` along the way. Each report yields a calibrated
+//! sample on the `spacemouse-state` stream (or a UDP datagram): axes are
+//! offset by the rest position captured with [`zero_spacemouse`], divided by
+//! `spacemouse.fullScale`, then deadzoned and clamped to `-1.0..=1.0`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hidapi::{HidApi, HidDevice};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::error::Result;
+use crate::input::Sink;
+use crate::settings::{SettingsStore, SpaceMouseSettings};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+const READ_TIMEOUT_MS: i32 = 100;
+/// Logitech-era and current 3Dconnexion vendor ids.
+const VENDOR_IDS: [u16; 2] = [0x046d, 0x256f];
+/// Usage page and usage of a multi-axis controller.
+const GENERIC_DESKTOP: u16 = 0x01;
+const MULTI_AXIS: u16 = 0x08;
+const AXIS_NAMES: [&str; 6] = ["x", "y", "z", "rx", "ry", "rz"];
+
+/// Calibrated state of the device, as emitted or sent over UDP.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceMouseSample {
+    pub seq: u64,
+    pub timestamp_ms: i64,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 3],
+    /// Bit `n` is set while button `n` is held.
+    pub buttons: u32,
+}
+
+#[derive(Clone, Serialize)]
+struct DeviceEvent {
+    name: String,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// Latest raw axis counts.
+    raw: Mutex<[i16; 6]>,
+    /// Raw counts treated as the rest position.
+    offsets: Mutex<[i16; 6]>,
+}
+
+/// Stop flag of the scanning thread plus the calibration state it reads.
+#[derive(Default)]
+pub struct SpaceMouse {
+    stop: Mutex<Option<Arc<AtomicBool>>>,
+    shared: Arc<Shared>,
+}
+
+/// Starts hot-plug scanning with the current `spacemouse` settings,
+/// replacing any running capture.
+#[tauri::command]
+pub fn start_spacemouse(
+    app: AppHandle,
+    spacemouse: State<'_, SpaceMouse>,
+    settings: State<'_, SettingsStore>,
+) -> Result<()> {
+    let config = settings.get().spacemouse;
+    let sink = Sink::new(app.clone(), config.output, &config.udp_target)?;
+    let mut running = spacemouse.stop.lock().unwrap();
+    if let Some(stop) = running.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let (stop, shared) = (stop.clone(), spacemouse.shared.clone());
+        std::thread::Builder::new()
+            .name("spacemouse".into())
+            .spawn(move || scan_loop(app, config, sink, shared, stop))?;
+    }
+    *running = Some(stop);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_spacemouse(spacemouse: State<'_, SpaceMouse>) {
+    if let Some(stop) = spacemouse.stop.lock().unwrap().take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Takes the current raw reading as the rest position, cancelling drift.
+#[tauri::command]
+pub fn zero_spacemouse(spacemouse: State<'_, SpaceMouse>) {
+    let raw = *spacemouse.shared.raw.lock().unwrap();
+    *spacemouse.shared.offsets.lock().unwrap() = raw;
+}
+
+fn scan_loop(
+    app: AppHandle,
+    config: SpaceMouseSettings,
+    sink: Sink,
+    shared: Arc<Shared>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut api = match HidApi::new() {
+        Ok(api) => api,
+        Err(err) => {
+            tracing::error!("HID unavailable; SpaceMouse disabled: {err}");
+            return;
+        }
+    };
+    let mut seq = 0;
+    while !stop.load(Ordering::Relaxed) {
+        if let Some((device, name)) = open_first(&mut api) {
+            tracing::info!(%name, "SpaceMouse connected");
+            let _ = app.emit("spacemouse-connected", DeviceEvent { name: name.clone() });
+            let result = read_loop(&device, &config, &sink, &shared, &stop, &mut seq);
+            if let Err(err) = result {
+                tracing::info!(%name, "SpaceMouse disconnected: {err}");
+            }
+            let _ = app.emit("spacemouse-disconnected", DeviceEvent { name });
+            *shared.raw.lock().unwrap() = [0; 6];
+            continue;
+        }
+        std::thread::sleep(SCAN_INTERVAL);
+    }
+}
+
+fn open_first(api: &mut HidApi) -> Option<(HidDevice, String)> {
+    if let Err(err) = api.refresh_devices() {
+        tracing::debug!("HID enumeration failed: {err}");
+        return None;
+    }
+    api.device_list()
+        .filter(|info| VENDOR_IDS.contains(&info.vendor_id()))
+        .filter(|info| info.usage_page() == GENERIC_DESKTOP && info.usage() == MULTI_AXIS)
+        .find_map(|info| {
+            let device = info.open_device(api).ok()?;
+            let name = info.product_string().unwrap_or("SpaceMouse").to_owned();
+            Some((device, name))
+        })
+}
+
+fn read_loop(
+    device: &HidDevice,
+    config: &SpaceMouseSettings,
+    sink: &Sink,
+    shared: &Shared,
+    stop: &AtomicBool,
+    seq: &mut u64,
+) -> hidapi::HidResult<()> {
+    let mut buttons = 0;
+    let mut report = [0u8; 64];
+    while !stop.load(Ordering::Relaxed) {
+        let len = device.read_timeout(&mut report, READ_TIMEOUT_MS)?;
+        if len == 0 {
+            continue;
+        }
+        let raw = {
+            let mut raw = shared.raw.lock().unwrap();
+            if !apply_report(&report[..len], &mut raw, &mut buttons) {
+                continue;
+            }
+            *raw
+        };
+        let offsets = *shared.offsets.lock().unwrap();
+        let axes: [f32; 6] =
+            std::array::from_fn(|i| calibrate(raw[i], offsets[i], AXIS_NAMES[i], config));
+
+        *seq += 1;
+        let sample = SpaceMouseSample {
+            seq: *seq,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            translation: [axes[0], axes[1], axes[2]],
+            rotation: [axes[3], axes[4], axes[5]],
+            buttons,
+        };
+        sink.send("spacemouse-state", &sample);
+    }
+    Ok(())
+}
+
+/// Folds one HID report into the raw state; returns `false` for unknown reports.
+///
+/// Report 1 carries translation (and, on newer devices, rotation too), report
+/// 2 rotation and report 3 the button bitmask, all little-endian.
+fn apply_report(report: &[u8], raw: &mut [i16; 6], buttons: &mut u32) -> bool {
+    let axis = |i: usize| i16::from_le_bytes([report[1 + 2 * i], report[2 + 2 * i]]);
+    match report[0] {
+        1 if report.len() >= 13 => *raw = std::array::from_fn(axis),
+        1 if report.len() >= 7 => raw[..3].copy_from_slice(&[axis(0), axis(1), axis(2)]),
+        2 if report.len() >= 7 => raw[3..].copy_from_slice(&[axis(0), axis(1), axis(2)]),
+        3 => {
+            let mut bytes = [0u8; 4];
+            let available = (report.len() - 1).min(4);
+            bytes[..available].copy_from_slice(&report[1..=available]);
+            *buttons = u32::from_le_bytes(bytes);
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn calibrate(raw: i16, offset: i16, name: &str, config: &SpaceMouseSettings) -> f32 {
+    let value = (f32::from(raw) - f32::from(offset)) / config.full_scale.max(1.0);
+    let value = if value.abs() <= config.deadzone {
+        0.0
+    } else {
+        value.clamp(-1.0, 1.0)
+    };
+    if config.inverted_axes.iter().any(|axis| axis == name) {
+        -value
+    } else {
+        value
+    }
+}
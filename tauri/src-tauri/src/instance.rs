@@ -0,0 +1,37 @@
+//! Single-instance handling.
+//!
+//! A second launch never starts its own sidecar: its arguments are forwarded
+//! to the running instance as a `second-instance` event and the existing main
+//! window is brought to the front. `percus://` links among the arguments are
+//! handled by [`crate::deep_link`] first, and `.percus` archives are opened
+//! by [`crate::dataset_import`].
+
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::dataset_import;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SecondInstance {
+    args: Vec<String>,
+    cwd: String,
+}
+
+/// Called by the single-instance plugin inside the already running process.
+pub fn on_second_instance(app: &AppHandle, args: Vec<String>, cwd: String) {
+    tracing::info!(?args, "second instance launched; focusing existing window");
+    focus_main_window(app);
+    dataset_import::open_archives(app, args.iter().skip(1), Path::new(&cwd));
+    let _ = app.emit("second-instance", SecondInstance { args, cwd });
+}
+
+pub fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
@@ -0,0 +1,174 @@
+//! Verification of the bundled backend binary before it is launched.
+//!
+//! `build.rs` hashes every sidecar bundled via `externalBin` into a manifest
+//! compiled into the shell, which the app's code signature covers in turn.
+//! Before a profile's `sidecar` is spawned, [`verify`] hashes the installed
+//! copy next to the shell's executable with SHA-256 and the supervisor
+//! refuses to launch it unless the hash matches. A sidecar missing from the
+//! manifest is refused as well in release builds; debug builds, which are
+//! usually made without the bundled binaries, launch it unverified.
+//! Profiles running a `program` or `module` launch nothing of the install's
+//! and are reported as `external`.
+//!
+//! The result of the last check is part of `backend_status`. Hashes are
+//! kept while the binary's size and modification time stay the same, so
+//! restarts do not read it again.
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::profiles::ProfileSpec;
+
+const MANIFEST: &str = include_str!(concat!(env!("OUT_DIR"), "/sidecar-manifest.txt"));
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityStatus {
+    /// `verified`, `mismatch`, `unlisted`, `unverified`, `missing` or
+    /// `external`.
+    pub state: &'static str,
+    pub sidecar: Option<String>,
+    pub path: Option<PathBuf>,
+    pub expected_sha256: Option<String>,
+    pub actual_sha256: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    stamp: Option<(u64, SystemTime)>,
+}
+
+impl IntegrityStatus {
+    fn new(state: &'static str) -> Self {
+        Self {
+            state,
+            sidecar: None,
+            path: None,
+            expected_sha256: None,
+            actual_sha256: None,
+            error: None,
+            stamp: None,
+        }
+    }
+
+    /// Fails when the binary must not be launched.
+    pub fn allow_launch(&self) -> Result<()> {
+        match self.state {
+            "verified" | "unverified" | "external" => Ok(()),
+            _ => Err(Error::Integrity(match (&self.sidecar, &self.error) {
+                (_, Some(error)) => error.clone(),
+                (Some(sidecar), None) => format!("{sidecar} is {}", self.state),
+                (None, None) => self.state.to_owned(),
+            })),
+        }
+    }
+}
+
+/// Checks the binary `spec` launches; `previous` is the last result, whose
+/// hash is reused while the file is unchanged.
+pub async fn verify(spec: &ProfileSpec, previous: Option<IntegrityStatus>) -> IntegrityStatus {
+    if spec.module.is_some() || spec.program.is_some() {
+        return IntegrityStatus::new("external");
+    }
+    let name = spec.sidecar.clone();
+    let mut status =
+        match tauri::async_runtime::spawn_blocking(move || check(&name, previous)).await {
+            Ok(status) => status,
+            Err(err) => {
+                let mut status = IntegrityStatus::new("missing");
+                status.error = Some(format!("verification failed: {err}"));
+                status
+            }
+        };
+    status.sidecar = Some(spec.sidecar.clone());
+    match status.state {
+        "verified" => tracing::info!(sidecar = %spec.sidecar, "sidecar verified"),
+        "unverified" => tracing::warn!(sidecar = %spec.sidecar, "sidecar launched unverified"),
+        state => tracing::error!(
+            sidecar = %spec.sidecar,
+            state,
+            expected = ?status.expected_sha256,
+            actual = ?status.actual_sha256,
+            "sidecar failed verification"
+        ),
+    }
+    status
+}
+
+fn check(name: &str, previous: Option<IntegrityStatus>) -> IntegrityStatus {
+    let expected = MANIFEST.lines().find_map(|line| {
+        let (listed, digest) = line.split_once(' ')?;
+        (listed == name).then(|| digest.trim().to_owned())
+    });
+    let mut status = IntegrityStatus::new("missing");
+    status.expected_sha256 = expected.clone();
+    let path = match sidecar_path(name) {
+        Ok(path) => path,
+        Err(err) => {
+            status.error = Some(format!("the shell's directory is unknown: {err}"));
+            return status;
+        }
+    };
+    status.path = Some(path.clone());
+    let stamp = std::fs::metadata(&path).and_then(|meta| Ok((meta.len(), meta.modified()?)));
+    let stamp = match stamp {
+        Ok(stamp) => stamp,
+        Err(err) => {
+            status.error = Some(format!("{}: {err}", path.display()));
+            return status;
+        }
+    };
+    let cached = previous
+        .filter(|previous| previous.path.as_ref() == Some(&path) && previous.stamp == Some(stamp))
+        .and_then(|previous| previous.actual_sha256);
+    let actual = match cached {
+        Some(actual) => actual,
+        None => match hash(&path) {
+            Ok(actual) => actual,
+            Err(err) => {
+                status.error = Some(format!("{}: {err}", path.display()));
+                return status;
+            }
+        },
+    };
+    status.stamp = Some(stamp);
+    status.state = match &expected {
+        Some(expected) if *expected == actual => "verified",
+        Some(_) => "mismatch",
+        None if cfg!(debug_assertions) => "unverified",
+        None => "unlisted",
+    };
+    status.actual_sha256 = Some(actual);
+    status
+}
+
+/// Where the shell plugin finds sidecar `name`: next to the executable.
+fn sidecar_path(name: &str) -> std::io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    let dir = exe
+        .parent()
+        .ok_or_else(|| std::io::Error::other("the executable has no directory"))?;
+    let file = if cfg!(windows) {
+        format!("{name}.exe")
+    } else {
+        name.to_owned()
+    };
+    Ok(dir.join(file))
+}
+
+fn hash(path: &std::path::Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
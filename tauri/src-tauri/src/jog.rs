@@ -0,0 +1,667 @@
+//! Low-latency manual jogging straight to the robot controller.
+//!
+//! Jogging through the backend adds around 100 ms, so the shell streams jog
+//! velocities to the controller's UDP streaming interface itself, at
+//! `jog.port` on the robot's address, every `1 / jog.rateHz`. [`jog`] (or
+//! the gamepad while `jog.gamepad` is set and `jog.deadmanButton` is held)
+//! sets the target as a fraction of `jog.maxLinearMmS`,
+//! `jog.maxAngularDegS` or `jog.maxJointDegS` per axis; the control loop
+//! ramps the streamed velocity towards it within the configured
+//! accelerations and falls back to zero when no command has arrived for
+//! `jog.commandTimeoutMs`. A change of frame first ramps down to a stop.
+//!
+//! Soft limits are checked against the position the controller reports
+//! back: an axis approaching `jog.workspaceMin` .. `jog.workspaceMax`
+//! (base frame, mm) or its `jog.jointLimits` (degrees; by default the
+//! revolute limits of the URDF, see [`crate::urdf`]) is braked within its
+//! acceleration so that it stops at the limit. While limits are set, nothing moves until a position
+//! has arrived or once the last one is older than `jog.commandTimeoutMs`,
+//! and tool-frame jogging is refused since its axes cannot be
+//! checked. An emergency stop zeroes the stream at once. A stop-robot
+//! safety zone (see [`crate::safety_zones`]) does too, and [`submit`] then
+//! refuses motion in the direction that drove into it until the violation
+//! clears; jogging back out stays possible.
+//!
+//! Datagrams are ASCII like the controller's TCP protocol (see
+//! [`crate::daihen_fd`]); [`encode`] and [`parse_position`] are the only
+//! places that know their layout.
+
+use std::net::Ipv4Addr;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::net::UdpSocket;
+
+use crate::daihen_fd;
+use crate::error::{Error, Result};
+use crate::settings::{JogSettings, SettingsStore};
+use crate::urdf;
+use crate::users::{self, Capability};
+
+/// The loop ends after streaming zero for this long.
+const IDLE_STOP: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JogFrame {
+    #[default]
+    Base,
+    Tool,
+    Joint,
+}
+
+impl JogFrame {
+    fn code(self) -> &'static str {
+        match self {
+            JogFrame::Base => "BASE",
+            JogFrame::Tool => "TOOL",
+            JogFrame::Joint => "JOINT",
+        }
+    }
+}
+
+/// Argument of [`jog`].
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JogCommand {
+    pub frame: JogFrame,
+    /// Per axis, -1 to 1 of its maximum speed: x, y, z, rx, ry, rz in the
+    /// Cartesian frames, joints 1 to 6 in `joint`.
+    pub velocity: [f64; 6],
+}
+
+/// Returned by [`jog_status`].
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JogStatus {
+    pub active: bool,
+    pub frame: JogFrame,
+    /// Velocity streamed last, in mm/s and deg/s.
+    pub velocity: [f64; 6],
+    /// Base-frame pose (mm, deg) last reported by the controller, while
+    /// no older than `jog.commandTimeoutMs`.
+    pub pose: Option<[f64; 6]>,
+    /// Joint angles (deg) last reported by the controller, likewise.
+    pub joints: Option<[f64; 6]>,
+    /// Axes being held back by a soft limit.
+    pub limited: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Shared {
+    target: JogCommand,
+    updated: Option<Instant>,
+    /// When the controller last reported its position.
+    received: Option<Instant>,
+    status: JogStatus,
+    /// Set by an emergency stop; the next tick streams zero without ramping.
+    halt: bool,
+    /// Frame and direction of the motion that violated a stop-robot zone.
+    blocked: Option<JogCommand>,
+}
+
+/// The jog control loop and what it streams.
+#[derive(Default)]
+pub struct Jog {
+    shared: StdMutex<Shared>,
+    task: StdMutex<Option<JoinHandle<()>>>,
+}
+
+/// Sets the jog target, starting the control loop if needed.
+#[tauri::command]
+pub fn jog(app: AppHandle, command: JogCommand) -> Result<()> {
+    submit(&app, command)
+}
+
+/// Ramps down to a stop.
+#[tauri::command]
+pub fn stop_jog(app: AppHandle, jog: State<'_, Jog>) -> Result<()> {
+    users::require(&app, Capability::Jog)?;
+    let mut shared = jog.shared.lock().unwrap();
+    shared.target.velocity = [0.0; 6];
+    shared.updated = Some(Instant::now());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn jog_status(app: AppHandle, jog: State<'_, Jog>) -> Result<JogStatus> {
+    users::require(&app, Capability::Jog)?;
+    Ok(jog.shared.lock().unwrap().status.clone())
+}
+
+/// Jog input from any source; see [`jog`].
+pub fn submit(app: &AppHandle, command: JogCommand) -> Result<()> {
+    users::require(app, Capability::Jog)?;
+    if command.velocity.iter().any(|value| !value.is_finite()) {
+        return Err(Error::Invalid("jog velocity must be finite".into()));
+    }
+    let settings = app.state::<SettingsStore>().get();
+    if command.frame == JogFrame::Tool && has_workspace(&settings.jog) {
+        return Err(Error::Invalid(
+            "tool-frame jogging is unavailable while workspace limits are set".into(),
+        ));
+    }
+    let host = settings
+        .robot_host()
+        .ok_or_else(|| Error::Invalid("no robot controller address configured".into()))?;
+    let jog = app.state::<Jog>();
+    {
+        let mut shared = jog.shared.lock().unwrap();
+        if let Some(blocked) = shared.blocked {
+            let further = command
+                .velocity
+                .iter()
+                .zip(blocked.velocity)
+                .any(|(wanted, blocked)| wanted * blocked > 0.0);
+            let moving = command.velocity.iter().any(|&value| value != 0.0);
+            if further || (moving && command.frame != blocked.frame) {
+                return Err(Error::Invalid(
+                    "jogging further into a violated safety zone is refused".into(),
+                ));
+            }
+        }
+        shared.target = JogCommand {
+            frame: command.frame,
+            velocity: command.velocity.map(|value| value.clamp(-1.0, 1.0)),
+        };
+        shared.updated = Some(Instant::now());
+    }
+    let mut task = jog.task.lock().unwrap();
+    if task.as_ref().is_none_or(|task| task.inner().is_finished()) {
+        let address = (host, settings.jog.port);
+        *task = Some(tauri::async_runtime::spawn(run(app.clone(), address)));
+    }
+    Ok(())
+}
+
+/// Zeroes the streamed velocity at once; called on an emergency stop.
+pub fn halt(app: &AppHandle) {
+    if let Some(jog) = app.try_state::<Jog>() {
+        let mut shared = jog.shared.lock().unwrap();
+        shared.target.velocity = [0.0; 6];
+        shared.halt = true;
+    }
+}
+
+/// Halts like [`halt`] and refuses further motion in the direction being
+/// streamed (or, when stopped already, commanded) until [`unblock`].
+pub fn block(app: &AppHandle) {
+    if let Some(jog) = app.try_state::<Jog>() {
+        let mut shared = jog.shared.lock().unwrap();
+        let streamed = shared.status.velocity;
+        let mut blocked = if streamed.iter().any(|&value| value != 0.0) {
+            JogCommand {
+                frame: shared.status.frame,
+                velocity: streamed,
+            }
+        } else {
+            shared.target
+        };
+        // A later intrusion adds its direction to the earlier ones.
+        if let Some(earlier) = shared
+            .blocked
+            .filter(|earlier| earlier.frame == blocked.frame)
+        {
+            for (value, earlier) in blocked.velocity.iter_mut().zip(earlier.velocity) {
+                if *value == 0.0 {
+                    *value = earlier;
+                }
+            }
+        }
+        shared.blocked = Some(blocked);
+        shared.target.velocity = [0.0; 6];
+        shared.halt = true;
+    }
+}
+
+/// Lifts the restriction of [`block`].
+pub fn unblock(app: &AppHandle) {
+    if let Some(jog) = app.try_state::<Jog>() {
+        jog.shared.lock().unwrap().blocked = None;
+    }
+}
+
+fn has_workspace(settings: &JogSettings) -> bool {
+    settings.workspace_min.is_some() || settings.workspace_max.is_some()
+}
+
+async fn run(app: AppHandle, address: (String, u16)) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(err) => return tracing::error!("jog socket: {err}"),
+    };
+    if let Err(err) = socket.connect((address.0.as_str(), address.1)).await {
+        return tracing::error!(host = %address.0, port = address.1, "jog stream: {err}");
+    }
+    let rate_hz = app
+        .state::<SettingsStore>()
+        .get()
+        .jog
+        .rate_hz
+        .clamp(1.0, 1000.0);
+    let period = Duration::from_secs_f64(1.0 / rate_hz);
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    tracing::info!(host = %address.0, port = address.1, rate_hz, "jog stream started");
+
+    let jog = app.state::<Jog>();
+    let mut velocity = [0.0; 6];
+    let mut frame = JogFrame::Base;
+    let mut idle_since = Instant::now();
+    let mut seq: u32 = 0;
+    let mut buffer = [0u8; 512];
+    loop {
+        ticker.tick().await;
+        let mut settings = app.state::<SettingsStore>().get().jog;
+        if settings.joint_limits.is_empty() {
+            if let Some(model) = urdf::current(&app) {
+                settings.joint_limits = model.joint_limits_deg();
+            }
+        }
+        let (datagram, stopped) = {
+            let mut shared = jog.shared.lock().unwrap();
+            while let Ok(len) = socket.try_recv(&mut buffer) {
+                if let Some((pose, joints)) =
+                    parse_position(&String::from_utf8_lossy(&buffer[..len]))
+                {
+                    shared.status.pose = Some(pose);
+                    shared.status.joints = Some(joints);
+                    shared.received = Some(Instant::now());
+                }
+            }
+            let timeout = Duration::from_millis(settings.command_timeout_ms);
+            if shared
+                .received
+                .is_some_and(|received| received.elapsed() >= timeout)
+            {
+                // A stale position cannot be checked against the limits.
+                shared.status.pose = None;
+                shared.status.joints = None;
+                shared.received = None;
+            }
+
+            let fresh = shared
+                .updated
+                .is_some_and(|updated| updated.elapsed() < timeout);
+            let target = shared.target;
+            let mut wanted = if fresh { target.velocity } else { [0.0; 6] };
+            if target.frame != frame {
+                // Switch frames only once stopped.
+                if velocity.iter().all(|&value| value == 0.0) {
+                    frame = target.frame;
+                } else {
+                    wanted = [0.0; 6];
+                }
+            }
+            let mut limited = Vec::new();
+            step(
+                &settings,
+                frame,
+                &shared.status,
+                &wanted,
+                &mut velocity,
+                period.as_secs_f64(),
+                &mut limited,
+            );
+            if std::mem::take(&mut shared.halt) {
+                velocity = [0.0; 6];
+            }
+
+            seq = seq.wrapping_add(1);
+            let datagram = encode(seq, frame, &velocity);
+            shared.status.frame = frame;
+            shared.status.velocity = velocity;
+            shared.status.limited = limited;
+            let moving = velocity.iter().any(|&value| value != 0.0);
+            let commanded = fresh && wanted.iter().any(|&value| value != 0.0);
+            if moving || commanded {
+                idle_since = Instant::now();
+            }
+            let stopped = idle_since.elapsed() >= IDLE_STOP;
+            shared.status.active = !stopped;
+            (datagram, stopped)
+        };
+        if let Err(err) = socket.send(datagram.as_bytes()).await {
+            tracing::debug!("jog datagram not sent: {err}");
+        }
+        if stopped {
+            tracing::info!("jog stream stopped");
+            return;
+        }
+    }
+}
+
+/// Maximum speed and acceleration of each axis in `frame`.
+fn axis_limits(settings: &JogSettings, frame: JogFrame) -> ([f64; 6], [f64; 6]) {
+    match frame {
+        JogFrame::Joint => (
+            [settings.max_joint_deg_s; 6],
+            [settings.joint_accel_deg_s2; 6],
+        ),
+        JogFrame::Base | JogFrame::Tool => {
+            let (linear, angular) = (settings.max_linear_mm_s, settings.max_angular_deg_s);
+            let (linear_accel, angular_accel) =
+                (settings.linear_accel_mm_s2, settings.angular_accel_deg_s2);
+            (
+                [linear, linear, linear, angular, angular, angular],
+                [
+                    linear_accel,
+                    linear_accel,
+                    linear_accel,
+                    angular_accel,
+                    angular_accel,
+                    angular_accel,
+                ],
+            )
+        }
+    }
+}
+
+/// Advances the streamed `velocity` one tick of `dt` towards `wanted`
+/// (fractions of each axis' maximum speed), within the accelerations and
+/// the soft limits.
+fn step(
+    settings: &JogSettings,
+    frame: JogFrame,
+    status: &JogStatus,
+    wanted: &[f64; 6],
+    velocity: &mut [f64; 6],
+    dt: f64,
+    limited: &mut Vec<usize>,
+) {
+    let (max_speed, accel) = axis_limits(settings, frame);
+    let mut target: [f64; 6] = std::array::from_fn(|axis| wanted[axis] * max_speed[axis]);
+    approach_limits(settings, frame, status, &mut target, velocity, &accel, dt);
+    for axis in 0..6 {
+        let step = accel[axis] * dt;
+        velocity[axis] += (target[axis] - velocity[axis]).clamp(-step, step);
+    }
+    soft_limit(settings, frame, status, velocity, &accel, dt, limited);
+}
+
+/// Axis, minimum and maximum.
+type Bound = (usize, f64, f64);
+
+/// The position checked in `frame` and the limits on it.
+fn bounds(
+    settings: &JogSettings,
+    frame: JogFrame,
+    status: &JogStatus,
+) -> (Option<[f64; 6]>, Vec<Bound>) {
+    match frame {
+        JogFrame::Joint => (
+            status.joints,
+            settings
+                .joint_limits
+                .iter()
+                .take(6)
+                .enumerate()
+                .map(|(axis, [min, max])| (axis, *min, *max))
+                .collect(),
+        ),
+        JogFrame::Base => {
+            let min = settings.workspace_min.unwrap_or([f64::NEG_INFINITY; 3]);
+            let max = settings.workspace_max.unwrap_or([f64::INFINITY; 3]);
+            let bounds = if has_workspace(settings) {
+                (0..3).map(|axis| (axis, min[axis], max[axis])).collect()
+            } else {
+                Vec::new()
+            };
+            (status.pose, bounds)
+        }
+        JogFrame::Tool => (None, Vec::new()),
+    }
+}
+
+/// Caps the target speed of each limited axis to what can still be
+/// stopped within the distance left to its limit, so that the ramp brakes
+/// ahead of it instead of [`soft_limit`] working against the ramp.
+fn approach_limits(
+    settings: &JogSettings,
+    frame: JogFrame,
+    status: &JogStatus,
+    target: &mut [f64; 6],
+    velocity: &[f64; 6],
+    accel: &[f64; 6],
+    dt: f64,
+) {
+    let (Some(position), bounds) = bounds(settings, frame, status) else {
+        return;
+    };
+    for (axis, min, max) in bounds {
+        let left = if target[axis] > 0.0 {
+            max - position[axis]
+        } else {
+            position[axis] - min
+        };
+        // Less the distance covered before the next position arrives.
+        let left = (left - velocity[axis].abs() * dt).max(0.0);
+        let cap = (2.0 * accel[axis] * left).sqrt();
+        target[axis] = target[axis].clamp(-cap, cap);
+    }
+}
+
+/// Slows axes whose stopping point lies beyond a limit, stopping those
+/// already past it and moving further out.
+fn soft_limit(
+    settings: &JogSettings,
+    frame: JogFrame,
+    status: &JogStatus,
+    velocity: &mut [f64; 6],
+    accel: &[f64; 6],
+    dt: f64,
+    limited: &mut Vec<usize>,
+) {
+    let (position, bounds) = bounds(settings, frame, status);
+    if bounds.is_empty() {
+        return;
+    }
+    let Some(position) = position else {
+        // Limits cannot be checked without a position.
+        *velocity = [0.0; 6];
+        limited.extend(0..6);
+        return;
+    };
+    for (axis, min, max) in bounds {
+        let v = velocity[axis];
+        if v == 0.0 {
+            continue;
+        }
+        let stopping = v * v / (2.0 * accel[axis].max(f64::EPSILON));
+        let stop_at = position[axis] + v * dt + v.signum() * stopping;
+        let past = (v > 0.0 && position[axis] >= max) || (v < 0.0 && position[axis] <= min);
+        if past {
+            velocity[axis] = 0.0;
+            limited.push(axis);
+        } else if stop_at > max || stop_at < min {
+            let step = accel[axis] * dt;
+            velocity[axis] -= v.signum() * step.min(v.abs());
+            limited.push(axis);
+        }
+    }
+}
+
+/// `JOG <seq> <frame> <v1> .. <v6>`, mm/s and deg/s.
+fn encode(seq: u32, frame: JogFrame, velocity: &[f64; 6]) -> String {
+    let values: Vec<String> = velocity.iter().map(|value| format!("{value:.3}")).collect();
+    format!("JOG {seq} {} {}", frame.code(), values.join(" "))
+}
+
+/// Parses `POS <seq> BASE x y z rx ry rz JOINT j1 .. j6` into the pose and
+/// the joint angles.
+fn parse_position(datagram: &str) -> Option<([f64; 6], [f64; 6])> {
+    let (tag, rest) = datagram.trim().split_once(char::is_whitespace)?;
+    if tag != "POS" {
+        return None;
+    }
+    let (_seq, payload) = rest.trim_start().split_once(char::is_whitespace)?;
+    let position = daihen_fd::parse_pose(payload)?;
+    Some((position.pose, position.joints))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCEL: [f64; 6] = [100.0; 6];
+    const DT: f64 = 0.01;
+
+    fn workspace(max_x: f64) -> JogSettings {
+        JogSettings {
+            workspace_min: Some([-1000.0; 3]),
+            workspace_max: Some([max_x, 1000.0, 1000.0]),
+            ..JogSettings::default()
+        }
+    }
+
+    fn at(x: f64) -> JogStatus {
+        JogStatus {
+            pose: Some([x, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            ..JogStatus::default()
+        }
+    }
+
+    fn limit(
+        settings: &JogSettings,
+        frame: JogFrame,
+        status: &JogStatus,
+        velocity: [f64; 6],
+    ) -> ([f64; 6], Vec<usize>) {
+        let mut velocity = velocity;
+        let mut limited = Vec::new();
+        soft_limit(
+            settings,
+            frame,
+            status,
+            &mut velocity,
+            &ACCEL,
+            DT,
+            &mut limited,
+        );
+        (velocity, limited)
+    }
+
+    #[test]
+    fn leaves_unlimited_axes_alone() {
+        let velocity = [50.0, -20.0, 0.0, 5.0, 0.0, 0.0];
+        let (limited_velocity, limited) = limit(
+            &JogSettings::default(),
+            JogFrame::Base,
+            &JogStatus::default(),
+            velocity,
+        );
+        assert_eq!(limited_velocity, velocity);
+        assert!(limited.is_empty());
+    }
+
+    #[test]
+    fn stops_without_a_position() {
+        let (velocity, limited) = limit(
+            &workspace(1000.0),
+            JogFrame::Base,
+            &JogStatus::default(),
+            [10.0; 6],
+        );
+        assert_eq!(velocity, [0.0; 6]);
+        assert_eq!(limited, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn slows_down_before_a_limit() {
+        // Stopping from 100 mm/s takes 50 mm, past the limit 10 mm away.
+        let (velocity, limited) = limit(
+            &workspace(1000.0),
+            JogFrame::Base,
+            &at(990.0),
+            [100.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_eq!(velocity[0], 99.0);
+        assert_eq!(limited, vec![0]);
+    }
+
+    #[test]
+    fn stops_past_a_limit_but_lets_it_back() {
+        let (velocity, _) = limit(
+            &workspace(1000.0),
+            JogFrame::Base,
+            &at(1000.0),
+            [10.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_eq!(velocity[0], 0.0);
+        let (velocity, limited) = limit(
+            &workspace(1000.0),
+            JogFrame::Base,
+            &at(1000.0),
+            [-10.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_eq!(velocity[0], -10.0);
+        assert!(limited.is_empty());
+    }
+
+    #[test]
+    fn checks_joint_limits() {
+        let settings = JogSettings {
+            joint_limits: vec![[-90.0, 90.0]; 6],
+            ..JogSettings::default()
+        };
+        let status = JogStatus {
+            joints: Some([90.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            ..JogStatus::default()
+        };
+        let (velocity, limited) = limit(
+            &settings,
+            JogFrame::Joint,
+            &status,
+            [5.0, 5.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_eq!(velocity, [0.0, 5.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(limited, vec![0]);
+    }
+
+    #[test]
+    fn brakes_to_a_stop_at_a_limit() {
+        let settings = workspace(1000.0);
+        let mut status = at(0.0);
+        let mut velocity = [0.0; 6];
+        let wanted = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let mut top_speed: f64 = 0.0;
+        for _ in 0..1000 {
+            let mut limited = Vec::new();
+            step(
+                &settings,
+                JogFrame::Base,
+                &status,
+                &wanted,
+                &mut velocity,
+                DT,
+                &mut limited,
+            );
+            let pose = status.pose.as_mut().unwrap();
+            pose[0] += velocity[0] * DT;
+            top_speed = top_speed.max(velocity[0]);
+            assert!(pose[0] <= 1000.0, "overshot to {}", pose[0]);
+        }
+        assert_eq!(top_speed, settings.max_linear_mm_s);
+        assert!(velocity[0] < 1.0, "still moving at {}", velocity[0]);
+        assert!(status.pose.unwrap()[0] > 995.0);
+    }
+
+    #[test]
+    fn parses_position_datagrams() {
+        let (pose, joints) =
+            parse_position("POS 17 BASE 1 2 3 4 5 6 JOINT 7 8 9 10 11 12\n").unwrap();
+        assert_eq!(pose, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(joints, [7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        assert!(parse_position("POS BASE 1 2 3 4 5 6 JOINT 7 8 9 10 11 12").is_none());
+        assert!(parse_position("JOG 17 BASE 1 2 3 4 5 6 JOINT 7 8 9 10 11 12").is_none());
+    }
+
+    #[test]
+    fn encodes_jog_datagrams() {
+        let datagram = encode(3, JogFrame::Joint, &[1.0, -2.5, 0.0, 0.0, 0.0, 0.125]);
+        assert_eq!(datagram, "JOG 3 JOINT 1.000 -2.500 0.000 0.000 0.000 0.125");
+    }
+}
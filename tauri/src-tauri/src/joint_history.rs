@@ -0,0 +1,376 @@
+//! In-memory history of joint states at their full rate, for plots and
+//! incident review.
+//!
+//! Samples are kept for the last `jointHistory.retentionSecs`, at most
+//! `maxSamples` of them, from every source the shell sees:
+//!
+//! - `ros2`: every message on the joint-states topic, before the throttling
+//!   of `ros2-joint-state` events (see [`crate::ros2`]);
+//! - `grpc`: `grpc-robot-state` messages of gateway subscriptions;
+//! - `backend`: `observation.state` on the backend's `recording.stateTopic`,
+//!   followed while `backendState` is set.
+//!
+//! Efforts carry the motor currents where the source reports them.
+//! [`query_joint_history`] returns a time window per source and joint set,
+//! optionally reduced to a number of buckets with their mean, minimum and
+//! maximum, so short spikes survive the downsampling.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Listener, Manager, State};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::backend_tls;
+use crate::error::{Error, Result};
+use crate::grpc::proto::RobotState;
+use crate::settings::{JointHistorySettings, Settings, SettingsStore};
+use crate::tf;
+use crate::time_sync;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+struct Sample {
+    timestamp_ns: i64,
+    source: Arc<str>,
+    names: Arc<[String]>,
+    position: Vec<f64>,
+    velocity: Vec<f64>,
+    effort: Vec<f64>,
+}
+
+/// One joint-state reading, as a source delivers it.
+pub struct JointReading {
+    pub names: Vec<String>,
+    pub position: Vec<f64>,
+    pub velocity: Vec<f64>,
+    pub effort: Vec<f64>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    samples: VecDeque<Sample>,
+    /// Latest joint names per source, shared by its samples.
+    names: HashMap<Arc<str>, Arc<[String]>>,
+}
+
+#[derive(Default)]
+pub struct JointHistory {
+    buffer: StdMutex<Buffer>,
+    settings: StdMutex<JointHistorySettings>,
+}
+
+impl JointHistory {
+    fn push(&self, source: &str, timestamp_ns: i64, reading: JointReading) {
+        let (retention_ns, max_samples) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                i64::try_from(settings.retention_secs)
+                    .unwrap_or(i64::MAX / 1_000_000_000)
+                    .saturating_mul(1_000_000_000),
+                settings.max_samples,
+            )
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        let (source, names) = match buffer.names.get_key_value(source) {
+            Some((key, names)) if **names == reading.names[..] => (key.clone(), names.clone()),
+            _ => {
+                let key: Arc<str> = source.into();
+                let names: Arc<[String]> = reading.names.into();
+                buffer.names.insert(key.clone(), names.clone());
+                (key, names)
+            }
+        };
+        buffer.samples.push_back(Sample {
+            timestamp_ns,
+            source,
+            names,
+            position: reading.position,
+            velocity: reading.velocity,
+            effort: reading.effort,
+        });
+        let oldest = timestamp_ns.saturating_sub(retention_ns);
+        while buffer.samples.len() > max_samples
+            || buffer
+                .samples
+                .front()
+                .is_some_and(|sample| sample.timestamp_ns < oldest)
+        {
+            buffer.samples.pop_front();
+        }
+    }
+}
+
+/// Adds a reading from `source` to the history, and its positions to the
+/// frame transforms.
+pub fn record(app: &AppHandle, source: &str, reading: JointReading) {
+    let timestamp_ns = time_sync::now_ns(app);
+    tf::ingest(app, timestamp_ns, &reading.names, &reading.position);
+    if let Some(history) = app.try_state::<JointHistory>() {
+        history.push(source, timestamp_ns, reading);
+    }
+}
+
+/// Follows settings changes, gateway robot state and the backend topic.
+pub fn init(app: &AppHandle) {
+    let history = JointHistory::default();
+    *history.settings.lock().unwrap() = app.state::<SettingsStore>().get().joint_history;
+    app.manage(history);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            *handle.state::<JointHistory>().settings.lock().unwrap() = settings.joint_history;
+        }
+    });
+
+    #[derive(Deserialize)]
+    struct GatewayMessage {
+        message: RobotState,
+    }
+    let handle = app.clone();
+    app.listen_any("grpc-robot-state", move |event| {
+        let Ok(GatewayMessage { message }) = serde_json::from_str(event.payload()) else {
+            return;
+        };
+        let names = (0..message.joint_positions.len())
+            .map(|index| format!("{}/j{}", message.robot_id, index + 1))
+            .collect();
+        record(
+            &handle,
+            "grpc",
+            JointReading {
+                names,
+                position: message.joint_positions,
+                velocity: Vec::new(),
+                effort: Vec::new(),
+            },
+        );
+    });
+
+    tauri::async_runtime::spawn(follow_backend(app.clone()));
+}
+
+/// Records `observation.state` from the backend's state topic.
+async fn follow_backend(app: AppHandle) {
+    loop {
+        let settings = app.state::<SettingsStore>().get();
+        if settings.joint_history.backend_state {
+            let topic = settings.recording.state_topic;
+            match backend_tls::ws_connect(&app, &topic).await {
+                Ok(mut socket) => {
+                    while let Some(Ok(message)) = socket.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        if let Some(position) = observation_state(&text) {
+                            let names = (0..position.len())
+                                .map(|index| format!("state.{index}"))
+                                .collect();
+                            record(
+                                &app,
+                                "backend",
+                                JointReading {
+                                    names,
+                                    position,
+                                    velocity: Vec::new(),
+                                    effort: Vec::new(),
+                                },
+                            );
+                        }
+                    }
+                }
+                Err(err) => tracing::debug!(%topic, "state topic unavailable: {err}"),
+            }
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+fn observation_state(text: &str) -> Option<Vec<f64>> {
+    let message: serde_json::Value = serde_json::from_str(text).ok()?;
+    message
+        .get("observation.state")?
+        .as_array()?
+        .iter()
+        .map(serde_json::Value::as_f64)
+        .collect()
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointRow {
+    /// Unix time in milliseconds; the bucket's centre when downsampled.
+    pub timestamp_ms: f64,
+    pub position: Vec<f64>,
+    pub velocity: Vec<f64>,
+    pub effort: Vec<f64>,
+}
+
+/// Samples of one source sharing a joint name list.
+struct Group<'a> {
+    source: Arc<str>,
+    names: Arc<[String]>,
+    samples: Vec<&'a Sample>,
+}
+
+/// The samples of one source while its joint names stayed the same.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointSeries {
+    pub source: String,
+    pub names: Vec<String>,
+    /// Per-bucket means when downsampled, otherwise the samples.
+    pub rows: Vec<JointRow>,
+    /// Per-bucket extremes; empty unless downsampled.
+    pub min: Vec<JointRow>,
+    pub max: Vec<JointRow>,
+}
+
+/// Samples between `start` and `end` (Unix milliseconds; by default the
+/// whole history up to now), optionally only those of `source`. With
+/// `downsample`, each series is reduced to at most that many buckets.
+#[tauri::command]
+pub fn query_joint_history(
+    history: State<'_, JointHistory>,
+    start: Option<f64>,
+    end: Option<f64>,
+    downsample: Option<usize>,
+    source: Option<String>,
+) -> Result<Vec<JointSeries>> {
+    let start_ns = start.map_or(i64::MIN, |ms| (ms * 1e6) as i64);
+    let end_ns = end.map_or(i64::MAX, |ms| (ms * 1e6) as i64);
+    if start_ns > end_ns {
+        return Err(Error::Invalid("start is after end".into()));
+    }
+    if downsample == Some(0) {
+        return Err(Error::Invalid("downsample must be at least 1".into()));
+    }
+
+    let buffer = history.buffer.lock().unwrap();
+    let first = buffer
+        .samples
+        .partition_point(|sample| sample.timestamp_ns < start_ns);
+    let mut groups: Vec<Group<'_>> = Vec::new();
+    for sample in buffer.samples.range(first..) {
+        if sample.timestamp_ns > end_ns {
+            break;
+        }
+        if source
+            .as_deref()
+            .is_some_and(|source| *sample.source != *source)
+        {
+            continue;
+        }
+        let group = groups.iter_mut().find(|group| {
+            Arc::ptr_eq(&group.source, &sample.source) && Arc::ptr_eq(&group.names, &sample.names)
+        });
+        match group {
+            Some(group) => group.samples.push(sample),
+            None => groups.push(Group {
+                source: sample.source.clone(),
+                names: sample.names.clone(),
+                samples: vec![sample],
+            }),
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(
+            |Group {
+                 source,
+                 names,
+                 samples,
+             }| {
+                let mut series = JointSeries {
+                    source: source.to_string(),
+                    names: names.to_vec(),
+                    rows: Vec::new(),
+                    min: Vec::new(),
+                    max: Vec::new(),
+                };
+                match downsample {
+                    Some(buckets) if samples.len() > buckets => {
+                        let first = samples[0].timestamp_ns;
+                        let span = (samples[samples.len() - 1].timestamp_ns - first).max(1) as f64;
+                        let mut start = 0;
+                        for bucket in 0..buckets {
+                            let limit = first as f64 + span * (bucket + 1) as f64 / buckets as f64;
+                            let mut stop = start;
+                            while stop < samples.len()
+                                && (samples[stop].timestamp_ns as f64 <= limit
+                                    || bucket + 1 == buckets)
+                            {
+                                stop += 1;
+                            }
+                            if stop > start {
+                                let centre =
+                                    first as f64 + span * (bucket as f64 + 0.5) / buckets as f64;
+                                let [mean, min, max] = reduce(&samples[start..stop], centre / 1e6);
+                                series.rows.push(mean);
+                                series.min.push(min);
+                                series.max.push(max);
+                            }
+                            start = stop;
+                        }
+                    }
+                    _ => {
+                        series.rows = samples
+                            .iter()
+                            .map(|sample| JointRow {
+                                timestamp_ms: sample.timestamp_ns as f64 / 1e6,
+                                position: sample.position.clone(),
+                                velocity: sample.velocity.clone(),
+                                effort: sample.effort.clone(),
+                            })
+                            .collect();
+                    }
+                }
+                series
+            },
+        )
+        .collect())
+}
+
+/// Mean, minimum and maximum rows of a bucket.
+fn reduce(samples: &[&Sample], timestamp_ms: f64) -> [JointRow; 3] {
+    let stats = |values: &dyn Fn(&Sample) -> &[f64]| {
+        let width = samples
+            .iter()
+            .map(|sample| values(sample).len())
+            .min()
+            .unwrap_or(0);
+        let mut mean = vec![0.0; width];
+        let mut min = vec![f64::INFINITY; width];
+        let mut max = vec![f64::NEG_INFINITY; width];
+        for sample in samples {
+            for (index, value) in values(sample)[..width].iter().enumerate() {
+                mean[index] += value;
+                min[index] = min[index].min(*value);
+                max[index] = max[index].max(*value);
+            }
+        }
+        for value in &mut mean {
+            *value /= samples.len() as f64;
+        }
+        [mean, min, max]
+    };
+    let [position_mean, position_min, position_max] = stats(&|sample| &sample.position);
+    let [velocity_mean, velocity_min, velocity_max] = stats(&|sample| &sample.velocity);
+    let [effort_mean, effort_min, effort_max] = stats(&|sample| &sample.effort);
+    let row = |position, velocity, effort| JointRow {
+        timestamp_ms,
+        position,
+        velocity,
+        effort,
+    };
+    [
+        row(position_mean, velocity_mean, effort_mean),
+        row(position_min, velocity_min, effort_min),
+        row(position_max, velocity_max, effort_max),
+    ]
+}
@@ -0,0 +1,316 @@
+//! Operator (kiosk) mode for shop-floor stations.
+//!
+//! While active the main window is fullscreen without decorations and
+//! cannot be closed, devtools are closed, navigation away from the app and
+//! new windows are refused, and the tray's quit and logs-folder entries do
+//! nothing. [`enter_kiosk`] turns it on and sets `operatorMode.enabled` so
+//! the next launch starts locked, with devtools disabled outright;
+//! [`exit_kiosk`] needs the supervisor PIN, unless a user allowed to leave
+//! operator mode is signed in (see [`crate::users`]).
+//!
+//! The PIN is set with [`set_supervisor_pin`] and stored in the keychain as
+//! a salted PBKDF2 hash, and is checked here rather than in the webview.
+//! After [`MAX_ATTEMPTS`] wrong PINs further attempts are refused for
+//! [`LOCKOUT`]. `kiosk-changed` is emitted on every change.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sha2::Sha256;
+use tauri::webview::NewWindowResponse;
+use tauri::{
+    AppHandle, Emitter, Manager, State, WebviewWindow, WebviewWindowBuilder, WindowEvent, Wry,
+};
+
+use crate::cli::{LaunchOptions, WindowMode};
+use crate::error::{Error, Result};
+use crate::secrets;
+use crate::settings::SettingsStore;
+use crate::users::{self, Capability};
+
+const MAIN_LABEL: &str = "main";
+/// Keychain entry holding the PIN hash; not reachable through the secret
+/// commands.
+pub const PIN_SECRET: &str = "supervisor-pin";
+const PIN_ROUNDS: u32 = 200_000;
+const MAX_ATTEMPTS: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct Kiosk {
+    active: AtomicBool,
+    attempts: StdMutex<Attempts>,
+}
+
+#[derive(Default)]
+struct Attempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KioskChanged {
+    active: bool,
+}
+
+/// Creates the main window, locked down if `operatorMode.enabled` is set.
+///
+/// The main window is built here rather than from the config so that its
+/// navigation and new-window checks can be installed.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let enabled = app.state::<SettingsStore>().get().operator_mode.enabled;
+    // An unreadable keychain keeps the station locked rather than open.
+    let locked = enabled
+        && pin_is_set().unwrap_or_else(|err| {
+            tracing::error!("cannot check supervisor PIN: {err}");
+            true
+        });
+    if enabled && !locked {
+        tracing::error!("operator mode is enabled but no supervisor PIN is set; starting unlocked");
+    }
+    app.manage(Kiosk {
+        active: AtomicBool::new(locked),
+        ..Kiosk::default()
+    });
+
+    let config = app
+        .config()
+        .app
+        .windows
+        .iter()
+        .find(|window| window.label == MAIN_LABEL)
+        .cloned()
+        .ok_or_else(|| Error::NotFound(format!("`{MAIN_LABEL}` window config")))?;
+    let mode = app.state::<LaunchOptions>().window;
+    let window = guard(app, WebviewWindowBuilder::from_config(app, &config)?)
+        .fullscreen(locked || config.fullscreen || mode == WindowMode::Fullscreen)
+        .maximized(config.maximized || mode == WindowMode::Maximized)
+        .decorations(!locked && config.decorations)
+        .devtools(!locked)
+        .build()?;
+    let closing = app.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::CloseRequested { api, .. } = event {
+            if is_active(&closing) {
+                api.prevent_close();
+            }
+        }
+    });
+    Ok(())
+}
+
+pub fn is_active(app: &AppHandle) -> bool {
+    app.try_state::<Kiosk>()
+        .is_some_and(|kiosk| kiosk.active.load(Ordering::SeqCst))
+}
+
+/// Refuses navigation away from the app and new windows while the kiosk is
+/// active.
+pub fn guard<'a, M: Manager<Wry>>(
+    app: &AppHandle,
+    builder: WebviewWindowBuilder<'a, Wry, M>,
+) -> WebviewWindowBuilder<'a, Wry, M> {
+    let navigating = app.clone();
+    let opening = app.clone();
+    builder
+        .on_navigation(move |url| {
+            let allowed = !is_active(&navigating) || is_app_url(&navigating, url);
+            if !allowed {
+                tracing::warn!(%url, "navigation blocked in operator mode");
+            }
+            allowed
+        })
+        .on_new_window(move |url, _| {
+            if is_active(&opening) {
+                tracing::warn!(%url, "new window blocked in operator mode");
+                NewWindowResponse::Deny
+            } else {
+                NewWindowResponse::Allow
+            }
+        })
+}
+
+#[tauri::command]
+pub async fn enter_kiosk(app: AppHandle, kiosk: State<'_, Kiosk>) -> Result<()> {
+    users::require(&app, Capability::EnterKiosk)?;
+    if !blocking(pin_is_set).await? {
+        return Err(Error::Kiosk("set a supervisor PIN first".into()));
+    }
+    kiosk.active.store(true, Ordering::SeqCst);
+    lock_window(&app, true)?;
+    app.state::<SettingsStore>()
+        .update(&app, |settings| settings.operator_mode.enabled = true)?;
+    tracing::info!("entered operator mode");
+    let _ = app.emit("kiosk-changed", KioskChanged { active: true });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn exit_kiosk(
+    app: AppHandle,
+    kiosk: State<'_, Kiosk>,
+    pin: Option<String>,
+) -> Result<()> {
+    if !kiosk.active.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    if !users::holds(&app, Capability::ExitKiosk) {
+        check_pin(&kiosk, pin.unwrap_or_default()).await?;
+    }
+    kiosk.active.store(false, Ordering::SeqCst);
+    lock_window(&app, false)?;
+    app.state::<SettingsStore>()
+        .update(&app, |settings| settings.operator_mode.enabled = false)?;
+    tracing::info!("left operator mode");
+    let _ = app.emit("kiosk-changed", KioskChanged { active: false });
+    Ok(())
+}
+
+/// Sets the supervisor PIN (4–12 digits); `current` must match the
+/// existing PIN, if any.
+#[tauri::command]
+pub async fn set_supervisor_pin(
+    kiosk: State<'_, Kiosk>,
+    current: Option<String>,
+    pin: String,
+) -> Result<()> {
+    if !(4..=12).contains(&pin.len()) || !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::Invalid(
+            "supervisor PIN must be 4 to 12 digits".into(),
+        ));
+    }
+    if blocking(pin_is_set).await? {
+        check_pin(&kiosk, current.unwrap_or_default()).await?;
+    }
+    blocking(move || secrets::set(PIN_SECRET, &encode_pin(&pin)?)).await
+}
+
+/// A salted PBKDF2 hash of `pin`, as stored in the keychain.
+pub fn encode_pin(pin: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    getrandom::fill(&mut salt).map_err(|err| Error::Kiosk(format!("random: {err}")))?;
+    let hash = hash_pin(pin, &salt, PIN_ROUNDS);
+    Ok(format!(
+        "pbkdf2-sha256${PIN_ROUNDS}${}${}",
+        hex::encode(salt),
+        hex::encode(hash)
+    ))
+}
+
+/// Verifies `pin`, counting failures towards the lockout.
+async fn check_pin(kiosk: &Kiosk, pin: String) -> Result<()> {
+    {
+        let attempts = kiosk.attempts.lock().unwrap();
+        if let Some(until) = attempts.locked_until {
+            let now = Instant::now();
+            if now < until {
+                return Err(Error::Kiosk(format!(
+                    "too many wrong PINs; try again in {} s",
+                    (until - now).as_secs() + 1
+                )));
+            }
+        }
+    }
+    let matches = blocking(move || verify_pin(&pin)).await?;
+    let mut attempts = kiosk.attempts.lock().unwrap();
+    if matches {
+        *attempts = Attempts::default();
+        return Ok(());
+    }
+    attempts.failures += 1;
+    tracing::warn!(failures = attempts.failures, "wrong supervisor PIN");
+    if attempts.failures >= MAX_ATTEMPTS {
+        attempts.failures = 0;
+        attempts.locked_until = Some(Instant::now() + LOCKOUT);
+    }
+    Err(Error::Kiosk("wrong supervisor PIN".into()))
+}
+
+fn pin_is_set() -> Result<bool> {
+    Ok(secrets::get(PIN_SECRET)?.is_some())
+}
+
+fn verify_pin(pin: &str) -> Result<bool> {
+    match secrets::get(PIN_SECRET)? {
+        Some(stored) => pin_matches(&stored, pin),
+        None => Ok(false),
+    }
+}
+
+/// Whether `pin` matches a hash made by [`encode_pin`].
+pub fn pin_matches(stored: &str, pin: &str) -> Result<bool> {
+    let invalid = || Error::Invalid("stored PIN hash".into());
+    let mut parts = stored.split('$');
+    if parts.next() != Some("pbkdf2-sha256") {
+        return Err(invalid());
+    }
+    let rounds: u32 = parts
+        .next()
+        .and_then(|rounds| rounds.parse().ok())
+        .ok_or_else(invalid)?;
+    let salt = parts
+        .next()
+        .and_then(|salt| hex::decode(salt).ok())
+        .ok_or_else(invalid)?;
+    let expected = parts
+        .next()
+        .and_then(|hash| hex::decode(hash).ok())
+        .ok_or_else(invalid)?;
+    let actual = hash_pin(pin, &salt, rounds);
+    // Compared without an early exit.
+    Ok(actual.len() == expected.len()
+        && actual
+            .iter()
+            .zip(&expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0)
+}
+
+fn hash_pin(pin: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(pin.as_bytes(), salt, rounds, &mut hash);
+    hash
+}
+
+fn lock_window(app: &AppHandle, locked: bool) -> Result<()> {
+    let Some(window) = app.get_webview_window(MAIN_LABEL) else {
+        return Ok(());
+    };
+    window.set_decorations(!locked)?;
+    window.set_fullscreen(locked)?;
+    if locked {
+        close_devtools(&window);
+        window.set_focus()?;
+    }
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn close_devtools(window: &WebviewWindow) {
+    window.close_devtools();
+}
+
+#[cfg(not(debug_assertions))]
+fn close_devtools(_window: &WebviewWindow) {}
+
+/// Whether `url` is served by the app itself (or the dev server).
+fn is_app_url(app: &AppHandle, url: &tauri::Url) -> bool {
+    if url.scheme() == "tauri" || url.host_str() == Some("tauri.localhost") {
+        return true;
+    }
+    app.config()
+        .build
+        .dev_url
+        .as_ref()
+        .is_some_and(|dev| dev.origin() == url.origin())
+}
+
+/// Runs a keychain or hashing call off the async runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?
+}
@@ -0,0 +1,341 @@
+//! Per-seat licenses for commercial deployments, and the checks that gate
+//! premium subsystems in the shell itself.
+//!
+//! A license is `license.json` in the app data directory:
+//! `{"payload": <base64 JSON>, "signature": <base64 Ed25519 signature of
+//! the payload bytes>}`, signed with the key whose public half is compiled
+//! in from `PERCUS_LICENSE_PUBLIC_KEY` (hex). Builds without a key do not
+//! enforce licensing at all, so open-source builds keep every feature.
+//!
+//! The payload ([`Claims`]) lists the licensed [`Feature`]s and an optional
+//! expiry. Offline licenses are installed from a file with
+//! [`install_license_file`]. Online ones come from [`activate_license`],
+//! which takes a seat for this installation from `licensing.server`; the
+//! server then binds the license to the installation id and issues it with
+//! a lease that the shell renews every `renewIntervalHours`. When renewals
+//! fail, the license is honored for `graceDays` past the lease before its
+//! features are turned off. Seat counts are enforced by the server.
+//!
+//! [`require`] is the enforcement hook for commands of premium subsystems;
+//! status changes are emitted as `license-changed`.
+
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::backend_tls;
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+
+const LICENSE_FILE: &str = "license.json";
+const INSTALL_ID_FILE: &str = "install-id";
+const PUBLIC_KEY: Option<&str> = option_env!("PERCUS_LICENSE_PUBLIC_KEY");
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Renewals are retried this often after a failure.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Subsystems that need a license.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// The robot gateway and backend sessions, which drive several robots
+    /// from one shell.
+    MultiRobot,
+    /// Uploads to object storage and Hugging Face Hub transfers.
+    CloudSync,
+}
+
+impl Feature {
+    fn label(self) -> &'static str {
+        match self {
+            Self::MultiRobot => "multi-robot control",
+            Self::CloudSync => "cloud sync",
+        }
+    }
+}
+
+/// What a license grants.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Claims {
+    pub license_id: String,
+    pub customer: String,
+    pub seats: u32,
+    /// Unknown names are ignored, so new features can be licensed ahead of
+    /// the shell supporting them.
+    pub features: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Installation the license was activated on; any when unset.
+    pub install_id: Option<String>,
+    /// End of the current lease of an activated license.
+    pub lease_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignedLicense {
+    payload: String,
+    signature: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseStatus {
+    /// `unenforced`, `unlicensed`, `valid`, `grace`, `expired` or `invalid`.
+    pub state: &'static str,
+    pub claims: Option<Claims>,
+    /// Features usable right now.
+    pub features: Vec<Feature>,
+    /// When features turn off unless the lease is renewed.
+    pub grace_until: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub install_id: String,
+}
+
+#[derive(Default)]
+pub struct Licensing {
+    license: StdMutex<Option<std::result::Result<Claims, String>>>,
+    install_id: StdMutex<String>,
+}
+
+/// Loads the installed license and keeps an activated one renewed.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let licensing = Licensing::default();
+    let install_id = install_id(app)?;
+    *licensing.license.lock().unwrap() = load(app, &install_id)?;
+    *licensing.install_id.lock().unwrap() = install_id;
+    app.manage(licensing);
+    if PUBLIC_KEY.is_some() {
+        tauri::async_runtime::spawn(renew_loop(app.clone()));
+    }
+    Ok(())
+}
+
+/// Fails unless `feature` is licensed, for commands of premium subsystems.
+pub fn require(app: &AppHandle, feature: Feature) -> Result<()> {
+    let status = status(app);
+    if status.features.contains(&feature) {
+        return Ok(());
+    }
+    Err(Error::License(match status.error {
+        Some(error) => format!("{} needs a license: {error}", feature.label()),
+        None => format!("{} is not covered by the license", feature.label()),
+    }))
+}
+
+#[tauri::command]
+pub fn get_license_status(app: AppHandle) -> LicenseStatus {
+    status(&app)
+}
+
+/// Installs a license file obtained offline.
+#[tauri::command]
+pub fn install_license_file(app: AppHandle, path: PathBuf) -> Result<LicenseStatus> {
+    let bytes = std::fs::read(&path)?;
+    install(&app, &bytes)
+}
+
+/// Takes a seat for this installation with `key`.
+#[tauri::command]
+pub async fn activate_license(app: AppHandle, key: String) -> Result<LicenseStatus> {
+    let install_id = app.state::<Licensing>().install_id.lock().unwrap().clone();
+    let body = serde_json::json!({ "licenseKey": key, "installId": install_id });
+    let bytes = post(&app, "/v1/activations", &body).await?;
+    install(&app, &bytes)
+}
+
+fn status(app: &AppHandle) -> LicenseStatus {
+    let licensing = app.state::<Licensing>();
+    let install_id = licensing.install_id.lock().unwrap().clone();
+    let license = licensing.license.lock().unwrap().clone();
+    let mut status = LicenseStatus {
+        state: "unlicensed",
+        claims: None,
+        features: Vec::new(),
+        grace_until: None,
+        error: None,
+        install_id,
+    };
+    if PUBLIC_KEY.is_none() {
+        status.state = "unenforced";
+        status.features = vec![Feature::MultiRobot, Feature::CloudSync];
+        return status;
+    }
+    let claims = match license {
+        None => {
+            status.error = Some("no license is installed".into());
+            return status;
+        }
+        Some(Err(error)) => {
+            status.state = "invalid";
+            status.error = Some(error);
+            return status;
+        }
+        Some(Ok(claims)) => claims,
+    };
+    let now = Utc::now();
+    let grace_days = app.state::<SettingsStore>().get().licensing.grace_days;
+    status.grace_until = claims
+        .lease_until
+        .map(|lease| lease + chrono::Duration::days(i64::from(grace_days)));
+    if claims.expires_at.is_some_and(|expires| expires <= now) {
+        status.state = "expired";
+        status.error = Some("the license has expired".into());
+    } else if status.grace_until.is_some_and(|until| until <= now) {
+        status.state = "expired";
+        status.error = Some("the license lease could not be renewed".into());
+    } else {
+        let leased = claims.lease_until.is_none_or(|lease| lease > now);
+        status.state = if leased { "valid" } else { "grace" };
+        status.features = claims
+            .features
+            .iter()
+            .filter_map(|name| serde_json::from_value(name.as_str().into()).ok())
+            .collect();
+    }
+    status.claims = Some(claims);
+    status
+}
+
+/// Verifies `bytes` as a license for this installation and makes it the
+/// installed one.
+fn install(app: &AppHandle, bytes: &[u8]) -> Result<LicenseStatus> {
+    let install_id = app.state::<Licensing>().install_id.lock().unwrap().clone();
+    let claims = verify(bytes, &install_id).map_err(Error::License)?;
+    let dir = app.path().app_data_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    crate::fsutil::write_atomic(&dir.join(LICENSE_FILE), bytes)?;
+    tracing::info!(license = %claims.license_id, "license installed");
+    *app.state::<Licensing>().license.lock().unwrap() = Some(Ok(claims));
+    let status = status(app);
+    let _ = app.emit("license-changed", status.clone());
+    Ok(status)
+}
+
+fn load(app: &AppHandle, install_id: &str) -> Result<Option<std::result::Result<Claims, String>>> {
+    let path = app.path().app_data_dir()?.join(LICENSE_FILE);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let license = verify(&bytes, install_id);
+    if let Err(error) = &license {
+        tracing::warn!("installed license rejected: {error}");
+    }
+    Ok(Some(license))
+}
+
+fn verify(bytes: &[u8], install_id: &str) -> std::result::Result<Claims, String> {
+    let key = PUBLIC_KEY
+        .and_then(|key| hex::decode(key.trim()).ok())
+        .ok_or("this build has no license key")?;
+    let signed: SignedLicense =
+        serde_json::from_slice(bytes).map_err(|err| format!("not a license file: {err}"))?;
+    let payload = STANDARD
+        .decode(&signed.payload)
+        .map_err(|_| "the license payload is not base64")?;
+    let signature = STANDARD
+        .decode(&signed.signature)
+        .map_err(|_| "the license signature is not base64")?;
+    UnparsedPublicKey::new(&ED25519, key)
+        .verify(&payload, &signature)
+        .map_err(|_| "the license signature is not valid")?;
+    let claims: Claims =
+        serde_json::from_slice(&payload).map_err(|err| format!("malformed license: {err}"))?;
+    if claims
+        .install_id
+        .as_deref()
+        .is_some_and(|id| id != install_id)
+    {
+        return Err("the license was activated on another installation".into());
+    }
+    Ok(claims)
+}
+
+/// Random id of this installation, created on first use.
+fn install_id(app: &AppHandle) -> Result<String> {
+    let dir = app.path().app_data_dir()?;
+    let path = dir.join(INSTALL_ID_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(id) if !id.trim().is_empty() => return Ok(id.trim().to_owned()),
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    let mut id = [0u8; 16];
+    getrandom::fill(&mut id).map_err(|err| Error::Invalid(format!("random: {err}")))?;
+    let id = hex::encode(id);
+    std::fs::create_dir_all(&dir)?;
+    backend_tls::write_private(&path, id.as_bytes())?;
+    Ok(id)
+}
+
+async fn post(app: &AppHandle, path: &str, body: &serde_json::Value) -> Result<Vec<u8>> {
+    let server = app
+        .state::<SettingsStore>()
+        .get()
+        .licensing
+        .server
+        .ok_or_else(|| Error::Invalid("licensing.server is not set".into()))?;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let response = client
+        .post(format!("{}{path}", server.trim_end_matches('/')))
+        .json(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(Error::License(format!(
+            "license server: {status} {message}"
+        )));
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Renews the lease of an activated license before it runs out.
+async fn renew_loop(app: AppHandle) {
+    loop {
+        let interval = Duration::from_secs(
+            app.state::<SettingsStore>()
+                .get()
+                .licensing
+                .renew_interval_hours
+                .max(1)
+                * 3600,
+        );
+        let leased = status(&app)
+            .claims
+            .filter(|claims| claims.lease_until.is_some());
+        let delay = match leased {
+            None => interval,
+            Some(claims) => {
+                let install_id = app.state::<Licensing>().install_id.lock().unwrap().clone();
+                let body = serde_json::json!({ "installId": install_id });
+                let path = format!("/v1/activations/{}/renew", claims.license_id);
+                let renewed = match post(&app, &path, &body).await {
+                    Ok(bytes) => install(&app, &bytes).map(|_| ()),
+                    Err(err) => Err(err),
+                };
+                match renewed {
+                    Ok(()) => interval,
+                    Err(err) => {
+                        tracing::warn!(license = %claims.license_id, "license renewal failed: {err}");
+                        let _ = app.emit("license-changed", status(&app));
+                        RETRY_INTERVAL.min(interval)
+                    }
+                }
+            }
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
@@ -0,0 +1,287 @@
+//! Structured logging for the shell and the sidecar.
+//!
+//! The shell's own tracing output and the sidecar's stdout/stderr are written
+//! as JSON lines to daily-rotated files under the app log directory. The
+//! number of files kept is bounded by `PERCUS_LOG_MAX_FILES`.
+//!
+//! Sidecar lines are also parsed and forwarded to the webview as `backend-log`
+//! events (`session:<id>:backend-log` for other sessions' backends) through
+//! a bounded channel. When the webview falls behind, lines are
+//! dropped rather than stalling the sidecar's pipes, and the next delivered
+//! entry carries the number of lines lost.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+use crate::metrics;
+use crate::otel;
+use crate::sidecar;
+
+const FILE_PREFIX: &str = "percus";
+const FILE_SUFFIX: &str = "jsonl";
+const DEFAULT_MAX_FILES: usize = 14;
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+/// Sidecar lines kept in memory for crash bundles.
+const RECENT_LINES: usize = 500;
+
+/// Keeps the background log writer alive and feeds the `backend-log` stream.
+pub struct LogState {
+    events: mpsc::Sender<BackendLog>,
+    dropped: AtomicU64,
+    recent: Mutex<VecDeque<String>>,
+    _guard: WorkerGuard,
+}
+
+impl LogState {
+    /// The most recent sidecar output lines, oldest first.
+    ///
+    /// Uses `try_lock` because this is also called from the panic hook.
+    pub fn recent_lines(&self) -> Vec<String> {
+        match self.recent.try_lock() {
+            Ok(recent) => recent.iter().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn remember(&self, line: &str) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(line.to_owned());
+    }
+}
+
+/// Output stream of the sidecar a line was read from.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Severity of a backend log line, following Python's `logging` levels.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "DEBUG" => Some(Level::Debug),
+            "INFO" => Some(Level::Info),
+            "WARNING" | "WARN" => Some(Level::Warning),
+            "ERROR" => Some(Level::Error),
+            "CRITICAL" | "FATAL" => Some(Level::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// Payload of the `backend-log` event.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendLog {
+    pub stream: Stream,
+    pub level: Level,
+    pub timestamp: Option<String>,
+    pub logger: Option<String>,
+    pub message: String,
+    /// Lines discarded since the previous delivered entry.
+    pub dropped: u64,
+    /// Session whose backend logged this; see [`crate::sidecar`].
+    #[serde(skip)]
+    pub session: Option<String>,
+}
+
+/// Installs the global subscriber; must run before anything else logs.
+pub fn init(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = app.path().app_log_dir()?;
+    std::fs::create_dir_all(&dir)?;
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(max_files())
+        .build(&dir)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    // `RUST_LOG` filters the log output only; exported spans have their
+    // own filter, see [`crate::otel`].
+    let (export, otel) = otel::layer();
+    let (command_timer, metrics) = metrics::layer();
+    tracing_subscriber::registry()
+        .with(export)
+        .with(command_timer)
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(log_filter()),
+        )
+        .with(
+            fmt::layer()
+                .json()
+                .with_writer(writer)
+                .with_filter(log_filter()),
+        )
+        .try_init()?;
+    app.manage(otel);
+    app.manage(metrics);
+
+    let (events, rx) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+    tauri::async_runtime::spawn(forward_events(app.clone(), rx));
+
+    tracing::info!(dir = %dir.display(), "logging initialised");
+    app.manage(LogState {
+        events,
+        dropped: AtomicU64::new(0),
+        recent: Mutex::new(VecDeque::with_capacity(RECENT_LINES)),
+        _guard: guard,
+    });
+    Ok(())
+}
+
+async fn forward_events(app: AppHandle, mut rx: mpsc::Receiver<BackendLog>) {
+    while let Some(entry) = rx.recv().await {
+        let event = match &entry.session {
+            Some(id) => sidecar::session_event(id, "backend-log"),
+            None => "backend-log".to_owned(),
+        };
+        let _ = app.emit(&event, entry);
+    }
+}
+
+/// Parses and records the output of one sidecar run.
+///
+/// Lines only carry a level when they start a Python log record, so
+/// continuation lines (tracebacks, multi-line messages) inherit the level of
+/// the record they belong to.
+pub struct SidecarLog {
+    app: AppHandle,
+    session: Option<String>,
+    last_level: [Level; 2],
+}
+
+impl SidecarLog {
+    pub fn new(app: &AppHandle) -> Self {
+        Self {
+            app: app.clone(),
+            session: None,
+            last_level: [Level::Info; 2],
+        }
+    }
+
+    /// For the backend of session `id`, whose lines are kept out of crash
+    /// reports.
+    pub fn for_session(app: &AppHandle, id: &str) -> Self {
+        Self {
+            session: Some(id.to_owned()),
+            ..Self::new(app)
+        }
+    }
+
+    pub fn line(&mut self, stream: Stream, bytes: &[u8]) {
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end();
+        if line.is_empty() {
+            return;
+        }
+
+        let mut entry = parse_record(stream, line).unwrap_or_else(|| BackendLog {
+            stream,
+            level: self.last_level[stream as usize],
+            timestamp: None,
+            logger: None,
+            message: line.to_owned(),
+            dropped: 0,
+            session: None,
+        });
+        entry.session = self.session.clone();
+        self.last_level[stream as usize] = entry.level;
+        record(&entry);
+
+        let state = self.app.state::<LogState>();
+        if self.session.is_none() {
+            state.remember(line);
+        }
+        entry.dropped = state.dropped.swap(0, Ordering::Relaxed);
+        if let Err(err) = state.events.try_send(entry) {
+            let lost = match err {
+                mpsc::error::TrySendError::Full(entry) => entry.dropped + 1,
+                mpsc::error::TrySendError::Closed(entry) => entry.dropped + 1,
+            };
+            state.dropped.fetch_add(lost, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Parses the backend's `asctime - name - levelname - message` format.
+fn parse_record(stream: Stream, line: &str) -> Option<BackendLog> {
+    let mut parts = line.splitn(4, " - ");
+    let timestamp = parts.next()?;
+    let logger = parts.next()?;
+    let level = Level::parse(parts.next()?)?;
+    let message = parts.next()?;
+    Some(BackendLog {
+        stream,
+        level,
+        timestamp: Some(timestamp.to_owned()),
+        logger: Some(logger.to_owned()),
+        message: message.to_owned(),
+        dropped: 0,
+        session: None,
+    })
+}
+
+fn record(entry: &BackendLog) {
+    let stream = entry.stream.as_str();
+    let logger = entry.logger.as_deref().unwrap_or("");
+    let session = entry.session.as_deref().unwrap_or("");
+    let message = &entry.message;
+    match entry.level {
+        Level::Debug => tracing::debug!(target: "backend", stream, logger, session, "{message}"),
+        Level::Info => tracing::info!(target: "backend", stream, logger, session, "{message}"),
+        Level::Warning => tracing::warn!(target: "backend", stream, logger, session, "{message}"),
+        Level::Error | Level::Critical => {
+            tracing::error!(target: "backend", stream, logger, session, "{message}")
+        }
+    }
+}
+
+fn log_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+fn max_files() -> usize {
+    std::env::var("PERCUS_LOG_MAX_FILES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|&count| count > 0)
+        .unwrap_or(DEFAULT_MAX_FILES)
+}
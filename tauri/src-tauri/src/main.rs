@@ -1,26 +1,481 @@
 //! Tauri application with backend sidecar.
 
-use tauri::Manager;
+use tauri::{Manager, RunEvent};
+
+mod analytics;
+mod audio;
+mod audit;
+mod auth;
+mod automation;
+mod backend_errors;
+mod backend_socket;
+mod backend_tls;
+mod bag;
+mod calibration;
+mod camera;
+mod canbus;
+mod cli;
+mod code_scan;
+mod compat;
+mod crash;
+mod daihen_fd;
+mod dataset_import;
+mod dataset_media;
+mod datasets;
+mod deep_link;
+mod diagnostics;
+mod discovery;
+mod disk_guard;
+mod downloads;
+mod driver_plugins;
+mod encoding;
+mod error;
+mod estop;
+mod event_batching;
+mod firmware;
+mod fleet;
+mod formatting;
+mod frames;
+mod fsutil;
+mod ft_sensor;
+mod gamepad;
+mod gripper;
+mod grpc;
+mod hdf5_export;
+mod headless;
+mod hub;
+mod input;
+mod instance;
+mod integrity;
+mod jog;
+mod joint_history;
+mod kiosk;
+mod licensing;
+mod logging;
+mod menu;
+mod metrics;
+mod modbus;
+mod mqtt;
+mod netmon;
+mod notifications;
+mod offline;
+mod opcua;
+mod orphans;
+mod otel;
+mod permissions;
+mod pointcloud;
+mod preflight;
+mod profiles;
+mod pyenv;
+mod readiness;
+mod recording;
+mod remote_assist;
+mod replay;
+mod retention;
+#[cfg(feature = "ros2")]
+mod ros2;
+mod rtsp;
+mod safety_zones;
+mod scheduler;
+mod screen_capture;
+mod secrets;
+mod self_test;
+mod serial;
+mod settings;
+mod shortcuts;
+mod sidecar;
+mod simulator;
+mod stream_qos;
+mod sysmon;
+mod teach;
+mod telemetry;
+mod tf;
+mod time_sync;
+mod tray;
+mod tunnels;
+mod updater;
+mod uploads;
+mod urdf;
+mod users;
+mod voice;
+mod watchdog;
+mod webrtc_relay;
+mod weld_signals;
+mod windows;
+mod ws_proxy;
 
 fn main() {
+    let options = cli::parse_env();
+    let headless = headless::requested();
+    let hidden = options.window == cli::WindowMode::Hidden;
+    let positional = options.positional.clone();
     tauri::Builder::default()
-        .setup(|app| {
+        // Must be registered first so a second launch exits before doing any work.
+        .plugin(tauri_plugin_single_instance::init(
+            instance::on_second_instance,
+        ))
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app, shortcut, event| {
+                    estop::on_shortcut(app, shortcut, event);
+                    shortcuts::on_shortcut(app, shortcut, event);
+                })
+                .build(),
+        )
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Focused(focused) = event {
+                shortcuts::on_focus(window.app_handle(), *focused);
+            }
+        })
+        .register_uri_scheme_protocol(frames::SCHEME, frames::handle)
+        .register_asynchronous_uri_scheme_protocol(backend_socket::SCHEME, backend_socket::handle)
+        .register_asynchronous_uri_scheme_protocol(dataset_media::SCHEME, dataset_media::handle)
+        .manage(options)
+        .manage(updater::PendingUpdate::default())
+        .manage(serial::SerialPorts::default())
+        .manage(daihen_fd::FdController::default())
+        .manage(modbus::WeldLink::default())
+        .manage(canbus::CanInterfaces::default())
+        .manage(gamepad::GamepadCapture::default())
+        .manage(input::spacemouse::SpaceMouse::default())
+        .manage(webrtc_relay::Streams::default())
+        .manage(rtsp::RtspStreams::default())
+        .manage(ws_proxy::WsProxy::default())
+        .manage(compat::Compatibility::default())
+        .manage(notifications::Notifications::default())
+        .manage(grpc::Gateway::default())
+        .manage(discovery::Discovery::default())
+        .manage(opcua::OpcUa::default())
+        .manage(recording::Recorder::default())
+        .manage(tunnels::Tunnels::default())
+        .manage(bag::Bag::default())
+        .manage(hdf5_export::Hdf5Exports::default())
+        .manage(preflight::LastPreflight::default())
+        .manage(screen_capture::ScreenCapture::default())
+        .manage(remote_assist::RemoteAssist::default())
+        .manage(jog::Jog::default())
+        .manage(weld_signals::WeldSignals::default())
+        .manage(users::Users::default())
+        .manage(downloads::Downloads::default())
+        .manage(hub::Hub::default())
+        .manage(auth::Auth::default())
+        .manage(firmware::Firmware::default())
+        .manage(calibration::Calibration::default())
+        .manage(pointcloud::PointClouds::default())
+        .manage(gripper::Gripper::default())
+        .manage(replay::Replays::default())
+        .manage(encoding::Encoders::default())
+        .manage(code_scan::CodeScanner::default())
+        .invoke_handler(tauri::generate_handler![
+            sidecar::get_backend_port,
+            backend_socket::get_backend_url,
+            compat::get_backend_compatibility,
+            sidecar::backend_status,
+            sidecar::restart_backend,
+            sidecar::create_session,
+            sidecar::list_sessions,
+            sidecar::stop_session,
+            tunnels::open_tunnel,
+            tunnels::close_tunnel,
+            tunnels::tunnel_status,
+            preflight::run_network_preflight,
+            diagnostics::export_diagnostics,
+            screen_capture::start_screen_capture,
+            screen_capture::stop_screen_capture,
+            remote_assist::get_remote_assist_status,
+            remote_assist::accept_remote_assist_offer,
+            remote_assist::respond_remote_assist,
+            remote_assist::end_remote_assist,
+            simulator::start_sim,
+            simulator::stop_sim,
+            simulator::sim_status,
+            jog::jog,
+            jog::stop_jog,
+            jog::jog_status,
+            permissions::get_permission_status,
+            profiles::list_profiles,
+            profiles::switch_profile,
+            settings::get_settings,
+            settings::set_settings,
+            secrets::set_secret,
+            secrets::get_secret,
+            secrets::delete_secret,
+            auth::start_device_login,
+            auth::start_browser_login,
+            auth::get_access_token,
+            auth::logout,
+            kiosk::enter_kiosk,
+            kiosk::exit_kiosk,
+            kiosk::set_supervisor_pin,
+            crash::list_crash_reports,
+            deep_link::take_deep_link,
+            updater::check_for_updates,
+            updater::install_update,
+            notifications::run_notification_action,
+            scheduler::list_jobs,
+            scheduler::run_job_now,
+            disk_guard::get_disk_status,
+            retention::preview_pruning,
+            serial::list_serial_ports,
+            serial::open_serial,
+            serial::write_serial,
+            serial::close_serial,
+            firmware::list_firmware_devices,
+            firmware::prepare_firmware_flash,
+            firmware::flash_firmware,
+            calibration::start_calibration,
+            calibration::capture_calibration_frame,
+            calibration::solve_calibration,
+            code_scan::start_code_scan,
+            code_scan::stop_code_scan,
+            pointcloud::start_point_cloud,
+            pointcloud::stop_point_cloud,
+            ft_sensor::start_ft_sensor,
+            ft_sensor::stop_ft_sensor,
+            ft_sensor::tare_ft_sensor,
+            ft_sensor::set_ft_alarm,
+            weld_signals::start_weld_signals,
+            weld_signals::stop_weld_signals,
+            weld_signals::set_weld_signal_bucket,
+            gripper::activate_gripper,
+            gripper::set_gripper_position,
+            gripper::get_gripper_status,
+            gripper::start_gripper_polling,
+            gripper::stop_gripper_polling,
+            joint_history::query_joint_history,
+            tf::lookup_transform,
+            tf::tf_snapshot,
+            urdf::load_urdf,
+            urdf::forward_kinematics,
+            urdf::check_joint_limits,
+            safety_zones::safety_zone_status,
+            safety_zones::zone_violation_log,
+            shortcuts::get_keymap,
+            shortcuts::set_binding,
+            event_batching::event_batch_stats,
+            audit::query_audit_log,
+            audit::verify_audit_log,
+            audit::export_audit_log,
+            users::current_user,
+            users::login_pin,
+            users::login_sso,
+            users::logout_user,
+            users::list_users,
+            users::set_user,
+            users::remove_user,
+            teach::list_point_lists,
+            teach::get_point_list,
+            teach::save_point_list,
+            teach::delete_point_list,
+            teach::capture_waypoint,
+            teach::remove_waypoint,
+            teach::move_waypoint,
+            teach::export_point_list,
+            fleet::get_fleet_status,
+            fleet::respond_fleet_command,
+            dataset_media::episode_media_url,
+            formatting::get_format_preferences,
+            self_test::run_self_test,
+            time_sync::get_time_sync_status,
+            time_sync::sync_clocks,
+            driver_plugins::list_plugins,
+            driver_plugins::load_plugin,
+            driver_plugins::invoke_plugin,
+            daihen_fd::connect_robot,
+            daihen_fd::disconnect_robot,
+            daihen_fd::robot_status,
+            daihen_fd::start_program,
+            daihen_fd::stop_program,
+            daihen_fd::read_robot_variable,
+            daihen_fd::write_robot_variable,
+            modbus::start_weld_telemetry,
+            modbus::stop_weld_telemetry,
+            modbus::write_weld_setpoint,
+            canbus::open_can,
+            canbus::close_can,
+            canbus::send_can_frame,
+            canbus::subscribe_can,
+            gamepad::start_gamepad,
+            gamepad::stop_gamepad,
+            input::spacemouse::start_spacemouse,
+            input::spacemouse::stop_spacemouse,
+            input::spacemouse::zero_spacemouse,
+            input::estop_button::get_estop_device_status,
+            webrtc_relay::start_stream,
+            webrtc_relay::stop_stream,
+            rtsp::start_rtsp,
+            rtsp::stop_rtsp,
+            rtsp::list_rtsp_streams,
+            camera::list_cameras,
+            camera::open_camera,
+            ws_proxy::ws_subscribe,
+            ws_proxy::ws_unsubscribe,
+            ws_proxy::ws_send,
+            licensing::get_license_status,
+            licensing::install_license_file,
+            licensing::activate_license,
+            grpc::grpc_list_robots,
+            grpc::grpc_get_robot_state,
+            grpc::grpc_send_command,
+            grpc::grpc_subscribe_robot_state,
+            grpc::grpc_subscribe_events,
+            grpc::grpc_unsubscribe,
+            mqtt::publish_mqtt,
+            discovery::start_discovery,
+            discovery::stop_discovery,
+            discovery::get_discovered_devices,
+            opcua::connect_opcua,
+            opcua::disconnect_opcua,
+            opcua::opcua_read,
+            opcua::opcua_write,
+            opcua::opcua_subscribe,
+            opcua::opcua_unsubscribe,
+            recording::start_recording,
+            recording::stop_recording,
+            recording::discard_episode,
+            recording::set_audio_muted,
+            recording::list_recovered_episodes,
+            recording::recover_episode,
+            recording::discard_recovered_episode,
+            audio::list_audio_devices,
+            voice::set_ptt,
+            datasets::list_datasets,
+            datasets::list_episodes,
+            datasets::inspect_episode,
+            datasets::rename_dataset,
+            datasets::delete_dataset,
+            datasets::delete_episode,
+            dataset_import::import_dataset,
+            dataset_import::take_pending_replay,
+            replay::open_replay,
+            replay::play_replay,
+            replay::pause_replay,
+            replay::seek_replay,
+            replay::set_replay_rate,
+            replay::close_replay,
+            bag::start_bag,
+            bag::stop_bag,
+            hdf5_export::export_hdf5,
+            uploads::enqueue_upload,
+            uploads::list_uploads,
+            uploads::pause_uploads,
+            uploads::resume_uploads,
+            uploads::remove_upload,
+            offline::get_offline_queue_status,
+            analytics::track_event,
+            analytics::set_analytics_enabled,
+            analytics::flush_analytics,
+            downloads::download_models,
+            downloads::cancel_download,
+            hub::set_hub_token,
+            hub::hub_whoami,
+            hub::push_dataset_to_hub,
+            hub::pull_model_from_hub,
+            sysmon::get_system_metrics_history,
+            netmon::get_network_quality,
+            watchdog::heartbeat,
+            windows::open_camera_window,
+            windows::open_telemetry_window,
+            windows::subscribe_events,
+            windows::unsubscribe_events,
+            #[cfg(feature = "ros2")]
+            ros2::start_ros2,
+            #[cfg(feature = "ros2")]
+            ros2::stop_ros2,
+            #[cfg(feature = "ros2")]
+            ros2::ros2_publish_twist,
+            #[cfg(feature = "ros2")]
+            ros2::ros2_publish_joint_jog,
+        ])
+        .setup(move |app| {
+            logging::init(app.handle())?;
+            crash::init(app.handle())?;
             // Start backend server as sidecar
             // The backend binary should be bundled with the app
-            let sidecar = app.shell().sidecar("percus-server")?;
-            let (mut rx, _child) = sidecar.args(["--port", "8000"]).spawn()?;
-
-            // Log sidecar output
-            tauri::async_runtime::spawn(async move {
-                while let Some(event) = rx.recv().await {
-                    if let tauri::api::process::CommandEvent::Stdout(line) = event {
-                        println!("[backend] {}", line);
-                    }
+            settings::init(app.handle())?;
+            audit::init(app.handle())?;
+            otel::init(app.handle());
+            profiles::init(app.handle())?;
+            simulator::init(app.handle());
+            licensing::init(app.handle())?;
+            windows::init(app.handle())?;
+            event_batching::init(app.handle());
+            // Also creates the main window, which headless runs go without.
+            if !headless {
+                kiosk::init(app.handle())?;
+            }
+            deep_link::init(app.handle());
+            dataset_import::init(app.handle())?;
+            recording::init(app.handle());
+            disk_guard::init(app.handle());
+            // Launched through a `.percus` file association.
+            dataset_import::open_archives(
+                app.handle(),
+                positional,
+                &std::env::current_dir().unwrap_or_default(),
+            );
+            frames::init(app.handle())?;
+            telemetry::init(app.handle())?;
+            uploads::init(app.handle())?;
+            offline::init(app.handle())?;
+            scheduler::init(app.handle())?;
+            remote_assist::init(app.handle());
+            fleet::init(app.handle());
+            analytics::init(app.handle())?;
+            sysmon::init(app.handle())?;
+            metrics::init(app.handle());
+            estop::init(app.handle());
+            shortcuts::init(app.handle());
+            ft_sensor::init(app.handle());
+            joint_history::init(app.handle());
+            urdf::init(app.handle());
+            tf::init(app.handle());
+            safety_zones::init(app.handle());
+            time_sync::init(app.handle());
+            driver_plugins::init(app.handle());
+            input::estop_button::init(app.handle())?;
+            #[cfg(feature = "ros2")]
+            ros2::init(app.handle());
+            if headless {
+                headless::init(app.handle())?;
+            } else {
+                tray::init(app.handle())?;
+                menu::init(app.handle())?;
+                if !hidden {
+                    readiness::show_splash(app.handle())?;
                 }
-            });
-
+            }
+            backend_tls::init(app.handle())?;
+            sidecar::start(app.handle().clone());
+            netmon::init(app.handle());
+            watchdog::init(app.handle());
+            mqtt::init(app.handle());
+            voice::init(app.handle());
+            automation::init(app.handle())?;
+            self_test::init(app.handle());
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            RunEvent::ExitRequested { api, .. } => sidecar::on_exit_requested(app, &api),
+            // macOS delivers opened files as an event instead of arguments.
+            #[cfg(target_os = "macos")]
+            RunEvent::Opened { urls } => {
+                let paths = urls.iter().filter_map(|url| url.to_file_path().ok());
+                dataset_import::open_archives(app, paths, std::path::Path::new("/"));
+            }
+            RunEvent::Exit => {
+                telemetry::flush_now(app);
+                tunnels::close_all(app);
+                sidecar::kill_now(app);
+                otel::shutdown(app);
+            }
+            _ => {}
+        });
 }
@@ -0,0 +1,247 @@
+//! Native application menu.
+//!
+//! Robot and View items call the shell's command handlers directly. Items
+//! that need the webview (picking a file, showing a page) focus the main
+//! window and emit `menu-action` to it with `{"action": ...}`, one of
+//! `import-episode`, `export-bundle` or `diagnostics`.
+//!
+//! [`sync`] keeps the Robot items in step with the controller connection:
+//! Connect while disconnected, Disconnect and E-Stop while connected. The
+//! Camera Windows submenu lists the configured cameras and follows
+//! `settings-changed`.
+
+use serde::Serialize;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::{AppHandle, Emitter, Listener, Manager, Wry};
+
+use crate::daihen_fd::{self, FdController};
+use crate::estop;
+use crate::instance::focus_main_window;
+use crate::settings::{Settings, SettingsStore};
+use crate::windows;
+
+const IMPORT_EPISODE: &str = "menu-import-episode";
+const EXPORT_BUNDLE: &str = "menu-export-bundle";
+const CONNECT_ROBOT: &str = "menu-connect-robot";
+const DISCONNECT_ROBOT: &str = "menu-disconnect-robot";
+const ESTOP: &str = "menu-estop";
+const TELEMETRY_WINDOW: &str = "menu-telemetry-window";
+const CAMERA_PREFIX: &str = "menu-camera:";
+const DIAGNOSTICS: &str = "menu-diagnostics";
+
+#[derive(Clone, Serialize)]
+struct MenuAction {
+    action: &'static str,
+}
+
+/// Items whose state changes at runtime.
+struct AppMenu {
+    connect: MenuItem<Wry>,
+    disconnect: MenuItem<Wry>,
+    estop: MenuItem<Wry>,
+    cameras: Submenu<Wry>,
+}
+
+/// Builds the menu and installs it on the app (macOS) or the main window.
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let item = |id: &str, text: &str, accelerator: Option<&str>| {
+        MenuItem::with_id(app, id, text, true, accelerator)
+    };
+    let connect = item(CONNECT_ROBOT, "Connect", None)?;
+    let disconnect = item(DISCONNECT_ROBOT, "Disconnect", None)?;
+    let estop = item(ESTOP, "E-Stop", None)?;
+    let cameras = Submenu::with_id(app, "menu-cameras", "Camera Windows", true)?;
+
+    let file = Submenu::with_items(
+        app,
+        "File",
+        true,
+        &[
+            &item(IMPORT_EPISODE, "Import Episode…", Some("CmdOrCtrl+O"))?,
+            &item(EXPORT_BUNDLE, "Export Bundle…", Some("CmdOrCtrl+E"))?,
+            &PredefinedMenuItem::separator(app)?,
+            &PredefinedMenuItem::close_window(app, None)?,
+        ],
+    )?;
+    let robot = Submenu::with_items(
+        app,
+        "Robot",
+        true,
+        &[
+            &connect,
+            &disconnect,
+            &PredefinedMenuItem::separator(app)?,
+            &estop,
+        ],
+    )?;
+    let view = Submenu::with_items(
+        app,
+        "View",
+        true,
+        &[
+            &cameras,
+            &item(TELEMETRY_WINDOW, "Telemetry Window", None)?,
+            &PredefinedMenuItem::separator(app)?,
+            &PredefinedMenuItem::fullscreen(app, None)?,
+        ],
+    )?;
+    let help = Submenu::with_items(
+        app,
+        "Help",
+        true,
+        &[&item(DIAGNOSTICS, "Diagnostics", None)?],
+    )?;
+
+    let menu = Menu::new(app)?;
+    // macOS puts the first submenu under the app's name, and text editing
+    // shortcuts in the webview only work with an Edit menu present.
+    #[cfg(target_os = "macos")]
+    {
+        menu.append(&Submenu::with_items(
+            app,
+            &app.package_info().name,
+            true,
+            &[
+                &PredefinedMenuItem::about(app, None, None)?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::hide(app, None)?,
+                &PredefinedMenuItem::hide_others(app, None)?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::quit(app, None)?,
+            ],
+        )?)?;
+        menu.append(&file)?;
+        menu.append(&Submenu::with_items(
+            app,
+            "Edit",
+            true,
+            &[
+                &PredefinedMenuItem::undo(app, None)?,
+                &PredefinedMenuItem::redo(app, None)?,
+                &PredefinedMenuItem::separator(app)?,
+                &PredefinedMenuItem::cut(app, None)?,
+                &PredefinedMenuItem::copy(app, None)?,
+                &PredefinedMenuItem::paste(app, None)?,
+                &PredefinedMenuItem::select_all(app, None)?,
+            ],
+        )?)?;
+    }
+    #[cfg(not(target_os = "macos"))]
+    menu.append(&file)?;
+    menu.append_items(&[&robot, &view, &help])?;
+
+    #[cfg(target_os = "macos")]
+    app.set_menu(menu)?;
+    #[cfg(not(target_os = "macos"))]
+    if let Some(window) = app.get_webview_window("main") {
+        window.set_menu(menu)?;
+    }
+    app.on_menu_event(on_menu_event);
+
+    fill_cameras(app, &cameras, &app.state::<SettingsStore>().get());
+    app.manage(AppMenu {
+        connect,
+        disconnect,
+        estop,
+        cameras,
+    });
+    sync(app);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            fill_cameras(&handle, &handle.state::<AppMenu>().cameras, &settings);
+        }
+    });
+    Ok(())
+}
+
+/// Enables the Robot items that apply to the current connection.
+pub fn sync(app: &AppHandle) {
+    let Some(menu) = app.try_state::<AppMenu>() else {
+        return;
+    };
+    let connected = app.state::<FdController>().is_connected();
+    let _ = menu.connect.set_enabled(!connected);
+    let _ = menu.disconnect.set_enabled(connected);
+    let _ = menu.estop.set_enabled(connected);
+}
+
+/// Replaces the Camera Windows items with the cameras in `settings`.
+fn fill_cameras(app: &AppHandle, cameras: &Submenu<Wry>, settings: &Settings) {
+    if let Ok(items) = cameras.items() {
+        for item in items {
+            let _ = cameras.remove(&item);
+        }
+    }
+    let sources = settings
+        .backend
+        .camera_indices
+        .iter()
+        .map(|index| (index.to_string(), format!("Camera {index}")))
+        .chain(
+            settings
+                .rtsp
+                .cameras
+                .iter()
+                .map(|camera| (camera.name.clone(), camera.name.clone())),
+        );
+    let mut empty = true;
+    for (source, label) in sources {
+        let id = format!("{CAMERA_PREFIX}{source}");
+        if let Ok(item) = MenuItem::with_id(app, id, label, true, None::<&str>) {
+            let _ = cameras.append(&item);
+            empty = false;
+        }
+    }
+    if empty {
+        if let Ok(item) = MenuItem::new(app, "No cameras configured", false, None::<&str>) {
+            let _ = cameras.append(&item);
+        }
+    }
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    let result = match id {
+        IMPORT_EPISODE => webview_action(app, "import-episode"),
+        EXPORT_BUNDLE => webview_action(app, "export-bundle"),
+        DIAGNOSTICS => webview_action(app, "diagnostics"),
+        CONNECT_ROBOT => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let controller = app.state::<FdController>();
+                if let Err(err) = daihen_fd::connect_robot(app.clone(), controller).await {
+                    tracing::warn!("robot connection from the menu failed: {err}");
+                }
+            });
+            Ok(())
+        }
+        DISCONNECT_ROBOT => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let _ = daihen_fd::disconnect_robot(app.clone(), app.state()).await;
+            });
+            Ok(())
+        }
+        ESTOP => {
+            estop::trigger(app, "menu", "application menu".into());
+            Ok(())
+        }
+        TELEMETRY_WINDOW => windows::open_telemetry_window(app.clone()).map(drop),
+        _ => match id.strip_prefix(CAMERA_PREFIX) {
+            Some(source) => windows::open_camera_window(app.clone(), source.to_owned()).map(drop),
+            // Tray items arrive here as well.
+            None => Ok(()),
+        },
+    };
+    if let Err(err) = result {
+        tracing::warn!(item = id, "menu action failed: {err}");
+    }
+}
+
+fn webview_action(app: &AppHandle, action: &'static str) -> crate::error::Result<()> {
+    focus_main_window(app);
+    app.emit_to("main", "menu-action", MenuAction { action })?;
+    Ok(())
+}
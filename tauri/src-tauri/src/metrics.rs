@@ -0,0 +1,455 @@
+//! Prometheus metrics of the shell, for plant monitoring to scrape.
+//!
+//! With `metrics.enabled`, `GET /metrics` on `metrics.bind` answers the
+//! text exposition format; the listener follows the settings. Binding a
+//! non-loopback address is what lets a monitoring server reach it, and
+//! there is no authentication, so only do that on the plant network.
+//!
+//! | Metric | Kind | Source |
+//! | --- | --- | --- |
+//! | `percus_sidecar_up` | gauge | whether the primary backend runs |
+//! | `percus_sidecar_restarts_total` | counter | its restarts since launch |
+//! | `percus_stream_fps{stream}` | gauge | frames sent per relayed stream, see [`crate::stream_qos`] |
+//! | `percus_stream_throughput_kbps{stream}` | gauge | the same streams' throughput |
+//! | `percus_recorder_recording` | gauge | whether an episode records |
+//! | `percus_recorder_buffered_rows` | gauge | rows held until the episode is saved |
+//! | `percus_recorder_lag_seconds` | gauge | how late the recorder took its last sample |
+//! | `percus_uploads_waiting` | gauge | uploads queued or running |
+//! | `percus_command_duration_seconds{command}` | histogram | command handler latency |
+//! | `percus_cpu_usage_percent` | gauge | see [`crate::sysmon`] |
+//! | `percus_memory_used_bytes`, `percus_memory_total_bytes` | gauge | |
+//! | `percus_disk_total_bytes{mount}`, `percus_disk_available_bytes{mount}` | gauge | |
+//!
+//! Command latencies are taken from Tauri's `ipc::request::handle` spans,
+//! which last until a command's response is sent, by the layer [`layer`]
+//! returns for [`crate::logging`] to install.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Listener, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::recording::Recorder;
+use crate::settings::{MetricsSettings, Settings, SettingsStore};
+use crate::sidecar::SidecarState;
+use crate::sysmon::SystemMonitor;
+use crate::uploads::Uploads;
+
+/// Tauri's span around a command, from the request to its response.
+const COMMAND_SPAN: &str = "ipc::request::handle";
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Streams not measured for this long have ended.
+const STREAM_STALE: Duration = Duration::from_secs(10);
+/// Larger request heads are refused.
+const MAX_REQUEST: usize = 8 * 1024;
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last is `+Inf`.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+}
+
+struct StreamWindow {
+    fps: f64,
+    throughput_kbps: f64,
+    at: Instant,
+}
+
+type Commands = Arc<StdMutex<BTreeMap<String, Histogram>>>;
+
+/// What the shell measures between scrapes.
+pub struct Metrics {
+    commands: Commands,
+    streams: StdMutex<BTreeMap<u32, StreamWindow>>,
+    buffered_rows: AtomicU64,
+    /// Nanoseconds.
+    recorder_lag: AtomicU64,
+    listener: StdMutex<Option<(String, JoinHandle<()>)>>,
+}
+
+/// Times the spans of [`COMMAND_SPAN`].
+struct CommandTimer(Commands);
+
+/// The layer feeding `percus_command_duration_seconds`, to install next to
+/// the log output, and the state to manage.
+pub fn layer<S>() -> (impl Layer<S>, Metrics)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let commands = Commands::default();
+    let metrics = Metrics {
+        commands: commands.clone(),
+        streams: StdMutex::default(),
+        buffered_rows: AtomicU64::new(0),
+        recorder_lag: AtomicU64::new(0),
+        listener: StdMutex::new(None),
+    };
+    let layer = CommandTimer(commands).with_filter(filter_fn(|meta| meta.name() == COMMAND_SPAN));
+    (layer, metrics)
+}
+
+/// Starts the listener when enabled, and follows the settings.
+pub fn init(app: &AppHandle) {
+    listen(app, &app.state::<SettingsStore>().get().metrics);
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            listen(&handle, &settings.metrics);
+        }
+    });
+}
+
+/// Records a measurement window of relayed stream `id`.
+pub fn stream_window(app: &AppHandle, id: u32, fps: f64, throughput_kbps: f64) {
+    let window = StreamWindow {
+        fps,
+        throughput_kbps,
+        at: Instant::now(),
+    };
+    app.state::<Metrics>()
+        .streams
+        .lock()
+        .unwrap()
+        .insert(id, window);
+}
+
+/// Records a recorder sample taken `lag` after it was due, with `rows`
+/// held for the episode.
+pub fn recorder_sample(app: &AppHandle, rows: usize, lag: Duration) {
+    let metrics = app.state::<Metrics>();
+    metrics.buffered_rows.store(rows as u64, Ordering::Relaxed);
+    metrics
+        .recorder_lag
+        .store(lag.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Replaces the listener with one for `settings`.
+fn listen(app: &AppHandle, settings: &MetricsSettings) {
+    let bind = settings.enabled.then(|| settings.bind.clone());
+    let metrics = app.state::<Metrics>();
+    let mut listener = metrics.listener.lock().unwrap();
+    if listener.as_ref().map(|(current, _)| current) == bind.as_ref() {
+        return;
+    }
+    if let Some((_, task)) = listener.take() {
+        task.abort();
+    }
+    if let Some(bind) = bind {
+        let task = tauri::async_runtime::spawn(serve(app.clone(), bind.clone()));
+        *listener = Some((bind, task));
+    }
+}
+
+async fn serve(app: AppHandle, bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => return tracing::error!(%bind, "metrics endpoint not started: {err}"),
+    };
+    tracing::info!(%bind, "metrics endpoint listening");
+    while let Ok((stream, _)) = listener.accept().await {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { answer(&app, stream).await });
+    }
+}
+
+/// Answers one request; connections are not kept alive.
+async fn answer(app: &AppHandle, mut stream: TcpStream) {
+    let (status, content_type, body) = match read_request_line(&mut stream).await {
+        Some(line) if line.starts_with("GET /metrics ") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(app),
+        ),
+        Some(_) => ("404 Not Found", "text/plain", "GET /metrics\n".to_owned()),
+        None => ("400 Bad Request", "text/plain", String::new()),
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// The request line, once the whole head has arrived.
+async fn read_request_line(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buffer.len() > MAX_REQUEST {
+            return None;
+        }
+        let len = stream.read(&mut chunk).await.ok()?;
+        if len == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..len]);
+    }
+    let head = String::from_utf8_lossy(&buffer);
+    head.lines().next().map(str::to_owned)
+}
+
+fn render(app: &AppHandle) -> String {
+    let metrics = app.state::<Metrics>();
+    let mut out = String::new();
+
+    let backend = app.state::<SidecarState>().status();
+    gauge(
+        &mut out,
+        "percus_sidecar_up",
+        "Whether the primary backend is running.",
+        &[(String::new(), f64::from(u8::from(backend.running)))],
+    );
+    header(
+        &mut out,
+        "percus_sidecar_restarts_total",
+        "counter",
+        "Restarts of the primary backend since launch.",
+    );
+    let _ = writeln!(
+        out,
+        "percus_sidecar_restarts_total {}",
+        backend.restart_count
+    );
+
+    let mut streams = metrics.streams.lock().unwrap();
+    streams.retain(|_, window| window.at.elapsed() < STREAM_STALE);
+    let label = |id: &u32| format!("{{stream=\"{id}\"}}");
+    let fps: Vec<_> = streams
+        .iter()
+        .map(|(id, window)| (label(id), window.fps))
+        .collect();
+    let throughput: Vec<_> = streams
+        .iter()
+        .map(|(id, window)| (label(id), window.throughput_kbps))
+        .collect();
+    drop(streams);
+    gauge(
+        &mut out,
+        "percus_stream_fps",
+        "Frames per second sent on each relayed camera stream.",
+        &fps,
+    );
+    gauge(
+        &mut out,
+        "percus_stream_throughput_kbps",
+        "Throughput of each relayed camera stream.",
+        &throughput,
+    );
+
+    let recording = app.state::<Recorder>().recording_dataset().is_some();
+    let (rows, lag) = if recording {
+        (
+            metrics.buffered_rows.load(Ordering::Relaxed) as f64,
+            metrics.recorder_lag.load(Ordering::Relaxed) as f64 / 1e9,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+    gauge(
+        &mut out,
+        "percus_recorder_recording",
+        "Whether an episode is being recorded.",
+        &[(String::new(), f64::from(u8::from(recording)))],
+    );
+    gauge(
+        &mut out,
+        "percus_recorder_buffered_rows",
+        "Rows of the episode being recorded held until it is saved.",
+        &[(String::new(), rows)],
+    );
+    gauge(
+        &mut out,
+        "percus_recorder_lag_seconds",
+        "How late the recorder took its last sample.",
+        &[(String::new(), lag)],
+    );
+    gauge(
+        &mut out,
+        "percus_uploads_waiting",
+        "Uploads queued or in progress.",
+        &[(String::new(), app.state::<Uploads>().waiting() as f64)],
+    );
+
+    header(
+        &mut out,
+        "percus_command_duration_seconds",
+        "histogram",
+        "Time from a command's request to its response.",
+    );
+    for (command, histogram) in metrics.commands.lock().unwrap().iter() {
+        let command = escape(command);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "percus_command_duration_seconds_bucket{{command=\"{command}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let total: u64 = histogram.counts.iter().sum();
+        let _ = writeln!(
+            out,
+            "percus_command_duration_seconds_bucket{{command=\"{command}\",le=\"+Inf\"}} {total}"
+        );
+        let _ = writeln!(
+            out,
+            "percus_command_duration_seconds_sum{{command=\"{command}\"}} {}",
+            histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "percus_command_duration_seconds_count{{command=\"{command}\"}} {total}"
+        );
+    }
+
+    if let Some(sample) = app.state::<SystemMonitor>().latest() {
+        gauge(
+            &mut out,
+            "percus_cpu_usage_percent",
+            "CPU usage averaged over all cores.",
+            &[(String::new(), f64::from(sample.cpu_percent))],
+        );
+        gauge(
+            &mut out,
+            "percus_memory_used_bytes",
+            "Memory in use.",
+            &[(String::new(), sample.memory_used_bytes as f64)],
+        );
+        gauge(
+            &mut out,
+            "percus_memory_total_bytes",
+            "Installed memory.",
+            &[(String::new(), sample.memory_total_bytes as f64)],
+        );
+        let mount = |mount: &str| format!("{{mount=\"{}\"}}", escape(mount));
+        let total: Vec<_> = sample
+            .disks
+            .iter()
+            .map(|disk| (mount(&disk.mount_point), disk.total_bytes as f64))
+            .collect();
+        let available: Vec<_> = sample
+            .disks
+            .iter()
+            .map(|disk| (mount(&disk.mount_point), disk.available_bytes as f64))
+            .collect();
+        gauge(
+            &mut out,
+            "percus_disk_total_bytes",
+            "Size of each mounted disk.",
+            &total,
+        );
+        gauge(
+            &mut out,
+            "percus_disk_available_bytes",
+            "Space available on each mounted disk.",
+            &available,
+        );
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// A gauge with one sample per `(labels, value)`; `labels` is empty or
+/// `{name="value",...}`.
+fn gauge(out: &mut String, name: &str, help: &str, samples: &[(String, f64)]) {
+    header(out, name, "gauge", help);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{name}{labels} {value}");
+    }
+}
+
+/// `value` escaped for a label.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// When a command span started, kept in its extensions.
+struct Started {
+    command: String,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct CommandField(Option<String>);
+
+impl Visit for CommandField {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "cmd" {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "cmd" {
+            self.0 = Some(format!("{value:?}").trim_matches('"').to_owned());
+        }
+    }
+}
+
+impl<S> Layer<S> for CommandTimer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut field = CommandField::default();
+        attrs.record(&mut field);
+        if let (Some(command), Some(span)) = (field.0, ctx.span(id)) {
+            span.extensions_mut().insert(Started {
+                command,
+                at: Instant::now(),
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(started) = span.extensions_mut().remove::<Started>() else {
+            return;
+        };
+        self.0
+            .lock()
+            .unwrap()
+            .entry(started.command)
+            .or_default()
+            .observe(started.at.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,349 @@
+//! Modbus/TCP client for welding power source telemetry.
+//!
+//! The register map comes from the `weld` settings section, e.g.
+//!
+//! ```json
+//! { "name": "arcCurrent", "address": 100, "table": "input", "kind": "u16", "scale": 0.1, "unit": "A" }
+//! ```
+//!
+//! A polling task reads every mapped signal and emits `weld-telemetry` events
+//! at `weld.rateHz`. Setpoints can be written to registers marked `writable`,
+//! but only when the caller explicitly passes `confirm: true`.
+
+use std::collections::BTreeMap;
+use std::net::ToSocketAddrs;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::Mutex;
+use tokio_modbus::client::Context;
+use tokio_modbus::prelude::*;
+
+use crate::audit;
+use crate::error::{Error, Result};
+use crate::settings::{SettingsStore, WeldSettings};
+use crate::users::{self, Capability};
+use crate::windows;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterTable {
+    #[default]
+    Holding,
+    Input,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterKind {
+    #[default]
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl RegisterKind {
+    fn width(self) -> u16 {
+        match self {
+            RegisterKind::U16 | RegisterKind::I16 => 1,
+            RegisterKind::U32 | RegisterKind::I32 | RegisterKind::F32 => 2,
+        }
+    }
+
+    /// Decodes big-endian words (high word first) into a raw value.
+    fn decode(self, words: &[u16]) -> f64 {
+        let wide = || (u32::from(words[0]) << 16) | u32::from(words[1]);
+        match self {
+            RegisterKind::U16 => f64::from(words[0]),
+            RegisterKind::I16 => f64::from(words[0] as i16),
+            RegisterKind::U32 => f64::from(wide()),
+            RegisterKind::I32 => f64::from(wide() as i32),
+            RegisterKind::F32 => f64::from(f32::from_bits(wide())),
+        }
+    }
+
+    /// Encodes a raw value, refusing one the register cannot hold rather
+    /// than saturating it.
+    fn encode(self, raw: f64) -> Result<Vec<u16>> {
+        let (min, max) = match self {
+            RegisterKind::U16 => (0.0, f64::from(u16::MAX)),
+            RegisterKind::I16 => (f64::from(i16::MIN), f64::from(i16::MAX)),
+            RegisterKind::U32 => (0.0, f64::from(u32::MAX)),
+            RegisterKind::I32 => (f64::from(i32::MIN), f64::from(i32::MAX)),
+            RegisterKind::F32 => (f64::from(f32::MIN), f64::from(f32::MAX)),
+        };
+        let rounded = match self {
+            RegisterKind::F32 => raw,
+            _ => raw.round(),
+        };
+        if !rounded.is_finite() || rounded < min || rounded > max {
+            return Err(Error::Invalid(format!(
+                "raw value {raw} does not fit the register"
+            )));
+        }
+        let split = |bits: u32| vec![(bits >> 16) as u16, bits as u16];
+        Ok(match self {
+            RegisterKind::U16 => vec![rounded as u16],
+            RegisterKind::I16 => vec![rounded as i16 as u16],
+            RegisterKind::U32 => split(rounded as u32),
+            RegisterKind::I32 => split(rounded as i32 as u32),
+            RegisterKind::F32 => split((rounded as f32).to_bits()),
+        })
+    }
+}
+
+/// One named signal in the welder's register map.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterSignal {
+    pub name: String,
+    pub address: u16,
+    #[serde(default)]
+    pub table: RegisterTable,
+    #[serde(default)]
+    pub kind: RegisterKind,
+    /// Engineering value = raw value × scale.
+    #[serde(default = "unit_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeldTelemetry {
+    pub timestamp: DateTime<Utc>,
+    pub values: BTreeMap<String, f64>,
+    pub errors: BTreeMap<String, String>,
+}
+
+/// Connection to the welder shared by the poller and setpoint writes.
+#[derive(Default)]
+pub struct WeldLink {
+    context: Mutex<Option<Context>>,
+    poller: StdMutex<Option<JoinHandle<()>>>,
+}
+
+impl WeldLink {
+    async fn ensure_connected(&self, settings: &WeldSettings) -> Result<()> {
+        let mut context = self.context.lock().await;
+        if context.is_none() {
+            let host = settings
+                .host
+                .as_deref()
+                .ok_or_else(|| Error::Invalid("no welder address configured".into()))?;
+            let address = (host, settings.port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| Error::NotFound(format!("address for {host}")))?;
+            *context = Some(tcp::connect_slave(address, Slave(settings.unit_id)).await?);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", name = "modbus.read", skip_all, fields(signal = %signal.name, address = signal.address))]
+    async fn read(&self, signal: &RegisterSignal) -> Result<f64> {
+        let mut guard = self.context.lock().await;
+        let context = guard
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("welder not connected".into()))?;
+        let width = signal.kind.width();
+        let words = match signal.table {
+            RegisterTable::Holding => context.read_holding_registers(signal.address, width).await,
+            RegisterTable::Input => context.read_input_registers(signal.address, width).await,
+        };
+        match words {
+            Ok(words) => Ok(signal.kind.decode(&words?) * signal.scale),
+            Err(err) => {
+                // Transport failures invalidate the connection; reconnect next tick.
+                guard.take();
+                Err(err.into())
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "debug", name = "modbus.write", skip(self, signal), fields(signal = %signal.name, address = signal.address))]
+    /// Writes `value` and returns the value the registers now hold, which
+    /// rounding may have changed.
+    async fn write(&self, signal: &RegisterSignal, value: f64) -> Result<f64> {
+        let words = signal.kind.encode(value / signal.scale)?;
+        let mut guard = self.context.lock().await;
+        let context = guard
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("welder not connected".into()))?;
+        context
+            .write_multiple_registers(signal.address, &words)
+            .await??;
+        Ok(signal.kind.decode(&words) * signal.scale)
+    }
+
+    async fn sample(&self, settings: &WeldSettings) -> WeldTelemetry {
+        let mut telemetry = WeldTelemetry {
+            timestamp: Utc::now(),
+            values: BTreeMap::new(),
+            errors: BTreeMap::new(),
+        };
+        if let Err(err) = self.ensure_connected(settings).await {
+            telemetry
+                .errors
+                .insert("connection".into(), err.to_string());
+            return telemetry;
+        }
+        for signal in &settings.registers {
+            match self.read(signal).await {
+                Ok(value) => {
+                    telemetry.values.insert(signal.name.clone(), value);
+                }
+                Err(err) => {
+                    telemetry
+                        .errors
+                        .insert(signal.name.clone(), err.to_string());
+                }
+            }
+        }
+        telemetry
+    }
+}
+
+async fn poll(app: AppHandle, settings: WeldSettings) {
+    let period = Duration::from_secs_f64(1.0 / settings.rate_hz.clamp(0.1, 1000.0));
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let telemetry = app.state::<WeldLink>().sample(&settings).await;
+        windows::emit(&app, "weld-telemetry", telemetry);
+    }
+}
+
+/// Starts polling the welder with the current `weld` settings.
+#[tauri::command]
+pub async fn start_weld_telemetry(app: AppHandle, link: State<'_, WeldLink>) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().weld;
+    link.context.lock().await.take();
+    link.ensure_connected(&settings).await?;
+    let poller = tauri::async_runtime::spawn(poll(app.clone(), settings));
+    if let Some(previous) = link.poller.lock().unwrap().replace(poller) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_weld_telemetry(link: State<'_, WeldLink>) -> Result<()> {
+    if let Some(poller) = link.poller.lock().unwrap().take() {
+        poller.abort();
+    }
+    link.context.lock().await.take();
+    Ok(())
+}
+
+/// Writes a setpoint in engineering units; requires `confirm: true`.
+#[tauri::command]
+pub async fn write_weld_setpoint(
+    app: AppHandle,
+    link: State<'_, WeldLink>,
+    name: String,
+    value: f64,
+    confirm: bool,
+) -> Result<()> {
+    users::require(&app, Capability::WriteWeldSetpoints)?;
+    if !confirm {
+        return Err(Error::Invalid("setpoint writes must be confirmed".into()));
+    }
+    let settings = app.state::<SettingsStore>().get().weld;
+    let signal = settings
+        .registers
+        .iter()
+        .find(|signal| signal.name == name)
+        .ok_or_else(|| Error::NotFound(format!("weld signal `{name}`")))?;
+    if !signal.writable {
+        return Err(Error::Invalid(format!("weld signal `{name}` is read-only")));
+    }
+
+    link.ensure_connected(&settings).await?;
+    let result = link.write(signal, value).await;
+    let detail = serde_json::json!({
+        "name": name,
+        "value": value,
+        "written": result.as_ref().ok(),
+        "unit": signal.unit,
+        "error": audit::outcome(&result),
+    });
+    audit::record(&app, "weldSetpointWrite", detail);
+    let written = result?;
+    tracing::info!(%name, value = written, unit = %signal.unit, "weld setpoint written");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_high_word_first() {
+        assert_eq!(
+            RegisterKind::U32.encode(65538.0).unwrap(),
+            vec![0x0001, 0x0002]
+        );
+        assert_eq!(RegisterKind::I16.encode(-2.0).unwrap(), vec![0xFFFE]);
+        assert_eq!(
+            RegisterKind::I32.encode(-1.0).unwrap(),
+            vec![0xFFFF, 0xFFFF]
+        );
+        assert_eq!(RegisterKind::F32.encode(1.5).unwrap(), vec![0x3FC0, 0x0000]);
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let cases = [
+            (RegisterKind::U16, 40000.0),
+            (RegisterKind::I16, -1234.0),
+            (RegisterKind::U32, 3_000_000_000.0),
+            (RegisterKind::I32, -70000.0),
+            (RegisterKind::F32, 2.25),
+        ];
+        for (kind, value) in cases {
+            let words = kind.encode(value).unwrap();
+            assert_eq!(words.len(), usize::from(kind.width()));
+            assert_eq!(kind.decode(&words), value);
+        }
+    }
+
+    #[test]
+    fn rounds_integers() {
+        assert_eq!(RegisterKind::U16.encode(12.6).unwrap(), vec![13]);
+        assert_eq!(RegisterKind::I16.decode(&[0x8000]), -32768.0);
+    }
+
+    #[test]
+    fn refuses_values_the_register_cannot_hold() {
+        let cases = [
+            (RegisterKind::U16, -5.0),
+            (RegisterKind::U16, 65536.0),
+            (RegisterKind::I16, 32768.0),
+            (RegisterKind::U32, -1.0),
+            (RegisterKind::I32, 3_000_000_000.0),
+            (RegisterKind::F32, 1e39),
+            (RegisterKind::U16, f64::NAN),
+            (RegisterKind::F32, f64::INFINITY),
+            // What a zero scale makes of any setpoint.
+            (RegisterKind::U16, 5.0 / 0.0),
+        ];
+        for (kind, raw) in cases {
+            assert!(matches!(kind.encode(raw), Err(Error::Invalid(_))), "{raw}");
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Publishes cell status to the factory MQTT broker.
+//!
+//! Configured by the `mqtt` settings section and restarted whenever it
+//! changes. Once connected the shell publishes, to the configured topics:
+//!
+//! - `availability`: retained `online`, with `offline` as the last will;
+//! - `backend`: retained [`BackendStatus`] snapshots on every backend
+//!   lifecycle event and every `statusIntervalSecs`;
+//! - `robot`: retained `robot-status` payloads from the controller link;
+//! - `episodes`: `episode-event` payloads emitted by the webview's recording
+//!   screens.
+//!
+//! [`publish_mqtt`] sends ad-hoc messages. rumqttc reconnects on the next
+//! poll after an error; the loop only spaces the attempts out with backoff
+//! and reports them as `mqtt-status`.
+
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS, Transport};
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::settings::{MqttSettings, Settings, SettingsStore};
+use crate::sidecar::{BackendStatus, SidecarState};
+
+const REQUEST_CAPACITY: usize = 64;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Backend events that trigger a fresh status snapshot.
+const BACKEND_EVENTS: [&str; 3] = ["backend-ready", "backend-restarted", "backend-error"];
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MqttStatus {
+    connected: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendMessage<'a> {
+    event: &'a str,
+    #[serde(flatten)]
+    status: BackendStatus,
+}
+
+struct Running {
+    settings: MqttSettings,
+    client: AsyncClient,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Running {
+    fn publish(&self, topic: &str, retain: bool, payload: impl Into<Vec<u8>>) -> Result<()> {
+        self.client
+            .try_publish(topic, qos(self.settings.qos), retain, payload)?;
+        Ok(())
+    }
+}
+
+/// The broker connection, if one is configured.
+#[derive(Default)]
+pub struct Mqtt(StdMutex<Option<Running>>);
+
+impl Mqtt {
+    /// Publishes on the topic picked from the running settings, dropping the
+    /// message when no broker is configured or the queue is full.
+    fn forward(&self, topic: impl Fn(&MqttSettings) -> &str, retain: bool, payload: &str) {
+        let running = self.0.lock().unwrap();
+        if let Some(running) = running.as_ref() {
+            let topic = topic(&running.settings);
+            if let Err(err) = running.publish(topic, retain, payload) {
+                tracing::debug!(%topic, "mqtt message dropped: {err}");
+            }
+        }
+    }
+}
+
+/// Connects if a broker is configured and forwards shell events to it.
+pub fn init(app: &AppHandle) {
+    app.manage(Mqtt::default());
+    restart(app, app.state::<SettingsStore>().get().mqtt);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) else {
+            return;
+        };
+        let mqtt = handle.state::<Mqtt>();
+        let unchanged = mqtt
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|running| running.settings == settings.mqtt)
+            .unwrap_or(settings.mqtt.host.is_none());
+        if !unchanged {
+            restart(&handle, settings.mqtt);
+        }
+    });
+    for name in BACKEND_EVENTS {
+        let handle = app.clone();
+        app.listen_any(name, move |_| publish_backend_status(&handle, name));
+    }
+    let handle = app.clone();
+    app.listen_any("robot-status", move |event| {
+        handle
+            .state::<Mqtt>()
+            .forward(|settings| &settings.topics.robot, true, event.payload());
+    });
+    let handle = app.clone();
+    app.listen_any("episode-event", move |event| {
+        handle.state::<Mqtt>().forward(
+            |settings| &settings.topics.episodes,
+            false,
+            event.payload(),
+        );
+    });
+}
+
+/// Publishes `payload` to `topic`: strings as-is, anything else as JSON.
+#[tauri::command]
+pub fn publish_mqtt(
+    mqtt: State<'_, Mqtt>,
+    topic: String,
+    payload: serde_json::Value,
+    retain: Option<bool>,
+) -> Result<()> {
+    let payload = match payload {
+        serde_json::Value::String(text) => text.into_bytes(),
+        other => serde_json::to_vec(&other)?,
+    };
+    let running = mqtt.0.lock().unwrap();
+    let running = running
+        .as_ref()
+        .ok_or_else(|| Error::Invalid("no MQTT broker configured".into()))?;
+    running.publish(&topic, retain.unwrap_or(false), payload)
+}
+
+fn restart(app: &AppHandle, settings: MqttSettings) {
+    let mqtt = app.state::<Mqtt>();
+    let mut running = mqtt.0.lock().unwrap();
+    if let Some(previous) = running.take() {
+        // Dropping the connection without a DISCONNECT makes the broker
+        // publish the `offline` will for the old configuration.
+        for task in previous.tasks {
+            task.abort();
+        }
+    }
+    let Some(host) = settings.host.clone() else {
+        return;
+    };
+
+    let client_id = settings.client_id.clone().unwrap_or_else(default_client_id);
+    let mut options = MqttOptions::new(client_id, host, settings.port);
+    options
+        .set_keep_alive(Duration::from_secs(settings.keep_alive_secs.max(5)))
+        .set_last_will(LastWill::new(
+            &settings.topics.availability,
+            "offline",
+            qos(settings.qos),
+            true,
+        ));
+    if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+        options.set_credentials(username, password);
+    }
+    if settings.tls {
+        let transport = match settings.ca_cert.as_ref().map(std::fs::read) {
+            Some(Ok(ca)) => Transport::tls(ca, None, None),
+            Some(Err(err)) => {
+                tracing::warn!("cannot read MQTT CA bundle: {err}");
+                emit_status(app, false, Some(err.to_string()));
+                return;
+            }
+            None => Transport::tls_with_default_config(),
+        };
+        options.set_transport(transport);
+    }
+
+    let (client, eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let mut tasks = vec![tauri::async_runtime::spawn(run(
+        app.clone(),
+        client.clone(),
+        settings.clone(),
+        eventloop,
+    ))];
+    if settings.status_interval_secs > 0 {
+        let interval = Duration::from_secs(settings.status_interval_secs);
+        tasks.push(tauri::async_runtime::spawn(heartbeat(
+            app.clone(),
+            interval,
+        )));
+    }
+    *running = Some(Running {
+        settings,
+        client,
+        tasks,
+    });
+}
+
+async fn run(
+    app: AppHandle,
+    client: AsyncClient,
+    settings: MqttSettings,
+    mut eventloop: rumqttc::EventLoop,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                backoff = INITIAL_BACKOFF;
+                emit_status(&app, true, None);
+                let _ = client.try_publish(
+                    &settings.topics.availability,
+                    qos(settings.qos),
+                    true,
+                    "online",
+                );
+                publish_backend_status(&app, "connected");
+            }
+            Ok(_) => {}
+            Err(err) => {
+                tracing::debug!("mqtt connection lost: {err}; retrying in {backoff:?}");
+                emit_status(&app, false, Some(err.to_string()));
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn heartbeat(app: AppHandle, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        publish_backend_status(&app, "heartbeat");
+    }
+}
+
+fn publish_backend_status(app: &AppHandle, event: &str) {
+    let Some(sidecar) = app.try_state::<SidecarState>() else {
+        return;
+    };
+    let message = BackendMessage {
+        event,
+        status: sidecar.status(),
+    };
+    match serde_json::to_string(&message) {
+        Ok(payload) => {
+            app.state::<Mqtt>()
+                .forward(|settings| &settings.topics.backend, true, &payload)
+        }
+        Err(err) => tracing::warn!("cannot encode backend status: {err}"),
+    }
+}
+
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+fn default_client_id() -> String {
+    let host = ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok())
+        .unwrap_or_else(|| "shell".into());
+    format!("percus-{host}")
+}
+
+fn emit_status(app: &AppHandle, connected: bool, error: Option<String>) {
+    let _ = app.emit("mqtt-status", MqttStatus { connected, error });
+}
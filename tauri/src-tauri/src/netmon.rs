@@ -0,0 +1,309 @@
+//! Network quality to the robot controller and the backend, so a congested
+//! plant network shows up before teleop becomes unsafe.
+//!
+//! Every `network.intervalMs` each target is probed once: the controller,
+//! the gRPC gateway and any `network.targets` with ICMP echo, the local
+//! backend (and targets given as `host:port`) with a TCP connect. Latency,
+//! jitter (mean difference between consecutive round trips, as in RFC 3550)
+//! and loss are computed over the last `network.window` probes and emitted
+//! as `network-quality`. A target whose window crosses one of the
+//! `max*` thresholds is flagged `degraded`; entering that state is logged
+//! and raises a desktop notification.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use surge_ping::{Client, Config, PingIdentifier, PingSequence, ICMP};
+use tauri::{AppHandle, Manager, State};
+
+use crate::notifications::{self, Category, Notice};
+use crate::settings::{NetworkMonitorSettings, Settings, SettingsStore};
+use crate::sidecar::SidecarState;
+use crate::windows;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetQuality {
+    pub name: String,
+    pub address: Option<String>,
+    /// Mean round trip over the window; `None` until a probe succeeded.
+    pub latency_ms: Option<f64>,
+    pub max_latency_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub loss_percent: f64,
+    pub samples: usize,
+    pub degraded: bool,
+    /// Thresholds exceeded, e.g. `latency 34.2 ms > 20 ms`.
+    pub reasons: Vec<String>,
+    /// Why the target cannot be probed at all.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NetworkQuality<'a> {
+    timestamp_ms: u64,
+    targets: &'a [TargetQuality],
+}
+
+/// Latest quality of every target.
+#[derive(Default)]
+pub struct NetworkMonitor(StdMutex<Vec<TargetQuality>>);
+
+#[derive(Clone, PartialEq)]
+enum Probe {
+    Icmp(String),
+    Tcp(String),
+}
+
+/// Probe outcomes of one target, newest last; `None` is a loss.
+#[derive(Default)]
+struct Window {
+    rtts: VecDeque<Option<f64>>,
+    degraded: bool,
+}
+
+/// Address probed and round trip, `None` when lost; `Err` when the target
+/// could not be probed at all.
+type ProbeOutcome = Result<(Option<IpAddr>, Option<Duration>), String>;
+
+pub fn init(app: &AppHandle) {
+    app.manage(NetworkMonitor::default());
+    tauri::async_runtime::spawn(run(app.clone()));
+}
+
+#[tauri::command]
+pub fn get_network_quality(monitor: State<'_, NetworkMonitor>) -> Vec<TargetQuality> {
+    monitor.0.lock().unwrap().clone()
+}
+
+async fn run(app: AppHandle) {
+    let icmp = [ICMP::V4, ICMP::V6].map(|kind| {
+        Client::new(&Config::builder().kind(kind).build()).map_err(|err| {
+            tracing::info!(?kind, "ICMP probes unavailable: {err}");
+            err.to_string()
+        })
+    });
+    let ident = PingIdentifier(std::process::id() as u16);
+    let mut seq = 0u16;
+    let mut windows: HashMap<String, Window> = HashMap::new();
+    let mut resolved: HashMap<String, IpAddr> = HashMap::new();
+    loop {
+        let store = app.state::<SettingsStore>();
+        let settings = store.get();
+        let network = settings.network.clone();
+        let interval = Duration::from_millis(network.interval_ms.max(50));
+        if !network.enabled {
+            windows.clear();
+            app.state::<NetworkMonitor>().0.lock().unwrap().clear();
+            tokio::time::sleep(interval).await;
+            continue;
+        }
+        let started = Instant::now();
+        let targets = targets(&app, &settings);
+        let timeout = Duration::from_millis(network.timeout_ms.max(1));
+        seq = seq.wrapping_add(1);
+
+        let probes = targets.iter().map(|(_, probe)| {
+            let icmp = &icmp;
+            let resolved = &resolved;
+            async move {
+                match probe {
+                    Probe::Icmp(host) => {
+                        let ip = match resolved.get(host) {
+                            Some(ip) => *ip,
+                            None => resolve(host).await?,
+                        };
+                        let client = icmp[usize::from(ip.is_ipv6())].as_ref()?;
+                        let mut pinger = client.pinger(ip, ident).await;
+                        pinger.timeout(timeout);
+                        let rtt = pinger
+                            .ping(PingSequence(seq), &[0; 16])
+                            .await
+                            .ok()
+                            .map(|(_, rtt)| rtt);
+                        Ok((Some(ip), rtt))
+                    }
+                    Probe::Tcp(address) => {
+                        let addr = match address.parse::<SocketAddr>() {
+                            Ok(addr) => addr,
+                            Err(_) => tokio::net::lookup_host(address.as_str())
+                                .await
+                                .map_err(|err| err.to_string())?
+                                .next()
+                                .ok_or_else(|| format!("{address} does not resolve"))?,
+                        };
+                        let sent = Instant::now();
+                        let connected =
+                            tokio::time::timeout(timeout, tokio::net::TcpStream::connect(addr))
+                                .await;
+                        let rtt = matches!(connected, Ok(Ok(_))).then(|| sent.elapsed());
+                        Ok((Some(addr.ip()), rtt))
+                    }
+                }
+            }
+        });
+        let outcomes: Vec<ProbeOutcome> = futures_util::future::join_all(probes).await;
+
+        let mut qualities = Vec::new();
+        for ((name, probe), outcome) in targets.iter().zip(outcomes) {
+            let window = windows.entry(name.clone()).or_default();
+            let (address, error) = match outcome {
+                Ok((ip, rtt)) => {
+                    if let (Probe::Icmp(host), Some(ip)) = (probe, ip) {
+                        resolved.insert(host.clone(), ip);
+                    }
+                    window
+                        .rtts
+                        .push_back(rtt.map(|rtt| rtt.as_secs_f64() * 1000.0));
+                    while window.rtts.len() > network.window.max(1) {
+                        window.rtts.pop_front();
+                    }
+                    let address = match probe {
+                        Probe::Icmp(host) | Probe::Tcp(host) => host.clone(),
+                    };
+                    (Some(address), None)
+                }
+                Err(error) => {
+                    if let Probe::Icmp(host) = probe {
+                        resolved.remove(host);
+                    }
+                    (None, Some(error))
+                }
+            };
+            let quality = quality(name, address, error, window, &network);
+            if quality.degraded && !window.degraded {
+                tracing::warn!(target = %name, reasons = ?quality.reasons, "network degraded");
+                notifications::notify(
+                    &app,
+                    Notice::new(
+                        Category::Network,
+                        format!("Network to {name} degraded"),
+                        quality.reasons.join(", "),
+                    ),
+                );
+            } else if !quality.degraded && window.degraded {
+                tracing::info!(target = %name, "network recovered");
+            }
+            window.degraded = quality.degraded;
+            qualities.push(quality);
+        }
+        windows.retain(|name, _| targets.iter().any(|(target, _)| target == name));
+
+        let event = NetworkQuality {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            targets: &qualities,
+        };
+        windows::emit(&app, "network-quality", event);
+        *app.state::<NetworkMonitor>().0.lock().unwrap() = qualities;
+        tokio::time::sleep(interval.saturating_sub(started.elapsed())).await;
+    }
+}
+
+/// Targets named `robot`, `gateway`, `backend` and the configured extras.
+fn targets(app: &AppHandle, settings: &Settings) -> Vec<(String, Probe)> {
+    let mut targets = Vec::new();
+    if let Some(host) = settings.robot_host() {
+        targets.push(("robot".to_owned(), Probe::Icmp(host)));
+    }
+    let gateway = settings
+        .grpc
+        .endpoint
+        .as_deref()
+        .and_then(|endpoint| reqwest::Url::parse(endpoint).ok())
+        .and_then(|url| url.host_str().map(str::to_owned));
+    if let Some(host) = gateway {
+        targets.push(("gateway".to_owned(), Probe::Icmp(host)));
+    }
+    let port = app.state::<SidecarState>().port();
+    if port != 0 {
+        targets.push((
+            "backend".to_owned(),
+            Probe::Tcp(format!("127.0.0.1:{port}")),
+        ));
+    }
+    for target in &settings.network.targets {
+        let probe = if target.host.parse::<IpAddr>().is_err() && target.host.contains(':') {
+            Probe::Tcp(target.host.clone())
+        } else {
+            Probe::Icmp(target.host.clone())
+        };
+        targets.push((target.name.clone(), probe));
+    }
+    targets
+}
+
+async fn resolve(host: &str) -> Result<IpAddr, String> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+    tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(|err| format!("{host}: {err}"))?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| format!("{host} does not resolve"))
+}
+
+fn quality(
+    name: &str,
+    address: Option<String>,
+    error: Option<String>,
+    window: &Window,
+    settings: &NetworkMonitorSettings,
+) -> TargetQuality {
+    let rtts: Vec<f64> = window.rtts.iter().flatten().copied().collect();
+    let samples = window.rtts.len();
+    let lost = samples - rtts.len();
+    let loss_percent = if samples == 0 {
+        0.0
+    } else {
+        lost as f64 * 100.0 / samples as f64
+    };
+    let latency_ms = (!rtts.is_empty()).then(|| rtts.iter().sum::<f64>() / rtts.len() as f64);
+    let max_latency_ms = rtts.iter().copied().reduce(f64::max);
+    let jitter_ms = (rtts.len() > 1).then(|| {
+        rtts.windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .sum::<f64>()
+            / (rtts.len() - 1) as f64
+    });
+
+    let mut reasons = Vec::new();
+    if let Some(latency) = latency_ms.filter(|&latency| latency > settings.max_latency_ms) {
+        reasons.push(format!(
+            "latency {latency:.1} ms > {} ms",
+            settings.max_latency_ms
+        ));
+    }
+    if let Some(jitter) = jitter_ms.filter(|&jitter| jitter > settings.max_jitter_ms) {
+        reasons.push(format!(
+            "jitter {jitter:.1} ms > {} ms",
+            settings.max_jitter_ms
+        ));
+    }
+    if loss_percent > settings.max_loss_percent {
+        reasons.push(format!(
+            "loss {loss_percent:.1}% > {}%",
+            settings.max_loss_percent
+        ));
+    }
+    TargetQuality {
+        name: name.to_owned(),
+        address,
+        latency_ms,
+        max_latency_ms,
+        jitter_ms,
+        loss_percent,
+        samples,
+        degraded: !reasons.is_empty(),
+        reasons,
+        error,
+    }
+}
@@ -0,0 +1,292 @@
+//! Native notifications for things that happen while nobody is watching
+//! the window: finished or failed uploads and exports, interrupted
+//! recordings, available updates, backend crashes, network degradation and
+//! safety stops.
+//!
+//! Every [`Notice`] has a [`Category`], each of which can be turned off in
+//! the `notifications` settings section; `backgroundOnly` keeps them quiet
+//! while the main window is focused. Whether or not the OS shows it, a
+//! notice is also emitted as `notification` so the webview can show it in
+//! the app. Its actions are offered as buttons on Linux and Windows, where
+//! clicking one runs it in the shell; on macOS and in the webview they run
+//! through [`run_notification_action`] with the notice's `id`.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex as StdMutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::error::{Error, Result};
+use crate::instance::focus_main_window;
+use crate::settings::{NotificationSettings, SettingsStore};
+use crate::sidecar;
+use crate::updater::{self, PendingUpdate};
+use crate::uploads::{self, Uploads};
+
+/// Notices whose actions can still be run.
+const KEPT: usize = 50;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Uploads,
+    Exports,
+    Recording,
+    Updates,
+    Backend,
+    Network,
+    Safety,
+}
+
+impl Category {
+    fn enabled(self, settings: &NotificationSettings) -> bool {
+        match self {
+            Category::Uploads => settings.uploads,
+            Category::Exports => settings.exports,
+            Category::Recording => settings.recording,
+            Category::Updates => settings.updates,
+            Category::Backend => settings.backend,
+            Category::Network => settings.network,
+            Category::Safety => settings.safety,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Action {
+    ShowWindow,
+    OpenFolder(PathBuf),
+    OpenLogs,
+    RetryUploads,
+    InstallUpdate,
+    RestartBackend,
+}
+
+impl Action {
+    fn id(&self) -> &'static str {
+        match self {
+            Action::ShowWindow => "show-window",
+            Action::OpenFolder(_) => "open-folder",
+            Action::OpenLogs => "open-logs",
+            Action::RetryUploads => "retry-uploads",
+            Action::InstallUpdate => "install-update",
+            Action::RestartBackend => "restart-backend",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Action::ShowWindow => "Show",
+            Action::OpenFolder(_) => "Open folder",
+            Action::OpenLogs => "Open logs",
+            Action::RetryUploads => "Retry",
+            Action::InstallUpdate => "Install",
+            Action::RestartBackend => "Restart backend",
+        }
+    }
+}
+
+/// A notification to raise.
+pub struct Notice {
+    category: Category,
+    title: String,
+    body: String,
+    actions: Vec<Action>,
+}
+
+impl Notice {
+    pub fn new(category: Category, title: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            category,
+            title: title.into(),
+            body: body.into(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.actions.push(action);
+        self
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct ActionInfo {
+    id: &'static str,
+    label: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NotificationEvent {
+    id: u32,
+    category: Category,
+    title: String,
+    body: String,
+    actions: Vec<ActionInfo>,
+    /// Whether the OS was asked to show it.
+    shown: bool,
+}
+
+#[derive(Default)]
+pub struct Notifications {
+    next_id: AtomicU32,
+    /// Recent notices' actions, oldest first.
+    actions: StdMutex<VecDeque<(u32, Vec<Action>)>>,
+}
+
+/// Raises `notice` as the settings allow, and emits it to the webview.
+pub fn notify(app: &AppHandle, notice: Notice) {
+    let settings = app.state::<SettingsStore>().get().notifications;
+    let focused = app
+        .get_webview_window("main")
+        .and_then(|window| window.is_focused().ok())
+        .unwrap_or(false);
+    let shown = settings.enabled
+        && notice.category.enabled(&settings)
+        && !(settings.background_only && focused);
+
+    let notifications = app.state::<Notifications>();
+    let id = notifications.next_id.fetch_add(1, Ordering::Relaxed);
+    if !notice.actions.is_empty() {
+        let mut kept = notifications.actions.lock().unwrap();
+        if kept.len() == KEPT {
+            kept.pop_front();
+        }
+        kept.push_back((id, notice.actions.clone()));
+    }
+    let event = NotificationEvent {
+        id,
+        category: notice.category,
+        title: notice.title.clone(),
+        body: notice.body.clone(),
+        actions: notice
+            .actions
+            .iter()
+            .map(|action| ActionInfo {
+                id: action.id(),
+                label: action.label(),
+            })
+            .collect(),
+        shown,
+    };
+    let _ = app.emit("notification", event);
+    if shown {
+        show(app, notice);
+    }
+}
+
+/// Runs action `action` of notice `id`.
+#[tauri::command]
+pub fn run_notification_action(
+    app: AppHandle,
+    notifications: State<'_, Notifications>,
+    id: u32,
+    action: String,
+) -> Result<()> {
+    let found = notifications
+        .actions
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(notice, _)| *notice == id)
+        .and_then(|(_, actions)| actions.iter().find(|known| known.id() == action).cloned())
+        .ok_or_else(|| Error::NotFound(format!("action `{action}` of notification {id}")))?;
+    run(&app, found);
+    Ok(())
+}
+
+fn run(app: &AppHandle, action: Action) {
+    tracing::debug!(?action, "notification action");
+    match action {
+        Action::ShowWindow => focus_main_window(app),
+        Action::OpenFolder(dir) => open(app, dir),
+        Action::OpenLogs => match app.path().app_log_dir() {
+            Ok(dir) => open(app, dir),
+            Err(err) => tracing::warn!("no logs folder: {err}"),
+        },
+        Action::RetryUploads => uploads::resume_uploads(app.state::<Uploads>()),
+        Action::InstallUpdate => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let pending = app.state::<PendingUpdate>();
+                if let Err(err) = updater::install_update(app.clone(), pending).await {
+                    tracing::warn!("update from a notification failed: {err}");
+                }
+            });
+        }
+        Action::RestartBackend => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { sidecar::restart(&app).await });
+        }
+    }
+}
+
+fn open(app: &AppHandle, dir: PathBuf) {
+    if let Err(err) = app.opener().open_path(dir.to_string_lossy(), None::<&str>) {
+        tracing::warn!(dir = %dir.display(), "failed to open folder: {err}");
+    }
+}
+
+/// With buttons, which the notification plugin does not offer on desktop.
+#[cfg(any(target_os = "linux", windows))]
+fn show(app: &AppHandle, notice: Notice) {
+    if notice.actions.is_empty() {
+        return show_plain(app, notice);
+    }
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname(&app.package_info().name)
+        .summary(&notice.title)
+        .body(&notice.body)
+        .auto_icon();
+    for action in &notice.actions {
+        notification.action(action.id(), action.label());
+    }
+    // Toasts are attributed to the installed app only; dev builds have no
+    // registered AppUserModelID.
+    #[cfg(windows)]
+    if !tauri::is_dev() {
+        notification.app_id(&app.config().identifier);
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || match notification.show() {
+        // Blocks until the notification is clicked or closed.
+        Ok(handle) => handle.wait_for_action(|clicked| {
+            let action = match clicked {
+                "default" => Some(Action::ShowWindow),
+                _ => notice
+                    .actions
+                    .into_iter()
+                    .find(|action| action.id() == clicked),
+            };
+            if let Some(action) = action {
+                run(&app, action);
+            }
+        }),
+        Err(err) => tracing::warn!("notification not shown: {err}"),
+    });
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn show(app: &AppHandle, notice: Notice) {
+    show_plain(app, notice);
+}
+
+fn show_plain(app: &AppHandle, notice: Notice) {
+    use tauri_plugin_notification::NotificationExt;
+
+    let shown = app
+        .notification()
+        .builder()
+        .title(notice.title)
+        .body(notice.body)
+        .show();
+    if let Err(err) = shown {
+        tracing::warn!("notification not shown: {err}");
+    }
+}
@@ -0,0 +1,303 @@
+//! Connectivity detection and a durable queue of cloud operations, for
+//! factory cells that regularly lose their internet connection.
+//!
+//! A background task requests `offline.probeUrl` every
+//! `checkIntervalSecs`; any HTTP response counts as online and
+//! [`FAILURES_BEFORE_OFFLINE`] failed probes in a row as offline. Changes
+//! are emitted as `connectivity-changed`.
+//!
+//! While offline the upload queue, which is already persisted by
+//! [`crate::uploads`], is held. Update checks and telemetry pushes are
+//! appended to `offline-queue.json` under the app data directory and run
+//! in order whenever the shell is online: a network error stops the drain
+//! and keeps the operation for the next attempt, other errors drop it after
+//! [`MAX_ATTEMPTS`]. [`get_offline_queue_status`] reports all of this, and
+//! every change is emitted as `offline-queue-status`.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::settings::SettingsStore;
+use crate::updater;
+use crate::uploads::{self, Uploads};
+
+const QUEUE_FILE: &str = "offline-queue.json";
+const FAILURES_BEFORE_OFFLINE: u32 = 2;
+const MAX_ATTEMPTS: u32 = 5;
+/// How often a blocked queue is retried while online.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Object key prefix of telemetry pushes.
+const TELEMETRY_PREFIX: &str = "telemetry";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Operation {
+    UpdateCheck,
+    /// A telemetry Parquet file for the upload bucket.
+    #[serde(rename_all = "camelCase")]
+    TelemetryPush {
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedOperation {
+    id: u64,
+    #[serde(flatten)]
+    operation: Operation,
+    queued_at: DateTime<Utc>,
+    attempts: u32,
+    last_error: Option<String>,
+}
+
+/// What `offline-queue.json` holds.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Queue {
+    next_id: u64,
+    operations: Vec<QueuedOperation>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfflineQueueStatus {
+    online: bool,
+    /// When connectivity last changed; unset until it first does.
+    since: Option<DateTime<Utc>>,
+    operations: Vec<QueuedOperation>,
+    /// Uploads held in their own queue.
+    waiting_uploads: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectivityChanged {
+    online: bool,
+}
+
+pub struct Offline {
+    path: PathBuf,
+    online: AtomicBool,
+    since: StdMutex<Option<DateTime<Utc>>>,
+    queue: StdMutex<Queue>,
+    wake: Notify,
+}
+
+impl Offline {
+    fn persist(&self, queue: &Queue) {
+        let written = serde_json::to_vec_pretty(queue)
+            .map_err(Error::from)
+            .and_then(|json| Ok(fsutil::write_atomic(&self.path, &json)?));
+        if let Err(err) = written {
+            tracing::warn!("offline queue not saved: {err}");
+        }
+    }
+}
+
+/// Loads the persisted queue and starts the connectivity and drain tasks.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_data_dir()?.join(QUEUE_FILE);
+    let queue: Queue = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!("offline queue unreadable, starting empty: {err}");
+            Queue::default()
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Queue::default(),
+        Err(err) => return Err(err.into()),
+    };
+    app.manage(Offline {
+        path,
+        // Until the first probe says otherwise.
+        online: AtomicBool::new(true),
+        since: StdMutex::new(None),
+        queue: StdMutex::new(queue),
+        wake: Notify::new(),
+    });
+    tauri::async_runtime::spawn(monitor(app.clone()));
+    tauri::async_runtime::spawn(drain(app.clone()));
+    Ok(())
+}
+
+pub fn is_online(app: &AppHandle) -> bool {
+    app.try_state::<Offline>()
+        .is_none_or(|offline| offline.online.load(Ordering::Relaxed))
+}
+
+/// Queues `operation`; it runs at once if the shell is online. An update
+/// check already queued is not queued twice.
+pub fn enqueue(app: &AppHandle, operation: Operation) {
+    let Some(offline) = app.try_state::<Offline>() else {
+        tracing::warn!("offline queue not ready; operation dropped");
+        return;
+    };
+    {
+        let mut queue = offline.queue.lock().unwrap();
+        if operation == Operation::UpdateCheck
+            && queue
+                .operations
+                .iter()
+                .any(|queued| queued.operation == operation)
+        {
+            return;
+        }
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.operations.push(QueuedOperation {
+            id,
+            operation,
+            queued_at: Utc::now(),
+            attempts: 0,
+            last_error: None,
+        });
+        offline.persist(&queue);
+    }
+    offline.wake.notify_one();
+    emit_status(app);
+}
+
+#[tauri::command]
+pub fn get_offline_queue_status(app: AppHandle, offline: State<'_, Offline>) -> OfflineQueueStatus {
+    status(&app, &offline)
+}
+
+fn status(app: &AppHandle, offline: &Offline) -> OfflineQueueStatus {
+    OfflineQueueStatus {
+        online: offline.online.load(Ordering::Relaxed),
+        since: *offline.since.lock().unwrap(),
+        operations: offline.queue.lock().unwrap().operations.clone(),
+        waiting_uploads: app
+            .try_state::<Uploads>()
+            .map_or(0, |uploads| uploads.waiting()),
+    }
+}
+
+fn emit_status(app: &AppHandle) {
+    let status = status(app, &app.state::<Offline>());
+    let _ = app.emit("offline-queue-status", status);
+}
+
+async fn monitor(app: AppHandle) {
+    let client = reqwest::Client::new();
+    let mut failures = 0u32;
+    loop {
+        let settings = app.state::<SettingsStore>().get().offline;
+        let reachable = !settings.enabled
+            || client
+                .head(&settings.probe_url)
+                .timeout(Duration::from_secs(settings.timeout_secs.max(1)))
+                .send()
+                .await
+                .is_ok();
+        failures = if reachable { 0 } else { failures + 1 };
+        let offline = app.state::<Offline>();
+        let was_online = offline.online.load(Ordering::Relaxed);
+        let online = reachable || (was_online && failures < FAILURES_BEFORE_OFFLINE);
+        if online != was_online {
+            offline.online.store(online, Ordering::Relaxed);
+            *offline.since.lock().unwrap() = Some(Utc::now());
+            if online {
+                tracing::info!("connectivity restored; draining the offline queue");
+            } else {
+                tracing::warn!(url = %settings.probe_url, "connectivity lost; cloud operations queued");
+            }
+            if let Some(uploads) = app.try_state::<Uploads>() {
+                uploads.set_offline(!online);
+            }
+            offline.wake.notify_one();
+            let _ = app.emit("connectivity-changed", ConnectivityChanged { online });
+            emit_status(&app);
+        }
+        tokio::time::sleep(Duration::from_secs(settings.check_interval_secs.max(1))).await;
+    }
+}
+
+async fn drain(app: AppHandle) {
+    let offline = app.state::<Offline>();
+    loop {
+        while offline.online.load(Ordering::Relaxed) {
+            let Some(next) = offline.queue.lock().unwrap().operations.first().cloned() else {
+                break;
+            };
+            let result = run(&app, &next.operation).await;
+            let blocked = {
+                let mut queue = offline.queue.lock().unwrap();
+                let blocked = match result {
+                    Ok(()) => {
+                        queue.operations.retain(|queued| queued.id != next.id);
+                        false
+                    }
+                    Err(err) => retry_later(&mut queue, next.id, err),
+                };
+                offline.persist(&queue);
+                blocked
+            };
+            emit_status(&app);
+            if blocked {
+                break;
+            }
+        }
+        let _ = tokio::time::timeout(RETRY_INTERVAL, offline.wake.notified()).await;
+    }
+}
+
+/// Records a failure of operation `id`; returns whether the queue should
+/// wait before the next attempt.
+fn retry_later(queue: &mut Queue, id: u64, err: Error) -> bool {
+    let network = matches!(err, Error::Http(_));
+    let Some(position) = queue.operations.iter().position(|queued| queued.id == id) else {
+        return false;
+    };
+    let queued = &mut queue.operations[position];
+    if !network {
+        queued.attempts += 1;
+    }
+    queued.last_error = Some(err.to_string());
+    if queued.attempts >= MAX_ATTEMPTS {
+        tracing::warn!(
+            id,
+            "queued operation dropped after {MAX_ATTEMPTS} attempts: {err}"
+        );
+        queue.operations.remove(position);
+        return false;
+    }
+    tracing::info!(
+        id,
+        attempts = queued.attempts,
+        "queued operation failed, retrying later: {err}"
+    );
+    true
+}
+
+async fn run(app: &AppHandle, operation: &Operation) -> Result<()> {
+    match operation {
+        Operation::UpdateCheck => {
+            if let Some(info) = updater::check(app).await? {
+                updater::announce(app, &info);
+                let _ = app.emit("update-available", info);
+            }
+            Ok(())
+        }
+        Operation::TelemetryPush { path } => {
+            let Some(name) = path.file_name() else {
+                return Ok(());
+            };
+            if !path.is_file() {
+                // Removed by retention while waiting.
+                tracing::info!(path = %path.display(), "telemetry push skipped; file is gone");
+                return Ok(());
+            }
+            let name = format!("{TELEMETRY_PREFIX}/{}", name.to_string_lossy());
+            uploads::put_file(app, path, &name).await
+        }
+    }
+}
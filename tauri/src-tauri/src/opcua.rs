@@ -0,0 +1,480 @@
+//! OPC UA client for the cell's safety PLC and cell controller.
+//!
+//! [`connect_opcua`] opens a session against the endpoint in the `opcua`
+//! settings section and monitors the nodes listed there (door switches,
+//! interlocks, ...) in one subscription; [`opcua_subscribe`] adds more at
+//! runtime. Every data change is emitted as `opcua-data-change` and
+//! connection changes as `opcua-status`. The session reconnects on its own
+//! and recreates the subscription after a reconnect.
+//!
+//! Values cross the IPC boundary as [`OpcValue`], tagged with their OPC UA
+//! type so writes reach the PLC with the data type it expects.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use opcua::client::transport::TcpConnector;
+use opcua::client::{
+    ClientBuilder, DataChangeCallback, IdentityToken, MonitoredItem, Session, SessionEventLoop,
+    SessionPollResult,
+};
+use opcua::crypto::SecurityPolicy;
+use opcua::types::{
+    AttributeId, DataValue, MessageSecurityMode, MonitoredItemCreateRequest, NodeId, NumericRange,
+    ReadValueId, StatusCode, TimestampsToReturn, UAString, Variant, WriteValue,
+};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+use crate::windows;
+
+/// A node watched from the moment the session is up.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpcUaNode {
+    /// Label used by the UI, e.g. `doorClosed`.
+    pub name: String,
+    /// e.g. `ns=2;s=Cell1.Safety.DoorClosed`.
+    pub node_id: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OpcSecurityMode {
+    None,
+    Sign,
+    SignAndEncrypt,
+}
+
+impl From<OpcSecurityMode> for MessageSecurityMode {
+    fn from(mode: OpcSecurityMode) -> Self {
+        match mode {
+            OpcSecurityMode::None => MessageSecurityMode::None,
+            OpcSecurityMode::Sign => MessageSecurityMode::Sign,
+            OpcSecurityMode::SignAndEncrypt => MessageSecurityMode::SignAndEncrypt,
+        }
+    }
+}
+
+/// A node value with its OPC UA type.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum OpcValue {
+    Null,
+    Boolean(bool),
+    SByte(i8),
+    Byte(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    /// RFC 3339; read-only.
+    DateTime(String),
+    Array(Vec<OpcValue>),
+    /// Any other type, rendered for display; read-only.
+    Other(String),
+}
+
+impl From<&Variant> for OpcValue {
+    fn from(variant: &Variant) -> Self {
+        match variant {
+            Variant::Empty => OpcValue::Null,
+            Variant::Boolean(v) => OpcValue::Boolean(*v),
+            Variant::SByte(v) => OpcValue::SByte(*v),
+            Variant::Byte(v) => OpcValue::Byte(*v),
+            Variant::Int16(v) => OpcValue::Int16(*v),
+            Variant::UInt16(v) => OpcValue::UInt16(*v),
+            Variant::Int32(v) => OpcValue::Int32(*v),
+            Variant::UInt32(v) => OpcValue::UInt32(*v),
+            Variant::Int64(v) => OpcValue::Int64(*v),
+            Variant::UInt64(v) => OpcValue::UInt64(*v),
+            Variant::Float(v) => OpcValue::Float(*v),
+            Variant::Double(v) => OpcValue::Double(*v),
+            Variant::String(v) => OpcValue::String(v.as_ref().to_owned()),
+            Variant::DateTime(v) => OpcValue::DateTime(v.to_rfc3339()),
+            Variant::Array(array) => OpcValue::Array(array.values.iter().map(Into::into).collect()),
+            other => OpcValue::Other(format!("{other:?}")),
+        }
+    }
+}
+
+impl TryFrom<OpcValue> for Variant {
+    type Error = Error;
+
+    fn try_from(value: OpcValue) -> Result<Self> {
+        Ok(match value {
+            OpcValue::Null => Variant::Empty,
+            OpcValue::Boolean(v) => Variant::Boolean(v),
+            OpcValue::SByte(v) => Variant::SByte(v),
+            OpcValue::Byte(v) => Variant::Byte(v),
+            OpcValue::Int16(v) => Variant::Int16(v),
+            OpcValue::UInt16(v) => Variant::UInt16(v),
+            OpcValue::Int32(v) => Variant::Int32(v),
+            OpcValue::UInt32(v) => Variant::UInt32(v),
+            OpcValue::Int64(v) => Variant::Int64(v),
+            OpcValue::UInt64(v) => Variant::UInt64(v),
+            OpcValue::Float(v) => Variant::Float(v),
+            OpcValue::Double(v) => Variant::Double(v),
+            OpcValue::String(v) => Variant::String(UAString::from(v)),
+            OpcValue::DateTime(_) | OpcValue::Array(_) | OpcValue::Other(_) => {
+                return Err(Error::Invalid(
+                    "only scalar values can be written to OPC UA nodes".into(),
+                ))
+            }
+        })
+    }
+}
+
+/// A read result or data change.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpcReading {
+    pub node_id: String,
+    /// Set for nodes from the settings.
+    pub name: Option<String>,
+    pub value: OpcValue,
+    /// `Good`, or the OPC UA status name.
+    pub status: String,
+    /// RFC 3339 source timestamp, when the server supplies one.
+    pub source_timestamp: Option<String>,
+}
+
+impl OpcReading {
+    fn new(node_id: String, name: Option<String>, value: &DataValue) -> Self {
+        Self {
+            node_id,
+            name,
+            value: value
+                .value
+                .as_ref()
+                .map(Into::into)
+                .unwrap_or(OpcValue::Null),
+            status: value.status.unwrap_or(StatusCode::Good).to_string(),
+            source_timestamp: value.source_timestamp.as_ref().map(|ts| ts.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpcStatus {
+    connected: bool,
+    error: Option<String>,
+}
+
+struct Connection {
+    session: Arc<Session>,
+    subscription_id: u32,
+    /// Monitored item ids, keyed by NodeId string.
+    monitored: HashMap<String, u32>,
+    event_loop: JoinHandle<()>,
+}
+
+/// The OPC UA session, if connected.
+#[derive(Default)]
+pub struct OpcUa(StdMutex<Option<Connection>>);
+
+impl OpcUa {
+    fn session(&self) -> Result<Arc<Session>> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|connection| connection.session.clone())
+            .ok_or_else(|| Error::Invalid("OPC UA is not connected".into()))
+    }
+}
+
+/// Connects to the configured endpoint and monitors the configured nodes.
+/// Reconnects if already connected, so changed settings take effect.
+#[tauri::command]
+pub async fn connect_opcua(app: AppHandle, opcua: State<'_, OpcUa>) -> Result<()> {
+    disconnect(&opcua).await;
+    let settings = app.state::<SettingsStore>().get().opcua;
+    let url = settings
+        .endpoint
+        .clone()
+        .ok_or_else(|| Error::Invalid("no OPC UA endpoint configured".into()))?;
+    let pki_dir = match &settings.pki_dir {
+        Some(dir) => dir.clone(),
+        None => app.path().app_data_dir()?.join("opcua-pki"),
+    };
+    let policy =
+        SecurityPolicy::from_str(&settings.security_policy).unwrap_or(SecurityPolicy::Unknown);
+    if policy == SecurityPolicy::Unknown {
+        return Err(Error::Invalid(format!(
+            "unknown OPC UA security policy {}",
+            settings.security_policy
+        )));
+    }
+    let mut client = ClientBuilder::new()
+        .application_name("Percus")
+        .application_uri("urn:percus:shell")
+        .product_uri("urn:percus:shell")
+        .pki_dir(pki_dir)
+        .create_sample_keypair(true)
+        .trust_server_certs(settings.trust_server_certs)
+        .session_retry_limit(-1)
+        .request_timeout(Duration::from_millis(settings.request_timeout_ms))
+        .client()
+        .map_err(|errors| Error::OpcUa(errors.join("; ")))?;
+    let identity = match (&settings.username, &settings.password) {
+        (Some(user), Some(password)) => IdentityToken::new_user_name(user, password.as_str()),
+        _ => IdentityToken::Anonymous,
+    };
+    let endpoint = (
+        url.as_str(),
+        policy.to_uri(),
+        MessageSecurityMode::from(settings.security_mode),
+    );
+    let (session, event_loop) = client
+        .connect_to_matching_endpoint(endpoint, identity)
+        .await
+        .map_err(|err| Error::OpcUa(err.to_string()))?;
+    let event_loop = tauri::async_runtime::spawn(run_event_loop(app.clone(), event_loop));
+    if !session.wait_for_connection().await {
+        event_loop.abort();
+        return Err(Error::OpcUa(format!("cannot connect to {url}")));
+    }
+    emit_status(&app, true, None);
+
+    // Keyed by the server's rendering of the id, which the callback sees.
+    let names: HashMap<String, String> = settings
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            Some((
+                parse_node_id(&node.node_id).ok()?.to_string(),
+                node.name.clone(),
+            ))
+        })
+        .collect();
+    let callback_app = app.clone();
+    let subscription_id = session
+        .create_subscription(
+            Duration::from_millis(settings.publishing_interval_ms),
+            30,
+            10,
+            0,
+            0,
+            true,
+            DataChangeCallback::new(move |value, item: &MonitoredItem| {
+                let node_id = item.item_to_monitor().node_id.to_string();
+                let name = names.get(&node_id).cloned();
+                let reading = OpcReading::new(node_id, name, &value);
+                windows::emit(&callback_app, "opcua-data-change", reading);
+            }),
+        )
+        .await
+        .map_err(|err| Error::OpcUa(err.to_string()))?;
+
+    *opcua.0.lock().unwrap() = Some(Connection {
+        session,
+        subscription_id,
+        monitored: HashMap::new(),
+        event_loop,
+    });
+    let node_ids = settings
+        .nodes
+        .into_iter()
+        .map(|node| node.node_id)
+        .collect();
+    monitor(&opcua, node_ids, sampling_interval(&app)).await
+}
+
+#[tauri::command]
+pub async fn disconnect_opcua(opcua: State<'_, OpcUa>) -> Result<()> {
+    disconnect(&opcua).await;
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(level = "debug", skip(opcua))]
+pub async fn opcua_read(opcua: State<'_, OpcUa>, node_ids: Vec<String>) -> Result<Vec<OpcReading>> {
+    let session = opcua.session()?;
+    let nodes = node_ids
+        .iter()
+        .map(|id| parse_node_id(id).map(ReadValueId::from))
+        .collect::<Result<Vec<_>>>()?;
+    let values = session
+        .read(&nodes, TimestampsToReturn::Source, 0.0)
+        .await
+        .map_err(|err| Error::OpcUa(err.to_string()))?;
+    Ok(node_ids
+        .into_iter()
+        .zip(values.iter())
+        .map(|(node_id, value)| OpcReading::new(node_id, None, value))
+        .collect())
+}
+
+#[tauri::command]
+#[tracing::instrument(level = "debug", skip(opcua, value))]
+pub async fn opcua_write(opcua: State<'_, OpcUa>, node_id: String, value: OpcValue) -> Result<()> {
+    let session = opcua.session()?;
+    let write = WriteValue {
+        node_id: parse_node_id(&node_id)?,
+        attribute_id: AttributeId::Value as u32,
+        index_range: NumericRange::None,
+        value: DataValue::value_only(Variant::try_from(value)?),
+    };
+    let results = session
+        .write(&[write])
+        .await
+        .map_err(|err| Error::OpcUa(err.to_string()))?;
+    match results.first() {
+        Some(status) if status.is_good() => Ok(()),
+        Some(status) => Err(Error::OpcUa(format!("write to {node_id}: {status}"))),
+        None => Err(Error::OpcUa(format!("write to {node_id}: no result"))),
+    }
+}
+
+/// Adds nodes to the subscription; already monitored nodes are skipped.
+#[tauri::command]
+pub async fn opcua_subscribe(
+    app: AppHandle,
+    opcua: State<'_, OpcUa>,
+    node_ids: Vec<String>,
+) -> Result<()> {
+    monitor(&opcua, node_ids, sampling_interval(&app)).await
+}
+
+#[tauri::command]
+pub async fn opcua_unsubscribe(opcua: State<'_, OpcUa>, node_ids: Vec<String>) -> Result<()> {
+    let (session, subscription_id, items) = {
+        let mut connection = opcua.0.lock().unwrap();
+        let connection = connection
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("OPC UA is not connected".into()))?;
+        let items: Vec<u32> = node_ids
+            .iter()
+            .filter_map(|id| connection.monitored.remove(id))
+            .collect();
+        (
+            connection.session.clone(),
+            connection.subscription_id,
+            items,
+        )
+    };
+    if !items.is_empty() {
+        session
+            .delete_monitored_items(subscription_id, &items)
+            .await
+            .map_err(|err| Error::OpcUa(err.to_string()))?;
+    }
+    Ok(())
+}
+
+fn sampling_interval(app: &AppHandle) -> Duration {
+    Duration::from_millis(
+        app.state::<SettingsStore>()
+            .get()
+            .opcua
+            .publishing_interval_ms,
+    )
+}
+
+async fn monitor(opcua: &OpcUa, node_ids: Vec<String>, sampling: Duration) -> Result<()> {
+    let (session, subscription_id, node_ids) = {
+        let connection = opcua.0.lock().unwrap();
+        let connection = connection
+            .as_ref()
+            .ok_or_else(|| Error::Invalid("OPC UA is not connected".into()))?;
+        let fresh: Vec<String> = node_ids
+            .into_iter()
+            .filter(|id| !connection.monitored.contains_key(id))
+            .collect();
+        (
+            connection.session.clone(),
+            connection.subscription_id,
+            fresh,
+        )
+    };
+    if node_ids.is_empty() {
+        return Ok(());
+    }
+    let requests = node_ids
+        .iter()
+        .map(|id| {
+            let mut request = MonitoredItemCreateRequest::from(parse_node_id(id)?);
+            request.requested_parameters.sampling_interval = sampling.as_secs_f64() * 1000.0;
+            request.requested_parameters.queue_size = 1;
+            Ok(request)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let created = session
+        .create_monitored_items(subscription_id, TimestampsToReturn::Source, requests)
+        .await
+        .map_err(|err| Error::OpcUa(err.to_string()))?;
+
+    let mut rejected = Vec::new();
+    let mut connection = opcua.0.lock().unwrap();
+    for (node_id, item) in node_ids.into_iter().zip(created) {
+        if item.result.status_code.is_good() {
+            if let Some(connection) = connection.as_mut() {
+                connection
+                    .monitored
+                    .insert(node_id, item.result.monitored_item_id);
+            }
+        } else {
+            rejected.push(format!("{node_id}: {}", item.result.status_code));
+        }
+    }
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::OpcUa(format!(
+            "cannot monitor {}",
+            rejected.join(", ")
+        )))
+    }
+}
+
+async fn disconnect(opcua: &OpcUa) {
+    let Some(connection) = opcua.0.lock().unwrap().take() else {
+        return;
+    };
+    if let Err(err) = connection.session.disconnect().await {
+        tracing::debug!("OPC UA disconnect: {err}");
+    }
+    connection.event_loop.abort();
+}
+
+async fn run_event_loop(app: AppHandle, event_loop: SessionEventLoop<TcpConnector>) {
+    let mut events = std::pin::pin!(event_loop.enter());
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(SessionPollResult::Reconnected(_)) => emit_status(&app, true, None),
+            Ok(SessionPollResult::ConnectionLost(code)) => {
+                tracing::warn!("OPC UA connection lost: {code}");
+                emit_status(&app, false, Some(code.to_string()));
+            }
+            Ok(SessionPollResult::ReconnectFailed(code)) => {
+                emit_status(&app, false, Some(code.to_string()))
+            }
+            Ok(_) => {}
+            Err(code) => {
+                emit_status(&app, false, Some(code.to_string()));
+                break;
+            }
+        }
+    }
+}
+
+fn parse_node_id(id: &str) -> Result<NodeId> {
+    NodeId::from_str(id).map_err(|_| Error::Invalid(format!("OPC UA node id {id}")))
+}
+
+fn emit_status(app: &AppHandle, connected: bool, error: Option<String>) {
+    let _ = app.emit("opcua-status", OpcStatus { connected, error });
+}
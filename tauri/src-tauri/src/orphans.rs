@@ -0,0 +1,143 @@
+//! Cleanup of backends left running by a shell that crashed.
+//!
+//! An orphaned `percus-server` keeps the cameras, ports and socket locked,
+//! so the next backend fails in ways that do not point at the cause. The
+//! supervisor therefore writes `backend.pid` next to each backend's data
+//! (the app data directory, or a session's data directory) once it is
+//! spawned and removes it when the backend exits. Before spawning, [`reap`]
+//! reads a lock left behind and terminates the process it names, but only
+//! when that process is still the one recorded: same start time and same
+//! executable, so a pid reused by the OS since is never touched. The lock
+//! is removed either way.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System, UpdateKind};
+
+use crate::fsutil;
+
+pub const LOCK_FILE: &str = "backend.pid";
+/// How long an orphan gets to exit after SIGTERM before it is killed.
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Lock {
+    pid: u32,
+    /// Seconds since the epoch, as the OS reports it.
+    started_at: u64,
+    exe: Option<PathBuf>,
+    /// Shell that spawned it.
+    shell_pid: u32,
+}
+
+/// Records the backend `pid` just spawned in `path`.
+pub fn record(path: &Path, pid: u32) {
+    let Some((started_at, exe)) = identify(pid) else {
+        tracing::warn!(pid, "backend exited before its lock was written");
+        return;
+    };
+    let lock = Lock {
+        pid,
+        started_at,
+        exe,
+        shell_pid: std::process::id(),
+    };
+    let written = serde_json::to_vec(&lock)
+        .map_err(std::io::Error::from)
+        .and_then(|bytes| fsutil::write_atomic(path, &bytes));
+    if let Err(err) = written {
+        tracing::warn!(path = %path.display(), "backend lock not written: {err}");
+    }
+}
+
+/// Removes the lock once its backend has exited.
+pub fn clear(path: &Path) {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            tracing::warn!(path = %path.display(), "backend lock not removed: {err}");
+        }
+        _ => {}
+    }
+}
+
+/// Terminates the backend recorded in `path`, if it still runs.
+pub async fn reap(path: &Path) {
+    let lock = match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice::<Lock>(&bytes),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+        Err(err) => {
+            tracing::warn!(path = %path.display(), "backend lock unreadable: {err}");
+            return;
+        }
+    };
+    let lock = match lock {
+        Ok(lock) => lock,
+        Err(err) => {
+            tracing::warn!(path = %path.display(), "malformed backend lock: {err}");
+            return clear(path);
+        }
+    };
+    let ours = identify(lock.pid).is_some_and(|(started_at, exe)| {
+        started_at == lock.started_at && (lock.exe.is_none() || exe == lock.exe)
+    });
+    if ours {
+        tracing::warn!(
+            pid = lock.pid,
+            shell_pid = lock.shell_pid,
+            "terminating backend left running by a previous shell"
+        );
+        terminate(lock.pid).await;
+    }
+    clear(path);
+}
+
+/// Start time and executable of process `pid`, if it exists.
+fn identify(pid: u32) -> Option<(u64, Option<PathBuf>)> {
+    let mut system = System::new();
+    let pid = Pid::from_u32(pid);
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing().with_exe(UpdateKind::Always),
+    );
+    let process = system.process(pid)?;
+    Some((process.start_time(), process.exe().map(Path::to_owned)))
+}
+
+async fn terminate(pid: u32) {
+    let mut system = System::new();
+    let pid = Pid::from_u32(pid);
+    let refresh = |system: &mut System| {
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+        system.process(pid).is_some()
+    };
+    if !refresh(&mut system) {
+        return;
+    }
+    // Windows has no SIGTERM; the process is killed outright there.
+    let asked = system
+        .process(pid)
+        .and_then(|process| process.kill_with(Signal::Term))
+        .unwrap_or(false);
+    if asked {
+        let deadline = tokio::time::Instant::now() + TERMINATE_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            if !refresh(&mut system) {
+                return;
+            }
+        }
+        tracing::warn!(%pid, "orphaned backend did not stop within {TERMINATE_TIMEOUT:?}; killing");
+    }
+    if let Some(process) = system.process(pid) {
+        process.kill();
+    }
+}
@@ -0,0 +1,314 @@
+//! OpenTelemetry traces of the shell, continued by the sidecar.
+//!
+//! With `otel.enabled`, spans are exported over OTLP (`grpc` or `http`) to
+//! `otel.endpoint`, sampled at `sampleRatio` unless the parent decided.
+//! Which spans are exported is set by `otel.filter`, an `EnvFilter`
+//! directive independent of `RUST_LOG`; by default it takes every command
+//! handler (Tauri's `ipc::request` spans), the webview's proxied requests
+//! ([`crate::backend_socket`], [`crate::ws_proxy`]) and the driver requests
+//! to the robot controller, power source, gripper and OPC UA server.
+//! Changed settings replace the exporter at once.
+//!
+//! Trace context reaches the sidecar as a W3C `traceparent` header on the
+//! requests the shell forwards (see [`inject`]) and, together with the
+//! `OTEL_*` variables pointing its own exporter at the same collector, as
+//! `TRACEPARENT` when it is spawned (see [`backend_env`]).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{Link, SpanKind, TraceId, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+use opentelemetry_otlp::{Protocol, WithExportConfig, WithHttpConfig, WithTonicConfig};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{
+    BatchSpanProcessor, Sampler, SamplingResult, SdkTracer, SdkTracerProvider, ShouldSample, Span,
+    SpanData, SpanProcessor,
+};
+use opentelemetry_sdk::Resource;
+use tauri::http::{HeaderMap, HeaderName, HeaderValue};
+use tauri::{AppHandle, Listener, Manager};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::filter::Filtered;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+use crate::error::{Error, Result};
+use crate::settings::{OtelSettings, Settings, SettingsStore};
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+/// `service.name` of the shell's spans.
+const SERVICE: &str = "percus-shell";
+/// `service.name` of the sidecar's spans.
+const BACKEND_SERVICE: &str = "percus-backend";
+
+/// The export layer, installed by [`crate::logging`] on the registry
+/// itself. It stays installed; [`init`] swaps its filter and exporter.
+pub type ExportLayer =
+    Filtered<OpenTelemetryLayer<Registry, SdkTracer>, reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Handles to the installed layer.
+pub struct Otel {
+    filter: reload::Handle<EnvFilter, Registry>,
+    exporting: Exporting,
+    ratio: Arc<AtomicU64>,
+    /// Settings the exporter was last built from, `None` while off.
+    applied: tokio::sync::Mutex<Option<OtelSettings>>,
+}
+
+/// The layer to install, exporting nothing until [`init`].
+pub fn layer() -> (ExportLayer, Otel) {
+    let exporting = Exporting::default();
+    let ratio = Arc::new(AtomicU64::new(1.0_f64.to_bits()));
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(exporting.clone())
+        .with_sampler(Sampler::ParentBased(Box::new(Ratio(ratio.clone()))))
+        .with_resource(resource())
+        .build();
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new("off"));
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(SERVICE))
+        .with_filter(filter);
+    let otel = Otel {
+        filter: filter_handle,
+        exporting,
+        ratio,
+        applied: tokio::sync::Mutex::new(None),
+    };
+    (layer, otel)
+}
+
+/// Starts exporting as the settings say, and follows them.
+pub fn init(app: &AppHandle) {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let settings = app.state::<SettingsStore>().get().otel;
+    tauri::async_runtime::spawn(apply(app.clone(), settings));
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            tauri::async_runtime::spawn(apply(handle.clone(), settings.otel));
+        }
+    });
+}
+
+/// Exports the spans still buffered; called on exit.
+pub fn shutdown(app: &AppHandle) {
+    let Some(otel) = app.try_state::<Otel>() else {
+        return;
+    };
+    if let Some(processor) = otel.exporting.replace(None) {
+        if let Err(err) = processor.shutdown_with_timeout(EXPORT_TIMEOUT) {
+            tracing::warn!("trace export on exit failed: {err}");
+        }
+    }
+}
+
+/// Adds the current span's `traceparent` to `headers`.
+pub fn inject(headers: &mut HeaderMap) {
+    for (name, value) in trace_context() {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Environment for a spawned backend: its exporter settings and the
+/// current span as `TRACEPARENT`; empty while export is off.
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+    let settings = app.state::<SettingsStore>().get().otel;
+    if !settings.enabled || !settings.propagate_to_backend {
+        return BTreeMap::new();
+    }
+    let protocol = match settings.protocol.as_str() {
+        "http" => "http/protobuf",
+        _ => "grpc",
+    };
+    let mut env = BTreeMap::from([
+        ("OTEL_SERVICE_NAME".into(), BACKEND_SERVICE.into()),
+        ("OTEL_EXPORTER_OTLP_ENDPOINT".into(), settings.endpoint),
+        ("OTEL_EXPORTER_OTLP_PROTOCOL".into(), protocol.into()),
+        (
+            "OTEL_TRACES_SAMPLER".into(),
+            "parentbased_traceidratio".into(),
+        ),
+        (
+            "OTEL_TRACES_SAMPLER_ARG".into(),
+            settings.sample_ratio.clamp(0.0, 1.0).to_string(),
+        ),
+        ("OTEL_PROPAGATORS".into(), "tracecontext".into()),
+    ]);
+    if !settings.headers.is_empty() {
+        let headers: Vec<String> = settings
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        env.insert("OTEL_EXPORTER_OTLP_HEADERS".into(), headers.join(","));
+    }
+    if let Some(parent) = trace_context().remove("traceparent") {
+        env.insert("TRACEPARENT".into(), parent);
+    }
+    env
+}
+
+fn trace_context() -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let context = tracing::Span::current().context();
+    TraceContextPropagator::new().inject_context(&context, &mut fields);
+    fields
+}
+
+async fn apply(app: AppHandle, settings: OtelSettings) {
+    let otel = app.state::<Otel>();
+    // Held while the exporter is built, so changes apply in order.
+    let mut applied = otel.applied.lock().await;
+    let wanted = settings.enabled.then_some(settings);
+    if *applied == wanted {
+        return;
+    }
+    *applied = wanted.clone();
+    let _ = otel.filter.reload(EnvFilter::new("off"));
+    if let Some(previous) = otel.exporting.replace(None) {
+        // Flushes what the previous exporter still holds.
+        tauri::async_runtime::spawn_blocking(move || {
+            let _ = previous.shutdown_with_timeout(EXPORT_TIMEOUT);
+        });
+    }
+    let Some(settings) = wanted else {
+        tracing::info!("trace export off");
+        return;
+    };
+    let filter = match EnvFilter::try_new(&settings.filter) {
+        Ok(filter) => filter,
+        Err(err) => {
+            tracing::warn!(filter = %settings.filter, "trace export off, bad filter: {err}");
+            return;
+        }
+    };
+    let processor = match processor(&settings) {
+        Ok(processor) => processor,
+        Err(err) => {
+            tracing::warn!(endpoint = %settings.endpoint, "trace export off: {err}");
+            return;
+        }
+    };
+    otel.ratio.store(
+        settings.sample_ratio.clamp(0.0, 1.0).to_bits(),
+        Ordering::Relaxed,
+    );
+    otel.exporting.replace(Some(processor));
+    let _ = otel.filter.reload(filter);
+    tracing::info!(endpoint = %settings.endpoint, protocol = %settings.protocol, "exporting traces");
+}
+
+/// Must run on the async runtime, which the gRPC exporter connects on.
+fn processor(settings: &OtelSettings) -> Result<BatchSpanProcessor> {
+    let headers: HashMap<String, String> = settings.headers.clone().into_iter().collect();
+    let builder = opentelemetry_otlp::SpanExporter::builder();
+    let exporter = match settings.protocol.as_str() {
+        "grpc" => {
+            let metadata = HeaderMap::try_from(&headers)
+                .map_err(|err| Error::Invalid(format!("otel.headers: {err}")))?;
+            builder
+                .with_tonic()
+                .with_endpoint(&settings.endpoint)
+                .with_timeout(EXPORT_TIMEOUT)
+                .with_metadata(MetadataMap::from_headers(metadata))
+                .build()
+        }
+        "http" => builder
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(&settings.endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .with_headers(headers)
+            .build(),
+        other => return Err(Error::Invalid(format!("otel.protocol `{other}`"))),
+    }
+    .map_err(|err| Error::Invalid(format!("otlp exporter: {err}")))?;
+    let mut processor = BatchSpanProcessor::builder(exporter).build();
+    // The provider only hands its resource to the processors it was built
+    // with.
+    processor.set_resource(&resource());
+    Ok(processor)
+}
+
+fn resource() -> Resource {
+    Resource::builder()
+        .with_service_name(SERVICE)
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Hands spans to the current exporter's batch processor, dropping them
+/// while there is none.
+#[derive(Clone, Debug, Default)]
+struct Exporting(Arc<StdMutex<Option<Arc<BatchSpanProcessor>>>>);
+
+impl Exporting {
+    /// Installs `processor` and returns the previous one.
+    fn replace(&self, processor: Option<BatchSpanProcessor>) -> Option<Arc<BatchSpanProcessor>> {
+        std::mem::replace(&mut *self.0.lock().unwrap(), processor.map(Arc::new))
+    }
+
+    fn current(&self) -> Option<Arc<BatchSpanProcessor>> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl SpanProcessor for Exporting {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        if let Some(processor) = self.current() {
+            processor.on_start(span, cx);
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if let Some(processor) = self.current() {
+            processor.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.current()
+            .map_or(Ok(()), |processor| processor.force_flush())
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.replace(None)
+            .map_or(Ok(()), |processor| processor.shutdown_with_timeout(timeout))
+    }
+}
+
+/// Trace-ID ratio sampling at a ratio that settings can change.
+#[derive(Clone, Debug)]
+struct Ratio(Arc<AtomicU64>);
+
+impl ShouldSample for Ratio {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let ratio = f64::from_bits(self.0.load(Ordering::Relaxed));
+        Sampler::TraceIdRatioBased(ratio).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        )
+    }
+}
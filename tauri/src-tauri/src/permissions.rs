@@ -0,0 +1,295 @@
+//! OS privacy permissions the backend needs: camera, microphone and, from
+//! macOS 15 on, local network access to reach the robot and cameras.
+//!
+//! On macOS they are granted to the app as a whole and the backend inherits
+//! them, but a prompt only appears for the app itself: a backend asking on
+//! its own is denied without one. [`ensure`] therefore asks for the
+//! undecided ones before the primary backend is spawned and waits for the
+//! answers. Denied permissions can only be changed in System Settings;
+//! [`get_permission_status`] gives onboarding the pane to open.
+//!
+//! Windows keeps the camera and microphone privacy switches, which are only
+//! read. On Linux, cameras fail to open for users outside the `video` group.
+//! Permissions a platform does not have are reported as `notRequired`.
+//!
+//! macOS has no query for local network access. It is probed by sending an
+//! empty mDNS query, which fails with `EHOSTUNREACH` while access is denied
+//! and shows the prompt when it was never asked for.
+
+use serde::Serialize;
+
+use crate::headless;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Permission {
+    Camera,
+    Microphone,
+    LocalNetwork,
+}
+
+const ALL: [Permission; 3] = [
+    Permission::Camera,
+    Permission::Microphone,
+    Permission::LocalNetwork,
+];
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionState {
+    Granted,
+    /// Also when blocked by device management.
+    Denied,
+    NotDetermined,
+    NotRequired,
+    Unknown,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionStatus {
+    pub permission: Permission,
+    pub state: PermissionState,
+    /// System Settings pane where the permission is changed.
+    pub settings_url: Option<&'static str>,
+}
+
+#[tauri::command]
+pub fn get_permission_status() -> Vec<PermissionStatus> {
+    ALL.into_iter()
+        .map(|permission| PermissionStatus {
+            permission,
+            state: state(permission),
+            settings_url: settings_url(permission),
+        })
+        .collect()
+}
+
+/// Asks for the permissions not decided yet, and logs the denied ones.
+/// Headless runs have nobody to answer, so they only log.
+pub async fn ensure() {
+    for permission in ALL {
+        let mut state = state(permission);
+        if state == PermissionState::NotDetermined && !headless::requested() {
+            state = request(permission).await;
+        }
+        if state == PermissionState::Denied {
+            tracing::warn!(
+                ?permission,
+                "permission denied; the backend cannot use it until it is granted in System Settings"
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn state(permission: Permission) -> PermissionState {
+    match permission {
+        Permission::Camera | Permission::Microphone => macos::capture_state(permission),
+        Permission::LocalNetwork => macos::probe_local_network(),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn state(permission: Permission) -> PermissionState {
+    let store = match permission {
+        Permission::Camera => "webcam",
+        Permission::Microphone => "microphone",
+        Permission::LocalNetwork => return PermissionState::NotRequired,
+    };
+    let key = format!(
+        r"HKCU\Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore\{store}"
+    );
+    let output = match std::process::Command::new("reg")
+        .args(["query", &key, "/v", "Value"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            tracing::debug!("privacy settings not readable: {err}");
+            return PermissionState::Unknown;
+        }
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        PermissionState::NotDetermined
+    } else if text.contains("Deny") {
+        PermissionState::Denied
+    } else if text.contains("Allow") {
+        PermissionState::Granted
+    } else {
+        PermissionState::Unknown
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn state(permission: Permission) -> PermissionState {
+    if permission != Permission::Camera {
+        return PermissionState::NotRequired;
+    }
+    let Ok(entries) = std::fs::read_dir("/dev") else {
+        return PermissionState::Unknown;
+    };
+    let devices: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("video"))
+        .map(|entry| entry.path())
+        .collect();
+    // Without a camera plugged in there is nothing to check access with.
+    if devices.is_empty() {
+        return PermissionState::NotDetermined;
+    }
+    let opened: Vec<_> = devices
+        .iter()
+        .map(|path| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)
+        })
+        .collect();
+    if opened.iter().any(|opened| opened.is_ok()) {
+        PermissionState::Granted
+    } else if opened.iter().any(
+        |opened| matches!(opened, Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied),
+    ) {
+        PermissionState::Denied
+    } else {
+        PermissionState::Unknown
+    }
+}
+
+#[cfg(target_os = "macos")]
+async fn request(permission: Permission) -> PermissionState {
+    match permission {
+        Permission::Camera | Permission::Microphone => {
+            match macos::request_capture(permission).await {
+                true => PermissionState::Granted,
+                false => PermissionState::Denied,
+            }
+        }
+        // The probe shows the prompt; access is decided in the background.
+        Permission::LocalNetwork => macos::probe_local_network(),
+    }
+}
+
+/// Nothing can be asked for; see [`state`].
+#[cfg(not(target_os = "macos"))]
+async fn request(permission: Permission) -> PermissionState {
+    state(permission)
+}
+
+fn settings_url(permission: Permission) -> Option<&'static str> {
+    if cfg!(target_os = "macos") {
+        Some(match permission {
+            Permission::Camera => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Camera"
+            }
+            Permission::Microphone => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone"
+            }
+            Permission::LocalNetwork => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_LocalNetwork"
+            }
+        })
+    } else if cfg!(target_os = "windows") {
+        match permission {
+            Permission::Camera => Some("ms-settings:privacy-webcam"),
+            Permission::Microphone => Some("ms-settings:privacy-microphone"),
+            Permission::LocalNetwork => None,
+        }
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::sync::Mutex as StdMutex;
+
+    use block2::RcBlock;
+    use objc2::msg_send;
+    use objc2::runtime::{AnyClass, AnyObject, Bool};
+
+    use super::{Permission, PermissionState};
+
+    #[link(name = "AVFoundation", kind = "framework")]
+    extern "C" {
+        static AVMediaTypeVideo: &'static AnyObject;
+        static AVMediaTypeAudio: &'static AnyObject;
+    }
+
+    fn media_type(permission: Permission) -> &'static AnyObject {
+        // SAFETY: constant NSStrings exported by AVFoundation.
+        unsafe {
+            match permission {
+                Permission::Microphone => AVMediaTypeAudio,
+                _ => AVMediaTypeVideo,
+            }
+        }
+    }
+
+    fn capture_device() -> Option<&'static AnyClass> {
+        AnyClass::get(c"AVCaptureDevice")
+    }
+
+    /// `AVCaptureDevice.authorizationStatus(for:)`.
+    pub fn capture_state(permission: Permission) -> PermissionState {
+        let Some(class) = capture_device() else {
+            return PermissionState::Unknown;
+        };
+        // SAFETY: class method taking an AVMediaType and returning an
+        // AVAuthorizationStatus (NSInteger).
+        let status: isize =
+            unsafe { msg_send![class, authorizationStatusForMediaType: media_type(permission)] };
+        match status {
+            0 => PermissionState::NotDetermined,
+            // Restricted, then denied.
+            1 | 2 => PermissionState::Denied,
+            3 => PermissionState::Granted,
+            _ => PermissionState::Unknown,
+        }
+    }
+
+    /// Shows the prompt and waits for the answer.
+    pub async fn request_capture(permission: Permission) -> bool {
+        let Some(class) = capture_device() else {
+            return false;
+        };
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        {
+            let sender = StdMutex::new(Some(sender));
+            let handler = RcBlock::new(move |granted: Bool| {
+                if let Some(sender) = sender.lock().unwrap().take() {
+                    let _ = sender.send(granted.as_bool());
+                }
+            });
+            // SAFETY: the handler is copied by AVFoundation and called once,
+            // on an arbitrary queue.
+            let () = unsafe {
+                msg_send![
+                    class,
+                    requestAccessForMediaType: media_type(permission),
+                    completionHandler: &*handler
+                ]
+            };
+        }
+        receiver.await.unwrap_or(false)
+    }
+
+    pub fn probe_local_network() -> PermissionState {
+        // Header of an mDNS query without questions.
+        let query = [0u8; 12];
+        let sent = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .and_then(|socket| socket.send_to(&query, (Ipv4Addr::new(224, 0, 0, 251), 5353)));
+        match sent {
+            Ok(_) => PermissionState::Granted,
+            Err(err) if err.raw_os_error() == Some(libc::EHOSTUNREACH) => PermissionState::Denied,
+            Err(err) => {
+                tracing::debug!("local network probe failed: {err}");
+                PermissionState::Unknown
+            }
+        }
+    }
+}
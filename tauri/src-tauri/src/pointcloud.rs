@@ -0,0 +1,303 @@
+//! Point clouds from depth frame rings, decimated for the 3D viewer.
+//!
+//! [`start_point_cloud`] follows a depth stream (`Z16 ` frames, e.g. a
+//! RealSense `16UC1` topic through [`crate::ros2`]), back-projects every new
+//! frame with the stream's pinhole intrinsics and averages the points per
+//! voxel. The voxel edge starts at `voxelSize` and grows while a frame
+//! would exceed `maxPoints`, shrinking back once the scene allows it, so the
+//! webview never receives more than its budget.
+//!
+//! Clouds go to the webview's `Channel` as raw bytes, which arrive as an
+//! `ArrayBuffer` without JSON. Each message is, little-endian:
+//!
+//! - frame sequence `u64`, timestamp in ns `u64`, point count `u32`, voxel
+//!   edge in metres `f32`
+//! - `count` points of `x y z` as `f32`, in metres in the camera's optical
+//!   frame (x right, y down, z forward)
+//!
+//! The header is 24 bytes, so the points can be viewed as a
+//! `Float32Array` in place.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{AppHandle, Manager, State};
+
+use crate::calibration;
+use crate::error::{Error, Result};
+use crate::frames::{self, Frame, FrameRings};
+
+const HEADER_LEN: usize = 24;
+/// Rebinning passes before a frame over budget is thinned instead.
+const MAX_PASSES: usize = 3;
+/// Largest pixel step taken when voxels cover many pixels.
+const MAX_STRIDE: usize = 8;
+
+/// Pinhole intrinsics of the depth image, in pixels.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Intrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PointCloudOptions {
+    pub max_points: usize,
+    /// Smallest voxel edge, in metres.
+    pub voxel_size: f32,
+    /// Metres per depth unit; RealSense and ROS `16UC1` use millimetres.
+    pub depth_scale: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+    pub max_hz: f64,
+    /// Taken from the stream's camera calibration when unset.
+    pub intrinsics: Option<Intrinsics>,
+}
+
+impl Default for PointCloudOptions {
+    fn default() -> Self {
+        Self {
+            max_points: 50_000,
+            voxel_size: 0.01,
+            depth_scale: 0.001,
+            min_depth: 0.1,
+            max_depth: 3.0,
+            max_hz: 15.0,
+            intrinsics: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PointClouds {
+    next_id: AtomicU32,
+    running: StdMutex<HashMap<u32, Arc<AtomicBool>>>,
+}
+
+/// Streams decimated clouds of depth stream `stream` to `channel` until
+/// [`stop_point_cloud`] is called or the webview goes away; returns the id
+/// to stop it with.
+#[tauri::command]
+pub fn start_point_cloud(
+    app: AppHandle,
+    clouds: State<'_, PointClouds>,
+    stream: String,
+    channel: Channel,
+    options: Option<PointCloudOptions>,
+) -> Result<u32> {
+    if !frames::valid_stream(&stream) {
+        return Err(Error::Invalid(format!("stream `{stream}`")));
+    }
+    let options = options.unwrap_or_default();
+    if options.max_points == 0
+        || options.voxel_size <= 0.0
+        || options.depth_scale <= 0.0
+        || options.max_depth <= options.min_depth
+        || options.max_hz <= 0.0
+    {
+        return Err(Error::Invalid("point cloud options".into()));
+    }
+    let id = clouds.next_id.fetch_add(1, Ordering::Relaxed);
+    let stop = Arc::new(AtomicBool::new(false));
+    clouds.running.lock().unwrap().insert(id, stop.clone());
+    tauri::async_runtime::spawn(async move {
+        if let Err(err) = run(&app, &stream, &channel, options, &stop).await {
+            tracing::warn!(stream = %stream, "point cloud stopped: {err}");
+        }
+        app.state::<PointClouds>()
+            .running
+            .lock()
+            .unwrap()
+            .remove(&id);
+    });
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn stop_point_cloud(clouds: State<'_, PointClouds>, id: u32) -> Result<()> {
+    let stop = clouds
+        .running
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("point cloud {id}")))?;
+    stop.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+async fn run(
+    app: &AppHandle,
+    stream: &str,
+    channel: &Channel,
+    options: PointCloudOptions,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / options.max_hz));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut last = None;
+    let mut voxel = options.voxel_size;
+    let mut intrinsics = options.intrinsics.map(|k| (0, 0, k));
+    while !stop.load(Ordering::Relaxed) {
+        ticker.tick().await;
+        let Some(frame) = app.state::<FrameRings>().latest_frame(stream)? else {
+            continue;
+        };
+        if last == Some(frame.seq) {
+            continue;
+        }
+        last = Some(frame.seq);
+        if &frame.fourcc != b"Z16 " {
+            return Err(Error::Unsupported(format!(
+                "{} frames; expected Z16 depth",
+                String::from_utf8_lossy(&frame.fourcc)
+            )));
+        }
+        let size = (frame.width, frame.height);
+        if frame.data.len() < size.0 as usize * size.1 as usize * 2 || size.0 == 0 {
+            return Err(Error::Invalid(format!(
+                "truncated depth frame {}",
+                frame.seq
+            )));
+        }
+        let k = match intrinsics {
+            Some((width, height, k)) if options.intrinsics.is_some() || (width, height) == size => {
+                k
+            }
+            _ => {
+                let [fx, fy, cx, cy] = calibration::intrinsics(app, stream, size.0, size.1)?
+                    .ok_or_else(|| {
+                        Error::NotFound(format!(
+                            "intrinsics for `{stream}`; calibrate it or pass them"
+                        ))
+                    })?;
+                let k = Intrinsics { fx, fy, cx, cy };
+                intrinsics = Some((size.0, size.1, k));
+                k
+            }
+        };
+        let built = {
+            let options = options.clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let points = decimate(&frame, k, &options, &mut voxel);
+                (encode(&frame, &points, voxel), voxel)
+            })
+            .await
+            .map_err(|err| Error::Stream(err.to_string()))?
+        };
+        let (message, adapted) = built;
+        voxel = adapted;
+        if channel.send(InvokeResponseBody::Raw(message)).is_err() {
+            // The webview reloaded or closed.
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Back-projects `frame` and returns one averaged point per occupied voxel,
+/// at most `max_points`. Adjusts `voxel` towards the smallest edge that
+/// keeps the frame within budget.
+fn decimate(
+    frame: &Frame,
+    k: Intrinsics,
+    options: &PointCloudOptions,
+    voxel: &mut f32,
+) -> Vec<[f32; 3]> {
+    let budget = options.max_points;
+    let mut cells = bin(frame, k, options, *voxel);
+    for _ in 0..MAX_PASSES {
+        if cells.len() <= budget {
+            break;
+        }
+        // Surfaces fill voxels with the square of their inverse edge.
+        *voxel *= (cells.len() as f32 / budget as f32).sqrt() * 1.05;
+        cells = bin(frame, k, options, *voxel);
+    }
+    if cells.len() < budget / 2 && *voxel > options.voxel_size {
+        let shrunk = *voxel * (cells.len().max(1) as f32 / budget as f32).sqrt().max(0.8);
+        *voxel = shrunk.max(options.voxel_size);
+    }
+
+    let mut points: Vec<[f32; 3]> = cells
+        .into_values()
+        .map(|(sum, n)| {
+            let n = n as f32;
+            [sum[0] / n, sum[1] / n, sum[2] / n]
+        })
+        .collect();
+    if points.len() > budget {
+        let step = points.len().div_ceil(budget);
+        points = points.into_iter().step_by(step).collect();
+    }
+    points
+}
+
+/// Sums of the points falling into each voxel, keyed by voxel index.
+fn bin(
+    frame: &Frame,
+    k: Intrinsics,
+    options: &PointCloudOptions,
+    voxel: f32,
+) -> HashMap<u64, ([f32; 3], u32)> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    // Sample at half the pixel width of a voxel at the far limit, so every
+    // voxel in range is still hit.
+    let stride = ((f64::from(voxel) * k.fx / f64::from(options.max_depth) / 2.0) as usize)
+        .clamp(1, MAX_STRIDE);
+    let (fx, fy, cx, cy) = (k.fx as f32, k.fy as f32, k.cx as f32, k.cy as f32);
+    let inverse = 1.0 / voxel;
+    let mut cells: HashMap<u64, ([f32; 3], u32)> =
+        HashMap::with_capacity(options.max_points.min(width * height / (stride * stride)));
+    for v in (0..height).step_by(stride) {
+        let row = &frame.data[v * width * 2..(v + 1) * width * 2];
+        for u in (0..width).step_by(stride) {
+            let raw = u16::from_le_bytes([row[2 * u], row[2 * u + 1]]);
+            if raw == 0 {
+                continue;
+            }
+            let z = f32::from(raw) * options.depth_scale;
+            if z < options.min_depth || z > options.max_depth {
+                continue;
+            }
+            let x = (u as f32 - cx) * z / fx;
+            let y = (v as f32 - cy) * z / fy;
+            let cell = cells
+                .entry(voxel_key(x * inverse, y * inverse, z * inverse))
+                .or_insert(([0.0; 3], 0));
+            cell.0[0] += x;
+            cell.0[1] += y;
+            cell.0[2] += z;
+            cell.1 += 1;
+        }
+    }
+    cells
+}
+
+/// Packs a voxel index into 21 bits per axis.
+fn voxel_key(x: f32, y: f32, z: f32) -> u64 {
+    const OFFSET: i64 = 1 << 20;
+    let axis = |value: f32| ((value.floor() as i64 + OFFSET) as u64) & 0x1f_ffff;
+    (axis(x) << 42) | (axis(y) << 21) | axis(z)
+}
+
+fn encode(frame: &Frame, points: &[[f32; 3]], voxel: f32) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + points.len() * 12);
+    message.extend_from_slice(&frame.seq.to_le_bytes());
+    message.extend_from_slice(&frame.timestamp_ns.to_le_bytes());
+    message.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    message.extend_from_slice(&voxel.to_le_bytes());
+    for point in points {
+        for value in point {
+            message.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    message
+}
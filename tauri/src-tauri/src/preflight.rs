@@ -0,0 +1,543 @@
+//! Network preflight for installs on locked-down plant networks.
+//!
+//! [`run_network_preflight`] checks, all at once and each within
+//! [`TIMEOUT`]:
+//!
+//! - `endpoint`: TCP to the robot controller, the weld power source, the
+//!   robot gateway, the MQTT broker and the OPC UA server, through the SSH
+//!   tunnel to them if one is open
+//! - `backend`: the webview's socket or HTTP port of the backend, its TLS
+//!   port and its `/health`
+//! - `cloud`: HTTPS to the update, Hub, upload, license, analytics and
+//!   connectivity endpoints; any HTTP response counts as reachable
+//! - `proxy`: proxy variables that route cloud or loopback traffic through a
+//!   proxy, and certificates replaced by a TLS-inspecting one
+//! - `vpn`: VPN interfaces and robot traffic leaving through one
+//! - `port`: local ports the shell listens on that something else holds
+//!
+//! Every check ends `pass`, `warn`, `fail` or `skipped` (nothing
+//! configured), with a message and, for problems, a hint at the usual cause.
+//! The last report is kept for diagnostic bundles.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use serde::Serialize;
+use tauri::http::Request;
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpStream;
+
+use crate::backend_socket;
+use crate::backend_tls;
+use crate::headless;
+use crate::settings::{Settings, SettingsStore};
+use crate::sidecar::{self, SidecarState};
+use crate::tunnels;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const PROXY_VARS: &[&str] = &["HTTPS_PROXY", "HTTP_PROXY", "ALL_PROXY"];
+/// Interface name prefixes of VPN clients (Linux).
+const VPN_INTERFACES: &[&str] = &["tun", "tap", "wg", "ppp", "tailscale", "zt", "utun"];
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skipped,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    /// `endpoint`, `backend`, `cloud`, `proxy`, `vpn` or `port`.
+    pub category: &'static str,
+    pub name: String,
+    /// `host:port` or URL checked.
+    pub target: Option<String>,
+    pub status: CheckStatus,
+    pub message: String,
+    pub hint: Option<String>,
+    pub latency_ms: Option<f64>,
+}
+
+/// The most recent report, if a preflight has run.
+#[derive(Default)]
+pub struct LastPreflight(StdMutex<Option<PreflightReport>>);
+
+impl LastPreflight {
+    pub fn get(&self) -> Option<PreflightReport> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightReport {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    /// The worst status of the checks.
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+impl Check {
+    fn new(category: &'static str, name: &str, target: Option<String>) -> Self {
+        Self {
+            category,
+            name: name.to_owned(),
+            target,
+            status: CheckStatus::Pass,
+            message: String::new(),
+            hint: None,
+            latency_ms: None,
+        }
+    }
+
+    fn with(mut self, status: CheckStatus, message: impl Into<String>) -> Self {
+        self.status = status;
+        self.message = message.into();
+        self
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    fn skipped(category: &'static str, name: &str, setting: &str) -> Self {
+        Self::new(category, name, None).with(CheckStatus::Skipped, format!("{setting} is not set"))
+    }
+}
+
+/// Runs every check and returns the report.
+#[tauri::command]
+pub async fn run_network_preflight(app: AppHandle) -> PreflightReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let settings = app.state::<SettingsStore>().get();
+    let robot_host = settings.robot_host();
+
+    let endpoints = join_all(endpoints(&app, &settings).into_iter().map(
+        |(name, setting, target)| async move {
+            match target {
+                Some(target) => tcp(name, target).await,
+                None => Check::skipped("endpoint", name, setting),
+            }
+        },
+    ));
+    let cloud = join_all(
+        cloud(&settings)
+            .into_iter()
+            .map(|(name, setting, url)| async move {
+                match url {
+                    Some(url) => https(name, url).await,
+                    None => Check::skipped("cloud", name, setting),
+                }
+            }),
+    );
+    let (endpoints, backend, cloud) = tokio::join!(endpoints, backend(&app), cloud);
+
+    let mut checks = endpoints;
+    checks.extend(backend);
+    checks.extend(cloud);
+    checks.push(proxy());
+    checks.push(vpn(robot_host.as_deref()));
+    checks.extend(ports(&app, &settings));
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let failed = count(CheckStatus::Fail);
+    let status = if failed > 0 {
+        CheckStatus::Fail
+    } else if count(CheckStatus::Warn) > 0 {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    tracing::info!(checks = checks.len(), failed, "network preflight finished");
+    let report = PreflightReport {
+        started_at,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        status,
+        checks,
+    };
+    *app.state::<LastPreflight>().0.lock().unwrap() = Some(report.clone());
+    report
+}
+
+/// Name, setting and `host:port` of each configured field endpoint.
+fn endpoints(
+    app: &AppHandle,
+    settings: &Settings,
+) -> Vec<(&'static str, &'static str, Option<String>)> {
+    let tunneled = |host: Option<String>, port: u16| {
+        host.map(|host| {
+            let (host, port) = tunnels::resolve(app, &host, port);
+            join(&host, port)
+        })
+    };
+    vec![
+        (
+            "Robot controller",
+            "robot.host",
+            tunneled(settings.robot_host(), settings.robot.port),
+        ),
+        (
+            "Weld power source",
+            "weld.host",
+            tunneled(settings.weld.host.clone(), settings.weld.port),
+        ),
+        (
+            "Robot gateway",
+            "grpc.endpoint",
+            settings.grpc.endpoint.as_deref().and_then(authority),
+        ),
+        (
+            "MQTT broker",
+            "mqtt.host",
+            tunneled(settings.mqtt.host.clone(), settings.mqtt.port),
+        ),
+        (
+            "OPC UA server",
+            "opcua.endpoint",
+            settings.opcua.endpoint.as_deref().and_then(authority),
+        ),
+    ]
+}
+
+/// Name, setting and URL of each cloud API.
+fn cloud(settings: &Settings) -> Vec<(&'static str, &'static str, Option<String>)> {
+    vec![
+        (
+            "Updates",
+            "updates.endpoint",
+            origin(&settings.updates.endpoint),
+        ),
+        (
+            "Hugging Face Hub",
+            "hub.endpoint",
+            origin(&settings.hub.endpoint),
+        ),
+        (
+            "Uploads",
+            "uploads.endpoint",
+            settings.uploads.endpoint.as_deref().and_then(origin),
+        ),
+        (
+            "License server",
+            "licensing.server",
+            settings.licensing.server.as_deref().and_then(origin),
+        ),
+        (
+            "Analytics",
+            "analytics.endpoint",
+            settings.analytics.endpoint.as_deref().and_then(origin),
+        ),
+        (
+            "Connectivity probe",
+            "offline.probeUrl",
+            Some(settings.offline.probe_url.clone()).filter(|url| !url.is_empty()),
+        ),
+    ]
+}
+
+async fn tcp(name: &str, target: String) -> Check {
+    let check = Check::new("endpoint", name, Some(target.clone()));
+    let (host, port) = target.rsplit_once(':').unwrap_or((&target, ""));
+    let host = host.trim_matches(|c| c == '[' || c == ']');
+    let Ok(port) = port.parse::<u16>() else {
+        return check.with(CheckStatus::Fail, "no port");
+    };
+    connect(check, host, port).await
+}
+
+async fn connect(mut check: Check, host: &str, port: u16) -> Check {
+    let addr = match tokio::time::timeout(TIMEOUT, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(mut addrs)) => addrs.next(),
+        Ok(Err(_)) | Err(_) => None,
+    };
+    let Some(addr) = addr else {
+        return check
+            .with(CheckStatus::Fail, format!("{host} does not resolve"))
+            .hint("Check the host name, or enter the IP address; the site DNS may not know it.");
+    };
+    let sent = Instant::now();
+    match tokio::time::timeout(TIMEOUT, TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => {
+            check.latency_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+            check.with(CheckStatus::Pass, format!("connected to {addr}"))
+        }
+        Ok(Err(err)) if err.kind() == std::io::ErrorKind::ConnectionRefused => check
+            .with(CheckStatus::Fail, format!("{addr} refused the connection"))
+            .hint(format!(
+                "The host is up but nothing listens on port {port}; check the port setting and that the service runs."
+            )),
+        Ok(Err(err)) => check
+            .with(CheckStatus::Fail, format!("{addr}: {err}"))
+            .hint("No route to the host; check the cabling, the PC's address and subnet, and the VPN."),
+        Err(_) => check
+            .with(
+                CheckStatus::Fail,
+                format!("no answer from {addr} within {}s", TIMEOUT.as_secs()),
+            )
+            .hint(format!(
+                "A firewall is probably dropping traffic; allow outbound TCP {port} to {host}."
+            )),
+    }
+}
+
+async fn backend(app: &AppHandle) -> Vec<Check> {
+    let state = app.state::<SidecarState>();
+    let (port, tls_port) = (state.port(), state.tls_port());
+    if tls_port == 0 {
+        return vec![Check::new("backend", "Backend", None)
+            .with(CheckStatus::Skipped, "the backend has not started")];
+    }
+    let loopback = Ipv4Addr::LOCALHOST.to_string();
+    let mut checks = Vec::new();
+    if let Some(socket) = state.socket() {
+        checks.push(socket_health(socket).await);
+    }
+    let ports = [("Backend HTTP port", port), ("Backend TLS port", tls_port)];
+    for (name, port) in ports.into_iter().filter(|(_, port)| *port != 0) {
+        let mut check = Check::new("backend", name, Some(join(&loopback, port)));
+        check = connect(check, &loopback, port).await;
+        checks.push(check);
+    }
+    let url = backend_tls::url(app, "https", "/health");
+    let mut check = Check::new("backend", "Backend health", Some(url.clone()));
+    let sent = Instant::now();
+    let response =
+        tokio::time::timeout(TIMEOUT, backend_tls::http_client(app).get(&url).send()).await;
+    check = match response {
+        Ok(Ok(response)) if response.status().is_success() => {
+            check.latency_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+            check.with(CheckStatus::Pass, "healthy")
+        }
+        Ok(Ok(response)) => check.with(CheckStatus::Fail, format!("answered {}", response.status())),
+        Ok(Err(err)) => check.with(CheckStatus::Fail, describe(&err)).hint(
+            "Loopback requests fail: an HTTP proxy intercepting 127.0.0.1 or endpoint security software may block them.",
+        ),
+        Err(_) => check.with(CheckStatus::Fail, "no answer"),
+    };
+    checks.push(check);
+    checks
+}
+
+async fn socket_health(socket: PathBuf) -> Check {
+    let mut check = Check::new(
+        "backend",
+        "Backend socket",
+        Some(socket.display().to_string()),
+    );
+    let request = Request::get("/health").body(Vec::new()).unwrap();
+    let sent = Instant::now();
+    match tokio::time::timeout(TIMEOUT, backend_socket::forward(&socket, request)).await {
+        Ok(Ok(response)) if response.status().is_success() => {
+            check.latency_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+            check.with(CheckStatus::Pass, "healthy")
+        }
+        Ok(Ok(response)) => check.with(CheckStatus::Fail, format!("answered {}", response.status())),
+        Ok(Err(err)) => check.with(CheckStatus::Fail, err.to_string()).hint(
+            "The backend does not serve its socket; set backend.transport to tcp if it cannot bind one.",
+        ),
+        Err(_) => check.with(CheckStatus::Fail, "no answer"),
+    }
+}
+
+async fn https(name: &str, url: String) -> Check {
+    let mut check = Check::new("cloud", name, Some(url.clone()));
+    // The system proxy settings apply, as they do to the real requests.
+    let client = match reqwest::Client::builder().timeout(TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => return check.with(CheckStatus::Fail, err.to_string()),
+    };
+    let sent = Instant::now();
+    match client.get(&url).send().await {
+        Ok(response) => {
+            check.latency_ms = Some(sent.elapsed().as_secs_f64() * 1000.0);
+            check.with(CheckStatus::Pass, format!("answered {}", response.status()))
+        }
+        Err(err) => {
+            let message = describe(&err);
+            let lower = message.to_lowercase();
+            let hint = if lower.contains("certificate") || lower.contains("unknownissuer") {
+                "The certificate is not trusted: a TLS-inspecting proxy or firewall probably replaces it; ask IT to exempt this host."
+            } else if err.is_timeout() {
+                "No answer: outbound HTTPS is probably blocked; ask IT to allow this host, or set HTTPS_PROXY."
+            } else if lower.contains("dns") || lower.contains("resolve") {
+                "The name does not resolve: the PC has no DNS for the internet; an HTTPS_PROXY may be required."
+            } else {
+                "Check the firewall and proxy settings for outbound HTTPS."
+            };
+            check.with(CheckStatus::Fail, message).hint(hint)
+        }
+    }
+}
+
+fn proxy() -> Check {
+    let check = Check::new("proxy", "Proxy", None);
+    let var = |name: &str| {
+        std::env::var(name)
+            .or_else(|_| std::env::var(name.to_lowercase()))
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+    let Some((name, proxy)) = PROXY_VARS.iter().find_map(|name| Some((*name, var(name)?))) else {
+        return check.with(CheckStatus::Pass, "no proxy is configured");
+    };
+    let no_proxy = var("NO_PROXY").unwrap_or_default();
+    let bypassed = no_proxy.split(',').map(str::trim).any(|entry| {
+        matches!(
+            entry,
+            "*" | "localhost" | "127.0.0.1" | "::1" | "127.0.0.0/8"
+        )
+    });
+    if bypassed {
+        check.with(
+            CheckStatus::Pass,
+            format!("cloud requests go through {name}={proxy}"),
+        )
+    } else {
+        check
+            .with(
+                CheckStatus::Warn,
+                format!("{name}={proxy} is set and NO_PROXY does not exempt localhost"),
+            )
+            .hint("Add localhost,127.0.0.1 to NO_PROXY so requests to the backend are not sent to the proxy.")
+    }
+}
+
+fn vpn(robot_host: Option<&str>) -> Check {
+    let check = Check::new("vpn", "VPN", robot_host.map(str::to_owned));
+    let interfaces = vpn_interfaces();
+    let robot = robot_host.and_then(|host| host.parse::<IpAddr>().ok());
+    // The address the OS picks to reach the robot tells which route it takes.
+    let source = robot.and_then(|robot| {
+        let socket = UdpSocket::bind(if robot.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, 0))
+        })
+        .ok()?;
+        socket.connect((robot, 9)).ok()?;
+        Some(socket.local_addr().ok()?.ip())
+    });
+    if let (Some(robot), Some(IpAddr::V4(source))) = (robot, source) {
+        // 100.64.0.0/10 is what overlay VPNs like Tailscale hand out.
+        let overlay = source.octets()[0] == 100 && source.octets()[1] & 0xC0 == 64;
+        let private = matches!(robot, IpAddr::V4(ip) if ip.is_private());
+        if overlay && private {
+            return check
+                .with(
+                    CheckStatus::Warn,
+                    format!("traffic to {robot} leaves from VPN address {source}"),
+                )
+                .hint("The VPN routes the robot's subnet; exclude it from the VPN (split tunneling) or disconnect.");
+        }
+    }
+    if interfaces.is_empty() {
+        return check.with(CheckStatus::Pass, "no VPN interface is up");
+    }
+    check
+        .with(
+            CheckStatus::Warn,
+            format!("VPN interfaces are up: {}", interfaces.join(", ")),
+        )
+        .hint("A VPN may capture the robot's subnet or block local traffic; disconnect it if the robot is unreachable.")
+}
+
+/// Up interfaces named like a VPN client's; only known on Linux.
+fn vpn_interfaces() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/net") else {
+        return Vec::new();
+    };
+    let mut interfaces: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| VPN_INTERFACES.iter().any(|prefix| name.starts_with(prefix)))
+        .filter(|name| {
+            std::fs::read_to_string(format!("/sys/class/net/{name}/operstate"))
+                .is_ok_and(|state| state.trim() != "down")
+        })
+        .collect();
+    interfaces.sort();
+    interfaces
+}
+
+fn ports(app: &AppHandle, settings: &Settings) -> Vec<Check> {
+    let mut checks = Vec::new();
+    // A backend on another port means the preferred one was taken.
+    let backend_port = app.state::<SidecarState>().port();
+    if backend_port != 0 && backend_port != sidecar::PREFERRED_PORT {
+        checks.push(port(
+            "Backend port",
+            sidecar::PREFERRED_PORT,
+            Ipv4Addr::UNSPECIFIED,
+            "The backend falls back to another port, so bookmarks and scripts using the usual URL break.",
+        ));
+    }
+    if let Some(admin_port) = settings
+        .headless
+        .admin_port
+        .filter(|_| !headless::requested())
+    {
+        checks.push(port(
+            "Headless admin port",
+            admin_port,
+            Ipv4Addr::LOCALHOST,
+            "Headless runs cannot serve the admin API; change headless.adminPort.",
+        ));
+    }
+    checks
+}
+
+fn port(name: &str, port: u16, ip: Ipv4Addr, hint: &str) -> Check {
+    let check = Check::new("port", name, Some(join(&ip.to_string(), port)));
+    match TcpListener::bind((ip, port)) {
+        Ok(_) => check.with(CheckStatus::Pass, format!("port {port} is free")),
+        Err(err) => check
+            .with(CheckStatus::Warn, format!("port {port} is in use: {err}"))
+            .hint(hint),
+    }
+}
+
+/// `host:port` of `url`, with the scheme's default port.
+fn authority(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let port = url.port_or_known_default().or(match url.scheme() {
+        "opc.tcp" => Some(4840),
+        _ => None,
+    })?;
+    Some(join(url.host_str()?, port))
+}
+
+/// Scheme and host of `url`, which answer even where the path needs auth.
+fn origin(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some(url.origin().ascii_serialization()).filter(|origin| origin != "null")
+}
+
+fn join(host: &str, port: u16) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{ip}]:{port}"),
+        _ => format!("{host}:{port}"),
+    }
+}
+
+/// `err` with its sources, which hold the TLS and DNS details.
+fn describe(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,215 @@
+//! Sidecar profiles (hardware vs simulation backend).
+//!
+//! Profiles live in `profiles.toml` under the app config directory and define
+//! which binary the supervisor launches and with what arguments and
+//! environment. A default file is written on first launch:
+//!
+//! ```toml
+//! default = "hardware"
+//!
+//! [profiles.hardware]
+//! label = "Hardware"
+//! sidecar = "percus-server"
+//!
+//! [profiles.simulation]
+//! label = "Simulation"
+//! sidecar = "percus-server"
+//! env = { PHI_SIMULATION = "1" }
+//! ```
+//!
+//! `sidecar` names a binary bundled via `externalBin`; `program` may instead
+//! point at any executable on disk, and `module` (e.g.
+//! `"interfaces_backend.main"`) runs a Python module from the embedded
+//! environment set up by [`crate::pyenv`]. The selected profile is remembered in
+//! `active-profile` next to the profiles file.
+//!
+//! A profile may also run a simulator, which [`crate::simulator`] starts
+//! and supervises while the profile is selected:
+//!
+//! ```toml
+//! [profiles.simulation.simulator]
+//! label = "MuJoCo"
+//! program = "/opt/mujoco/bin/simulate"
+//! scene = "scenes/cell.xml"
+//! port = 7600
+//! args = ["{scene}", "--port", "{port}"]
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::audit;
+use crate::cli::LaunchOptions;
+use crate::error::{Error, Result};
+use crate::sidecar;
+use crate::simulator;
+use crate::users::{self, Capability};
+
+const PROFILES_FILE: &str = "profiles.toml";
+const ACTIVE_FILE: &str = "active-profile";
+
+const DEFAULT_PROFILES: &str = r#"default = "hardware"
+
+[profiles.hardware]
+label = "Hardware"
+sidecar = "percus-server"
+
+[profiles.simulation]
+label = "Simulation"
+sidecar = "percus-server"
+env = { PHI_SIMULATION = "1" }
+"#;
+
+#[derive(Deserialize)]
+struct ProfilesFile {
+    default: String,
+    profiles: BTreeMap<String, ProfileSpec>,
+}
+
+/// How to launch the backend for one profile.
+#[derive(Clone, Deserialize)]
+pub struct ProfileSpec {
+    pub label: Option<String>,
+    #[serde(default = "default_sidecar")]
+    pub sidecar: String,
+    pub program: Option<PathBuf>,
+    /// Python module to run instead of a binary.
+    pub module: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub simulator: Option<SimulatorSpec>,
+}
+
+/// A simulator to run next to the backend.
+#[derive(Clone, Deserialize)]
+pub struct SimulatorSpec {
+    pub label: Option<String>,
+    pub program: PathBuf,
+    /// Scene or stage file; relative paths are under the profiles file's
+    /// directory.
+    pub scene: Option<PathBuf>,
+    /// Port the simulator serves; a free one when unset.
+    pub port: Option<u16>,
+    /// `{scene}` and `{port}` are replaced.
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+fn default_sidecar() -> String {
+    "percus-server".into()
+}
+
+/// Entry returned by [`list_profiles`].
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileSummary {
+    name: String,
+    label: String,
+    active: bool,
+}
+
+pub struct Profiles {
+    specs: BTreeMap<String, ProfileSpec>,
+    active: Mutex<String>,
+    active_path: PathBuf,
+}
+
+impl Profiles {
+    /// Name and spec of the profile the supervisor should launch.
+    pub fn active(&self) -> (String, ProfileSpec) {
+        let name = self.active.lock().unwrap().clone();
+        let spec = self.specs[&name].clone();
+        (name, spec)
+    }
+
+    /// Spec of profile `name`, if it exists.
+    pub fn get(&self, name: &str) -> Option<ProfileSpec> {
+        self.specs.get(name).cloned()
+    }
+}
+
+/// Loads `profiles.toml`, writing the default file if it does not exist yet.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let dir = app.path().app_config_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(PROFILES_FILE);
+    if !path.exists() {
+        std::fs::write(&path, DEFAULT_PROFILES)?;
+    }
+
+    let file: ProfilesFile = toml::from_str(&std::fs::read_to_string(&path)?)?;
+    if !file.profiles.contains_key(&file.default) {
+        return Err(Error::NotFound(format!(
+            "default profile `{}`",
+            file.default
+        )));
+    }
+
+    let active_path = dir.join(ACTIVE_FILE);
+    let active = std::fs::read_to_string(&active_path)
+        .ok()
+        .map(|name| name.trim().to_owned())
+        .filter(|name| file.profiles.contains_key(name))
+        .unwrap_or(file.default);
+    // `--profile` applies to this run only, so it is not written back.
+    let active = match app.state::<LaunchOptions>().profile.clone() {
+        Some(name) if !file.profiles.contains_key(&name) => {
+            return Err(Error::NotFound(format!(
+                "profile `{name}` given by --profile"
+            )));
+        }
+        Some(name) => name,
+        None => active,
+    };
+
+    app.manage(Profiles {
+        specs: file.profiles,
+        active: Mutex::new(active),
+        active_path,
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_profiles(profiles: State<'_, Profiles>) -> Vec<ProfileSummary> {
+    let active = profiles.active.lock().unwrap();
+    profiles
+        .specs
+        .iter()
+        .map(|(name, spec)| ProfileSummary {
+            name: name.clone(),
+            label: spec.label.clone().unwrap_or_else(|| name.clone()),
+            active: *name == *active,
+        })
+        .collect()
+}
+
+/// Stops the running backend and relaunches it with the selected profile.
+#[tauri::command]
+pub async fn switch_profile(app: AppHandle, name: String) -> Result<()> {
+    users::require(&app, Capability::SwitchProfiles)?;
+    let profiles = app.state::<Profiles>();
+    if !profiles.specs.contains_key(&name) {
+        return Err(Error::NotFound(format!("profile `{name}`")));
+    }
+    std::fs::write(&profiles.active_path, &name)?;
+    let previous = std::mem::replace(&mut *profiles.active.lock().unwrap(), name.clone());
+    audit::record(
+        &app,
+        "profileSwitch",
+        serde_json::json!({ "from": previous, "to": name }),
+    );
+
+    tracing::info!(profile = %name, "switching backend profile");
+    simulator::follow_profile(&app).await;
+    sidecar::restart(&app).await;
+    Ok(())
+}
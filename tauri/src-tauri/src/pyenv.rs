@@ -0,0 +1,348 @@
+//! Embedded Python environment the backend can run from instead of a frozen
+//! `percus-server` binary.
+//!
+//! A profile with `module` set (see [`crate::profiles`]) is launched as
+//! `python -m <module>` from a virtual environment under
+//! `<app data>/python`. [`ensure`] prepares that environment before every
+//! spawn and only does work when something changed:
+//!
+//! - the runtime, a python-build-standalone `install_only` archive, is
+//!   taken from the bundled `python/runtime.tar.gz` or downloaded from
+//!   `python.runtimeUrl`, checked against `python.runtimeSha256`, and
+//!   extracted; a new runtime also gets a fresh virtual environment;
+//! - the packages listed in the bundled `python/requirements.txt` are
+//!   installed with `pip --require-hashes`, so every wheel must match the
+//!   hash pinned there. They come from the bundled `python/wheels`
+//!   directory if there is one, otherwise from `python.indexUrl` or PyPI.
+//!   A changed requirements file, e.g. after an app update, installs again.
+//!
+//! What is installed is recorded in `state.json`. Setup reports its steps
+//! as `python-setup-progress` and its outcome as `python-setup-finished`.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::downloads;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::settings::SettingsStore;
+
+const ENV_ROOT: &str = "python";
+const STATE_FILE: &str = "state.json";
+const RUNTIME_DIR: &str = "runtime";
+const VENV_DIR: &str = "env";
+/// Bundled resources, relative to the resource directory.
+const RESOURCES: &str = "python";
+const RUNTIME_ARCHIVE: &str = "runtime.tar.gz";
+const REQUIREMENTS: &str = "requirements.txt";
+const WHEELS: &str = "wheels";
+
+/// What `state.json` records as installed.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct State {
+    runtime_sha256: Option<String>,
+    requirements_sha256: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Stage {
+    Runtime,
+    Environment,
+    Packages,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SetupProgress {
+    stage: Stage,
+    /// 0 to 1, when known.
+    fraction: Option<f64>,
+    message: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SetupFinished {
+    error: Option<String>,
+}
+
+/// Where the runtime archive comes from.
+enum RuntimeSource {
+    Bundled(PathBuf),
+    Remote { url: String, sha256: String },
+}
+
+/// Brings the environment up to date; returns its Python interpreter.
+pub async fn ensure(app: &AppHandle) -> Result<PathBuf> {
+    let root = app.path().app_data_dir()?.join(ENV_ROOT);
+    let resources = app.path().resource_dir()?.join(RESOURCES);
+    let requirements = resources.join(REQUIREMENTS);
+    if !requirements.is_file() {
+        return Err(Error::NotFound(format!(
+            "bundled {RESOURCES}/{REQUIREMENTS}"
+        )));
+    }
+    std::fs::create_dir_all(&root)?;
+    let state_path = root.join(STATE_FILE);
+    let mut state: State = std::fs::read(&state_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    let source = runtime_source(app, &resources)?;
+    let runtime_sha256 = match &source {
+        RuntimeSource::Bundled(archive) => downloads::file_sha256(archive).await?,
+        RuntimeSource::Remote { sha256, .. } => sha256.to_ascii_lowercase(),
+    };
+    let requirements_sha256 = downloads::file_sha256(&requirements).await?;
+    let python = venv_python(&root.join(VENV_DIR));
+    let runtime_current =
+        state.runtime_sha256.as_deref() == Some(runtime_sha256.as_str()) && python.is_file();
+    if runtime_current && state.requirements_sha256.as_deref() == Some(&requirements_sha256) {
+        return Ok(python);
+    }
+
+    tracing::info!(root = %root.display(), "setting up the Python environment");
+    let result = async {
+        if !runtime_current {
+            install_runtime(app, &root, &source, &runtime_sha256).await?;
+            state = State {
+                runtime_sha256: Some(runtime_sha256),
+                requirements_sha256: None,
+            };
+            save(&state_path, &state)?;
+        }
+        install_packages(app, &python, &resources).await?;
+        state.requirements_sha256 = Some(requirements_sha256);
+        save(&state_path, &state)
+    }
+    .await;
+    match &result {
+        Ok(()) => tracing::info!("Python environment ready"),
+        Err(err) => tracing::error!("Python environment setup failed: {err}"),
+    }
+    let _ = app.emit(
+        "python-setup-finished",
+        SetupFinished {
+            error: result.as_ref().err().map(ToString::to_string),
+        },
+    );
+    result.map(|()| python)
+}
+
+fn runtime_source(app: &AppHandle, resources: &Path) -> Result<RuntimeSource> {
+    let bundled = resources.join(RUNTIME_ARCHIVE);
+    if bundled.is_file() {
+        return Ok(RuntimeSource::Bundled(bundled));
+    }
+    let settings = app.state::<SettingsStore>().get().python;
+    match (settings.runtime_url, settings.runtime_sha256) {
+        (Some(url), Some(sha256)) => Ok(RuntimeSource::Remote { url, sha256 }),
+        _ => Err(Error::Invalid(
+            "no Python runtime is bundled; set python.runtimeUrl and python.runtimeSha256".into(),
+        )),
+    }
+}
+
+/// Replaces the runtime and creates a new virtual environment on it.
+async fn install_runtime(
+    app: &AppHandle,
+    root: &Path,
+    source: &RuntimeSource,
+    sha256: &str,
+) -> Result<()> {
+    let downloaded;
+    let archive = match source {
+        RuntimeSource::Bundled(archive) => archive.as_path(),
+        RuntimeSource::Remote { url, .. } => {
+            let part = root.join(format!("{sha256}.part"));
+            let cancelled = AtomicBool::new(false);
+            downloads::fetch(
+                reqwest::Client::new().get(url),
+                &part,
+                &cancelled,
+                |done, total| {
+                    progress(
+                        app,
+                        Stage::Runtime,
+                        total.map(|total| done as f64 / total.max(1) as f64),
+                        None,
+                    )
+                },
+            )
+            .await?;
+            let actual = downloads::file_sha256(&part).await?;
+            if actual != sha256 {
+                let _ = std::fs::remove_file(&part);
+                return Err(Error::Invalid(format!(
+                    "Python runtime from {url} has SHA-256 {actual}, expected {sha256}"
+                )));
+            }
+            downloaded = part;
+            downloaded.as_path()
+        }
+    };
+
+    progress(app, Stage::Runtime, None, Some("extracting".into()));
+    let staging = root.join(format!("{RUNTIME_DIR}.new"));
+    let _ = std::fs::remove_dir_all(&staging);
+    {
+        let archive = archive.to_owned();
+        let staging = staging.clone();
+        tauri::async_runtime::spawn_blocking(move || extract(&archive, &staging))
+            .await
+            .map_err(|err| Error::Stream(err.to_string()))??;
+    }
+    let runtime = root.join(RUNTIME_DIR);
+    let venv = root.join(VENV_DIR);
+    let _ = std::fs::remove_dir_all(&venv);
+    let _ = std::fs::remove_dir_all(&runtime);
+    std::fs::rename(&staging, &runtime)?;
+    if let RuntimeSource::Remote { .. } = source {
+        let _ = std::fs::remove_file(archive);
+    }
+
+    progress(app, Stage::Environment, None, None);
+    let mut command = Command::new(runtime_python(&runtime));
+    command.arg("-m").arg("venv").arg(&venv);
+    run(command, |_| {}).await
+}
+
+fn extract(archive: &Path, to: &Path) -> Result<()> {
+    let file = std::fs::File::open(archive)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    std::fs::create_dir_all(to)?;
+    // `unpack_in` refuses entries that would land outside `to`.
+    for entry in archive.entries()? {
+        entry?.unpack_in(to)?;
+    }
+    Ok(())
+}
+
+/// Installs the pinned requirements into the virtual environment.
+async fn install_packages(app: &AppHandle, python: &Path, resources: &Path) -> Result<()> {
+    let requirements = resources.join(REQUIREMENTS);
+    let total = std::fs::read_to_string(&requirements)?
+        .lines()
+        .filter(|line| line.starts_with(|c: char| c.is_ascii_alphanumeric()))
+        .count();
+    let mut command = Command::new(python);
+    command
+        .args([
+            "-m",
+            "pip",
+            "install",
+            "--disable-pip-version-check",
+            "--no-input",
+        ])
+        .args(["--require-hashes", "--only-binary", ":all:", "-r"])
+        .arg(&requirements);
+    let wheels = resources.join(WHEELS);
+    if wheels.is_dir() {
+        command.arg("--no-index").arg("--find-links").arg(&wheels);
+    } else if let Some(index) = app.state::<SettingsStore>().get().python.index_url {
+        command.args(["--index-url", &index]);
+    }
+
+    progress(app, Stage::Packages, Some(0.0), None);
+    let mut done = 0;
+    run(command, |line| {
+        // One of these per requirement, before it is fetched.
+        if let Some(package) = line
+            .strip_prefix("Collecting ")
+            .or_else(|| line.strip_prefix("Processing "))
+        {
+            done += 1;
+            progress(
+                app,
+                Stage::Packages,
+                Some((done as f64 / total.max(1) as f64).min(1.0)),
+                Some(package.to_owned()),
+            );
+        }
+    })
+    .await
+}
+
+/// Runs a setup command, passing each line it prints to `line`.
+async fn run(mut command: Command, mut line: impl FnMut(&str)) -> Result<()> {
+    #[cfg(windows)]
+    command.creation_flags(CREATE_NO_WINDOW);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let errors = tauri::async_runtime::spawn(async move {
+        let mut output = Vec::new();
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::warn!(target: "pyenv", "{line}");
+            output.push(line);
+        }
+        output
+    });
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+    while let Some(output) = lines.next_line().await? {
+        tracing::debug!(target: "pyenv", "{output}");
+        line(output.trim());
+    }
+    let status = child.wait().await?;
+    let errors = errors.await.unwrap_or_default();
+    if status.success() {
+        return Ok(());
+    }
+    let tail = errors[errors.len().saturating_sub(5)..].join("\n");
+    Err(Error::Stream(format!(
+        "{:?} failed ({status}): {tail}",
+        command.as_std().get_program()
+    )))
+}
+
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+fn progress(app: &AppHandle, stage: Stage, fraction: Option<f64>, message: Option<String>) {
+    let _ = app.emit(
+        "python-setup-progress",
+        SetupProgress {
+            stage,
+            fraction,
+            message,
+        },
+    );
+}
+
+fn save(path: &Path, state: &State) -> Result<()> {
+    Ok(fsutil::write_atomic(
+        path,
+        &serde_json::to_vec_pretty(state)?,
+    )?)
+}
+
+/// Interpreter of an extracted `install_only` runtime.
+fn runtime_python(runtime: &Path) -> PathBuf {
+    if cfg!(windows) {
+        runtime.join("python/python.exe")
+    } else {
+        runtime.join("python/bin/python3")
+    }
+}
+
+fn venv_python(venv: &Path) -> PathBuf {
+    if cfg!(windows) {
+        venv.join("Scripts/python.exe")
+    } else {
+        venv.join("bin/python")
+    }
+}
@@ -0,0 +1,120 @@
+//! Backend readiness gating.
+//!
+//! The main window starts hidden; a splash window is shown until the sidecar
+//! answers `/health`, after which the main window is revealed and
+//! `backend-ready` is emitted, provided the backend passes the version
+//! handshake of [`crate::compat`]. The event fires again after every
+//! restart. Backends of other sessions are only reported, in their own
+//! namespace.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+
+use crate::backend_socket;
+use crate::backend_tls;
+use crate::cli::{LaunchOptions, WindowMode};
+use crate::compat;
+use crate::sidecar::{self, SidecarState};
+
+const MAIN_LABEL: &str = "main";
+const SPLASH_LABEL: &str = "splash";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// After this long the main window is shown anyway so the UI can report the failure.
+const READY_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendReady {
+    port: u16,
+    /// Base URL of the webview's API requests.
+    url: String,
+}
+
+/// Shows the splash window while the backend boots.
+pub fn show_splash(app: &AppHandle) -> tauri::Result<()> {
+    WebviewWindowBuilder::new(app, SPLASH_LABEL, WebviewUrl::App("splash.html".into()))
+        .title("Percus AI")
+        .inner_size(420.0, 260.0)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .center()
+        .build()?;
+    Ok(())
+}
+
+/// Polls the backend health endpoint until it responds or the timeout elapses.
+pub async fn wait_until_ready(app: AppHandle, port: u16) {
+    let url = backend_tls::url(&app, "https", "/health");
+    if healthy(&app, &url).await {
+        let compatibility = compat::check(&app, app.state::<SidecarState>().tls_port()).await;
+        compat::record(&app, &compatibility);
+        // The window shows either way, so the UI can offer the remedies.
+        reveal_main_window(&app);
+        if compatibility.compatible {
+            let url = backend_socket::get_backend_url(app.state());
+            let _ = app.emit("backend-ready", BackendReady { port, url });
+        } else {
+            let _ = app.emit("backend-incompatible", compatibility);
+        }
+        return;
+    }
+    tracing::warn!(port, "backend not ready after {READY_TIMEOUT:?}");
+    reveal_main_window(&app);
+}
+
+/// Like [`wait_until_ready`] for the backend of session `id`, which reports
+/// as `session:<id>:backend-ready` and leaves the windows alone.
+pub async fn wait_until_session_ready(app: AppHandle, id: String, port: u16, tls_port: u16) {
+    let url = backend_tls::url_at(tls_port, "https", "/health");
+    if healthy(&app, &url).await {
+        let compatibility = compat::check(&app, tls_port).await;
+        if compatibility.compatible {
+            let _ = app.emit(
+                &sidecar::session_event(&id, "backend-ready"),
+                BackendReady {
+                    port,
+                    url: format!("http://127.0.0.1:{port}"),
+                },
+            );
+        } else {
+            let _ = app.emit(
+                &sidecar::session_event(&id, "backend-incompatible"),
+                compatibility,
+            );
+        }
+        return;
+    }
+    tracing::warn!(session = %id, port, "session backend not ready after {READY_TIMEOUT:?}");
+}
+
+/// Whether `url` answers with success before [`READY_TIMEOUT`].
+async fn healthy(app: &AppHandle, url: &str) -> bool {
+    let client = backend_tls::http_client(app);
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while Instant::now() < deadline {
+        let response = client.get(url).timeout(REQUEST_TIMEOUT).send().await;
+        if response.is_ok_and(|response| response.status().is_success()) {
+            return true;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    false
+}
+
+fn reveal_main_window(app: &AppHandle) {
+    if let Some(splash) = app.get_webview_window(SPLASH_LABEL) {
+        let _ = splash.close();
+    }
+    // `--window hidden` leaves it to the tray or a second launch.
+    if app.state::<LaunchOptions>().window == WindowMode::Hidden {
+        return;
+    }
+    if let Some(main) = app.get_webview_window(MAIN_LABEL) {
+        let _ = main.show();
+        let _ = main.set_focus();
+    }
+}
@@ -0,0 +1,475 @@
+//! On-disk LeRobot dataset layout (codebase version 2.1).
+//!
+//! ```text
+//! <root>/<dataset>/
+//!   meta/info.json              features, fps, totals, path templates
+//!   meta/tasks.jsonl            {"task_index", "task"}
+//!   meta/episodes.jsonl         {"episode_index", "tasks", "length"}
+//!   meta/episodes_stats.jsonl   per-episode min/max/mean/std/count
+//!   data/chunk-000/episode_000000.parquet
+//!   videos/chunk-000/observation.images.<camera>/episode_000000.mp4
+//!   audio/chunk-000/episode_000000.wav   optional microphone track
+//! ```
+//!
+//! Episodes are grouped in chunks of [`CHUNK_SIZE`]. Only numeric features
+//! get statistics; LeRobot computes image statistics itself when they are
+//! missing.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, Int64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+
+pub const CODEBASE_VERSION: &str = "v2.1";
+pub const CHUNK_SIZE: usize = 1000;
+const DATA_PATH: &str = "data/chunk-{episode_chunk:03d}/episode_{episode_index:06d}.parquet";
+const VIDEO_PATH: &str =
+    "videos/chunk-{episode_chunk:03d}/{video_key}/episode_{episode_index:06d}.mp4";
+/// Not part of LeRobot's format, which ignores the key.
+const AUDIO_PATH: &str = "audio/chunk-{episode_chunk:03d}/episode_{episode_index:06d}.wav";
+
+/// One sampled row; `timestamp` is `frame_index / fps`.
+pub struct Row {
+    pub state: Vec<f32>,
+    pub action: Vec<f32>,
+}
+
+/// A camera's encoded episode video.
+pub struct Video {
+    /// `observation.images.<camera>`.
+    pub key: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Everything captured for one episode.
+pub struct Episode {
+    pub index: usize,
+    pub task: String,
+    pub rows: Vec<Row>,
+    pub videos: Vec<Video>,
+    /// Whether the episode has an audio track at [`audio_path`].
+    pub audio: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TaskLine {
+    task_index: usize,
+    task: String,
+}
+
+/// `meta/info.json`, or the values a new dataset starts with.
+pub struct Dataset {
+    dir: PathBuf,
+    info: Map<String, Value>,
+}
+
+impl Dataset {
+    pub fn open(dir: PathBuf, fps: u32, robot_type: Option<&str>) -> Result<Self> {
+        let path = dir.join("meta/info.json");
+        let info = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let info: Map<String, Value> = serde_json::from_slice(&bytes)?;
+                let existing = info.get("fps").and_then(Value::as_u64);
+                if existing != Some(u64::from(fps)) {
+                    return Err(Error::Invalid(format!(
+                        "dataset {} is recorded at {} fps, not {fps}",
+                        dir.display(),
+                        existing.map_or("unknown".into(), |fps| fps.to_string())
+                    )));
+                }
+                info
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let info = json!({
+                    "codebase_version": CODEBASE_VERSION,
+                    "robot_type": robot_type,
+                    "total_episodes": 0,
+                    "total_frames": 0,
+                    "total_tasks": 0,
+                    "total_videos": 0,
+                    "total_chunks": 0,
+                    "chunks_size": CHUNK_SIZE,
+                    "fps": fps,
+                    "splits": {},
+                    "data_path": DATA_PATH,
+                    "video_path": VIDEO_PATH,
+                    "features": {},
+                });
+                let Value::Object(info) = info else {
+                    unreachable!()
+                };
+                info
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { dir, info })
+    }
+
+    fn total(&self, key: &str) -> usize {
+        self.info.get(key).and_then(Value::as_u64).unwrap_or(0) as usize
+    }
+
+    fn fps(&self) -> u32 {
+        self.info.get("fps").and_then(Value::as_u64).unwrap_or(1) as u32
+    }
+
+    pub fn next_episode(&self) -> usize {
+        self.total("total_episodes")
+    }
+
+    pub fn video_path(&self, episode: usize, key: &str) -> PathBuf {
+        video_path(&self.dir, episode, key)
+    }
+
+    pub fn audio_path(&self, episode: usize) -> PathBuf {
+        audio_path(&self.dir, episode)
+    }
+
+    /// Writes the episode's table and appends it to the metadata. Nothing is
+    /// written when the episode does not match the dataset's features.
+    pub fn save(&mut self, episode: &Episode, codec: &str) -> Result<()> {
+        let Some(first) = episode.rows.first() else {
+            return Err(Error::Invalid("episode has no frames".into()));
+        };
+        let features = self.features(first, &episode.videos, codec);
+        match self.info.get("features").and_then(Value::as_object) {
+            Some(existing) if !existing.is_empty() => check_features(existing, &features)?,
+            _ => {
+                self.info
+                    .insert("features".into(), Value::Object(features.clone()));
+            }
+        }
+
+        let task_index = self.task_index(&episode.task)?;
+        let first_index = self.total("total_frames");
+        let path = data_path(&self.dir, episode.index);
+        std::fs::create_dir_all(path.parent().expect("data path has a parent"))?;
+        let batch = self.batch(episode, first_index, task_index, first)?;
+        let stats = stats(&batch);
+        let mut writer = ArrowWriter::try_new(
+            File::create(&path)?,
+            batch.schema(),
+            Some(
+                WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build(),
+            ),
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        let length = episode.rows.len();
+        append_line(
+            &self.dir.join("meta/episodes.jsonl"),
+            &json!({
+                "episode_index": episode.index,
+                "tasks": [episode.task],
+                "length": length,
+            }),
+        )?;
+        append_line(
+            &self.dir.join("meta/episodes_stats.jsonl"),
+            &json!({ "episode_index": episode.index, "stats": stats }),
+        )?;
+
+        let episodes = self.next_episode().max(episode.index + 1);
+        let videos = self.total("total_videos") + episode.videos.len();
+        self.info.insert("total_episodes".into(), episodes.into());
+        self.info
+            .insert("total_frames".into(), (first_index + length).into());
+        self.info.insert("total_videos".into(), videos.into());
+        self.info
+            .insert("total_chunks".into(), episodes.div_ceil(CHUNK_SIZE).into());
+        self.info
+            .insert("splits".into(), json!({ "train": format!("0:{episodes}") }));
+        if episode.audio {
+            self.info.insert("audio_path".into(), AUDIO_PATH.into());
+        }
+        fsutil::write_atomic(
+            &self.dir.join("meta/info.json"),
+            &serde_json::to_vec_pretty(&self.info)?,
+        )?;
+        Ok(())
+    }
+
+    /// Looks the task up in `tasks.jsonl`, appending it when new.
+    fn task_index(&mut self, task: &str) -> Result<usize> {
+        let path = self.dir.join("meta/tasks.jsonl");
+        let mut count = 0;
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let entry: TaskLine = serde_json::from_str(&line)?;
+                    if entry.task == task {
+                        return Ok(entry.task_index);
+                    }
+                    count = count.max(entry.task_index + 1);
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        append_line(
+            &path,
+            &TaskLine {
+                task_index: count,
+                task: task.to_owned(),
+            },
+        )?;
+        self.info.insert("total_tasks".into(), (count + 1).into());
+        Ok(count)
+    }
+
+    fn features(&self, first: &Row, videos: &[Video], codec: &str) -> Map<String, Value> {
+        let mut features = Map::new();
+        features.insert(
+            "observation.state".into(),
+            json!({ "dtype": "float32", "shape": [first.state.len()], "names": null }),
+        );
+        features.insert(
+            "action".into(),
+            json!({ "dtype": "float32", "shape": [first.action.len()], "names": null }),
+        );
+        for video in videos {
+            features.insert(
+                video.key.clone(),
+                json!({
+                    "dtype": "video",
+                    "shape": [video.height, video.width, 3],
+                    "names": ["height", "width", "channels"],
+                    "info": {
+                        "video.height": video.height,
+                        "video.width": video.width,
+                        "video.codec": codec_name(codec),
+                        "video.pix_fmt": "yuv420p",
+                        "video.is_depth_map": false,
+                        "video.fps": self.fps(),
+                        "video.channels": 3,
+                        "has_audio": false,
+                    },
+                }),
+            );
+        }
+        for (name, dtype) in [
+            ("timestamp", "float32"),
+            ("frame_index", "int64"),
+            ("episode_index", "int64"),
+            ("index", "int64"),
+            ("task_index", "int64"),
+        ] {
+            features.insert(
+                name.into(),
+                json!({ "dtype": dtype, "shape": [1], "names": null }),
+            );
+        }
+        features
+    }
+
+    fn batch(
+        &self,
+        episode: &Episode,
+        first_index: usize,
+        task_index: usize,
+        first: &Row,
+    ) -> Result<RecordBatch> {
+        let len = episode.rows.len();
+        let fps = self.fps() as f32;
+        let state = vector_column(
+            episode.rows.iter().map(|row| &row.state[..]),
+            first.state.len(),
+        )?;
+        let action = vector_column(
+            episode.rows.iter().map(|row| &row.action[..]),
+            first.action.len(),
+        )?;
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("observation.state", Arc::new(state)),
+            ("action", Arc::new(action)),
+            (
+                "timestamp",
+                Arc::new(Float32Array::from_iter_values(
+                    (0..len).map(|frame| frame as f32 / fps),
+                )),
+            ),
+            (
+                "frame_index",
+                Arc::new(Int64Array::from_iter_values(0..len as i64)),
+            ),
+            (
+                "episode_index",
+                Arc::new(Int64Array::from_value(episode.index as i64, len)),
+            ),
+            (
+                "index",
+                Arc::new(Int64Array::from_iter_values(
+                    (0..len).map(|frame| (first_index + frame) as i64),
+                )),
+            ),
+            (
+                "task_index",
+                Arc::new(Int64Array::from_value(task_index as i64, len)),
+            ),
+        ];
+        let schema = Schema::new(
+            columns
+                .iter()
+                .map(|(name, column)| Field::new(*name, column.data_type().clone(), false))
+                .collect::<Vec<_>>(),
+        );
+        let columns = columns.into_iter().map(|(_, column)| column).collect();
+        Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+    }
+}
+
+pub fn data_path(dir: &Path, episode: usize) -> PathBuf {
+    dir.join(format!(
+        "data/chunk-{:03}/episode_{episode:06}.parquet",
+        episode / CHUNK_SIZE
+    ))
+}
+
+pub fn video_path(dir: &Path, episode: usize, key: &str) -> PathBuf {
+    dir.join(format!(
+        "videos/chunk-{:03}/{key}/episode_{episode:06}.mp4",
+        episode / CHUNK_SIZE
+    ))
+}
+
+pub fn audio_path(dir: &Path, episode: usize) -> PathBuf {
+    dir.join(format!(
+        "audio/chunk-{:03}/episode_{episode:06}.wav",
+        episode / CHUNK_SIZE
+    ))
+}
+
+/// Packs equally long vectors into a `fixed_size_list<float32>` column.
+fn vector_column<'a>(
+    rows: impl Iterator<Item = &'a [f32]>,
+    width: usize,
+) -> Result<FixedSizeListArray> {
+    let mut values = Vec::new();
+    for row in rows {
+        if row.len() != width {
+            return Err(Error::Invalid(format!(
+                "vector length changed from {width} to {} during the episode",
+                row.len()
+            )));
+        }
+        values.extend_from_slice(row);
+    }
+    let item = Arc::new(Field::new("item", DataType::Float32, false));
+    Ok(FixedSizeListArray::try_new(
+        item,
+        width as i32,
+        Arc::new(Float32Array::from(values)),
+        None,
+    )?)
+}
+
+/// Per-dimension min/max/mean/std/count of every numeric column.
+fn stats(batch: &RecordBatch) -> Map<String, Value> {
+    let mut stats = Map::new();
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        let (width, values): (usize, Vec<f64>) =
+            if let Some(list) = column.as_any().downcast_ref::<FixedSizeListArray>() {
+                let values = list.values().as_any().downcast_ref::<Float32Array>();
+                let values = values.map(|values| values.values().iter().map(|&v| f64::from(v)));
+                (
+                    list.value_length() as usize,
+                    values.into_iter().flatten().collect(),
+                )
+            } else if let Some(floats) = column.as_any().downcast_ref::<Float32Array>() {
+                (1, floats.values().iter().map(|&v| f64::from(v)).collect())
+            } else if let Some(ints) = column.as_any().downcast_ref::<Int64Array>() {
+                (1, ints.values().iter().map(|&v| v as f64).collect())
+            } else {
+                continue;
+            };
+        if width == 0 {
+            continue;
+        }
+        let count = values.len() / width;
+        let mut min = vec![f64::INFINITY; width];
+        let mut max = vec![f64::NEG_INFINITY; width];
+        let mut sum = vec![0.0; width];
+        let mut sum_sq = vec![0.0; width];
+        for row in values.chunks_exact(width) {
+            for (dim, &value) in row.iter().enumerate() {
+                min[dim] = min[dim].min(value);
+                max[dim] = max[dim].max(value);
+                sum[dim] += value;
+                sum_sq[dim] += value * value;
+            }
+        }
+        let n = count.max(1) as f64;
+        let mean: Vec<f64> = sum.iter().map(|sum| sum / n).collect();
+        let std: Vec<f64> = sum_sq
+            .iter()
+            .zip(&mean)
+            .map(|(sum_sq, mean)| (sum_sq / n - mean * mean).max(0.0).sqrt())
+            .collect();
+        stats.insert(
+            field.name().clone(),
+            json!({ "min": min, "max": max, "mean": mean, "std": std, "count": [count] }),
+        );
+    }
+    stats
+}
+
+fn check_features(existing: &Map<String, Value>, features: &Map<String, Value>) -> Result<()> {
+    for (name, feature) in features {
+        let shape = |feature: &Value| feature.get("shape").cloned();
+        if existing.get(name).map(shape) != Some(shape(feature)) {
+            return Err(Error::Invalid(format!(
+                "episode feature {name} does not match the dataset"
+            )));
+        }
+    }
+    if existing.len() != features.len() {
+        return Err(Error::Invalid(
+            "episode cameras do not match the dataset".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Short codec name LeRobot records for an ffmpeg encoder.
+fn codec_name(encoder: &str) -> &str {
+    match encoder {
+        "libsvtav1" | "libaom-av1" | "librav1e" => "av1",
+        "libx264" => "h264",
+        "libx265" => "hevc",
+        // Hardware encoders are named `<codec>_<api>`, e.g. `hevc_nvenc`.
+        other => other.split_once('_').map_or(other, |(codec, _)| codec),
+    }
+}
+
+fn append_line(path: &Path, value: &impl Serialize) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)?;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+//! Append-only journal of the episode being recorded, so a crash of the app
+//! or the PC loses at most the last checkpoint interval of it.
+//!
+//! Each episode gets `<dataset>/.journal/<started>-<episode>/`, which holds
+//! everything until the episode is saved:
+//!
+//! - `meta.json`: dataset, task, cameras and encoder, written at start
+//! - `rows.bin`: header `PRCSJRNL` + version `u32`, then per row a payload
+//!   length `u32` and CRC-32 `u32` followed by the payload: state and action
+//!   as a `u32` count and `f32` values each, all little-endian
+//! - `index.json`: rows and bytes of `rows.bin` known to be on disk, and the
+//!   camera frame sizes, rewritten every [`CHECKPOINT_INTERVAL`] after
+//!   syncing the rows
+//! - `<camera>.mp4`: the videos, as fragmented MP4 so that a truncated file
+//!   still plays up to its last fragment
+//!
+//! [`scan`] finds the journals left behind. [`read_rows`] trusts the rows up
+//! to the checkpoint and reads on until the first torn or corrupt record.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::dataset::Row;
+use crate::error::{Error, Result};
+use crate::fsutil;
+
+const JOURNAL_DIR: &str = ".journal";
+const META_FILE: &str = "meta.json";
+const ROWS_FILE: &str = "rows.bin";
+const INDEX_FILE: &str = "index.json";
+const MAGIC: &[u8; 8] = b"PRCSJRNL";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 12;
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalCamera {
+    pub stream: String,
+    /// `observation.images.<stream>`.
+    pub key: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub dataset: String,
+    pub task: String,
+    pub episode_index: usize,
+    pub fps: u32,
+    pub codec: String,
+    pub cameras: Vec<JournalCamera>,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Checkpoint {
+    pub rows: usize,
+    pub offset: u64,
+    /// Frame size per camera key, once known.
+    pub sizes: Vec<(String, u32, u32)>,
+}
+
+/// The journal being written.
+pub struct Journal {
+    dir: PathBuf,
+    rows: BufWriter<File>,
+    checkpoint: Checkpoint,
+    last_checkpoint: Instant,
+}
+
+impl Journal {
+    pub fn create(dataset_dir: &Path, meta: &Meta) -> Result<Self> {
+        let name = format!(
+            "{}-{:06}",
+            meta.started_at.format("%Y%m%dT%H%M%S%3f"),
+            meta.episode_index
+        );
+        let dir = dataset_dir.join(JOURNAL_DIR).join(name);
+        std::fs::create_dir_all(&dir)?;
+        fsutil::write_atomic(&dir.join(META_FILE), &serde_json::to_vec_pretty(meta)?)?;
+        let mut rows = BufWriter::new(File::create(dir.join(ROWS_FILE))?);
+        rows.write_all(MAGIC)?;
+        rows.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            dir,
+            rows,
+            checkpoint: Checkpoint {
+                offset: HEADER_LEN,
+                ..Checkpoint::default()
+            },
+            last_checkpoint: Instant::now(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the video of camera `key` is encoded to.
+    pub fn video_path(&self, key: &str) -> PathBuf {
+        video_path(&self.dir, key)
+    }
+
+    pub fn append(&mut self, row: &Row) -> Result<()> {
+        let mut payload = Vec::with_capacity(8 + 4 * (row.state.len() + row.action.len()));
+        for vector in [&row.state, &row.action] {
+            payload.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+            for value in vector {
+                payload.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        self.rows.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.rows
+            .write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        self.rows.write_all(&payload)?;
+        self.checkpoint.rows += 1;
+        self.checkpoint.offset += 8 + payload.len() as u64;
+        Ok(())
+    }
+
+    /// Records the camera frame size used for the episode's metadata.
+    pub fn set_size(&mut self, key: &str, width: u32, height: u32) {
+        self.checkpoint
+            .sizes
+            .retain(|(existing, _, _)| existing != key);
+        self.checkpoint.sizes.push((key.to_owned(), width, height));
+    }
+
+    /// Syncs the rows and writes `index.json` when the interval has passed.
+    pub fn checkpoint_if_due(&mut self) -> Result<()> {
+        if self.last_checkpoint.elapsed() < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+        self.last_checkpoint = Instant::now();
+        self.rows.flush()?;
+        self.rows.get_ref().sync_data()?;
+        fsutil::write_atomic(
+            &self.dir.join(INDEX_FILE),
+            &serde_json::to_vec(&self.checkpoint)?,
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the journal and whatever is left in it.
+    pub fn remove(self) {
+        drop(self.rows);
+        if let Err(err) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!(dir = %self.dir.display(), "journal not removed: {err}");
+        }
+        remove_empty_parent(&self.dir);
+    }
+}
+
+pub fn video_path(journal: &Path, key: &str) -> PathBuf {
+    journal.join(format!("{key}.mp4"))
+}
+
+/// Journal directories left in the datasets under `root`, with their meta.
+pub fn scan(root: &Path) -> Vec<(PathBuf, Meta)> {
+    let mut found = Vec::new();
+    // Datasets are `name` or `owner/name`.
+    let mut dataset_dirs = Vec::new();
+    for entry in read_dirs(root) {
+        dataset_dirs.extend(read_dirs(&entry));
+        dataset_dirs.push(entry);
+    }
+    for dataset in dataset_dirs {
+        for journal in read_dirs(&dataset.join(JOURNAL_DIR)) {
+            let meta = std::fs::read(journal.join(META_FILE))
+                .map_err(Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<Meta>(&bytes)?));
+            match meta {
+                Ok(meta) => found.push((journal, meta)),
+                Err(err) => {
+                    tracing::warn!(dir = %journal.display(), "unreadable episode journal: {err}")
+                }
+            }
+        }
+    }
+    found
+}
+
+/// The rows of the journal at `dir` up to the first damaged record, and the
+/// last checkpoint.
+pub fn read_rows(dir: &Path) -> Result<(Vec<Row>, Checkpoint)> {
+    let checkpoint: Checkpoint = std::fs::read(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    let mut data = Vec::new();
+    File::open(dir.join(ROWS_FILE))?.read_to_end(&mut data)?;
+    if data.len() < HEADER_LEN as usize || &data[..8] != MAGIC {
+        return Err(Error::Invalid(format!(
+            "not an episode journal: {}",
+            dir.display()
+        )));
+    }
+    let mut rows = Vec::new();
+    let mut at = HEADER_LEN as usize;
+    while let Some(header) = data.get(at..at + 8) {
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let Some(payload) = data.get(at + 8..at + 8 + len) else {
+            break;
+        };
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        let Some(row) = parse_row(payload) else {
+            break;
+        };
+        rows.push(row);
+        at += 8 + len;
+    }
+    if rows.len() < checkpoint.rows {
+        tracing::warn!(
+            dir = %dir.display(),
+            rows = rows.len(),
+            checkpoint = checkpoint.rows,
+            "journal is damaged before its checkpoint"
+        );
+    }
+    Ok((rows, checkpoint))
+}
+
+fn parse_row(payload: &[u8]) -> Option<Row> {
+    let mut at = 0;
+    let mut vector = || -> Option<Vec<f32>> {
+        let count = u32::from_le_bytes(payload.get(at..at + 4)?.try_into().ok()?) as usize;
+        at += 4;
+        let values = payload
+            .get(at..at + 4 * count)?
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        at += 4 * count;
+        Some(values)
+    };
+    let state = vector()?;
+    let action = vector()?;
+    Some(Row { state, action })
+}
+
+/// Deletes the journal at `dir`.
+pub fn remove_dir(dir: &Path) -> Result<()> {
+    std::fs::remove_dir_all(dir)?;
+    remove_empty_parent(dir);
+    Ok(())
+}
+
+/// Drops `.journal` once its last episode is gone.
+fn remove_empty_parent(dir: &Path) {
+    if let Some(parent) = dir.parent() {
+        let _ = std::fs::remove_dir(parent);
+    }
+}
+
+fn read_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .collect()
+}
@@ -0,0 +1,963 @@
+//! Episode recording into LeRobot datasets, independent of the backend's
+//! Python process.
+//!
+//! [`start_recording`] samples the robot state topic (a backend WebSocket
+//! delivering `{"observation.state": [...], "action": [...]}`) and the
+//! latest frame of every requested camera ring at the `recording.fps` rate.
+//! Rows are kept in memory and each camera's JPEG frames are piped into an
+//! `ffmpeg` encoder, so a saturated backend only delays the state messages,
+//! never the capture itself. A camera that has no new frame repeats the
+//! previous one to keep the videos in step with the table.
+//!
+//! With `audio.enabled`, the microphone is recorded alongside as well (see
+//! [`crate::audio`]); [`set_audio_muted`] silences it for the episode.
+//!
+//! [`stop_recording`] finishes the encoders and appends the episode to the
+//! dataset (see [`dataset`]); [`discard_episode`] drops it instead. Both
+//! work after the capture was interrupted, e.g. when the state topic went
+//! silent. Progress is reported as `episode-event`, which the MQTT bridge
+//! forwards to the factory broker.
+//!
+//! Until it is saved, an episode lives in a journal next to the dataset
+//! (see [`journal`]). Journals left by a crash are found at startup, cut to
+//! the prefix that their rows and every video agree on, and offered with
+//! [`list_recovered_episodes`] (also emitted as `recovered-episodes`);
+//! [`recover_episode`] appends one to its dataset and
+//! [`discard_recovered_episode`] deletes it.
+//!
+//! A screen capture of the app window started for the episode (see
+//! [`crate::screen_capture`]) ends with it, as does the full-rate file of
+//! the weld signals streamed meanwhile (see [`crate::weld_signals`]).
+
+mod dataset;
+mod journal;
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message;
+
+pub use self::dataset::CHUNK_SIZE;
+use self::dataset::{Dataset, Episode, Row, Video};
+use self::journal::{Journal, JournalCamera, Meta};
+use crate::audio::{Timeline, Track};
+use crate::backend_tls;
+use crate::disk_guard;
+use crate::encoding::{self, Codec};
+use crate::error::{Error, Result};
+use crate::frames::{self, FrameRings};
+use crate::metrics;
+use crate::notifications::{self, Action, Category, Notice};
+use crate::screen_capture;
+use crate::settings::{RecordingSettings, SettingsStore};
+use crate::time_sync;
+use crate::weld_signals;
+
+/// The capture stops when the state topic is silent for this long.
+const STATE_TIMEOUT: Duration = Duration::from_secs(2);
+const RECONNECT_DELAY: Duration = Duration::from_millis(250);
+/// Lets a truncated video play up to its last fragment.
+pub const FRAGMENTED_MP4: [&str; 2] = ["-movflags", "+frag_keyframe+empty_moov+default_base_moof"];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingRequest {
+    /// Directory under the recording root, e.g. `cell1/weld_pick`.
+    pub dataset: String,
+    /// Language instruction stored with the episode.
+    pub task: String,
+    /// Frame ring streams; recorded as `observation.images.<stream>`.
+    pub cameras: Vec<String>,
+    /// Starts the episode with its audio track muted.
+    #[serde(default)]
+    pub mute_audio: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodeInfo {
+    pub dataset: String,
+    pub episode_index: usize,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EpisodeEvent<'a> {
+    /// `started`, `interrupted`, `saved`, `discarded` or `recovered`.
+    event: &'a str,
+    dataset: &'a str,
+    episode_index: usize,
+    frames: usize,
+    error: Option<String>,
+}
+
+/// Latest message of the state topic and when it arrived.
+type LatestState = Arc<StdMutex<Option<(Instant, Row)>>>;
+
+struct Encoder {
+    video: Video,
+    stream: String,
+    path: PathBuf,
+    child: Child,
+    stdin: Option<ChildStdin>,
+    last: Option<(u64, Vec<u8>)>,
+}
+
+/// The episode captured so far, handed to the capture and back once it is
+/// finished or interrupted.
+struct Capture {
+    rows: Vec<Row>,
+    encoders: Vec<Encoder>,
+    journal: Journal,
+}
+
+struct Active {
+    info: EpisodeInfo,
+    task: String,
+    dataset: Dataset,
+    codec: String,
+    audio: Option<Track>,
+    timeline: Arc<Timeline>,
+    stop: Option<oneshot::Sender<()>>,
+    capture: JoinHandle<Capture>,
+    state: JoinHandle<()>,
+}
+
+/// The episode being recorded, if any.
+#[derive(Default)]
+pub struct Recorder(StdMutex<Option<Active>>);
+
+/// An incomplete episode found at startup.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveredEpisode {
+    pub id: String,
+    pub dataset: String,
+    pub task: String,
+    /// Index the episode was recorded as; it is appended as the dataset's
+    /// next episode.
+    pub episode_index: usize,
+    /// Frames that can be salvaged.
+    pub frames: usize,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub cameras: Vec<String>,
+}
+
+struct Recovered {
+    episode: RecoveredEpisode,
+    dir: PathBuf,
+    meta: Meta,
+}
+
+/// Journals left by a crash, until recovered or discarded.
+#[derive(Default)]
+pub struct RecoveredEpisodes(StdMutex<Vec<Recovered>>);
+
+impl Recorder {
+    /// Name of the dataset an episode is being recorded into.
+    pub fn recording_dataset(&self) -> Option<String> {
+        let active = self.0.lock().unwrap();
+        active.as_ref().map(|active| active.info.dataset.clone())
+    }
+
+    /// The episode being recorded.
+    pub fn recording_episode(&self) -> Option<EpisodeInfo> {
+        let active = self.0.lock().unwrap();
+        active.as_ref().map(|active| active.info.clone())
+    }
+
+    /// [`time_sync::now_ns`] at the episode's first row, once it has one.
+    pub fn episode_start_ns(&self) -> Option<i64> {
+        let active = self.0.lock().unwrap();
+        let start = active.as_ref()?.timeline.start_ns.load(Ordering::Relaxed);
+        (start != 0).then_some(start)
+    }
+}
+
+#[tauri::command]
+pub async fn start_recording(
+    app: AppHandle,
+    recorder: State<'_, Recorder>,
+    request: RecordingRequest,
+) -> Result<EpisodeInfo> {
+    // Before the busy check, so no await separates it from taking the slot.
+    let encoder = video_encoder(&app).await;
+    if recorder.0.lock().unwrap().is_some() {
+        return Err(Error::DeviceBusy("an episode is already recording".into()));
+    }
+    if let Some(camera) = request.cameras.iter().find(|c| !frames::valid_stream(c)) {
+        return Err(Error::Invalid(format!("camera stream {camera}")));
+    }
+    let dir = dataset_dir(&app, &request.dataset)?;
+    disk_guard::check_start(&app)?;
+    let settings = app.state::<SettingsStore>().get().recording;
+    let fps = settings.fps.max(1);
+    let dataset = Dataset::open(dir.clone(), fps, settings.robot_type.as_deref())?;
+    let episode_index = dataset.next_episode();
+    let cameras: Vec<JournalCamera> = request
+        .cameras
+        .iter()
+        .map(|camera| JournalCamera {
+            stream: camera.clone(),
+            key: format!("observation.images.{camera}"),
+        })
+        .collect();
+    let journal = Journal::create(
+        &dir,
+        &Meta {
+            dataset: request.dataset.clone(),
+            task: request.task.clone(),
+            episode_index,
+            fps,
+            codec: encoder.clone(),
+            cameras: cameras.clone(),
+            started_at: chrono::Utc::now(),
+        },
+    )?;
+
+    let mut encoders = Vec::new();
+    for camera in cameras {
+        let path = journal.video_path(&camera.key);
+        match spawn_encoder(&app, &settings, &encoder, &camera.stream, camera.key, path) {
+            Ok(spawned) => encoders.push(spawned),
+            Err(err) => {
+                journal.remove();
+                return Err(err);
+            }
+        }
+    }
+
+    let timeline = Arc::new(Timeline::default());
+    let audio_settings = app.state::<SettingsStore>().get().audio;
+    let audio = if audio_settings.enabled {
+        let path = journal.dir().join("audio.wav");
+        let track = Track::start(
+            &app,
+            &audio_settings,
+            path,
+            timeline.clone(),
+            request.mute_audio,
+        );
+        match track {
+            Ok(track) => Some(track),
+            Err(err) => {
+                journal.remove();
+                return Err(err);
+            }
+        }
+    } else {
+        None
+    };
+
+    let latest = LatestState::default();
+    let state = tauri::async_runtime::spawn(follow_state(
+        app.clone(),
+        settings.state_topic.clone(),
+        latest.clone(),
+    ));
+    let info = EpisodeInfo {
+        dataset: request.dataset,
+        episode_index,
+        path: dir,
+    };
+    let (stop, stopped) = oneshot::channel();
+    let capture = tauri::async_runtime::spawn(capture(
+        app.clone(),
+        info.clone(),
+        fps,
+        latest,
+        Capture {
+            rows: Vec::new(),
+            encoders,
+            journal,
+        },
+        timeline.clone(),
+        stopped,
+    ));
+    emit_event(&app, "started", &info, 0, None);
+    *recorder.0.lock().unwrap() = Some(Active {
+        info: info.clone(),
+        task: request.task,
+        dataset,
+        codec: encoder,
+        audio,
+        timeline,
+        stop: Some(stop),
+        capture,
+        state,
+    });
+    tracing::info!(dataset = %info.dataset, episode = episode_index, "recording started");
+    Ok(info)
+}
+
+/// Finishes the episode and appends it to the dataset.
+#[tauri::command]
+pub async fn stop_recording(app: AppHandle, recorder: State<'_, Recorder>) -> Result<EpisodeInfo> {
+    screen_capture::end(&app, false).await;
+    let mut active = take_active(&recorder)?;
+    weld_signals::end(&app, false).await;
+    let Capture {
+        rows,
+        encoders,
+        journal,
+    } = finish_capture(&mut active).await?;
+    let index = active.info.episode_index;
+    let mut paths = Vec::new();
+    let mut videos = Vec::new();
+    for mut encoder in encoders {
+        drop(encoder.stdin.take());
+        let status = encoder.child.wait().await?;
+        if !status.success() {
+            remove_files(&paths);
+            journal.remove();
+            return Err(Error::Stream(format!(
+                "ffmpeg exited with {status} encoding {}",
+                encoder.stream
+            )));
+        }
+        let path = active.dataset.video_path(index, &encoder.video.key);
+        if let Err(err) = move_file(&encoder.path, &path) {
+            remove_files(&paths);
+            journal.remove();
+            return Err(err);
+        }
+        paths.push(path);
+        videos.push(encoder.video);
+    }
+    let mut audio = false;
+    if let Some(track) = active.audio.take() {
+        let recorded = track.path().to_owned();
+        let path = active.dataset.audio_path(index);
+        let moved = match track.finish().await {
+            Ok(true) => move_file(&recorded, &path).map(|()| true),
+            Ok(false) => Ok(false),
+            Err(err) => Err(err),
+        };
+        match moved {
+            Ok(moved) => {
+                audio = moved;
+                if moved {
+                    paths.push(path);
+                }
+            }
+            // The notes are not worth losing the demonstration for.
+            Err(err) => tracing::warn!("episode saved without audio: {err}"),
+        }
+    }
+
+    let episode = Episode {
+        index,
+        task: active.task,
+        rows,
+        videos,
+        audio,
+    };
+    let frames = episode.rows.len();
+    let mut dataset = active.dataset;
+    let codec = active.codec;
+    let saved = tauri::async_runtime::spawn_blocking(move || dataset.save(&episode, &codec))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    if let Err(err) = saved {
+        remove_files(&paths);
+        journal.remove();
+        return Err(err);
+    }
+    journal.remove();
+    emit_event(&app, "saved", &active.info, frames, None);
+    tracing::info!(dataset = %active.info.dataset, frames, "episode saved");
+    Ok(active.info)
+}
+
+/// Drops the episode being recorded without touching the dataset.
+#[tauri::command]
+pub async fn discard_episode(app: AppHandle, recorder: State<'_, Recorder>) -> Result<()> {
+    screen_capture::end(&app, true).await;
+    let mut active = take_active(&recorder)?;
+    weld_signals::end(&app, true).await;
+    let capture = finish_capture(&mut active).await?;
+    let frames = capture.rows.len();
+    for mut encoder in capture.encoders {
+        let _ = encoder.child.kill().await;
+    }
+    if let Some(track) = active.audio.take() {
+        let _ = track.finish().await;
+    }
+    capture.journal.remove();
+    emit_event(&app, "discarded", &active.info, frames, None);
+    Ok(())
+}
+
+/// Mutes or unmutes the audio track of the episode being recorded.
+#[tauri::command]
+pub fn set_audio_muted(recorder: State<'_, Recorder>, muted: bool) -> Result<()> {
+    let active = recorder.0.lock().unwrap();
+    let active = active
+        .as_ref()
+        .ok_or_else(|| Error::Invalid("no episode is recording".into()))?;
+    let track = active
+        .audio
+        .as_ref()
+        .ok_or_else(|| Error::Invalid("the episode has no audio track".into()))?;
+    track.set_muted(muted);
+    Ok(())
+}
+
+/// Looks for episodes a crash left unfinished.
+pub fn init(app: &AppHandle) {
+    app.manage(RecoveredEpisodes::default());
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let root = match datasets_root(&app) {
+            Ok(root) => root,
+            Err(err) => return tracing::warn!("episode recovery skipped: {err}"),
+        };
+        let journals = tauri::async_runtime::spawn_blocking(move || journal::scan(&root))
+            .await
+            .unwrap_or_default();
+        let mut found = Vec::new();
+        for (dir, meta) in journals {
+            match salvage(&app, &dir, &meta).await {
+                Ok(episode) => found.push(Recovered { episode, dir, meta }),
+                Err(err) => {
+                    tracing::warn!(dir = %dir.display(), "episode journal not recoverable: {err}")
+                }
+            }
+        }
+        if found.is_empty() {
+            return;
+        }
+        tracing::info!(count = found.len(), "found incomplete episodes");
+        let episodes: Vec<RecoveredEpisode> =
+            found.iter().map(|found| found.episode.clone()).collect();
+        app.state::<RecoveredEpisodes>()
+            .0
+            .lock()
+            .unwrap()
+            .extend(found);
+        let _ = app.emit("recovered-episodes", episodes);
+    });
+}
+
+/// Episodes left unfinished by a crash.
+#[tauri::command]
+pub fn list_recovered_episodes(recovered: State<'_, RecoveredEpisodes>) -> Vec<RecoveredEpisode> {
+    let recovered = recovered.0.lock().unwrap();
+    recovered
+        .iter()
+        .map(|found| found.episode.clone())
+        .collect()
+}
+
+/// Appends the salvageable part of a recovered episode to its dataset.
+#[tauri::command]
+pub async fn recover_episode(
+    app: AppHandle,
+    recorder: State<'_, Recorder>,
+    recovered: State<'_, RecoveredEpisodes>,
+    id: String,
+) -> Result<EpisodeInfo> {
+    if recorder.0.lock().unwrap().is_some() {
+        return Err(Error::DeviceBusy("an episode is recording".into()));
+    }
+    let (dir, meta, frames) = {
+        let recovered = recovered.0.lock().unwrap();
+        let found = recovered
+            .iter()
+            .find(|found| found.episode.id == id)
+            .ok_or_else(|| Error::NotFound(format!("recovered episode {id}")))?;
+        (found.dir.clone(), found.meta.clone(), found.episode.frames)
+    };
+    if frames == 0 {
+        return Err(Error::Invalid(format!(
+            "episode {id} has no frames to recover"
+        )));
+    }
+    let dataset_dir = dataset_dir(&app, &meta.dataset)?;
+    let robot_type = app.state::<SettingsStore>().get().recording.robot_type;
+    let mut dataset = Dataset::open(dataset_dir.clone(), meta.fps, robot_type.as_deref())?;
+    let index = dataset.next_episode();
+    let (mut rows, checkpoint) = journal::read_rows(&dir)?;
+    rows.truncate(frames);
+
+    let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+    let mut paths = Vec::new();
+    let mut videos = Vec::new();
+    for camera in &meta.cameras {
+        let path = dataset.video_path(index, &camera.key);
+        std::fs::create_dir_all(path.parent().expect("video path has a parent"))?;
+        let status = Command::new(&ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+            .arg(journal::video_path(&dir, &camera.key))
+            .args([
+                "-map",
+                "0:v:0",
+                "-c",
+                "copy",
+                "-frames:v",
+                &frames.to_string(),
+            ])
+            .arg(&path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status()
+            .await?;
+        paths.push(path);
+        if !status.success() {
+            remove_files(&paths);
+            return Err(Error::Stream(format!(
+                "ffmpeg exited with {status} recovering {}",
+                camera.stream
+            )));
+        }
+        let (width, height) = checkpoint
+            .sizes
+            .iter()
+            .find(|(key, _, _)| *key == camera.key)
+            .map_or((0, 0), |(_, width, height)| (*width, *height));
+        videos.push(Video {
+            key: camera.key.clone(),
+            width,
+            height,
+        });
+    }
+
+    let episode = Episode {
+        index,
+        task: meta.task.clone(),
+        rows,
+        videos,
+        // The track is only finalized on stop, so there is none to salvage.
+        audio: false,
+    };
+    let codec = meta.codec.clone();
+    let saved = tauri::async_runtime::spawn_blocking(move || dataset.save(&episode, &codec))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    if let Err(err) = saved {
+        remove_files(&paths);
+        return Err(err);
+    }
+    forget_recovered(&recovered, &id, &dir);
+    let info = EpisodeInfo {
+        dataset: meta.dataset,
+        episode_index: index,
+        path: dataset_dir,
+    };
+    emit_event(&app, "recovered", &info, frames, None);
+    tracing::info!(dataset = %info.dataset, frames, "episode recovered");
+    Ok(info)
+}
+
+/// Deletes a recovered episode for good.
+#[tauri::command]
+pub fn discard_recovered_episode(
+    recovered: State<'_, RecoveredEpisodes>,
+    id: String,
+) -> Result<()> {
+    let dir = {
+        let recovered = recovered.0.lock().unwrap();
+        recovered
+            .iter()
+            .find(|found| found.episode.id == id)
+            .map(|found| found.dir.clone())
+            .ok_or_else(|| Error::NotFound(format!("recovered episode {id}")))?
+    };
+    forget_recovered(&recovered, &id, &dir);
+    Ok(())
+}
+
+fn forget_recovered(recovered: &RecoveredEpisodes, id: &str, dir: &Path) {
+    recovered
+        .0
+        .lock()
+        .unwrap()
+        .retain(|found| found.episode.id != id);
+    if let Err(err) = journal::remove_dir(dir) {
+        tracing::warn!(dir = %dir.display(), "episode journal not removed: {err}");
+    }
+}
+
+/// What of the journal at `dir` can be saved: the rows that every camera
+/// has a frame for.
+async fn salvage(app: &AppHandle, dir: &Path, meta: &Meta) -> Result<RecoveredEpisode> {
+    let rows_dir = dir.to_owned();
+    let (rows, _) = tauri::async_runtime::spawn_blocking(move || journal::read_rows(&rows_dir))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))??;
+    let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+    let mut frames = rows.len();
+    for camera in &meta.cameras {
+        let video = journal::video_path(dir, &camera.key);
+        frames = frames.min(count_frames(&ffmpeg, &video).await);
+    }
+    Ok(RecoveredEpisode {
+        id: dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        dataset: meta.dataset.clone(),
+        task: meta.task.clone(),
+        episode_index: meta.episode_index,
+        frames,
+        started_at: meta.started_at,
+        cameras: meta
+            .cameras
+            .iter()
+            .map(|camera| camera.stream.clone())
+            .collect(),
+    })
+}
+
+/// Frames of the first video stream of `path` that decode, 0 when none do.
+async fn count_frames(ffmpeg: &str, path: &Path) -> usize {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args(["-map", "0:v:0", "-c", "copy", "-f", "null", "-"])
+        .args(["-progress", "pipe:1", "-nostats"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await;
+    let Ok(output) = output else {
+        return 0;
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("frame="))
+        .filter_map(|frames| frames.trim().parse().ok())
+        .next_back()
+        .unwrap_or(0)
+}
+
+/// Directory of `dataset` under the recording root.
+pub fn dataset_dir(app: &AppHandle, dataset: &str) -> Result<PathBuf> {
+    if !valid_dataset(dataset) {
+        return Err(Error::Invalid(format!("dataset name {dataset}")));
+    }
+    Ok(datasets_root(app)?.join(dataset))
+}
+
+/// Directory holding every recorded dataset.
+pub fn datasets_root(app: &AppHandle) -> Result<PathBuf> {
+    Ok(match app.state::<SettingsStore>().get().recording.root {
+        Some(root) => root,
+        None => app.path().app_data_dir()?.join("datasets"),
+    })
+}
+
+/// Table of episode `episode` in the dataset at `dir`.
+pub fn episode_data_path(dir: &Path, episode: usize) -> PathBuf {
+    dataset::data_path(dir, episode)
+}
+
+/// Video of `key` (`observation.images.<camera>`) for episode `episode`.
+pub fn episode_video_path(dir: &Path, episode: usize, key: &str) -> PathBuf {
+    dataset::video_path(dir, episode, key)
+}
+
+fn take_active(recorder: &Recorder) -> Result<Active> {
+    recorder
+        .0
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::Invalid("no episode is recording".into()))
+}
+
+async fn finish_capture(active: &mut Active) -> Result<Capture> {
+    if let Some(stop) = active.stop.take() {
+        let _ = stop.send(());
+    }
+    active.state.abort();
+    (&mut active.capture)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))
+}
+
+/// The encoder `recording.videoCodec` names, picking one for `h264` and
+/// `hevc`.
+async fn video_encoder(app: &AppHandle) -> String {
+    let codec = app.state::<SettingsStore>().get().recording.video_codec;
+    match Codec::parse(&codec) {
+        Some(parsed) => {
+            let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+            encoding::pick(app, &ffmpeg, parsed).await
+        }
+        None => codec,
+    }
+}
+
+fn spawn_encoder(
+    app: &AppHandle,
+    settings: &RecordingSettings,
+    encoder: &str,
+    stream: &str,
+    key: String,
+    path: PathBuf,
+) -> Result<Encoder> {
+    std::fs::create_dir_all(path.parent().expect("video path has a parent"))?;
+    let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+    let fps = settings.fps.max(1).to_string();
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args([
+            "-f",
+            "image2pipe",
+            "-framerate",
+            &fps,
+            "-c:v",
+            "mjpeg",
+            "-i",
+            "-",
+        ])
+        .args(["-c:v", encoder, "-pix_fmt", encoding::pix_fmt(encoder)])
+        .args(["-g", "2"])
+        .args(encoding::quality_args(encoder, settings.crf))
+        .args(FRAGMENTED_MP4)
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdin = child.stdin.take();
+    Ok(Encoder {
+        video: Video {
+            key,
+            width: 0,
+            height: 0,
+        },
+        stream: stream.to_owned(),
+        path,
+        child,
+        stdin,
+        last: None,
+    })
+}
+
+/// Samples state and frames at `fps` until stopped or interrupted.
+async fn capture(
+    app: AppHandle,
+    info: EpisodeInfo,
+    fps: u32,
+    latest: LatestState,
+    started: Capture,
+    timeline: Arc<Timeline>,
+    mut stopped: oneshot::Receiver<()>,
+) -> Capture {
+    let rings = app.state::<FrameRings>();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(fps)));
+    let Capture {
+        mut rows,
+        mut encoders,
+        mut journal,
+    } = started;
+    let error = loop {
+        let due = tokio::select! {
+            _ = &mut stopped => break None,
+            due = ticker.tick() => due,
+        };
+        let state = {
+            let latest = latest.lock().unwrap();
+            match latest.as_ref() {
+                // Recording starts with the first state message.
+                None => continue,
+                Some((at, _)) if at.elapsed() > STATE_TIMEOUT => {
+                    break Some("robot state stopped arriving".to_owned())
+                }
+                Some((_, row)) => Row {
+                    state: row.state.clone(),
+                    action: row.action.clone(),
+                },
+            }
+        };
+        for encoder in &mut encoders {
+            let Ok(Some(frame)) = rings.latest_frame(&encoder.stream) else {
+                continue;
+            };
+            if encoder
+                .last
+                .as_ref()
+                .is_some_and(|(seq, _)| *seq == frame.seq)
+            {
+                continue;
+            }
+            if !matches!(&frame.fourcc, b"MJPG" | b"JPEG") {
+                continue;
+            }
+            if encoder.last.is_none() {
+                let (width, height) = match (frame.width, frame.height) {
+                    (0, _) | (_, 0) => jpeg_size(&frame.data).unwrap_or((0, 0)),
+                    size => size,
+                };
+                encoder.video.width = width;
+                encoder.video.height = height;
+                journal.set_size(&encoder.video.key, width, height);
+            }
+            encoder.last = Some((frame.seq, frame.data));
+        }
+        // Every camera needs a first frame before the first row.
+        if rows.is_empty() && encoders.iter().any(|encoder| encoder.last.is_none()) {
+            continue;
+        }
+        let mut failed = None;
+        for encoder in &mut encoders {
+            let (Some(stdin), Some((_, jpeg))) = (encoder.stdin.as_mut(), &encoder.last) else {
+                continue;
+            };
+            if let Err(err) = stdin.write_all(jpeg).await {
+                failed = Some(format!("encoder for {} failed: {err}", encoder.stream));
+                break;
+            }
+        }
+        if failed.is_some() {
+            break failed;
+        }
+        let journaled = journal
+            .append(&state)
+            .and_then(|()| journal.checkpoint_if_due());
+        if let Err(err) = journaled {
+            break Some(format!("episode journal failed: {err}"));
+        }
+        if rows.is_empty() {
+            let now = time_sync::now_ns(&app);
+            timeline.start_ns.store(now, Ordering::Relaxed);
+        }
+        rows.push(state);
+        metrics::recorder_sample(&app, rows.len(), due.elapsed());
+    };
+    let now = time_sync::now_ns(&app);
+    timeline.end_ns.store(now, Ordering::Relaxed);
+    if let Some(error) = &error {
+        tracing::warn!("recording interrupted: {error}");
+        emit_event(&app, "interrupted", &info, rows.len(), Some(error.clone()));
+        notifications::notify(
+            &app,
+            Notice::new(
+                Category::Recording,
+                format!("Recording of episode {} interrupted", info.episode_index),
+                error,
+            )
+            .action(Action::ShowWindow),
+        );
+    }
+    Capture {
+        rows,
+        encoders,
+        journal,
+    }
+}
+
+/// Keeps `latest` up to date from the backend's state topic.
+async fn follow_state(app: AppHandle, topic: String, latest: LatestState) {
+    loop {
+        match backend_tls::ws_connect(&app, &topic).await {
+            Ok(mut socket) => {
+                while let Some(Ok(message)) = socket.next().await {
+                    let Message::Text(text) = message else {
+                        continue;
+                    };
+                    match parse_state(&text) {
+                        Some(row) => *latest.lock().unwrap() = Some((Instant::now(), row)),
+                        None => tracing::debug!(%topic, "ignoring state message without vectors"),
+                    }
+                }
+            }
+            Err(err) => tracing::debug!(%topic, "state topic unavailable: {err}"),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+fn parse_state(text: &str) -> Option<Row> {
+    let message: serde_json::Value = serde_json::from_str(text).ok()?;
+    let vector = |key: &str| -> Option<Vec<f32>> {
+        message
+            .get(key)?
+            .as_array()?
+            .iter()
+            .map(|value| value.as_f64().map(|value| value as f32))
+            .collect()
+    };
+    Some(Row {
+        state: vector("observation.state")?,
+        action: vector("action")?,
+    })
+}
+
+/// Reads the frame size from a baseline or progressive JPEG's SOF marker.
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    while at + 9 < data.len() {
+        if data[at] != 0xFF {
+            return None;
+        }
+        let marker = data[at + 1];
+        let len = usize::from(u16::from_be_bytes([data[at + 2], data[at + 3]]));
+        if matches!(marker, 0xC0..=0xC3) {
+            let height = u16::from_be_bytes([data[at + 5], data[at + 6]]);
+            let width = u16::from_be_bytes([data[at + 7], data[at + 8]]);
+            return Some((u32::from(width), u32::from(height)));
+        }
+        at += 2 + len;
+    }
+    None
+}
+
+/// `name` or `owner/name`, each part a plain path component.
+pub fn valid_dataset(dataset: &str) -> bool {
+    let parts: Vec<&str> = dataset.split('/').collect();
+    parts.len() <= 2
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to.parent().expect("dataset path has a parent"))?;
+    std::fs::rename(from, to)?;
+    Ok(())
+}
+
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn emit_event(
+    app: &AppHandle,
+    event: &str,
+    info: &EpisodeInfo,
+    frames: usize,
+    error: Option<String>,
+) {
+    let payload = EpisodeEvent {
+        event,
+        dataset: &info.dataset,
+        episode_index: info.episode_index,
+        frames,
+        error,
+    };
+    let _ = app.emit("episode-event", payload);
+}
@@ -0,0 +1,597 @@
+//! View-only remote assist: a support engineer watches the cell live.
+//!
+//! A request arrives either through the support relay at
+//! `remoteAssist.relayUrl`, which must be `wss://` since it carries the
+//! bearer token and which the shell stays connected to while `enabled`, or
+//! as a direct WebRTC offer passed to [`accept_remote_assist_offer`].
+//! Nothing is published until the operator agrees: the main window is
+//! brought up and `remote-assist-request` is emitted with the request's
+//! `id`; [`respond_remote_assist`] grants it for up to `maxMinutes`, and a
+//! request left unanswered for `consentTimeoutSecs` is declined.
+//!
+//! A granted session answers the offer with a peer connection carrying an
+//! H.264 track per `remoteAssist.cameras` stream (every backend camera by
+//! default), a `telemetry` data channel forwarding the `telemetry.channels`
+//! events, throttled to [`TELEMETRY_INTERVAL`] per channel, and a `logs`
+//! channel with the backend log tail and every `backend-log` entry after
+//! it. Anything the engineer sends is ignored. The session ends when its
+//! time is up, [`end_remote_assist`] is called or the connection drops;
+//! `remote-assist-status` reports every change.
+//!
+//! The relay speaks JSON over a WebSocket authenticated with the signed-in
+//! user's access token (see [`crate::auth`]): it sends
+//! `{"type": "offer", "id", "engineer", "sdp"}` and gets back
+//! `{"type": "answer", "id", "sdp"}` or `{"type": "declined", "id", "reason"}`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, EventId, Listener, Manager, State};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use crate::auth;
+use crate::error::{Error, Result};
+use crate::instance::focus_main_window;
+use crate::logging::LogState;
+use crate::settings::{RemoteAssistSettings, Settings, SettingsStore};
+use crate::webrtc_relay::{self, StreamSource};
+
+/// Shortest gap between two forwarded samples of one telemetry channel.
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// Log lines sent when the `logs` channel opens.
+const LOG_TAIL: usize = 200;
+const RELAY_RETRY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Via {
+    Relay,
+    Direct,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AssistRequest {
+    id: u32,
+    engineer: String,
+    via: Via,
+    max_minutes: u32,
+    expires_in_secs: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssistStatus {
+    pub active: bool,
+    pub engineer: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub cameras: Vec<String>,
+    /// Why the last session ended.
+    pub ended: Option<String>,
+    pub relay_connected: bool,
+    /// Why the relay is not connected.
+    pub relay_error: Option<String>,
+}
+
+struct Pending {
+    engineer: String,
+    reply: oneshot::Sender<Option<u32>>,
+}
+
+struct Session {
+    engineer: String,
+    started_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    cameras: Vec<String>,
+    peer: Arc<RTCPeerConnection>,
+    listeners: Vec<EventId>,
+    /// Camera pumps; aborting one stops its ffmpeg.
+    tracks: Vec<JoinHandle<Result<()>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+pub struct RemoteAssist {
+    next_id: AtomicU32,
+    pending: StdMutex<HashMap<u32, Pending>>,
+    session: StdMutex<Option<Session>>,
+    /// Set while a granted request is being published.
+    starting: StdMutex<bool>,
+    ended: StdMutex<Option<String>>,
+    /// Relay URL and the task connected to it.
+    relay: StdMutex<Option<(String, JoinHandle<()>)>>,
+    relay_connected: StdMutex<bool>,
+    relay_error: StdMutex<Option<String>>,
+}
+
+/// Connects to the relay when configured, and follows the settings.
+pub fn init(app: &AppHandle) {
+    connect_relay(app, &app.state::<SettingsStore>().get().remote_assist);
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            connect_relay(&handle, &settings.remote_assist);
+            if !settings.remote_assist.enabled {
+                let handle = handle.clone();
+                tauri::async_runtime::spawn(async move { end(&handle, "disabled").await });
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_remote_assist_status(app: AppHandle) -> AssistStatus {
+    status(&app)
+}
+
+/// Asks the operator to let `engineer` watch, and answers `offer` if they
+/// agree.
+#[tauri::command]
+pub async fn accept_remote_assist_offer(
+    app: AppHandle,
+    engineer: String,
+    offer: String,
+) -> Result<String> {
+    request(&app, engineer, offer, Via::Direct).await
+}
+
+/// The operator's answer to request `id`; `minutes` defaults to
+/// `maxMinutes`.
+#[tauri::command]
+pub fn respond_remote_assist(
+    app: AppHandle,
+    assist: State<'_, RemoteAssist>,
+    id: u32,
+    accept: bool,
+    minutes: Option<u32>,
+) -> Result<()> {
+    let pending = assist
+        .pending
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("remote assist request {id}")))?;
+    let max = app.state::<SettingsStore>().get().remote_assist.max_minutes;
+    let granted = accept.then(|| minutes.unwrap_or(max).clamp(1, max.max(1)));
+    tracing::info!(id, engineer = %pending.engineer, ?granted, "remote assist answered");
+    let _ = pending.reply.send(granted);
+    Ok(())
+}
+
+/// Ends the session now.
+#[tauri::command]
+pub async fn end_remote_assist(app: AppHandle) -> Result<()> {
+    if app
+        .state::<RemoteAssist>()
+        .session
+        .lock()
+        .unwrap()
+        .is_none()
+    {
+        return Err(Error::Invalid("no remote assist session".into()));
+    }
+    end(&app, "ended by the operator").await;
+    Ok(())
+}
+
+async fn request(app: &AppHandle, engineer: String, offer: String, via: Via) -> Result<String> {
+    let settings = app.state::<SettingsStore>().get().remote_assist;
+    if !settings.enabled {
+        return Err(Error::Unsupported("remote assist is disabled".into()));
+    }
+    let assist = app.state::<RemoteAssist>();
+    if assist.session.lock().unwrap().is_some() || *assist.starting.lock().unwrap() {
+        return Err(Error::DeviceBusy(
+            "a remote assist session is running".into(),
+        ));
+    }
+    let id = assist.next_id.fetch_add(1, Ordering::Relaxed);
+    let (reply, answered) = oneshot::channel();
+    assist.pending.lock().unwrap().insert(
+        id,
+        Pending {
+            engineer: engineer.clone(),
+            reply,
+        },
+    );
+    tracing::info!(id, %engineer, "remote assist requested");
+    focus_main_window(app);
+    let request = AssistRequest {
+        id,
+        engineer: engineer.clone(),
+        via,
+        max_minutes: settings.max_minutes,
+        expires_in_secs: settings.consent_timeout_secs,
+    };
+    let _ = app.emit("remote-assist-request", request);
+
+    let timeout = Duration::from_secs(settings.consent_timeout_secs.max(1));
+    let granted = tokio::time::timeout(timeout, answered).await;
+    assist.pending.lock().unwrap().remove(&id);
+    let minutes = match granted {
+        Ok(Ok(Some(minutes))) => minutes,
+        Ok(_) => return Err(Error::Invalid("the operator declined".into())),
+        Err(_) => return Err(Error::Invalid("the operator did not answer".into())),
+    };
+    // Another request may have been granted meanwhile; only one publishes.
+    {
+        let session = assist.session.lock().unwrap();
+        let mut starting = assist.starting.lock().unwrap();
+        if session.is_some() || *starting {
+            tracing::info!(id, %engineer, "remote assist refused, a session is running");
+            return Err(Error::DeviceBusy(
+                "a remote assist session is running".into(),
+            ));
+        }
+        *starting = true;
+    }
+    let result = start(app, &settings, engineer, offer, minutes).await;
+    *assist.starting.lock().unwrap() = false;
+    result
+}
+
+async fn start(
+    app: &AppHandle,
+    settings: &RemoteAssistSettings,
+    engineer: String,
+    offer: String,
+    minutes: u32,
+) -> Result<String> {
+    let mut config = RTCConfiguration::default();
+    if !settings.ice_servers.is_empty() {
+        config.ice_servers.push(RTCIceServer {
+            urls: settings.ice_servers.clone(),
+            username: settings.turn_username.clone(),
+            credential: settings.turn_credential.clone(),
+        });
+    }
+    let peer = webrtc_relay::new_peer_connection(config).await?;
+    match publish(app, settings, &peer, engineer, offer, minutes).await {
+        Ok(answer) => Ok(answer),
+        Err(err) => {
+            let _ = peer.close().await;
+            Err(err)
+        }
+    }
+}
+
+/// Adds the streams to `peer`, answers `offer` and registers the session.
+async fn publish(
+    app: &AppHandle,
+    settings: &RemoteAssistSettings,
+    peer: &Arc<RTCPeerConnection>,
+    engineer: String,
+    offer: String,
+    minutes: u32,
+) -> Result<String> {
+    let cameras = if settings.cameras.is_empty() {
+        let backend = app.state::<SettingsStore>().get().backend;
+        backend
+            .camera_indices
+            .iter()
+            .map(|index| format!("/api/cameras/{index}/mjpeg"))
+            .collect()
+    } else {
+        settings.cameras.clone()
+    };
+    let mut tracks = Vec::new();
+    for (index, path) in cameras.iter().enumerate() {
+        let source = StreamSource::Backend { path: path.clone() };
+        let id = format!("camera{index}");
+        match webrtc_relay::add_track(app, peer, &source, &id).await {
+            Ok(track) => tracks.push(track),
+            Err(err) => {
+                for track in tracks {
+                    track.abort();
+                }
+                return Err(err);
+            }
+        }
+    }
+    let telemetry = peer.create_data_channel("telemetry", None).await?;
+    let logs = peer.create_data_channel("logs", None).await?;
+    // View-only: channels the engineer opens are never read.
+    peer.on_data_channel(Box::new(|_| Box::pin(async {})));
+
+    peer.set_remote_description(RTCSessionDescription::offer(offer)?)
+        .await?;
+    let answer = peer.create_answer(None).await?;
+    let mut gathered = peer.gathering_complete_promise().await;
+    peer.set_local_description(answer).await?;
+    let _ = gathered.recv().await;
+    let sdp = peer
+        .local_description()
+        .await
+        .ok_or_else(|| Error::Stream("no local description after answering".into()))?
+        .sdp;
+
+    let mut listeners = Vec::new();
+    let mut tasks = Vec::new();
+    tasks.push(forward_telemetry(app, telemetry, &mut listeners));
+    tasks.push(forward_logs(app, logs, &mut listeners));
+    let started_at = Utc::now();
+    let ends_at = started_at + chrono::Duration::minutes(i64::from(minutes));
+    tasks.push(tauri::async_runtime::spawn({
+        let app = app.clone();
+        async move {
+            tokio::time::sleep(Duration::from_secs(u64::from(minutes) * 60)).await;
+            end(&app, "time is up").await;
+        }
+    }));
+    {
+        let app = app.clone();
+        peer.on_peer_connection_state_change(Box::new(move |state| {
+            let app = app.clone();
+            Box::pin(async move {
+                if matches!(
+                    state,
+                    RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+                ) {
+                    end(&app, "the connection closed").await;
+                }
+            })
+        }));
+    }
+
+    tracing::info!(%engineer, minutes, cameras = cameras.len(), "remote assist started");
+    let assist = app.state::<RemoteAssist>();
+    *assist.ended.lock().unwrap() = None;
+    *assist.session.lock().unwrap() = Some(Session {
+        engineer,
+        started_at,
+        ends_at,
+        cameras,
+        peer: peer.clone(),
+        listeners,
+        tracks,
+        tasks,
+    });
+    emit_status(app);
+    Ok(sdp)
+}
+
+/// Sends each `telemetry.channels` event as `{"channel", "payload"}`.
+fn forward_telemetry(
+    app: &AppHandle,
+    channel: Arc<RTCDataChannel>,
+    listeners: &mut Vec<EventId>,
+) -> JoinHandle<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let last_sent = Arc::new(StdMutex::new(HashMap::<String, Instant>::new()));
+    for name in app.state::<SettingsStore>().get().telemetry.channels {
+        let sender = sender.clone();
+        let last_sent = last_sent.clone();
+        let event = name.clone();
+        listeners.push(app.listen_any(event, move |event| {
+            let now = Instant::now();
+            let mut last_sent = last_sent.lock().unwrap();
+            let due = last_sent
+                .get(&name)
+                .is_none_or(|at| now.duration_since(*at) >= TELEMETRY_INTERVAL);
+            if !due {
+                return;
+            }
+            last_sent.insert(name.clone(), now);
+            let payload: Value = serde_json::from_str(event.payload()).unwrap_or(Value::Null);
+            let _ = sender.send(json!({ "channel": name, "payload": payload }).to_string());
+        }));
+    }
+    tauri::async_runtime::spawn(async move {
+        while let Some(text) = receiver.recv().await {
+            // Samples before the channel opens are dropped.
+            let _ = channel.send_text(text).await;
+        }
+    })
+}
+
+/// Sends the log tail once the channel opens, then each `backend-log` entry.
+fn forward_logs(
+    app: &AppHandle,
+    channel: Arc<RTCDataChannel>,
+    listeners: &mut Vec<EventId>,
+) -> JoinHandle<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let (opened, open) = oneshot::channel();
+    let opened = StdMutex::new(Some(opened));
+    channel.on_open(Box::new(move || {
+        if let Some(opened) = opened.lock().unwrap().take() {
+            let _ = opened.send(());
+        }
+        Box::pin(async {})
+    }));
+    listeners.push(app.listen_any("backend-log", move |event| {
+        let _ = sender.send(event.payload().to_owned());
+    }));
+    let tail = app
+        .try_state::<LogState>()
+        .map(|logs| logs.recent_lines())
+        .unwrap_or_default();
+    tauri::async_runtime::spawn(async move {
+        if open.await.is_err() {
+            return;
+        }
+        for line in &tail[tail.len().saturating_sub(LOG_TAIL)..] {
+            let _ = channel.send_text(line.clone()).await;
+        }
+        while let Some(text) = receiver.recv().await {
+            let _ = channel.send_text(text).await;
+        }
+    })
+}
+
+/// Tears the session down once, however it ended.
+async fn end(app: &AppHandle, reason: &str) {
+    let assist = app.state::<RemoteAssist>();
+    let Some(session) = assist.session.lock().unwrap().take() else {
+        return;
+    };
+    tracing::info!(engineer = %session.engineer, reason, "remote assist ended");
+    *assist.ended.lock().unwrap() = Some(reason.to_owned());
+    for listener in session.listeners {
+        app.unlisten(listener);
+    }
+    for track in session.tracks {
+        track.abort();
+    }
+    let _ = session.peer.close().await;
+    emit_status(app);
+    // Last, because this may be the timer task itself.
+    for task in session.tasks {
+        task.abort();
+    }
+}
+
+fn status(app: &AppHandle) -> AssistStatus {
+    let assist = app.state::<RemoteAssist>();
+    let session = assist.session.lock().unwrap();
+    let status = AssistStatus {
+        active: session.is_some(),
+        engineer: session.as_ref().map(|session| session.engineer.clone()),
+        started_at: session.as_ref().map(|session| session.started_at),
+        ends_at: session.as_ref().map(|session| session.ends_at),
+        cameras: session
+            .as_ref()
+            .map(|session| session.cameras.clone())
+            .unwrap_or_default(),
+        ended: assist.ended.lock().unwrap().clone(),
+        relay_connected: *assist.relay_connected.lock().unwrap(),
+        relay_error: assist.relay_error.lock().unwrap().clone(),
+    };
+    status
+}
+
+fn emit_status(app: &AppHandle) {
+    let _ = app.emit("remote-assist-status", status(app));
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum RelayMessage {
+    Offer {
+        id: String,
+        engineer: String,
+        sdp: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Replaces the relay connection with one for `settings`.
+fn connect_relay(app: &AppHandle, settings: &RemoteAssistSettings) {
+    let url = settings.relay_url.clone().filter(|_| settings.enabled);
+    let assist = app.state::<RemoteAssist>();
+    let mut relay = assist.relay.lock().unwrap();
+    if relay.as_ref().map(|(current, _)| current) == url.as_ref() {
+        return;
+    }
+    if let Some((_, task)) = relay.take() {
+        task.abort();
+        *assist.relay_connected.lock().unwrap() = false;
+    }
+    *assist.relay_error.lock().unwrap() = None;
+    if let Some(url) = url {
+        let task = tauri::async_runtime::spawn(run_relay(app.clone(), url.clone()));
+        *relay = Some((url, task));
+    }
+}
+
+async fn run_relay(app: AppHandle, url: String) {
+    if !url.starts_with("wss://") {
+        tracing::error!(%url, "remoteAssist.relayUrl must use wss://");
+        *app.state::<RemoteAssist>().relay_error.lock().unwrap() =
+            Some("remoteAssist.relayUrl must use wss://, it carries the access token".into());
+        return emit_status(&app);
+    }
+    loop {
+        let error = match relay_session(&app, &url).await {
+            Ok(()) => {
+                tracing::info!(%url, "support relay closed the connection");
+                None
+            }
+            Err(err) => {
+                tracing::warn!(%url, "support relay: {err}");
+                Some(err.to_string())
+            }
+        };
+        {
+            let assist = app.state::<RemoteAssist>();
+            *assist.relay_connected.lock().unwrap() = false;
+            *assist.relay_error.lock().unwrap() = error;
+        }
+        emit_status(&app);
+        tokio::time::sleep(RELAY_RETRY).await;
+    }
+}
+
+async fn relay_session(app: &AppHandle, url: &str) -> Result<()> {
+    let token = auth::access_token(app)
+        .await?
+        .ok_or_else(|| Error::Auth("sign in to use the support relay".into()))?;
+    let mut handshake = url
+        .into_client_request()
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    let bearer = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|err| Error::Auth(err.to_string()))?;
+    handshake.headers_mut().insert("Authorization", bearer);
+    let (socket, _) = tokio_tungstenite::connect_async(handshake)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?;
+    tracing::info!(%url, "connected to the support relay");
+    {
+        let assist = app.state::<RemoteAssist>();
+        *assist.relay_connected.lock().unwrap() = true;
+        *assist.relay_error.lock().unwrap() = None;
+    }
+    emit_status(app);
+
+    let (mut sink, mut stream) = socket.split();
+    let (replies, mut outgoing) = mpsc::unbounded_channel::<Value>();
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(Error::Stream(err.to_string())),
+                };
+                let Ok(RelayMessage::Offer { id, engineer, sdp }) = serde_json::from_str(&text) else {
+                    tracing::debug!("ignoring relay message: {text}");
+                    continue;
+                };
+                // Consent can take a while; keep reading meanwhile.
+                let app = app.clone();
+                let replies = replies.clone();
+                tauri::async_runtime::spawn(async move {
+                    let reply = match request(&app, engineer, sdp, Via::Relay).await {
+                        Ok(sdp) => json!({ "type": "answer", "id": id, "sdp": sdp }),
+                        Err(err) => json!({ "type": "declined", "id": id, "reason": err.to_string() }),
+                    };
+                    let _ = replies.send(reply);
+                });
+            }
+            Some(reply) = outgoing.recv() => {
+                sink.send(Message::Text(reply.to_string().into()))
+                    .await
+                    .map_err(|err| Error::Stream(err.to_string()))?;
+            }
+        }
+    }
+}
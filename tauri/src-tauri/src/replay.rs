@@ -0,0 +1,583 @@
+//! Episode replay inside the app, so annotators can review episodes without
+//! exporting them.
+//!
+//! [`open_replay`] loads an episode, paused at its first frame. Each camera
+//! video is decoded by an `ffmpeg` child into JPEG frames that go to the
+//! frame ring `replay<id>-<camera>`, so the webview shows them through
+//! `frame://` like a live camera. Every frame shown is also emitted as
+//! `replay-frame` with the table row of the same index.
+//!
+//! The timeline is the recorded one, `frame_index / fps`, scaled by the
+//! playback rate. When decoding falls behind, frames are skipped rather
+//! than the timeline slowed down; a seek restarts the decoders at the new
+//! position. [`play_replay`], [`pause_replay`], [`seek_replay`] and
+//! [`set_replay_rate`] answer with the new [`ReplayStatus`], which is also
+//! emitted as `replay-status`, as is reaching the end of the episode.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use arrow_array::{Array, FixedSizeListArray, Float32Array};
+use bytes::{Bytes, BytesMut};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::Notify;
+
+use crate::error::{Error, Result};
+use crate::frames::{self, FrameRings, RingWriter};
+use crate::recording;
+use crate::settings::SettingsStore;
+use crate::time_sync;
+use crate::windows;
+
+const CAMERA_PREFIX: &str = "observation.images.";
+const RING_SLOTS: usize = 4;
+const JPEG_QUALITY: &str = "5";
+/// Decoders further behind than this many seconds seek instead of reading
+/// their way forward.
+const MAX_SKIP_SECS: f64 = 2.0;
+const MIN_RATE: f64 = 0.05;
+const MAX_RATE: f64 = 16.0;
+
+/// One row of the episode table.
+struct TableRow {
+    timestamp: f32,
+    state: Vec<f32>,
+    action: Vec<f32>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayCamera {
+    /// `observation.images.<camera>`.
+    pub key: String,
+    /// Frame ring the camera is replayed into.
+    pub stream: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayInfo {
+    pub id: u32,
+    pub dataset: String,
+    pub episode_index: usize,
+    pub fps: f64,
+    pub frames: usize,
+    pub cameras: Vec<ReplayCamera>,
+    pub status: ReplayStatus,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayStatus {
+    pub id: u32,
+    pub playing: bool,
+    pub rate: f64,
+    /// Frame at the playback position.
+    pub frame_index: usize,
+    /// Whether playback stopped at the last frame.
+    pub finished: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplayFrame<'a> {
+    id: u32,
+    frame_index: usize,
+    /// Seconds since the episode start.
+    timestamp: f32,
+    state: &'a [f32],
+    action: &'a [f32],
+}
+
+/// The playback position as a function of wall-clock time.
+struct Clock {
+    fps: f64,
+    frames: usize,
+    playing: bool,
+    rate: f64,
+    /// Position at `anchor`, in frames.
+    anchor_frame: f64,
+    anchor: Instant,
+    finished: bool,
+}
+
+impl Clock {
+    fn position(&self, now: Instant) -> f64 {
+        let position = if self.playing {
+            self.anchor_frame + now.duration_since(self.anchor).as_secs_f64() * self.fps * self.rate
+        } else {
+            self.anchor_frame
+        };
+        position.clamp(0.0, self.last() as f64)
+    }
+
+    fn last(&self) -> usize {
+        self.frames.saturating_sub(1)
+    }
+
+    /// Restarts the timeline at the current position, e.g. before changing
+    /// the rate.
+    fn reanchor(&mut self, now: Instant) {
+        self.anchor_frame = self.position(now);
+        self.anchor = now;
+    }
+
+    /// How long until the next frame is due.
+    fn until_next(&self, now: Instant) -> Option<Duration> {
+        if !self.playing {
+            return None;
+        }
+        let position = self.position(now);
+        let frames = position.floor() + 1.0 - position;
+        Some(Duration::from_secs_f64(frames / (self.fps * self.rate)))
+    }
+}
+
+struct Shared {
+    id: u32,
+    clock: StdMutex<Clock>,
+    changed: Notify,
+}
+
+impl Shared {
+    fn status(&self) -> ReplayStatus {
+        let clock = self.clock.lock().unwrap();
+        ReplayStatus {
+            id: self.id,
+            playing: clock.playing,
+            rate: clock.rate,
+            frame_index: clock.position(Instant::now()) as usize,
+            finished: clock.finished,
+        }
+    }
+
+    /// Applies `change` to the clock, wakes the player and reports the
+    /// result.
+    fn update(&self, app: &AppHandle, change: impl FnOnce(&mut Clock, Instant)) -> ReplayStatus {
+        {
+            let mut clock = self.clock.lock().unwrap();
+            change(&mut clock, Instant::now());
+        }
+        self.changed.notify_one();
+        let status = self.status();
+        let _ = app.emit("replay-status", status.clone());
+        status
+    }
+}
+
+struct Session {
+    shared: Arc<Shared>,
+    player: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct Replays {
+    next_id: AtomicU32,
+    sessions: StdMutex<HashMap<u32, Session>>,
+}
+
+impl Replays {
+    fn shared(&self, id: u32) -> Result<Arc<Shared>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|session| session.shared.clone())
+            .ok_or_else(|| Error::NotFound(format!("replay {id}")))
+    }
+}
+
+/// Loads `episode_index` of `dataset` for replay, paused at the first frame.
+#[tauri::command]
+pub async fn open_replay(
+    app: AppHandle,
+    replays: State<'_, Replays>,
+    dataset: String,
+    episode_index: usize,
+) -> Result<ReplayInfo> {
+    let dir = recording::dataset_dir(&app, &dataset)?;
+    let info: serde_json::Map<String, Value> =
+        serde_json::from_slice(&std::fs::read(dir.join("meta/info.json"))?)?;
+    let fps = info
+        .get("fps")
+        .and_then(Value::as_f64)
+        .filter(|fps| *fps > 0.0)
+        .ok_or_else(|| Error::Invalid(format!("dataset {dataset} has no fps")))?;
+    let table_path = recording::episode_data_path(&dir, episode_index);
+    if !table_path.is_file() {
+        return Err(Error::NotFound(format!(
+            "episode {episode_index} of {dataset}"
+        )));
+    }
+    let rows = tauri::async_runtime::spawn_blocking(move || read_table(&table_path))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))??;
+    if rows.is_empty() {
+        return Err(Error::Invalid("episode has no frames".into()));
+    }
+
+    let id = replays.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+    let rings = app.state::<FrameRings>();
+    let mut cameras = Vec::new();
+    let mut decoders = Vec::new();
+    let features = info.get("features").and_then(Value::as_object);
+    for (key, feature) in features.into_iter().flatten() {
+        if feature.get("dtype").and_then(Value::as_str) != Some("video") {
+            continue;
+        }
+        let size = |name: &str, index: usize| {
+            feature
+                .pointer(&format!("/info/video.{name}"))
+                .or_else(|| feature.get("shape")?.get(index))
+                .and_then(Value::as_u64)
+                .unwrap_or(0) as u32
+        };
+        let (width, height) = (size("width", 1), size("height", 0));
+        let camera = key.strip_prefix(CAMERA_PREFIX).unwrap_or(key);
+        let stream = format!("replay{id}-{camera}");
+        if !frames::valid_stream(&stream) || width == 0 || height == 0 {
+            tracing::warn!(%key, "camera not replayable");
+            continue;
+        }
+        // Raw RGB bounds any JPEG ffmpeg produces at this quality.
+        let capacity = width as usize * height as usize * 3;
+        decoders.push(Decoder {
+            ffmpeg: ffmpeg.clone(),
+            path: recording::episode_video_path(&dir, episode_index, key),
+            fps,
+            ring: RingWriter::create(&rings, &stream, RING_SLOTS, capacity)?,
+            width,
+            height,
+            child: None,
+            pending: BytesMut::new(),
+            next: 0,
+        });
+        cameras.push(ReplayCamera {
+            key: key.clone(),
+            stream,
+            width,
+            height,
+        });
+    }
+
+    let shared = Arc::new(Shared {
+        id,
+        clock: StdMutex::new(Clock {
+            fps,
+            frames: rows.len(),
+            playing: false,
+            rate: 1.0,
+            anchor_frame: 0.0,
+            anchor: Instant::now(),
+            finished: false,
+        }),
+        changed: Notify::new(),
+    });
+    let frames = rows.len();
+    let player = tauri::async_runtime::spawn(play(app.clone(), shared.clone(), rows, decoders));
+    let status = shared.status();
+    replays
+        .sessions
+        .lock()
+        .unwrap()
+        .insert(id, Session { shared, player });
+    tracing::info!(%dataset, episode = episode_index, id, "replay opened");
+    Ok(ReplayInfo {
+        id,
+        dataset,
+        episode_index,
+        fps,
+        frames,
+        cameras,
+        status,
+    })
+}
+
+/// Plays from the current position, or from the start once finished.
+#[tauri::command]
+pub fn play_replay(app: AppHandle, replays: State<'_, Replays>, id: u32) -> Result<ReplayStatus> {
+    Ok(replays.shared(id)?.update(&app, |clock, now| {
+        if clock.finished || clock.position(now) as usize >= clock.last() {
+            clock.anchor_frame = 0.0;
+        } else {
+            clock.reanchor(now);
+        }
+        clock.anchor = now;
+        clock.playing = true;
+        clock.finished = false;
+    }))
+}
+
+#[tauri::command]
+pub fn pause_replay(app: AppHandle, replays: State<'_, Replays>, id: u32) -> Result<ReplayStatus> {
+    Ok(replays.shared(id)?.update(&app, |clock, now| {
+        clock.reanchor(now);
+        clock.playing = false;
+    }))
+}
+
+/// Moves to `frame_index`, or to `timestamp` (seconds) when given instead.
+#[tauri::command]
+pub fn seek_replay(
+    app: AppHandle,
+    replays: State<'_, Replays>,
+    id: u32,
+    frame_index: Option<usize>,
+    timestamp: Option<f64>,
+) -> Result<ReplayStatus> {
+    let shared = replays.shared(id)?;
+    if frame_index.is_none() && timestamp.is_none() {
+        return Err(Error::Invalid(
+            "seek needs a frame index or timestamp".into(),
+        ));
+    }
+    Ok(shared.update(&app, |clock, now| {
+        let frame = match (frame_index, timestamp) {
+            (Some(frame), _) => frame as f64,
+            (None, Some(timestamp)) => (timestamp * clock.fps).round(),
+            (None, None) => unreachable!(),
+        };
+        clock.anchor_frame = frame.clamp(0.0, clock.last() as f64);
+        clock.anchor = now;
+        clock.finished = false;
+    }))
+}
+
+/// Sets the playback speed; 1 is the recorded pace.
+#[tauri::command]
+pub fn set_replay_rate(
+    app: AppHandle,
+    replays: State<'_, Replays>,
+    id: u32,
+    rate: f64,
+) -> Result<ReplayStatus> {
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return Err(Error::Invalid(format!(
+            "rate must be between {MIN_RATE} and {MAX_RATE}"
+        )));
+    }
+    Ok(replays.shared(id)?.update(&app, |clock, now| {
+        clock.reanchor(now);
+        clock.rate = rate;
+    }))
+}
+
+/// Stops the replay and removes its frame rings.
+#[tauri::command]
+pub fn close_replay(replays: State<'_, Replays>, id: u32) -> Result<()> {
+    let session = replays
+        .sessions
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("replay {id}")))?;
+    // Dropping the player kills its decoders and removes the rings.
+    session.player.abort();
+    Ok(())
+}
+
+/// Shows the frame at the playback position whenever it changes.
+async fn play(
+    app: AppHandle,
+    shared: Arc<Shared>,
+    rows: Vec<TableRow>,
+    mut decoders: Vec<Decoder>,
+) {
+    let mut shown = None;
+    loop {
+        let now = Instant::now();
+        let (frame, wait, finished) = {
+            let mut clock = shared.clock.lock().unwrap();
+            let frame = clock.position(now) as usize;
+            let finished = clock.playing && frame >= clock.last();
+            if finished {
+                clock.reanchor(now);
+                clock.playing = false;
+                clock.finished = true;
+            }
+            (frame, clock.until_next(now), finished)
+        };
+        // Registered before rendering so no change in between is missed.
+        let changed = shared.changed.notified();
+
+        if shown != Some(frame) {
+            let timestamp_ns = time_sync::now_ns(&app).max(0) as u64;
+            for decoder in &mut decoders {
+                if let Err(err) = decoder.show(frame, timestamp_ns).await {
+                    tracing::warn!(path = %decoder.path.display(), "replay frame not decoded: {err}");
+                }
+            }
+            let row = &rows[frame];
+            windows::emit(
+                &app,
+                "replay-frame",
+                ReplayFrame {
+                    id: shared.id,
+                    frame_index: frame,
+                    timestamp: row.timestamp,
+                    state: &row.state,
+                    action: &row.action,
+                },
+            );
+            shown = Some(frame);
+        }
+        if finished {
+            let _ = app.emit("replay-status", shared.status());
+        }
+
+        match wait {
+            Some(wait) => {
+                tokio::select! {
+                    _ = changed => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+            None => changed.await,
+        }
+    }
+}
+
+/// One camera video, decoded forward from the last seek.
+struct Decoder {
+    ffmpeg: String,
+    path: PathBuf,
+    fps: f64,
+    ring: RingWriter,
+    width: u32,
+    height: u32,
+    child: Option<(Child, ChildStdout)>,
+    pending: BytesMut,
+    /// Index of the next frame out of ffmpeg.
+    next: usize,
+}
+
+impl Decoder {
+    /// Writes frame `frame` to the ring, decoding up to it.
+    async fn show(&mut self, frame: usize, timestamp_ns: u64) -> Result<()> {
+        let behind = frame.saturating_sub(self.next) as f64 / self.fps;
+        if self.child.is_none() || frame < self.next || behind > MAX_SKIP_SECS {
+            self.seek(frame)?;
+        }
+        let mut jpeg = None;
+        while self.next <= frame {
+            match self.next_jpeg().await? {
+                Some(next) => jpeg = Some(next),
+                // The video is shorter than the table; keep the last frame.
+                None => break,
+            }
+            self.next += 1;
+        }
+        if let Some(jpeg) = jpeg {
+            self.ring
+                .write(&jpeg, *b"MJPG", self.width, self.height, timestamp_ns)?;
+        }
+        Ok(())
+    }
+
+    /// Restarts ffmpeg at `frame`.
+    fn seek(&mut self, frame: usize) -> Result<()> {
+        let start = format!("{:.6}", frame as f64 / self.fps);
+        let mut child = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-ss", &start, "-i"])
+            .arg(&self.path)
+            .args([
+                "-an",
+                "-c:v",
+                "mjpeg",
+                "-q:v",
+                JPEG_QUALITY,
+                "-f",
+                "mjpeg",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        self.child = Some((child, stdout));
+        self.pending.clear();
+        self.next = frame;
+        Ok(())
+    }
+
+    /// The next JPEG out of ffmpeg, `None` at the end of the video.
+    async fn next_jpeg(&mut self) -> Result<Option<Bytes>> {
+        let Some((_, stdout)) = &mut self.child else {
+            return Ok(None);
+        };
+        let mut chunk = vec![0u8; 64 * 1024];
+        loop {
+            while let Some(end) = self
+                .pending
+                .windows(2)
+                .position(|pair| pair == [0xFF, 0xD9])
+            {
+                let jpeg = self.pending.split_to(end + 2).freeze();
+                if jpeg.starts_with(&[0xFF, 0xD8]) {
+                    return Ok(Some(jpeg));
+                }
+            }
+            let read = stdout.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+/// Timestamps, states and actions of an episode table.
+fn read_table(path: &std::path::Path) -> Result<Vec<TableRow>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        let vectors = |name: &str| -> Vec<Vec<f32>> {
+            let Some(list) = batch
+                .column_by_name(name)
+                .and_then(|column| column.as_any().downcast_ref::<FixedSizeListArray>())
+            else {
+                return vec![Vec::new(); batch.num_rows()];
+            };
+            (0..list.len())
+                .map(|row| {
+                    let values = list.value(row);
+                    values
+                        .as_any()
+                        .downcast_ref::<Float32Array>()
+                        .map(|values| values.values().to_vec())
+                        .unwrap_or_default()
+                })
+                .collect()
+        };
+        let states = vectors("observation.state");
+        let actions = vectors("action");
+        let timestamps = batch
+            .column_by_name("timestamp")
+            .and_then(|column| column.as_any().downcast_ref::<Float32Array>());
+        for (row, (state, action)) in states.into_iter().zip(actions).enumerate() {
+            rows.push(TableRow {
+                timestamp: timestamps.map_or(0.0, |timestamps| timestamps.value(row)),
+                state,
+                action,
+            });
+        }
+    }
+    Ok(rows)
+}
@@ -0,0 +1,292 @@
+//! Retention rules for recorded and generated data.
+//!
+//! The `retention` settings hold a rule per data class: `episodes` (whole
+//! datasets under the recording root), `telemetry` files, `logs` and
+//! `crashBundles`. A rule deletes items older than `maxAgeDays` and then,
+//! oldest first, as many as it takes to bring the class under `maxTotalGb`;
+//! with `uploadedOnly` only items whose files the upload queue has finished
+//! are deleted, though the others still count towards the total. A rule of
+//! 0 does not apply. Items changed within the last hour, and the dataset an
+//! episode is being recorded into, are never deleted.
+//!
+//! The rules are enforced by the scheduler's `retention` job, and only for
+//! classes whose `enabled` is set; [`preview_pruning`] lists what every
+//! class's rule would delete right now, enabled or not, and deletes nothing.
+//! `telemetry.retentionDays` and `retentionGb` keep applying after each
+//! telemetry flush.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::crash;
+use crate::error::{Error, Result};
+use crate::recording::{self, Recorder};
+use crate::settings::{RetentionRule, SettingsStore};
+use crate::telemetry;
+use crate::uploads::Uploads;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+/// Items changed more recently may still be written to.
+const GRACE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DataClass {
+    Episodes,
+    Telemetry,
+    Logs,
+    CrashBundles,
+}
+
+const CLASSES: [DataClass; 4] = [
+    DataClass::Episodes,
+    DataClass::Telemetry,
+    DataClass::Logs,
+    DataClass::CrashBundles,
+];
+
+/// A file or directory a rule applies to.
+struct Item {
+    path: PathBuf,
+    bytes: u64,
+    modified: SystemTime,
+    uploaded: bool,
+    protected: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneCandidate {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub modified: DateTime<Utc>,
+    /// `age` or `size`.
+    pub reason: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassPreview {
+    pub class: DataClass,
+    pub enabled: bool,
+    pub items: usize,
+    pub total_bytes: u64,
+    pub delete: Vec<PruneCandidate>,
+    pub freed_bytes: u64,
+    /// Why the class could not be inspected.
+    pub error: Option<String>,
+}
+
+/// What each class's rule would delete now.
+#[tauri::command]
+pub async fn preview_pruning(app: AppHandle) -> Result<Vec<ClassPreview>> {
+    tauri::async_runtime::spawn_blocking(move || {
+        CLASSES.iter().map(|&class| preview(&app, class)).collect()
+    })
+    .await
+    .map_err(|err| Error::Invalid(format!("pruning preview failed: {err}")))
+}
+
+/// Applies the enabled rules; run by the scheduler.
+pub async fn prune(app: &AppHandle) -> Result<String> {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let (mut removed, mut freed, mut failed) = (0, 0, 0);
+        for class in CLASSES {
+            let preview = preview(&app, class);
+            if !preview.enabled {
+                continue;
+            }
+            if let Some(error) = &preview.error {
+                tracing::warn!(?class, "retention skipped: {error}");
+                failed += 1;
+                continue;
+            }
+            for candidate in preview.delete {
+                let deleted = if candidate.path.is_dir() {
+                    std::fs::remove_dir_all(&candidate.path)
+                } else {
+                    std::fs::remove_file(&candidate.path)
+                };
+                match deleted {
+                    Ok(()) => {
+                        tracing::info!(
+                            ?class,
+                            path = %candidate.path.display(),
+                            reason = candidate.reason,
+                            "pruned"
+                        );
+                        removed += 1;
+                        freed += candidate.bytes;
+                    }
+                    Err(err) => {
+                        tracing::warn!(path = %candidate.path.display(), "not pruned: {err}");
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        let message = format!(
+            "removed {removed} item(s), {:.1} MB",
+            freed as f64 / 1_000_000.0
+        );
+        match failed {
+            0 => Ok(message),
+            _ => Err(Error::Invalid(format!("{message}; {failed} failed"))),
+        }
+    })
+    .await
+    .map_err(|err| Error::Invalid(format!("pruning failed: {err}")))?
+}
+
+fn preview(app: &AppHandle, class: DataClass) -> ClassPreview {
+    let settings = app.state::<SettingsStore>().get();
+    let rule = match class {
+        DataClass::Episodes => settings.retention.episodes,
+        DataClass::Telemetry => settings.retention.telemetry,
+        DataClass::Logs => settings.retention.logs,
+        DataClass::CrashBundles => settings.retention.crash_bundles,
+    };
+    let mut preview = ClassPreview {
+        class,
+        enabled: rule.enabled,
+        items: 0,
+        total_bytes: 0,
+        delete: Vec::new(),
+        freed_bytes: 0,
+        error: None,
+    };
+    let items = match items(app, class) {
+        Ok(items) => items,
+        Err(err) => {
+            preview.error = Some(err.to_string());
+            return preview;
+        }
+    };
+    preview.items = items.len();
+    preview.total_bytes = items.iter().map(|item| item.bytes).sum();
+    preview.delete = plan(&rule, items);
+    preview.freed_bytes = preview.delete.iter().map(|item| item.bytes).sum();
+    preview
+}
+
+/// Items to delete under `rule`, oldest first.
+fn plan(rule: &RetentionRule, mut items: Vec<Item>) -> Vec<PruneCandidate> {
+    items.sort_by_key(|item| item.modified);
+    let max_age = Duration::from_secs(u64::from(rule.max_age_days) * 24 * 60 * 60);
+    let max_bytes = (rule.max_total_gb * GB) as u64;
+    let mut total: u64 = items.iter().map(|item| item.bytes).sum();
+    let mut delete = Vec::new();
+    for item in items {
+        if item.protected || (rule.uploaded_only && !item.uploaded) {
+            continue;
+        }
+        let old = rule.max_age_days > 0 && item.modified.elapsed().is_ok_and(|age| age > max_age);
+        let reason = if old {
+            "age"
+        } else if rule.max_total_gb > 0.0 && total > max_bytes {
+            "size"
+        } else {
+            continue;
+        };
+        total -= item.bytes;
+        delete.push(PruneCandidate {
+            path: item.path,
+            bytes: item.bytes,
+            modified: item.modified.into(),
+            reason,
+        });
+    }
+    delete
+}
+
+fn items(app: &AppHandle, class: DataClass) -> Result<Vec<Item>> {
+    let uploaded = app.state::<Uploads>().uploaded_files();
+    let settings = app.state::<SettingsStore>().get();
+    let mut items = Vec::new();
+    match class {
+        DataClass::Episodes => {
+            let recording = app.state::<Recorder>().recording_dataset();
+            for dir in subdirectories(&recording::datasets_root(app)?)? {
+                let name = dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+                let mut item = item(&dir, &uploaded)?;
+                item.protected |= name.is_some() && name == recording;
+                items.push(item);
+            }
+        }
+        DataClass::Telemetry => {
+            let dir = telemetry::telemetry_dir(app, &settings.telemetry)?;
+            for file in files(&dir)? {
+                items.push(item(&file, &uploaded)?);
+            }
+        }
+        DataClass::Logs => {
+            for file in files(&app.path().app_log_dir()?)? {
+                items.push(item(&file, &uploaded)?);
+            }
+        }
+        DataClass::CrashBundles => {
+            for dir in subdirectories(&crash::crash_dir(app)?)? {
+                items.push(item(&dir, &uploaded)?);
+            }
+        }
+    }
+    Ok(items)
+}
+
+fn item(path: &Path, uploaded: &HashSet<PathBuf>) -> std::io::Result<Item> {
+    let files = if path.is_dir() {
+        files(path)?
+    } else {
+        vec![path.to_owned()]
+    };
+    let mut bytes = 0;
+    let mut modified = std::fs::metadata(path)?.modified()?;
+    for file in &files {
+        let meta = std::fs::metadata(file)?;
+        bytes += meta.len();
+        modified = modified.max(meta.modified()?);
+    }
+    Ok(Item {
+        path: path.to_owned(),
+        bytes,
+        modified,
+        uploaded: !files.is_empty() && files.iter().all(|file| uploaded.contains(file)),
+        protected: modified.elapsed().map_or(true, |age| age < GRACE),
+    })
+}
+
+fn subdirectories(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    Ok(entries(dir)?
+        .into_iter()
+        .filter(|path| path.is_dir())
+        .collect())
+}
+
+/// Files under `dir`, recursively.
+fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in entries(dir)? {
+        if path.is_dir() {
+            files.extend(self::files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn entries(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| Ok(entry?.path())).collect(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,386 @@
+//! ROS 2 bridge, built with the `ros2` cargo feature.
+//!
+//! [`start_ros2`] brings up an rclrs node on its own thread, configured by
+//! the `ros2` settings section, so the app can drive ROS 2 robots without
+//! the Python sidecar:
+//!
+//! - the joint-states topic is forwarded as `ros2-joint-state` events,
+//!   throttled to `jointStateMaxHz`, and kept at its full rate in
+//!   [`crate::joint_history`];
+//! - each configured image topic is written into a frame ring and served as
+//!   `frame://localhost/<name>/latest`, like backend cameras;
+//! - [`ros2_publish_twist`] and [`ros2_publish_joint_jog`] publish teleop
+//!   commands (`Twist`/`TwistStamped` and `control_msgs/JointJog`).
+
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use rclrs::{
+    Context, CreateBasicExecutor, ExecutorCommands, IntoNodeOptions, IntoPrimitiveOptions, Node,
+    Publisher, RclrsError, SpinOptions,
+};
+use ros_env::builtin_interfaces::msg::Time;
+use ros_env::control_msgs::msg::JointJog;
+use ros_env::geometry_msgs::msg::{Twist, TwistStamped, Vector3};
+use ros_env::sensor_msgs::msg::{CompressedImage, Image, JointState};
+use ros_env::std_msgs::msg::Header;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::oneshot;
+
+use crate::error::{Error, Result};
+use crate::frames::{FrameRings, RingWriter};
+use crate::joint_history::{self, JointReading};
+use crate::settings::{Ros2Camera, Ros2Settings, SettingsStore};
+use crate::windows;
+
+const RING_SLOTS: usize = 4;
+const RING_CAPACITY: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JointStateEvent {
+    stamp_ns: i64,
+    name: Vec<String>,
+    position: Vec<f64>,
+    velocity: Vec<f64>,
+    effort: Vec<f64>,
+}
+
+/// Cartesian velocity command, in m/s and rad/s.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwistCommand {
+    pub linear: [f64; 3],
+    pub angular: [f64; 3],
+    /// Defaults to `commandFrame` from the settings.
+    pub frame: Option<String>,
+}
+
+/// Joint-space jog; `velocities` and `displacements` follow `jointNames`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointJogCommand {
+    pub joint_names: Vec<String>,
+    #[serde(default)]
+    pub velocities: Vec<f64>,
+    #[serde(default)]
+    pub displacements: Vec<f64>,
+    #[serde(default)]
+    pub duration: f64,
+}
+
+enum TwistPublisher {
+    Plain(Publisher<Twist>),
+    Stamped(Publisher<TwistStamped>),
+}
+
+struct Running {
+    node: Node,
+    executor: Arc<ExecutorCommands>,
+    twist: TwistPublisher,
+    joint_jog: Publisher<JointJog>,
+    command_frame: String,
+    thread: std::thread::JoinHandle<()>,
+}
+
+/// The running node, if any.
+#[derive(Default)]
+pub struct Ros2Bridge(StdMutex<Option<Running>>);
+
+/// Registered from setup because managed state cannot be feature-gated in
+/// the builder chain.
+pub fn init(app: &AppHandle) {
+    app.manage(Ros2Bridge::default());
+}
+
+/// Starts the node; a no-op if it is already running.
+#[tauri::command]
+pub async fn start_ros2(app: AppHandle, bridge: State<'_, Ros2Bridge>) -> Result<()> {
+    if bridge.0.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    let settings = app.state::<SettingsStore>().get().ros2;
+    let (ready, started) = oneshot::channel();
+    let command_frame = settings.command_frame.clone();
+    let thread = std::thread::Builder::new()
+        .name("ros2".into())
+        .spawn(move || run(app, settings, ready))?;
+    let (node, executor, twist, joint_jog) = started
+        .await
+        .map_err(|_| Error::Invalid("ros2 node thread exited".into()))??;
+
+    let mut running = bridge.0.lock().unwrap();
+    if running.is_some() {
+        // Lost a race with another start; keep the first node.
+        executor.halt_spinning();
+        return Ok(());
+    }
+    *running = Some(Running {
+        node,
+        executor,
+        twist,
+        joint_jog,
+        command_frame,
+        thread,
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_ros2(bridge: State<'_, Ros2Bridge>) -> Result<()> {
+    let Some(running) = bridge.0.lock().unwrap().take() else {
+        return Ok(());
+    };
+    running.executor.halt_spinning();
+    drop(running.node);
+    if running.thread.join().is_err() {
+        tracing::warn!("ros2 node thread panicked");
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn ros2_publish_twist(bridge: State<'_, Ros2Bridge>, twist: TwistCommand) -> Result<()> {
+    let running = bridge.0.lock().unwrap();
+    let running = running
+        .as_ref()
+        .ok_or_else(|| Error::Invalid("ros2 bridge is not running".into()))?;
+    let [x, y, z] = twist.linear;
+    let [roll, pitch, yaw] = twist.angular;
+    let message = Twist {
+        linear: Vector3 { x, y, z },
+        angular: Vector3 {
+            x: roll,
+            y: pitch,
+            z: yaw,
+        },
+    };
+    match &running.twist {
+        TwistPublisher::Plain(publisher) => publisher.publish(message)?,
+        TwistPublisher::Stamped(publisher) => publisher.publish(TwistStamped {
+            header: header(
+                &running.node,
+                twist.frame.unwrap_or_else(|| running.command_frame.clone()),
+            ),
+            twist: message,
+        })?,
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn ros2_publish_joint_jog(bridge: State<'_, Ros2Bridge>, jog: JointJogCommand) -> Result<()> {
+    let running = bridge.0.lock().unwrap();
+    let running = running
+        .as_ref()
+        .ok_or_else(|| Error::Invalid("ros2 bridge is not running".into()))?;
+    running.joint_jog.publish(JointJog {
+        header: header(&running.node, String::new()),
+        joint_names: jog.joint_names,
+        displacements: jog.displacements,
+        velocities: jog.velocities,
+        duration: jog.duration,
+    })?;
+    Ok(())
+}
+
+type Started = (
+    Node,
+    Arc<ExecutorCommands>,
+    TwistPublisher,
+    Publisher<JointJog>,
+);
+
+/// Body of the node thread: builds the node, reports back, then spins until
+/// [`stop_ros2`] halts the executor.
+fn run(app: AppHandle, settings: Ros2Settings, ready: oneshot::Sender<Result<Started>>) {
+    let built = (|| -> std::result::Result<_, RclrsError> {
+        let context = Context::default_from_env()?;
+        let executor = context.create_basic_executor();
+        let node = executor.create_node(
+            settings
+                .node_name
+                .as_str()
+                .namespace(settings.namespace.as_str()),
+        )?;
+        let mut subscriptions = vec![joint_states(&app, &node, &settings)?];
+        for camera in &settings.cameras {
+            subscriptions.push(camera_feed(&app, &node, camera)?);
+        }
+        let twist = if settings.twist_stamped {
+            TwistPublisher::Stamped(node.create_publisher(settings.twist_topic.as_str())?)
+        } else {
+            TwistPublisher::Plain(node.create_publisher(settings.twist_topic.as_str())?)
+        };
+        let joint_jog = node.create_publisher(settings.joint_jog_topic.as_str())?;
+        Ok((executor, node, subscriptions, twist, joint_jog))
+    })();
+    let (mut executor, subscriptions) = match built {
+        Ok((executor, node, subscriptions, twist, joint_jog)) => {
+            let commands = executor.commands().clone();
+            if ready.send(Ok((node, commands, twist, joint_jog))).is_err() {
+                return;
+            }
+            (executor, subscriptions)
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err.into()));
+            return;
+        }
+    };
+    for err in executor.spin(SpinOptions::default()) {
+        tracing::warn!("ros2 executor: {err}");
+    }
+    drop(subscriptions);
+}
+
+/// Keeps a subscription alive without naming its message type.
+type AnySubscription = Box<dyn std::any::Any + Send>;
+
+fn joint_states(
+    app: &AppHandle,
+    node: &Node,
+    settings: &Ros2Settings,
+) -> std::result::Result<AnySubscription, RclrsError> {
+    let app = app.clone();
+    let min_interval = Duration::from_secs_f64(1.0 / settings.joint_state_max_hz.max(1.0));
+    let last = StdMutex::new(None::<Instant>);
+    let subscription = node.create_subscription(
+        settings.joint_states_topic.as_str(),
+        move |msg: JointState| {
+            joint_history::record(
+                &app,
+                "ros2",
+                JointReading {
+                    names: msg.name.clone(),
+                    position: msg.position.clone(),
+                    velocity: msg.velocity.clone(),
+                    effort: msg.effort.clone(),
+                },
+            );
+            let now = Instant::now();
+            {
+                let mut last = last.lock().unwrap();
+                if last.is_some_and(|last| now - last < min_interval) {
+                    return;
+                }
+                *last = Some(now);
+            }
+            let event = JointStateEvent {
+                stamp_ns: stamp_ns(&msg.header.stamp),
+                name: msg.name,
+                position: msg.position,
+                velocity: msg.velocity,
+                effort: msg.effort,
+            };
+            windows::emit(&app, "ros2-joint-state", event);
+        },
+    )?;
+    Ok(Box::new(subscription))
+}
+
+fn camera_feed(
+    app: &AppHandle,
+    node: &Node,
+    camera: &Ros2Camera,
+) -> std::result::Result<AnySubscription, RclrsError> {
+    let rings = app.state::<FrameRings>();
+    let writer = match RingWriter::create(&rings, &camera.name, RING_SLOTS, RING_CAPACITY) {
+        Ok(writer) => StdMutex::new(writer),
+        Err(err) => {
+            tracing::warn!(camera = %camera.name, "cannot create frame ring: {err}");
+            return Ok(Box::new(()));
+        }
+    };
+    let app = app.clone();
+    let name = camera.name.clone();
+    let topic = camera.topic.as_str().sensor_data_qos();
+    let publish = move |data: &[u8], fourcc, width, height, stamp: &Time| {
+        let mut writer = writer.lock().unwrap();
+        if data.len() > writer.capacity() {
+            // Grow the ring; the reader remaps it when the file changes.
+            let capacity = data.len().next_power_of_two();
+            match RingWriter::create(&app.state::<FrameRings>(), &name, RING_SLOTS, capacity) {
+                Ok(grown) => *writer = grown,
+                Err(err) => {
+                    tracing::warn!(camera = %name, "cannot grow frame ring: {err}");
+                    return;
+                }
+            }
+        }
+        let timestamp = stamp_ns(stamp).max(0) as u64;
+        if let Err(err) = writer.write(data, fourcc, width, height, timestamp) {
+            tracing::debug!(camera = %name, "dropped frame: {err}");
+        }
+    };
+
+    if camera.topic.ends_with("/compressed") {
+        let subscription = node.create_subscription(topic, move |msg: CompressedImage| {
+            let fourcc = if msg.format.contains("png") {
+                *b"PNG "
+            } else {
+                *b"MJPG"
+            };
+            publish(&msg.data, fourcc, 0, 0, &msg.header.stamp);
+        })?;
+        Ok(Box::new(subscription))
+    } else {
+        let subscription = node.create_subscription(topic, move |msg: Image| {
+            let Some((fourcc, bytes_per_pixel)) = raw_format(&msg.encoding) else {
+                publish(
+                    &msg.data,
+                    *b"RAW ",
+                    msg.width,
+                    msg.height,
+                    &msg.header.stamp,
+                );
+                return;
+            };
+            let row = msg.width as usize * bytes_per_pixel;
+            let step = msg.step as usize;
+            if step == row {
+                publish(&msg.data, fourcc, msg.width, msg.height, &msg.header.stamp);
+                return;
+            }
+            // Drop row padding; ring frames are tightly packed.
+            let packed: Vec<u8> = msg
+                .data
+                .chunks(step.max(1))
+                .take(msg.height as usize)
+                .flat_map(|line| &line[..row.min(line.len())])
+                .copied()
+                .collect();
+            publish(&packed, fourcc, msg.width, msg.height, &msg.header.stamp);
+        })?;
+        Ok(Box::new(subscription))
+    }
+}
+
+/// Ring fourcc and pixel size of a `sensor_msgs/Image` encoding.
+fn raw_format(encoding: &str) -> Option<([u8; 4], usize)> {
+    match encoding {
+        "rgb8" => Some((*b"RGB3", 3)),
+        "bgr8" => Some((*b"BGR3", 3)),
+        "rgba8" => Some((*b"AB24", 4)),
+        "bgra8" => Some((*b"AR24", 4)),
+        "mono8" => Some((*b"GREY", 1)),
+        "mono16" => Some((*b"Y16 ", 2)),
+        // Depth in millimetres, e.g. from a RealSense.
+        "16UC1" => Some((*b"Z16 ", 2)),
+        "yuv422" | "uyvy" => Some((*b"UYVY", 2)),
+        "yuv422_yuy2" | "yuyv" => Some((*b"YUYV", 2)),
+        _ => None,
+    }
+}
+
+fn stamp_ns(stamp: &Time) -> i64 {
+    i64::from(stamp.sec) * 1_000_000_000 + i64::from(stamp.nanosec)
+}
+
+fn header(node: &Node, frame_id: String) -> Header {
+    Header {
+        stamp: node.get_clock().now().to_ros_msg().unwrap_or_default(),
+        frame_id,
+    }
+}
@@ -0,0 +1,325 @@
+//! RTSP camera ingestion, re-served to the webview as MJPEG.
+//!
+//! Cameras are listed in the `rtsp` settings section. [`start_rtsp`] runs an
+//! `ffmpeg` child that pulls the camera and transcodes it to JPEG frames,
+//! restarting it with backoff whenever the camera drops. Frames are served on
+//! a loopback HTTP port as `multipart/x-mixed-replace`, so an `<img>` element
+//! can show the feed; `rtsp-status` reports connection changes.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::process::Stdio;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::Command;
+use tokio::sync::{watch, OnceCell};
+
+use crate::error::{Error, Result};
+use crate::settings::SettingsStore;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A connection delivering frames for this long resets the backoff.
+const STABLE_RUN: Duration = Duration::from_secs(30);
+/// Socket timeout passed to ffmpeg, in microseconds.
+const RTSP_TIMEOUT_US: &str = "5000000";
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const BOUNDARY: &str = "frame";
+
+/// One RTSP camera from the settings.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RtspCamera {
+    pub name: String,
+    pub url: String,
+    /// Interleave RTP over the RTSP connection; avoids UDP loss on busy networks.
+    #[serde(default = "default_tcp")]
+    pub tcp: bool,
+}
+
+fn default_tcp() -> bool {
+    true
+}
+
+/// Returned by [`start_rtsp`] and [`list_rtsp_streams`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RtspStream {
+    pub name: String,
+    /// Loopback MJPEG URL to use as an `<img>` source.
+    pub url: String,
+    pub connected: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RtspStatus {
+    name: String,
+    connected: bool,
+    error: Option<String>,
+}
+
+struct Feed {
+    frames: watch::Sender<Option<Bytes>>,
+    task: JoinHandle<()>,
+}
+
+/// Running feeds and the loopback server that re-serves them.
+#[derive(Default)]
+pub struct RtspStreams {
+    port: OnceCell<u16>,
+    feeds: StdMutex<HashMap<String, Feed>>,
+}
+
+impl RtspStreams {
+    fn describe(&self, name: &str, port: u16) -> Option<RtspStream> {
+        let feeds = self.feeds.lock().unwrap();
+        let connected = feeds.get(name)?.frames.borrow().is_some();
+        Some(RtspStream {
+            name: name.to_owned(),
+            url: format!("http://127.0.0.1:{port}/rtsp/{name}"),
+            connected,
+        })
+    }
+
+    fn subscribe(&self, name: &str) -> Option<watch::Receiver<Option<Bytes>>> {
+        let feeds = self.feeds.lock().unwrap();
+        Some(feeds.get(name)?.frames.subscribe())
+    }
+}
+
+/// Starts ingesting the configured camera `name`; a no-op if already running.
+#[tauri::command]
+pub async fn start_rtsp(
+    app: AppHandle,
+    streams: State<'_, RtspStreams>,
+    name: String,
+) -> Result<RtspStream> {
+    let settings = app.state::<SettingsStore>().get().rtsp;
+    let camera = settings
+        .cameras
+        .into_iter()
+        .find(|camera| camera.name == name)
+        .ok_or_else(|| Error::NotFound(format!("RTSP camera {name}")))?;
+    let port = streams
+        .port
+        .get_or_try_init(|| serve(app.clone()))
+        .await
+        .copied()?;
+
+    {
+        let mut feeds = streams.feeds.lock().unwrap();
+        if !feeds.contains_key(&name) {
+            let (frames, _) = watch::channel(None);
+            let task = tauri::async_runtime::spawn(ingest(
+                app.clone(),
+                camera,
+                settings.jpeg_quality,
+                frames.clone(),
+            ));
+            feeds.insert(name.clone(), Feed { frames, task });
+        }
+    }
+    Ok(streams
+        .describe(&name, port)
+        .expect("feed was just inserted"))
+}
+
+#[tauri::command]
+pub fn stop_rtsp(streams: State<'_, RtspStreams>, name: String) -> Result<()> {
+    let feed = streams
+        .feeds
+        .lock()
+        .unwrap()
+        .remove(&name)
+        .ok_or_else(|| Error::NotFound(format!("RTSP stream {name}")))?;
+    feed.task.abort();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_rtsp_streams(streams: State<'_, RtspStreams>) -> Vec<RtspStream> {
+    let Some(&port) = streams.port.get() else {
+        return Vec::new();
+    };
+    let names: Vec<String> = streams.feeds.lock().unwrap().keys().cloned().collect();
+    names
+        .iter()
+        .filter_map(|name| streams.describe(name, port))
+        .collect()
+}
+
+/// Keeps one camera connected, reconnecting with exponential backoff.
+async fn ingest(
+    app: AppHandle,
+    camera: RtspCamera,
+    quality: u8,
+    frames: watch::Sender<Option<Bytes>>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let started = Instant::now();
+        let error = match run_ffmpeg(&app, &camera, quality, &frames).await {
+            Ok(()) => "stream ended".to_owned(),
+            Err(err) => err.to_string(),
+        };
+        frames.send_replace(None);
+        if started.elapsed() >= STABLE_RUN {
+            backoff = INITIAL_BACKOFF;
+        }
+        tracing::warn!(camera = %camera.name, "RTSP disconnected: {error}; retrying in {backoff:?}");
+        let _ = app.emit(
+            "rtsp-status",
+            RtspStatus {
+                name: camera.name.clone(),
+                connected: false,
+                error: Some(error),
+            },
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn run_ffmpeg(
+    app: &AppHandle,
+    camera: &RtspCamera,
+    quality: u8,
+    frames: &watch::Sender<Option<Bytes>>,
+) -> Result<()> {
+    let ffmpeg = app.state::<SettingsStore>().get().video.ffmpeg;
+    let transport = if camera.tcp { "tcp" } else { "udp" };
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-fflags", "nobuffer"])
+        .args(["-rtsp_transport", transport, "-timeout", RTSP_TIMEOUT_US])
+        .args(["-i", &camera.url, "-an", "-c:v", "mjpeg"])
+        .args([
+            "-q:v",
+            &quality.clamp(2, 31).to_string(),
+            "-f",
+            "mjpeg",
+            "-",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+
+    let mut pending = BytesMut::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let read = stdout.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        while let Some(end) = pending.windows(2).position(|pair| pair == [0xFF, 0xD9]) {
+            let frame = pending.split_to(end + 2).freeze();
+            if !frame.starts_with(&[0xFF, 0xD8]) {
+                continue;
+            }
+            if frames.send_replace(Some(frame)).is_none() {
+                tracing::info!(camera = %camera.name, "RTSP connected");
+                let _ = app.emit(
+                    "rtsp-status",
+                    RtspStatus {
+                        name: camera.name.clone(),
+                        connected: true,
+                        error: None,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Binds the loopback MJPEG server and returns its port.
+async fn serve(app: AppHandle) -> Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    tracing::info!(port, "RTSP re-stream server listening");
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((socket, _)) => {
+                    tauri::async_runtime::spawn(handle_client(app.clone(), socket));
+                }
+                Err(err) => tracing::warn!("RTSP re-stream accept failed: {err}"),
+            }
+        }
+    });
+    Ok(port)
+}
+
+async fn handle_client(app: AppHandle, mut socket: TcpStream) {
+    let Some(path) = read_request_path(&mut socket).await else {
+        return;
+    };
+    let frames = path
+        .strip_prefix("/rtsp/")
+        .and_then(|name| app.state::<RtspStreams>().subscribe(name));
+    let Some(mut frames) = frames else {
+        let _ = socket
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+        return;
+    };
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\n\
+         Cache-Control: no-store\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n"
+    );
+    if socket.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    // Each client only ever sees the newest frame, so a slow one drops frames
+    // instead of building latency.
+    while frames.changed().await.is_ok() {
+        let Some(frame) = frames.borrow_and_update().clone() else {
+            continue;
+        };
+        let part = format!(
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            frame.len()
+        );
+        let written = async {
+            socket.write_all(part.as_bytes()).await?;
+            socket.write_all(&frame).await?;
+            socket.write_all(b"\r\n").await
+        };
+        if written.await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads the request head and returns the path of a `GET` request.
+async fn read_request_path(socket: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return None;
+        }
+        let read = socket.read(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next()?.split_whitespace();
+    if request_line.next()? != "GET" {
+        return None;
+    }
+    let path = request_line.next()?;
+    Some(path.split('?').next().unwrap_or(path).to_owned())
+}
@@ -0,0 +1,446 @@
+//! Keep-out and keep-in zones of the cell, checked against the robot's
+//! streamed positions.
+//!
+//! `safetyZones.zones` are boxes, spheres and vertical cylinders given in a
+//! frame of [`crate::tf`] (by default `tf.baseFrame`), or ranges of one
+//! joint's position. Every `1 / safetyZones.checkHz` the origin of each
+//! frame in `safetyZones.frames` (the tool and whichever links should stay
+//! clear) is looked up at the latest joint positions and checked against
+//! every shape: a point within `safetyZones.margin` of a keep-out zone, or
+//! closer than that to the boundary of a keep-in zone, violates it. Joint
+//! zones are checked against the joint's latest position, without margin.
+//! Checks pause while no joint positions arrived for `staleMs`.
+//!
+//! A violation is emitted as `zone-violation` when it starts and again with
+//! `active: false` once the position is back past the margin by
+//! [`CLEAR_HYSTERESIS`]. Zones with `stopRobot` also block jogging (see
+//! [`crate::jog::block`]) and send the watchdog's safe stop (see
+//! [`crate::watchdog::safe_stop`]) when violated, and block jogging again
+//! whenever the intrusion grows while the violation lasts. Both transitions
+//! are appended to `safety-zones.jsonl` under the app data directory for
+//! audit, which [`zone_violation_log`] reads back.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use nalgebra::{Point3, Vector2};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audit;
+use crate::error::Result;
+use crate::jog;
+use crate::notifications::{self, Action, Category, Notice};
+use crate::settings::{SafetyZoneSettings, SettingsStore};
+use crate::tf;
+use crate::time_sync;
+use crate::watchdog;
+
+const LOG_FILE: &str = "safety-zones.jsonl";
+/// Distance past the margin, in metres (or joint units), a position must
+/// be back by to clear its violation.
+const CLEAR_HYSTERESIS: f64 = 0.005;
+const DEFAULT_LOG_ENTRIES: usize = 200;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ZoneKind {
+    /// The robot must stay out.
+    #[default]
+    KeepOut,
+    /// The robot must stay inside.
+    KeepIn,
+}
+
+/// Lengths in metres; joint positions as their source reports them.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "camelCase")]
+pub enum ZoneShape {
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+    },
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+    },
+    /// Upright along the frame's z axis.
+    #[serde(rename_all = "camelCase")]
+    Cylinder {
+        center: [f64; 2],
+        radius: f64,
+        z_min: f64,
+        z_max: f64,
+    },
+    Joint {
+        joint: String,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl ZoneShape {
+    /// How far `point` is outside the shape, negative inside. Outside the
+    /// corners of boxes and cylinders this underestimates, which only errs
+    /// on the safe side.
+    fn distance(&self, point: &Point3<f64>) -> f64 {
+        match self {
+            ZoneShape::Box { min, max } => (0..3)
+                .map(|axis| (min[axis] - point[axis]).max(point[axis] - max[axis]))
+                .fold(f64::NEG_INFINITY, f64::max),
+            ZoneShape::Sphere { center, radius } => (point - Point3::from(*center)).norm() - radius,
+            ZoneShape::Cylinder {
+                center,
+                radius,
+                z_min,
+                z_max,
+            } => {
+                let radial = (Vector2::new(point.x, point.y) - Vector2::from(*center)).norm();
+                (radial - radius).max(z_min - point.z).max(point.z - z_max)
+            }
+            ZoneShape::Joint { min, max, .. } => (min - point.x).max(point.x - max),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyZone {
+    pub name: String,
+    #[serde(default)]
+    pub kind: ZoneKind,
+    #[serde(flatten)]
+    pub shape: ZoneShape,
+    /// Frame the shape is given in; `tf.baseFrame` if unset.
+    #[serde(default)]
+    pub frame: Option<String>,
+    /// Halt jogging and safe-stop the robot on a violation.
+    #[serde(default)]
+    pub stop_robot: bool,
+}
+
+/// Payload of `zone-violation`, an entry of the audit log.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneViolation {
+    pub zone: String,
+    pub kind: ZoneKind,
+    /// The frame, or the joint, that violated the zone.
+    pub subject: String,
+    /// `false` once the violation cleared.
+    pub active: bool,
+    /// In the zone's frame, or the joint position.
+    pub position: Vec<f64>,
+    /// Signed distance from the zone's boundary, negative inside.
+    pub distance: f64,
+    /// Whether the safe stop was sent.
+    pub stopped: bool,
+    /// Why the safe stop could not be delivered.
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Violations in progress, by zone and subject.
+#[derive(Default)]
+pub struct SafetyZones(StdMutex<HashMap<(String, String), Active>>);
+
+struct Active {
+    violation: ZoneViolation,
+    /// Whether the zone stops the robot.
+    stop: bool,
+    /// Smallest intrusion since the violation started.
+    least: f64,
+}
+
+pub fn init(app: &AppHandle) {
+    app.manage(SafetyZones::default());
+    tauri::async_runtime::spawn(monitor(app.clone()));
+}
+
+/// The violations in progress.
+#[tauri::command]
+pub fn safety_zone_status(zones: State<'_, SafetyZones>) -> Vec<ZoneViolation> {
+    let mut active: Vec<ZoneViolation> = zones
+        .0
+        .lock()
+        .unwrap()
+        .values()
+        .map(|active| active.violation.clone())
+        .collect();
+    active.sort_by_key(|violation| violation.timestamp);
+    active
+}
+
+/// The last `limit` (200 by default) entries of the audit log, oldest
+/// first.
+#[tauri::command]
+pub async fn zone_violation_log(
+    app: AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<ZoneViolation>> {
+    let path = log_path(&app)?;
+    let text = match tokio::fs::read_to_string(&path).await {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let entries: Vec<ZoneViolation> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let skip = entries
+        .len()
+        .saturating_sub(limit.unwrap_or(DEFAULT_LOG_ENTRIES));
+    Ok(entries.into_iter().skip(skip).collect())
+}
+
+fn log_path(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(LOG_FILE))
+}
+
+async fn monitor(app: AppHandle) {
+    loop {
+        let settings = app.state::<SettingsStore>().get();
+        let zones = settings.safety_zones;
+        let period = Duration::from_secs_f64(1.0 / zones.check_hz.clamp(1.0, 1000.0));
+        tokio::time::sleep(period).await;
+        if !zones.enabled || zones.zones.is_empty() {
+            continue;
+        }
+        let fresh = tf::last_update_ns(&app).is_some_and(|updated| {
+            time_sync::now_ns(&app) - updated
+                <= i64::try_from(zones.stale_ms).unwrap_or(i64::MAX / 1_000_000) * 1_000_000
+        });
+        if !fresh {
+            continue;
+        }
+        for zone in &zones.zones {
+            let frame = zone.frame.as_deref().unwrap_or(&settings.tf.base_frame);
+            match &zone.shape {
+                ZoneShape::Joint { joint, .. } => {
+                    if let Some((_, position)) = tf::joint_position(&app, joint) {
+                        let point = Point3::new(position, 0.0, 0.0);
+                        check(
+                            &app,
+                            &zones,
+                            zone,
+                            joint,
+                            vec![position],
+                            zone.shape.distance(&point),
+                            0.0,
+                        )
+                        .await;
+                    }
+                }
+                _ => {
+                    for subject in &zones.frames {
+                        let Ok(pose) = tf::latest(&app, frame, subject) else {
+                            continue;
+                        };
+                        let point = Point3::from(pose.translation.vector);
+                        let distance = zone.shape.distance(&point);
+                        let position = vec![point.x, point.y, point.z];
+                        check(
+                            &app,
+                            &zones,
+                            zone,
+                            subject,
+                            position,
+                            distance,
+                            zones.margin,
+                        )
+                        .await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Starts or clears the violation of `zone` by `subject`.
+async fn check(
+    app: &AppHandle,
+    settings: &SafetyZoneSettings,
+    zone: &SafetyZone,
+    subject: &str,
+    position: Vec<f64>,
+    distance: f64,
+    margin: f64,
+) {
+    // Distance into the forbidden side of the boundary plus the margin.
+    let intrusion = match zone.kind {
+        ZoneKind::KeepOut => margin - distance,
+        ZoneKind::KeepIn => distance + margin,
+    };
+    let key = (zone.name.clone(), subject.to_string());
+    let stop = zone.stop_robot || settings.stop_robot;
+    let (active, deeper) = match app.state::<SafetyZones>().0.lock().unwrap().get_mut(&key) {
+        Some(active) => {
+            let deeper = intrusion > active.least + CLEAR_HYSTERESIS;
+            active.least = active.least.min(intrusion);
+            (true, deeper)
+        }
+        None => (false, false),
+    };
+    if active && deeper && stop {
+        // Still moving into the zone: refuse that direction too.
+        tracing::warn!(zone = %zone.name, %subject, distance, "safety zone intrusion grew");
+        jog::block(app);
+    }
+    if !active && intrusion > 0.0 {
+        let mut violation = ZoneViolation {
+            zone: zone.name.clone(),
+            kind: zone.kind,
+            subject: subject.to_string(),
+            active: true,
+            position,
+            distance,
+            stopped: false,
+            error: None,
+            timestamp: Utc::now(),
+        };
+        tracing::error!(zone = %zone.name, %subject, distance, "safety zone violated");
+        if stop {
+            jog::block(app);
+            violation.stopped = true;
+            if let Err(err) = watchdog::safe_stop(app).await {
+                tracing::error!("safety zone stop failed: {err}");
+                violation.error = Some(err.to_string());
+            }
+        }
+        let entry = serde_json::json!({
+            "zone": zone.name,
+            "subject": subject,
+            "stopped": violation.stopped,
+            "error": violation.error,
+        });
+        audit::record(app, "safetyZoneViolation", entry);
+        notifications::notify(
+            app,
+            Notice::new(
+                Category::Safety,
+                "Safety zone violated",
+                format!("{subject} violated zone {}", zone.name),
+            )
+            .action(Action::ShowWindow),
+        );
+        app.state::<SafetyZones>().0.lock().unwrap().insert(
+            key,
+            Active {
+                violation: violation.clone(),
+                stop,
+                least: intrusion,
+            },
+        );
+        report(app, &violation);
+    } else if active && intrusion < -CLEAR_HYSTERESIS {
+        let (removed, stopping) = {
+            let zones = app.state::<SafetyZones>();
+            let mut violations = zones.0.lock().unwrap();
+            let removed = violations.remove(&key);
+            (removed, violations.values().any(|active| active.stop))
+        };
+        if !stopping {
+            jog::unblock(app);
+        }
+        if let Some(Active {
+            violation: started, ..
+        }) = removed
+        {
+            tracing::info!(zone = %zone.name, %subject, "safety zone violation cleared");
+            report(
+                app,
+                &ZoneViolation {
+                    active: false,
+                    position,
+                    distance,
+                    stopped: false,
+                    error: None,
+                    timestamp: Utc::now(),
+                    ..started
+                },
+            );
+        }
+    }
+}
+
+fn report(app: &AppHandle, violation: &ZoneViolation) {
+    let _ = app.emit("zone-violation", violation.clone());
+    let logged = log_path(app).and_then(|path| {
+        std::fs::create_dir_all(path.parent().expect("log path has a parent"))?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let mut line = serde_json::to_vec(violation)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    });
+    if let Err(err) = logged {
+        tracing::warn!("safety zone audit log not written: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn measures_boxes() {
+        let shape = ZoneShape::Box {
+            min: [0.0, 0.0, 0.0],
+            max: [1.0, 2.0, 3.0],
+        };
+        assert_near(shape.distance(&Point3::new(0.5, 1.0, 1.0)), -0.5);
+        assert_near(shape.distance(&Point3::new(1.5, 1.0, 1.0)), 0.5);
+        assert_near(shape.distance(&Point3::new(0.5, 1.0, 3.25)), 0.25);
+    }
+
+    #[test]
+    fn measures_spheres() {
+        let shape = ZoneShape::Sphere {
+            center: [1.0, 0.0, 0.0],
+            radius: 0.5,
+        };
+        assert_near(shape.distance(&Point3::new(1.0, 0.0, 0.0)), -0.5);
+        assert_near(shape.distance(&Point3::new(1.0, 2.0, 0.0)), 1.5);
+    }
+
+    #[test]
+    fn measures_cylinders() {
+        let shape = ZoneShape::Cylinder {
+            center: [0.0, 0.0],
+            radius: 1.0,
+            z_min: 0.0,
+            z_max: 2.0,
+        };
+        assert_near(shape.distance(&Point3::new(0.0, 0.0, 1.0)), -1.0);
+        assert_near(shape.distance(&Point3::new(3.0, 4.0, 1.0)), 4.0);
+        assert_near(shape.distance(&Point3::new(0.0, 0.5, 2.5)), 0.5);
+    }
+
+    #[test]
+    fn measures_joint_ranges() {
+        let shape = ZoneShape::Joint {
+            joint: "joint_1".into(),
+            min: -1.0,
+            max: 1.0,
+        };
+        assert_near(shape.distance(&Point3::new(0.25, 0.0, 0.0)), -0.75);
+        assert_near(shape.distance(&Point3::new(1.5, 0.0, 0.0)), 0.5);
+    }
+}
@@ -0,0 +1,487 @@
+//! Scheduled background jobs.
+//!
+//! `scheduler.jobs` in settings lists jobs with a cron-like `schedule` in
+//! local time: five fields, minute hour day-of-month month day-of-week,
+//! each `*`, a number, a range `a-b`, a list `a,b` or a step `*/n`/`a-b/n`;
+//! `@hourly`, `@daily` and `@weekly` are shorthands. A job is one of
+//!
+//! - `dataset-upload`: queues every dataset changed since the job last
+//!   succeeded with [`crate::uploads`];
+//! - `log-prune`: deletes log files older than `logRetentionDays`;
+//! - `update-check`: checks for an update and notifies when one is found,
+//!   or queues the check with [`crate::offline`] while offline;
+//! - `retention`: applies the enabled [`crate::retention`] rules.
+//!
+//! Runs missed while the app was closed are not caught up. [`list_jobs`]
+//! reports each job's next run and recent results, which are kept in
+//! `jobs.json` in the app data directory; [`run_job_now`] runs one at once.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Datelike, Duration as TimeDelta, Local, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::offline::{self, Operation};
+use crate::recording;
+use crate::retention;
+use crate::settings::SettingsStore;
+use crate::updater;
+use crate::uploads::{self, Uploads};
+
+const HISTORY_FILE: &str = "jobs.json";
+/// Results kept per job.
+const HISTORY_LEN: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+    DatasetUpload,
+    LogPrune,
+    UpdateCheck,
+    Retention,
+}
+
+/// One entry of `scheduler.jobs`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledJob {
+    pub id: String,
+    pub kind: JobKind,
+    pub schedule: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The jobs a fresh install runs; uploads wait for a bucket to be set up.
+pub fn default_jobs() -> Vec<ScheduledJob> {
+    let job = |id: &str, kind, schedule: &str, enabled| ScheduledJob {
+        id: id.into(),
+        kind,
+        schedule: schedule.into(),
+        enabled,
+    };
+    vec![
+        job("nightly-upload", JobKind::DatasetUpload, "0 2 * * *", false),
+        job("weekly-log-prune", JobKind::LogPrune, "0 3 * * 0", true),
+        job("update-check", JobKind::UpdateCheck, "17 */6 * * *", true),
+        job("retention", JobKind::Retention, "40 * * * *", true),
+    ]
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Trigger {
+    Schedule,
+    Manual,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRun {
+    started_at: DateTime<Utc>,
+    duration_ms: u64,
+    trigger: Trigger,
+    ok: bool,
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    #[serde(flatten)]
+    job: ScheduledJob,
+    next_run: Option<DateTime<Local>>,
+    /// Why `schedule` is not understood.
+    schedule_error: Option<String>,
+    running: bool,
+    /// Most recent first.
+    history: Vec<JobRun>,
+}
+
+pub struct Scheduler {
+    path: PathBuf,
+    history: StdMutex<BTreeMap<String, Vec<JobRun>>>,
+    running: StdMutex<HashSet<String>>,
+}
+
+/// Loads the run history and starts the scheduling loop.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_data_dir()?.join(HISTORY_FILE);
+    let history = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!("ignoring unreadable {HISTORY_FILE}: {err}");
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    };
+    app.manage(Scheduler {
+        path,
+        history: StdMutex::new(history),
+        running: StdMutex::default(),
+    });
+    tauri::async_runtime::spawn(run_schedule(app.clone()));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_jobs(app: AppHandle, scheduler: State<'_, Scheduler>) -> Vec<JobInfo> {
+    let jobs = app.state::<SettingsStore>().get().scheduler.jobs;
+    let history = scheduler.history.lock().unwrap();
+    let running = scheduler.running.lock().unwrap();
+    let now = Local::now();
+    jobs.into_iter()
+        .map(|job| {
+            let (next_run, schedule_error) = match Schedule::parse(&job.schedule) {
+                Ok(schedule) if job.enabled => (schedule.next_after(now), None),
+                Ok(_) => (None, None),
+                Err(err) => (None, Some(err)),
+            };
+            JobInfo {
+                next_run,
+                schedule_error,
+                running: running.contains(&job.id),
+                history: history.get(&job.id).cloned().unwrap_or_default(),
+                job,
+            }
+        })
+        .collect()
+}
+
+/// Runs job `id` now, whether or not it is enabled, and returns the result.
+#[tauri::command]
+pub async fn run_job_now(app: AppHandle, id: String) -> Result<JobRun> {
+    let job = app
+        .state::<SettingsStore>()
+        .get()
+        .scheduler
+        .jobs
+        .into_iter()
+        .find(|job| job.id == id)
+        .ok_or_else(|| Error::NotFound(format!("job `{id}`")))?;
+    run(&app, job, Trigger::Manual)
+        .await
+        .ok_or_else(|| Error::DeviceBusy(format!("job `{id}` is already running")))
+}
+
+async fn run_schedule(app: AppHandle) {
+    loop {
+        // Wake just after each minute starts.
+        let now = Local::now();
+        let wait = 60 - u64::from(now.second()) + 1;
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+        let minute = truncate(Local::now());
+        for job in app.state::<SettingsStore>().get().scheduler.jobs {
+            if !job.enabled {
+                continue;
+            }
+            match Schedule::parse(&job.schedule) {
+                Ok(schedule) if schedule.matches(&minute) => {
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move {
+                        run(&app, job, Trigger::Schedule).await;
+                    });
+                }
+                Ok(_) => {}
+                Err(err) if minute.minute() == 0 => {
+                    tracing::warn!(job = %job.id, "job not scheduled: {err}");
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// Runs `job` and records the result; `None` if it is already running.
+async fn run(app: &AppHandle, job: ScheduledJob, trigger: Trigger) -> Option<JobRun> {
+    let scheduler = app.state::<Scheduler>();
+    if !scheduler.running.lock().unwrap().insert(job.id.clone()) {
+        tracing::info!(job = %job.id, "job still running; skipped");
+        return None;
+    }
+    let started_at = Utc::now();
+    let started = Instant::now();
+    tracing::info!(job = %job.id, kind = ?job.kind, "job started");
+    let result = match job.kind {
+        JobKind::DatasetUpload => upload_datasets(app, &job.id).await,
+        JobKind::LogPrune => prune_logs(app).await,
+        JobKind::UpdateCheck => check_for_update(app).await,
+        JobKind::Retention => retention::prune(app).await,
+    };
+    let (ok, message) = match result {
+        Ok(message) => (true, message),
+        Err(err) => (false, err.to_string()),
+    };
+    if ok {
+        tracing::info!(job = %job.id, "job finished: {message}");
+    } else {
+        tracing::warn!(job = %job.id, "job failed: {message}");
+    }
+    let run = JobRun {
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        trigger,
+        ok,
+        message,
+    };
+    {
+        let mut history = scheduler.history.lock().unwrap();
+        let runs = history.entry(job.id.clone()).or_default();
+        runs.insert(0, run.clone());
+        runs.truncate(HISTORY_LEN);
+        let written = serde_json::to_vec_pretty(&*history)
+            .map_err(std::io::Error::from)
+            .and_then(|bytes| fsutil::write_atomic(&scheduler.path, &bytes));
+        if let Err(err) = written {
+            tracing::warn!("{HISTORY_FILE} not written: {err}");
+        }
+    }
+    scheduler.running.lock().unwrap().remove(&job.id);
+    let _ = app.emit("job-finished", &run);
+    Some(run)
+}
+
+async fn upload_datasets(app: &AppHandle, id: &str) -> Result<String> {
+    let since = app
+        .state::<Scheduler>()
+        .history
+        .lock()
+        .unwrap()
+        .get(id)
+        .and_then(|runs| runs.iter().find(|run| run.ok))
+        .map(|run| SystemTime::from(run.started_at));
+    let root = recording::datasets_root(app)?;
+    let changed = tauri::async_runtime::spawn_blocking(move || changed_datasets(&root, since))
+        .await
+        .map_err(|err| Error::Invalid(format!("dataset scan failed: {err}")))??;
+    if changed.is_empty() {
+        return Ok("no dataset changed".into());
+    }
+    let count = changed.len();
+    for dir in changed {
+        uploads::enqueue_upload(app.clone(), app.state::<Uploads>(), dir, None)?;
+    }
+    Ok(format!("queued {count} dataset(s)"))
+}
+
+/// Datasets under `root` with a file modified after `since`.
+fn changed_datasets(root: &Path, since: Option<SystemTime>) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut changed = Vec::new();
+    for entry in entries {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let modified = latest_modification(&dir)?;
+        if since.is_none_or(|since| modified.is_some_and(|modified| modified > since)) {
+            changed.push(dir);
+        }
+    }
+    changed.sort();
+    Ok(changed)
+}
+
+fn latest_modification(dir: &Path) -> std::io::Result<Option<SystemTime>> {
+    let mut latest = None;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let modified = if meta.is_dir() {
+            latest_modification(&entry.path())?
+        } else {
+            Some(meta.modified()?)
+        };
+        latest = latest.max(modified);
+    }
+    Ok(latest)
+}
+
+async fn prune_logs(app: &AppHandle) -> Result<String> {
+    let days = app
+        .state::<SettingsStore>()
+        .get()
+        .scheduler
+        .log_retention_days;
+    if days == 0 {
+        return Ok("log retention is unlimited".into());
+    }
+    let dir = app.path().app_log_dir()?;
+    let max_age = Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+    let (files, bytes) = tauri::async_runtime::spawn_blocking(move || prune(&dir, max_age))
+        .await
+        .map_err(|err| Error::Invalid(format!("log pruning failed: {err}")))??;
+    Ok(format!(
+        "removed {files} file(s), {:.1} MB",
+        bytes as f64 / 1_000_000.0
+    ))
+}
+
+/// Removes files under `dir` last modified more than `max_age` ago.
+fn prune(dir: &Path, max_age: Duration) -> std::io::Result<(usize, u64)> {
+    let (mut files, mut bytes) = (0, 0);
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            let (more_files, more_bytes) = prune(&entry.path(), max_age)?;
+            files += more_files;
+            bytes += more_bytes;
+        } else if meta.modified()?.elapsed().is_ok_and(|age| age > max_age) {
+            std::fs::remove_file(entry.path())?;
+            files += 1;
+            bytes += meta.len();
+        }
+    }
+    Ok((files, bytes))
+}
+
+async fn check_for_update(app: &AppHandle) -> Result<String> {
+    if !offline::is_online(app) {
+        offline::enqueue(app, Operation::UpdateCheck);
+        return Ok("offline; check queued until the connection is back".into());
+    }
+    Ok(match updater::check(app).await? {
+        Some(info) => {
+            updater::announce(app, &info);
+            let message = format!("update {} available", info.version);
+            let _ = app.emit("update-available", info);
+            message
+        }
+        None => "up to date".into(),
+    })
+}
+
+fn truncate(time: DateTime<Local>) -> DateTime<Local> {
+    time.with_second(0)
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(time)
+}
+
+/// A parsed `schedule`; each field is a bit set of the values it matches.
+struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month and day of week were both restricted, in which
+    /// case either matching is enough, as in cron.
+    either_day: bool,
+}
+
+impl Schedule {
+    fn parse(schedule: &str) -> std::result::Result<Self, String> {
+        let expanded = match schedule.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("`{schedule}` does not have five fields"));
+        };
+        let mut weekdays = field(weekday, 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+
+    fn matches(&self, time: &DateTime<Local>) -> bool {
+        let bit = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        let day = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+            && day
+    }
+
+    /// First matching minute after `time`, within a year.
+    fn next_after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut next = truncate(time) + TimeDelta::minutes(1);
+        let end = time + TimeDelta::days(366);
+        while next < end {
+            if self.matches(&next) {
+                return Some(next);
+            }
+            next += TimeDelta::minutes(1);
+        }
+        None
+    }
+}
+
+/// Bit set of the values `field` matches within `min..=max`.
+fn field(field: &str, min: u32, max: u32) -> std::result::Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+        let number = |text: &str| {
+            text.parse::<u32>()
+                .ok()
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| format!("`{text}` is not between {min} and {max}"))
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None => {
+                    let value = number(range)?;
+                    (value, if step.is_some() { max } else { value })
+                }
+            },
+        };
+        let step = match step {
+            Some(step) => step
+                .parse::<u32>()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(|| format!("`{step}` is not a step"))?,
+            None => 1,
+        };
+        if start > end {
+            return Err(format!("`{range}` is an empty range"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
@@ -0,0 +1,284 @@
+//! Video of the operator UI, for reviewing incidents next to the telemetry.
+//!
+//! [`start_screen_capture`] has ffmpeg grab the area of an app window
+//! (`x11grab` on Linux, `gdigrab` on Windows, `avfoundation` on macOS) while
+//! an episode records, into `screen/episode_<index>.mp4` under the episode's
+//! dataset. The area is taken when the capture starts and does not follow
+//! the window if it moves. Wayland sessions cannot be captured.
+//!
+//! `episode_<index>.json` beside the video holds [`time_sync::now_ns`] at
+//! the start and end of the capture and at the episode's first row, the
+//! clock the recorder and telemetry share, so the video can be laid over
+//! both. [`stop_screen_capture`] ends the capture; saving or discarding the
+//! episode also ends it, discarding it with the episode.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+
+use crate::encoding::{self, Codec};
+use crate::error::{Error, Result};
+use crate::recording::{self, Recorder};
+use crate::settings::{ScreenCaptureSettings, SettingsStore};
+use crate::time_sync;
+
+/// How long ffmpeg gets to finish the file after being asked to quit.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenCaptureInfo {
+    pub dataset: String,
+    pub episode_index: usize,
+    pub window: String,
+    pub path: PathBuf,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+    /// [`time_sync::now_ns`] when ffmpeg was started.
+    pub start_ns: i64,
+    /// Set once the capture has ended.
+    pub end_ns: Option<i64>,
+    /// [`time_sync::now_ns`] at the episode's first row.
+    pub episode_start_ns: Option<i64>,
+}
+
+struct Active {
+    info: ScreenCaptureInfo,
+    child: Child,
+}
+
+/// The capture running, if any.
+#[derive(Default)]
+pub struct ScreenCapture(StdMutex<Option<Active>>);
+
+/// Starts capturing `window` (default `main`) for the episode being
+/// recorded.
+#[tauri::command]
+pub async fn start_screen_capture(
+    app: AppHandle,
+    capture: State<'_, ScreenCapture>,
+    window: Option<String>,
+) -> Result<ScreenCaptureInfo> {
+    let settings = app.state::<SettingsStore>().get();
+    // Before the busy check, so no await separates it from taking the slot.
+    let ffmpeg = settings.video.ffmpeg.clone();
+    let encoder = encoding::pick(&app, &ffmpeg, Codec::H264).await;
+    if capture.0.lock().unwrap().is_some() {
+        return Err(Error::DeviceBusy(
+            "the screen is already being captured".into(),
+        ));
+    }
+    let recorder = app.state::<Recorder>();
+    let episode = recorder
+        .recording_episode()
+        .ok_or_else(|| Error::Invalid("no episode is recording".into()))?;
+    let label = window.unwrap_or_else(|| "main".into());
+    let target = app
+        .get_webview_window(&label)
+        .ok_or_else(|| Error::NotFound(format!("window {label}")))?;
+    let position = target.outer_position()?;
+    let size = target.outer_size()?;
+    // Even sizes, as yuv420p needs.
+    let (width, height) = (size.width & !1, size.height & !1);
+    if width == 0 || height == 0 {
+        return Err(Error::Invalid(format!("window {label} is not visible")));
+    }
+
+    let fps = settings.screen_capture.fps.max(1);
+    let path = video_path(&episode.path, episode.episode_index);
+    std::fs::create_dir_all(path.parent().expect("video path has a parent"))?;
+    let area = Area {
+        x: position.x,
+        y: position.y,
+        width,
+        height,
+    };
+    let child = Command::new(&ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(input_args(&settings.screen_capture, fps, &area)?)
+        .args(["-c:v", &encoder, "-pix_fmt", encoding::pix_fmt(&encoder)])
+        .args(encoding::quality_args(&encoder, settings.recording.crf))
+        .args(recording::FRAGMENTED_MP4)
+        .arg(&path)
+        // ffmpeg stops cleanly on `q`.
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let info = ScreenCaptureInfo {
+        dataset: episode.dataset,
+        episode_index: episode.episode_index,
+        window: label,
+        path,
+        fps,
+        width,
+        height,
+        start_ns: time_sync::now_ns(&app),
+        end_ns: None,
+        episode_start_ns: recorder.episode_start_ns(),
+    };
+    write_meta(&info)?;
+    tracing::info!(
+        dataset = %info.dataset,
+        episode = info.episode_index,
+        window = %info.window,
+        "screen capture started"
+    );
+    *capture.0.lock().unwrap() = Some(Active {
+        info: info.clone(),
+        child,
+    });
+    Ok(info)
+}
+
+/// Ends the capture and returns where it was saved.
+#[tauri::command]
+pub async fn stop_screen_capture(
+    app: AppHandle,
+    capture: State<'_, ScreenCapture>,
+) -> Result<ScreenCaptureInfo> {
+    let active = capture
+        .0
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::Invalid("the screen is not being captured".into()))?;
+    finish(&app, active).await
+}
+
+/// Ends a capture running for the episode being saved or, with `discard`,
+/// deleted.
+pub async fn end(app: &AppHandle, discard: bool) {
+    let Some(mut active) = app.state::<ScreenCapture>().0.lock().unwrap().take() else {
+        return;
+    };
+    if discard {
+        let _ = active.child.kill().await;
+        let _ = std::fs::remove_file(&active.info.path);
+        let _ = std::fs::remove_file(active.info.path.with_extension("json"));
+        return;
+    }
+    if let Err(err) = finish(app, active).await {
+        tracing::warn!("screen capture of the episode lost: {err}");
+    }
+}
+
+async fn finish(app: &AppHandle, mut active: Active) -> Result<ScreenCaptureInfo> {
+    let mut info = active.info;
+    info.end_ns = Some(time_sync::now_ns(app));
+    if info.episode_start_ns.is_none() {
+        info.episode_start_ns = app.state::<Recorder>().episode_start_ns();
+    }
+    if let Some(mut stdin) = active.child.stdin.take() {
+        let _ = stdin.write_all(b"q").await;
+    }
+    let status = match tokio::time::timeout(FINISH_TIMEOUT, active.child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            tracing::warn!("ffmpeg did not finish the screen capture; killing it");
+            let _ = active.child.kill().await;
+            active.child.wait().await?
+        }
+    };
+    write_meta(&info)?;
+    // Killed or not, the fragmented file plays up to its last fragment.
+    if !status.success() && !info.path.exists() {
+        return Err(Error::Stream(format!(
+            "ffmpeg exited with {status} capturing window {}",
+            info.window
+        )));
+    }
+    tracing::info!(path = %info.path.display(), "screen capture saved");
+    Ok(info)
+}
+
+fn video_path(dataset_dir: &Path, episode: usize) -> PathBuf {
+    dataset_dir
+        .join("screen")
+        .join(format!("episode_{episode:06}.mp4"))
+}
+
+fn write_meta(info: &ScreenCaptureInfo) -> Result<()> {
+    std::fs::write(
+        info.path.with_extension("json"),
+        serde_json::to_vec_pretty(info)?,
+    )?;
+    Ok(())
+}
+
+/// Screen area in physical pixels.
+struct Area {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+#[cfg(target_os = "linux")]
+fn input_args(settings: &ScreenCaptureSettings, fps: u32, area: &Area) -> Result<Vec<String>> {
+    let display = std::env::var("DISPLAY")
+        .map_err(|_| Error::Unsupported("screen capture needs an X11 display".into()))?;
+    Ok(vec![
+        "-f".into(),
+        "x11grab".into(),
+        "-draw_mouse".into(),
+        u8::from(settings.draw_cursor).to_string(),
+        "-framerate".into(),
+        fps.to_string(),
+        "-video_size".into(),
+        format!("{}x{}", area.width, area.height),
+        "-i".into(),
+        format!("{display}+{},{}", area.x.max(0), area.y.max(0)),
+    ])
+}
+
+#[cfg(windows)]
+fn input_args(settings: &ScreenCaptureSettings, fps: u32, area: &Area) -> Result<Vec<String>> {
+    Ok(vec![
+        "-f".into(),
+        "gdigrab".into(),
+        "-draw_mouse".into(),
+        u8::from(settings.draw_cursor).to_string(),
+        "-framerate".into(),
+        fps.to_string(),
+        "-offset_x".into(),
+        area.x.to_string(),
+        "-offset_y".into(),
+        area.y.to_string(),
+        "-video_size".into(),
+        format!("{}x{}", area.width, area.height),
+        "-i".into(),
+        "desktop".into(),
+    ])
+}
+
+/// avfoundation grabs the whole main screen, so the window is cropped out.
+#[cfg(target_os = "macos")]
+fn input_args(settings: &ScreenCaptureSettings, fps: u32, area: &Area) -> Result<Vec<String>> {
+    Ok(vec![
+        "-f".into(),
+        "avfoundation".into(),
+        "-capture_cursor".into(),
+        u8::from(settings.draw_cursor).to_string(),
+        "-framerate".into(),
+        fps.to_string(),
+        "-i".into(),
+        "Capture screen 0:none".into(),
+        "-vf".into(),
+        format!(
+            "crop={}:{}:{}:{}",
+            area.width,
+            area.height,
+            area.x.max(0),
+            area.y.max(0)
+        ),
+    ])
+}
@@ -0,0 +1,151 @@
+//! Credentials kept in the OS keychain (macOS Keychain, Windows Credential
+//! Manager, Secret Service on Linux) instead of plaintext settings.
+//!
+//! Secrets are stored under the app identifier as service, one entry per
+//! name. The backend receives them as environment variables listed in
+//! `backend.secretEnv` (variable → secret name), resolved at every spawn.
+//! On first launch after an upgrade, `backend.extraEnv` entries that look
+//! like credentials are moved into the keychain and replaced by
+//! `secretEnv` references; see [`migrate_backend_env`].
+
+use std::collections::BTreeMap;
+
+use tauri::AppHandle;
+
+use crate::audit;
+use crate::auth;
+use crate::error::{Error, Result};
+use crate::kiosk;
+use crate::settings::BackendSettings;
+use crate::users::{self, Capability};
+
+const SERVICE: &str = "ai.percus.desktop";
+/// Variable name fragments that mark an `extraEnv` entry as a credential.
+const CREDENTIAL_MARKERS: [&str; 5] = ["TOKEN", "PASSWORD", "SECRET", "API_KEY", "ACCESS_KEY"];
+/// Secrets the commands refuse to touch.
+const SHELL_ONLY: [&str; 3] = [kiosk::PIN_SECRET, audit::KEY_SECRET, auth::REFRESH_SECRET];
+/// Prefix of the secrets migrated out of `backend.extraEnv`.
+const BACKEND_ENV_PREFIX: &str = "backend-env/";
+
+/// The secret stored as `name`, if any.
+pub fn get(name: &str) -> Result<Option<String>> {
+    match entry(name)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn set(name: &str, value: &str) -> Result<()> {
+    entry(name)?.set_password(value)?;
+    Ok(())
+}
+
+/// Removes `name`; removing a missing secret is not an error.
+pub fn delete(name: &str) -> Result<()> {
+    match entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[tauri::command]
+pub async fn set_secret(app: AppHandle, name: String, value: String) -> Result<()> {
+    users::require(&app, Capability::EditSettings)?;
+    shell_only(&name)?;
+    blocking(move || set(&name, &value)).await
+}
+
+#[tauri::command]
+pub async fn get_secret(name: String) -> Result<Option<String>> {
+    shell_only(&name)?;
+    blocking(move || get(&name)).await
+}
+
+#[tauri::command]
+pub async fn delete_secret(app: AppHandle, name: String) -> Result<()> {
+    users::require(&app, Capability::EditSettings)?;
+    shell_only(&name)?;
+    blocking(move || delete(&name)).await
+}
+
+/// Environment for the backend from `backend.secretEnv`; secrets that are
+/// missing or unreadable are left out and logged.
+pub fn backend_env(secret_env: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    secret_env
+        .iter()
+        .filter_map(|(variable, name)| match get(name) {
+            Ok(Some(value)) => Some((variable.clone(), value)),
+            Ok(None) => {
+                tracing::warn!(%variable, secret = %name, "secret for backend not in keychain");
+                None
+            }
+            Err(err) => {
+                tracing::warn!(%variable, secret = %name, "cannot read secret for backend: {err}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Moves credential-like `extraEnv` entries into the keychain; returns
+/// whether `backend` changed. Entries the keychain refuses stay in place.
+pub fn migrate_backend_env(backend: &mut BackendSettings) -> bool {
+    let credentials: Vec<String> = backend
+        .extra_env
+        .keys()
+        .filter(|variable| {
+            let upper = variable.to_ascii_uppercase();
+            CREDENTIAL_MARKERS
+                .iter()
+                .any(|marker| upper.contains(marker))
+        })
+        .cloned()
+        .collect();
+    let mut changed = false;
+    for variable in credentials {
+        let name = format!("{BACKEND_ENV_PREFIX}{variable}");
+        match set(&name, &backend.extra_env[&variable]) {
+            Ok(()) => {
+                tracing::info!(%variable, "moved backend credential into the keychain");
+                backend.extra_env.remove(&variable);
+                backend.secret_env.insert(variable, name);
+                changed = true;
+            }
+            Err(err) => {
+                tracing::warn!(%variable, "backend credential left in settings: {err}");
+            }
+        }
+    }
+    changed
+}
+
+/// Refuses secrets that only the shell itself may read or change.
+fn shell_only(name: &str) -> Result<()> {
+    if SHELL_ONLY.contains(&name) || name.starts_with(users::PIN_PREFIX) {
+        return Err(Error::Invalid(format!(
+            "secret `{name}` is managed by the shell"
+        )));
+    }
+    Ok(())
+}
+
+fn entry(name: &str) -> Result<keyring::Entry> {
+    let valid = !name.is_empty()
+        && name.len() <= 128
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+    if !valid {
+        return Err(Error::Invalid(format!("secret name `{name}`")));
+    }
+    Ok(keyring::Entry::new(SERVICE, name)?)
+}
+
+/// Runs a keychain call off the async runtime; platform stores may block on
+/// IPC or an unlock prompt.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?
+}
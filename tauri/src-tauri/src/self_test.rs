@@ -0,0 +1,473 @@
+//! Commissioning self-test of the cell.
+//!
+//! [`run_self_test`] exercises each subsystem, all at once:
+//!
+//! - `backend`: the sidecar is running and answers `/health`, waiting up to
+//!   [`SPAWN_TIMEOUT`] for it to start
+//! - `robot`: a status request to the controller, or TCP to it while not
+//!   connected
+//! - `camera`: a test capture from every camera not in use elsewhere
+//! - `disk`: write speed of `selfTest.diskTestMb` to the recording root,
+//!   against `selfTest.minWriteMbPerSec`
+//! - `gpu`: NVIDIA GPUs visible to the driver
+//! - `clock`: a round of [`crate::time_sync`], against `timeSync.maxSkewMs`
+//!
+//! Each check ends `pass`, `warn`, `fail` or `skipped`, as in
+//! [`crate::preflight`], with a hint at the remedy for problems. With
+//! `selfTest.runAtStartup` the test runs after launch, emits
+//! `self-test-finished` and notifies when something failed. The last report
+//! is kept for diagnostic bundles.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::net::TcpStream;
+
+use crate::backend_tls;
+use crate::camera;
+use crate::daihen_fd::FdController;
+use crate::notifications::{self, Action, Category, Notice};
+use crate::preflight::CheckStatus;
+use crate::recording;
+use crate::settings::SettingsStore;
+use crate::sidecar::SidecarState;
+use crate::sysmon;
+use crate::time_sync;
+use crate::tunnels;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a backend that is still starting is waited for.
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MB: f64 = 1024.0 * 1024.0;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestCheck {
+    /// `backend`, `robot`, `camera`, `disk`, `gpu` or `clock`.
+    pub subsystem: &'static str,
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    pub hint: Option<String>,
+    pub duration_ms: f64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: f64,
+    /// The worst status of the checks.
+    pub status: CheckStatus,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+/// The most recent report, if a self-test has run.
+#[derive(Default)]
+pub struct LastSelfTest(StdMutex<Option<SelfTestReport>>);
+
+impl LastSelfTest {
+    pub fn get(&self) -> Option<SelfTestReport> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl SelfTestCheck {
+    fn new(subsystem: &'static str, name: impl Into<String>) -> Self {
+        Self {
+            subsystem,
+            name: name.into(),
+            status: CheckStatus::Pass,
+            message: String::new(),
+            hint: None,
+            duration_ms: 0.0,
+        }
+    }
+
+    fn with(mut self, status: CheckStatus, message: impl Into<String>) -> Self {
+        self.status = status;
+        self.message = message.into();
+        self
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    fn timed(mut self, started: Instant) -> Self {
+        self.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+        self
+    }
+}
+
+/// Runs the self-test after launch when `selfTest.runAtStartup` is set.
+pub fn init(app: &AppHandle) {
+    app.manage(LastSelfTest::default());
+    if !app.state::<SettingsStore>().get().self_test.run_at_startup {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let report = run_self_test(app.clone()).await;
+        let _ = app.emit("self-test-finished", &report);
+        let failed: Vec<&str> = report
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .map(|check| check.name.as_str())
+            .collect();
+        if !failed.is_empty() {
+            notifications::notify(
+                &app,
+                Notice::new(
+                    Category::Backend,
+                    "Self-test failed",
+                    format!("Failed: {}", failed.join(", ")),
+                )
+                .action(Action::ShowWindow),
+            );
+        }
+    });
+}
+
+/// Runs every check and returns the report.
+#[tauri::command]
+pub async fn run_self_test(app: AppHandle) -> SelfTestReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let (backend, robot, cameras, disk, gpu, clock) = tokio::join!(
+        backend(&app),
+        robot(&app),
+        cameras(),
+        disk(&app),
+        gpu(),
+        clock(&app),
+    );
+    let mut checks = vec![backend, robot];
+    checks.extend(cameras);
+    checks.extend([disk, gpu, clock]);
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let failed = count(CheckStatus::Fail);
+    let status = if failed > 0 {
+        CheckStatus::Fail
+    } else if count(CheckStatus::Warn) > 0 {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    tracing::info!(checks = checks.len(), failed, "self-test finished");
+    let report = SelfTestReport {
+        started_at,
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        status,
+        checks,
+    };
+    *app.state::<LastSelfTest>().0.lock().unwrap() = Some(report.clone());
+    report
+}
+
+async fn backend(app: &AppHandle) -> SelfTestCheck {
+    let started = Instant::now();
+    let check = SelfTestCheck::new("backend", "Backend");
+    let state = app.state::<SidecarState>();
+    while !state.status().running && started.elapsed() < SPAWN_TIMEOUT {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    let status = state.status();
+    if let Some(integrity) = status
+        .integrity
+        .as_ref()
+        .filter(|integrity| integrity.state == "mismatch" || integrity.state == "missing")
+    {
+        return check
+            .with(
+                CheckStatus::Fail,
+                format!("backend binary {}", integrity.state),
+            )
+            .hint("Reinstall the app; the bundled backend was changed or removed.")
+            .timed(started);
+    }
+    if !status.running {
+        let exit = status
+            .last_exit_code
+            .map_or_else(String::new, |code| format!("; last exit code {code}"));
+        return check
+            .with(CheckStatus::Fail, format!("not running{exit}"))
+            .hint("Open the backend log from the diagnostics page; a missing Python runtime or a port held by another program usually keeps it from starting.")
+            .timed(started);
+    }
+    // A backend that just started takes a while to answer.
+    let url = backend_tls::url(app, "https", "/health");
+    let client = backend_tls::http_client(app);
+    let response = loop {
+        let response = client.get(&url).timeout(TIMEOUT).send().await;
+        let healthy = matches!(&response, Ok(response) if response.status().is_success());
+        if healthy || started.elapsed() >= SPAWN_TIMEOUT {
+            break response;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    };
+    let check = match response {
+        Ok(response) if response.status().is_success() => check.with(
+            CheckStatus::Pass,
+            format!("running, {} restart(s)", status.restart_count),
+        ),
+        Ok(response) => check
+            .with(
+                CheckStatus::Fail,
+                format!("/health answered {}", response.status()),
+            )
+            .hint("The backend is running but unhealthy; restart it and check its log."),
+        Err(err) => check
+            .with(CheckStatus::Fail, format!("/health: {err}"))
+            .hint(
+            "Run the network preflight; endpoint security software may block loopback requests.",
+        ),
+    };
+    check.timed(started)
+}
+
+async fn robot(app: &AppHandle) -> SelfTestCheck {
+    let started = Instant::now();
+    let settings = app.state::<SettingsStore>().get();
+    let check = SelfTestCheck::new("robot", "Robot controller");
+    let Some(host) = settings.robot_host() else {
+        return check.with(CheckStatus::Skipped, "robot.host is not set");
+    };
+    let controller = app.state::<FdController>();
+    if controller.is_connected() {
+        let check = match tokio::time::timeout(TIMEOUT, controller.status()).await {
+            Ok(Ok(status)) => match status.alarm {
+                Some(alarm) => check
+                    .with(CheckStatus::Warn, format!("connected; alarm {alarm}"))
+                    .hint("Clear the alarm on the teach pendant."),
+                None => check.with(
+                    CheckStatus::Pass,
+                    format!(
+                        "connected; {}",
+                        status.mode.as_deref().unwrap_or("mode unknown")
+                    ),
+                ),
+            },
+            Ok(Err(err)) => check
+                .with(CheckStatus::Fail, err.to_string())
+                .hint("Reconnect to the controller; it stopped answering status requests."),
+            Err(_) => check
+                .with(CheckStatus::Fail, "no answer to a status request")
+                .hint("Reconnect to the controller; it stopped answering status requests."),
+        };
+        return check.timed(started);
+    }
+    let (host, port) = tunnels::resolve(app, &host, settings.robot.port);
+    let check = match tokio::time::timeout(TIMEOUT, TcpStream::connect((host.as_str(), port))).await {
+        Ok(Ok(_)) => check
+            .with(CheckStatus::Warn, format!("{host}:{port} reachable, not connected"))
+            .hint("Connect to the robot to use it."),
+        Ok(Err(err)) => check
+            .with(CheckStatus::Fail, format!("{host}:{port}: {err}"))
+            .hint("Check the Ethernet cable, the controller's IP settings and robot.host, and that the controller's external communication is enabled."),
+        Err(_) => check
+            .with(CheckStatus::Fail, format!("{host}:{port} does not answer"))
+            .hint("Check the Ethernet cable, the controller's IP settings and robot.host; a firewall may drop the traffic."),
+    };
+    check.timed(started)
+}
+
+async fn cameras() -> Vec<SelfTestCheck> {
+    let started = Instant::now();
+    let cameras = match camera::list_cameras().await {
+        Ok(cameras) => cameras,
+        Err(err) => {
+            return vec![SelfTestCheck::new("camera", "Cameras")
+                .with(CheckStatus::Fail, err.to_string())
+                .hint("On Linux, add the user to the `video` group and log in again.")
+                .timed(started)]
+        }
+    };
+    if cameras.is_empty() {
+        return vec![SelfTestCheck::new("camera", "Cameras")
+            .with(CheckStatus::Warn, "no camera found")
+            .hint("Plug the cameras into USB 3 ports; hubs without their own power may not supply them.")
+            .timed(started)];
+    }
+    let mut checks = Vec::new();
+    for info in cameras {
+        let started = Instant::now();
+        let check = SelfTestCheck::new("camera", info.name.clone());
+        if info.busy == Some(true) {
+            checks.push(
+                check
+                    .with(CheckStatus::Warn, format!("{} is in use", info.id))
+                    .hint("Close the program using the camera, or stop the backend's stream, and run the self-test again.")
+                    .timed(started),
+            );
+            continue;
+        }
+        let check = match camera::open_camera(info.id.clone(), None).await {
+            Ok(probe) => check.with(
+                CheckStatus::Pass,
+                format!(
+                    "{} {}x{}, first frame after {} ms",
+                    probe.fourcc, probe.width, probe.height, probe.first_frame_ms
+                ),
+            ),
+            Err(err) => check
+                .with(CheckStatus::Fail, format!("{}: {err}", info.id))
+                .hint("Replug the camera; if it keeps failing, try another port or cable."),
+        };
+        checks.push(check.timed(started));
+    }
+    checks
+}
+
+async fn disk(app: &AppHandle) -> SelfTestCheck {
+    let started = Instant::now();
+    let settings = app.state::<SettingsStore>().get().self_test;
+    let check = SelfTestCheck::new("disk", "Recording disk write speed");
+    let root = match recording::datasets_root(app) {
+        Ok(root) => root,
+        Err(err) => return check.with(CheckStatus::Fail, err.to_string()),
+    };
+    let bytes = settings.disk_test_mb.max(1) * 1024 * 1024;
+    let written = tauri::async_runtime::spawn_blocking(move || write_speed(&root, bytes)).await;
+    let check = match written {
+        Ok(Ok(elapsed)) => {
+            let rate = bytes as f64 / MB / elapsed.as_secs_f64().max(f64::EPSILON);
+            let message = format!("{rate:.0} MB/s");
+            if rate < settings.min_write_mb_per_sec {
+                check
+                    .with(CheckStatus::Warn, message)
+                    .hint("Record to a local SSD; network shares and USB 2 drives cannot keep up with several cameras.")
+            } else {
+                check.with(CheckStatus::Pass, message)
+            }
+        }
+        Ok(Err(err)) => check
+            .with(CheckStatus::Fail, err.to_string())
+            .hint("Set recording.root to a writable folder with free space."),
+        Err(err) => check.with(CheckStatus::Fail, err.to_string()),
+    };
+    check.timed(started)
+}
+
+/// Time to write and flush `bytes` to a scratch file in `dir`.
+fn write_speed(dir: &Path, bytes: u64) -> std::io::Result<Duration> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(".self-test-{}.tmp", std::process::id()));
+    let chunk = vec![0x5a_u8; 1024 * 1024];
+    let started = Instant::now();
+    let result = std::fs::File::create(&path).and_then(|mut file| {
+        let mut left = bytes;
+        while left > 0 {
+            let len = left.min(chunk.len() as u64) as usize;
+            file.write_all(&chunk[..len])?;
+            left -= len as u64;
+        }
+        file.sync_all()
+    });
+    let elapsed = started.elapsed();
+    let _ = std::fs::remove_file(&path);
+    result.map(|()| elapsed)
+}
+
+async fn gpu() -> SelfTestCheck {
+    let started = Instant::now();
+    let check = SelfTestCheck::new("gpu", "GPU");
+    let gpus = tauri::async_runtime::spawn_blocking(|| {
+        Nvml::init()
+            .map(|nvml| sysmon::gpu_metrics(&nvml))
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())
+    .and_then(|gpus| gpus);
+    let check = match gpus {
+        Ok(gpus) if !gpus.is_empty() => {
+            let names: Vec<String> = gpus
+                .iter()
+                .map(|gpu| {
+                    format!(
+                        "{} ({:.0} GB)",
+                        gpu.name,
+                        gpu.memory_total_bytes as f64 / (MB * 1024.0)
+                    )
+                })
+                .collect();
+            check.with(CheckStatus::Pass, names.join(", "))
+        }
+        Ok(_) => check
+            .with(CheckStatus::Warn, "the NVIDIA driver reports no GPU")
+            .hint("Policies will run on the CPU; check that the GPU is seated and powered."),
+        Err(err) => check
+            .with(CheckStatus::Warn, format!("no NVIDIA driver: {err}"))
+            .hint("Install the NVIDIA driver to run policies and encode video on the GPU."),
+    };
+    check.timed(started)
+}
+
+async fn clock(app: &AppHandle) -> SelfTestCheck {
+    let started = Instant::now();
+    let settings = app.state::<SettingsStore>().get().time_sync;
+    let check = SelfTestCheck::new("clock", "Clock sync");
+    if !settings.enabled {
+        return check.with(CheckStatus::Skipped, "timeSync.enabled is off");
+    }
+    let status = match time_sync::sync_clocks(app.clone()).await {
+        Ok(status) => status,
+        Err(err) => {
+            return check
+                .with(CheckStatus::Fail, err.to_string())
+                .timed(started)
+        }
+    };
+    let mut parts = Vec::new();
+    let mut hints = Vec::new();
+    let mut skewed = false;
+    for (clock, offset, hint) in [
+        (
+            "NTP",
+            &status.ntp,
+            "Enable time synchronization on this PC.",
+        ),
+        (
+            "robot",
+            &status.robot,
+            "Set the controller clock, or enable its NTP client.",
+        ),
+    ] {
+        if let Some(offset) = offset {
+            parts.push(format!("{clock} offset {:.1} ms", offset.offset_ms));
+            if offset.offset_ms.abs() > settings.max_skew_ms {
+                skewed = true;
+                hints.push(hint);
+            }
+        }
+    }
+    for (clock, error) in &status.errors {
+        parts.push(format!("{clock}: {error}"));
+    }
+    let message = if parts.is_empty() {
+        "no clock to compare with".to_owned()
+    } else {
+        parts.join("; ")
+    };
+    let check = if skewed {
+        check.with(CheckStatus::Warn, message).hint(hints.join(" "))
+    } else if !status.errors.is_empty() {
+        check
+            .with(CheckStatus::Warn, message)
+            .hint("Check that timeSync.ntpServer is reachable from the plant network.")
+    } else {
+        check.with(CheckStatus::Pass, message)
+    };
+    check.timed(started)
+}
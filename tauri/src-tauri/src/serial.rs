@@ -0,0 +1,248 @@
+//! Serial port access for grippers and teach pendants (RS-232/RS-485).
+//!
+//! Each open port gets a reader thread that emits `serial-data` events with
+//! the received bytes; `serial-closed` is emitted when the port is closed or
+//! fails.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serialport::{DataBits, FlowControl, Parity, SerialPort, SerialPortType, StopBits};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::{Error, Result};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const READ_BUFFER: usize = 4096;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialPortInfo {
+    path: String,
+    kind: &'static str,
+    vid: Option<u16>,
+    pid: Option<u16>,
+    serial_number: Option<String>,
+    manufacturer: Option<String>,
+    product: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParityConfig {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControlConfig {
+    #[default]
+    None,
+    Software,
+    Hardware,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: ParityConfig,
+    pub stop_bits: u8,
+    pub flow_control: FlowControlConfig,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            data_bits: 8,
+            parity: ParityConfig::None,
+            stop_bits: 1,
+            flow_control: FlowControlConfig::None,
+        }
+    }
+}
+
+impl SerialConfig {
+    /// Opens `path` with these line settings.
+    pub fn open(&self, path: &str, timeout: Duration) -> Result<Box<dyn SerialPort>> {
+        let data_bits = match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            other => return Err(Error::Invalid(format!("data bits {other}"))),
+        };
+        let stop_bits = match self.stop_bits {
+            1 => StopBits::One,
+            2 => StopBits::Two,
+            other => return Err(Error::Invalid(format!("stop bits {other}"))),
+        };
+        let parity = match self.parity {
+            ParityConfig::None => Parity::None,
+            ParityConfig::Odd => Parity::Odd,
+            ParityConfig::Even => Parity::Even,
+        };
+        let flow_control = match self.flow_control {
+            FlowControlConfig::None => FlowControl::None,
+            FlowControlConfig::Software => FlowControl::Software,
+            FlowControlConfig::Hardware => FlowControl::Hardware,
+        };
+        Ok(serialport::new(path, self.baud_rate)
+            .data_bits(data_bits)
+            .stop_bits(stop_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .timeout(timeout)
+            .open()?)
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerialData {
+    path: String,
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerialClosed {
+    path: String,
+    error: Option<String>,
+}
+
+struct OpenPort {
+    writer: Box<dyn SerialPort>,
+    stop: Arc<AtomicBool>,
+}
+
+/// Ports opened through [`open_serial`], keyed by path.
+#[derive(Default)]
+pub struct SerialPorts(Mutex<HashMap<String, OpenPort>>);
+
+impl SerialPorts {
+    pub fn is_open(&self, path: &str) -> bool {
+        self.0.lock().unwrap().contains_key(path)
+    }
+}
+
+#[tauri::command]
+pub fn list_serial_ports() -> Result<Vec<SerialPortInfo>> {
+    let ports = serialport::available_ports()?;
+    Ok(ports
+        .into_iter()
+        .map(|port| {
+            let mut info = SerialPortInfo {
+                path: port.port_name,
+                kind: "unknown",
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            };
+            match port.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    info.kind = "usb";
+                    info.vid = Some(usb.vid);
+                    info.pid = Some(usb.pid);
+                    info.serial_number = usb.serial_number;
+                    info.manufacturer = usb.manufacturer;
+                    info.product = usb.product;
+                }
+                SerialPortType::PciPort => info.kind = "pci",
+                SerialPortType::BluetoothPort => info.kind = "bluetooth",
+                SerialPortType::Unknown => {}
+            }
+            info
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn open_serial(
+    app: AppHandle,
+    ports: State<'_, SerialPorts>,
+    path: String,
+    config: Option<SerialConfig>,
+) -> Result<()> {
+    let mut open = ports.0.lock().unwrap();
+    if open.contains_key(&path) {
+        return Err(Error::Invalid(format!("{path} is already open")));
+    }
+
+    let writer = config.unwrap_or_default().open(&path, READ_TIMEOUT)?;
+    let reader = writer.try_clone()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let path = path.clone();
+        let stop = stop.clone();
+        std::thread::Builder::new()
+            .name(format!("serial {path}"))
+            .spawn(move || read_loop(app, path, reader, stop))?;
+    }
+    tracing::info!(%path, "serial port opened");
+    open.insert(path, OpenPort { writer, stop });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn write_serial(ports: State<'_, SerialPorts>, path: String, data: Vec<u8>) -> Result<()> {
+    let mut open = ports.0.lock().unwrap();
+    let port = open
+        .get_mut(&path)
+        .ok_or_else(|| Error::NotFound(format!("open serial port {path}")))?;
+    port.writer.write_all(&data)?;
+    port.writer.flush()?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn close_serial(ports: State<'_, SerialPorts>, path: String) -> Result<()> {
+    let port = ports
+        .0
+        .lock()
+        .unwrap()
+        .remove(&path)
+        .ok_or_else(|| Error::NotFound(format!("open serial port {path}")))?;
+    port.stop.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+fn read_loop(app: AppHandle, path: String, mut port: Box<dyn SerialPort>, stop: Arc<AtomicBool>) {
+    let mut buffer = [0u8; READ_BUFFER];
+    let error = loop {
+        if stop.load(Ordering::Relaxed) {
+            break None;
+        }
+        match port.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(count) => {
+                let data = SerialData {
+                    path: path.clone(),
+                    data: buffer[..count].to_vec(),
+                };
+                let _ = app.emit("serial-data", data);
+            }
+            Err(err) if err.kind() == ErrorKind::TimedOut => {}
+            Err(err) => break Some(err.to_string()),
+        }
+    };
+
+    if let Some(error) = &error {
+        tracing::warn!(%path, "serial port failed: {error}");
+        if let Some(ports) = app.try_state::<SerialPorts>() {
+            ports.0.lock().unwrap().remove(&path);
+        }
+    }
+    let _ = app.emit("serial-closed", SerialClosed { path, error });
+}
@@ -0,0 +1,1773 @@
+//! Persisted shell settings.
+//!
+//! Settings are stored as JSON in `settings.json` under the app config
+//! directory. The `backend` section is injected into the sidecar environment
+//! at spawn time; changing it only takes effect after a backend restart, which
+//! [`set_settings`] reports via `restartRequired`. Credentials belong in the
+//! keychain ([`crate::secrets`]), not here.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audit;
+use crate::error::Result;
+use crate::event_batching::BatchTopic;
+use crate::fleet::ConsentPolicy;
+use crate::formatting::UnitSystem;
+use crate::fsutil;
+use crate::kiosk;
+use crate::opcua::{OpcSecurityMode, OpcUaNode};
+use crate::safety_zones::SafetyZone;
+use crate::secrets;
+use crate::shortcuts::Binding;
+use crate::stream_qos::QualityProfile;
+use crate::tf::TfFrame;
+use crate::users::{self, Capability, Role, UserAccount};
+use crate::weld_signals::WeldChannel;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub backend: BackendSettings,
+    pub updates: UpdateSettings,
+    pub robot: RobotSettings,
+    pub weld: WeldSettings,
+    pub can: CanSettings,
+    pub gamepad: GamepadSettings,
+    pub spacemouse: SpaceMouseSettings,
+    pub video: VideoSettings,
+    pub rtsp: RtspSettings,
+    pub grpc: GrpcSettings,
+    pub ros2: Ros2Settings,
+    pub mqtt: MqttSettings,
+    pub opcua: OpcUaSettings,
+    pub recording: RecordingSettings,
+    pub bag: BagSettings,
+    pub telemetry: TelemetrySettings,
+    pub uploads: UploadSettings,
+    pub hub: HubSettings,
+    pub network: NetworkMonitorSettings,
+    pub watchdog: WatchdogSettings,
+    pub estop: EstopSettings,
+    pub estop_buttons: EstopButtonSettings,
+    pub auth: AuthSettings,
+    pub operator_mode: OperatorModeSettings,
+    pub offline: OfflineSettings,
+    pub python: PythonSettings,
+    pub ft_sensor: FtSensorSettings,
+    pub gripper: GripperSettings,
+    pub joint_history: JointHistorySettings,
+    pub time_sync: TimeSyncSettings,
+    pub driver_plugins: DriverPluginSettings,
+    pub headless: HeadlessSettings,
+    pub automation: AutomationSettings,
+    pub audio: AudioSettings,
+    pub voice: VoiceSettings,
+    pub licensing: LicensingSettings,
+    pub analytics: AnalyticsSettings,
+    pub stream_qos: StreamQosSettings,
+    pub tunnels: TunnelSettings,
+    pub notifications: NotificationSettings,
+    pub scheduler: SchedulerSettings,
+    pub disk_guard: DiskGuardSettings,
+    pub retention: RetentionSettings,
+    pub screen_capture: ScreenCaptureSettings,
+    pub remote_assist: RemoteAssistSettings,
+    pub otel: OtelSettings,
+    pub metrics: MetricsSettings,
+    pub jog: JogSettings,
+    pub tf: TfSettings,
+    pub urdf: UrdfSettings,
+    pub weld_signals: WeldSignalSettings,
+    pub safety_zones: SafetyZoneSettings,
+    pub shortcuts: ShortcutSettings,
+    pub event_batching: EventBatchingSettings,
+    pub users: UsersSettings,
+    pub fleet: FleetSettings,
+    pub formatting: FormattingSettings,
+    pub self_test: SelfTestSettings,
+}
+
+/// Values passed to `percus-server` on spawn.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BackendSettings {
+    pub robot_ip: Option<String>,
+    pub camera_indices: Vec<u32>,
+    pub model_dir: Option<PathBuf>,
+    pub extra_args: Vec<String>,
+    pub extra_env: BTreeMap<String, String>,
+    /// Variables filled from the keychain at spawn: variable → secret name.
+    pub secret_env: BTreeMap<String, String>,
+    /// How the webview reaches the primary backend; see
+    /// [`crate::backend_socket`]. Sessions always serve TCP.
+    pub transport: BackendTransport,
+}
+
+impl BackendSettings {
+    /// Environment variables understood by the backend.
+    pub fn env(&self) -> BTreeMap<String, String> {
+        let mut env = self.extra_env.clone();
+        if let Some(ip) = &self.robot_ip {
+            env.insert("PHI_ROBOT_IP".into(), ip.clone());
+        }
+        if !self.camera_indices.is_empty() {
+            let indices: Vec<String> = self.camera_indices.iter().map(u32::to_string).collect();
+            env.insert("PHI_CAMERA_INDICES".into(), indices.join(","));
+        }
+        if let Some(dir) = &self.model_dir {
+            env.insert("PHI_MODEL_DIR".into(), dir.display().to_string());
+        }
+        env
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendTransport {
+    /// Unix domain socket; TCP where the backend cannot serve one.
+    Socket,
+    #[default]
+    Tcp,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseChannel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+impl ReleaseChannel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReleaseChannel::Stable => "stable",
+            ReleaseChannel::Beta => "beta",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UpdateSettings {
+    pub channel: ReleaseChannel,
+    /// Update manifest URL; `{channel}` is replaced with the selected channel
+    /// and the updater's own `{{target}}`/`{{arch}}`/`{{current_version}}`
+    /// placeholders are honoured.
+    pub endpoint: String,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self {
+            channel: ReleaseChannel::Stable,
+            endpoint: "https://releases.percus.ai/desktop/{channel}/{{target}}-{{arch}}.json"
+                .into(),
+        }
+    }
+}
+
+/// Native connection to the robot controller.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RobotSettings {
+    /// Controller address; falls back to `backend.robotIp` when unset.
+    pub host: Option<String>,
+    pub port: u16,
+    pub poll_interval_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for RobotSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 10000,
+            poll_interval_ms: 100,
+            timeout_ms: 500,
+        }
+    }
+}
+
+/// Modbus/TCP connection to the welding power source and its register map.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WeldSettings {
+    pub host: Option<String>,
+    pub port: u16,
+    pub unit_id: u8,
+    /// Rate of `weld-telemetry` events.
+    pub rate_hz: f64,
+    pub registers: Vec<crate::modbus::RegisterSignal>,
+}
+
+impl Default for WeldSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 502,
+            unit_id: 1,
+            rate_hz: 10.0,
+            registers: Vec::new(),
+        }
+    }
+}
+
+/// DBC-style decode table applied to received CAN frames.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CanSettings {
+    pub signals: Vec<crate::canbus::CanSignal>,
+}
+
+/// Where sampled teleoperation input is delivered.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputForwarding {
+    #[default]
+    Events,
+    /// JSON datagrams sent straight to the backend, bypassing the webview.
+    Udp,
+}
+
+/// Native gamepad capture for teleoperation.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GamepadSettings {
+    pub rate_hz: f64,
+    /// Axis magnitude below which input reads as zero.
+    pub deadzone: f32,
+    pub scale: f32,
+    /// Axis names whose sign is flipped, e.g. `leftStickY`.
+    pub inverted_axes: Vec<String>,
+    pub output: InputForwarding,
+    pub udp_target: String,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            rate_hz: 250.0,
+            deadzone: 0.08,
+            scale: 1.0,
+            inverted_axes: Vec::new(),
+            output: InputForwarding::Events,
+            udp_target: "127.0.0.1:8765".into(),
+        }
+    }
+}
+
+/// 3Dconnexion SpaceMouse capture and calibration.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SpaceMouseSettings {
+    /// Raw count reported at full deflection; maps to 1.0.
+    pub full_scale: f32,
+    /// Normalised magnitude below which an axis reads as zero.
+    pub deadzone: f32,
+    /// Axis names whose sign is flipped: `x`, `y`, `z`, `rx`, `ry`, `rz`.
+    pub inverted_axes: Vec<String>,
+    pub output: InputForwarding,
+    pub udp_target: String,
+}
+
+impl Default for SpaceMouseSettings {
+    fn default() -> Self {
+        Self {
+            full_scale: 350.0,
+            deadzone: 0.05,
+            inverted_axes: Vec::new(),
+            output: InputForwarding::Events,
+            udp_target: "127.0.0.1:8766".into(),
+        }
+    }
+}
+
+/// Encoding of relayed camera streams.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct VideoSettings {
+    /// ffmpeg executable, looked up on `PATH` unless absolute.
+    pub ffmpeg: String,
+    /// ffmpeg encoder name; picked automatically when unset.
+    pub encoder: Option<String>,
+    pub framerate: u32,
+    pub bitrate_kbps: u32,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            ffmpeg: "ffmpeg".into(),
+            encoder: None,
+            framerate: 30,
+            bitrate_kbps: 4000,
+        }
+    }
+}
+
+/// RTSP cameras re-served to the webview as MJPEG.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RtspSettings {
+    pub cameras: Vec<crate::rtsp::RtspCamera>,
+    /// ffmpeg JPEG quantiser, 2 (best) to 31.
+    pub jpeg_quality: u8,
+}
+
+impl Default for RtspSettings {
+    fn default() -> Self {
+        Self {
+            cameras: Vec::new(),
+            jpeg_quality: 5,
+        }
+    }
+}
+
+/// Connection to the on-prem robot gateway.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GrpcSettings {
+    /// e.g. `https://gateway.local:50051`; TLS is used for `https` URLs.
+    pub endpoint: Option<String>,
+    /// PEM bundle trusted in addition to the system roots.
+    pub ca_cert: Option<PathBuf>,
+    /// Overrides the name checked against the server certificate.
+    pub domain_name: Option<String>,
+    pub connect_timeout_ms: u64,
+    /// Deadline of unary calls; streams have none.
+    pub deadline_ms: u64,
+}
+
+impl Default for GrpcSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            ca_cert: None,
+            domain_name: None,
+            connect_timeout_ms: 3000,
+            deadline_ms: 5000,
+        }
+    }
+}
+
+/// ROS 2 bridge; only used by builds with the `ros2` feature.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Ros2Settings {
+    pub node_name: String,
+    pub namespace: String,
+    pub joint_states_topic: String,
+    /// Upper bound on `ros2-joint-state` events per second.
+    pub joint_state_max_hz: f64,
+    pub cameras: Vec<Ros2Camera>,
+    pub twist_topic: String,
+    /// Publish `TwistStamped`, as MoveIt Servo expects, instead of `Twist`.
+    pub twist_stamped: bool,
+    pub joint_jog_topic: String,
+    /// Frame of published twists unless a command names one.
+    pub command_frame: String,
+}
+
+impl Default for Ros2Settings {
+    fn default() -> Self {
+        Self {
+            node_name: "percus_shell".into(),
+            namespace: String::new(),
+            joint_states_topic: "/joint_states".into(),
+            joint_state_max_hz: 60.0,
+            cameras: Vec::new(),
+            twist_topic: "/servo_node/delta_twist_cmds".into(),
+            twist_stamped: true,
+            joint_jog_topic: "/servo_node/delta_joint_cmds".into(),
+            command_frame: "base_link".into(),
+        }
+    }
+}
+
+/// An image topic re-served as the `frame://` stream `name`. Topics ending
+/// in `/compressed` are read as `CompressedImage`, others as raw `Image`.
+///
+/// Kept here rather than in `ros2` so settings parse in every build.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ros2Camera {
+    pub name: String,
+    pub topic: String,
+}
+
+/// Factory MQTT broker that cell status is published to.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MqttSettings {
+    /// Publishing is off while unset.
+    pub host: Option<String>,
+    pub port: u16,
+    pub tls: bool,
+    /// PEM bundle to trust instead of the system roots.
+    pub ca_cert: Option<PathBuf>,
+    /// Defaults to `percus-<hostname>`.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 0, 1 or 2.
+    pub qos: u8,
+    pub keep_alive_secs: u64,
+    /// Period of the retained backend status; 0 publishes on changes only.
+    pub status_interval_secs: u64,
+    pub topics: MqttTopics,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 1883,
+            tls: false,
+            ca_cert: None,
+            client_id: None,
+            username: None,
+            password: None,
+            qos: 1,
+            keep_alive_secs: 30,
+            status_interval_secs: 30,
+            topics: MqttTopics::default(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MqttTopics {
+    /// Retained `online`/`offline`, the latter as the broker-side last will.
+    pub availability: String,
+    pub backend: String,
+    pub robot: String,
+    pub episodes: String,
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        Self {
+            availability: "percus/cell/availability".into(),
+            backend: "percus/cell/backend".into(),
+            robot: "percus/cell/robot".into(),
+            episodes: "percus/cell/episodes".into(),
+        }
+    }
+}
+
+/// OPC UA server of the cell's safety PLC.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OpcUaSettings {
+    /// e.g. `opc.tcp://192.168.0.20:4840`.
+    pub endpoint: Option<String>,
+    /// Policy name or URI, e.g. `None` or `Basic256Sha256`.
+    pub security_policy: String,
+    pub security_mode: OpcSecurityMode,
+    /// Anonymous unless both are set.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Client certificate and trusted server certificates; defaults to
+    /// `opcua-pki` under the app data directory.
+    pub pki_dir: Option<PathBuf>,
+    /// Trust any server certificate instead of only those in `pki/trusted`.
+    pub trust_server_certs: bool,
+    /// Also the sampling interval of the monitored nodes.
+    pub publishing_interval_ms: u64,
+    pub request_timeout_ms: u64,
+    /// Monitored as soon as the session is up.
+    pub nodes: Vec<OpcUaNode>,
+}
+
+impl Default for OpcUaSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            security_policy: "None".into(),
+            security_mode: OpcSecurityMode::None,
+            username: None,
+            password: None,
+            pki_dir: None,
+            trust_server_certs: false,
+            publishing_interval_ms: 250,
+            request_timeout_ms: 5000,
+            nodes: Vec::new(),
+        }
+    }
+}
+
+/// Episode recording done by the shell itself.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RecordingSettings {
+    /// Datasets live in `<root>/<dataset>`; defaults to `datasets` under the
+    /// app data directory.
+    pub root: Option<PathBuf>,
+    pub fps: u32,
+    /// Backend WebSocket path streaming `observation.state` and `action`.
+    pub state_topic: String,
+    /// Stored as `robot_type` in new datasets.
+    pub robot_type: Option<String>,
+    /// `h264` or `hevc` to use the GPU's encoder where there is one (see
+    /// [`crate::encoding`]), or an ffmpeg encoder name.
+    pub video_codec: String,
+    /// Software CRF, mapped to each hardware encoder's quality scale.
+    pub crf: u8,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            root: None,
+            fps: 30,
+            state_topic: "/api/operate/ws/robot-state".into(),
+            robot_type: None,
+            video_codec: "h264".into(),
+            crf: 30,
+        }
+    }
+}
+
+/// MCAP recordings of the shell's event traffic.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BagSettings {
+    /// Defaults to `bags` under the app data directory.
+    pub dir: Option<PathBuf>,
+    /// A new file is started past this size; 0 keeps one file.
+    pub split_size_mb: u64,
+    /// Event names recorded, one channel each.
+    pub channels: Vec<String>,
+}
+
+impl Default for BagSettings {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            split_size_mb: 1024,
+            channels: [
+                "robot-status",
+                "gamepad-state",
+                "spacemouse-state",
+                "weld-telemetry",
+                "backend-log",
+                "ft-sample",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Always-on Parquet log of high-rate robot and process signals.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TelemetrySettings {
+    pub enabled: bool,
+    /// Defaults to `telemetry` under the app data directory.
+    pub dir: Option<PathBuf>,
+    /// Event names logged.
+    pub channels: Vec<String>,
+    pub flush_interval_secs: u64,
+    /// Files older than this are deleted; 0 keeps them regardless of age.
+    pub retention_days: u32,
+    /// Oldest files are deleted beyond this total; 0 disables the limit.
+    pub retention_gb: f64,
+    /// Push every flushed file to the upload bucket under `telemetry/`,
+    /// through the offline queue.
+    pub push: bool,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            channels: [
+                "grpc-robot-state",
+                "ros2-joint-state",
+                "robot-status",
+                "weld-telemetry",
+            ]
+            .map(String::from)
+            .to_vec(),
+            flush_interval_secs: 10,
+            retention_days: 14,
+            retention_gb: 5.0,
+            push: false,
+        }
+    }
+}
+
+/// S3-compatible bucket (AWS S3, MinIO) recordings are uploaded to.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UploadSettings {
+    /// e.g. `https://s3.ap-northeast-1.amazonaws.com` or a MinIO URL;
+    /// uploading is off while unset.
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Prepended to every object key.
+    pub prefix: String,
+    /// `endpoint/bucket/key` rather than `bucket.endpoint/key`; MinIO
+    /// usually needs this.
+    pub path_style: bool,
+    /// Multipart part size; at least 5.
+    pub part_size_mb: u64,
+    /// 0 is unlimited.
+    pub bandwidth_limit_kbps: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            region: "us-east-1".into(),
+            bucket: String::new(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            prefix: String::new(),
+            path_style: true,
+            part_size_mb: 64,
+            bandwidth_limit_kbps: 0,
+            max_backoff_secs: 300,
+        }
+    }
+}
+
+/// Hugging Face Hub the shell pushes datasets to and pulls models from.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HubSettings {
+    /// The public Hub, or a mirror or self-hosted endpoint.
+    pub endpoint: String,
+}
+
+impl Default for HubSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://huggingface.co".into(),
+        }
+    }
+}
+
+/// Latency probes to the controller and backend, and the limits for safe
+/// teleop.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NetworkMonitorSettings {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    /// Probes per target the statistics are computed over.
+    pub window: usize,
+    /// A probe unanswered for this long counts as lost.
+    pub timeout_ms: u64,
+    pub max_latency_ms: f64,
+    pub max_jitter_ms: f64,
+    pub max_loss_percent: f64,
+    /// Probed in addition to the controller, gateway and backend.
+    pub targets: Vec<NetworkTarget>,
+}
+
+impl Default for NetworkMonitorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 500,
+            window: 40,
+            timeout_ms: 1000,
+            max_latency_ms: 20.0,
+            max_jitter_ms: 5.0,
+            max_loss_percent: 2.0,
+            targets: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkTarget {
+    pub name: String,
+    /// Pinged, or TCP-connected to when given as `host:port`.
+    pub host: String,
+}
+
+/// Deadlines for the UI heartbeat and backend health, and the controller
+/// command sent when one is missed.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WatchdogSettings {
+    pub enabled: bool,
+    /// Longest gap between two `heartbeat` calls from the UI.
+    pub ui_timeout_ms: u64,
+    pub backend_interval_ms: u64,
+    /// Longest time without a healthy answer from the backend.
+    pub backend_timeout_ms: u64,
+    /// Line sent to the FD controller to bring the robot to a safe stop.
+    pub stop_command: String,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ui_timeout_ms: 1500,
+            backend_interval_ms: 500,
+            backend_timeout_ms: 3000,
+            stop_command: "STOP".into(),
+        }
+    }
+}
+
+/// System-wide emergency-stop hotkey.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EstopSettings {
+    pub enabled: bool,
+    /// Accelerator such as `CommandOrControl+Space` or `Ctrl+Shift+F12`.
+    pub shortcut: String,
+}
+
+impl Default for EstopSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            shortcut: "CommandOrControl+Space".into(),
+        }
+    }
+}
+
+/// USB HID emergency-stop buttons and how their reports are decoded.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EstopButtonSettings {
+    pub enabled: bool,
+    pub devices: Vec<HidDeviceId>,
+    /// Byte of the input report holding the button; on devices with
+    /// numbered reports byte 0 is the report id.
+    pub report_byte: usize,
+    pub pressed_mask: u8,
+    /// The button reads as pressed while the masked bits are clear.
+    pub active_low: bool,
+    /// Unplugging a button stops the robot like pressing it.
+    pub stop_on_disconnect: bool,
+}
+
+impl Default for EstopButtonSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            devices: Vec::new(),
+            report_byte: 0,
+            pressed_mask: 0x01,
+            active_low: false,
+            stop_on_disconnect: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HidDeviceId {
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+/// OAuth provider of the cloud account. Sign-in is unavailable while
+/// `clientId` or `tokenEndpoint` is unset.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AuthSettings {
+    pub client_id: Option<String>,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub device_authorization_endpoint: Option<String>,
+    /// Asked who is signed in for station logins; see [`crate::users`].
+    pub userinfo_endpoint: Option<String>,
+    pub scopes: Vec<String>,
+    /// Variable the backend receives the access token in.
+    pub access_token_env: String,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            client_id: None,
+            authorization_endpoint: None,
+            token_endpoint: None,
+            device_authorization_endpoint: None,
+            userinfo_endpoint: None,
+            scopes: vec!["openid".into(), "offline_access".into()],
+            access_token_env: "PHI_ACCESS_TOKEN".into(),
+        }
+    }
+}
+
+/// Shop-floor lock-down; see [`crate::kiosk`]. Only the shell changes this
+/// section while the kiosk is active.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OperatorModeSettings {
+    /// Start in the kiosk; set by `enter_kiosk` and cleared by `exit_kiosk`.
+    pub enabled: bool,
+}
+
+/// Connectivity checks behind the offline queue; see [`crate::offline`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OfflineSettings {
+    /// When off the shell always assumes it is online.
+    pub enabled: bool,
+    /// Any HTTP response from this URL counts as online.
+    pub probe_url: String,
+    pub check_interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for OfflineSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            probe_url: "https://connectivitycheck.gstatic.com/generate_204".into(),
+            check_interval_secs: 10,
+            timeout_secs: 5,
+        }
+    }
+}
+
+/// Sources of the embedded Python environment; see [`crate::pyenv`]. The
+/// bundled runtime and wheels take precedence.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PythonSettings {
+    /// python-build-standalone `install_only` archive to download.
+    pub runtime_url: Option<String>,
+    pub runtime_sha256: Option<String>,
+    /// Package index used instead of PyPI.
+    pub index_url: Option<String>,
+}
+
+/// ATI Net F/T sensor streaming RDT over UDP; see [`crate::ft_sensor`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FtSensorSettings {
+    pub host: Option<String>,
+    pub port: u16,
+    /// `cfgcpf` and `cfgcpt` of the sensor's active calibration.
+    pub counts_per_force: f64,
+    pub counts_per_torque: f64,
+    /// Rate of the averaged `ft-sensor-state` events.
+    pub display_hz: f64,
+    pub alarm: FtAlarmSettings,
+}
+
+impl Default for FtSensorSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 49152,
+            counts_per_force: 1_000_000.0,
+            counts_per_torque: 1_000_000.0,
+            display_hz: 30.0,
+            alarm: FtAlarmSettings::default(),
+        }
+    }
+}
+
+/// Limits on the tared readings; unset limits are not checked.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FtAlarmSettings {
+    /// Magnitude of the force vector, in newtons.
+    pub max_force: Option<f64>,
+    /// Magnitude of the torque vector, in newton metres.
+    pub max_torque: Option<f64>,
+    /// Trigger the emergency stop when a limit is exceeded.
+    pub stop_robot: bool,
+}
+
+/// Robotiq 2F gripper on a Modbus RTU serial link; see [`crate::gripper`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GripperSettings {
+    /// Serial device, e.g. `/dev/ttyUSB0` or `COM3`.
+    pub port: Option<String>,
+    pub baud_rate: u32,
+    pub slave_id: u8,
+    /// Rate of `gripper-status` events while polling.
+    pub poll_hz: f64,
+}
+
+impl Default for GripperSettings {
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud_rate: 115_200,
+            slave_id: 9,
+            poll_hz: 10.0,
+        }
+    }
+}
+
+/// In-memory joint-state history; see [`crate::joint_history`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JointHistorySettings {
+    pub retention_secs: u64,
+    /// Oldest samples are dropped beyond this count, whatever their age.
+    pub max_samples: usize,
+    /// Also record `observation.state` from `recording.stateTopic`.
+    pub backend_state: bool,
+}
+
+impl Default for JointHistorySettings {
+    fn default() -> Self {
+        Self {
+            retention_secs: 300,
+            max_samples: 1_000_000,
+            backend_state: true,
+        }
+    }
+}
+
+/// Clock the shell's own timestamps are corrected to.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimeReference {
+    #[default]
+    Ntp,
+    /// The robot controller's clock, for cells without internet access.
+    Robot,
+    /// Leave timestamps on the PC clock; offsets are still monitored.
+    None,
+}
+
+/// Clock offset measurement; see [`crate::time_sync`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeSyncSettings {
+    pub enabled: bool,
+    /// Not queried while unset.
+    pub ntp_server: Option<String>,
+    pub interval_secs: u64,
+    pub reference: TimeReference,
+    /// `clock-skew` is raised when a clock is further off than this.
+    pub max_skew_ms: f64,
+}
+
+impl Default for TimeSyncSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ntp_server: Some("pool.ntp.org".into()),
+            interval_secs: 60,
+            reference: TimeReference::Ntp,
+            max_skew_ms: 5.0,
+        }
+    }
+}
+
+/// Hardware driver plugins; see [`crate::driver_plugins`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DriverPluginSettings {
+    /// Load every library in `plugins` under the app data directory at
+    /// startup.
+    pub load_at_startup: bool,
+}
+
+impl Default for DriverPluginSettings {
+    fn default() -> Self {
+        Self {
+            load_at_startup: true,
+        }
+    }
+}
+
+/// Admin API of `--headless` runs; see [`crate::headless`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HeadlessSettings {
+    /// Loopback port of the token-protected HTTP listener; unset disables it.
+    pub admin_port: Option<u16>,
+    /// Listen on `admin.sock` in the app data directory (Unix only).
+    pub admin_socket: bool,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        Self {
+            admin_port: Some(7420),
+            admin_socket: true,
+        }
+    }
+}
+
+/// JSON-RPC server for end-to-end tests; see [`crate::automation`].
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AutomationSettings {
+    pub enabled: bool,
+}
+
+/// Microphone track recorded with each episode; see [`crate::audio`].
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AudioSettings {
+    pub enabled: bool,
+    /// Input device name from `list_audio_devices`; the system default when
+    /// unset.
+    pub device: Option<String>,
+}
+
+/// Push-to-talk stream to the backend; see [`crate::voice`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct VoiceSettings {
+    pub enabled: bool,
+    /// Backend WebSocket the audio is sent to.
+    pub path: String,
+    /// Length of each PCM chunk.
+    pub chunk_ms: u32,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/ws/voice".into(),
+            chunk_ms: 20,
+        }
+    }
+}
+
+/// Online license activation; see [`crate::licensing`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LicensingSettings {
+    /// Base URL of the activation server.
+    pub server: Option<String>,
+    pub renew_interval_hours: u64,
+    /// Days an activated license keeps working after its lease ran out.
+    pub grace_days: u32,
+}
+
+impl Default for LicensingSettings {
+    fn default() -> Self {
+        Self {
+            server: None,
+            renew_interval_hours: 24,
+            grace_days: 14,
+        }
+    }
+}
+
+/// Opt-in usage metrics; see [`crate::analytics`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnalyticsSettings {
+    /// Off until the operator opts in.
+    pub enabled: bool,
+    /// URL batches are `POST`ed to as JSON.
+    pub endpoint: Option<String>,
+    pub batch_size: usize,
+    pub flush_interval_secs: u64,
+}
+
+impl Default for AnalyticsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: None,
+            batch_size: 100,
+            flush_interval_secs: 300,
+        }
+    }
+}
+
+/// Adaptive quality of relayed camera streams.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StreamQosSettings {
+    /// When off, streams stay at the `video` settings whatever the network.
+    pub enabled: bool,
+    /// Steps down from the `video` settings at the source resolution, tried
+    /// in order while a stream is congested.
+    pub profiles: Vec<QualityProfile>,
+    /// How often quality is measured and reported.
+    pub interval_ms: u64,
+    /// Packet loss the viewer reports above which a stream steps down.
+    pub max_loss_percent: f32,
+    /// Frame latency above which a stream steps down.
+    pub max_latency_ms: u32,
+    /// How long a stream has to be uncongested before stepping back up.
+    pub upgrade_after_secs: u64,
+}
+
+impl Default for StreamQosSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            profiles: vec![
+                QualityProfile {
+                    name: "medium".into(),
+                    max_height: Some(480),
+                    framerate: 15,
+                    bitrate_kbps: 1500,
+                },
+                QualityProfile {
+                    name: "low".into(),
+                    max_height: Some(360),
+                    framerate: 10,
+                    bitrate_kbps: 600,
+                },
+                QualityProfile {
+                    name: "minimal".into(),
+                    max_height: Some(240),
+                    framerate: 5,
+                    bitrate_kbps: 250,
+                },
+            ],
+            interval_ms: 2000,
+            max_loss_percent: 5.0,
+            max_latency_ms: 300,
+            upgrade_after_secs: 15,
+        }
+    }
+}
+
+/// SSH tunnels to remote cells.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TunnelSettings {
+    /// OpenSSH client, looked up on `PATH` unless absolute.
+    pub ssh: String,
+    /// A tunnel is dropped after three unanswered keepalives this far apart.
+    pub keepalive_secs: u32,
+}
+
+impl Default for TunnelSettings {
+    fn default() -> Self {
+        Self {
+            ssh: "ssh".into(),
+            keepalive_secs: 15,
+        }
+    }
+}
+
+/// Native notifications; see [`crate::notifications`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    /// Only raise them while the main window is not focused.
+    pub background_only: bool,
+    pub uploads: bool,
+    pub exports: bool,
+    pub recording: bool,
+    pub updates: bool,
+    pub backend: bool,
+    pub network: bool,
+    pub safety: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            background_only: false,
+            uploads: true,
+            exports: true,
+            recording: true,
+            updates: true,
+            backend: true,
+            network: true,
+            safety: true,
+        }
+    }
+}
+
+/// Background jobs; see [`crate::scheduler`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SchedulerSettings {
+    pub jobs: Vec<crate::scheduler::ScheduledJob>,
+    /// Age at which `log-prune` deletes a log file; 0 keeps them all.
+    pub log_retention_days: u32,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        Self {
+            jobs: crate::scheduler::default_jobs(),
+            log_retention_days: 30,
+        }
+    }
+}
+
+/// Free-space limits of the recording volume; see [`crate::disk_guard`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiskGuardSettings {
+    pub enabled: bool,
+    /// Below this `start_recording` is refused.
+    pub min_free_gb: f64,
+    pub warn_free_gb: f64,
+    /// Also where telemetry stops writing.
+    pub critical_free_gb: f64,
+    /// Below this a recording is stopped and saved; 0 never stops it.
+    pub stop_free_gb: f64,
+    pub interval_secs: u64,
+}
+
+impl Default for DiskGuardSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_free_gb: 10.0,
+            warn_free_gb: 5.0,
+            critical_free_gb: 2.0,
+            stop_free_gb: 1.0,
+            interval_secs: 5,
+        }
+    }
+}
+
+/// Pruning rules per data class; see [`crate::retention`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetentionSettings {
+    pub episodes: RetentionRule,
+    pub telemetry: RetentionRule,
+    pub logs: RetentionRule,
+    pub crash_bundles: RetentionRule,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        let rule = |max_age_days, max_total_gb, uploaded_only| RetentionRule {
+            enabled: false,
+            max_age_days,
+            max_total_gb,
+            uploaded_only,
+        };
+        Self {
+            episodes: rule(0, 0.0, true),
+            telemetry: rule(30, 10.0, false),
+            logs: rule(30, 2.0, false),
+            crash_bundles: rule(90, 1.0, false),
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetentionRule {
+    pub enabled: bool,
+    /// 0 keeps items of any age.
+    pub max_age_days: u32,
+    /// 0 keeps any amount.
+    pub max_total_gb: f64,
+    /// Only delete items the upload queue has finished.
+    pub uploaded_only: bool,
+}
+
+/// Recording of the app window alongside an episode; see
+/// [`crate::screen_capture`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ScreenCaptureSettings {
+    pub fps: u32,
+    pub draw_cursor: bool,
+}
+
+impl Default for ScreenCaptureSettings {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            draw_cursor: true,
+        }
+    }
+}
+
+/// View-only sessions for support engineers; see [`crate::remote_assist`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RemoteAssistSettings {
+    pub enabled: bool,
+    /// `wss` URL of the support relay; without it only direct offers are
+    /// accepted.
+    pub relay_url: Option<String>,
+    /// STUN and TURN URLs for sessions across networks.
+    pub ice_servers: Vec<String>,
+    pub turn_username: String,
+    pub turn_credential: String,
+    /// Backend MJPEG paths to publish; every `backend.cameraIndices` camera
+    /// when empty.
+    pub cameras: Vec<String>,
+    /// Longest session the operator can grant.
+    pub max_minutes: u32,
+    /// Requests the operator has not answered by then are declined.
+    pub consent_timeout_secs: u64,
+}
+
+impl Default for RemoteAssistSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            relay_url: None,
+            ice_servers: vec!["stun:stun.l.google.com:19302".into()],
+            turn_username: String::new(),
+            turn_credential: String::new(),
+            cameras: Vec::new(),
+            max_minutes: 30,
+            consent_timeout_secs: 60,
+        }
+    }
+}
+
+/// Trace export over OTLP; see [`crate::otel`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OtelSettings {
+    pub enabled: bool,
+    /// Collector URL, e.g. `http://localhost:4317` for gRPC or
+    /// `http://localhost:4318/v1/traces` for HTTP.
+    pub endpoint: String,
+    /// `grpc` or `http` (protobuf).
+    pub protocol: String,
+    /// Sent with every export, e.g. an `authorization` header.
+    pub headers: BTreeMap<String, String>,
+    /// Share of root traces exported, 0 to 1.
+    pub sample_ratio: f64,
+    /// `EnvFilter` directive choosing the exported spans.
+    pub filter: String,
+    /// Points the sidecar's exporter at the same collector.
+    pub propagate_to_backend: bool,
+}
+
+impl Default for OtelSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".into(),
+            protocol: "grpc".into(),
+            headers: BTreeMap::new(),
+            sample_ratio: 1.0,
+            filter: "info,percus_tauri=debug,tauri::ipc::protocol=trace".into(),
+            propagate_to_backend: true,
+        }
+    }
+}
+
+/// Prometheus endpoint; see [`crate::metrics`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    /// Address and port to listen on, e.g. `0.0.0.0:9464` to be scraped
+    /// from the plant network.
+    pub bind: String,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:9464".into(),
+        }
+    }
+}
+
+/// Direct jog stream to the robot controller; see [`crate::jog`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JogSettings {
+    /// UDP port of the controller's streaming interface.
+    pub port: u16,
+    pub rate_hz: f64,
+    pub max_linear_mm_s: f64,
+    pub max_angular_deg_s: f64,
+    pub max_joint_deg_s: f64,
+    pub linear_accel_mm_s2: f64,
+    pub angular_accel_deg_s2: f64,
+    pub joint_accel_deg_s2: f64,
+    /// Jog commands older than this read as zero.
+    pub command_timeout_ms: u64,
+    /// Corners of the base-frame box the tool may be jogged in, in mm.
+    pub workspace_min: Option<[f64; 3]>,
+    pub workspace_max: Option<[f64; 3]>,
+    /// `[min, max]` degrees per joint, from joint 1.
+    pub joint_limits: Vec<[f64; 2]>,
+    /// Jog from the gamepad capture while `deadmanButton` is held: left
+    /// stick x/y, right stick y as z and right stick x as rz.
+    pub gamepad: bool,
+    pub deadman_button: String,
+}
+
+impl Default for JogSettings {
+    fn default() -> Self {
+        Self {
+            port: 10020,
+            rate_hz: 100.0,
+            max_linear_mm_s: 250.0,
+            max_angular_deg_s: 45.0,
+            max_joint_deg_s: 30.0,
+            linear_accel_mm_s2: 1000.0,
+            angular_accel_deg_s2: 180.0,
+            joint_accel_deg_s2: 120.0,
+            command_timeout_ms: 250,
+            workspace_min: None,
+            workspace_max: None,
+            joint_limits: Vec::new(),
+            gamepad: false,
+            deadman_button: "rightBumper".into(),
+        }
+    }
+}
+
+/// Frame transforms for the 3D viewer; see [`crate::tf`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TfSettings {
+    pub frames: Vec<TfFrame>,
+    /// Parent of cameras calibrated as fixed in the cell.
+    pub base_frame: String,
+    /// Parent of cameras calibrated on the arm.
+    pub tool_frame: String,
+    /// Joint positions kept for lookups in the past.
+    pub buffer_secs: u64,
+    /// `0` stops `tf-snapshot`.
+    pub snapshot_hz: f64,
+}
+
+impl Default for TfSettings {
+    fn default() -> Self {
+        Self {
+            frames: Vec::new(),
+            base_frame: "base_link".into(),
+            tool_frame: "tool0".into(),
+            buffer_secs: 10,
+            snapshot_hz: 30.0,
+        }
+    }
+}
+
+/// The robot model; see [`crate::urdf`].
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UrdfSettings {
+    /// Defaults to the bundled `urdf/robot.urdf`.
+    pub path: Option<PathBuf>,
+    /// Directories `package://<name>/` mesh references resolve against.
+    pub package_dirs: BTreeMap<String, PathBuf>,
+}
+
+/// Waveform stream of arc voltage and current; see
+/// [`crate::weld_signals`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WeldSignalSettings {
+    /// UDP address the power source sends its waveform datagrams to.
+    pub bind: String,
+    /// In the order of the samples in a datagram.
+    pub channels: Vec<WeldChannel>,
+    /// Display bucket of `weld-signal` envelopes.
+    pub bucket_ms: f64,
+    /// Rate of `weld-signal` events.
+    pub emit_hz: f64,
+    /// Keep every sample beside episodes being recorded.
+    pub record: bool,
+}
+
+impl Default for WeldSignalSettings {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:10030".into(),
+            channels: vec![
+                WeldChannel {
+                    name: "arcVoltage".into(),
+                    unit: "V".into(),
+                },
+                WeldChannel {
+                    name: "arcCurrent".into(),
+                    unit: "A".into(),
+                },
+            ],
+            bucket_ms: 5.0,
+            emit_hz: 20.0,
+            record: true,
+        }
+    }
+}
+
+/// Keep-out and keep-in zones of the cell; see [`crate::safety_zones`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SafetyZoneSettings {
+    pub enabled: bool,
+    pub zones: Vec<SafetyZone>,
+    /// Frames checked against the zones.
+    pub frames: Vec<String>,
+    /// Clearance in metres, kept from keep-out zones and from the boundary
+    /// of keep-in zones.
+    pub margin: f64,
+    pub check_hz: f64,
+    /// Pause checks once joint positions are older than this.
+    pub stale_ms: u64,
+    /// Safe-stop the robot on a violation of any zone.
+    pub stop_robot: bool,
+}
+
+impl Default for SafetyZoneSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            zones: Vec::new(),
+            frames: vec!["tool0".into()],
+            margin: 0.02,
+            check_hz: 50.0,
+            stale_ms: 500,
+            stop_robot: false,
+        }
+    }
+}
+
+/// Keymap overrides by action; see [`crate::shortcuts`].
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ShortcutSettings {
+    pub bindings: BTreeMap<String, Binding>,
+}
+
+/// High-rate events sent in batches; see [`crate::event_batching`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EventBatchingSettings {
+    pub enabled: bool,
+    /// Event name → batching of that event.
+    pub topics: BTreeMap<String, BatchTopic>,
+}
+
+impl Default for EventBatchingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            topics: BTreeMap::from([
+                ("gamepad-state".into(), BatchTopic::default()),
+                ("ros2-joint-state".into(), BatchTopic::default()),
+            ]),
+        }
+    }
+}
+
+/// Station accounts and what operators may do; see [`crate::users`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UsersSettings {
+    pub enabled: bool,
+    pub accounts: Vec<UserAccount>,
+    pub operator_capabilities: Vec<Capability>,
+    /// Role of SSO identities without an account; refused if unset.
+    pub sso_default_role: Option<Role>,
+    pub session_timeout_mins: Option<u64>,
+}
+
+impl Default for UsersSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            accounts: Vec::new(),
+            operator_capabilities: vec![Capability::RunPrograms],
+            sso_default_role: None,
+            session_timeout_mins: Some(480),
+        }
+    }
+}
+
+/// Fleet dashboard reporting; see [`crate::fleet`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FleetSettings {
+    pub enabled: bool,
+    /// WebSocket endpoint of the fleet server.
+    pub url: Option<String>,
+    pub heartbeat_secs: u64,
+    /// How long an `ask` command waits for the operator.
+    pub consent_timeout_secs: u64,
+    pub diagnostics: ConsentPolicy,
+    pub update_check: ConsentPolicy,
+}
+
+impl Default for FleetSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: None,
+            heartbeat_secs: 30,
+            consent_timeout_secs: 120,
+            diagnostics: ConsentPolicy::Ask,
+            update_check: ConsentPolicy::Allow,
+        }
+    }
+}
+
+/// Locale and units of displayed and exported values; see
+/// [`crate::formatting`].
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormattingSettings {
+    /// BCP 47 tag; the system's when unset.
+    pub locale: Option<String>,
+    /// Follows the locale when unset.
+    pub unit_system: Option<UnitSystem>,
+    pub date_format: Option<String>,
+    pub time_format: Option<String>,
+}
+
+/// Commissioning checks; see [`crate::self_test`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelfTestSettings {
+    pub run_at_startup: bool,
+    /// Size of the scratch file timed on the recording disk.
+    pub disk_test_mb: u64,
+    /// Slower disks are reported as a warning.
+    pub min_write_mb_per_sec: f64,
+}
+
+impl Default for SelfTestSettings {
+    fn default() -> Self {
+        Self {
+            run_at_startup: false,
+            disk_test_mb: 64,
+            min_write_mb_per_sec: 50.0,
+        }
+    }
+}
+
+impl Settings {
+    /// Address of the robot controller, if one is configured.
+    pub fn robot_host(&self) -> Option<String> {
+        self.robot
+            .host
+            .clone()
+            .or_else(|| self.backend.robot_ip.clone())
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    current: Mutex<Settings>,
+    /// Backend section the running sidecar was spawned with.
+    applied_backend: Mutex<Option<BackendSettings>>,
+}
+
+impl SettingsStore {
+    pub fn get(&self) -> Settings {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Returns the backend section for a spawn and records it as applied.
+    pub fn backend_for_spawn(&self) -> BackendSettings {
+        let backend = self.current.lock().unwrap().backend.clone();
+        *self.applied_backend.lock().unwrap() = Some(backend.clone());
+        backend
+    }
+
+    /// Applies `change` to the current settings, saves them and notifies
+    /// listeners as [`set_settings`] does.
+    pub fn update(&self, app: &AppHandle, change: impl FnOnce(&mut Settings)) -> Result<()> {
+        let previous = self.get();
+        let mut settings = previous.clone();
+        change(&mut settings);
+        self.save(settings.clone())?;
+        audit::settings_changed(app, &previous, &settings);
+        let _ = app.emit("settings-changed", settings);
+        Ok(())
+    }
+
+    fn save(&self, settings: Settings) -> Result<()> {
+        fsutil::write_atomic(&self.path, &serde_json::to_vec_pretty(&settings)?)?;
+        *self.current.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    fn restart_required(&self) -> bool {
+        let current = &self.current.lock().unwrap().backend;
+        self.applied_backend
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|applied| applied != current)
+    }
+}
+
+/// Loads `settings.json`, falling back to defaults when missing or unreadable.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_config_dir()?.join(SETTINGS_FILE);
+    let mut settings = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!("ignoring unreadable {}: {err}", path.display());
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    };
+    if secrets::migrate_backend_env(&mut settings.backend) {
+        fsutil::write_atomic(&path, &serde_json::to_vec_pretty(&settings)?)?;
+    }
+    app.manage(SettingsStore {
+        path,
+        current: Mutex::new(settings),
+        applied_backend: Mutex::new(None),
+    });
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsUpdate {
+    /// The backend must be restarted for the new values to take effect.
+    restart_required: bool,
+}
+
+#[tauri::command]
+pub fn get_settings(store: State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+#[tauri::command]
+pub fn set_settings(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    mut settings: Settings,
+) -> Result<SettingsUpdate> {
+    users::require(&app, Capability::EditSettings)?;
+    let previous = store.get();
+    if kiosk::is_active(&app) {
+        settings.operator_mode = previous.operator_mode.clone();
+    }
+    // Accounts and enforcement are not part of what `EditSettings` allows.
+    if users::require(&app, Capability::ManageUsers).is_err() {
+        settings.users = previous.users.clone();
+    }
+    store.save(settings.clone())?;
+    audit::settings_changed(&app, &previous, &settings);
+    let _ = app.emit("settings-changed", settings);
+    Ok(SettingsUpdate {
+        restart_required: store.restart_required(),
+    })
+}
@@ -0,0 +1,323 @@
+//! Operator-remappable keyboard shortcuts.
+//!
+//! The keymap binds each action of [`ACTIONS`] to an accelerator: the
+//! defaults below, overridden per action by `shortcuts.bindings`. Global
+//! bindings are registered with the OS at all times; in-app bindings only
+//! while one of the app's windows has focus, so they never take keys away
+//! from other applications. Pressing and releasing a bound key emits
+//! `shortcut-triggered` and the UI performs the action, holding a jog for
+//! as long as the key is down.
+//!
+//! The emergency stop is the `estop` action of the keymap, but stays
+//! registered and handled by [`crate::estop`] so it keeps working without
+//! the webview; its binding is `estop.shortcut` and is always global.
+//!
+//! [`set_binding`] refuses an accelerator that another action already
+//! uses. Conflicts that reach the settings otherwise (an edited settings
+//! file) leave the later binding unregistered and are reported by
+//! [`get_keymap`].
+
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::error::{Error, Result};
+use crate::settings::{Settings, SettingsStore};
+use crate::users::{self, Capability};
+
+/// The emergency stop's action, bound through `estop.shortcut`.
+const ESTOP: &str = "estop";
+
+/// Actions with their default accelerator and scope (`true` for global).
+pub const ACTIONS: &[(&str, Option<&str>, bool)] = &[
+    (ESTOP, None, true),
+    ("recordingStart", Some("CommandOrControl+R"), false),
+    ("recordingStop", Some("CommandOrControl+Shift+R"), false),
+    ("jogXPlus", Some("Alt+ArrowRight"), false),
+    ("jogXMinus", Some("Alt+ArrowLeft"), false),
+    ("jogYPlus", Some("Alt+ArrowUp"), false),
+    ("jogYMinus", Some("Alt+ArrowDown"), false),
+    ("jogZPlus", Some("Alt+PageUp"), false),
+    ("jogZMinus", Some("Alt+PageDown"), false),
+    ("jogStop", Some("Alt+Space"), false),
+];
+
+/// An entry of `shortcuts.bindings`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Binding {
+    /// Such as `CommandOrControl+Shift+R`; unset leaves the action unbound.
+    pub accelerator: Option<String>,
+    /// Registered even while no window of the app has focus.
+    #[serde(default)]
+    pub global: bool,
+}
+
+/// An entry of [`get_keymap`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBinding {
+    pub action: String,
+    pub accelerator: Option<String>,
+    pub global: bool,
+    /// Whether the OS currently delivers the shortcut to the app.
+    pub registered: bool,
+    /// Why the binding is not in effect: an action bound to the same keys,
+    /// an unparseable accelerator or an OS refusal.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShortcutTriggered {
+    action: String,
+    pressed: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    registered: HashMap<Shortcut, String>,
+    /// Per action, why it is not registered.
+    errors: HashMap<String, String>,
+    focused: bool,
+}
+
+/// The shortcuts registered for the keymap.
+#[derive(Default)]
+pub struct Shortcuts(StdMutex<Registry>);
+
+/// Registers the keymap and follows settings changes.
+pub fn init(app: &AppHandle) {
+    app.manage(Shortcuts::default());
+    apply(app, &app.state::<SettingsStore>().get());
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            apply(&handle, &settings);
+        }
+    });
+}
+
+/// Registers or releases the in-app bindings as the app gains or loses
+/// focus.
+pub fn on_focus(app: &AppHandle, focused: bool) {
+    let Some(shortcuts) = app.try_state::<Shortcuts>() else {
+        return;
+    };
+    let changed = {
+        let mut registry = shortcuts.0.lock().unwrap();
+        let changed = registry.focused != focused;
+        registry.focused = focused;
+        changed
+    };
+    if changed {
+        apply(app, &app.state::<SettingsStore>().get());
+    }
+}
+
+/// Global shortcut handler, next to [`crate::estop::on_shortcut`].
+pub fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    let action = app.try_state::<Shortcuts>().and_then(|shortcuts| {
+        shortcuts
+            .0
+            .lock()
+            .unwrap()
+            .registered
+            .get(shortcut)
+            .cloned()
+    });
+    if let Some(action) = action {
+        let triggered = ShortcutTriggered {
+            action,
+            pressed: event.state == ShortcutState::Pressed,
+        };
+        let _ = app.emit("shortcut-triggered", triggered);
+    }
+}
+
+#[tauri::command]
+pub fn get_keymap(
+    store: State<'_, SettingsStore>,
+    shortcuts: State<'_, Shortcuts>,
+) -> Vec<KeyBinding> {
+    let registry = shortcuts.0.lock().unwrap();
+    keymap(&store.get())
+        .into_iter()
+        .map(|(action, binding)| KeyBinding {
+            // `estop` registers the emergency stop itself.
+            registered: if action == ESTOP {
+                binding.accelerator.is_some()
+            } else {
+                registry.registered.values().any(|bound| *bound == action)
+            },
+            error: registry.errors.get(&action).cloned(),
+            accelerator: binding.accelerator,
+            global: binding.global,
+            action,
+        })
+        .collect()
+}
+
+/// Binds `action` to `accelerator` (unbinds it when unset), in-app unless
+/// `global`; the keymap is re-registered at once.
+#[tauri::command]
+pub fn set_binding(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    shortcuts: State<'_, Shortcuts>,
+    action: String,
+    accelerator: Option<String>,
+    global: Option<bool>,
+) -> Result<Vec<KeyBinding>> {
+    users::require(&app, Capability::EditSettings)?;
+    if !ACTIONS.iter().any(|(known, ..)| *known == action) {
+        return Err(Error::Invalid(format!(
+            "unknown shortcut action `{action}`"
+        )));
+    }
+    let bindings = keymap(&store.get());
+    let global = global.unwrap_or_else(|| {
+        bindings
+            .iter()
+            .any(|(other, binding)| *other == action && binding.global)
+    });
+    if let Some(accelerator) = &accelerator {
+        let shortcut = parse(accelerator)?;
+        let taken = bindings.into_iter().find(|(other, binding)| {
+            *other != action
+                && binding
+                    .accelerator
+                    .as_deref()
+                    .is_some_and(|bound| parse(bound).is_ok_and(|bound| bound == shortcut))
+        });
+        if let Some((other, _)) = taken {
+            return Err(Error::Invalid(format!(
+                "{accelerator} is already bound to {other}"
+            )));
+        }
+    }
+    if action == ESTOP {
+        if !global {
+            return Err(Error::Invalid(
+                "the emergency stop shortcut is always global".into(),
+            ));
+        }
+        store.update(&app, |settings| match accelerator {
+            Some(accelerator) => {
+                settings.estop.enabled = true;
+                settings.estop.shortcut = accelerator;
+            }
+            None => settings.estop.enabled = false,
+        })?;
+    } else {
+        store.update(&app, |settings| {
+            settings.shortcuts.bindings.insert(
+                action,
+                Binding {
+                    accelerator,
+                    global,
+                },
+            );
+        })?;
+    }
+    // Registers before returning, ahead of the settings listener.
+    apply(&app, &store.get());
+    Ok(get_keymap(store, shortcuts))
+}
+
+/// Every action of [`ACTIONS`], in order, with its binding in `settings`.
+fn keymap(settings: &Settings) -> Vec<(String, Binding)> {
+    ACTIONS
+        .iter()
+        .map(|(action, accelerator, global)| {
+            let binding = if *action == ESTOP {
+                Binding {
+                    accelerator: settings
+                        .estop
+                        .enabled
+                        .then(|| settings.estop.shortcut.clone()),
+                    global: true,
+                }
+            } else {
+                settings
+                    .shortcuts
+                    .bindings
+                    .get(*action)
+                    .cloned()
+                    .unwrap_or_else(|| Binding {
+                        accelerator: accelerator.map(str::to_string),
+                        global: *global,
+                    })
+            };
+            (action.to_string(), binding)
+        })
+        .collect()
+}
+
+fn parse(accelerator: &str) -> Result<Shortcut> {
+    accelerator
+        .parse::<Shortcut>()
+        .map_err(|err| Error::Invalid(format!("shortcut `{accelerator}`: {err}")))
+}
+
+/// Registers the bindings in effect and releases the rest.
+fn apply(app: &AppHandle, settings: &Settings) {
+    let shortcuts = app.state::<Shortcuts>();
+    let mut registry = shortcuts.0.lock().unwrap();
+    let mut errors = HashMap::new();
+    // In the order of `ACTIONS`, so the emergency stop keeps its keys.
+    let mut taken: HashMap<Shortcut, String> = HashMap::new();
+    let mut wanted: HashMap<Shortcut, String> = HashMap::new();
+    for (action, binding) in keymap(settings) {
+        let Some(accelerator) = binding.accelerator else {
+            continue;
+        };
+        let shortcut = match parse(&accelerator) {
+            Ok(shortcut) => shortcut,
+            Err(err) => {
+                errors.insert(action, err.to_string());
+                continue;
+            }
+        };
+        if let Some(other) = taken.get(&shortcut) {
+            errors.insert(action, format!("{accelerator} is already bound to {other}"));
+            continue;
+        }
+        taken.insert(shortcut, action.clone());
+        if action != ESTOP && (binding.global || registry.focused) {
+            wanted.insert(shortcut, action);
+        }
+    }
+
+    let stale: Vec<Shortcut> = registry
+        .registered
+        .keys()
+        .filter(|shortcut| wanted.get(shortcut) != registry.registered.get(shortcut))
+        .copied()
+        .collect();
+    for shortcut in stale {
+        registry.registered.remove(&shortcut);
+        if let Err(err) = app.global_shortcut().unregister(shortcut) {
+            tracing::warn!(%shortcut, "shortcut not released: {err}");
+        }
+    }
+    for (shortcut, action) in wanted {
+        if registry.registered.contains_key(&shortcut) {
+            continue;
+        }
+        match app.global_shortcut().register(shortcut) {
+            Ok(()) => {
+                registry.registered.insert(shortcut, action);
+            }
+            Err(err) => {
+                tracing::warn!(%shortcut, %action, "shortcut unavailable: {err}");
+                errors.insert(action, err.to_string());
+            }
+        }
+    }
+    registry.errors = errors;
+}
@@ -0,0 +1,731 @@
+//! Supervisor for the `percus-server` sidecar.
+//!
+//! The backend is restarted with exponential backoff whenever it terminates,
+//! and the frontend is told via `backend-restarted` so it can reconnect.
+//! On app exit the child is asked to terminate and force-killed if it does
+//! not stop within [`SHUTDOWN_TIMEOUT`]. [`restart`] stops the child the same
+//! way and relaunches it immediately, e.g. after a profile switch.
+//! A backend a crashed shell left running is terminated before the first
+//! spawn; see [`crate::orphans`].
+//!
+//! Cells with several arms run one more backend per extra robot as a
+//! session ([`create_session`]): supervised the same way, with its own
+//! ports, profile, backend settings and data directory. Everything else in
+//! the shell talks to the primary backend. Session backends report under
+//! `session:<id>:` (`session:<id>:backend-restarted`, `...:backend-ready`,
+//! `...:backend-log`, `...:backend-error`), and `session-stopped` follows
+//! [`stop_session`].
+//!
+//! A simulator the active profile runs is stopped with the backend on exit;
+//! see [`crate::simulator`].
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, ExitRequestApi, Manager, State};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent, TerminatedPayload};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::Notify;
+
+use crate::auth;
+use crate::backend_errors::ErrorClassifier;
+use crate::backend_socket;
+use crate::backend_tls;
+use crate::calibration;
+use crate::cli::LaunchOptions;
+use crate::crash;
+use crate::error::{Error, Result};
+use crate::frames::FrameRings;
+use crate::integrity::{self, IntegrityStatus};
+use crate::licensing::{self, Feature};
+use crate::logging::{SidecarLog, Stream};
+use crate::notifications::{self, Action, Category, Notice};
+use crate::orphans;
+use crate::otel;
+use crate::permissions;
+use crate::profiles::Profiles;
+use crate::pyenv;
+use crate::readiness;
+use crate::secrets;
+use crate::settings::{BackendSettings, BackendTransport, SettingsStore};
+use crate::simulator;
+
+/// Port tried first so a default install keeps the familiar URL.
+pub const PREFERRED_PORT: u16 = 8000;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A run lasting at least this long is considered healthy and resets the backoff.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// How long the backend gets to release the port and cameras before it is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendRestarted {
+    restart_count: u32,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+}
+
+/// Snapshot returned by [`backend_status`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendStatus {
+    pub running: bool,
+    pub profile: Option<String>,
+    pub pid: Option<u32>,
+    pub port: u16,
+    pub uptime_secs: Option<u64>,
+    pub last_exit_code: Option<i32>,
+    pub restart_count: u32,
+    /// Verification of the binary last launched.
+    pub integrity: Option<IntegrityStatus>,
+}
+
+#[derive(Default)]
+struct RunInfo {
+    profile: Option<String>,
+    started_at: Option<Instant>,
+    last_exit_code: Option<i32>,
+    restart_count: u32,
+}
+
+/// A backend run next to the primary one, configured by the caller.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionConfig {
+    /// Letters, digits, `-` and `_`; namespaces the session's events.
+    pub id: String,
+    /// Launch profile; the active one when unset.
+    pub profile: Option<String>,
+    /// Used in place of the `backend` settings section.
+    #[serde(default)]
+    pub backend: BackendSettings,
+    /// Passed as `PHYSICAL_AI_DATA_DIR`; `sessions/<id>` in the app data
+    /// directory when unset.
+    pub data_dir: Option<PathBuf>,
+}
+
+/// Returned by [`list_sessions`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStatus {
+    pub id: String,
+    pub data_dir: PathBuf,
+    pub tls_port: u16,
+    pub backend: BackendStatus,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionStopped {
+    id: String,
+}
+
+struct Session {
+    config: SessionConfig,
+    data_dir: PathBuf,
+    state: SidecarState,
+}
+
+/// Sessions started with [`create_session`], by id.
+#[derive(Default)]
+pub struct Sessions(Mutex<HashMap<String, Arc<Session>>>);
+
+/// Shared state of a supervised sidecar.
+#[derive(Default)]
+pub struct SidecarState {
+    /// Plain port serving the webview; 0 while it uses [`Self::socket`].
+    port: AtomicU16,
+    /// Port serving the shell over mutual TLS; see [`backend_tls`].
+    tls_port: AtomicU16,
+    /// Socket or pipe serving the webview; see [`backend_socket`].
+    socket: Mutex<Option<PathBuf>>,
+    integrity: Mutex<Option<IntegrityStatus>>,
+    child: Mutex<Option<CommandChild>>,
+    run: Mutex<RunInfo>,
+    /// Kept across runs so its notifications stay rate-limited through a
+    /// crash loop.
+    errors: Mutex<Option<ErrorClassifier>>,
+    /// The primary backend's supervisor loop.
+    supervisor: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    shutting_down: AtomicBool,
+    restart_requested: AtomicBool,
+    terminated: Notify,
+}
+
+impl SidecarState {
+    pub fn port(&self) -> u16 {
+        self.port.load(Ordering::Relaxed)
+    }
+
+    pub fn tls_port(&self) -> u16 {
+        self.tls_port.load(Ordering::Relaxed)
+    }
+
+    pub fn socket(&self) -> Option<PathBuf> {
+        self.socket.lock().unwrap().clone()
+    }
+
+    fn is_running(&self) -> bool {
+        self.child.lock().unwrap().is_some()
+    }
+
+    pub fn status(&self) -> BackendStatus {
+        let pid = self.child.lock().unwrap().as_ref().map(CommandChild::pid);
+        let run = self.run.lock().unwrap();
+        BackendStatus {
+            running: pid.is_some(),
+            profile: run.profile.clone(),
+            pid,
+            port: self.port(),
+            uptime_secs: pid
+                .and(run.started_at)
+                .map(|started| started.elapsed().as_secs()),
+            last_exit_code: run.last_exit_code,
+            restart_count: run.restart_count,
+            integrity: self.integrity.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Reports whether the backend is alive, for the UI status widget.
+#[tauri::command]
+pub fn backend_status(state: State<'_, SidecarState>) -> BackendStatus {
+    state.status()
+}
+
+/// Returns the port of the local backend so the webview never hardcodes it;
+/// 0 while it serves a socket (see [`backend_socket::get_backend_url`]).
+#[tauri::command]
+pub fn get_backend_port(state: State<'_, SidecarState>) -> u16 {
+    state.port()
+}
+
+/// Restarts the backend, e.g. to apply changed settings.
+#[tauri::command]
+pub async fn restart_backend(app: AppHandle) {
+    restart(&app).await;
+}
+
+/// Starts a backend session, e.g. for the second arm of a cell.
+#[tauri::command]
+pub fn create_session(
+    app: AppHandle,
+    sessions: State<'_, Sessions>,
+    config: SessionConfig,
+) -> Result<SessionStatus> {
+    licensing::require(&app, Feature::MultiRobot)?;
+    let valid_id = !config.id.is_empty()
+        && config.id.len() <= 32
+        && config
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    if !valid_id {
+        return Err(Error::Invalid(format!("session id {}", config.id)));
+    }
+    if let Some(profile) = &config.profile {
+        if app.state::<Profiles>().get(profile).is_none() {
+            return Err(Error::NotFound(format!("profile `{profile}`")));
+        }
+    }
+    let data_dir = match &config.data_dir {
+        Some(dir) => dir.clone(),
+        None => app.path().app_data_dir()?.join("sessions").join(&config.id),
+    };
+    std::fs::create_dir_all(&data_dir)?;
+    let mut sessions = sessions.0.lock().unwrap();
+    if sessions.contains_key(&config.id) {
+        return Err(Error::DeviceBusy(format!("session {} exists", config.id)));
+    }
+    let session = Arc::new(Session {
+        config,
+        data_dir,
+        state: SidecarState::default(),
+    });
+    sessions.insert(session.config.id.clone(), session.clone());
+    tracing::info!(session = %session.config.id, "session created");
+    tauri::async_runtime::spawn(supervise(app, Some(session.clone())));
+    Ok(session.status())
+}
+
+#[tauri::command]
+pub fn list_sessions(sessions: State<'_, Sessions>) -> Vec<SessionStatus> {
+    let sessions = sessions.0.lock().unwrap();
+    let mut statuses: Vec<SessionStatus> = sessions.values().map(|s| s.status()).collect();
+    statuses.sort_by(|a, b| a.id.cmp(&b.id));
+    statuses
+}
+
+/// Stops a session's backend for good.
+#[tauri::command]
+pub async fn stop_session(app: AppHandle, sessions: State<'_, Sessions>, id: String) -> Result<()> {
+    let session = sessions
+        .0
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("session {id}")))?;
+    session.state.shutting_down.store(true, Ordering::SeqCst);
+    stop_child(&session.state).await;
+    tracing::info!(session = %id, "session stopped");
+    let _ = app.emit("session-stopped", SessionStopped { id });
+    Ok(())
+}
+
+/// Event `event` of session `id`'s backend.
+pub fn session_event(id: &str, event: &str) -> String {
+    format!("session:{id}:{event}")
+}
+
+impl Session {
+    fn status(&self) -> SessionStatus {
+        SessionStatus {
+            id: self.config.id.clone(),
+            data_dir: self.data_dir.clone(),
+            tls_port: self.state.tls_port(),
+            backend: self.state.status(),
+        }
+    }
+}
+
+/// Picks a port for the next spawn, keeping the previous one while it is
+/// free on the loopback address the backend binds. Only the primary backend
+/// tries [`PREFERRED_PORT`].
+fn allocate_port(previous: u16, taken: u16, preferred: bool) -> std::io::Result<u16> {
+    let preferred = if preferred { PREFERRED_PORT } else { 0 };
+    for candidate in [previous, preferred] {
+        if candidate != 0
+            && candidate != taken
+            && TcpListener::bind((Ipv4Addr::LOCALHOST, candidate)).is_ok()
+        {
+            return Ok(candidate);
+        }
+    }
+    loop {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        if port != taken {
+            return Ok(port);
+        }
+    }
+}
+
+/// Starts the supervisor loop on the async runtime, once the OS permissions
+/// the backend needs are asked for.
+pub fn start(app: AppHandle) {
+    app.manage(SidecarState::default());
+    app.manage(Sessions::default());
+    let supervisor = tauri::async_runtime::spawn({
+        let app = app.clone();
+        async move {
+            permissions::ensure().await;
+            supervise(app, None).await;
+        }
+    });
+    *app.state::<SidecarState>().supervisor.lock().unwrap() = Some(supervisor);
+}
+
+/// Supervises the primary backend again after [`shutdown`], e.g. when what
+/// it was shut down for failed. Sessions stay stopped.
+pub async fn resume(app: &AppHandle) {
+    let state = app.state::<SidecarState>();
+    let previous = state.supervisor.lock().unwrap().take();
+    if let Some(previous) = previous {
+        // Sees `shutting_down` and ends; only then may the flag be cleared.
+        let _ = previous.await;
+    }
+    state.shutting_down.store(false, Ordering::SeqCst);
+    tracing::info!("backend supervisor resumed");
+    let supervisor = tauri::async_runtime::spawn(supervise(app.clone(), None));
+    *state.supervisor.lock().unwrap() = Some(supervisor);
+}
+
+/// Runs the primary backend, or `session`'s, until shut down.
+async fn supervise(app: AppHandle, session: Option<Arc<Session>>) {
+    let primary = app.state::<SidecarState>();
+    let state = session.as_ref().map_or(&*primary, |session| &session.state);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut restart_count = 0;
+    let mut last_exit: Option<TerminatedPayload> = None;
+    // One notification per crash streak, which a stable run ends.
+    let mut crash_notified = false;
+
+    while !state.shutting_down.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        match run_once(&app, session.as_deref(), restart_count, last_exit.take()).await {
+            Ok(exit) => last_exit = exit,
+            Err(err) => {
+                tracing::error!(session = ?session_id(&session), "failed to spawn backend: {err}")
+            }
+        }
+        state.child.lock().unwrap().take();
+        state.run.lock().unwrap().last_exit_code = last_exit.as_ref().and_then(|exit| exit.code);
+        state.terminated.notify_waiters();
+        if state.shutting_down.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let stable = started.elapsed() >= STABLE_RUN;
+        if stable {
+            crash_notified = false;
+        }
+        if state.restart_requested.swap(false, Ordering::SeqCst) {
+            backoff = INITIAL_BACKOFF;
+            tracing::info!("restarting backend on request");
+        } else {
+            // Crash reports bundle the primary backend's recent output.
+            let crashed = last_exit.as_ref().is_none_or(|exit| exit.code != Some(0));
+            if crashed && session.is_none() {
+                crash::record_sidecar_crash(&app, last_exit.as_ref());
+            }
+            if crashed && !std::mem::replace(&mut crash_notified, true) {
+                let title = match session_id(&session) {
+                    Some(id) => format!("Backend of session {id} crashed"),
+                    None => "Backend crashed".to_owned(),
+                };
+                let cause = match last_exit.as_ref().map(|exit| (exit.code, exit.signal)) {
+                    Some((Some(code), _)) => format!("exit code {code}"),
+                    Some((None, Some(signal))) => format!("signal {signal}"),
+                    _ => "it could not be started".to_owned(),
+                };
+                notifications::notify(
+                    &app,
+                    Notice::new(
+                        Category::Backend,
+                        title,
+                        format!("Stopped with {cause}; restarting in {backoff:?}"),
+                    )
+                    .action(Action::OpenLogs),
+                );
+            }
+            if stable {
+                backoff = INITIAL_BACKOFF;
+            }
+            tracing::warn!(
+                session = ?session_id(&session),
+                ?last_exit,
+                "backend exited; restarting in {backoff:?}"
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        restart_count += 1;
+        state.run.lock().unwrap().restart_count = restart_count;
+    }
+}
+
+fn session_id(session: &Option<Arc<Session>>) -> Option<&str> {
+    session.as_ref().map(|session| session.config.id.as_str())
+}
+
+/// Spawns the sidecar once and waits for it to terminate.
+///
+/// The backend's traces continue this span.
+#[tracing::instrument(
+    name = "sidecar.run",
+    skip_all,
+    fields(session = session.map(|session| session.config.id.as_str()), restart_count)
+)]
+async fn run_once(
+    app: &AppHandle,
+    session: Option<&Session>,
+    restart_count: u32,
+    last_exit: Option<TerminatedPayload>,
+) -> Result<Option<TerminatedPayload>> {
+    let primary = app.state::<SidecarState>();
+    let state = session.map_or(&*primary, |session| &session.state);
+    let (profile, spec) = match session.and_then(|session| session.config.profile.clone()) {
+        Some(name) => {
+            let spec = app
+                .state::<Profiles>()
+                .get(&name)
+                .ok_or_else(|| Error::NotFound(format!("profile `{name}`")))?;
+            (name, spec)
+        }
+        None => app.state::<Profiles>().active(),
+    };
+    let previous = state.integrity.lock().unwrap().clone();
+    let integrity = integrity::verify(&spec, previous).await;
+    *state.integrity.lock().unwrap() = Some(integrity.clone());
+    integrity.allow_launch()?;
+    let backend = match session {
+        Some(session) => session.config.backend.clone(),
+        None => app.state::<SettingsStore>().backend_for_spawn(),
+    };
+    // Command-line options apply to the primary backend only.
+    let launch = match session {
+        Some(_) => LaunchOptions::default(),
+        None => app.state::<LaunchOptions>().inner().clone(),
+    };
+    let token_env = auth::backend_env(app).await;
+    let lock = match session {
+        Some(session) => session.data_dir.join(orphans::LOCK_FILE),
+        None => app.path().app_data_dir()?.join(orphans::LOCK_FILE),
+    };
+    orphans::reap(&lock).await;
+    let socket = match session {
+        None if backend.transport == BackendTransport::Socket
+            && backend_socket::SUPPORTED
+            && launch.port.is_none() =>
+        {
+            let path = backend_socket::path(app)?;
+            backend_socket::remove_stale(&path);
+            Some(path)
+        }
+        _ => None,
+    };
+    let port = match socket {
+        Some(_) => 0,
+        None => match launch.port {
+            Some(port) => port,
+            None => allocate_port(state.port(), 0, session.is_none())?,
+        },
+    };
+    let tls_port = allocate_port(state.tls_port(), port, false)?;
+    let listen_args = match &socket {
+        Some(path) => backend_socket::spawn_args(path),
+        // Only the shell talks to it; `backend.extraArgs` may widen this.
+        None => vec![
+            "--host".into(),
+            "127.0.0.1".into(),
+            "--port".into(),
+            port.to_string(),
+        ],
+    };
+    // A session's frame rings stay out of the shell's, which serve the
+    // primary backend's cameras.
+    let (frame_dir, data_env) = match session {
+        Some(session) => (
+            session.data_dir.join("frames"),
+            Some(("PHYSICAL_AI_DATA_DIR", session.data_dir.clone())),
+        ),
+        None => {
+            if let Some(dir) = &launch.data_dir {
+                std::fs::create_dir_all(dir)?;
+            }
+            (
+                app.state::<FrameRings>().dir().to_owned(),
+                launch.data_dir.map(|dir| ("PHYSICAL_AI_DATA_DIR", dir)),
+            )
+        }
+    };
+    let command = match (&spec.module, &spec.program) {
+        (Some(module), _) => app
+            .shell()
+            .command(pyenv::ensure(app).await?)
+            .args(["-m", module]),
+        (None, Some(program)) => app.shell().command(program),
+        (None, None) => app.shell().sidecar(&spec.sidecar)?,
+    };
+    let (mut rx, child) = command
+        .args(&spec.args)
+        .args(&backend.extra_args)
+        .args(launch.backend_args)
+        .args(listen_args)
+        .args(backend_tls::spawn_args(app, tls_port))
+        .envs(spec.env)
+        .envs(backend.env())
+        .envs(launch.backend_env)
+        .envs(secrets::backend_env(&backend.secret_env))
+        .envs(token_env)
+        .envs(otel::backend_env(app))
+        .envs(simulator::backend_env(app, &profile))
+        .env("PHI_FRAME_DIR", frame_dir)
+        .envs(data_env)
+        .envs(
+            calibration::dir(app)
+                .ok()
+                .map(|dir| ("PHI_CAMERA_CALIBRATION_DIR", dir)),
+        )
+        .spawn()?;
+    tracing::info!(
+        session = ?session.map(|session| &session.config.id),
+        %profile,
+        pid = child.pid(),
+        port,
+        tls_port,
+        socket = ?socket,
+        "backend started"
+    );
+    state.port.store(port, Ordering::Relaxed);
+    state.tls_port.store(tls_port, Ordering::Relaxed);
+    *state.socket.lock().unwrap() = socket;
+    orphans::record(&lock, child.pid());
+    *state.child.lock().unwrap() = Some(child);
+    {
+        let mut run = state.run.lock().unwrap();
+        run.profile = Some(profile);
+        run.started_at = Some(Instant::now());
+    }
+    let ready = match session {
+        Some(session) => tauri::async_runtime::spawn(readiness::wait_until_session_ready(
+            app.clone(),
+            session.config.id.clone(),
+            port,
+            tls_port,
+        )),
+        None => tauri::async_runtime::spawn(readiness::wait_until_ready(app.clone(), port)),
+    };
+
+    if restart_count > 0 {
+        let payload = BackendRestarted {
+            restart_count,
+            exit_code: last_exit.as_ref().and_then(|exit| exit.code),
+            signal: last_exit.as_ref().and_then(|exit| exit.signal),
+        };
+        let event = match session {
+            Some(session) => session_event(&session.config.id, "backend-restarted"),
+            None => "backend-restarted".to_owned(),
+        };
+        let _ = app.emit(&event, payload);
+    }
+
+    let mut log = match session {
+        Some(session) => SidecarLog::for_session(app, &session.config.id),
+        None => SidecarLog::new(app),
+    };
+    let mut errors = state
+        .errors
+        .lock()
+        .unwrap()
+        .take()
+        .unwrap_or_else(|| match session {
+            Some(session) => ErrorClassifier::for_session(app, &session.config.id),
+            None => ErrorClassifier::new(app),
+        });
+    errors.restarted();
+    let mut terminated = None;
+    while let Some(event) = rx.recv().await {
+        match event {
+            CommandEvent::Stdout(line) => log.line(Stream::Stdout, &line),
+            CommandEvent::Stderr(line) => {
+                log.line(Stream::Stderr, &line);
+                errors.line(&line);
+            }
+            CommandEvent::Terminated(payload) => {
+                terminated = Some(payload);
+                break;
+            }
+            _ => {}
+        }
+    }
+    *state.errors.lock().unwrap() = Some(errors);
+    ready.abort();
+    orphans::clear(&lock);
+    Ok(terminated)
+}
+
+/// Stops the supervisors and terminates the sidecars, force-killing them on
+/// timeout.
+pub async fn shutdown(app: &AppHandle) {
+    let sessions = take_sessions(app);
+    for session in &sessions {
+        session.state.shutting_down.store(true, Ordering::SeqCst);
+    }
+    let state = app.state::<SidecarState>();
+    state.shutting_down.store(true, Ordering::SeqCst);
+    let stopping = sessions.iter().map(|session| stop_child(&session.state));
+    futures_util::future::join3(
+        stop_child(&state),
+        futures_util::future::join_all(stopping),
+        simulator::stop(app),
+    )
+    .await;
+}
+
+fn take_sessions(app: &AppHandle) -> Vec<Arc<Session>> {
+    let sessions = app.state::<Sessions>();
+    let mut sessions = sessions.0.lock().unwrap();
+    sessions.drain().map(|(_, session)| session).collect()
+}
+
+/// Terminates the sidecar and lets the supervisor relaunch it without backoff.
+pub async fn restart(app: &AppHandle) {
+    let state = app.state::<SidecarState>();
+    state.restart_requested.store(true, Ordering::SeqCst);
+    if !stop_child(&state).await {
+        state.restart_requested.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Gracefully stops the current child; returns `false` if none was running.
+async fn stop_child(state: &SidecarState) -> bool {
+    let terminated = state.terminated.notified();
+    let pid = match state.child.lock().unwrap().as_ref() {
+        Some(child) => child.pid(),
+        None => return false,
+    };
+    if request_termination(pid) {
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, terminated)
+            .await
+            .is_ok()
+        {
+            return true;
+        }
+        tracing::warn!(
+            pid,
+            "backend did not stop within {SHUTDOWN_TIMEOUT:?}; killing"
+        );
+    }
+    kill_child(state);
+    true
+}
+
+/// Kills the sidecars immediately if they are still running.
+pub fn kill_now(app: &AppHandle) {
+    for session in take_sessions(app) {
+        session.state.shutting_down.store(true, Ordering::SeqCst);
+        kill_child(&session.state);
+    }
+    let state = app.state::<SidecarState>();
+    state.shutting_down.store(true, Ordering::SeqCst);
+    kill_child(&state);
+    simulator::kill_now(app);
+}
+
+fn kill_child(state: &SidecarState) {
+    let child = state.child.lock().unwrap().take();
+    if let Some(child) = child {
+        let _ = child.kill();
+    }
+}
+
+/// Defers the exit until the sidecars have been shut down.
+pub fn on_exit_requested(app: &AppHandle, api: &ExitRequestApi) {
+    let sessions_running = app
+        .state::<Sessions>()
+        .0
+        .lock()
+        .unwrap()
+        .values()
+        .any(|session| session.state.is_running());
+    if !app.state::<SidecarState>().is_running() && !sessions_running && !simulator::is_running(app)
+    {
+        return;
+    }
+    api.prevent_exit();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        shutdown(&app).await;
+        app.exit(0);
+    });
+}
+
+/// Asks the process to exit; returns `false` when no graceful signal exists.
+#[cfg(unix)]
+pub fn request_termination(pid: u32) -> bool {
+    // SAFETY: `kill` has no memory-safety preconditions; a stale pid only yields ESRCH.
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn request_termination(_pid: u32) -> bool {
+    false
+}
@@ -0,0 +1,371 @@
+//! Simulator processes (MuJoCo, Isaac Sim) for validation runs.
+//!
+//! A profile with a `simulator` table (see [`crate::profiles`]) has its
+//! simulator started when the profile is selected, before the backend,
+//! and stopped when another profile is. The simulator is restarted with
+//! exponential backoff whenever it exits, like the backend, and told to
+//! terminate on app exit. The backend it belongs to is spawned with
+//! `PHI_SIM_HOST` and `PHI_SIM_PORT` pointing at it.
+//!
+//! [`start_sim`] and [`stop_sim`] start and stop it by hand; a simulator
+//! stopped that way stays stopped until started again or the profile is
+//! switched. Changes are reported as `sim-status`, which also says once
+//! the simulator accepts connections on its port.
+
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::sync::oneshot;
+
+use crate::error::{Error, Result};
+use crate::profiles::{Profiles, SimulatorSpec};
+use crate::sidecar;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// A run lasting at least this long resets the backoff.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// Simulators save state and release GPUs slower than the backend.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Isaac Sim takes minutes to load a stage.
+const READY_TIMEOUT: Duration = Duration::from_secs(300);
+const READY_POLL: Duration = Duration::from_millis(500);
+
+/// Payload of `sim-status` and [`sim_status`].
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimStatus {
+    pub running: bool,
+    /// Whether the simulator accepts connections on `port`.
+    pub ready: bool,
+    pub profile: Option<String>,
+    pub label: Option<String>,
+    pub pid: Option<u32>,
+    pub port: Option<u16>,
+    pub scene: Option<PathBuf>,
+    pub restart_count: u32,
+    pub last_exit_code: Option<i32>,
+}
+
+struct Running {
+    profile: String,
+    port: u16,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+/// The simulator being supervised, if any.
+#[derive(Default)]
+pub struct Simulator {
+    running: StdMutex<Option<Running>>,
+    status: Arc<StdMutex<SimStatus>>,
+}
+
+/// Starts the active profile's simulator, if it has one.
+pub fn init(app: &AppHandle) {
+    app.manage(Simulator::default());
+    let (profile, spec) = app.state::<Profiles>().active();
+    if let Some(spec) = spec.simulator {
+        if let Err(err) = launch(app, profile, spec) {
+            tracing::warn!("simulator not started: {err}");
+        }
+    }
+}
+
+/// Starts the simulator of `profile`, by default the active one.
+#[tauri::command]
+pub async fn start_sim(app: AppHandle, profile: Option<String>) -> Result<SimStatus> {
+    let profiles = app.state::<Profiles>();
+    let (profile, spec) = match profile {
+        Some(name) => {
+            let spec = profiles
+                .get(&name)
+                .ok_or_else(|| Error::NotFound(format!("profile `{name}`")))?;
+            (name, spec)
+        }
+        None => profiles.active(),
+    };
+    let spec = spec
+        .simulator
+        .ok_or_else(|| Error::NotFound(format!("simulator of profile `{profile}`")))?;
+    launch(&app, profile, spec)?;
+    Ok(sim_status(app.state::<Simulator>()))
+}
+
+/// Stops the simulator until it is started again or the profile changes.
+#[tauri::command]
+pub async fn stop_sim(app: AppHandle) -> Result<SimStatus> {
+    if !stop(&app).await {
+        return Err(Error::Invalid("no simulator is running".into()));
+    }
+    Ok(sim_status(app.state::<Simulator>()))
+}
+
+#[tauri::command]
+pub fn sim_status(simulator: State<'_, Simulator>) -> SimStatus {
+    simulator.status.lock().unwrap().clone()
+}
+
+/// Runs the simulator of the newly active profile in place of the current
+/// one; called on a profile switch.
+pub async fn follow_profile(app: &AppHandle) {
+    let (profile, spec) = app.state::<Profiles>().active();
+    let current = app
+        .state::<Simulator>()
+        .running
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|running| running.profile.clone());
+    if current.as_ref() == Some(&profile) {
+        return;
+    }
+    stop(app).await;
+    if let Some(spec) = spec.simulator {
+        if let Err(err) = launch(app, profile, spec) {
+            tracing::warn!("simulator not started: {err}");
+        }
+    }
+}
+
+/// Environment telling the backend of `profile` where its simulator is.
+pub fn backend_env(app: &AppHandle, profile: &str) -> BTreeMap<String, String> {
+    let simulator = app.state::<Simulator>();
+    let running = simulator.running.lock().unwrap();
+    match running.as_ref() {
+        Some(running) if running.profile == profile => BTreeMap::from([
+            ("PHI_SIM_HOST".into(), Ipv4Addr::LOCALHOST.to_string()),
+            ("PHI_SIM_PORT".into(), running.port.to_string()),
+        ]),
+        _ => BTreeMap::new(),
+    }
+}
+
+/// Whether a simulator is being supervised.
+pub fn is_running(app: &AppHandle) -> bool {
+    app.try_state::<Simulator>()
+        .is_some_and(|simulator| simulator.running.lock().unwrap().is_some())
+}
+
+/// Terminates the simulator, force-killing it on timeout; returns `false`
+/// if none was running.
+pub async fn stop(app: &AppHandle) -> bool {
+    let Some(simulator) = app.try_state::<Simulator>() else {
+        return false;
+    };
+    let Some(running) = simulator.running.lock().unwrap().take() else {
+        return false;
+    };
+    let _ = running.stop.send(());
+    let _ = running.task.await;
+    true
+}
+
+/// Kills the simulator immediately if it is still running.
+pub fn kill_now(app: &AppHandle) {
+    if let Some(simulator) = app.try_state::<Simulator>() {
+        if let Some(running) = simulator.running.lock().unwrap().take() {
+            // Dropping the child kills it.
+            running.task.abort();
+        }
+    }
+}
+
+fn launch(app: &AppHandle, profile: String, spec: SimulatorSpec) -> Result<()> {
+    let simulator = app.state::<Simulator>();
+    let mut running = simulator.running.lock().unwrap();
+    if let Some(current) = running.as_ref() {
+        return Err(Error::DeviceBusy(format!(
+            "the simulator of profile `{}` is running",
+            current.profile
+        )));
+    }
+    let scene = match &spec.scene {
+        Some(scene) => {
+            let scene = app.path().app_config_dir()?.join(scene);
+            if !scene.exists() {
+                return Err(Error::NotFound(format!("scene {}", scene.display())));
+            }
+            Some(scene)
+        }
+        None => None,
+    };
+    let port = match spec.port {
+        Some(port) => port,
+        None => TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+            .local_addr()?
+            .port(),
+    };
+    *simulator.status.lock().unwrap() = SimStatus {
+        profile: Some(profile.clone()),
+        label: spec.label.clone(),
+        port: Some(port),
+        scene: scene.clone(),
+        ..SimStatus::default()
+    };
+    let (stop, stopped) = oneshot::channel();
+    let task = tauri::async_runtime::spawn(supervise(
+        app.clone(),
+        spec,
+        scene,
+        port,
+        simulator.status.clone(),
+        stopped,
+    ));
+    tracing::info!(%profile, port, "simulator supervisor started");
+    *running = Some(Running {
+        profile,
+        port,
+        stop,
+        task,
+    });
+    Ok(())
+}
+
+/// Runs the simulator until `stopped`, restarting it when it exits.
+async fn supervise(
+    app: AppHandle,
+    spec: SimulatorSpec,
+    scene: Option<PathBuf>,
+    port: u16,
+    status: Arc<StdMutex<SimStatus>>,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match spawn(&spec, scene.as_deref(), port) {
+            Ok(mut child) => {
+                let pid = child.id();
+                tracing::info!(pid, port, "simulator started");
+                update(&app, &status, |status| {
+                    status.running = true;
+                    status.ready = false;
+                    status.pid = pid;
+                });
+                let ready = tauri::async_runtime::spawn(wait_until_ready(
+                    app.clone(),
+                    status.clone(),
+                    port,
+                ));
+                let exit = tokio::select! {
+                    exit = child.wait() => exit,
+                    _ = &mut stopped => {
+                        ready.abort();
+                        terminate(&mut child).await;
+                        update(&app, &status, |status| {
+                            status.running = false;
+                            status.ready = false;
+                            status.pid = None;
+                        });
+                        tracing::info!("simulator stopped");
+                        return;
+                    }
+                };
+                ready.abort();
+                let code = exit.ok().and_then(|exit| exit.code());
+                tracing::warn!(code, "simulator exited");
+                update(&app, &status, |status| {
+                    status.running = false;
+                    status.ready = false;
+                    status.pid = None;
+                    status.last_exit_code = code;
+                });
+            }
+            Err(err) => tracing::error!("failed to spawn simulator: {err}"),
+        }
+        if started.elapsed() >= STABLE_RUN {
+            backoff = INITIAL_BACKOFF;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = &mut stopped => return,
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        update(&app, &status, |status| status.restart_count += 1);
+    }
+}
+
+fn spawn(spec: &SimulatorSpec, scene: Option<&std::path::Path>, port: u16) -> Result<Child> {
+    let scene = scene.map(|scene| scene.display().to_string());
+    let args = spec.args.iter().map(|arg| {
+        arg.replace("{scene}", scene.as_deref().unwrap_or_default())
+            .replace("{port}", &port.to_string())
+    });
+    let mut child = Command::new(&spec.program)
+        .args(args)
+        .envs(&spec.env)
+        .env("PHI_SIM_PORT", port.to_string())
+        .envs(scene.as_ref().map(|scene| ("PHI_SIM_SCENE", scene)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(stdout) = child.stdout.take() {
+        tauri::async_runtime::spawn(log_lines(stdout));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tauri::async_runtime::spawn(log_lines(stderr));
+    }
+    Ok(child)
+}
+
+async fn log_lines(output: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!(target: "simulator", "{line}");
+    }
+}
+
+async fn terminate(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        if sidecar::request_termination(pid)
+            && tokio::time::timeout(SHUTDOWN_TIMEOUT, child.wait())
+                .await
+                .is_ok()
+        {
+            return;
+        }
+        tracing::warn!(
+            pid,
+            "simulator did not stop within {SHUTDOWN_TIMEOUT:?}; killing"
+        );
+    }
+    let _ = child.kill().await;
+}
+
+async fn wait_until_ready(app: AppHandle, status: Arc<StdMutex<SimStatus>>, port: u16) {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while Instant::now() < deadline {
+        if TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .is_ok()
+        {
+            tracing::info!(port, "simulator ready");
+            update(&app, &status, |status| status.ready = true);
+            return;
+        }
+        tokio::time::sleep(READY_POLL).await;
+    }
+    tracing::warn!(
+        port,
+        "simulator not accepting connections after {READY_TIMEOUT:?}"
+    );
+}
+
+fn update(app: &AppHandle, status: &StdMutex<SimStatus>, change: impl FnOnce(&mut SimStatus)) {
+    let mut status = status.lock().unwrap();
+    change(&mut status);
+    let _ = app.emit("sim-status", status.clone());
+}
@@ -0,0 +1,219 @@
+//! Quality control of relayed camera streams on congested networks.
+//!
+//! Every stream of [`crate::webrtc_relay`] is measured over windows of
+//! `streamQos.intervalMs`: the throughput and frame rate handed to the peer
+//! connection, the packet loss the viewer reports in RTCP receiver reports,
+//! and the frame latency, i.e. the time a frame takes from leaving ffmpeg to
+//! being written to the track plus half the round trip the reports give.
+//!
+//! A stream starts at the `video` settings at the source resolution (level
+//! 0) and steps down to the next of `streamQos.profiles` after a window with
+//! too much loss or latency, back up after `upgradeAfterSecs` without. The
+//! relay applies a level by restarting the stream's encoder, so a step costs
+//! a keyframe. Each window is reported as `stream-quality`.
+
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::watch;
+use webrtc::rtcp::packet::Packet;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+
+use crate::metrics;
+use crate::settings::{Settings, SettingsStore};
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch.
+const NTP_OFFSET: u64 = 2_208_988_800;
+
+/// How a stream is encoded at one quality level.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityProfile {
+    pub name: String,
+    /// Frames are scaled down to this height, keeping their aspect ratio.
+    pub max_height: Option<u32>,
+    pub framerate: u32,
+    pub bitrate_kbps: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamQuality {
+    id: u32,
+    level: usize,
+    profile: QualityProfile,
+    /// Whether this window changed the level.
+    changed: bool,
+    throughput_kbps: f64,
+    fps: f64,
+    loss_percent: f64,
+    latency_ms: f64,
+    rtt_ms: Option<f64>,
+}
+
+/// Measurements of the current window.
+#[derive(Default)]
+struct Window {
+    bytes: u64,
+    frames: u32,
+    send: Duration,
+    /// Worst loss fraction reported, out of 256.
+    loss: u8,
+    rtt: Option<Duration>,
+}
+
+/// Measurements of one stream, fed by the relay.
+#[derive(Default)]
+pub struct StreamStats(StdMutex<Window>);
+
+impl StreamStats {
+    /// Records a frame of `bytes` that took `send` to hand to the track.
+    pub fn frame_sent(&self, bytes: usize, send: Duration) {
+        let mut window = self.0.lock().unwrap();
+        window.bytes += bytes as u64;
+        window.frames += 1;
+        window.send += send;
+    }
+
+    /// Records the receiver reports among RTCP `packets`.
+    pub fn rtcp(&self, packets: &[Box<dyn Packet + Send + Sync>]) {
+        let now = ntp_middle(SystemTime::now());
+        let mut window = self.0.lock().unwrap();
+        let reports = packets
+            .iter()
+            .filter_map(|packet| packet.as_any().downcast_ref::<ReceiverReport>())
+            .flat_map(|report| &report.reports);
+        for report in reports {
+            window.loss = window.loss.max(report.fraction_lost);
+            if report.last_sender_report != 0 {
+                // In 1/65536 s, from the sender report the viewer last saw.
+                let rtt = now
+                    .wrapping_sub(report.last_sender_report)
+                    .wrapping_sub(report.delay);
+                if rtt < 65_536 * 10 {
+                    window.rtt = Some(Duration::from_secs_f64(f64::from(rtt) / 65_536.0));
+                }
+            }
+        }
+    }
+
+    fn take(&self) -> Window {
+        let mut window = self.0.lock().unwrap();
+        let rtt = window.rtt;
+        let taken = std::mem::take(&mut *window);
+        // The round trip is only reported every few seconds.
+        window.rtt = rtt;
+        taken
+    }
+}
+
+/// Quality levels of a stream, best first.
+pub fn profiles(settings: &Settings) -> Vec<QualityProfile> {
+    let mut profiles = vec![QualityProfile {
+        name: "source".into(),
+        max_height: None,
+        framerate: settings.video.framerate,
+        bitrate_kbps: settings.video.bitrate_kbps,
+    }];
+    if settings.stream_qos.enabled {
+        profiles.extend(settings.stream_qos.profiles.iter().cloned());
+    }
+    profiles
+}
+
+/// The profile of `level`, or the lowest one when there are fewer.
+pub fn profile(app: &AppHandle, level: usize) -> QualityProfile {
+    let mut profiles = profiles(&app.state::<SettingsStore>().get());
+    profiles.swap_remove(level.min(profiles.len() - 1))
+}
+
+/// Measures stream `id` and sets its `level` until aborted.
+pub fn spawn(
+    app: AppHandle,
+    id: u32,
+    stats: Arc<StreamStats>,
+    level: watch::Sender<usize>,
+) -> JoinHandle<()> {
+    tauri::async_runtime::spawn(async move {
+        let mut clean = Duration::ZERO;
+        let mut settling = false;
+        loop {
+            let interval = Duration::from_millis(
+                app.state::<SettingsStore>()
+                    .get()
+                    .stream_qos
+                    .interval_ms
+                    .max(100),
+            );
+            tokio::time::sleep(interval).await;
+            let settings = app.state::<SettingsStore>().get();
+            let qos = &settings.stream_qos;
+            let levels = profiles(&settings).len();
+            let window = stats.take();
+            let seconds = interval.as_secs_f64();
+            let loss_percent = f64::from(window.loss) * 100.0 / 256.0;
+            let rtt_ms = window.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0);
+            let send_ms = match window.frames {
+                0 => 0.0,
+                frames => window.send.as_secs_f64() * 1000.0 / f64::from(frames),
+            };
+            let latency_ms = send_ms + rtt_ms.unwrap_or(0.0) / 2.0;
+            let congested = loss_percent > f64::from(qos.max_loss_percent)
+                || latency_ms > f64::from(qos.max_latency_ms);
+
+            let current = (*level.borrow()).min(levels - 1);
+            let mut next = current;
+            if settling {
+                // The window spanning an encoder restart says little.
+                settling = false;
+            } else if congested {
+                clean = Duration::ZERO;
+                next = (current + 1).min(levels - 1);
+            } else {
+                clean += interval;
+                if current > 0 && clean >= Duration::from_secs(qos.upgrade_after_secs) {
+                    clean = Duration::ZERO;
+                    next = current - 1;
+                }
+            }
+            let changed = next != current;
+            if changed {
+                settling = true;
+                tracing::info!(
+                    id,
+                    level = next,
+                    loss_percent,
+                    latency_ms,
+                    "stream quality changed"
+                );
+                if level.send(next).is_err() {
+                    return;
+                }
+            }
+            let quality = StreamQuality {
+                id,
+                level: next,
+                profile: profile(&app, next),
+                changed,
+                throughput_kbps: window.bytes as f64 * 8.0 / 1000.0 / seconds,
+                fps: f64::from(window.frames) / seconds,
+                loss_percent,
+                latency_ms,
+                rtt_ms,
+            };
+            metrics::stream_window(&app, id, quality.fps, quality.throughput_kbps);
+            let _ = app.emit("stream-quality", quality);
+        }
+    })
+}
+
+/// The middle 32 bits of the NTP timestamp of `time`, as RTCP uses them.
+fn ntp_middle(time: SystemTime) -> u32 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since.as_secs() + NTP_OFFSET;
+    let fraction = (u64::from(since.subsec_nanos()) << 32) / 1_000_000_000;
+    (((seconds & 0xFFFF) << 16) | (fraction >> 16)) as u32
+}
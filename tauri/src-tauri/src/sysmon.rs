@@ -0,0 +1,189 @@
+//! Host resource sampling, to tell CPU, GPU and disk bottlenecks apart
+//! when inference stutters.
+//!
+//! A `sysmon` thread samples CPU, memory, disks and, through NVML, NVIDIA
+//! GPUs every [`INTERVAL`], emits each sample as `system-metrics` and keeps
+//! the last [`HISTORY`] for [`get_system_metrics_history`]. Machines without
+//! the NVIDIA driver simply report no GPUs.
+
+use std::collections::VecDeque;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use sysinfo::{Disks, System};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::Result;
+use crate::windows;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+/// Samples kept, ten minutes at [`INTERVAL`].
+const HISTORY: usize = 600;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemMetrics {
+    pub timestamp_ms: u64,
+    /// Average over all cores, 0 to 100.
+    pub cpu_percent: f32,
+    pub cpu_per_core: Vec<f32>,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+    pub swap_used_bytes: u64,
+    pub disks: Vec<DiskMetrics>,
+    pub gpus: Vec<GpuMetrics>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskMetrics {
+    pub mount_point: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    pub read_bytes_per_sec: u64,
+    pub written_bytes_per_sec: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuMetrics {
+    pub index: u32,
+    pub name: String,
+    /// Time a kernel was running, 0 to 100.
+    pub utilization_percent: u32,
+    /// Time device memory was being read or written, 0 to 100.
+    pub memory_utilization_percent: u32,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+    pub temperature_c: Option<u32>,
+    pub power_watts: Option<f32>,
+}
+
+/// Recent samples, oldest first.
+#[derive(Default)]
+pub struct SystemMonitor(StdMutex<VecDeque<SystemMetrics>>);
+
+/// Starts the sampling thread.
+pub fn init(app: &AppHandle) -> Result<()> {
+    app.manage(SystemMonitor::default());
+    let app = app.clone();
+    std::thread::Builder::new()
+        .name("sysmon".into())
+        .spawn(move || sample_loop(&app))?;
+    Ok(())
+}
+
+impl SystemMonitor {
+    /// The most recent sample.
+    pub fn latest(&self) -> Option<SystemMetrics> {
+        self.0.lock().unwrap().back().cloned()
+    }
+}
+
+/// Samples from the last `seconds`, or all that are kept.
+#[tauri::command]
+pub fn get_system_metrics_history(
+    monitor: State<'_, SystemMonitor>,
+    seconds: Option<u64>,
+) -> Vec<SystemMetrics> {
+    let history = monitor.0.lock().unwrap();
+    let since = seconds.map_or(0, |seconds| now_ms().saturating_sub(seconds * 1000));
+    history
+        .iter()
+        .filter(|sample| sample.timestamp_ms >= since)
+        .cloned()
+        .collect()
+}
+
+fn sample_loop(app: &AppHandle) {
+    let mut system = System::new();
+    let mut disks = Disks::new_with_refreshed_list();
+    let nvml = match Nvml::init() {
+        Ok(nvml) => Some(nvml),
+        Err(err) => {
+            tracing::debug!("no NVIDIA GPU metrics: {err}");
+            None
+        }
+    };
+    // CPU usage is measured between two refreshes.
+    system.refresh_cpu_usage();
+    let mut last = Instant::now();
+    loop {
+        std::thread::sleep(INTERVAL.saturating_sub(last.elapsed()));
+        let elapsed = last.elapsed().as_secs_f64().max(f64::EPSILON);
+        last = Instant::now();
+
+        system.refresh_cpu_usage();
+        system.refresh_memory();
+        disks.refresh(true);
+        let per_sec = |bytes: u64| (bytes as f64 / elapsed) as u64;
+        let mut disk_metrics: Vec<DiskMetrics> = Vec::new();
+        for disk in disks.list() {
+            let mount_point = disk.mount_point().display().to_string();
+            if disk_metrics
+                .iter()
+                .any(|seen| seen.mount_point == mount_point)
+            {
+                continue;
+            }
+            let usage = disk.usage();
+            disk_metrics.push(DiskMetrics {
+                mount_point,
+                total_bytes: disk.total_space(),
+                available_bytes: disk.available_space(),
+                read_bytes_per_sec: per_sec(usage.read_bytes),
+                written_bytes_per_sec: per_sec(usage.written_bytes),
+            });
+        }
+
+        let metrics = SystemMetrics {
+            timestamp_ms: now_ms(),
+            cpu_percent: system.global_cpu_usage(),
+            cpu_per_core: system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            memory_used_bytes: system.used_memory(),
+            memory_total_bytes: system.total_memory(),
+            swap_used_bytes: system.used_swap(),
+            disks: disk_metrics,
+            gpus: nvml.as_ref().map(gpu_metrics).unwrap_or_default(),
+        };
+        let monitor = app.state::<SystemMonitor>();
+        let mut history = monitor.0.lock().unwrap();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(metrics.clone());
+        drop(history);
+        windows::emit(app, "system-metrics", metrics);
+    }
+}
+
+pub fn gpu_metrics(nvml: &Nvml) -> Vec<GpuMetrics> {
+    let count = nvml.device_count().unwrap_or(0);
+    (0..count)
+        .filter_map(|index| {
+            let device = nvml.device_by_index(index).ok()?;
+            let utilization = device.utilization_rates().ok()?;
+            let memory = device.memory_info().ok()?;
+            Some(GpuMetrics {
+                index,
+                name: device.name().unwrap_or_default(),
+                utilization_percent: utilization.gpu,
+                memory_utilization_percent: utilization.memory,
+                memory_used_bytes: memory.used,
+                memory_total_bytes: memory.total,
+                temperature_c: device.temperature(TemperatureSensor::Gpu).ok(),
+                power_watts: device.power_usage().ok().map(|mw| mw as f32 / 1000.0),
+            })
+        })
+        .collect()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,418 @@
+//! Teach-point lists captured from the live robot.
+//!
+//! A [`PointList`] is an ordered list of named waypoints with free-form
+//! metadata, kept as `teach/<name>.json` in the app data directory.
+//! [`capture_waypoint`] reads the controller's current pose and joint
+//! angles over the shell's own connection (see [`crate::daihen_fd`]) and
+//! inserts them into a list. Points are reordered and removed with their
+//! own commands; names, speeds and metadata are edited by saving the whole
+//! list with [`save_point_list`].
+//!
+//! [`export_point_list`] writes a list as JSON for the backend or as a job
+//! text. The job text is not a job file the FD controller loads: it is
+//! this shell's own ASCII format, in the style of the controller's line
+//! protocol, for reviewing a list or re-entering it on the teach pendant.
+//! Comments start with `'`:
+//!
+//! ```text
+//! JOB <program or list name>
+//! P1 = BASE x y z rx ry rz JOINT j1 j2 j3 j4 j5 j6
+//! MOVEJ P1 V=20
+//! MOVEL P2 V=150
+//! END
+//! ```
+//!
+//! with the pose in mm and degrees and `V` in percent for joint moves and
+//! mm/s, at most `jog.maxLinearMmS`, for linear ones; [`encode_job`] is
+//! the only place that knows it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::daihen_fd::FdController;
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::settings::SettingsStore;
+use crate::users::{self, Capability};
+
+const TEACH_DIR: &str = "teach";
+const JSON_FORMAT: &str = "percus-teach/1";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Motion {
+    /// Joint-interpolated; `speed` in percent of the maximum.
+    #[default]
+    Joint,
+    /// Straight line in the base frame; `speed` in mm/s.
+    Linear,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Waypoint {
+    pub name: String,
+    /// Base frame, mm and degrees.
+    pub pose: [f64; 6],
+    /// Degrees.
+    pub joints: [f64; 6],
+    #[serde(default)]
+    pub motion: Motion,
+    pub speed: f64,
+    #[serde(default)]
+    pub comment: Option<String>,
+    pub captured_at: DateTime<Utc>,
+    /// The signed-in user who captured it, if any.
+    #[serde(default)]
+    pub captured_by: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointList {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Program number the job is loaded as on the controller.
+    #[serde(default)]
+    pub program: Option<u32>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub points: Vec<Waypoint>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Returned by [`list_point_lists`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointListSummary {
+    pub name: String,
+    pub description: String,
+    pub points: usize,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Argument of [`capture_waypoint`].
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CaptureRequest {
+    /// `P<n>` with the next free number if unset.
+    pub name: Option<String>,
+    /// Position to insert at; appended if unset.
+    pub index: Option<usize>,
+    pub motion: Motion,
+    /// 20 % for joint moves and 100 mm/s for linear ones if unset.
+    pub speed: Option<f64>,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// The job text of the module documentation, not a controller file.
+    #[serde(alias = "fdJob")]
+    JobText,
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonExport<'a> {
+    format: &'static str,
+    exported_at: DateTime<Utc>,
+    #[serde(flatten)]
+    list: &'a PointList,
+}
+
+#[tauri::command]
+pub fn list_point_lists(app: AppHandle) -> Result<Vec<PointListSummary>> {
+    let dir = teach_dir(&app)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut lists = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match read(&path) {
+            Ok(list) => lists.push(PointListSummary {
+                name: list.name,
+                description: list.description,
+                points: list.points.len(),
+                updated_at: list.updated_at,
+            }),
+            Err(err) => tracing::warn!(path = %path.display(), "skipping teach list: {err}"),
+        }
+    }
+    lists.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(lists)
+}
+
+#[tauri::command]
+pub fn get_point_list(app: AppHandle, name: String) -> Result<PointList> {
+    read(&list_path(&app, &name)?)
+}
+
+/// Creates or replaces a list, e.g. after editing its metadata or points.
+#[tauri::command]
+pub fn save_point_list(app: AppHandle, mut list: PointList) -> Result<PointList> {
+    users::require(&app, Capability::Teach)?;
+    list_path(&app, &list.name)?;
+    list.updated_at = Utc::now();
+    write(&app, &list)?;
+    Ok(list)
+}
+
+#[tauri::command]
+pub fn delete_point_list(app: AppHandle, name: String) -> Result<()> {
+    users::require(&app, Capability::Teach)?;
+    match std::fs::remove_file(list_path(&app, &name)?) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(Error::NotFound(format!("teach list `{name}`")))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Adds the robot's current position to `list`, creating the list if
+/// needed.
+#[tauri::command]
+pub async fn capture_waypoint(
+    app: AppHandle,
+    controller: State<'_, FdController>,
+    list: String,
+    request: Option<CaptureRequest>,
+) -> Result<PointList> {
+    users::require(&app, Capability::Teach)?;
+    let request = request.unwrap_or_default();
+    let path = list_path(&app, &list)?;
+    let position = controller.position().await?;
+    let mut points = match read(&path) {
+        Ok(points) => points,
+        Err(Error::NotFound(_)) => PointList {
+            name: list,
+            description: String::new(),
+            program: None,
+            metadata: BTreeMap::new(),
+            points: Vec::new(),
+            updated_at: Utc::now(),
+        },
+        Err(err) => return Err(err),
+    };
+    let name = request.name.unwrap_or_else(|| next_name(&points));
+    if points.points.iter().any(|point| point.name == name) {
+        return Err(Error::Invalid(format!("waypoint `{name}` already exists")));
+    }
+    let waypoint = Waypoint {
+        name,
+        pose: position.pose,
+        joints: position.joints,
+        motion: request.motion,
+        speed: request.speed.unwrap_or(match request.motion {
+            Motion::Joint => 20.0,
+            Motion::Linear => 100.0,
+        }),
+        comment: request.comment,
+        captured_at: Utc::now(),
+        captured_by: users::current_name(&app),
+    };
+    tracing::info!(list = %points.name, waypoint = %waypoint.name, "waypoint captured");
+    let index = request
+        .index
+        .unwrap_or(points.points.len())
+        .min(points.points.len());
+    points.points.insert(index, waypoint);
+    points.updated_at = Utc::now();
+    write(&app, &points)?;
+    Ok(points)
+}
+
+#[tauri::command]
+pub fn remove_waypoint(app: AppHandle, list: String, index: usize) -> Result<PointList> {
+    users::require(&app, Capability::Teach)?;
+    edit(&app, &list, |points| {
+        check_index(points, index)?;
+        points.points.remove(index);
+        Ok(())
+    })
+}
+
+/// Moves the waypoint at `from` to `to`.
+#[tauri::command]
+pub fn move_waypoint(app: AppHandle, list: String, from: usize, to: usize) -> Result<PointList> {
+    users::require(&app, Capability::Teach)?;
+    edit(&app, &list, |points| {
+        check_index(points, from)?;
+        check_index(points, to)?;
+        let waypoint = points.points.remove(from);
+        points.points.insert(to, waypoint);
+        Ok(())
+    })
+}
+
+/// Writes `list` to `path` in `format`; returns the path.
+#[tauri::command]
+pub fn export_point_list(
+    app: AppHandle,
+    list: String,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<PathBuf> {
+    let points = read(&list_path(&app, &list)?)?;
+    let contents = match format {
+        ExportFormat::JobText => {
+            let max_linear = app.state::<SettingsStore>().get().jog.max_linear_mm_s;
+            encode_job(&points, max_linear)?.into_bytes()
+        }
+        ExportFormat::Json => serde_json::to_vec_pretty(&JsonExport {
+            format: JSON_FORMAT,
+            exported_at: Utc::now(),
+            list: &points,
+        })?,
+    };
+    fsutil::write_atomic(&path, &contents)?;
+    tracing::info!(%list, path = %path.display(), "teach list exported");
+    Ok(path)
+}
+
+/// The job text of `list`, linear moves no faster than `max_linear_mm_s`;
+/// see the module documentation.
+pub fn encode_job(list: &PointList, max_linear_mm_s: f64) -> Result<String> {
+    if list.points.is_empty() {
+        return Err(Error::Invalid(format!(
+            "teach list `{}` has no points",
+            list.name
+        )));
+    }
+    let single_line = |text: &str| text.replace(['\r', '\n'], " ");
+    let mut job = String::new();
+    let _ = writeln!(job, "' {}", single_line(&list.name));
+    if !list.description.is_empty() {
+        let _ = writeln!(job, "' {}", single_line(&list.description));
+    }
+    for (key, value) in &list.metadata {
+        let _ = writeln!(job, "' {}: {}", single_line(key), single_line(value));
+    }
+    let _ = writeln!(job, "' exported {}", Utc::now().to_rfc3339());
+    let _ = writeln!(job, "' job text, not a controller job file");
+    let program = list
+        .program
+        .map_or_else(|| list.name.clone(), |program| program.to_string());
+    let _ = writeln!(job, "JOB {program}");
+    // Points are numbered by position, since the controller's point names
+    // cannot carry the free-form ones.
+    for (index, point) in list.points.iter().enumerate() {
+        let values = |values: &[f64; 6]| {
+            values
+                .iter()
+                .map(|value| format!("{value:.3}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let _ = writeln!(
+            job,
+            "P{} = BASE {} JOINT {} ' {}",
+            index + 1,
+            values(&point.pose),
+            values(&point.joints),
+            single_line(&point.name)
+        );
+    }
+    for (index, point) in list.points.iter().enumerate() {
+        let instruction = match point.motion {
+            Motion::Joint => "MOVEJ",
+            Motion::Linear => "MOVEL",
+        };
+        let speed = match point.motion {
+            Motion::Joint => point.speed.clamp(0.1, 100.0),
+            Motion::Linear => point.speed.clamp(0.1, max_linear_mm_s.max(0.1)),
+        };
+        let comment = point
+            .comment
+            .as_deref()
+            .map(|comment| format!(" ' {}", single_line(comment)))
+            .unwrap_or_default();
+        let _ = writeln!(job, "{instruction} P{} V={speed:.1}{comment}", index + 1);
+    }
+    job.push_str("END\n");
+    Ok(job)
+}
+
+fn teach_dir(app: &AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(TEACH_DIR))
+}
+
+fn list_path(app: &AppHandle, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    if !valid {
+        return Err(Error::Invalid(format!("teach list name `{name}`")));
+    }
+    Ok(teach_dir(app)?.join(format!("{name}.json")))
+}
+
+fn read(path: &Path) -> Result<PointList> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound(format!(
+            "teach list `{}`",
+            path.file_stem().unwrap_or_default().to_string_lossy()
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write(app: &AppHandle, list: &PointList) -> Result<()> {
+    let path = list_path(app, &list.name)?;
+    std::fs::create_dir_all(teach_dir(app)?)?;
+    fsutil::write_atomic(&path, &serde_json::to_vec_pretty(list)?)?;
+    Ok(())
+}
+
+fn edit(
+    app: &AppHandle,
+    name: &str,
+    change: impl FnOnce(&mut PointList) -> Result<()>,
+) -> Result<PointList> {
+    let mut list = read(&list_path(app, name)?)?;
+    change(&mut list)?;
+    list.updated_at = Utc::now();
+    write(app, &list)?;
+    Ok(list)
+}
+
+fn check_index(list: &PointList, index: usize) -> Result<()> {
+    if index >= list.points.len() {
+        return Err(Error::Invalid(format!(
+            "waypoint {index} is out of range; `{}` has {}",
+            list.name,
+            list.points.len()
+        )));
+    }
+    Ok(())
+}
+
+/// `P<n>` one past the highest number in use.
+fn next_name(list: &PointList) -> String {
+    let highest = list
+        .points
+        .iter()
+        .filter_map(|point| point.name.strip_prefix('P')?.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+    format!("P{}", highest + 1)
+}
@@ -0,0 +1,309 @@
+//! Always-on telemetry log, independent of episode recording.
+//!
+//! Every event named in `telemetry.channels` is flattened into its numeric
+//! leaves (see [`numeric_leaves`]) and buffered in memory; a background
+//! thread writes the buffer to a Parquet file every `flushIntervalSecs`.
+//! Files are long-format tables (`timestamp_ns`, `channel`, `signal`,
+//! `value`) named after the time of their first sample, so directory order
+//! is time order. After each flush the oldest files are deleted until the
+//! remainder is within `retentionDays` and `retentionGb`.
+//!
+//! The buffer is capped at [`MAX_BUFFERED`] rows; samples beyond that are
+//! counted and dropped until the next flush. The last partial interval is
+//! flushed when the app exits. With `push`, every written file is also
+//! queued for the upload bucket through [`crate::offline`].
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime};
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+use tauri::{AppHandle, EventId, Listener, Manager};
+
+use crate::disk_guard;
+use crate::error::Result;
+use crate::offline::{self, Operation};
+use crate::settings::{Settings, SettingsStore, TelemetrySettings};
+use crate::time_sync;
+
+const MAX_BUFFERED: usize = 2_000_000;
+const FILE_PREFIX: &str = "telemetry-";
+
+struct Sample {
+    timestamp_ns: i64,
+    channel: Arc<str>,
+    signal: String,
+    value: f64,
+}
+
+/// Buffered samples and the listeners feeding them.
+#[derive(Default)]
+pub struct Telemetry {
+    buffer: StdMutex<Vec<Sample>>,
+    dropped: AtomicU64,
+    listeners: StdMutex<(Vec<String>, Vec<EventId>)>,
+    /// Serializes flushes from the thread and from exit.
+    flushing: StdMutex<()>,
+}
+
+impl Telemetry {
+    fn push(&self, channel: &Arc<str>, timestamp_ns: i64, payload: &str) {
+        let Ok(value) = serde_json::from_str::<Value>(payload) else {
+            return;
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        for (signal, value) in numeric_leaves(&value) {
+            if buffer.len() >= MAX_BUFFERED {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            buffer.push(Sample {
+                timestamp_ns,
+                channel: channel.clone(),
+                signal,
+                value,
+            });
+        }
+    }
+}
+
+/// Registers the channel listeners and starts the flush thread.
+pub fn init(app: &AppHandle) -> Result<()> {
+    app.manage(Telemetry::default());
+    listen(app, &app.state::<SettingsStore>().get().telemetry);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            listen(&handle, &settings.telemetry);
+        }
+    });
+
+    let handle = app.clone();
+    std::thread::Builder::new()
+        .name("telemetry".into())
+        .spawn(move || loop {
+            let settings = handle.state::<SettingsStore>().get().telemetry;
+            std::thread::sleep(Duration::from_secs(settings.flush_interval_secs.max(1)));
+            if settings.enabled {
+                flush(&handle, &settings);
+            }
+        })?;
+    Ok(())
+}
+
+/// Writes whatever is buffered; called on exit.
+pub fn flush_now(app: &AppHandle) {
+    let settings = app.state::<SettingsStore>().get().telemetry;
+    if settings.enabled {
+        flush(app, &settings);
+    }
+}
+
+/// Numeric leaves of a JSON value keyed by dotted path (`values.current`,
+/// `message.jointPositions.0`); booleans count as 0 or 1.
+pub fn numeric_leaves(value: &Value) -> Vec<(String, f64)> {
+    let mut leaves = Vec::new();
+    flatten(value, String::new(), &mut leaves);
+    leaves
+}
+
+fn flatten(value: &Value, path: String, leaves: &mut Vec<(String, f64)>) {
+    let child = |key: &str| -> String {
+        if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                leaves.push((path, number));
+            }
+        }
+        Value::Bool(flag) => leaves.push((path, f64::from(u8::from(*flag)))),
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(item, child(&index.to_string()), leaves);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, item) in fields {
+                flatten(item, child(key), leaves);
+            }
+        }
+        Value::Null | Value::String(_) => {}
+    }
+}
+
+/// Re-registers the listeners when the channel list changed.
+fn listen(app: &AppHandle, settings: &TelemetrySettings) {
+    let telemetry = app.state::<Telemetry>();
+    let mut listeners = telemetry.listeners.lock().unwrap();
+    let channels = if settings.enabled {
+        settings.channels.clone()
+    } else {
+        Vec::new()
+    };
+    if listeners.0 == channels {
+        return;
+    }
+    for id in listeners.1.drain(..) {
+        app.unlisten(id);
+    }
+    listeners.1 = channels
+        .iter()
+        .map(|channel| {
+            let handle = app.clone();
+            let name: Arc<str> = channel.as_str().into();
+            app.listen_any(channel, move |event| {
+                let timestamp_ns = time_sync::now_ns(&handle);
+                handle
+                    .state::<Telemetry>()
+                    .push(&name, timestamp_ns, event.payload());
+            })
+        })
+        .collect();
+    listeners.0 = channels;
+}
+
+fn flush(app: &AppHandle, settings: &TelemetrySettings) {
+    let telemetry = app.state::<Telemetry>();
+    let _flushing = telemetry.flushing.lock().unwrap();
+    let samples = std::mem::take(&mut *telemetry.buffer.lock().unwrap());
+    let dropped = telemetry.dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        tracing::warn!(dropped, "telemetry buffer full; samples dropped");
+    }
+    let dir = match telemetry_dir(app, settings) {
+        Ok(dir) => dir,
+        Err(err) => {
+            tracing::warn!("telemetry directory unavailable: {err}");
+            return;
+        }
+    };
+    if !samples.is_empty() && !disk_guard::has_room(app, &dir) {
+        tracing::warn!(
+            rows = samples.len(),
+            "telemetry not written; the disk is nearly full"
+        );
+    } else if !samples.is_empty() {
+        match write_file(&dir, &samples) {
+            Ok(path) if settings.push => offline::enqueue(app, Operation::TelemetryPush { path }),
+            Ok(_) => {}
+            Err(err) => tracing::warn!(rows = samples.len(), "telemetry flush failed: {err}"),
+        }
+    }
+    if let Err(err) = enforce_retention(&dir, settings) {
+        tracing::warn!("telemetry retention failed: {err}");
+    }
+}
+
+/// Where telemetry files are written.
+pub fn telemetry_dir(app: &AppHandle, settings: &TelemetrySettings) -> Result<PathBuf> {
+    let dir = match &settings.dir {
+        Some(dir) => dir.clone(),
+        None => app.path().app_data_dir()?.join("telemetry"),
+    };
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn write_file(dir: &Path, samples: &[Sample]) -> Result<PathBuf> {
+    let first = chrono::DateTime::from_timestamp_nanos(samples[0].timestamp_ns);
+    let name = format!(
+        "{FILE_PREFIX}{}.parquet",
+        first.format("%Y%m%dT%H%M%S%.3fZ")
+    );
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "timestamp_ns",
+            Arc::new(Int64Array::from_iter_values(
+                samples.iter().map(|sample| sample.timestamp_ns),
+            )),
+        ),
+        (
+            "channel",
+            Arc::new(StringArray::from_iter_values(
+                samples.iter().map(|sample| &*sample.channel),
+            )),
+        ),
+        (
+            "signal",
+            Arc::new(StringArray::from_iter_values(
+                samples.iter().map(|sample| sample.signal.as_str()),
+            )),
+        ),
+        (
+            "value",
+            Arc::new(Float64Array::from_iter_values(
+                samples.iter().map(|sample| sample.value),
+            )),
+        ),
+    ];
+    let schema = Schema::new(vec![
+        Field::new("timestamp_ns", DataType::Int64, false),
+        Field::new("channel", DataType::Utf8, false),
+        Field::new("signal", DataType::Utf8, false),
+        Field::new("value", DataType::Float64, false),
+    ]);
+    let batch = RecordBatch::try_new(
+        Arc::new(schema),
+        columns.into_iter().map(|(_, column)| column).collect(),
+    )?;
+
+    // Written under a temporary name so readers never open a partial file.
+    let path = dir.join(&name);
+    let tmp = path.with_extension("parquet.tmp");
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(
+        std::fs::File::create(&tmp)?,
+        batch.schema(),
+        Some(properties),
+    )?;
+    writer.write(&batch)?;
+    writer.close()?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+/// Deletes the oldest files beyond the age and size limits.
+fn enforce_retention(dir: &Path, settings: &TelemetrySettings) -> Result<()> {
+    let mut files: Vec<(PathBuf, SystemTime, u64)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(FILE_PREFIX) && name.ends_with(".parquet")
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+        })
+        .collect();
+    // Newest first, by name (the first sample's time).
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    let max_age = Duration::from_secs(u64::from(settings.retention_days) * 24 * 60 * 60);
+    let max_bytes = (settings.retention_gb * 1024.0 * 1024.0 * 1024.0) as u64;
+    let mut kept = 0u64;
+    for (path, modified, len) in files {
+        let too_old =
+            settings.retention_days > 0 && modified.elapsed().is_ok_and(|age| age > max_age);
+        let too_big = settings.retention_gb > 0.0 && kept + len > max_bytes;
+        if too_old || too_big {
+            std::fs::remove_file(&path)?;
+        } else {
+            kept += len;
+        }
+    }
+    Ok(())
+}
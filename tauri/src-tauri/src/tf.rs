@@ -0,0 +1,493 @@
+//! Frame transforms for the 3D viewer, kept by the shell so the webview no
+//! longer walks kinematic chains itself.
+//!
+//! The frame tree has four kinds of edges:
+//!
+//! - `tf.frames`: a fixed origin relative to the parent, followed by a
+//!   rotation about (or translation along) `axis` by the position of
+//!   `joint` if one is named;
+//! - the joints of the robot's URDF (see [`crate::urdf`]), each making its
+//!   child link a frame under its parent link, unless `tf.frames` defines
+//!   the same frame;
+//! - camera extrinsics solved by [`crate::calibration`]: a calibrated stream
+//!   becomes `<stream>_optical_frame` under `tf.toolFrame` or
+//!   `tf.baseFrame`, depending on how the camera was mounted, re-read when a
+//!   calibration is solved;
+//! - joint positions, taken from every reading [`crate::joint_history`]
+//!   records and kept for `tf.bufferSecs` per joint.
+//!
+//! [`lookup_transform`] resolves a transform at a point in time by
+//! interpolating every joint on the chain between its samples, so frames
+//! stay consistent with each other however the sources interleave. While
+//! joints move, `tf-snapshot` carries every frame in its tree's root at
+//! most `tf.snapshotHz` times a second; [`tf_snapshot`] returns the same on
+//! demand. Distances are metres, joint positions as the source reports
+//! them times `scale`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, Unit, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+
+use crate::calibration::{self, Mount};
+use crate::error::{Error, Result};
+use crate::settings::{Settings, SettingsStore, TfSettings};
+use crate::time_sync;
+use crate::urdf::{self, JointInfo, JointKind};
+
+/// Polled at this interval while snapshots are off.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// One edge of the frame tree, as configured in `tf.frames`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TfFrame {
+    pub name: String,
+    pub parent: String,
+    /// `x y z` in metres and fixed-axis `roll pitch yaw` in degrees.
+    pub origin: [f64; 6],
+    /// Joint whose position moves the frame; fixed if unset.
+    pub joint: Option<String>,
+    /// In the frame's own coordinates, after `origin`.
+    pub axis: [f64; 3],
+    /// Translate along `axis` instead of rotating about it.
+    pub prismatic: bool,
+    /// Converts the joint position to radians or metres.
+    pub scale: f64,
+}
+
+impl Default for TfFrame {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            parent: String::new(),
+            origin: [0.0; 6],
+            joint: None,
+            axis: [0.0, 0.0, 1.0],
+            prismatic: false,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Payload of [`lookup_transform`] and an entry of `tf-snapshot`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transform {
+    pub parent: String,
+    pub child: String,
+    /// Unix milliseconds the transform holds at.
+    pub timestamp_ms: f64,
+    /// Position of the child's origin in the parent, in metres.
+    pub translation: [f64; 3],
+    /// Rotation from child to parent as `x y z w`.
+    pub rotation: [f64; 4],
+}
+
+impl Transform {
+    fn new(parent: &str, child: &str, timestamp_ns: i64, isometry: &Isometry3<f64>) -> Self {
+        let translation = isometry.translation.vector;
+        let rotation = isometry.rotation.quaternion();
+        Self {
+            parent: parent.into(),
+            child: child.into(),
+            timestamp_ms: timestamp_ns as f64 / 1e6,
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+        }
+    }
+}
+
+/// Payload of `tf-snapshot` and [`tf_snapshot`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TfSnapshot {
+    pub timestamp_ms: f64,
+    /// Every frame whose joints have positions, relative to its root.
+    pub transforms: Vec<Transform>,
+}
+
+struct Motion {
+    joint: String,
+    axis: Unit<Vector3<f64>>,
+    prismatic: bool,
+    scale: f64,
+    offset: f64,
+}
+
+struct Link {
+    parent: String,
+    origin: Isometry3<f64>,
+    motion: Option<Motion>,
+}
+
+#[derive(Default)]
+struct Tree {
+    /// Keyed by child frame.
+    links: HashMap<String, Link>,
+    /// Joint positions by time, oldest first.
+    joints: HashMap<String, VecDeque<(i64, f64)>>,
+    buffer_ns: i64,
+    /// Joint positions arrived since the last snapshot.
+    moved: bool,
+    /// When joint positions last arrived.
+    updated_ns: Option<i64>,
+}
+
+impl Tree {
+    fn ingest(&mut self, timestamp_ns: i64, names: &[String], positions: &[f64]) {
+        for (name, &position) in names.iter().zip(positions) {
+            let samples = self.joints.entry(name.clone()).or_default();
+            if samples.back().is_some_and(|&(last, _)| timestamp_ns < last) {
+                // Sources with skewed clocks must not reorder the buffer.
+                continue;
+            }
+            samples.push_back((timestamp_ns, position));
+            let oldest = timestamp_ns.saturating_sub(self.buffer_ns);
+            while samples.len() > 1 && samples[0].0 < oldest {
+                samples.pop_front();
+            }
+        }
+        self.moved = true;
+        self.updated_ns = Some(timestamp_ns);
+    }
+
+    /// Position of `joint` at `timestamp_ns`, the latest one if `None`.
+    fn joint(&self, joint: &str, timestamp_ns: Option<i64>) -> Result<f64> {
+        let samples = self
+            .joints
+            .get(joint)
+            .filter(|samples| !samples.is_empty())
+            .ok_or_else(|| Error::NotFound(format!("position of joint `{joint}`")))?;
+        let Some(time) = timestamp_ns else {
+            return Ok(samples[samples.len() - 1].1);
+        };
+        let after = samples.partition_point(|&(sample, _)| sample < time);
+        if after == 0 {
+            if samples[0].0 == time {
+                return Ok(samples[0].1);
+            }
+            return Err(Error::Invalid(format!(
+                "joint `{joint}` has no position buffered that early"
+            )));
+        }
+        let Some(&(t1, p1)) = samples.get(after) else {
+            // Held at the latest position until a newer one arrives.
+            return Ok(samples[after - 1].1);
+        };
+        let (t0, p0) = samples[after - 1];
+        let fraction = (time - t0) as f64 / (t1 - t0).max(1) as f64;
+        Ok(p0 + (p1 - p0) * fraction)
+    }
+
+    /// Transform from `frame` to the root of its tree, and that root.
+    fn to_root(&self, frame: &str, timestamp_ns: Option<i64>) -> Result<(Isometry3<f64>, String)> {
+        let mut isometry = Isometry3::identity();
+        let mut current = frame;
+        // A chain longer than the tree has edges loops back on itself.
+        for _ in 0..=self.links.len() {
+            let Some(link) = self.links.get(current) else {
+                return Ok((isometry, current.to_string()));
+            };
+            let local = match &link.motion {
+                Some(motion) => {
+                    let position =
+                        self.joint(&motion.joint, timestamp_ns)? * motion.scale + motion.offset;
+                    let moved = if motion.prismatic {
+                        Isometry3::from_parts(
+                            Translation3::from(motion.axis.into_inner() * position),
+                            UnitQuaternion::identity(),
+                        )
+                    } else {
+                        Isometry3::from_parts(
+                            Translation3::identity(),
+                            UnitQuaternion::from_axis_angle(&motion.axis, position),
+                        )
+                    };
+                    link.origin * moved
+                }
+                None => link.origin,
+            };
+            isometry = local * isometry;
+            current = &link.parent;
+        }
+        Err(Error::Invalid(format!(
+            "frame `{frame}` is part of a cycle"
+        )))
+    }
+
+    fn lookup(
+        &self,
+        parent: &str,
+        child: &str,
+        timestamp_ns: Option<i64>,
+    ) -> Result<Isometry3<f64>> {
+        let (child_to_root, child_root) = self.to_root(child, timestamp_ns)?;
+        let (parent_to_root, parent_root) = self.to_root(parent, timestamp_ns)?;
+        if child_root != parent_root {
+            return Err(Error::NotFound(format!(
+                "transform between `{parent}` and `{child}`: not in one tree"
+            )));
+        }
+        Ok(parent_to_root.inverse() * child_to_root)
+    }
+
+    fn snapshot(&self, timestamp_ns: i64) -> TfSnapshot {
+        let mut transforms: Vec<Transform> = self
+            .links
+            .keys()
+            .filter_map(|frame| {
+                let (isometry, root) = self.to_root(frame, None).ok()?;
+                Some(Transform::new(&root, frame, timestamp_ns, &isometry))
+            })
+            .collect();
+        transforms.sort_by(|a, b| a.child.cmp(&b.child));
+        TfSnapshot {
+            timestamp_ms: timestamp_ns as f64 / 1e6,
+            transforms,
+        }
+    }
+}
+
+/// The frame tree and buffered joint positions.
+#[derive(Default)]
+pub struct Tf(StdMutex<Tree>);
+
+/// Builds the tree and keeps it in line with settings and calibrations.
+pub fn init(app: &AppHandle) {
+    app.manage(Tf::default());
+    rebuild(app, &app.state::<SettingsStore>().get().tf);
+
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            rebuild(&handle, &settings.tf);
+        }
+    });
+    let handle = app.clone();
+    app.listen_any("calibration-solved", move |_| {
+        rebuild(&handle, &handle.state::<SettingsStore>().get().tf);
+    });
+
+    tauri::async_runtime::spawn(publish(app.clone()));
+}
+
+/// Buffers joint positions read at `timestamp_ns`; called by
+/// [`crate::joint_history::record`].
+pub fn ingest(app: &AppHandle, timestamp_ns: i64, names: &[String], positions: &[f64]) {
+    if let Some(tf) = app.try_state::<Tf>() {
+        tf.0.lock().unwrap().ingest(timestamp_ns, names, positions);
+    }
+}
+
+/// Pose of `child` in `parent` at the latest joint positions.
+pub fn latest(app: &AppHandle, parent: &str, child: &str) -> Result<Isometry3<f64>> {
+    let tf = app
+        .try_state::<Tf>()
+        .ok_or_else(|| Error::NotFound("frame transforms".into()))?;
+    let tree = tf.0.lock().unwrap();
+    tree.lookup(parent, child, None)
+}
+
+/// Latest position of `joint` and when it was read.
+pub fn joint_position(app: &AppHandle, joint: &str) -> Option<(i64, f64)> {
+    let tf = app.try_state::<Tf>()?;
+    let tree = tf.0.lock().unwrap();
+    tree.joints.get(joint)?.back().copied()
+}
+
+/// When joint positions last arrived, if ever.
+pub fn last_update_ns(app: &AppHandle) -> Option<i64> {
+    app.try_state::<Tf>()?.0.lock().unwrap().updated_ns
+}
+
+/// Pose of `child` in `parent` at `time` (Unix milliseconds; the latest
+/// joint positions if unset).
+#[tauri::command]
+pub fn lookup_transform(
+    app: AppHandle,
+    tf: State<'_, Tf>,
+    parent: String,
+    child: String,
+    time: Option<f64>,
+) -> Result<Transform> {
+    let timestamp_ns = time.map(|ms| (ms * 1e6) as i64);
+    let isometry = tf.0.lock().unwrap().lookup(&parent, &child, timestamp_ns)?;
+    let timestamp_ns = timestamp_ns.unwrap_or_else(|| time_sync::now_ns(&app));
+    Ok(Transform::new(&parent, &child, timestamp_ns, &isometry))
+}
+
+#[tauri::command]
+pub fn tf_snapshot(app: AppHandle, tf: State<'_, Tf>) -> TfSnapshot {
+    tf.0.lock().unwrap().snapshot(time_sync::now_ns(&app))
+}
+
+fn rebuild(app: &AppHandle, settings: &TfSettings) {
+    let mut links = HashMap::new();
+    for frame in &settings.frames {
+        if frame.name.is_empty() || frame.parent.is_empty() {
+            tracing::warn!(name = %frame.name, "tf frame without name or parent ignored");
+            continue;
+        }
+        let motion = match &frame.joint {
+            Some(joint) => {
+                let Some(axis) = Unit::try_new(Vector3::from(frame.axis), 1e-9) else {
+                    tracing::warn!(name = %frame.name, "tf frame with a zero axis ignored");
+                    continue;
+                };
+                Some(Motion {
+                    joint: joint.clone(),
+                    axis,
+                    prismatic: frame.prismatic,
+                    scale: frame.scale,
+                    offset: 0.0,
+                })
+            }
+            None => None,
+        };
+        let link = Link {
+            parent: frame.parent.clone(),
+            origin: origin(&frame.origin),
+            motion,
+        };
+        if links.insert(frame.name.clone(), link).is_some() {
+            tracing::warn!(name = %frame.name, "tf frame defined twice; the last one is used");
+        }
+    }
+    if let Some(model) = urdf::current(app) {
+        for joint in &model.joints {
+            links
+                .entry(joint.child.clone())
+                .or_insert_with(|| urdf_link(joint));
+        }
+    }
+    for (stream, mount, matrix) in calibrated_cameras(app) {
+        let parent = match mount {
+            Mount::Hand => &settings.tool_frame,
+            Mount::Fixed => &settings.base_frame,
+        };
+        links
+            .entry(format!("{stream}_optical_frame"))
+            .or_insert_with(|| Link {
+                parent: parent.clone(),
+                origin: matrix,
+                motion: None,
+            });
+    }
+
+    let tf = app.state::<Tf>();
+    let mut tree = tf.0.lock().unwrap();
+    tree.links = links;
+    tree.buffer_ns = i64::try_from(settings.buffer_secs)
+        .unwrap_or(i64::MAX / 1_000_000_000)
+        .saturating_mul(1_000_000_000);
+    tree.moved = true;
+}
+
+/// Rebuilds the tree, e.g. after another URDF was loaded.
+pub fn refresh(app: &AppHandle) {
+    if app.try_state::<Tf>().is_some() {
+        rebuild(app, &app.state::<SettingsStore>().get().tf);
+    }
+}
+
+fn urdf_link(joint: &JointInfo) -> Link {
+    let axis = Unit::try_new(Vector3::from(joint.axis), 1e-9);
+    let motion = match (joint.kind, axis) {
+        (JointKind::Revolute | JointKind::Continuous | JointKind::Prismatic, Some(axis)) => {
+            let (driver, scale, offset) = match &joint.mimic {
+                Some(mimic) => (mimic.joint.clone(), mimic.multiplier, mimic.offset),
+                None => (joint.name.clone(), 1.0, 0.0),
+            };
+            Some(Motion {
+                joint: driver,
+                axis,
+                prismatic: joint.kind == JointKind::Prismatic,
+                scale,
+                offset,
+            })
+        }
+        _ => None,
+    };
+    Link {
+        parent: joint.parent.clone(),
+        origin: joint.origin(),
+        motion,
+    }
+}
+
+/// `x y z roll pitch yaw` in metres and degrees.
+fn origin(origin: &[f64; 6]) -> Isometry3<f64> {
+    let [x, y, z, roll, pitch, yaw] = *origin;
+    Isometry3::from_parts(
+        Translation3::new(x, y, z),
+        UnitQuaternion::from_euler_angles(roll.to_radians(), pitch.to_radians(), yaw.to_radians()),
+    )
+}
+
+/// Hand–eye solutions of the calibration files, by stream.
+fn calibrated_cameras(app: &AppHandle) -> Vec<(String, Mount, Isometry3<f64>)> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Stored {
+        stream: String,
+        camera_to_robot: Option<StoredHandEye>,
+    }
+    #[derive(Deserialize)]
+    struct StoredHandEye {
+        mount: Mount,
+        matrix: [[f64; 4]; 4],
+    }
+
+    let Ok(entries) = calibration::dir(app).and_then(|dir| Ok(std::fs::read_dir(dir)?)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let bytes = std::fs::read(entry.path()).ok()?;
+            let stored: Stored = match serde_json::from_slice(&bytes) {
+                Ok(stored) => stored,
+                Err(err) => {
+                    tracing::warn!(path = %entry.path().display(), "calibration unreadable: {err}");
+                    return None;
+                }
+            };
+            let hand_eye = stored.camera_to_robot?;
+            let m = hand_eye.matrix;
+            let rotation = Rotation3::from_matrix(&Matrix3::new(
+                m[0][0], m[0][1], m[0][2], m[1][0], m[1][1], m[1][2], m[2][0], m[2][1], m[2][2],
+            ));
+            let isometry = Isometry3::from_parts(
+                Translation3::new(m[0][3], m[1][3], m[2][3]),
+                UnitQuaternion::from_rotation_matrix(&rotation),
+            );
+            Some((stored.stream, hand_eye.mount, isometry))
+        })
+        .collect()
+}
+
+/// Emits `tf-snapshot` while joints move.
+async fn publish(app: AppHandle) {
+    loop {
+        let hz = app.state::<SettingsStore>().get().tf.snapshot_hz;
+        if hz <= 0.0 {
+            tokio::time::sleep(IDLE_POLL).await;
+            continue;
+        }
+        tokio::time::sleep(Duration::from_secs_f64(1.0 / hz.min(240.0))).await;
+        let snapshot = {
+            let tf = app.state::<Tf>();
+            let mut tree = tf.0.lock().unwrap();
+            if !std::mem::take(&mut tree.moved) {
+                continue;
+            }
+            tree.snapshot(time_sync::now_ns(&app))
+        };
+        let _ = app.emit("tf-snapshot", snapshot);
+    }
+}
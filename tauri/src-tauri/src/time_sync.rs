@@ -0,0 +1,308 @@
+//! Clock offsets to NTP and the robot controller, and the correction applied
+//! to the shell's own timestamps.
+//!
+//! Every `timeSync.intervalSecs` the PC clock is compared with `ntpServer`
+//! over SNTP and, while [`crate::daihen_fd`] is connected, with the
+//! controller clock; each offset is the sample with the shortest round trip
+//! out of a few. The offset of the `reference` clock becomes the correction
+//! that [`now_ns`] adds to the system time; telemetry, bags, the joint
+//! history and the force/torque sensor stamp their samples with it. Episode
+//! datasets are unaffected, as their timestamps count frames from the
+//! episode start.
+//!
+//! A clock further than `maxSkewMs` from the corrected time raises
+//! `clock-skew`, and again with `exceeded: false` once it is back within.
+//! Each round is emitted as `time-sync-status`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::net::UdpSocket;
+
+use crate::daihen_fd::FdController;
+use crate::error::{Error, Result};
+use crate::settings::{SettingsStore, TimeReference, TimeSyncSettings};
+
+const NTP_PORT: u16 = 123;
+/// Seconds from 1900, the NTP epoch, to 1970.
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+const NTP_TIMEOUT: Duration = Duration::from_secs(2);
+const NTP_SAMPLES: usize = 4;
+const ROBOT_SAMPLES: usize = 5;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockOffset {
+    /// How far the clock is ahead of the PC clock.
+    pub offset_ms: f64,
+    pub round_trip_ms: f64,
+    pub measured_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeSyncStatus {
+    pub reference: TimeReference,
+    /// Added to the PC clock by [`now_ns`].
+    pub correction_ms: f64,
+    pub ntp: Option<ClockOffset>,
+    pub robot: Option<ClockOffset>,
+    /// Why a clock could not be measured in the last round.
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClockSkew {
+    /// `ntp`, `robot` or `pc`.
+    clock: &'static str,
+    exceeded: bool,
+    skew_ms: f64,
+    max_skew_ms: f64,
+}
+
+#[derive(Default)]
+pub struct TimeSync {
+    correction_ns: AtomicI64,
+    status: StdMutex<TimeSyncStatus>,
+    /// Clocks currently beyond `maxSkewMs`.
+    skewed: StdMutex<HashSet<&'static str>>,
+}
+
+/// Starts the measurement task.
+pub fn init(app: &AppHandle) {
+    app.manage(TimeSync::default());
+    tauri::async_runtime::spawn(run(app.clone()));
+}
+
+/// The corrected current time, in Unix nanoseconds.
+pub fn now_ns(app: &AppHandle) -> i64 {
+    let correction = app
+        .try_state::<TimeSync>()
+        .map_or(0, |sync| sync.correction_ns.load(Ordering::Relaxed));
+    system_ns().saturating_add(correction)
+}
+
+#[tauri::command]
+pub fn get_time_sync_status(sync: State<'_, TimeSync>) -> TimeSyncStatus {
+    sync.status.lock().unwrap().clone()
+}
+
+/// Measures the clocks now instead of at the next interval.
+#[tauri::command]
+pub async fn sync_clocks(app: AppHandle) -> Result<TimeSyncStatus> {
+    let settings = app.state::<SettingsStore>().get().time_sync;
+    if !settings.enabled {
+        return Err(Error::Invalid("timeSync is disabled".into()));
+    }
+    Ok(measure(&app, &settings).await)
+}
+
+async fn run(app: AppHandle) {
+    loop {
+        let settings = app.state::<SettingsStore>().get().time_sync;
+        if settings.enabled {
+            measure(&app, &settings).await;
+        }
+        tokio::time::sleep(Duration::from_secs(settings.interval_secs.max(1))).await;
+    }
+}
+
+/// One round: measures every clock, updates the correction and reports skew.
+async fn measure(app: &AppHandle, settings: &TimeSyncSettings) -> TimeSyncStatus {
+    let mut status = TimeSyncStatus {
+        reference: settings.reference,
+        ..TimeSyncStatus::default()
+    };
+    if let Some(server) = &settings.ntp_server {
+        match ntp_offset(server).await {
+            Ok(offset) => status.ntp = Some(offset),
+            Err(err) => {
+                status.errors.insert("ntp".into(), err.to_string());
+            }
+        }
+    }
+    let controller = app.state::<FdController>();
+    if controller.is_connected() {
+        match robot_offset(&controller).await {
+            Ok(offset) => status.robot = Some(offset),
+            Err(err) => {
+                status.errors.insert("robot".into(), err.to_string());
+            }
+        }
+    }
+
+    let sync = app.state::<TimeSync>();
+    let reference = match settings.reference {
+        TimeReference::Ntp => status.ntp.as_ref(),
+        TimeReference::Robot => status.robot.as_ref(),
+        TimeReference::None => None,
+    };
+    match (settings.reference, reference) {
+        (TimeReference::None, _) => sync.correction_ns.store(0, Ordering::Relaxed),
+        (_, Some(offset)) => sync
+            .correction_ns
+            .store((offset.offset_ms * 1e6) as i64, Ordering::Relaxed),
+        // Keep the last correction until the reference answers again.
+        (_, None) => {}
+    }
+    let correction_ms = sync.correction_ns.load(Ordering::Relaxed) as f64 / 1e6;
+    status.correction_ms = correction_ms;
+
+    // The PC clock itself is off by the correction it needs.
+    let mut skews = vec![("pc", Some(-correction_ms))];
+    skews.push((
+        "ntp",
+        status.ntp.as_ref().map(|ntp| ntp.offset_ms - correction_ms),
+    ));
+    skews.push((
+        "robot",
+        status
+            .robot
+            .as_ref()
+            .map(|robot| robot.offset_ms - correction_ms),
+    ));
+    {
+        let mut skewed = sync.skewed.lock().unwrap();
+        for (clock, skew) in skews {
+            let Some(skew) = skew else {
+                continue;
+            };
+            let exceeded = skew.abs() > settings.max_skew_ms;
+            let changed = if exceeded {
+                skewed.insert(clock)
+            } else {
+                skewed.remove(clock)
+            };
+            if !changed {
+                continue;
+            }
+            if exceeded {
+                tracing::warn!(
+                    clock,
+                    skew_ms = skew,
+                    "clock skew beyond {} ms",
+                    settings.max_skew_ms
+                );
+            } else {
+                tracing::info!(clock, skew_ms = skew, "clock skew back within limits");
+            }
+            let _ = app.emit(
+                "clock-skew",
+                ClockSkew {
+                    clock,
+                    exceeded,
+                    skew_ms: skew,
+                    max_skew_ms: settings.max_skew_ms,
+                },
+            );
+        }
+    }
+
+    *sync.status.lock().unwrap() = status.clone();
+    let _ = app.emit("time-sync-status", status.clone());
+    status
+}
+
+/// SNTP (RFC 4330) offset to `server`.
+async fn ntp_offset(server: &str) -> Result<ClockOffset> {
+    let address = tokio::net::lookup_host((server, NTP_PORT))
+        .await?
+        .next()
+        .ok_or_else(|| Error::NotFound(format!("address of {server}")))?;
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    socket.connect(address).await?;
+
+    let mut best: Option<(i64, i64)> = None;
+    let mut last_error = None;
+    for _ in 0..NTP_SAMPLES {
+        let mut request = [0u8; 48];
+        // Leap indicator 0, version 4, client mode.
+        request[0] = 0x23;
+        let sent = system_ns();
+        request[40..48].copy_from_slice(&to_ntp(sent).to_be_bytes());
+        socket.send(&request).await?;
+
+        let mut reply = [0u8; 48];
+        let received = match tokio::time::timeout(NTP_TIMEOUT, socket.recv(&mut reply)).await {
+            Ok(Ok(len)) if len >= 48 => system_ns(),
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => return Err(err.into()),
+            Err(_) => {
+                last_error = Some(format!("{server} did not answer"));
+                continue;
+            }
+        };
+        // Server mode, a real stratum and our own request echoed back.
+        if reply[0] & 0x07 != 4 || reply[1] == 0 || reply[24..32] != request[40..48] {
+            last_error = Some(format!("{server} sent an unusable reply"));
+            continue;
+        }
+        let server_received = from_ntp(u64::from_be_bytes(reply[32..40].try_into().unwrap()));
+        let server_sent = from_ntp(u64::from_be_bytes(reply[40..48].try_into().unwrap()));
+        let offset = ((server_received - sent) + (server_sent - received)) / 2;
+        let round_trip = (received - sent) - (server_sent - server_received);
+        if best.is_none_or(|(_, best)| round_trip < best) {
+            best = Some((offset, round_trip));
+        }
+    }
+    let (offset, round_trip) =
+        best.ok_or_else(|| Error::Stream(last_error.unwrap_or_else(|| "no NTP reply".into())))?;
+    Ok(ClockOffset {
+        offset_ms: offset as f64 / 1e6,
+        round_trip_ms: round_trip.max(0) as f64 / 1e6,
+        measured_at: Utc::now(),
+    })
+}
+
+/// Offset of the controller clock, assuming the reply was taken halfway
+/// through the round trip.
+async fn robot_offset(controller: &FdController) -> Result<ClockOffset> {
+    let mut best: Option<(i64, i64)> = None;
+    for _ in 0..ROBOT_SAMPLES {
+        let sent = system_ns();
+        let clock_ms = controller.clock().await?;
+        let received = system_ns();
+        let round_trip = received - sent;
+        let offset = clock_ms * 1_000_000 - (sent + round_trip / 2);
+        if best.is_none_or(|(_, best)| round_trip < best) {
+            best = Some((offset, round_trip));
+        }
+    }
+    let (offset, round_trip) = best.expect("at least one sample");
+    Ok(ClockOffset {
+        offset_ms: offset as f64 / 1e6,
+        round_trip_ms: round_trip as f64 / 1e6,
+        measured_at: Utc::now(),
+    })
+}
+
+fn system_ns() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i64
+}
+
+/// Unix nanoseconds as a 32.32 fixed-point NTP timestamp.
+fn to_ntp(ns: i64) -> u64 {
+    let seconds = ns.div_euclid(1_000_000_000) + NTP_UNIX_OFFSET;
+    let fraction = (ns.rem_euclid(1_000_000_000) as u64) << 32;
+    ((seconds as u64) << 32) | (fraction / 1_000_000_000)
+}
+
+fn from_ntp(timestamp: u64) -> i64 {
+    let seconds = (timestamp >> 32) as i64 - NTP_UNIX_OFFSET;
+    let fraction = ((timestamp & 0xffff_ffff) * 1_000_000_000) >> 32;
+    seconds * 1_000_000_000 + fraction as i64
+}
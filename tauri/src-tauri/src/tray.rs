@@ -0,0 +1,75 @@
+//! System tray with backend controls, for when the window is minimised
+//! during long recordings.
+
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::instance::focus_main_window;
+use crate::kiosk;
+use crate::sidecar;
+
+const SHOW_WINDOW: &str = "show-window";
+const RESTART_BACKEND: &str = "restart-backend";
+const OPEN_LOGS: &str = "open-logs";
+const QUIT: &str = "quit";
+
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(app, SHOW_WINDOW, "Show window", true, None::<&str>)?,
+            &MenuItem::with_id(app, RESTART_BACKEND, "Restart backend", true, None::<&str>)?,
+            &MenuItem::with_id(app, OPEN_LOGS, "Open logs folder", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, QUIT, "Quit", true, None::<&str>)?,
+        ],
+    )?;
+
+    let mut tray = TrayIconBuilder::with_id("main")
+        .tooltip("Percus AI")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(on_tray_icon_event);
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        SHOW_WINDOW => focus_main_window(app),
+        RESTART_BACKEND => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move { sidecar::restart(&app).await });
+        }
+        // Neither may leave the operator station while it is locked.
+        OPEN_LOGS | QUIT if kiosk::is_active(app) => {}
+        OPEN_LOGS => match app.path().app_log_dir() {
+            Ok(dir) => {
+                if let Err(err) = app.opener().open_path(dir.to_string_lossy(), None::<&str>) {
+                    tracing::warn!("failed to open logs folder: {err}");
+                }
+            }
+            Err(err) => tracing::warn!("no logs folder: {err}"),
+        },
+        // Goes through `RunEvent::ExitRequested`, which stops the sidecar first.
+        QUIT => app.exit(0),
+        _ => {}
+    }
+}
+
+fn on_tray_icon_event(tray: &TrayIcon, event: TrayIconEvent) {
+    if let TrayIconEvent::Click {
+        button: MouseButton::Left,
+        button_state: MouseButtonState::Up,
+        ..
+    } = event
+    {
+        focus_main_window(tray.app_handle());
+    }
+}
@@ -0,0 +1,386 @@
+//! SSH port forwards to remote cells, for support engineers.
+//!
+//! [`open_tunnel`] forwards a local port to an endpoint behind an SSH
+//! server, optionally through jump hosts, with the system `ssh` client
+//! (`tunnels.ssh`). The private key comes from the keychain (see
+//! [`crate::secrets`]) and is written, with the tunnel's client config, to
+//! `tunnels/` in the app data directory only while the tunnel is open. Host
+//! keys are trusted on first use and pinned in `tunnels/known_hosts`.
+//!
+//! A tunnel whose `ssh` exits is reconnected with exponential backoff. Its
+//! state is reported as `tunnel-status`: `connecting` until the local port
+//! accepts connections, `connected`, `reconnecting` after a drop and
+//! `closed` after [`close_tunnel`]. [`resolve`] maps a remote endpoint to
+//! the local end of its tunnel, so connections made by the shell itself go
+//! through it.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::process::{Child, ChildStderr, Command};
+
+use crate::backend_tls;
+use crate::error::{Error, Result};
+use crate::secrets;
+use crate::settings::SettingsStore;
+
+const DIR: &str = "tunnels";
+const KNOWN_HOSTS: &str = "known_hosts";
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How often a running `ssh` is checked.
+const POLL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TunnelRequest {
+    /// Letters, digits, `-` and `_`.
+    pub id: String,
+    /// SSH server doing the forward, `[user@]host[:port]`.
+    pub host: String,
+    /// Hosts to jump through first, in order, each `[user@]host[:port]`.
+    #[serde(default)]
+    pub jump_hosts: Vec<String>,
+    /// Keychain secret holding the private key, for every hop.
+    pub key_secret: String,
+    /// Endpoint as reached from `host`, e.g. the robot controller.
+    pub remote_host: String,
+    pub remote_port: u16,
+    /// Any free port when unset.
+    pub local_port: Option<u16>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TunnelStatus {
+    pub id: String,
+    /// `connecting`, `connected`, `reconnecting` or `closed`.
+    pub state: &'static str,
+    pub host: String,
+    pub remote_host: String,
+    pub remote_port: u16,
+    pub local_port: u16,
+    pub connected_since: Option<DateTime<Utc>>,
+    pub reconnects: u32,
+    /// Why the last connection ended.
+    pub error: Option<String>,
+}
+
+struct Tunnel {
+    request: TunnelRequest,
+    status: StdMutex<TunnelStatus>,
+    /// The running `ssh`, so it can be killed from outside the task.
+    child: StdMutex<Option<Child>>,
+    task: StdMutex<Option<JoinHandle<()>>>,
+}
+
+/// Open tunnels, by id.
+#[derive(Default)]
+pub struct Tunnels(StdMutex<HashMap<String, Arc<Tunnel>>>);
+
+/// Forwards a local port to `remoteHost:remotePort` through `host`.
+#[tauri::command]
+pub async fn open_tunnel(
+    app: AppHandle,
+    tunnels: State<'_, Tunnels>,
+    request: TunnelRequest,
+) -> Result<TunnelStatus> {
+    let valid_id = !request.id.is_empty()
+        && request.id.len() <= 32
+        && request
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    if !valid_id {
+        return Err(Error::Invalid(format!("tunnel id {}", request.id)));
+    }
+    // Everything after `-` would be taken as an ssh option.
+    let hosts = std::iter::once(&request.host).chain(&request.jump_hosts);
+    if let Some(host) = hosts
+        .chain([&request.remote_host])
+        .find(|host| host.is_empty() || host.starts_with('-') || host.contains([',', ' ']))
+    {
+        return Err(Error::Invalid(format!("tunnel host {host}")));
+    }
+    if tunnels.0.lock().unwrap().contains_key(&request.id) {
+        return Err(Error::DeviceBusy(format!("tunnel {} is open", request.id)));
+    }
+    let name = request.key_secret.clone();
+    let key = tauri::async_runtime::spawn_blocking(move || secrets::get(&name))
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))??
+        .ok_or_else(|| Error::NotFound(format!("secret {}", request.key_secret)))?;
+    let local_port = match request.local_port {
+        Some(port) => port,
+        None => TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+            .local_addr()?
+            .port(),
+    };
+    write_files(&app, &request.id, &key)?;
+
+    let tunnel = Arc::new(Tunnel {
+        status: StdMutex::new(TunnelStatus {
+            id: request.id.clone(),
+            state: "connecting",
+            host: request.host.clone(),
+            remote_host: request.remote_host.clone(),
+            remote_port: request.remote_port,
+            local_port,
+            connected_since: None,
+            reconnects: 0,
+            error: None,
+        }),
+        request,
+        child: StdMutex::new(None),
+        task: StdMutex::new(None),
+    });
+    {
+        let mut tunnels = tunnels.0.lock().unwrap();
+        if tunnels.contains_key(&tunnel.request.id) {
+            return Err(Error::DeviceBusy(format!(
+                "tunnel {} is open",
+                tunnel.request.id
+            )));
+        }
+        tunnels.insert(tunnel.request.id.clone(), tunnel.clone());
+    }
+    let task = tauri::async_runtime::spawn(supervise(app.clone(), tunnel.clone()));
+    *tunnel.task.lock().unwrap() = Some(task);
+    tracing::info!(
+        id = %tunnel.request.id,
+        host = %tunnel.request.host,
+        local_port,
+        "tunnel opened"
+    );
+    let status = tunnel.status.lock().unwrap().clone();
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn close_tunnel(app: AppHandle, tunnels: State<'_, Tunnels>, id: String) -> Result<()> {
+    let tunnel = tunnels
+        .0
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or_else(|| Error::NotFound(format!("tunnel {id}")))?;
+    stop(&tunnel);
+    remove_files(&app, &id);
+    let status = {
+        let mut status = tunnel.status.lock().unwrap();
+        status.state = "closed";
+        status.connected_since = None;
+        status.clone()
+    };
+    let _ = app.emit("tunnel-status", status);
+    tracing::info!(%id, "tunnel closed");
+    Ok(())
+}
+
+/// Every open tunnel.
+#[tauri::command]
+pub fn tunnel_status(tunnels: State<'_, Tunnels>) -> Vec<TunnelStatus> {
+    let tunnels = tunnels.0.lock().unwrap();
+    let mut statuses: Vec<TunnelStatus> = tunnels
+        .values()
+        .map(|tunnel| tunnel.status.lock().unwrap().clone())
+        .collect();
+    statuses.sort_by(|a, b| a.id.cmp(&b.id));
+    statuses
+}
+
+/// Where to connect for `host:port`: the local end of a tunnel to it, or
+/// the endpoint itself.
+pub fn resolve(app: &AppHandle, host: &str, port: u16) -> (String, u16) {
+    let tunnels = app.state::<Tunnels>();
+    let tunnels = tunnels.0.lock().unwrap();
+    let forwarded = tunnels.values().find(|tunnel| {
+        tunnel.request.remote_host.eq_ignore_ascii_case(host) && tunnel.request.remote_port == port
+    });
+    match forwarded {
+        Some(tunnel) => (
+            Ipv4Addr::LOCALHOST.to_string(),
+            tunnel.status.lock().unwrap().local_port,
+        ),
+        None => (host.to_owned(), port),
+    }
+}
+
+/// Kills every tunnel, on exit.
+pub fn close_all(app: &AppHandle) {
+    let tunnels: Vec<_> = app.state::<Tunnels>().0.lock().unwrap().drain().collect();
+    for (id, tunnel) in tunnels {
+        stop(&tunnel);
+        remove_files(app, &id);
+    }
+}
+
+fn stop(tunnel: &Tunnel) {
+    if let Some(task) = tunnel.task.lock().unwrap().take() {
+        task.abort();
+    }
+    if let Some(mut child) = tunnel.child.lock().unwrap().take() {
+        let _ = child.start_kill();
+    }
+}
+
+/// Keeps `ssh` running for `tunnel` until the task is aborted.
+async fn supervise(app: AppHandle, tunnel: Arc<Tunnel>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let error = match spawn_ssh(&app, &tunnel) {
+            Ok(stderr) => {
+                let error = watch(&app, &tunnel, stderr).await;
+                if tunnel.status.lock().unwrap().connected_since.is_some() {
+                    backoff = INITIAL_BACKOFF;
+                }
+                error
+            }
+            Err(err) => err.to_string(),
+        };
+        tracing::warn!(id = %tunnel.request.id, "tunnel dropped, retrying in {backoff:?}: {error}");
+        update(&app, &tunnel, |status| {
+            status.state = "reconnecting";
+            status.connected_since = None;
+            status.reconnects += 1;
+            status.error = Some(error);
+        });
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn spawn_ssh(app: &AppHandle, tunnel: &Tunnel) -> Result<ChildStderr> {
+    let request = &tunnel.request;
+    let local_port = tunnel.status.lock().unwrap().local_port;
+    let dir = app.path().app_data_dir()?.join(DIR);
+    let ssh = app.state::<SettingsStore>().get().tunnels.ssh;
+    let mut command = Command::new(ssh);
+    command
+        .arg("-F")
+        .arg(dir.join(format!("{}.config", request.id)))
+        .args(["-N", "-L"])
+        .arg(format!(
+            "127.0.0.1:{local_port}:{}:{}",
+            request.remote_host, request.remote_port
+        ));
+    if !request.jump_hosts.is_empty() {
+        command.arg("-J").arg(request.jump_hosts.join(","));
+    }
+    let mut child = command
+        .arg(format!("ssh://{}", request.host))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    *tunnel.child.lock().unwrap() = Some(child);
+    Ok(stderr)
+}
+
+/// Reports the tunnel connected once its port answers, and returns why its
+/// `ssh` exited.
+async fn watch(app: &AppHandle, tunnel: &Tunnel, mut stderr: ChildStderr) -> String {
+    let output = tauri::async_runtime::spawn(async move {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output).await;
+        output
+    });
+    let local_port = tunnel.status.lock().unwrap().local_port;
+    let status = loop {
+        tokio::time::sleep(POLL).await;
+        let exited = match tunnel.child.lock().unwrap().as_mut() {
+            Some(child) => child.try_wait(),
+            None => return "stopped".into(),
+        };
+        match exited {
+            Ok(Some(status)) => break status.to_string(),
+            Ok(None) => {}
+            Err(err) => break err.to_string(),
+        }
+        let connected = tunnel.status.lock().unwrap().connected_since.is_some();
+        if !connected
+            && TcpStream::connect((Ipv4Addr::LOCALHOST, local_port))
+                .await
+                .is_ok()
+        {
+            tracing::info!(id = %tunnel.request.id, local_port, "tunnel connected");
+            update(app, tunnel, |status| {
+                status.state = "connected";
+                status.connected_since = Some(Utc::now());
+                status.error = None;
+            });
+        }
+    };
+    tunnel.child.lock().unwrap().take();
+    let output = output.await.unwrap_or_default();
+    match output.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+        Some(line) => line.to_owned(),
+        None => format!("ssh exited with {status}"),
+    }
+}
+
+fn update(app: &AppHandle, tunnel: &Tunnel, change: impl FnOnce(&mut TunnelStatus)) {
+    let status = {
+        let mut status = tunnel.status.lock().unwrap();
+        change(&mut status);
+        status.clone()
+    };
+    let _ = app.emit("tunnel-status", status);
+}
+
+/// Writes the key and client config of tunnel `id`.
+fn write_files(app: &AppHandle, id: &str, key: &str) -> Result<()> {
+    let dir = app.path().app_data_dir()?.join(DIR);
+    std::fs::create_dir_all(&dir)?;
+    let key_path = key_file(&dir, id);
+    let mut key = key.trim_end().to_owned();
+    key.push('\n');
+    backend_tls::write_private(&key_path, key.as_bytes())?;
+    let keepalive = app
+        .state::<SettingsStore>()
+        .get()
+        .tunnels
+        .keepalive_secs
+        .max(1);
+    // Jump hosts are reached by `ssh` processes that read the same file.
+    let config = format!(
+        "Host *\n\
+         \x20 IdentityFile \"{}\"\n\
+         \x20 IdentitiesOnly yes\n\
+         \x20 UserKnownHostsFile \"{}\"\n\
+         \x20 StrictHostKeyChecking accept-new\n\
+         \x20 BatchMode yes\n\
+         \x20 ExitOnForwardFailure yes\n\
+         \x20 ServerAliveInterval {keepalive}\n\
+         \x20 ServerAliveCountMax 3\n\
+         \x20 LogLevel ERROR\n",
+        key_path.display(),
+        dir.join(KNOWN_HOSTS).display(),
+    );
+    backend_tls::write_private(&dir.join(format!("{id}.config")), config.as_bytes())
+}
+
+fn remove_files(app: &AppHandle, id: &str) {
+    let Ok(dir) = app.path().app_data_dir().map(|dir| dir.join(DIR)) else {
+        return;
+    };
+    for path in [key_file(&dir, id), dir.join(format!("{id}.config"))] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn key_file(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.key"))
+}
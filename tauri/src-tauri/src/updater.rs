@@ -0,0 +1,124 @@
+//! Application updates with stable/beta release channels.
+//!
+//! [`check_for_updates`] queries the manifest for the channel selected in
+//! settings and keeps the result; [`install_update`] downloads it while
+//! emitting `update-progress`, stops the sidecar cleanly, installs, and
+//! relaunches the app; if installing fails, the sidecar is started again. A
+//! check requested while offline is queued with [`crate::offline`] and its
+//! result emitted as `update-available`.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::error::{Error, Result};
+use crate::notifications::{self, Action, Category, Notice};
+use crate::offline::{self, Operation};
+use crate::settings::SettingsStore;
+use crate::sidecar;
+use crate::users::{self, Capability};
+
+/// Update found by the last check, waiting to be installed.
+#[derive(Default)]
+pub struct PendingUpdate(Mutex<Option<Update>>);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+    pub version: String,
+    current_version: String,
+    channel: &'static str,
+    notes: Option<String>,
+    date: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateProgress {
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+#[tauri::command]
+pub async fn check_for_updates(app: AppHandle) -> Result<Option<UpdateInfo>> {
+    if !offline::is_online(&app) {
+        offline::enqueue(&app, Operation::UpdateCheck);
+        return Err(Error::Offline(
+            "update check queued until the connection is back".into(),
+        ));
+    }
+    check(&app).await
+}
+
+/// Checks the selected channel and keeps the update found, if any.
+pub async fn check(app: &AppHandle) -> Result<Option<UpdateInfo>> {
+    let updates = app.state::<SettingsStore>().get().updates;
+    let endpoint = updates
+        .endpoint
+        .replace("{channel}", updates.channel.as_str());
+    let endpoint = endpoint
+        .parse()
+        .map_err(|err| Error::Invalid(format!("update endpoint `{endpoint}`: {err}")))?;
+
+    let update = app
+        .updater_builder()
+        .endpoints(vec![endpoint])?
+        .build()?
+        .check()
+        .await?;
+    let info = update.as_ref().map(|update| UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        channel: updates.channel.as_str(),
+        notes: update.body.clone(),
+        date: update.date.map(|date| date.to_string()),
+    });
+    *app.state::<PendingUpdate>().0.lock().unwrap() = update;
+    Ok(info)
+}
+
+/// Notifies that the update found by a background check can be installed.
+pub fn announce(app: &AppHandle, info: &UpdateInfo) {
+    notifications::notify(
+        app,
+        Notice::new(
+            Category::Updates,
+            format!("Update {} available", info.version),
+            format!("You are running {}", info.current_version),
+        )
+        .action(Action::InstallUpdate),
+    );
+}
+
+#[tauri::command]
+pub async fn install_update(app: AppHandle, pending: State<'_, PendingUpdate>) -> Result<()> {
+    users::require(&app, Capability::InstallUpdates)?;
+    let update = pending
+        .0
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::NotFound("pending update; run check_for_updates first".into()))?;
+
+    let mut downloaded = 0u64;
+    let bytes = update
+        .download(
+            |chunk, total| {
+                downloaded += chunk as u64;
+                let _ = app.emit("update-progress", UpdateProgress { downloaded, total });
+            },
+            || {},
+        )
+        .await?;
+
+    tracing::info!(version = %update.version, "installing update");
+    sidecar::shutdown(&app).await;
+    if let Err(err) = update.install(bytes) {
+        tracing::error!(version = %update.version, "update not installed: {err}");
+        sidecar::resume(&app).await;
+        return Err(err.into());
+    }
+    app.restart();
+}
@@ -0,0 +1,989 @@
+//! Resumable uploads of recordings to an S3-compatible bucket (AWS, MinIO).
+//!
+//! [`enqueue_upload`] adds a file or a whole dataset directory to a queue
+//! persisted in `uploads.json` under the app data directory; one worker task
+//! uploads the queue in order. Files of at least `partSizeMb` use multipart
+//! uploads whose upload id and finished parts are persisted after every
+//! part, so a dropped connection or a restart resumes at the next part
+//! instead of the start of a multi-GB video.
+//!
+//! Network errors, throttling and 5xx responses are retried with
+//! exponential backoff up to `maxBackoffSecs`, indefinitely; other client
+//! errors (bad credentials, missing bucket) fail the upload until
+//! [`resume_uploads`] requeues it. [`pause_uploads`] interrupts the part in
+//! flight. Requests are signed with AWS Signature V4 over an unsigned
+//! payload, and bodies are streamed at no more than `bandwidthLimitKbps`.
+//!
+//! Progress is reported as `upload-progress`, state changes as
+//! `upload-status`. While [`crate::offline`] reports no connectivity the
+//! worker holds the queue as if paused. [`put_file`] sends single small
+//! files outside the queue.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use crate::error::{Error, Result};
+use crate::fsutil;
+use crate::licensing::{self, Feature};
+use crate::notifications::{self, Action, Category, Notice};
+use crate::settings::{SettingsStore, UploadSettings};
+
+/// S3's smallest part size other than the last part's.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Body chunk size, and so the granularity of throttling and pausing.
+const CHUNK: usize = 256 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UploadStatus {
+    Queued,
+    Uploading,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartRecord {
+    number: u32,
+    etag: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadFile {
+    pub path: PathBuf,
+    pub key: String,
+    pub size: u64,
+    pub done: bool,
+    /// Multipart upload in progress, with the part size it was started at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_id: Option<String>,
+    #[serde(default)]
+    part_size: u64,
+    #[serde(default)]
+    parts: Vec<PartRecord>,
+}
+
+impl UploadFile {
+    fn sent(&self) -> u64 {
+        if self.done {
+            self.size
+        } else {
+            (self.parts.len() as u64 * self.part_size).min(self.size)
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadJob {
+    pub id: u64,
+    pub source: PathBuf,
+    pub bucket: String,
+    pub status: UploadStatus,
+    pub error: Option<String>,
+    pub files: Vec<UploadFile>,
+}
+
+impl UploadJob {
+    fn progress(&self) -> UploadProgress {
+        UploadProgress {
+            id: self.id,
+            bytes_sent: self.files.iter().map(UploadFile::sent).sum(),
+            bytes_total: self.files.iter().map(|file| file.size).sum(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadProgress {
+    id: u64,
+    bytes_sent: u64,
+    bytes_total: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadStatusEvent {
+    id: u64,
+    status: UploadStatus,
+    error: Option<String>,
+}
+
+/// What `uploads.json` holds.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Queue {
+    paused: bool,
+    next_id: u64,
+    jobs: Vec<UploadJob>,
+}
+
+pub struct Uploads {
+    path: PathBuf,
+    queue: StdMutex<Queue>,
+    paused: AtomicBool,
+    /// Held for lack of connectivity; not persisted.
+    offline: AtomicBool,
+    wake: Notify,
+}
+
+impl Uploads {
+    fn persist(&self, queue: &Queue) {
+        let written = serde_json::to_vec_pretty(queue)
+            .map_err(Error::from)
+            .and_then(|json| Ok(fsutil::write_atomic(&self.path, &json)?));
+        if let Err(err) = written {
+            tracing::warn!("upload queue not saved: {err}");
+        }
+    }
+
+    /// Applies `change` to job `id` and saves the queue.
+    fn update(&self, id: u64, change: impl FnOnce(&mut UploadJob)) -> Option<UploadJob> {
+        let mut queue = self.queue.lock().unwrap();
+        let job = queue.jobs.iter_mut().find(|job| job.id == id)?;
+        change(job);
+        let job = job.clone();
+        self.persist(&queue);
+        Some(job)
+    }
+
+    /// Files the queue has finished uploading.
+    pub fn uploaded_files(&self) -> HashSet<PathBuf> {
+        let queue = self.queue.lock().unwrap();
+        queue
+            .jobs
+            .iter()
+            .flat_map(|job| &job.files)
+            .filter(|file| file.done)
+            .map(|file| file.path.clone())
+            .collect()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed) || self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Holds the queue while offline and restarts it once back online.
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+        self.wake.notify_one();
+    }
+
+    /// Uploads queued or in progress.
+    pub fn waiting(&self) -> usize {
+        self.queue
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.status, UploadStatus::Queued | UploadStatus::Uploading))
+            .count()
+    }
+}
+
+/// Loads the persisted queue and starts the upload worker.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_data_dir()?.join("uploads.json");
+    let mut queue: Queue = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!("upload queue unreadable, starting empty: {err}");
+            Queue::default()
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Queue::default(),
+        Err(err) => return Err(err.into()),
+    };
+    for job in &mut queue.jobs {
+        if job.status == UploadStatus::Uploading {
+            job.status = UploadStatus::Queued;
+        }
+    }
+    app.manage(Uploads {
+        path,
+        paused: AtomicBool::new(queue.paused),
+        offline: AtomicBool::new(false),
+        queue: StdMutex::new(queue),
+        wake: Notify::new(),
+    });
+    tauri::async_runtime::spawn(worker(app.clone()));
+    Ok(())
+}
+
+/// Queues a file, or every file below a directory, for upload. Keys are
+/// `<prefix>/<name>/<relative path>`, `name` defaulting to the file or
+/// directory name.
+#[tauri::command]
+pub fn enqueue_upload(
+    app: AppHandle,
+    uploads: State<'_, Uploads>,
+    path: PathBuf,
+    name: Option<String>,
+) -> Result<UploadJob> {
+    licensing::require(&app, Feature::CloudSync)?;
+    let settings = app.state::<SettingsStore>().get().uploads;
+    if settings.endpoint.is_none() || settings.bucket.is_empty() {
+        return Err(Error::Invalid("no upload bucket is configured".into()));
+    }
+    let name = match name {
+        Some(name) => name,
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| Error::Invalid(format!("upload path {}", path.display())))?,
+    };
+    let base = object_key(&settings, &name);
+
+    let mut files = Vec::new();
+    if path.is_dir() {
+        for file in fsutil::walk_files(&path)? {
+            let relative = file.strip_prefix(&path).unwrap_or(&file);
+            let relative = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(new_file(file.clone(), format!("{base}/{relative}"))?);
+        }
+    } else {
+        files.push(new_file(path.clone(), base)?);
+    }
+    if files.is_empty() {
+        return Err(Error::Invalid(format!("{} has no files", path.display())));
+    }
+
+    let mut queue = uploads.queue.lock().unwrap();
+    let job = UploadJob {
+        id: queue.next_id,
+        source: path,
+        bucket: settings.bucket,
+        status: UploadStatus::Queued,
+        error: None,
+        files,
+    };
+    queue.next_id += 1;
+    queue.jobs.push(job.clone());
+    uploads.persist(&queue);
+    drop(queue);
+    uploads.wake.notify_one();
+    tracing::info!(id = job.id, files = job.files.len(), "upload queued");
+    Ok(job)
+}
+
+#[tauri::command]
+pub fn list_uploads(uploads: State<'_, Uploads>) -> Vec<UploadJob> {
+    uploads.queue.lock().unwrap().jobs.clone()
+}
+
+/// Stops uploading, mid-part, until [`resume_uploads`].
+#[tauri::command]
+pub fn pause_uploads(uploads: State<'_, Uploads>) {
+    uploads.paused.store(true, Ordering::Relaxed);
+    let mut queue = uploads.queue.lock().unwrap();
+    queue.paused = true;
+    uploads.persist(&queue);
+    drop(queue);
+    uploads.wake.notify_one();
+}
+
+/// Restarts uploading, requeueing failed uploads.
+#[tauri::command]
+pub fn resume_uploads(uploads: State<'_, Uploads>) {
+    uploads.paused.store(false, Ordering::Relaxed);
+    let mut queue = uploads.queue.lock().unwrap();
+    queue.paused = false;
+    for job in &mut queue.jobs {
+        if job.status == UploadStatus::Failed {
+            job.status = UploadStatus::Queued;
+            job.error = None;
+        }
+    }
+    uploads.persist(&queue);
+    drop(queue);
+    uploads.wake.notify_one();
+}
+
+/// Drops an upload from the queue. Finished objects stay in the bucket; an
+/// unfinished multipart upload is aborted.
+#[tauri::command]
+pub fn remove_upload(app: AppHandle, uploads: State<'_, Uploads>, id: u64) -> Result<()> {
+    let mut queue = uploads.queue.lock().unwrap();
+    let position = queue
+        .jobs
+        .iter()
+        .position(|job| job.id == id)
+        .ok_or_else(|| Error::NotFound(format!("upload {id}")))?;
+    let job = queue.jobs.remove(position);
+    uploads.persist(&queue);
+    drop(queue);
+    let aborts: Vec<(String, String)> = job
+        .files
+        .into_iter()
+        .filter_map(|file| Some((file.key, file.upload_id?)))
+        .collect();
+    if !aborts.is_empty() {
+        let settings = app.state::<SettingsStore>().get().uploads;
+        tauri::async_runtime::spawn(async move {
+            let Ok(s3) = S3::new(&settings, &job.bucket) else {
+                return;
+            };
+            for (key, upload_id) in aborts {
+                let query = [("uploadId", upload_id.as_str())];
+                let request = s3.request(Method::DELETE, &key, &query);
+                if let Err(err) = s3.send(request).await {
+                    tracing::debug!(%key, "multipart upload not aborted: {err:?}");
+                }
+            }
+        });
+    }
+    uploads.wake.notify_one();
+    Ok(())
+}
+
+/// Uploads `path` in a single request as `name` below the configured
+/// prefix, bypassing the queue; meant for small files.
+pub async fn put_file(app: &AppHandle, path: &Path, name: &str) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().uploads;
+    if settings.endpoint.is_none() || settings.bucket.is_empty() {
+        return Err(Error::Invalid("no upload bucket is configured".into()));
+    }
+    let s3 = S3::new(&settings, &settings.bucket)?;
+    let data = tokio::fs::read(path).await?;
+    let request = s3
+        .request(Method::PUT, &object_key(&settings, name), &[])
+        .header(CONTENT_LENGTH, data.len())
+        .body(data);
+    match s3.send(request).await {
+        Ok(_) => Ok(()),
+        Err(S3Failure::Transient(err) | S3Failure::Fatal(err)) => Err(err),
+        Err(S3Failure::NoSuchUpload) => Err(Error::S3("NoSuchUpload".into())),
+    }
+}
+
+/// `name` below the configured prefix.
+fn object_key(settings: &UploadSettings, name: &str) -> String {
+    [settings.prefix.trim_matches('/'), name.trim_matches('/')]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn new_file(path: PathBuf, key: String) -> Result<UploadFile> {
+    let size = std::fs::metadata(&path)?.len();
+    Ok(UploadFile {
+        path,
+        key,
+        size,
+        done: false,
+        upload_id: None,
+        part_size: 0,
+        parts: Vec::new(),
+    })
+}
+
+/// Why an upload attempt stopped.
+enum Failure {
+    Paused,
+    Retry(Error),
+    Fatal(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Http(_) => Failure::Retry(err),
+            other => Failure::Fatal(other),
+        }
+    }
+}
+
+async fn worker(app: AppHandle) {
+    let uploads = app.state::<Uploads>();
+    let mut attempt = 0u32;
+    loop {
+        let next = (!uploads.is_paused())
+            .then(|| {
+                let queue = uploads.queue.lock().unwrap();
+                queue
+                    .jobs
+                    .iter()
+                    .find(|job| {
+                        matches!(job.status, UploadStatus::Queued | UploadStatus::Uploading)
+                    })
+                    .map(|job| job.id)
+            })
+            .flatten();
+        let Some(id) = next else {
+            uploads.wake.notified().await;
+            continue;
+        };
+
+        set_status(&app, id, UploadStatus::Uploading, None);
+        let settings = app.state::<SettingsStore>().get().uploads;
+        match run_job(&app, &settings, id).await {
+            Ok(()) => {
+                attempt = 0;
+                let job = set_status(&app, id, UploadStatus::Done, None);
+                tracing::info!(id, "upload finished");
+                if let Some(job) = job {
+                    notifications::notify(
+                        &app,
+                        Notice::new(
+                            Category::Uploads,
+                            "Upload finished",
+                            format!("{} is in {}", display_name(&job), job.bucket),
+                        ),
+                    );
+                }
+            }
+            Err(Failure::Paused) => {
+                set_status(&app, id, UploadStatus::Queued, None);
+            }
+            Err(Failure::Fatal(err)) => {
+                attempt = 0;
+                tracing::warn!(id, "upload failed: {err}");
+                let job = set_status(&app, id, UploadStatus::Failed, Some(err.to_string()));
+                if let Some(job) = job {
+                    notifications::notify(
+                        &app,
+                        Notice::new(
+                            Category::Uploads,
+                            format!("Upload of {} failed", display_name(&job)),
+                            err.to_string(),
+                        )
+                        .action(Action::RetryUploads)
+                        .action(Action::ShowWindow),
+                    );
+                }
+            }
+            Err(Failure::Retry(err)) => {
+                let backoff = Duration::from_secs(
+                    (1u64 << attempt.min(16)).min(settings.max_backoff_secs.max(1)),
+                );
+                attempt += 1;
+                tracing::info!(id, attempt, ?backoff, "upload interrupted, retrying: {err}");
+                set_status(&app, id, UploadStatus::Queued, Some(err.to_string()));
+                // Pausing, resuming or queueing cuts the wait short.
+                let _ = tokio::time::timeout(backoff, uploads.wake.notified()).await;
+            }
+        }
+    }
+}
+
+/// Returns the job as updated, unless it was removed.
+fn set_status(
+    app: &AppHandle,
+    id: u64,
+    status: UploadStatus,
+    error: Option<String>,
+) -> Option<UploadJob> {
+    let uploads = app.state::<Uploads>();
+    let updated = uploads.update(id, |job| {
+        job.status = status;
+        job.error = error.clone();
+    });
+    if updated.is_some() {
+        let _ = app.emit("upload-status", UploadStatusEvent { id, status, error });
+    }
+    updated
+}
+
+fn display_name(job: &UploadJob) -> String {
+    job.source.file_name().map_or_else(
+        || job.source.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+async fn run_job(app: &AppHandle, settings: &UploadSettings, id: u64) -> Result<(), Failure> {
+    let uploads = app.state::<Uploads>();
+    let Some(job) = uploads
+        .queue
+        .lock()
+        .unwrap()
+        .jobs
+        .iter()
+        .find(|job| job.id == id)
+        .cloned()
+    else {
+        return Ok(());
+    };
+    let s3 = S3::new(settings, &job.bucket)?;
+    let part_size = (settings.part_size_mb * 1024 * 1024).max(MIN_PART_SIZE);
+    for index in 0..job.files.len() {
+        let Some(file) = current_file(&uploads, id, index) else {
+            // Removed while uploading.
+            return Ok(());
+        };
+        if file.done {
+            continue;
+        }
+        let size = std::fs::metadata(&file.path)
+            .map_err(|err| Failure::Fatal(err.into()))?
+            .len();
+        if size != file.size {
+            // Changed since it was queued; whatever was sent is stale.
+            uploads.update(id, |job| {
+                let file = &mut job.files[index];
+                file.size = size;
+                file.upload_id = None;
+                file.parts.clear();
+            });
+        }
+        upload_file(app, &s3, id, index, part_size).await?;
+    }
+    Ok(())
+}
+
+fn current_file(uploads: &Uploads, id: u64, index: usize) -> Option<UploadFile> {
+    let queue = uploads.queue.lock().unwrap();
+    let job = queue.jobs.iter().find(|job| job.id == id)?;
+    job.files.get(index).cloned()
+}
+
+async fn upload_file(
+    app: &AppHandle,
+    s3: &S3,
+    id: u64,
+    index: usize,
+    part_size: u64,
+) -> Result<(), Failure> {
+    let uploads = app.state::<Uploads>();
+    let Some(mut file) = current_file(&uploads, id, index) else {
+        return Ok(());
+    };
+
+    if file.upload_id.is_none() && file.size < part_size {
+        let data = read_range(&file.path, 0, file.size).await?;
+        let request = s3
+            .request(Method::PUT, &file.key, &[])
+            .header(CONTENT_LENGTH, data.len())
+            .body(throttled_body(app, s3.bandwidth, id, 0, data));
+        s3.send(request)
+            .await
+            .map_err(|err| paused_or(&uploads, err))?;
+        finish_file(app, id, index);
+        return Ok(());
+    }
+
+    let upload_id = match file.upload_id.clone() {
+        Some(upload_id) => upload_id,
+        None => {
+            let request = s3.request(Method::POST, &file.key, &[("uploads", "")]);
+            let body = s3
+                .send(request)
+                .await
+                .map_err(|err| paused_or(&uploads, err))?;
+            let upload_id = xml_value(&body, "UploadId")
+                .ok_or_else(|| Failure::Retry(Error::S3("no UploadId in response".into())))?;
+            uploads.update(id, |job| {
+                let file = &mut job.files[index];
+                file.upload_id = Some(upload_id.clone());
+                file.part_size = part_size;
+                file.parts.clear();
+            });
+            file.part_size = part_size;
+            file.parts.clear();
+            upload_id
+        }
+    };
+
+    let parts = file.size.div_ceil(file.part_size).max(1);
+    for number in 1..=parts as u32 {
+        if file.parts.iter().any(|part| part.number == number) {
+            continue;
+        }
+        let offset = u64::from(number - 1) * file.part_size;
+        let len = file.part_size.min(file.size - offset);
+        let data = read_range(&file.path, offset, len).await?;
+        let number_text = number.to_string();
+        let query = [
+            ("partNumber", number_text.as_str()),
+            ("uploadId", &upload_id),
+        ];
+        let sent_before = uploads
+            .update(id, |_| {})
+            .map_or(0, |job| job.progress().bytes_sent);
+        let request = s3
+            .request(Method::PUT, &file.key, &query)
+            .header(CONTENT_LENGTH, data.len())
+            .body(throttled_body(app, s3.bandwidth, id, sent_before, data));
+        let response = match s3.send_raw(request).await {
+            Ok(response) => response,
+            Err(S3Failure::NoSuchUpload) => {
+                forget_upload(&uploads, id, index);
+                return Err(Failure::Retry(Error::S3("multipart upload expired".into())));
+            }
+            Err(err) => return Err(paused_or(&uploads, err)),
+        };
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned)
+            .ok_or_else(|| Failure::Retry(Error::S3(format!("no ETag for part {number}"))))?;
+        let part = PartRecord { number, etag };
+        file.parts.push(part.clone());
+        if let Some(job) = uploads.update(id, |job| job.files[index].parts.push(part)) {
+            let _ = app.emit("upload-progress", job.progress());
+        }
+    }
+
+    file.parts.sort_by_key(|part| part.number);
+    let mut body = String::from("<CompleteMultipartUpload>");
+    for part in &file.parts {
+        let _ = write!(
+            body,
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            part.number,
+            xml_escape(&part.etag)
+        );
+    }
+    body.push_str("</CompleteMultipartUpload>");
+    let request = s3
+        .request(Method::POST, &file.key, &[("uploadId", &upload_id)])
+        .header(CONTENT_TYPE, "application/xml")
+        .body(body);
+    match s3.send(request).await {
+        Ok(_) => {}
+        Err(S3Failure::NoSuchUpload) => {
+            forget_upload(&uploads, id, index);
+            return Err(Failure::Retry(Error::S3("multipart upload expired".into())));
+        }
+        Err(err) => return Err(paused_or(&uploads, err)),
+    }
+    finish_file(app, id, index);
+    Ok(())
+}
+
+fn finish_file(app: &AppHandle, id: u64, index: usize) {
+    let uploads = app.state::<Uploads>();
+    let job = uploads.update(id, |job| {
+        let file = &mut job.files[index];
+        file.done = true;
+        file.upload_id = None;
+        file.parts.clear();
+    });
+    if let Some(job) = job {
+        let _ = app.emit("upload-progress", job.progress());
+    }
+}
+
+/// Starts the file over after the server dropped its multipart upload.
+fn forget_upload(uploads: &Uploads, id: u64, index: usize) {
+    uploads.update(id, |job| {
+        let file = &mut job.files[index];
+        file.upload_id = None;
+        file.parts.clear();
+    });
+}
+
+fn paused_or(uploads: &Uploads, err: S3Failure) -> Failure {
+    if uploads.is_paused() {
+        return Failure::Paused;
+    }
+    match err {
+        S3Failure::Transient(err) => Failure::Retry(err),
+        S3Failure::NoSuchUpload => Failure::Retry(Error::S3("multipart upload expired".into())),
+        S3Failure::Fatal(err) => Failure::Fatal(err),
+    }
+}
+
+async fn read_range(path: &Path, offset: u64, len: u64) -> Result<Bytes, Failure> {
+    let path = path.to_owned();
+    tauri::async_runtime::spawn_blocking(move || -> std::io::Result<Bytes> {
+        let mut file = std::fs::File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut data)?;
+        Ok(data.into())
+    })
+    .await
+    .map_err(|err| Failure::Fatal(Error::Stream(err.to_string())))?
+    .map_err(|err| Failure::Fatal(err.into()))
+}
+
+/// Streams `data` in chunks, no faster than `bandwidth` bytes per second
+/// (0 is unlimited), ending early with an error once uploads are paused.
+fn throttled_body(
+    app: &AppHandle,
+    bandwidth: u64,
+    id: u64,
+    sent_before: u64,
+    data: Bytes,
+) -> reqwest::Body {
+    let app = app.clone();
+    let total: u64 = app
+        .state::<Uploads>()
+        .queue
+        .lock()
+        .unwrap()
+        .jobs
+        .iter()
+        .find(|job| job.id == id)
+        .map_or(0, |job| job.files.iter().map(|file| file.size).sum());
+    let start = Instant::now();
+    let stream =
+        futures_util::stream::unfold((0usize, start, app), move |(offset, last_progress, app)| {
+            let data = data.clone();
+            async move {
+                if offset >= data.len() {
+                    return None;
+                }
+                if app.state::<Uploads>().is_paused() {
+                    let paused = std::io::Error::other("uploads paused");
+                    return Some((Err(paused), (data.len(), last_progress, app)));
+                }
+                let end = (offset + CHUNK).min(data.len());
+                if bandwidth > 0 {
+                    let due = Duration::from_secs_f64(end as f64 / bandwidth as f64);
+                    if let Some(wait) = due.checked_sub(start.elapsed()) {
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+                let mut last_progress = last_progress;
+                if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                    last_progress = Instant::now();
+                    let progress = UploadProgress {
+                        id,
+                        bytes_sent: sent_before + offset as u64,
+                        bytes_total: total,
+                    };
+                    let _ = app.emit("upload-progress", progress);
+                }
+                let chunk = data.slice(offset..end);
+                Some((Ok::<_, std::io::Error>(chunk), (end, last_progress, app)))
+            }
+        });
+    reqwest::Body::wrap_stream(stream)
+}
+
+/// How a request to the bucket failed.
+#[derive(Debug)]
+enum S3Failure {
+    Transient(Error),
+    NoSuchUpload,
+    Fatal(Error),
+}
+
+/// A bucket on an S3-compatible endpoint, with SigV4 credentials.
+struct S3 {
+    client: reqwest::Client,
+    endpoint: Url,
+    region: String,
+    bucket: String,
+    access_key_id: String,
+    secret_access_key: String,
+    path_style: bool,
+    /// Bytes per second; 0 is unlimited.
+    bandwidth: u64,
+}
+
+impl S3 {
+    fn new(settings: &UploadSettings, bucket: &str) -> Result<Self> {
+        let endpoint = settings
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| Error::Invalid("no upload endpoint is configured".into()))?;
+        let endpoint = Url::parse(endpoint)
+            .map_err(|err| Error::Invalid(format!("upload endpoint `{endpoint}`: {err}")))?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(60))
+            .build()?;
+        Ok(Self {
+            client,
+            endpoint,
+            region: settings.region.clone(),
+            bucket: bucket.to_owned(),
+            access_key_id: settings.access_key_id.clone(),
+            secret_access_key: settings.secret_access_key.clone(),
+            path_style: settings.path_style,
+            bandwidth: settings.bandwidth_limit_kbps * 1000 / 8,
+        })
+    }
+
+    fn url(&self, key: &str, query: &str) -> Url {
+        let mut url = self.endpoint.clone();
+        let base = url.path().trim_end_matches('/').to_owned();
+        let key = uri_encode(key, true);
+        if self.path_style {
+            url.set_path(&format!("{base}/{}/{key}", uri_encode(&self.bucket, false)));
+        } else {
+            if let Some(host) = url.host_str().map(|host| format!("{}.{host}", self.bucket)) {
+                let _ = url.set_host(Some(&host));
+            }
+            url.set_path(&format!("{base}/{key}"));
+        }
+        url.set_query((!query.is_empty()).then_some(query));
+        url
+    }
+
+    /// A request signed with AWS Signature V4.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::RequestBuilder {
+        let mut pairs: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect();
+        pairs.sort();
+        let query = pairs
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let url = self.url(key, &query);
+
+        let now = chrono::Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let mut host = url.host_str().unwrap_or_default().to_owned();
+        if let Some(port) = url.port() {
+            let _ = write!(host, ":{port}");
+        }
+        let canonical = format!(
+            "{method}\n{}\n{query}\nhost:{host}\nx-amz-content-sha256:{UNSIGNED_PAYLOAD}\nx-amz-date:{timestamp}\n\nhost;x-amz-content-sha256;x-amz-date\n{UNSIGNED_PAYLOAD}",
+            url.path()
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical.as_bytes()))
+        );
+        let mut key = hmac(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&key, to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+            self.access_key_id
+        );
+        self.client
+            .request(method, url)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header("x-amz-date", timestamp)
+            .header("authorization", authorization)
+    }
+
+    async fn send_raw(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, S3Failure> {
+        let response = request
+            .send()
+            .await
+            .map_err(|err| S3Failure::Transient(err.into()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(classify(status, &body))
+    }
+
+    /// Sends the request and returns the response body.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<String, S3Failure> {
+        let body = self
+            .send_raw(request)
+            .await?
+            .text()
+            .await
+            .map_err(|err| S3Failure::Transient(err.into()))?;
+        // CompleteMultipartUpload reports some failures in a 200 response.
+        if body.contains("<Error>") {
+            return Err(classify(StatusCode::INTERNAL_SERVER_ERROR, &body));
+        }
+        Ok(body)
+    }
+}
+
+fn classify(status: StatusCode, body: &str) -> S3Failure {
+    let code = xml_value(body, "Code").unwrap_or_default();
+    if code == "NoSuchUpload" {
+        return S3Failure::NoSuchUpload;
+    }
+    let message = xml_value(body, "Message").unwrap_or_default();
+    let err = Error::S3(format!("{status} {code} {message}").trim().to_owned());
+    if status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || code == "RequestTimeout"
+        || code == "SlowDown"
+    {
+        S3Failure::Transient(err)
+    } else {
+        S3Failure::Fatal(err)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters (and `/`
+/// in object keys), as SigV4 requires.
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// Text of the first `<tag>` element; S3 responses are small and flat
+/// enough not to need an XML parser.
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{tag}>"))?;
+    Some(
+        body[start..end]
+            .replace("&quot;", "\"")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,524 @@
+//! The robot's URDF: one model for the 3D viewer, the frame transforms and
+//! the jog limiter.
+//!
+//! The model comes from `urdf.path`, or else from `urdf/robot.urdf` in the
+//! app's resources; [`load_urdf`] loads another file in its place until the
+//! settings change. Mesh references are resolved to absolute paths for the
+//! webview: `package://<name>/...` against `urdf.packageDirs`, or else the
+//! nearest directory called `<name>` above the URDF, or else the directory
+//! above the one holding it (the `<package>/urdf/robot.urdf` layout);
+//! `file://` and relative paths as they are.
+//!
+//! Once loaded, the model's joints become edges of [`crate::tf`]'s tree and
+//! its revolute joint limits are the jog limiter's unless `jog.jointLimits`
+//! is set. [`forward_kinematics`] and [`check_joint_limits`] answer for
+//! given joint positions, in radians and metres as in the URDF.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+
+use nalgebra::{Isometry3, Translation3, Unit, UnitQuaternion, Vector3};
+use serde::Serialize;
+use tauri::{AppHandle, Listener, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::settings::{Settings, SettingsStore, UrdfSettings};
+use crate::tf;
+
+const BUNDLED: &str = "urdf/robot.urdf";
+const PACKAGE_SCHEME: &str = "package://";
+const FILE_SCHEME: &str = "file://";
+
+/// Returned by [`load_urdf`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotModel {
+    pub name: String,
+    pub source: PathBuf,
+    /// The link no joint has as its child.
+    pub root: String,
+    pub links: Vec<LinkInfo>,
+    /// Parents before children.
+    pub joints: Vec<JointInfo>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkInfo {
+    pub name: String,
+    pub visuals: Vec<VisualInfo>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VisualInfo {
+    pub xyz: [f64; 3],
+    pub rpy: [f64; 3],
+    pub geometry: GeometryInfo,
+    /// RGBA from 0 to 1, resolving named materials.
+    pub color: Option<[f64; 4]>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GeometryInfo {
+    Box {
+        size: [f64; 3],
+    },
+    Cylinder {
+        radius: f64,
+        length: f64,
+    },
+    Capsule {
+        radius: f64,
+        length: f64,
+    },
+    Sphere {
+        radius: f64,
+    },
+    Mesh {
+        /// As written in the URDF.
+        uri: String,
+        /// `None` if a `package://` reference could not be resolved.
+        path: Option<PathBuf>,
+        scale: [f64; 3],
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JointKind {
+    Revolute,
+    Continuous,
+    Prismatic,
+    Fixed,
+    /// Floating and planar joints are shown at their origin.
+    Other,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointInfo {
+    pub name: String,
+    pub kind: JointKind,
+    pub parent: String,
+    pub child: String,
+    pub xyz: [f64; 3],
+    pub rpy: [f64; 3],
+    pub axis: [f64; 3],
+    /// Revolute and prismatic joints only.
+    pub limit: Option<JointLimit>,
+    pub mimic: Option<MimicInfo>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointLimit {
+    pub lower: f64,
+    pub upper: f64,
+    pub velocity: f64,
+    pub effort: f64,
+}
+
+/// The joint follows `multiplier * position(joint) + offset`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MimicInfo {
+    pub joint: String,
+    pub multiplier: f64,
+    pub offset: f64,
+}
+
+impl JointInfo {
+    pub fn origin(&self) -> Isometry3<f64> {
+        let [roll, pitch, yaw] = self.rpy;
+        Isometry3::from_parts(
+            Translation3::from(Vector3::from(self.xyz)),
+            UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+        )
+    }
+
+    /// Whether the joint moves by a position of its own, not mimicking
+    /// another.
+    pub fn is_actuated(&self) -> bool {
+        self.mimic.is_none()
+            && matches!(
+                self.kind,
+                JointKind::Revolute | JointKind::Continuous | JointKind::Prismatic
+            )
+    }
+
+    /// Child pose in the parent at `position`.
+    fn transform(&self, position: f64) -> Isometry3<f64> {
+        let axis = Unit::try_new(Vector3::from(self.axis), 1e-9).unwrap_or(Vector3::x_axis());
+        let motion = match self.kind {
+            JointKind::Revolute | JointKind::Continuous => Isometry3::from_parts(
+                Translation3::identity(),
+                UnitQuaternion::from_axis_angle(&axis, position),
+            ),
+            JointKind::Prismatic => Isometry3::from_parts(
+                Translation3::from(axis.into_inner() * position),
+                UnitQuaternion::identity(),
+            ),
+            JointKind::Fixed | JointKind::Other => Isometry3::identity(),
+        };
+        self.origin() * motion
+    }
+
+    fn position(&self, positions: &HashMap<String, f64>) -> f64 {
+        match &self.mimic {
+            Some(mimic) => {
+                positions.get(&mimic.joint).copied().unwrap_or(0.0) * mimic.multiplier
+                    + mimic.offset
+            }
+            None => positions.get(&self.name).copied().unwrap_or(0.0),
+        }
+    }
+}
+
+impl RobotModel {
+    /// Pose of every link in the root, for joint `positions` (missing ones
+    /// at zero).
+    pub fn forward(&self, positions: &HashMap<String, f64>) -> HashMap<String, Isometry3<f64>> {
+        let mut poses = HashMap::from([(self.root.clone(), Isometry3::identity())]);
+        for joint in &self.joints {
+            let parent = poses[&joint.parent];
+            poses.insert(
+                joint.child.clone(),
+                parent * joint.transform(joint.position(positions)),
+            );
+        }
+        poses
+    }
+
+    /// Limits of the actuated joints in chain order, in degrees; joints
+    /// without angular limits are unbounded.
+    pub fn joint_limits_deg(&self) -> Vec<[f64; 2]> {
+        self.joints
+            .iter()
+            .filter(|joint| joint.is_actuated())
+            .map(|joint| match (joint.kind, joint.limit) {
+                (JointKind::Revolute, Some(limit)) => {
+                    [limit.lower.to_degrees(), limit.upper.to_degrees()]
+                }
+                _ => [f64::NEG_INFINITY, f64::INFINITY],
+            })
+            .collect()
+    }
+}
+
+/// Returned by [`forward_kinematics`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPose {
+    pub link: String,
+    /// In the root link, in metres.
+    pub translation: [f64; 3],
+    /// `x y z w`.
+    pub rotation: [f64; 4],
+}
+
+/// Returned by [`check_joint_limits`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitViolation {
+    pub joint: String,
+    pub position: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// The loaded model, if any.
+#[derive(Default)]
+pub struct Urdf(StdMutex<Option<Arc<RobotModel>>>);
+
+/// Loads the configured or bundled URDF and reloads it with the settings.
+pub fn init(app: &AppHandle) {
+    app.manage(Urdf::default());
+    reload(app, &app.state::<SettingsStore>().get().urdf);
+    let handle = app.clone();
+    let applied = StdMutex::new(app.state::<SettingsStore>().get().urdf);
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            let mut applied = applied.lock().unwrap();
+            if *applied != settings.urdf {
+                *applied = settings.urdf.clone();
+                reload(&handle, &settings.urdf);
+                tf::refresh(&handle);
+            }
+        }
+    });
+}
+
+/// The loaded model, if any.
+pub fn current(app: &AppHandle) -> Option<Arc<RobotModel>> {
+    app.try_state::<Urdf>()?.0.lock().unwrap().clone()
+}
+
+/// Loads `path`, or the configured or bundled URDF, as the robot's model.
+#[tauri::command]
+pub async fn load_urdf(app: AppHandle, path: Option<PathBuf>) -> Result<RobotModel> {
+    let settings = app.state::<SettingsStore>().get().urdf;
+    let path = match path {
+        Some(path) => path,
+        None => configured_path(&app, &settings)?,
+    };
+    let model = {
+        let path = path.clone();
+        tauri::async_runtime::spawn_blocking(move || parse(&path, &settings))
+            .await
+            .map_err(|err| Error::Stream(err.to_string()))??
+    };
+    tracing::info!(path = %path.display(), robot = %model.name, "urdf loaded");
+    *app.state::<Urdf>().0.lock().unwrap() = Some(Arc::new(model.clone()));
+    tf::refresh(&app);
+    Ok(model)
+}
+
+/// Pose of every link in the root link for joint `positions`, by joint
+/// name; joints left out are at zero.
+#[tauri::command]
+pub fn forward_kinematics(
+    urdf: State<'_, Urdf>,
+    positions: HashMap<String, f64>,
+) -> Result<Vec<LinkPose>> {
+    let model = loaded(&urdf)?;
+    let mut poses: Vec<LinkPose> = model
+        .forward(&positions)
+        .into_iter()
+        .map(|(link, pose)| {
+            let translation = pose.translation.vector;
+            let rotation = pose.rotation.quaternion();
+            LinkPose {
+                link,
+                translation: [translation.x, translation.y, translation.z],
+                rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+            }
+        })
+        .collect();
+    poses.sort_by(|a, b| a.link.cmp(&b.link));
+    Ok(poses)
+}
+
+/// The joints of `positions` outside their URDF limits.
+#[tauri::command]
+pub fn check_joint_limits(
+    urdf: State<'_, Urdf>,
+    positions: HashMap<String, f64>,
+) -> Result<Vec<LimitViolation>> {
+    let model = loaded(&urdf)?;
+    let mut violations = Vec::new();
+    for joint in &model.joints {
+        let Some(limit) = joint.limit else {
+            continue;
+        };
+        let Some(&position) = positions.get(&joint.name) else {
+            continue;
+        };
+        if !(limit.lower..=limit.upper).contains(&position) {
+            violations.push(LimitViolation {
+                joint: joint.name.clone(),
+                position,
+                lower: limit.lower,
+                upper: limit.upper,
+            });
+        }
+    }
+    Ok(violations)
+}
+
+fn loaded(urdf: &Urdf) -> Result<Arc<RobotModel>> {
+    urdf.0
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| Error::NotFound("robot model; no URDF is loaded".into()))
+}
+
+fn configured_path(app: &AppHandle, settings: &UrdfSettings) -> Result<PathBuf> {
+    match &settings.path {
+        Some(path) => Ok(path.clone()),
+        None => Ok(app.path().resource_dir()?.join(BUNDLED)),
+    }
+}
+
+fn reload(app: &AppHandle, settings: &UrdfSettings) {
+    let model = match configured_path(app, settings) {
+        Ok(path) if settings.path.is_none() && !path.is_file() => {
+            tracing::debug!(path = %path.display(), "no bundled urdf");
+            None
+        }
+        Ok(path) => match parse(&path, settings) {
+            Ok(model) => {
+                tracing::info!(path = %path.display(), robot = %model.name, "urdf loaded");
+                Some(Arc::new(model))
+            }
+            Err(err) => {
+                tracing::warn!(path = %path.display(), "urdf not loaded: {err}");
+                None
+            }
+        },
+        Err(err) => {
+            tracing::warn!("urdf not loaded: {err}");
+            None
+        }
+    };
+    *app.state::<Urdf>().0.lock().unwrap() = model;
+}
+
+fn parse(path: &Path, settings: &UrdfSettings) -> Result<RobotModel> {
+    let robot = urdf_rs::read_file(path)?;
+    let materials: HashMap<&str, [f64; 4]> = robot
+        .materials
+        .iter()
+        .filter_map(|material| Some((material.name.as_str(), material.color.as_ref()?.rgba.0)))
+        .collect();
+
+    let links = robot
+        .links
+        .iter()
+        .map(|link| LinkInfo {
+            name: link.name.clone(),
+            visuals: link
+                .visual
+                .iter()
+                .map(|visual| VisualInfo {
+                    xyz: visual.origin.xyz.0,
+                    rpy: visual.origin.rpy.0,
+                    geometry: geometry(&visual.geometry, path, settings),
+                    color: visual.material.as_ref().and_then(|material| {
+                        material
+                            .color
+                            .as_ref()
+                            .map(|color| color.rgba.0)
+                            .or_else(|| materials.get(material.name.as_str()).copied())
+                    }),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let joints: Vec<JointInfo> = robot
+        .joints
+        .iter()
+        .map(|joint| {
+            let kind = match joint.joint_type {
+                urdf_rs::JointType::Revolute => JointKind::Revolute,
+                urdf_rs::JointType::Continuous => JointKind::Continuous,
+                urdf_rs::JointType::Prismatic => JointKind::Prismatic,
+                urdf_rs::JointType::Fixed => JointKind::Fixed,
+                _ => JointKind::Other,
+            };
+            JointInfo {
+                name: joint.name.clone(),
+                kind,
+                parent: joint.parent.link.clone(),
+                child: joint.child.link.clone(),
+                xyz: joint.origin.xyz.0,
+                rpy: joint.origin.rpy.0,
+                axis: joint.axis.xyz.0,
+                limit: matches!(kind, JointKind::Revolute | JointKind::Prismatic).then_some(
+                    JointLimit {
+                        lower: joint.limit.lower,
+                        upper: joint.limit.upper,
+                        velocity: joint.limit.velocity,
+                        effort: joint.limit.effort,
+                    },
+                ),
+                mimic: joint.mimic.as_ref().map(|mimic| MimicInfo {
+                    joint: mimic.joint.clone(),
+                    multiplier: mimic.multiplier.unwrap_or(1.0),
+                    offset: mimic.offset.unwrap_or(0.0),
+                }),
+            }
+        })
+        .collect();
+
+    let mut roots = robot
+        .links
+        .iter()
+        .map(|link| &link.name)
+        .filter(|link| !joints.iter().any(|joint| &joint.child == *link));
+    let root = match (roots.next(), roots.next()) {
+        (Some(root), None) => root.clone(),
+        (None, _) => return Err(Error::Invalid("urdf has no root link".into())),
+        (Some(first), Some(second)) => {
+            return Err(Error::Invalid(format!(
+                "urdf has more than one root link: `{first}`, `{second}`"
+            )))
+        }
+    };
+    let joints = chain_order(&root, joints)?;
+    Ok(RobotModel {
+        name: robot.name,
+        source: path.to_path_buf(),
+        root,
+        links,
+        joints,
+    })
+}
+
+/// Orders joints breadth-first from `root`, failing on joints that hang
+/// off no link reachable from it.
+fn chain_order(root: &str, joints: Vec<JointInfo>) -> Result<Vec<JointInfo>> {
+    let total = joints.len();
+    let mut remaining = joints;
+    let mut ordered = Vec::with_capacity(total);
+    let mut queue = VecDeque::from([root.to_string()]);
+    while let Some(link) = queue.pop_front() {
+        let (children, rest): (Vec<_>, Vec<_>) = remaining
+            .into_iter()
+            .partition(|joint| joint.parent == link);
+        remaining = rest;
+        queue.extend(children.iter().map(|joint| joint.child.clone()));
+        ordered.extend(children);
+    }
+    if let Some(joint) = remaining.first() {
+        return Err(Error::Invalid(format!(
+            "urdf joint `{}` is not connected to the root link",
+            joint.name
+        )));
+    }
+    Ok(ordered)
+}
+
+fn geometry(geometry: &urdf_rs::Geometry, urdf: &Path, settings: &UrdfSettings) -> GeometryInfo {
+    match geometry {
+        urdf_rs::Geometry::Box { size } => GeometryInfo::Box { size: size.0 },
+        urdf_rs::Geometry::Cylinder { radius, length } => GeometryInfo::Cylinder {
+            radius: *radius,
+            length: *length,
+        },
+        urdf_rs::Geometry::Capsule { radius, length } => GeometryInfo::Capsule {
+            radius: *radius,
+            length: *length,
+        },
+        urdf_rs::Geometry::Sphere { radius } => GeometryInfo::Sphere { radius: *radius },
+        urdf_rs::Geometry::Mesh { filename, scale } => GeometryInfo::Mesh {
+            uri: filename.clone(),
+            path: resolve_mesh(filename, urdf, settings),
+            scale: scale.as_ref().map_or([1.0; 3], |scale| scale.0),
+        },
+    }
+}
+
+fn resolve_mesh(uri: &str, urdf: &Path, settings: &UrdfSettings) -> Option<PathBuf> {
+    let dir = urdf.parent().unwrap_or(Path::new("."));
+    if let Some(reference) = uri.strip_prefix(PACKAGE_SCHEME) {
+        let (package, rest) = reference.split_once('/').unwrap_or((reference, ""));
+        let package_dir = settings.package_dirs.get(package).cloned().or_else(|| {
+            dir.ancestors()
+                .find(|ancestor| ancestor.file_name().is_some_and(|name| name == package))
+                .or_else(|| dir.parent())
+                .map(Path::to_path_buf)
+        });
+        return package_dir.map(|package_dir| package_dir.join(rest));
+    }
+    let path = Path::new(uri.strip_prefix(FILE_SCHEME).unwrap_or(uri));
+    Some(if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        dir.join(path)
+    })
+}
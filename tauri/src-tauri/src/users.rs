@@ -0,0 +1,566 @@
+//! Station users, their roles and the capabilities the shell enforces.
+//!
+//! Accounts are `users.accounts`: a name, a [`Role`] and optionally the
+//! subject (or e-mail) of their SSO identity. PINs are set with
+//! [`set_user`] and kept in the keychain as salted hashes (see
+//! [`crate::kiosk::encode_pin`]) under `user-pin/<name>`, out of reach of
+//! the secret commands. [`login_pin`] checks a PIN here rather than in the
+//! webview, with the kiosk's lockout after repeated failures;
+//! [`login_sso`] asks the OAuth provider's userinfo endpoint who is signed
+//! in (see [`crate::auth`]) and maps the identity onto an account, or onto
+//! `users.ssoDefaultRole` for unknown ones. A session ends with
+//! [`logout_user`] or after `users.sessionTimeoutMins`; every change is
+//! emitted as `user-changed` and recorded in [`crate::audit`].
+//!
+//! Supervisors hold every [`Capability`], operators those in
+//! `users.operatorCapabilities`. Commands that need one call [`require`]
+//! first, which refuses them unless the signed-in user holds it. Nothing is
+//! enforced while `users.enabled` is off or no supervisor account exists,
+//! so a station cannot lock itself out. The automation API acts with the
+//! rights of whoever is signed in at the station.
+
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audit;
+use crate::auth;
+use crate::error::{Error, Result};
+use crate::kiosk;
+use crate::secrets;
+use crate::settings::{SettingsStore, UsersSettings};
+
+/// Keychain prefix of the users' PIN hashes.
+pub const PIN_PREFIX: &str = "user-pin/";
+const MAX_ATTEMPTS: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    #[default]
+    Operator,
+    Supervisor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Capability {
+    RunPrograms,
+    Jog,
+    Teach,
+    WriteRobotVariables,
+    WriteWeldSetpoints,
+    EditSettings,
+    SwitchProfiles,
+    EnterKiosk,
+    ExitKiosk,
+    DeleteDatasets,
+    FlashFirmware,
+    InstallUpdates,
+    ManageUsers,
+}
+
+const ALL: [Capability; 13] = [
+    Capability::RunPrograms,
+    Capability::Jog,
+    Capability::Teach,
+    Capability::WriteRobotVariables,
+    Capability::WriteWeldSetpoints,
+    Capability::EditSettings,
+    Capability::SwitchProfiles,
+    Capability::EnterKiosk,
+    Capability::ExitKiosk,
+    Capability::DeleteDatasets,
+    Capability::FlashFirmware,
+    Capability::InstallUpdates,
+    Capability::ManageUsers,
+];
+
+impl Capability {
+    fn label(self) -> &'static str {
+        match self {
+            Capability::RunPrograms => "starting robot programs",
+            Capability::Jog => "jogging the robot",
+            Capability::Teach => "editing teach points",
+            Capability::WriteRobotVariables => "writing robot variables",
+            Capability::WriteWeldSetpoints => "changing weld setpoints",
+            Capability::EditSettings => "changing settings",
+            Capability::SwitchProfiles => "switching profiles",
+            Capability::EnterKiosk => "entering operator mode",
+            Capability::ExitKiosk => "leaving operator mode",
+            Capability::DeleteDatasets => "deleting datasets",
+            Capability::FlashFirmware => "flashing firmware",
+            Capability::InstallUpdates => "installing updates",
+            Capability::ManageUsers => "managing users",
+        }
+    }
+}
+
+/// An entry of `users.accounts`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserAccount {
+    pub name: String,
+    #[serde(default)]
+    pub role: Role,
+    /// `sub` or `email` claim of the account's SSO identity.
+    #[serde(default)]
+    pub sso_subject: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoginMethod {
+    Pin,
+    Sso,
+}
+
+/// Returned by [`current_user`] and emitted as `user-changed`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentUser {
+    pub name: String,
+    pub role: Role,
+    pub method: LoginMethod,
+    pub since: DateTime<Utc>,
+    pub capabilities: Vec<Capability>,
+}
+
+/// Returned by [`list_users`].
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSummary {
+    pub name: String,
+    pub role: Role,
+    pub sso_subject: Option<String>,
+    pub has_pin: bool,
+}
+
+struct Session {
+    user: CurrentUser,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct Attempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Users {
+    session: StdMutex<Option<Session>>,
+    attempts: StdMutex<Attempts>,
+}
+
+/// Refuses unless the signed-in user holds `capability`, while enforcement
+/// is on.
+pub fn require(app: &AppHandle, capability: Capability) -> Result<()> {
+    let settings = app.state::<SettingsStore>().get().users;
+    if !enforced(&settings) {
+        return Ok(());
+    }
+    allowed(signed_in(app).as_ref(), capability)
+}
+
+/// Whether `user` may do what `capability` covers, while enforced.
+fn allowed(user: Option<&CurrentUser>, capability: Capability) -> Result<()> {
+    match user {
+        Some(user) if user.capabilities.contains(&capability) => Ok(()),
+        Some(user) => Err(Error::Forbidden(format!(
+            "{} may not do {}",
+            user.name,
+            capability.label()
+        ))),
+        None => Err(Error::Forbidden(format!(
+            "sign in to allow {}",
+            capability.label()
+        ))),
+    }
+}
+
+/// Whether a signed-in user holds `capability`; unlike [`require`], never
+/// true without one.
+pub fn holds(app: &AppHandle, capability: Capability) -> bool {
+    enforced(&app.state::<SettingsStore>().get().users)
+        && signed_in(app).is_some_and(|user| user.capabilities.contains(&capability))
+}
+
+/// Name of the signed-in user, if any.
+pub fn current_name(app: &AppHandle) -> Option<String> {
+    signed_in(app).map(|user| user.name)
+}
+
+#[tauri::command]
+pub fn current_user(app: AppHandle) -> Option<CurrentUser> {
+    signed_in(&app)
+}
+
+#[tauri::command]
+pub async fn login_pin(
+    app: AppHandle,
+    users: State<'_, Users>,
+    name: String,
+    pin: String,
+) -> Result<CurrentUser> {
+    let settings = app.state::<SettingsStore>().get().users;
+    {
+        let attempts = users.attempts.lock().unwrap();
+        if let Some(until) = attempts.locked_until {
+            let now = Instant::now();
+            if now < until {
+                return Err(Error::Forbidden(format!(
+                    "too many wrong PINs; try again in {} s",
+                    (until - now).as_secs() + 1
+                )));
+            }
+        }
+    }
+    let account = settings
+        .accounts
+        .iter()
+        .find(|account| account.name == name)
+        .cloned();
+    let secret = format!("{PIN_PREFIX}{name}");
+    let matches = match &account {
+        Some(_) => {
+            blocking(move || match secrets::get(&secret)? {
+                Some(stored) => kiosk::pin_matches(&stored, &pin),
+                None => Ok(false),
+            })
+            .await?
+        }
+        None => false,
+    };
+    let account = match (matches, account) {
+        (true, Some(account)) => {
+            *users.attempts.lock().unwrap() = Attempts::default();
+            account
+        }
+        _ => {
+            let mut attempts = users.attempts.lock().unwrap();
+            attempts.failures += 1;
+            tracing::warn!(%name, failures = attempts.failures, "wrong user PIN");
+            if attempts.failures >= MAX_ATTEMPTS {
+                attempts.failures = 0;
+                attempts.locked_until = Some(Instant::now() + LOCKOUT);
+            }
+            audit::record(&app, "loginFailed", serde_json::json!({ "name": name }));
+            return Err(Error::Forbidden("unknown user or wrong PIN".into()));
+        }
+    };
+    Ok(start(
+        &app,
+        &settings,
+        account.name,
+        account.role,
+        LoginMethod::Pin,
+    ))
+}
+
+/// Signs in as the account of the identity the shell is signed in to the
+/// cloud with.
+#[tauri::command]
+pub async fn login_sso(app: AppHandle) -> Result<CurrentUser> {
+    let claims = auth::userinfo(&app).await?;
+    let claim = |name: &str| {
+        claims
+            .get(name)
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    };
+    let subject = claim("sub");
+    let email = claim("email");
+    let settings = app.state::<SettingsStore>().get().users;
+    let account = settings.accounts.iter().find(|account| {
+        account.sso_subject.is_some()
+            && (account.sso_subject == subject || account.sso_subject == email)
+    });
+    let (name, role) = match (account, settings.sso_default_role) {
+        (Some(account), _) => (account.name.clone(), account.role),
+        (None, Some(role)) => {
+            let name = email
+                .or_else(|| claim("name"))
+                .or(subject)
+                .ok_or_else(|| Error::Auth("userinfo has no subject".into()))?;
+            (name, role)
+        }
+        (None, None) => {
+            audit::record(&app, "loginFailed", serde_json::json!({ "sso": subject }));
+            return Err(Error::Forbidden("no account for this SSO identity".into()));
+        }
+    };
+    Ok(start(&app, &settings, name, role, LoginMethod::Sso))
+}
+
+#[tauri::command]
+pub fn logout_user(app: AppHandle, users: State<'_, Users>) {
+    let session = users.session.lock().unwrap().take();
+    if let Some(session) = session {
+        audit::record(
+            &app,
+            "logout",
+            serde_json::json!({ "name": session.user.name }),
+        );
+    }
+    let _ = app.emit("user-changed", None::<CurrentUser>);
+}
+
+#[tauri::command]
+pub async fn list_users(app: AppHandle) -> Result<Vec<UserSummary>> {
+    let accounts = app.state::<SettingsStore>().get().users.accounts;
+    blocking(move || {
+        accounts
+            .into_iter()
+            .map(|account| {
+                Ok(UserSummary {
+                    has_pin: secrets::get(&format!("{PIN_PREFIX}{}", account.name))?.is_some(),
+                    name: account.name,
+                    role: account.role,
+                    sso_subject: account.sso_subject,
+                })
+            })
+            .collect()
+    })
+    .await
+}
+
+/// Adds or changes an account; `pin` (4–12 digits) replaces its PIN when
+/// given. Needs [`Capability::ManageUsers`] once a supervisor exists.
+#[tauri::command]
+pub async fn set_user(app: AppHandle, account: UserAccount, pin: Option<String>) -> Result<()> {
+    require(&app, Capability::ManageUsers)?;
+    let valid = !account.name.is_empty()
+        && account.name.len() <= 64
+        && account
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(Error::Invalid(format!("user name `{}`", account.name)));
+    }
+    if let Some(pin) = pin {
+        if !(4..=12).contains(&pin.len()) || !pin.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::Invalid("user PIN must be 4 to 12 digits".into()));
+        }
+        let secret = format!("{PIN_PREFIX}{}", account.name);
+        blocking(move || secrets::set(&secret, &kiosk::encode_pin(&pin)?)).await?;
+    }
+    let detail = serde_json::json!({ "name": account.name, "role": account.role });
+    app.state::<SettingsStore>().update(&app, |settings| {
+        let accounts = &mut settings.users.accounts;
+        match accounts
+            .iter_mut()
+            .find(|existing| existing.name == account.name)
+        {
+            Some(existing) => *existing = account,
+            None => accounts.push(account),
+        }
+    })?;
+    audit::record(&app, "userChanged", detail);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_user(app: AppHandle, name: String) -> Result<()> {
+    require(&app, Capability::ManageUsers)?;
+    let secret = format!("{PIN_PREFIX}{name}");
+    blocking(move || secrets::delete(&secret)).await?;
+    app.state::<SettingsStore>().update(&app, |settings| {
+        settings
+            .users
+            .accounts
+            .retain(|account| account.name != name);
+    })?;
+    audit::record(&app, "userRemoved", serde_json::json!({ "name": name }));
+    Ok(())
+}
+
+fn enforced(settings: &UsersSettings) -> bool {
+    settings.enabled
+        && settings
+            .accounts
+            .iter()
+            .any(|account| account.role == Role::Supervisor)
+}
+
+fn capabilities(settings: &UsersSettings, role: Role) -> Vec<Capability> {
+    match role {
+        Role::Supervisor => ALL.to_vec(),
+        Role::Operator => ALL
+            .into_iter()
+            .filter(|capability| settings.operator_capabilities.contains(capability))
+            .collect(),
+    }
+}
+
+fn start(
+    app: &AppHandle,
+    settings: &UsersSettings,
+    name: String,
+    role: Role,
+    method: LoginMethod,
+) -> CurrentUser {
+    let user = CurrentUser {
+        capabilities: capabilities(settings, role),
+        name,
+        role,
+        method,
+        since: Utc::now(),
+    };
+    tracing::info!(name = %user.name, ?role, "user signed in");
+    *app.state::<Users>().session.lock().unwrap() = Some(Session {
+        user: user.clone(),
+        started: Instant::now(),
+    });
+    audit::record(
+        app,
+        "login",
+        serde_json::json!({ "name": user.name, "role": role, "method": method }),
+    );
+    let _ = app.emit("user-changed", Some(user.clone()));
+    user
+}
+
+/// The signed-in user, recording the logout of a session that timed out.
+/// Capabilities follow the current settings.
+fn signed_in(app: &AppHandle) -> Option<CurrentUser> {
+    match current(app) {
+        Lookup::SignedIn(user) => Some(user),
+        Lookup::None => None,
+        Lookup::Expired(name) => {
+            tracing::info!(%name, "user session timed out");
+            audit::record(
+                app,
+                "logout",
+                serde_json::json!({ "name": name, "timeout": true }),
+            );
+            let _ = app.emit("user-changed", None::<CurrentUser>);
+            None
+        }
+    }
+}
+
+enum Lookup {
+    SignedIn(CurrentUser),
+    None,
+    /// The session of this user timed out and was ended.
+    Expired(String),
+}
+
+/// The session, ending it once timed out; the caller records that, with no
+/// lock held.
+fn current(app: &AppHandle) -> Lookup {
+    let settings = app.state::<SettingsStore>().get().users;
+    let Some(users) = app.try_state::<Users>() else {
+        return Lookup::None;
+    };
+    let mut session = users.session.lock().unwrap();
+    let expired = session.as_ref().is_some_and(|session| {
+        settings
+            .session_timeout_mins
+            .is_some_and(|mins| session.started.elapsed() >= Duration::from_secs(mins * 60))
+    });
+    if expired {
+        return session
+            .take()
+            .map_or(Lookup::None, |session| Lookup::Expired(session.user.name));
+    }
+    session.as_ref().map_or(Lookup::None, |session| {
+        Lookup::SignedIn(CurrentUser {
+            capabilities: capabilities(&settings, session.user.role),
+            ..session.user.clone()
+        })
+    })
+}
+
+/// Runs a keychain or hashing call off the async runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Stream(err.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enforcing(operator_capabilities: Vec<Capability>) -> UsersSettings {
+        UsersSettings {
+            enabled: true,
+            accounts: vec![UserAccount {
+                name: "lead".into(),
+                role: Role::Supervisor,
+                sso_subject: None,
+            }],
+            operator_capabilities,
+            ..UsersSettings::default()
+        }
+    }
+
+    fn user(settings: &UsersSettings, role: Role) -> CurrentUser {
+        CurrentUser {
+            name: "someone".into(),
+            role,
+            method: LoginMethod::Pin,
+            since: Utc::now(),
+            capabilities: capabilities(settings, role),
+        }
+    }
+
+    #[test]
+    fn refuses_an_operator_without_capabilities() {
+        let settings = enforcing(Vec::new());
+        assert!(enforced(&settings));
+        let operator = user(&settings, Role::Operator);
+        for capability in ALL {
+            assert!(matches!(
+                allowed(Some(&operator), capability),
+                Err(Error::Forbidden(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn refuses_nobody_signed_in() {
+        assert!(matches!(
+            allowed(None, Capability::Jog),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn grants_operators_their_capabilities_only() {
+        let settings = enforcing(vec![Capability::Jog, Capability::Teach]);
+        let operator = user(&settings, Role::Operator);
+        assert!(allowed(Some(&operator), Capability::Jog).is_ok());
+        assert!(allowed(Some(&operator), Capability::Teach).is_ok());
+        assert!(allowed(Some(&operator), Capability::FlashFirmware).is_err());
+        assert!(allowed(Some(&operator), Capability::DeleteDatasets).is_err());
+    }
+
+    #[test]
+    fn grants_supervisors_everything() {
+        let settings = enforcing(Vec::new());
+        let supervisor = user(&settings, Role::Supervisor);
+        for capability in ALL {
+            assert!(allowed(Some(&supervisor), capability).is_ok());
+        }
+    }
+
+    #[test]
+    fn enforces_only_with_a_supervisor_account() {
+        assert!(!enforced(&UsersSettings {
+            enabled: true,
+            ..UsersSettings::default()
+        }));
+        assert!(!enforced(&UsersSettings {
+            enabled: false,
+            ..enforcing(Vec::new())
+        }));
+    }
+}
@@ -0,0 +1,318 @@
+//! Push-to-talk microphone stream for the backend's voice commands.
+//!
+//! With `voice.enabled` set, the shell keeps `audio.device` open and, while
+//! push-to-talk is held ([`set_ptt`]), sends its audio to the backend
+//! WebSocket at `voice.path`: binary messages of `voice.chunkMs` of 16 kHz
+//! mono signed 16-bit little-endian PCM. Text messages frame them:
+//!
+//! - `{"type": "config", "sampleRate": 16000, "channels": 1, "format":
+//!   "s16le", "chunkMs": ...}` once per connection,
+//! - `{"type": "ptt", "active": true}` before an utterance's first chunk and
+//!   `{"type": "ptt", "active": false}` after its last.
+//!
+//! The microphone is opened at 16 kHz when it supports that and resampled
+//! otherwise. Audio captured while the backend is unreachable is dropped
+//! rather than delivered late.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig};
+use futures_util::SinkExt;
+use rubato::{FftFixedIn, Resampler};
+use serde_json::json;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Listener, Manager, State};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::audio::{self, audio_error};
+use crate::backend_tls;
+use crate::error::{Error, Result};
+use crate::settings::{Settings, SettingsStore, VoiceSettings};
+
+const SAMPLE_RATE: u32 = 16_000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often the capture thread checks whether it should stop.
+const POLL: Duration = Duration::from_millis(200);
+
+/// What goes to the backend, in order.
+enum Outgoing {
+    Ptt(bool),
+    Chunk(Vec<u8>),
+}
+
+/// A microphone buffer, downmixed to mono.
+struct Captured {
+    ptt: bool,
+    samples: Vec<f32>,
+}
+
+struct Running {
+    /// The `voice` section and `audio.device` it was started with.
+    settings: (VoiceSettings, Option<String>),
+    stop: Arc<AtomicBool>,
+    socket: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct Voice {
+    ptt: Arc<AtomicBool>,
+    running: StdMutex<Option<Running>>,
+}
+
+/// Starts streaming when enabled and follows settings changes.
+pub fn init(app: &AppHandle) {
+    app.manage(Voice::default());
+    apply(app, &app.state::<SettingsStore>().get());
+    let handle = app.clone();
+    app.listen_any("settings-changed", move |event| {
+        if let Ok(settings) = serde_json::from_str::<Settings>(event.payload()) {
+            apply(&handle, &settings);
+        }
+    });
+}
+
+/// Holds or releases push-to-talk.
+#[tauri::command]
+pub fn set_ptt(voice: State<'_, Voice>, active: bool) -> Result<()> {
+    if voice.running.lock().unwrap().is_none() {
+        return Err(Error::Invalid("voice.enabled is off".into()));
+    }
+    voice.ptt.store(active, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Restarts the stream when its settings changed.
+fn apply(app: &AppHandle, settings: &Settings) {
+    let voice = app.state::<Voice>();
+    let wanted = (settings.voice.clone(), settings.audio.device.clone());
+    let mut running = voice.running.lock().unwrap();
+    if running.as_ref().map(|running| &running.settings) == Some(&wanted) {
+        return;
+    }
+    if let Some(running) = running.take() {
+        running.stop.store(true, Ordering::Relaxed);
+        running.socket.abort();
+    }
+    voice.ptt.store(false, Ordering::Relaxed);
+    if !wanted.0.enabled {
+        return;
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = unbounded_channel();
+    let (ready, opened) = mpsc::channel();
+    {
+        let config = wanted.clone();
+        let ptt = voice.ptt.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            let chunk_ms = config.0.chunk_ms.max(1);
+            let (captured, capture) = mpsc::channel();
+            let opened = audio::input_device(config.1.as_deref())
+                .and_then(|device| open(&device, ptt, captured));
+            let (stream, rate) = match opened {
+                Ok(opened) => opened,
+                Err(err) => {
+                    let _ = ready.send(Err(err));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            if let Err(err) = encode(rate, chunk_ms, &capture, &sender, &stop) {
+                tracing::warn!("voice stream stopped: {err}");
+            }
+            drop(stream);
+        });
+    }
+    match opened.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => return tracing::warn!("voice stream not started: {err}"),
+        Err(_) => return,
+    }
+    let socket = tauri::async_runtime::spawn(send(app.clone(), wanted.0.clone(), receiver));
+    *running = Some(Running {
+        settings: wanted,
+        stop,
+        socket,
+    });
+}
+
+/// Opens `device` at 16 kHz when it can, at its default rate otherwise.
+fn open(
+    device: &Device,
+    ptt: Arc<AtomicBool>,
+    captured: mpsc::Sender<Captured>,
+) -> Result<(Stream, u32)> {
+    let native = device
+        .supported_input_configs()
+        .map_err(audio_error)?
+        .filter(|range| {
+            range.min_sample_rate().0 <= SAMPLE_RATE && SAMPLE_RATE <= range.max_sample_rate().0
+        })
+        .min_by_key(|range| range.channels())
+        .map(|range| range.with_sample_rate(SampleRate(SAMPLE_RATE)));
+    let config = match native {
+        Some(config) => config,
+        None => device.default_input_config().map_err(audio_error)?,
+    };
+    let rate = config.sample_rate().0;
+    let format = config.sample_format();
+    let config = config.config();
+    let stream = match format {
+        SampleFormat::I8 => input_stream::<i8>(device, &config, ptt, captured),
+        SampleFormat::I16 => input_stream::<i16>(device, &config, ptt, captured),
+        SampleFormat::I32 => input_stream::<i32>(device, &config, ptt, captured),
+        SampleFormat::U8 => input_stream::<u8>(device, &config, ptt, captured),
+        SampleFormat::U16 => input_stream::<u16>(device, &config, ptt, captured),
+        SampleFormat::F32 => input_stream::<f32>(device, &config, ptt, captured),
+        SampleFormat::F64 => input_stream::<f64>(device, &config, ptt, captured),
+        other => Err(Error::Unsupported(format!("audio sample format {other}"))),
+    }?;
+    stream.play().map_err(audio_error)?;
+    Ok((stream, rate))
+}
+
+fn input_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    ptt: Arc<AtomicBool>,
+    captured: mpsc::Sender<Captured>,
+) -> Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = usize::from(config.channels.max(1));
+    let mut held = false;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let ptt = ptt.load(Ordering::Relaxed);
+                // One more buffer after the release, so the end gets flushed.
+                if !ptt && !held {
+                    return;
+                }
+                held = ptt;
+                let samples = data
+                    .chunks(channels)
+                    .map(|frame| {
+                        frame.iter().map(|&s| s.to_sample::<f32>()).sum::<f32>() / channels as f32
+                    })
+                    .collect();
+                let _ = captured.send(Captured { ptt, samples });
+            },
+            |err| tracing::warn!("voice capture: {err}"),
+            None,
+        )
+        .map_err(audio_error)
+}
+
+/// Cuts push-to-talk audio into 16 kHz chunks until stopped.
+fn encode(
+    rate: u32,
+    chunk_ms: u32,
+    capture: &mpsc::Receiver<Captured>,
+    sender: &UnboundedSender<Outgoing>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let frames_in = (rate * chunk_ms / 1000).max(1) as usize;
+    let mut resampler = if rate == SAMPLE_RATE {
+        None
+    } else {
+        let resampler =
+            FftFixedIn::<f32>::new(rate as usize, SAMPLE_RATE as usize, frames_in, 1, 1)
+                .map_err(audio_error)?;
+        Some(resampler)
+    };
+    let mut pending = Vec::with_capacity(frames_in);
+    let mut talking = false;
+    while !stop.load(Ordering::Relaxed) {
+        let Ok(captured) = capture.recv_timeout(POLL) else {
+            continue;
+        };
+        if captured.ptt && !talking {
+            talking = true;
+            pending.clear();
+            if let Some(resampler) = &mut resampler {
+                resampler.reset();
+            }
+            let _ = sender.send(Outgoing::Ptt(true));
+        }
+        if !talking {
+            continue;
+        }
+        pending.extend_from_slice(&captured.samples);
+        if !captured.ptt {
+            // Pad the last chunk out to full length.
+            let full = pending.len().div_ceil(frames_in).max(1) * frames_in;
+            pending.resize(full, 0.0);
+        }
+        while pending.len() >= frames_in {
+            let chunk: Vec<f32> = pending.drain(..frames_in).collect();
+            let chunk = match &mut resampler {
+                Some(resampler) => resampler
+                    .process(&[chunk], None)
+                    .map_err(audio_error)?
+                    .swap_remove(0),
+                None => chunk,
+            };
+            let bytes = chunk
+                .iter()
+                .flat_map(|&sample| {
+                    let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+                    sample.to_le_bytes()
+                })
+                .collect();
+            let _ = sender.send(Outgoing::Chunk(bytes));
+        }
+        if !captured.ptt {
+            talking = false;
+            let _ = sender.send(Outgoing::Ptt(false));
+        }
+    }
+    Ok(())
+}
+
+/// Delivers the stream to the backend, reconnecting as needed.
+async fn send(app: AppHandle, settings: VoiceSettings, mut receiver: UnboundedReceiver<Outgoing>) {
+    let config = json!({
+        "type": "config",
+        "sampleRate": SAMPLE_RATE,
+        "channels": 1,
+        "format": "s16le",
+        "chunkMs": settings.chunk_ms.max(1),
+    });
+    loop {
+        match backend_tls::ws_connect(&app, &settings.path).await {
+            Ok(mut socket) => {
+                tracing::info!(path = %settings.path, "voice stream connected");
+                let mut result = socket.send(Message::Text(config.to_string().into())).await;
+                if app.state::<Voice>().ptt.load(Ordering::Relaxed) {
+                    let start = json!({ "type": "ptt", "active": true });
+                    result = result.and(socket.send(Message::Text(start.to_string().into())).await);
+                }
+                while result.is_ok() {
+                    let Some(outgoing) = receiver.recv().await else {
+                        return;
+                    };
+                    result = match outgoing {
+                        Outgoing::Ptt(active) => {
+                            let message = json!({ "type": "ptt", "active": active });
+                            socket.send(Message::Text(message.to_string().into())).await
+                        }
+                        Outgoing::Chunk(bytes) => socket.send(Message::Binary(bytes.into())).await,
+                    };
+                }
+            }
+            Err(err) => tracing::debug!(path = %settings.path, "voice stream unavailable: {err}"),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+        // Stale audio is worse than none for a voice command.
+        while receiver.try_recv().is_ok() {}
+    }
+}
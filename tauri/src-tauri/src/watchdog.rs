@@ -0,0 +1,165 @@
+//! Safe stop when the UI or the backend stops responding while a robot is
+//! connected.
+//!
+//! The UI calls [`heartbeat`] periodically and the shell polls the backend's
+//! `/health` every `watchdog.backendIntervalMs`. Each side is armed by its
+//! first sign of life, so neither trips while the app is still starting.
+//! When a side then misses its deadline and the FD controller is connected,
+//! `watchdog.stopCommand` is sent to the controller directly, without going
+//! through the backend, and `watchdog-tripped` is emitted. The watchdog
+//! trips once per outage and re-arms when both sides are responsive again.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audit;
+use crate::backend_tls;
+use crate::daihen_fd::{FdCommand, FdController};
+use crate::error::{Error, Result};
+use crate::notifications::{self, Action, Category, Notice};
+use crate::settings::{SettingsStore, WatchdogSettings};
+use crate::sidecar::SidecarState;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WatchdogTripped {
+    reason: String,
+    stop_command: String,
+    /// Why the stop command could not be delivered.
+    error: Option<String>,
+}
+
+/// Last sign of life of each side; `None` until it is armed.
+#[derive(Default)]
+pub struct Watchdog {
+    ui: StdMutex<Option<Instant>>,
+    backend: StdMutex<Option<Instant>>,
+    tripped: AtomicBool,
+}
+
+pub fn init(app: &AppHandle) {
+    app.manage(Watchdog::default());
+    tauri::async_runtime::spawn(probe_backend(app.clone()));
+    tauri::async_runtime::spawn(supervise(app.clone()));
+}
+
+/// Called by the UI at least every `watchdog.uiTimeoutMs`.
+#[tauri::command]
+pub fn heartbeat(watchdog: State<'_, Watchdog>) {
+    *watchdog.ui.lock().unwrap() = Some(Instant::now());
+}
+
+async fn probe_backend(app: AppHandle) {
+    let client = backend_tls::http_client(&app);
+    loop {
+        let settings = app.state::<SettingsStore>().get().watchdog;
+        let port = app.state::<SidecarState>().tls_port();
+        if settings.enabled && port != 0 {
+            let response = client
+                .get(backend_tls::url(&app, "https", "/health"))
+                .timeout(Duration::from_millis(settings.backend_timeout_ms.max(1)))
+                .send()
+                .await;
+            if response.is_ok_and(|response| response.status().is_success()) {
+                *app.state::<Watchdog>().backend.lock().unwrap() = Some(Instant::now());
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(settings.backend_interval_ms.max(50))).await;
+    }
+}
+
+async fn supervise(app: AppHandle) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let settings = app.state::<SettingsStore>().get().watchdog;
+        let watchdog = app.state::<Watchdog>();
+        if !settings.enabled {
+            // Re-armed by fresh signs of life once enabled again.
+            watchdog.ui.lock().unwrap().take();
+            watchdog.backend.lock().unwrap().take();
+            watchdog.tripped.store(false, Ordering::Relaxed);
+            continue;
+        }
+        match missed_deadline(&watchdog, &settings) {
+            Some(reason) => {
+                if app.state::<FdController>().is_connected()
+                    && !watchdog.tripped.swap(true, Ordering::Relaxed)
+                {
+                    trip(&app, &settings, reason).await;
+                }
+            }
+            None => {
+                if watchdog.tripped.swap(false, Ordering::Relaxed) {
+                    tracing::info!("watchdog re-armed");
+                }
+            }
+        }
+    }
+}
+
+fn missed_deadline(watchdog: &Watchdog, settings: &WatchdogSettings) -> Option<String> {
+    let overdue = |last: &StdMutex<Option<Instant>>, timeout_ms: u64| {
+        last.lock()
+            .unwrap()
+            .map(|last| last.elapsed())
+            .filter(|&elapsed| elapsed > Duration::from_millis(timeout_ms))
+    };
+    if let Some(elapsed) = overdue(&watchdog.ui, settings.ui_timeout_ms) {
+        return Some(format!("no UI heartbeat for {} ms", elapsed.as_millis()));
+    }
+    if let Some(elapsed) = overdue(&watchdog.backend, settings.backend_timeout_ms) {
+        return Some(format!(
+            "backend unresponsive for {} ms",
+            elapsed.as_millis()
+        ));
+    }
+    None
+}
+
+/// Sends `watchdog.stopCommand` to the FD controller; the safe stop shared
+/// by the watchdog and [`crate::safety_zones`].
+pub async fn safe_stop(app: &AppHandle) -> Result<()> {
+    let command = app.state::<SettingsStore>().get().watchdog.stop_command;
+    if command.contains(['\r', '\n']) {
+        return Err(Error::Invalid(
+            "watchdog.stopCommand must be a single line".into(),
+        ));
+    }
+    app.state::<FdController>()
+        .urgent(app, FdCommand::Raw(&command))
+        .await
+        .map(|_| ())
+}
+
+async fn trip(app: &AppHandle, settings: &WatchdogSettings, reason: String) {
+    tracing::error!(%reason, command = %settings.stop_command, "watchdog tripped; stopping robot");
+    let result = safe_stop(app).await;
+    let error = result.err().map(|err| {
+        tracing::error!("watchdog safe stop failed: {err}");
+        err.to_string()
+    });
+    let entry = serde_json::json!({ "source": "watchdog", "reason": reason, "error": error });
+    audit::record(app, "safeStop", entry);
+    let body = match &error {
+        Some(error) => format!("{reason}; stop command failed: {error}"),
+        None => reason.clone(),
+    };
+    notifications::notify(
+        app,
+        Notice::new(Category::Safety, "Robot stopped by watchdog", body).action(Action::ShowWindow),
+    );
+    let tripped = WatchdogTripped {
+        reason,
+        stop_command: settings.stop_command.clone(),
+        error,
+    };
+    let _ = app.emit("watchdog-tripped", tripped);
+}
@@ -0,0 +1,442 @@
+//! WebRTC relay for low-latency camera streams.
+//!
+//! [`start_stream`] takes an SDP offer from the webview and answers it with a
+//! peer connection carrying one H.264 track. Frames come from an `ffmpeg`
+//! child that reads either a backend MJPEG endpoint or a local camera and
+//! encodes with `video.encoder`, or the H.264 encoder [`encoding::pick`]
+//! chooses for this machine. ffmpeg inserts access unit delimiters so the
+//! Annex B output can be split into samples without parsing slices.
+//!
+//! Streams adapt their resolution, frame rate and bitrate to the network
+//! (see [`crate::stream_qos`]); a quality change restarts ffmpeg on the same
+//! track.
+//!
+//! A stream ends when [`stop_stream`] is called, the peer connection fails or
+//! ffmpeg exits; `stream-ended` reports which stream stopped and why.
+//!
+//! [`add_track`] feeds the same kind of track into another peer connection,
+//! at a fixed quality, for [`crate::remote_assist`].
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
+use webrtc::api::APIBuilder;
+use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+use crate::backend_tls;
+use crate::encoding::{self, Codec};
+use crate::error::{Error, Result};
+use crate::settings::{SettingsStore, VideoSettings};
+use crate::stream_qos::{self, QualityProfile, StreamStats};
+
+/// Start code followed by an access unit delimiter NAL.
+const AUD: [u8; 5] = [0, 0, 0, 1, 0x09];
+
+/// Where a stream's frames come from.
+#[derive(Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StreamSource {
+    /// An MJPEG endpoint of the local backend, e.g. `/api/cameras/0/mjpeg`.
+    Backend { path: String },
+    /// A camera opened directly: a `/dev/video*` path on Linux, an index on
+    /// macOS, a device name on Windows.
+    Camera { device: String },
+}
+
+impl StreamSource {
+    fn input_args(&self, app: &AppHandle) -> Vec<String> {
+        match self {
+            StreamSource::Backend { path } => {
+                let mut args = backend_tls::ffmpeg_input_args(app);
+                args.extend([
+                    "-f".into(),
+                    "mjpeg".into(),
+                    "-i".into(),
+                    backend_tls::url(app, "https", path),
+                ]);
+                args
+            }
+            StreamSource::Camera { device } => {
+                let (format, input) = if cfg!(target_os = "linux") {
+                    ("v4l2", device.clone())
+                } else if cfg!(target_os = "macos") {
+                    ("avfoundation", device.clone())
+                } else {
+                    ("dshow", format!("video={device}"))
+                };
+                vec!["-f".into(), format.into(), "-i".into(), input]
+            }
+        }
+    }
+}
+
+/// Returned by [`start_stream`]; `sdp` is the answer to hand to the webview's
+/// `RTCPeerConnection`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamAnswer {
+    pub id: u32,
+    pub sdp: String,
+    pub encoder: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamEnded {
+    id: u32,
+    error: Option<String>,
+}
+
+struct ActiveStream {
+    peer: Arc<RTCPeerConnection>,
+    pump: JoinHandle<()>,
+    qos: JoinHandle<()>,
+}
+
+/// Streams currently being relayed, keyed by the id from [`start_stream`].
+#[derive(Default)]
+pub struct Streams {
+    next_id: AtomicU32,
+    active: Mutex<HashMap<u32, ActiveStream>>,
+}
+
+#[tauri::command]
+pub async fn start_stream(
+    app: AppHandle,
+    streams: State<'_, Streams>,
+    settings: State<'_, SettingsStore>,
+    source: StreamSource,
+    offer: String,
+) -> Result<StreamAnswer> {
+    let config = settings.get().video;
+    let encoder = match config.encoder.clone() {
+        Some(encoder) => encoder,
+        None => encoding::pick(&app, &config.ffmpeg, Codec::H264).await,
+    };
+
+    // The webview is on the same host, so host candidates are enough.
+    let peer = new_peer_connection(RTCConfiguration::default()).await?;
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            ..Default::default()
+        },
+        "video".to_owned(),
+        "percus".to_owned(),
+    ));
+    let sender = peer
+        .add_track(track.clone() as Arc<dyn TrackLocal + Send + Sync>)
+        .await?;
+    let stats = Arc::new(StreamStats::default());
+    // RTCP has to be drained for the interceptors (NACK, reports) to run.
+    tauri::async_runtime::spawn({
+        let stats = stats.clone();
+        async move {
+            let mut buf = vec![0u8; 1500];
+            while let Ok((packets, _)) = sender.read(&mut buf).await {
+                stats.rtcp(&packets);
+            }
+        }
+    });
+
+    peer.set_remote_description(RTCSessionDescription::offer(offer)?)
+        .await?;
+    let answer = peer.create_answer(None).await?;
+    let mut gathered = peer.gathering_complete_promise().await;
+    peer.set_local_description(answer).await?;
+    let _ = gathered.recv().await;
+    let sdp = peer
+        .local_description()
+        .await
+        .ok_or_else(|| Error::Stream("no local description after answering".into()))?
+        .sdp;
+
+    let id = streams.next_id.fetch_add(1, Ordering::Relaxed);
+    let profile = stream_qos::profile(&app, 0);
+    let child = match spawn_ffmpeg(&app, &config, &encoder, &source, &profile) {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = peer.close().await;
+            return Err(err);
+        }
+    };
+    let (level, levels) = watch::channel(0);
+    {
+        // Held until the stream is registered so an early ffmpeg exit still finds it.
+        let mut active = streams.active.lock().unwrap();
+        let pump = tauri::async_runtime::spawn({
+            let app = app.clone();
+            let stats = stats.clone();
+            let encoder = encoder.clone();
+            async move {
+                let relay = Relay {
+                    app: &app,
+                    encoder: &encoder,
+                    source: &source,
+                    track: &track,
+                    stats: &stats,
+                };
+                let result = relay.run(child, profile, levels).await;
+                finish(&app, id, result.err().map(|err| err.to_string())).await;
+            }
+        });
+        let qos = stream_qos::spawn(app.clone(), id, stats, level);
+        active.insert(
+            id,
+            ActiveStream {
+                peer: peer.clone(),
+                pump,
+                qos,
+            },
+        );
+    }
+
+    {
+        let app = app.clone();
+        peer.on_peer_connection_state_change(Box::new(move |state| {
+            let app = app.clone();
+            Box::pin(async move {
+                if matches!(
+                    state,
+                    RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+                ) {
+                    finish(&app, id, None).await;
+                }
+            })
+        }));
+    }
+
+    tracing::info!(id, %encoder, "stream started");
+    Ok(StreamAnswer { id, sdp, encoder })
+}
+
+#[tauri::command]
+pub async fn stop_stream(app: AppHandle, id: u32) -> Result<()> {
+    if !app
+        .state::<Streams>()
+        .active
+        .lock()
+        .unwrap()
+        .contains_key(&id)
+    {
+        return Err(Error::NotFound(format!("stream {id}")));
+    }
+    finish(&app, id, None).await;
+    Ok(())
+}
+
+/// Tears a stream down once, however it ended.
+async fn finish(app: &AppHandle, id: u32, error: Option<String>) {
+    let stream = app.state::<Streams>().active.lock().unwrap().remove(&id);
+    let Some(stream) = stream else {
+        return;
+    };
+    match &error {
+        Some(error) => tracing::warn!(id, "stream ended: {error}"),
+        None => tracing::info!(id, "stream ended"),
+    }
+    let _ = app.emit("stream-ended", StreamEnded { id, error });
+    stream.qos.abort();
+    let _ = stream.peer.close().await;
+    // Last, because this may be the pump task itself.
+    stream.pump.abort();
+}
+
+/// Adds an H.264 track of `source` named `id` to `peer`, at the top quality
+/// level; the returned task feeds it until ffmpeg exits.
+pub async fn add_track(
+    app: &AppHandle,
+    peer: &RTCPeerConnection,
+    source: &StreamSource,
+    id: &str,
+) -> Result<JoinHandle<Result<()>>> {
+    let config = app.state::<SettingsStore>().get().video;
+    let encoder = match config.encoder.clone() {
+        Some(encoder) => encoder,
+        None => encoding::pick(app, &config.ffmpeg, Codec::H264).await,
+    };
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            ..Default::default()
+        },
+        id.to_owned(),
+        "percus".to_owned(),
+    ));
+    let sender = peer
+        .add_track(track.clone() as Arc<dyn TrackLocal + Send + Sync>)
+        .await?;
+    let stats = Arc::new(StreamStats::default());
+    tauri::async_runtime::spawn({
+        let stats = stats.clone();
+        async move {
+            let mut buf = vec![0u8; 1500];
+            while let Ok((packets, _)) = sender.read(&mut buf).await {
+                stats.rtcp(&packets);
+            }
+        }
+    });
+    let profile = stream_qos::profile(app, 0);
+    let mut child = spawn_ffmpeg(app, &config, &encoder, source, &profile)?;
+    let frame_duration = Duration::from_secs_f64(1.0 / f64::from(profile.framerate.max(1)));
+    Ok(tauri::async_runtime::spawn(async move {
+        let stdout = child.stdout.take().expect("stdout is piped");
+        // Keeps ffmpeg alive as long as the pump.
+        let _child = child;
+        pump_samples(stdout, &track, frame_duration, &stats).await
+    }))
+}
+
+pub async fn new_peer_connection(config: RTCConfiguration) -> Result<Arc<RTCPeerConnection>> {
+    let mut media = MediaEngine::default();
+    media.register_default_codecs()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media)?;
+    let api = APIBuilder::new()
+        .with_media_engine(media)
+        .with_interceptor_registry(registry)
+        .build();
+    Ok(Arc::new(api.new_peer_connection(config).await?))
+}
+
+/// What the pump of one stream needs to restart its encoder.
+struct Relay<'a> {
+    app: &'a AppHandle,
+    encoder: &'a str,
+    source: &'a StreamSource,
+    track: &'a TrackLocalStaticSample,
+    stats: &'a StreamStats,
+}
+
+impl Relay<'_> {
+    /// Pumps ffmpeg's output into the track, restarting ffmpeg whenever the
+    /// quality level changes.
+    async fn run(
+        &self,
+        mut child: Child,
+        mut profile: QualityProfile,
+        mut levels: watch::Receiver<usize>,
+    ) -> Result<()> {
+        loop {
+            let stdout = child.stdout.take().expect("stdout is piped");
+            let frame_duration = Duration::from_secs_f64(1.0 / f64::from(profile.framerate.max(1)));
+            tokio::select! {
+                result = pump_samples(stdout, self.track, frame_duration, self.stats) => return result,
+                changed = levels.changed() => {
+                    if changed.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            profile = stream_qos::profile(self.app, *levels.borrow_and_update());
+            let config = self.app.state::<SettingsStore>().get().video;
+            // The old encoder is killed as it is replaced.
+            child = spawn_ffmpeg(self.app, &config, self.encoder, self.source, &profile)?;
+        }
+    }
+}
+
+fn spawn_ffmpeg(
+    app: &AppHandle,
+    config: &VideoSettings,
+    encoder: &str,
+    source: &StreamSource,
+    profile: &QualityProfile,
+) -> Result<Child> {
+    let mut command = Command::new(&config.ffmpeg);
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-fflags", "nobuffer"])
+        .args(source.input_args(app))
+        .args(["-an", "-c:v", encoder])
+        .args(low_latency_args(encoder))
+        .args(["-pix_fmt", encoding::pix_fmt(encoder), "-bf", "0"])
+        .args(["-g", &profile.framerate.to_string()])
+        .args(["-r", &profile.framerate.to_string()])
+        .args(["-b:v", &format!("{}k", profile.bitrate_kbps)]);
+    if let Some(height) = profile.max_height {
+        command.args(["-vf", &format!("scale=-2:'min(ih,{height})'")]);
+    }
+    command
+        .args(["-bsf:v", "h264_metadata=aud=insert", "-f", "h264", "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    Ok(command.spawn()?)
+}
+
+fn low_latency_args(encoder: &str) -> &'static [&'static str] {
+    match encoder {
+        "libx264" => &[
+            "-preset",
+            "ultrafast",
+            "-tune",
+            "zerolatency",
+            "-profile:v",
+            "baseline",
+        ],
+        "h264_nvenc" => &["-preset", "p1", "-tune", "ull", "-profile:v", "baseline"],
+        "h264_videotoolbox" => &["-realtime", "1", "-profile:v", "baseline"],
+        _ => &[],
+    }
+}
+
+/// Splits ffmpeg's Annex B output at access unit delimiters into samples.
+async fn pump_samples(
+    mut stdout: tokio::process::ChildStdout,
+    track: &TrackLocalStaticSample,
+    duration: Duration,
+    stats: &StreamStats,
+) -> Result<()> {
+    let mut pending = BytesMut::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let read = stdout.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(Error::Stream("ffmpeg exited".into()));
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        while let Some(end) = find_aud(&pending[1..]).map(|at| at + 1) {
+            let unit = pending.split_to(end).freeze();
+            if unit.starts_with(&AUD) {
+                let bytes = unit.len();
+                let started = Instant::now();
+                write_unit(track, unit, duration).await?;
+                stats.frame_sent(bytes, started.elapsed());
+            }
+        }
+    }
+}
+
+async fn write_unit(track: &TrackLocalStaticSample, data: Bytes, duration: Duration) -> Result<()> {
+    let sample = Sample {
+        data,
+        timestamp: SystemTime::now(),
+        duration,
+        ..Default::default()
+    };
+    track.write_sample(&sample).await?;
+    Ok(())
+}
+
+fn find_aud(data: &[u8]) -> Option<usize> {
+    data.windows(AUD.len()).position(|window| window == AUD)
+}
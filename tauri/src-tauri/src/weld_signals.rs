@@ -0,0 +1,571 @@
+//! Arc voltage and current at the power source's waveform rate (kHz).
+//!
+//! [`start_weld_signals`] listens on `weldSignals.bind` for the waveform
+//! datagrams the power source pushes and receives them on their own
+//! thread, so the raw samples never reach the event channel:
+//!
+//! - `weld-signal` carries, `weldSignals.emitHz` times a second, the
+//!   minimum, maximum and mean of each channel per display bucket of
+//!   `weldSignals.bucketMs` (see [`set_weld_signal_bucket`] for a chart that
+//!   zooms), so spikes a plain decimation would drop stay visible;
+//! - while an episode records and `weldSignals.record` is set, every sample
+//!   is written to `weld/episode_<index>.parquet` under the episode's
+//!   dataset, with its [`time_sync::now_ns`] timestamp so it lines up with
+//!   the episode's rows. Saving the episode keeps the file, discarding it
+//!   deletes it (see [`end`]).
+//!
+//! A datagram is little-endian: magic `PWSG`, sequence `u32`, sample rate
+//! in Hz `u32`, channel count `u16`, sample count `u16`, then the samples
+//! as interleaved `f32`, channels in the order of `weldSignals.channels`.
+//! Samples are placed back to back at the sample rate, ending at the
+//! datagram's arrival, and re-anchored to the arrival time whenever the two
+//! drift apart by more than [`RESYNC`].
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use arrow_array::{ArrayRef, Float32Array, Int64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::{Error, Result};
+use crate::recording::{EpisodeInfo, Recorder};
+use crate::settings::{SettingsStore, WeldSignalSettings};
+use crate::time_sync;
+use crate::windows;
+
+const MAGIC: &[u8; 4] = b"PWSG";
+const HEADER_LEN: usize = 16;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Drift between the sample clock and arrival times tolerated before the
+/// samples are re-anchored.
+const RESYNC: Duration = Duration::from_millis(20);
+/// Samples buffered before a row group is written.
+const FLUSH_ROWS: usize = 65_536;
+const MIN_BUCKET_MS: f64 = 0.1;
+
+/// One channel of the waveform datagrams.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeldChannel {
+    pub name: String,
+    #[serde(default)]
+    pub unit: String,
+}
+
+/// Payload of `weld-signal`: consecutive buckets, starting at `startMs`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WeldSignalEnvelope {
+    channels: Vec<String>,
+    /// Unix milliseconds at the start of the first bucket.
+    start_ms: f64,
+    bucket_ms: f64,
+    /// Per channel, one value per bucket; `null` for buckets without
+    /// samples.
+    min: Vec<Vec<Option<f32>>>,
+    max: Vec<Vec<Option<f32>>>,
+    mean: Vec<Vec<Option<f32>>>,
+    sample_rate_hz: u32,
+    /// Datagrams lost since the stream started.
+    dropped: u64,
+}
+
+struct Running {
+    stop: Arc<AtomicBool>,
+    bucket_ns: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+pub struct WeldSignals {
+    running: StdMutex<Option<Running>>,
+    /// The episode's full-rate file, if one is being written.
+    episode: Arc<StdMutex<Option<EpisodeWriter>>>,
+}
+
+#[tauri::command]
+pub fn start_weld_signals(
+    app: AppHandle,
+    signals: State<'_, WeldSignals>,
+    store: State<'_, SettingsStore>,
+) -> Result<()> {
+    let mut running = signals.running.lock().unwrap();
+    if running
+        .as_ref()
+        .is_some_and(|running| !running.stop.load(Ordering::Relaxed))
+    {
+        return Err(Error::DeviceBusy(
+            "weld signals are already streaming".into(),
+        ));
+    }
+    let settings = store.get().weld_signals;
+    if settings.channels.is_empty() {
+        return Err(Error::Invalid("weldSignals.channels is empty".into()));
+    }
+    let bind = settings.bind.clone();
+    let socket = UdpSocket::bind(&bind)?;
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let bucket_ns = Arc::new(AtomicU64::new(bucket_ns(settings.bucket_ms)));
+    let receiver = Receiver {
+        app: app.clone(),
+        socket,
+        settings,
+        stop: stop.clone(),
+        bucket_ns: bucket_ns.clone(),
+        episode: signals.episode.clone(),
+    };
+    std::thread::Builder::new()
+        .name("weld-signals".into())
+        .spawn(move || receiver.run())?;
+    tracing::info!(%bind, "weld signals listening");
+    *running = Some(Running { stop, bucket_ns });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_weld_signals(signals: State<'_, WeldSignals>) -> Result<()> {
+    let running = signals
+        .running
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| Error::NotFound("weld signal stream".into()))?;
+    running.stop.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Changes the display bucket of the running stream, e.g. to one pixel of
+/// the chart after zooming; the setting is left alone.
+#[tauri::command]
+pub fn set_weld_signal_bucket(signals: State<'_, WeldSignals>, bucket_ms: f64) -> Result<()> {
+    if !bucket_ms.is_finite() || bucket_ms < MIN_BUCKET_MS {
+        return Err(Error::Invalid(format!(
+            "bucket must be at least {MIN_BUCKET_MS} ms"
+        )));
+    }
+    let running = signals.running.lock().unwrap();
+    let running = running
+        .as_ref()
+        .ok_or_else(|| Error::NotFound("weld signal stream".into()))?;
+    running
+        .bucket_ns
+        .store(self::bucket_ns(bucket_ms), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Finishes the full-rate file of the episode being saved or, with
+/// `discard`, deletes it.
+pub async fn end(app: &AppHandle, discard: bool) {
+    let Some(writer) = app.state::<WeldSignals>().episode.lock().unwrap().take() else {
+        return;
+    };
+    let finished = tauri::async_runtime::spawn_blocking(move || {
+        if discard {
+            writer.discard();
+            Ok(None)
+        } else {
+            writer.finish().map(Some)
+        }
+    })
+    .await;
+    match finished {
+        Ok(Ok(Some(path))) => tracing::info!(path = %path.display(), "weld signals saved"),
+        Ok(Ok(None)) => {}
+        Ok(Err(err)) => tracing::warn!("weld signals of the episode lost: {err}"),
+        Err(err) => tracing::warn!("weld signals of the episode lost: {err}"),
+    }
+}
+
+fn bucket_ns(bucket_ms: f64) -> u64 {
+    (bucket_ms.max(MIN_BUCKET_MS) * 1e6) as u64
+}
+
+struct Packet<'a> {
+    sequence: u32,
+    rate_hz: u32,
+    channels: usize,
+    samples: usize,
+    data: &'a [u8],
+}
+
+impl Packet<'_> {
+    fn parse(datagram: &[u8]) -> Option<Packet<'_>> {
+        let header = datagram.get(..HEADER_LEN)?;
+        if &header[..4] != MAGIC {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u16_at = |at: usize| u16::from_le_bytes(header[at..at + 2].try_into().unwrap());
+        let channels = usize::from(u16_at(12));
+        let samples = usize::from(u16_at(14));
+        let data = datagram.get(HEADER_LEN..HEADER_LEN + channels * samples * 4)?;
+        Some(Packet {
+            sequence: u32_at(4),
+            rate_hz: u32_at(8),
+            channels,
+            samples,
+            data,
+        })
+    }
+
+    fn value(&self, sample: usize, channel: usize) -> f32 {
+        let at = (sample * self.channels + channel) * 4;
+        f32::from_le_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+}
+
+/// Extremes and sum of one channel over one bucket.
+#[derive(Clone, Copy)]
+struct Bucket {
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: u32,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Bucket {
+    fn add(&mut self, value: f32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += f64::from(value);
+        self.count += 1;
+    }
+}
+
+/// Finished buckets waiting for the next `weld-signal`.
+struct Envelope {
+    bucket_ns: u64,
+    /// Index of the bucket being filled, counted from the Unix epoch.
+    current: Option<i64>,
+    filling: Vec<Bucket>,
+    /// Index of the first finished bucket and the buckets per channel.
+    first: Option<i64>,
+    finished: Vec<Vec<Bucket>>,
+}
+
+impl Envelope {
+    fn new(channels: usize, bucket_ns: u64) -> Self {
+        Self {
+            bucket_ns,
+            current: None,
+            filling: vec![Bucket::default(); channels],
+            first: None,
+            finished: vec![Vec::new(); channels],
+        }
+    }
+
+    fn add(&mut self, timestamp_ns: i64, values: impl Iterator<Item = f32>) {
+        let index = timestamp_ns.div_euclid(self.bucket_ns as i64);
+        match self.current {
+            Some(current) if index < current => return,
+            Some(current) if index > current => {
+                self.close(current);
+                // Gaps are padded so buckets stay consecutive.
+                for _ in current + 1..index {
+                    for buckets in &mut self.finished {
+                        buckets.push(Bucket::default());
+                    }
+                }
+                self.current = Some(index);
+            }
+            Some(_) => {}
+            None => self.current = Some(index),
+        }
+        for (bucket, value) in self.filling.iter_mut().zip(values) {
+            bucket.add(value);
+        }
+    }
+
+    fn close(&mut self, index: i64) {
+        self.first.get_or_insert(index);
+        for (buckets, bucket) in self.finished.iter_mut().zip(&mut self.filling) {
+            buckets.push(std::mem::take(bucket));
+        }
+    }
+
+    /// The finished buckets, leaving the one being filled.
+    fn take(
+        &mut self,
+        settings: &WeldSignalSettings,
+        rate_hz: u32,
+        dropped: u64,
+    ) -> Option<WeldSignalEnvelope> {
+        let first = self.first.take()?;
+        let column = |pick: &dyn Fn(&Bucket) -> f32| -> Vec<Vec<Option<f32>>> {
+            self.finished
+                .iter()
+                .map(|buckets| {
+                    buckets
+                        .iter()
+                        .map(|bucket| (bucket.count > 0).then(|| pick(bucket)))
+                        .collect()
+                })
+                .collect()
+        };
+        let envelope = WeldSignalEnvelope {
+            channels: settings
+                .channels
+                .iter()
+                .map(|channel| channel.name.clone())
+                .collect(),
+            start_ms: (first as f64) * self.bucket_ns as f64 / 1e6,
+            bucket_ms: self.bucket_ns as f64 / 1e6,
+            min: column(&|bucket| bucket.min),
+            max: column(&|bucket| bucket.max),
+            mean: column(&|bucket| (bucket.sum / f64::from(bucket.count)) as f32),
+            sample_rate_hz: rate_hz,
+            dropped,
+        };
+        for buckets in &mut self.finished {
+            buckets.clear();
+        }
+        Some(envelope)
+    }
+}
+
+/// Full-rate samples of one episode, written as Parquet row groups.
+struct EpisodeWriter {
+    dataset: String,
+    episode_index: usize,
+    path: PathBuf,
+    tmp: PathBuf,
+    schema: Arc<Schema>,
+    writer: ArrowWriter<File>,
+    timestamps: Vec<i64>,
+    values: Vec<Vec<f32>>,
+}
+
+impl EpisodeWriter {
+    fn create(episode: &EpisodeInfo, settings: &WeldSignalSettings) -> Result<Self> {
+        let path = episode
+            .path
+            .join("weld")
+            .join(format!("episode_{:06}.parquet", episode.episode_index));
+        std::fs::create_dir_all(path.parent().expect("weld path has a parent"))?;
+        let mut fields = vec![Field::new("timestamp_ns", DataType::Int64, false)];
+        fields.extend(settings.channels.iter().map(|channel| {
+            Field::new(&channel.name, DataType::Float32, false)
+                .with_metadata(HashMap::from([("unit".into(), channel.unit.clone())]))
+        }));
+        let schema = Arc::new(Schema::new(fields));
+        // Written under a temporary name until the episode is saved.
+        let tmp = path.with_extension("parquet.tmp");
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(File::create(&tmp)?, schema.clone(), Some(properties))?;
+        Ok(Self {
+            dataset: episode.dataset.clone(),
+            episode_index: episode.episode_index,
+            path,
+            tmp,
+            schema,
+            writer,
+            timestamps: Vec::new(),
+            values: vec![Vec::new(); settings.channels.len()],
+        })
+    }
+
+    fn is_for(&self, episode: &EpisodeInfo) -> bool {
+        self.dataset == episode.dataset && self.episode_index == episode.episode_index
+    }
+
+    fn push(&mut self, timestamp_ns: i64, values: impl Iterator<Item = f32>) -> Result<()> {
+        self.timestamps.push(timestamp_ns);
+        for (column, value) in self.values.iter_mut().zip(values) {
+            column.push(value);
+        }
+        if self.timestamps.len() >= FLUSH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.timestamps.is_empty() {
+            return Ok(());
+        }
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(std::mem::take(
+            &mut self.timestamps,
+        )))];
+        for column in &mut self.values {
+            columns.push(Arc::new(Float32Array::from(std::mem::take(column))));
+        }
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<PathBuf> {
+        self.flush()?;
+        self.writer.close()?;
+        std::fs::rename(&self.tmp, &self.path)?;
+        Ok(self.path)
+    }
+
+    fn discard(self) {
+        drop(self.writer);
+        let _ = std::fs::remove_file(&self.tmp);
+    }
+}
+
+struct Receiver {
+    app: AppHandle,
+    socket: UdpSocket,
+    settings: WeldSignalSettings,
+    stop: Arc<AtomicBool>,
+    bucket_ns: Arc<AtomicU64>,
+    episode: Arc<StdMutex<Option<EpisodeWriter>>>,
+}
+
+impl Receiver {
+    fn run(self) {
+        let emit_interval = Duration::from_secs_f64(1.0 / self.settings.emit_hz.clamp(1.0, 120.0));
+        let channels = self.settings.channels.len();
+        let mut buffer = vec![0u8; 65_536];
+        let mut envelope = Envelope::new(channels, self.bucket_ns.load(Ordering::Relaxed));
+        let mut last_emit = Instant::now();
+        let mut last_sequence = None;
+        let mut dropped = 0u64;
+        let mut rate_hz = 0;
+        // Timestamp the next sample continues from.
+        let mut next_ns: Option<i64> = None;
+        let mut mismatch_logged = false;
+        // An episode whose file could not be created.
+        let mut skipped = None;
+        while !self.stop.load(Ordering::Relaxed) {
+            let bucket_ns = self.bucket_ns.load(Ordering::Relaxed);
+            if bucket_ns != envelope.bucket_ns {
+                envelope = Envelope::new(channels, bucket_ns);
+            }
+            let received = self.socket.recv(&mut buffer);
+            if last_emit.elapsed() >= emit_interval {
+                if let Some(event) = envelope.take(&self.settings, rate_hz, dropped) {
+                    windows::emit(&self.app, "weld-signal", event);
+                }
+                last_emit = Instant::now();
+            }
+            let len = match received {
+                Ok(len) => len,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue;
+                }
+                Err(err) => {
+                    tracing::debug!("weld signal receive failed: {err}");
+                    std::thread::sleep(READ_TIMEOUT);
+                    continue;
+                }
+            };
+            let arrived_ns = time_sync::now_ns(&self.app);
+            let Some(packet) = Packet::parse(&buffer[..len]) else {
+                continue;
+            };
+            if packet.channels != channels || packet.rate_hz == 0 {
+                if !mismatch_logged {
+                    tracing::warn!(
+                        channels = packet.channels,
+                        configured = channels,
+                        rate_hz = packet.rate_hz,
+                        "weld signal datagrams do not match weldSignals.channels"
+                    );
+                    mismatch_logged = true;
+                }
+                continue;
+            }
+            if let Some(last) = last_sequence {
+                dropped += u64::from(packet.sequence.wrapping_sub(last).saturating_sub(1));
+            }
+            last_sequence = Some(packet.sequence);
+            rate_hz = packet.rate_hz;
+
+            let period_ns = 1e9 / f64::from(packet.rate_hz);
+            let span_ns = (period_ns * packet.samples.saturating_sub(1) as f64) as i64;
+            let anchored = arrived_ns - span_ns;
+            let start_ns = match next_ns {
+                Some(next) if (next - anchored).abs() <= RESYNC.as_nanos() as i64 => next,
+                _ => anchored,
+            };
+            next_ns = Some(start_ns + (period_ns * packet.samples as f64) as i64);
+
+            let mut episode = self.episode.lock().unwrap();
+            self.follow_episode(&mut episode, &mut skipped);
+            for sample in 0..packet.samples {
+                let timestamp_ns = start_ns + (period_ns * sample as f64) as i64;
+                let values = (0..channels).map(|channel| packet.value(sample, channel));
+                envelope.add(timestamp_ns, values.clone());
+                if let Some(writer) = episode.as_mut() {
+                    if let Err(err) = writer.push(timestamp_ns, values) {
+                        tracing::warn!("weld signals of the episode lost: {err}");
+                        if let Some(writer) = episode.take() {
+                            writer.discard();
+                        }
+                    }
+                }
+            }
+        }
+        tracing::info!(dropped, "weld signals stopped");
+    }
+
+    /// Opens the file of the episode being recorded, finishing that of an
+    /// earlier one left open.
+    fn follow_episode(
+        &self,
+        episode: &mut Option<EpisodeWriter>,
+        skipped: &mut Option<(String, usize)>,
+    ) {
+        if !self.settings.record {
+            return;
+        }
+        let recording = self.app.state::<Recorder>().recording_episode();
+        let Some(recording) = recording else {
+            return;
+        };
+        let key = (recording.dataset.clone(), recording.episode_index);
+        if episode
+            .as_ref()
+            .is_some_and(|writer| writer.is_for(&recording))
+            || skipped.as_ref() == Some(&key)
+        {
+            return;
+        }
+        if let Some(previous) = episode.take() {
+            if let Err(err) = previous.finish() {
+                tracing::warn!("weld signals of the previous episode lost: {err}");
+            }
+        }
+        match EpisodeWriter::create(&recording, &self.settings) {
+            Ok(writer) => *episode = Some(writer),
+            Err(err) => {
+                tracing::warn!("weld signals not recorded: {err}");
+                *skipped = Some(key);
+            }
+        }
+    }
+}
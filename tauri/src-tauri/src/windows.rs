@@ -0,0 +1,245 @@
+//! Detachable camera and telemetry windows.
+//!
+//! [`open_camera_window`] and [`open_telemetry_window`] create secondary
+//! windows (labels `camera-<source>` and `telemetry`) or focus them if they
+//! are already open. Their position and size are kept in `windows.json` in
+//! the app config directory and restored the next time they are opened.
+//!
+//! High-rate streams are sent through [`emit`]: once any window has called
+//! [`subscribe_events`] for an event, it is delivered only to the
+//! subscribed windows; with no subscribers it is broadcast as before.
+//! Windows are unsubscribed when they close. Pages should listen through
+//! their own window (`getCurrentWebviewWindow().listen`), since a global
+//! `listen` receives events addressed to any window. Rust listeners
+//! always receive every event. Topics configured for batching arrive as
+//! `<event>-batch` instead; see [`crate::event_batching`].
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, Emitter, EventTarget, Manager, PhysicalPosition, PhysicalSize, State, WebviewUrl,
+    WebviewWindow, WebviewWindowBuilder, WindowEvent,
+};
+
+use crate::error::{Error, Result};
+use crate::event_batching;
+use crate::fsutil;
+use crate::kiosk;
+
+const GEOMETRY_FILE: &str = "windows.json";
+const TELEMETRY_LABEL: &str = "telemetry";
+const CAMERA_PREFIX: &str = "camera-";
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+pub struct Windows {
+    path: PathBuf,
+    geometry: StdMutex<HashMap<String, Geometry>>,
+    /// Event name → labels of the windows it is routed to.
+    subscriptions: StdMutex<HashMap<String, BTreeSet<String>>>,
+}
+
+/// Loads the saved window geometry.
+pub fn init(app: &AppHandle) -> Result<()> {
+    let path = app.path().app_config_dir()?.join(GEOMETRY_FILE);
+    let geometry = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!("ignoring unreadable {GEOMETRY_FILE}: {err}");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    };
+    app.manage(Windows {
+        path,
+        geometry: StdMutex::new(geometry),
+        subscriptions: StdMutex::default(),
+    });
+    Ok(())
+}
+
+/// Opens a window showing camera `source`; returns its label.
+#[tauri::command]
+pub fn open_camera_window(app: AppHandle, source: String) -> Result<String> {
+    let valid = !source.is_empty()
+        && source
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    if !valid {
+        return Err(Error::Invalid(format!("camera source `{source}`")));
+    }
+    open(
+        &app,
+        &format!("{CAMERA_PREFIX}{source}"),
+        &format!("index.html#/windows/camera/{source}"),
+        &format!("Camera – {source}"),
+        (960.0, 540.0),
+    )
+}
+
+/// Opens the telemetry window; returns its label.
+#[tauri::command]
+pub fn open_telemetry_window(app: AppHandle) -> Result<String> {
+    open(
+        &app,
+        TELEMETRY_LABEL,
+        "index.html#/windows/telemetry",
+        "Telemetry",
+        (800.0, 600.0),
+    )
+}
+
+/// Routes `events` to the calling window.
+#[tauri::command]
+pub fn subscribe_events(window: WebviewWindow, windows: State<'_, Windows>, events: Vec<String>) {
+    let mut subscriptions = windows.subscriptions.lock().unwrap();
+    for event in events {
+        subscriptions
+            .entry(event)
+            .or_default()
+            .insert(window.label().to_owned());
+    }
+}
+
+/// Stops routing `events` to the calling window.
+#[tauri::command]
+pub fn unsubscribe_events(window: WebviewWindow, windows: State<'_, Windows>, events: Vec<String>) {
+    let mut subscriptions = windows.subscriptions.lock().unwrap();
+    for event in events {
+        if let Some(labels) = subscriptions.get_mut(&event) {
+            labels.remove(window.label());
+            if labels.is_empty() {
+                subscriptions.remove(&event);
+            }
+        }
+    }
+}
+
+/// Emits `event` to the windows subscribed to it, or to all windows if
+/// none is.
+pub fn emit<S: Serialize + Clone>(app: &AppHandle, event: &str, payload: S) {
+    if event_batching::offer(app, event, &payload) {
+        return;
+    }
+    deliver(app, event, event, payload);
+}
+
+/// Emits `event` routed like `topic`, bypassing batching.
+pub fn deliver<S: Serialize + Clone>(app: &AppHandle, topic: &str, event: &str, payload: S) {
+    let labels = app
+        .state::<Windows>()
+        .subscriptions
+        .lock()
+        .unwrap()
+        .get(topic)
+        .cloned();
+    match labels {
+        Some(labels) => {
+            let _ = app.emit_filter(event, payload, |target| match target {
+                EventTarget::WebviewWindow { label } => labels.contains(label.as_str()),
+                _ => false,
+            });
+        }
+        None => {
+            let _ = app.emit(event, payload);
+        }
+    }
+}
+
+fn open(
+    app: &AppHandle,
+    label: &str,
+    url: &str,
+    title: &str,
+    default_size: (f64, f64),
+) -> Result<String> {
+    if let Some(window) = app.get_webview_window(label) {
+        window.unminimize()?;
+        window.set_focus()?;
+        return Ok(label.to_owned());
+    }
+
+    let windows = app.state::<Windows>();
+    let saved = windows.geometry.lock().unwrap().get(label).copied();
+    let window = kiosk::guard(
+        app,
+        WebviewWindowBuilder::new(app, label, WebviewUrl::App(url.into())),
+    )
+    .title(title)
+    .inner_size(default_size.0, default_size.1)
+    .visible(false)
+    .build()?;
+    if let Some(geometry) = saved {
+        // Restored only if still on a connected monitor, so a window saved on
+        // an unplugged screen comes back at the default place.
+        let on_screen = window.available_monitors()?.iter().any(|monitor| {
+            let position = monitor.position();
+            let size = monitor.size();
+            (position.x..position.x + size.width as i32).contains(&geometry.x)
+                && (position.y..position.y + size.height as i32).contains(&geometry.y)
+        });
+        if on_screen {
+            window.set_position(PhysicalPosition::new(geometry.x, geometry.y))?;
+            window.set_size(PhysicalSize::new(geometry.width, geometry.height))?;
+        }
+    }
+    window.show()?;
+
+    let app_handle = app.clone();
+    let tracked = window.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+            if tracked.is_minimized().unwrap_or(false) || tracked.is_maximized().unwrap_or(false) {
+                return;
+            }
+            if let (Ok(position), Ok(size)) = (tracked.outer_position(), tracked.inner_size()) {
+                app_handle
+                    .state::<Windows>()
+                    .geometry
+                    .lock()
+                    .unwrap()
+                    .insert(
+                        tracked.label().to_owned(),
+                        Geometry {
+                            x: position.x,
+                            y: position.y,
+                            width: size.width,
+                            height: size.height,
+                        },
+                    );
+            }
+        }
+        WindowEvent::Destroyed => closed(&app_handle, tracked.label()),
+        _ => {}
+    });
+    Ok(label.to_owned())
+}
+
+/// Drops the window's subscriptions and saves its geometry.
+fn closed(app: &AppHandle, label: &str) {
+    let windows = app.state::<Windows>();
+    windows.subscriptions.lock().unwrap().retain(|_, labels| {
+        labels.remove(label);
+        !labels.is_empty()
+    });
+    let geometry = windows.geometry.lock().unwrap().clone();
+    let result = serde_json::to_vec_pretty(&geometry)
+        .map_err(Error::from)
+        .and_then(|bytes| {
+            if let Some(dir) = windows.path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            Ok(fsutil::write_atomic(&windows.path, &bytes)?)
+        });
+    if let Err(err) = result {
+        tracing::warn!("cannot save {GEOMETRY_FILE}: {err}");
+    }
+}
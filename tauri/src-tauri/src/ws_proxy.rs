@@ -0,0 +1,206 @@
+//! WebSocket proxy between the webview and the backend.
+//!
+//! The webview subscribes to a topic, which is a backend WebSocket path such
+//! as `/api/training/ws/gpu-availability`, instead of connecting itself. All
+//! subscribers of a topic share one upstream connection, which the shell
+//! opens against the sidecar's current port and reopens with backoff when
+//! the backend restarts or the connection drops. Messages sent while it is
+//! down are queued (up to [`OUTBOX_CAPACITY`]) and flushed on reconnect.
+//!
+//! Incoming messages arrive as `ws-message` events, parsed as JSON when
+//! possible; `ws-status` reports connection changes. A normal close from the
+//! backend ends the topic instead of reconnecting, since many endpoints
+//! close once their job is done.
+
+use std::collections::HashMap;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::backend_tls;
+use crate::error::{Error, Result};
+
+const OUTBOX_CAPACITY: usize = 256;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WsMessage {
+    topic: String,
+    data: serde_json::Value,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WsStatus {
+    topic: String,
+    connected: bool,
+    /// The topic ended and will not reconnect.
+    closed: bool,
+    error: Option<String>,
+}
+
+struct Topic {
+    subscribers: usize,
+    outbox: mpsc::Sender<String>,
+    task: JoinHandle<()>,
+}
+
+/// Upstream connections, keyed by topic.
+#[derive(Default)]
+pub struct WsProxy(StdMutex<HashMap<String, Topic>>);
+
+/// Subscribes to `topic`, connecting upstream for the first subscriber.
+#[tauri::command]
+pub fn ws_subscribe(app: AppHandle, proxy: State<'_, WsProxy>, topic: String) -> Result<()> {
+    if !topic.starts_with('/') {
+        return Err(Error::Invalid(format!(
+            "topic must be a backend path: {topic}"
+        )));
+    }
+    let mut topics = proxy.0.lock().unwrap();
+    if let Some(existing) = topics.get_mut(&topic) {
+        existing.subscribers += 1;
+        return Ok(());
+    }
+    let (outbox, rx) = mpsc::channel(OUTBOX_CAPACITY);
+    let task = tauri::async_runtime::spawn(run_topic(app.clone(), topic.clone(), rx));
+    topics.insert(
+        topic,
+        Topic {
+            subscribers: 1,
+            outbox,
+            task,
+        },
+    );
+    Ok(())
+}
+
+/// Drops one subscription; the connection closes with the last one.
+#[tauri::command]
+pub fn ws_unsubscribe(proxy: State<'_, WsProxy>, topic: String) -> Result<()> {
+    let mut topics = proxy.0.lock().unwrap();
+    let existing = topics
+        .get_mut(&topic)
+        .ok_or_else(|| Error::NotFound(format!("ws topic {topic}")))?;
+    existing.subscribers -= 1;
+    if existing.subscribers == 0 {
+        if let Some(topic) = topics.remove(&topic) {
+            topic.task.abort();
+        }
+    }
+    Ok(())
+}
+
+/// Sends a text message upstream, queueing it while disconnected.
+#[tauri::command]
+pub fn ws_send(proxy: State<'_, WsProxy>, topic: String, message: String) -> Result<()> {
+    let topics = proxy.0.lock().unwrap();
+    let existing = topics
+        .get(&topic)
+        .ok_or_else(|| Error::NotFound(format!("ws topic {topic}")))?;
+    existing
+        .outbox
+        .try_send(message)
+        .map_err(|_| Error::Invalid(format!("outbox of {topic} is full")))
+}
+
+/// How an upstream connection ended.
+enum Ended {
+    /// The backend finished the conversation; do not reconnect.
+    Closed,
+    /// Dropped or the backend is restarting; reconnect.
+    Lost(String),
+}
+
+#[tracing::instrument(name = "ws_proxy.topic", skip(app, outbox))]
+async fn run_topic(app: AppHandle, topic: String, mut outbox: mpsc::Receiver<String>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let ended = match backend_tls::ws_connect(&app, &topic).await {
+            Ok(socket) => {
+                backoff = INITIAL_BACKOFF;
+                emit_status(&app, &topic, true, false, None);
+                relay(&app, &topic, socket, &mut outbox).await
+            }
+            Err(err) => Ended::Lost(err.to_string()),
+        };
+        match ended {
+            Ended::Closed => {
+                emit_status(&app, &topic, false, true, None);
+                app.state::<WsProxy>().0.lock().unwrap().remove(&topic);
+                return;
+            }
+            Ended::Lost(error) => {
+                tracing::debug!(%topic, "ws upstream lost: {error}; retrying in {backoff:?}");
+                emit_status(&app, &topic, false, false, Some(error));
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn relay<S>(
+    app: &AppHandle,
+    topic: &str,
+    socket: S,
+    outbox: &mut mpsc::Receiver<String>,
+) -> Ended
+where
+    S: StreamExt<Item = tokio_tungstenite::tungstenite::Result<Message>>
+        + SinkExt<Message, Error = tokio_tungstenite::tungstenite::Error>
+        + Unpin,
+{
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            incoming = stream.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    let data = serde_json::from_str(&text)
+                        .unwrap_or_else(|_| serde_json::Value::String(text.to_string()));
+                    let payload = WsMessage { topic: topic.to_owned(), data };
+                    let _ = app.emit("ws-message", payload);
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    let code = frame.map(|frame| frame.code);
+                    return match code {
+                        Some(CloseCode::Away | CloseCode::Restart | CloseCode::Again) => {
+                            Ended::Lost("backend going away".into())
+                        }
+                        _ => Ended::Closed,
+                    };
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Ended::Lost(err.to_string()),
+                None => return Ended::Lost("connection closed".into()),
+            },
+            outgoing = outbox.recv() => {
+                let Some(message) = outgoing else {
+                    return Ended::Closed;
+                };
+                if let Err(err) = sink.send(Message::text(message)).await {
+                    return Ended::Lost(err.to_string());
+                }
+            }
+        }
+    }
+}
+
+fn emit_status(app: &AppHandle, topic: &str, connected: bool, closed: bool, error: Option<String>) {
+    let status = WsStatus {
+        topic: topic.to_owned(),
+        connected,
+        closed,
+        error,
+    };
+    let _ = app.emit("ws-status", status);
+}
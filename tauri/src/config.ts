@@ -1,21 +1,27 @@
 /**
  * Backend URL configuration for Tauri.
- * Default to localhost since backend runs as sidecar.
+ * Default to the local sidecar, served on a socket or a port chosen at launch.
  */
 
+import { invoke } from '@tauri-apps/api/core';
+
 const STORAGE_KEY = 'PERCUS_BACKEND_URL';
-const DEFAULT_URL = 'http://localhost:8000';
 
-export function getBackendUrl(): string {
-  // In Tauri, default to localhost (sidecar)
+export async function getBackendUrl(): Promise<string> {
+  // In Tauri, default to the sidecar
   // User can override for remote backend
-  return localStorage.getItem(STORAGE_KEY) ?? DEFAULT_URL;
+  const override = localStorage.getItem(STORAGE_KEY);
+  if (override) {
+    return override;
+  }
+  return invoke<string>('get_backend_url');
 }
 
 export function setBackendUrl(url: string): void {
   localStorage.setItem(STORAGE_KEY, url);
 }
 
-export function isLocalBackend(): boolean {
-  return getBackendUrl().includes('localhost');
+export async function isLocalBackend(): Promise<boolean> {
+  const url = await getBackendUrl();
+  return url.includes('localhost') || url.includes('127.0.0.1');
 }